@@ -23,6 +23,11 @@ use util::{
 // Frequency of PM Timer in HZ.
 const PM_TIMER_FREQUENCY: u128 = 3_579_545;
 pub const ACPI_BITMASK_SLEEP_ENABLE: u16 = 0x2000;
+pub const ACPI_BITMASK_SLEEP_TYPE: u16 = 0x1C00;
+pub const ACPI_BITMASK_WAKE_STATUS: u16 = 0x8000;
+pub const ACPI_BITMASK_POWER_BUTTON_STATUS: u16 = 0x0100;
+pub const ACPI_BITMASK_POWER_BUTTON_ENABLE: u16 = 0x0100;
+const ACPI_SLEEP_TYPE_SHIFT: u16 = 10;
 
 /// ACPI Power Management Timer
 #[allow(clippy::upper_case_acronyms)]
@@ -86,6 +91,18 @@ impl AcpiPmEvent {
         }
     }
 
+    /// Set WAK_STS bit of PM1 Status Registers when VM wakes up from sleep state.
+    pub fn set_wake_status(&mut self) {
+        self.status |= ACPI_BITMASK_WAKE_STATUS;
+    }
+
+    /// Set PWRBTN_STS bit of PM1 Status Registers when the power button is pressed.
+    /// Return true if the power button event is enabled by guest, and SCI should be raised.
+    pub fn press_power_button(&mut self) -> bool {
+        self.status |= ACPI_BITMASK_POWER_BUTTON_STATUS;
+        self.enable & ACPI_BITMASK_POWER_BUTTON_ENABLE != 0
+    }
+
     pub fn write(&mut self, data: &[u8], _base: GuestAddress, offset: u64) -> bool {
         match offset {
             0 => {
@@ -125,7 +142,12 @@ impl AcpiPmCtrl {
         write_data_u16(data, self.control)
     }
 
-    // Return true when guest want poweroff.
+    /// Get the SLP_TYP field written by guest, it tells which sleep state guest wants to enter.
+    pub fn sleep_type(&self) -> u8 {
+        ((self.control & ACPI_BITMASK_SLEEP_TYPE) >> ACPI_SLEEP_TYPE_SHIFT) as u8
+    }
+
+    // Return true when guest want to enter sleep state, see `sleep_type` for the state.
     pub fn write(&mut self, data: &[u8], _base: GuestAddress, _offset: u64) -> bool {
         let mut value = 0;
         if !read_data_u16(data, &mut value) {
@@ -135,3 +157,53 @@ impl AcpiPmCtrl {
         value & ACPI_BITMASK_SLEEP_ENABLE != 0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pm_sleep_and_wake() {
+        let mut pm_ctrl = AcpiPmCtrl::new();
+        // SLP_TYP is 1 and SLP_EN is set.
+        let value = (1_u16 << ACPI_SLEEP_TYPE_SHIFT) | ACPI_BITMASK_SLEEP_ENABLE;
+        assert!(pm_ctrl.write(&value.to_le_bytes(), GuestAddress(0), 0));
+        assert_eq!(pm_ctrl.sleep_type(), 1);
+        let mut data = [0_u8; 2];
+        assert!(pm_ctrl.read(&mut data, GuestAddress(0), 0));
+        assert_eq!(u16::from_le_bytes(data) & ACPI_BITMASK_SLEEP_ENABLE, 0);
+
+        let mut pm_evt = AcpiPmEvent::new();
+        pm_evt.set_wake_status();
+        assert!(pm_evt.read(&mut data, GuestAddress(0), 0));
+        assert_eq!(u16::from_le_bytes(data), ACPI_BITMASK_WAKE_STATUS);
+        // WAK_STS is cleared by writing 1.
+        assert!(pm_evt.write(&ACPI_BITMASK_WAKE_STATUS.to_le_bytes(), GuestAddress(0), 0));
+        assert!(pm_evt.read(&mut data, GuestAddress(0), 0));
+        assert_eq!(u16::from_le_bytes(data), 0);
+    }
+
+    #[test]
+    fn test_pm_power_button() {
+        let mut pm_evt = AcpiPmEvent::new();
+        let mut data = [0_u8; 2];
+        // SCI is not raised before guest enables the power button event.
+        assert!(!pm_evt.press_power_button());
+        assert!(pm_evt.read(&mut data, GuestAddress(0), 0));
+        assert_eq!(u16::from_le_bytes(data), ACPI_BITMASK_POWER_BUTTON_STATUS);
+        assert!(pm_evt.write(
+            &ACPI_BITMASK_POWER_BUTTON_STATUS.to_le_bytes(),
+            GuestAddress(0),
+            0
+        ));
+
+        assert!(pm_evt.write(
+            &ACPI_BITMASK_POWER_BUTTON_ENABLE.to_le_bytes(),
+            GuestAddress(0),
+            2
+        ));
+        assert!(pm_evt.press_power_button());
+        assert!(pm_evt.read(&mut data, GuestAddress(0), 0));
+        assert_eq!(u16::from_le_bytes(data), ACPI_BITMASK_POWER_BUTTON_STATUS);
+    }
+}
@@ -124,7 +124,7 @@ impl AcpiTable {
         AcpiTable {
             entries: AcpiTableHeader {
                 signature,
-                length: 0,
+                length: std::mem::size_of::<AcpiTableHeader>() as u32,
                 revision,
                 checksum: 0,
                 oem_id,
@@ -512,6 +512,32 @@ pub mod madt_subtable {
             Vec::from(self.as_bytes())
         }
     }
+
+    /// Interrupt Source Override structure.
+    #[repr(C, packed)]
+    #[derive(Default, Copy, Clone)]
+    pub struct AcpiInterruptSourceOverride {
+        /// Type ID.
+        pub type_id: u8,
+        /// The length of this structure.
+        pub length: u8,
+        /// Bus, 0 means ISA.
+        pub bus: u8,
+        /// Bus-relative interrupt source (IRQ).
+        pub source: u8,
+        /// The GSI that this bus-relative interrupt source will signal.
+        pub gsi: u32,
+        /// MPS INTI flags, including polarity and trigger mode.
+        pub flags: u16,
+    }
+
+    impl ByteCode for AcpiInterruptSourceOverride {}
+
+    impl AmlBuilder for AcpiInterruptSourceOverride {
+        fn aml_bytes(&self) -> Vec<u8> {
+            Vec::from(self.as_bytes())
+        }
+    }
 }
 
 /// This module describes ACPI MADT's sub-tables on aarch64 platform.
@@ -647,3 +673,56 @@ pub mod madt_subtable {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_acpi_table_len() {
+        let header_len = std::mem::size_of::<AcpiTableHeader>();
+        let mut table = AcpiTable::new(*b"TEST", 1, *b"STRATO", *b"VIRTTEST", 1);
+        // A table without any child only contains the header.
+        assert_eq!(table.table_len(), header_len);
+        assert_eq!(table.aml_bytes()[4..=7], (header_len as u32).to_le_bytes());
+
+        table.append_child(&[1_u8, 2, 3, 4]);
+        assert_eq!(table.table_len(), header_len + 4);
+        assert_eq!(
+            table.aml_bytes()[4..=7],
+            (header_len as u32 + 4).to_le_bytes()
+        );
+
+        table.set_table_len(header_len + 16);
+        assert_eq!(table.table_len(), header_len + 16);
+        assert_eq!(
+            table.aml_bytes()[4..=7],
+            (header_len as u32 + 16).to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn test_acpi_table_set_field() {
+        let header_len = std::mem::size_of::<AcpiTableHeader>();
+        let mut table = AcpiTable::new(*b"TEST", 1, *b"STRATO", *b"VIRTTEST", 1);
+        table.set_table_len(header_len + 8);
+        table.set_field(header_len, 0x1234_5678_u32);
+        table.set_field(header_len + 4, 0xAB_u8);
+
+        let bytes = table.aml_bytes();
+        assert_eq!(&bytes[0..4], b"TEST");
+        assert_eq!(
+            bytes[header_len..header_len + 4],
+            0x1234_5678_u32.to_le_bytes()
+        );
+        assert_eq!(bytes[header_len + 4], 0xAB);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_acpi_table_set_field_overflow() {
+        let header_len = std::mem::size_of::<AcpiTableHeader>();
+        let mut table = AcpiTable::new(*b"TEST", 1, *b"STRATO", *b"VIRTTEST", 1);
+        table.set_field(header_len - 2, 0_u32);
+    }
+}
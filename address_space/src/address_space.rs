@@ -11,7 +11,7 @@
 // See the Mulan PSL v2 for more details.
 
 use anyhow::{anyhow, Context, Result};
-use arc_swap::ArcSwap;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use std::fmt;
 use std::fmt::Debug;
 use std::io::Write;
@@ -23,8 +23,8 @@ use util::byte_code::ByteCode;
 use util::test_helper::is_test_enabled;
 
 use crate::{
-    AddressRange, AddressSpaceError, FlatRange, GuestAddress, Listener, ListenerReqType, Region,
-    RegionIoEventFd, RegionType,
+    AddressRange, AddressSpaceError, FlatRange, GuestAddress, Listener, ListenerReqType,
+    ProtectedMemory, Region, RegionIoEventFd, RegionType,
 };
 
 /// Contains an array of `FlatRange`.
@@ -118,6 +118,17 @@ pub struct RegionCache {
 
 type ListenerObj = Arc<Mutex<dyn Listener>>;
 
+/// Host memory consumption of a Ram range in `AddressSpace`.
+#[derive(Clone, Debug)]
+pub struct RamResidentInfo {
+    /// Name of the Ram region.
+    pub name: String,
+    /// Guest address range.
+    pub addr_range: AddressRange,
+    /// Size in bytes of the memory resident in host.
+    pub resident: u64,
+}
+
 /// Address Space of memory.
 #[derive(Clone)]
 pub struct AddressSpace {
@@ -127,11 +138,17 @@ pub struct AddressSpace {
     root: Region,
     /// `flat_view` is the output of rendering all regions in parent `address-space`,
     /// every time the topology changed (add/delete region), `flat_view` would be updated.
+    /// Accesses load a snapshot of it without any lock, and a new one is swapped in
+    /// after it's regenerated, so accesses of vcpus don't contend with each other.
     flat_view: Arc<ArcSwap<FlatView>>,
+    /// Serialize the updates of `flat_view`, which is only taken by writers.
+    update_lock: Arc<Mutex<()>>,
     /// The triggered call-backs when flat_view changed.
     listeners: Arc<Mutex<Vec<ListenerObj>>>,
     /// The current layout of ioeventfds, which is compared with new ones in topology-update stage.
     ioeventfds: Arc<Mutex<Vec<RegionIoEventFd>>>,
+    /// Guest memory of protected VM, only the shared part of which is accessible.
+    protected: Arc<ArcSwapOption<ProtectedMemory>>,
 }
 
 impl fmt::Debug for AddressSpace {
@@ -156,8 +173,10 @@ impl AddressSpace {
             name: String::from(name),
             root: root.clone(),
             flat_view: Arc::new(ArcSwap::new(Arc::new(FlatView::default()))),
+            update_lock: Arc::new(Mutex::new(())),
             listeners: Arc::new(Mutex::new(Vec::new())),
             ioeventfds: Arc::new(Mutex::new(Vec::new())),
+            protected: Arc::new(ArcSwapOption::empty()),
         });
 
         root.set_belonged_address_space(&space);
@@ -210,6 +229,7 @@ impl AddressSpace {
         for fr in self.flat_view.load().0.iter() {
             locked_listener.handle_request(Some(fr), None, ListenerReqType::AddRegion)?;
         }
+        locked_listener.commit()?;
         locked_listener.enable();
 
         let mut idx = 0;
@@ -286,6 +306,15 @@ impl AddressSpace {
         }
     }
 
+    /// Notify the listeners that all the changes of a topology update are handled.
+    fn commit_listeners(&self) -> Result<()> {
+        self.listeners
+            .lock()
+            .unwrap()
+            .iter()
+            .try_for_each(|ml| ml.lock().unwrap().commit())
+    }
+
     /// Update the topology pass.
     ///
     /// # Arguments
@@ -432,6 +461,34 @@ impl AddressSpace {
         Ok(())
     }
 
+    /// Restrict accesses to guest memory of protected VM, the private part of which
+    /// can't be accessed through this `AddressSpace` any more.
+    ///
+    /// # Arguments
+    ///
+    /// * `memory` - Guest memory of protected VM.
+    pub fn set_protected(&self, memory: Arc<ProtectedMemory>) {
+        self.protected.store(Some(memory));
+    }
+
+    /// Get the guest memory of protected VM, `None` if the VM is not protected.
+    pub fn protected_memory(&self) -> Option<Arc<ProtectedMemory>> {
+        self.protected.load_full()
+    }
+
+    /// Check that the memory accessed is shared if the VM is protected.
+    fn check_shared(&self, addr: GuestAddress, count: u64) -> Result<()> {
+        if let Some(memory) = self.protected.load().as_ref() {
+            if !memory.is_shared(addr.raw_value(), count) {
+                return Err(anyhow!(AddressSpaceError::PrivateAccess(
+                    addr.raw_value(),
+                    count
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Return the host address according to the given `GuestAddress`.
     ///
     /// # Arguments
@@ -484,6 +541,7 @@ impl AddressSpace {
     /// * `addr` - Guest address.
     /// * `count` - Memory needed length
     pub fn get_address_map(&self, addr: GuestAddress, count: u64) -> Result<Vec<Iovec>> {
+        self.check_shared(addr, count)?;
         let mut len = count;
         let mut start = addr;
         let mut hva_iovec = Vec::new();
@@ -565,6 +623,51 @@ impl AddressSpace {
         None
     }
 
+    /// Get the name of the region which the `GuestAddress` belongs to.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Guest address.
+    pub fn get_region_name(&self, addr: GuestAddress) -> Option<String> {
+        self.flat_view
+            .load()
+            .find_flatrange(addr)
+            .map(|fr| fr.owner.name.clone())
+    }
+
+    /// Get the guest address ranges of all Ram ranges in the flat view.
+    pub fn get_ram_ranges(&self) -> Vec<AddressRange> {
+        self.flat_view
+            .load()
+            .0
+            .iter()
+            .filter(|fr| fr.owner.region_type() == RegionType::Ram)
+            .map(|fr| fr.addr_range)
+            .collect()
+    }
+
+    /// Get the host memory consumption of each Ram range in the flat view.
+    pub fn get_ram_resident(&self) -> Result<Vec<RamResidentInfo>> {
+        let view = self.flat_view.load();
+        let mut infos = Vec::new();
+        for fr in view
+            .0
+            .iter()
+            .filter(|fr| fr.owner.region_type() == RegionType::Ram)
+        {
+            let resident = fr
+                .owner
+                .resident_size(fr.offset_in_region, fr.addr_range.size)
+                .with_context(|| format!("Failed to get resident size of {}", fr.owner.name))?;
+            infos.push(RamResidentInfo {
+                name: fr.owner.name.clone(),
+                addr_range: fr.addr_range,
+                resident,
+            });
+        }
+        Ok(infos)
+    }
+
     /// Return the end address of memory according to all Ram regions in AddressSpace.
     pub fn memory_end_address(&self) -> GuestAddress {
         self.flat_view
@@ -588,6 +691,7 @@ impl AddressSpace {
     ///
     /// Return Error if the `addr` is not mapped.
     pub fn read(&self, dst: &mut dyn std::io::Write, addr: GuestAddress, count: u64) -> Result<()> {
+        self.check_shared(addr, count)?;
         let view = self.flat_view.load();
 
         view.read(dst, addr, count)?;
@@ -606,6 +710,7 @@ impl AddressSpace {
     ///
     /// Return Error if the `addr` is not mapped.
     pub fn write(&self, src: &mut dyn std::io::Read, addr: GuestAddress, count: u64) -> Result<()> {
+        self.check_shared(addr, count)?;
         let view = self.flat_view.load();
 
         if is_test_enabled() {
@@ -711,6 +816,8 @@ impl AddressSpace {
 
     /// Update the topology of memory.
     pub fn update_topology(&self) -> Result<()> {
+        // The old flat view must not be replaced by others until the new one is stored.
+        let _update = self.update_lock.lock().unwrap();
         let old_fv = self.flat_view.load();
 
         let addr_range = AddressRange::new(GuestAddress(0), self.root.size());
@@ -723,6 +830,8 @@ impl AddressSpace {
             .with_context(|| "Failed to update topology (first pass)")?;
         self.update_topology_pass(&old_fv, &new_fv, true)
             .with_context(|| "Failed to update topology (second pass)")?;
+        self.commit_listeners()
+            .with_context(|| "Failed to commit topology update to listeners")?;
 
         self.flat_view.store(Arc::new(new_fv));
         self.update_ioeventfds()
@@ -737,10 +846,12 @@ mod test {
 
     use super::*;
     use crate::{HostMemMapping, RegionOps};
+    use util::unix::host_page_size;
 
     #[derive(Default, Clone)]
     struct TestListener {
         reqs: Arc<Mutex<Vec<(ListenerReqType, AddressRange)>>>,
+        commits: Arc<Mutex<u32>>,
         enabled: bool,
     }
 
@@ -783,6 +894,11 @@ mod test {
             }
             Ok(())
         }
+
+        fn commit(&self) -> Result<()> {
+            *self.commits.lock().unwrap() += 1;
+            Ok(())
+        }
     }
 
     // the listeners in AddressSpace is settled in ascending order by priority
@@ -990,12 +1106,15 @@ mod test {
         //  D:                  [DDDDDD]
         // the flat_view is as follows,
         //        [CCCCCCCCCCCC][DDDDDD][CCCCCCCCCCCCCCCCCCC]
+        let commits = *listener.lock().unwrap().commits.lock().unwrap();
         let region_d = Region::init_io_region(1000, default_ops, "region_d");
         region_b.add_subregion(region_d.clone(), 0).unwrap();
 
         let locked_listener = listener.lock().unwrap();
         assert_eq!(space.flat_view.load().0.len(), 3);
         assert_eq!(locked_listener.reqs.lock().unwrap().len(), 4);
+        // all the changes are committed once
+        assert_eq!(*locked_listener.commits.lock().unwrap(), commits + 1);
         // delete flat-range 0~6000 first, belonging to region_c
         assert_eq!(
             locked_listener.reqs.lock().unwrap().get(0).unwrap().1,
@@ -1019,6 +1138,50 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_concurrent_update_topology() {
+        let root = Region::init_container_region(8000, "root");
+        let space = AddressSpace::new(root.clone(), "space").unwrap();
+        let ops = RegionOps {
+            read: Arc::new(|data: &mut [u8], _: GuestAddress, _: u64| -> bool {
+                data.fill(0xa5);
+                true
+            }),
+            write: Arc::new(|_: &[u8], _: GuestAddress, _: u64| -> bool { true }),
+        };
+
+        let mut handles = Vec::new();
+        for i in 0..8_u64 {
+            let root = root.clone();
+            let ops = ops.clone();
+            handles.push(std::thread::spawn(move || {
+                for j in 0..10_u64 {
+                    let region = Region::init_io_region(10, ops.clone(), "region");
+                    root.add_subregion(region, (i * 10 + j) * 10).unwrap();
+                }
+            }));
+        }
+        let reader_space = space.clone();
+        let reader = std::thread::spawn(move || {
+            for _ in 0..1000 {
+                let mut data = [0_u8; 4];
+                if reader_space
+                    .read(&mut data.as_mut(), GuestAddress(0), 4)
+                    .is_ok()
+                {
+                    assert_eq!(data, [0xa5; 4]);
+                }
+            }
+        });
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        reader.join().unwrap();
+
+        // None of the updates is overwritten by a stale flat view.
+        assert_eq!(space.flat_view.load().0.len(), 80);
+    }
+
     #[test]
     fn test_update_ioeventfd() {
         let ioeventfds = vec![RegionIoEventFd {
@@ -1194,6 +1357,52 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_get_ram_resident() {
+        let page_size = host_page_size();
+        let root = Region::init_container_region(page_size * 16, "root");
+        let space = AddressSpace::new(root.clone(), "space").unwrap();
+        let ram = Arc::new(
+            HostMemMapping::new(
+                GuestAddress(0),
+                None,
+                page_size * 8,
+                None,
+                false,
+                false,
+                false,
+            )
+            .unwrap(),
+        );
+        let region_a = Region::init_ram_region(ram.clone(), "region_a");
+        root.add_subregion(region_a, 0).unwrap();
+        // The ram is split by a higher priority io region.
+        let default_ops = RegionOps {
+            read: Arc::new(|_: &mut [u8], _: GuestAddress, _: u64| -> bool { true }),
+            write: Arc::new(|_: &[u8], _: GuestAddress, _: u64| -> bool { true }),
+        };
+        let region_b = Region::init_io_region(page_size * 2, default_ops, "region_b");
+        region_b.set_priority(1);
+        root.add_subregion(region_b, page_size * 2).unwrap();
+
+        space
+            .write_object(&1_u8, GuestAddress(page_size * 5))
+            .unwrap();
+        let infos = space.get_ram_resident().unwrap();
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[0].name, "region_a");
+        assert_eq!(
+            infos[0].addr_range,
+            AddressRange::new(GuestAddress(0), page_size * 2)
+        );
+        assert_eq!(infos[0].resident, 0);
+        assert_eq!(
+            infos[1].addr_range,
+            AddressRange::new(GuestAddress(page_size * 4), page_size * 4)
+        );
+        assert_eq!(infos[1].resident, page_size);
+    }
+
     #[test]
     fn test_write_and_read_object() {
         let root = Region::init_container_region(8000, "root");
@@ -1211,4 +1420,35 @@ mod test {
         assert_eq!(data1, 10000);
         assert!(space.write_object(&data, GuestAddress(993)).is_err());
     }
+
+    #[test]
+    fn test_protected_access() {
+        let root = Region::init_container_region(0x4000, "root");
+        let space = AddressSpace::new(root.clone(), "space").unwrap();
+        let ram = Arc::new(
+            HostMemMapping::new(GuestAddress(0), None, 0x2000, None, false, false, false).unwrap(),
+        );
+        root.add_subregion(Region::init_ram_region(ram, "ram"), 0)
+            .unwrap();
+
+        let memory = Arc::new(ProtectedMemory::default());
+        memory.protect(0, 0x2000);
+        space.set_protected(memory.clone());
+        assert!(space.protected_memory().is_some());
+
+        let data: u64 = 10000;
+        assert!(space.write_object(&data, GuestAddress(0x1000)).is_err());
+        assert!(space.read_object::<u64>(GuestAddress(0x1000)).is_err());
+        assert!(space.get_address_map(GuestAddress(0x1000), 8).is_err());
+
+        // Memory shared by guest is accessible.
+        memory.set_shared(0x1000, 0x1000, true);
+        assert!(space.write_object(&data, GuestAddress(0x1000)).is_ok());
+        assert_eq!(
+            space.read_object::<u64>(GuestAddress(0x1000)).unwrap(),
+            10000
+        );
+        assert!(space.get_address_map(GuestAddress(0x1000), 8).is_ok());
+        assert!(space.get_address_map(GuestAddress(0xffc), 8).is_err());
+    }
 }
@@ -50,4 +50,6 @@ pub enum AddressSpaceError {
     KvmSlotOverlap { add: (u64, u64), exist: (u64, u64) },
     #[error("Invalid offset: offset 0x{0:X}, data length 0x{1:X}, region size 0x{2:X}")]
     InvalidOffset(u64, u64, u64),
+    #[error("Failed to access private memory of protected VM, addr 0x{0:X}, size 0x{1:X}")]
+    PrivateAccess(u64, u64),
 }
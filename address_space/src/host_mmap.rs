@@ -17,7 +17,7 @@ use std::sync::Arc;
 use std::thread;
 
 use anyhow::{bail, Context, Result};
-use log::{error, info};
+use log::{error, info, warn};
 use machine_manager::config::{HostMemPolicy, MachineMemConfig, MemZoneConfig};
 use util::{
     syscall::mbind,
@@ -31,6 +31,8 @@ const MAX_PREALLOC_THREAD: u8 = 16;
 const MPOL_MF_STRICT: u32 = 1;
 /// Move pages owned by this process to conform to mapping.
 const MPOL_MF_MOVE: u32 = 2;
+/// Max number of pages checked by one `mincore`, which limits the size of result vector.
+const MINCORE_PAGES_PER_CALL: usize = 65536;
 
 /// FileBackend represents backend-file of `HostMemMapping`.
 #[derive(Clone, Debug)]
@@ -232,6 +234,36 @@ pub fn mem_prealloc(host_addr: u64, size: u64, nr_vcpus: u8) {
     }
 }
 
+/// Lock the memory in host RAM, which populates the memory and prevents it from being swapped out.
+///
+/// # Arguments
+///
+/// * `host_addr` - The start host address of memory.
+/// * `size` - Size of memory.
+fn mem_lock(host_addr: u64, size: u64) -> Result<()> {
+    // SAFETY: The memory is mapped with the size.
+    let ret = unsafe { libc::mlock(host_addr as *const libc::c_void, size as libc::size_t) };
+    if ret == 0 {
+        return Ok(());
+    }
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::ENOMEM) | Some(libc::EPERM) => Err(err).with_context(|| {
+            format!(
+                "Failed to lock 0x{:x} bytes of guest memory, please raise the limit of locked memory (ulimit -l) or grant CAP_IPC_LOCK",
+                size
+            )
+        }),
+        Some(libc::EAGAIN) => Err(err).with_context(|| {
+            format!(
+                "Failed to lock 0x{:x} bytes of guest memory, there is not enough free memory on host",
+                size
+            )
+        }),
+        _ => Err(err).with_context(|| "Failed to lock guest memory"),
+    }
+}
+
 /// If the memory is not configured numa, use this
 ///
 /// # Arguments
@@ -242,24 +274,7 @@ pub fn create_default_mem(mem_config: &MachineMemConfig, thread_num: u8) -> Resu
     let mut f_back: Option<FileBackend> = None;
 
     if mem_config.mem_share {
-        let anon_mem_name = String::from("stratovirt_anon_mem");
-
-        let anon_fd =
-            unsafe { libc::syscall(libc::SYS_memfd_create, anon_mem_name.as_ptr(), 0) } as RawFd;
-        if anon_fd < 0 {
-            return Err(std::io::Error::last_os_error()).with_context(|| "Failed to create memfd");
-        }
-
-        let anon_file = unsafe { File::from_raw_fd(anon_fd) };
-        anon_file
-            .set_len(mem_config.mem_size)
-            .with_context(|| "Failed to set the length of anonymous file that backs memory")?;
-
-        f_back = Some(FileBackend {
-            file: Arc::new(anon_file),
-            offset: 0,
-            page_size: host_page_size(),
-        });
+        f_back = Some(create_memfd(mem_config.mem_size, None, true)?);
     } else if let Some(path) = &mem_config.mem_path {
         f_back = Some(
             FileBackend::new_mem(path, mem_config.mem_size)
@@ -279,46 +294,103 @@ pub fn create_default_mem(mem_config: &MachineMemConfig, thread_num: u8) -> Resu
     if mem_config.mem_prealloc {
         mem_prealloc(block.host_address(), mem_config.mem_size, thread_num);
     }
+    if mem_config.mem_lock {
+        mem_lock(block.host_address(), mem_config.mem_size)?;
+    }
     let region = Region::init_ram_region(block, "DefaultRam");
 
     Ok(region)
 }
 
-/// If the memory is configured numa, use this
+/// Create memfd with the given size to back memory.
 ///
 /// # Arguments
 ///
-/// * `mem_config` - The config of default memory.
-/// * `thread_num` - The num of mem preallocv threads, typically the number of vCPUs.
-pub fn create_backend_mem(mem_config: &MemZoneConfig, thread_num: u8) -> Result<Region> {
-    let mut f_back: Option<FileBackend> = None;
+/// * `size` - Size of memfd.
+/// * `hugetlb_size` - Allocate memory from hugetlbfs with the page size if it's `Some`,
+///   0 means the default huge page size of host.
+/// * `seal` - Seal the size of memfd, so that the processes which the fd is passed
+///   to, such as vhost-user backends, can not grow or shrink it.
+fn create_memfd(size: u64, hugetlb_size: Option<u64>, seal: bool) -> Result<FileBackend> {
+    let anon_mem_name = String::from("stratovirt_anon_mem");
+    let mut flags = 0;
+    if seal {
+        flags |= libc::MFD_ALLOW_SEALING;
+    }
+    if let Some(page_size) = hugetlb_size {
+        flags |= libc::MFD_HUGETLB;
+        if page_size != 0 {
+            flags |= page_size.trailing_zeros() << libc::MFD_HUGE_SHIFT;
+        }
+    }
 
-    if mem_config.memfd {
-        let anon_mem_name = String::from("stratovirt_anon_mem");
+    let anon_fd =
+        unsafe { libc::syscall(libc::SYS_memfd_create, anon_mem_name.as_ptr(), flags) } as RawFd;
+    if anon_fd < 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| "Failed to create memfd");
+    }
 
-        let anon_fd =
-            unsafe { libc::syscall(libc::SYS_memfd_create, anon_mem_name.as_ptr(), 0) } as RawFd;
-        if anon_fd < 0 {
-            return Err(std::io::Error::last_os_error()).with_context(|| "Failed to create memfd");
+    let anon_file = unsafe { File::from_raw_fd(anon_fd) };
+    anon_file
+        .set_len(size)
+        .with_context(|| "Failed to set the length of anonymous file that backs memory")?;
+    if seal {
+        let seals = libc::F_SEAL_GROW | libc::F_SEAL_SHRINK | libc::F_SEAL_SEAL;
+        // SAFETY: The fd is valid and created with MFD_ALLOW_SEALING.
+        let ret = unsafe { libc::fcntl(anon_file.as_raw_fd(), libc::F_ADD_SEALS, seals) };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error()).with_context(|| "Failed to seal memfd");
         }
+    }
 
-        let anon_file = unsafe { File::from_raw_fd(anon_fd) };
-        anon_file
-            .set_len(mem_config.size)
-            .with_context(|| "Failed to set the length of anonymous file that backs memory")?;
+    let page_size = if hugetlb_size.is_some() {
+        // Safe because struct `statfs` only contains plain-data-type field,
+        // and set to all-zero will not cause any undefined behavior.
+        let mut fstat: libc::statfs = unsafe { std::mem::zeroed() };
+        unsafe { libc::fstatfs(anon_file.as_raw_fd(), &mut fstat) };
+        fstat.f_bsize as u64
+    } else {
+        host_page_size()
+    };
+
+    Ok(FileBackend {
+        file: Arc::new(anon_file),
+        offset: 0,
+        page_size,
+    })
+}
 
-        f_back = Some(FileBackend {
-            file: Arc::new(anon_file),
-            offset: 0,
-            page_size: host_page_size(),
-        });
+/// Map the memory of memory zone.
+///
+/// # Arguments
+///
+/// * `mem_config` - The config of memory zone.
+/// * `hugetlb` - Allocate memory from hugetlbfs or not.
+fn create_backend_mapping(mem_config: &MemZoneConfig, hugetlb: bool) -> Result<HostMemMapping> {
+    let mut f_back: Option<FileBackend> = None;
+
+    if mem_config.memfd {
+        let hugetlb_size = if hugetlb {
+            Some(mem_config.hugetlb_size)
+        } else {
+            None
+        };
+        f_back = Some(create_memfd(
+            mem_config.size,
+            hugetlb_size,
+            mem_config.seal,
+        )?);
     } else if let Some(path) = &mem_config.mem_path {
         f_back = Some(
             FileBackend::new_mem(path, mem_config.size)
                 .with_context(|| "Failed to create file that backs memory")?,
         );
+    } else if mem_config.share {
+        // Shared anonymous memory is backed by memfd, so that the fd can be passed
+        // to other processes, such as vhost-user backends.
+        f_back = Some(create_memfd(mem_config.size, None, true)?);
     }
-    let block = Arc::new(HostMemMapping::new(
+    HostMemMapping::new(
         GuestAddress(0),
         None,
         mem_config.size,
@@ -326,11 +398,44 @@ pub fn create_backend_mem(mem_config: &MemZoneConfig, thread_num: u8) -> Result<
         mem_config.dump_guest_core,
         mem_config.share,
         false,
-    )?);
+    )
+}
+
+/// If the memory is configured numa, use this
+///
+/// # Arguments
+///
+/// * `mem_config` - The config of default memory.
+/// * `thread_num` - The num of mem preallocv threads, typically the number of vCPUs.
+/// * `lock` - Lock the memory in host RAM or not.
+pub fn create_backend_mem(
+    mem_config: &MemZoneConfig,
+    thread_num: u8,
+    lock: bool,
+) -> Result<Region> {
+    let mut mapping = match create_backend_mapping(mem_config, mem_config.hugetlb) {
+        Err(e) if mem_config.hugetlb && mem_config.hugetlb_fallback => {
+            warn!(
+                "Failed to allocate huge pages for memory backend {}: {:?}, fall back to normal pages",
+                mem_config.id, e
+            );
+            create_backend_mapping(mem_config, false)?
+        }
+        ret => ret?,
+    };
+    if let Some(thp) = mem_config.thp {
+        mapping.set_thp(thp)?;
+    }
+    let block = Arc::new(mapping);
+    // Set the numa policy before any page is allocated, so that the pages are allocated
+    // on the host nodes directly instead of being migrated.
+    set_host_memory_policy(&block, mem_config)?;
     if mem_config.prealloc {
         mem_prealloc(block.host_address(), mem_config.size, thread_num);
     }
-    set_host_memory_policy(&block, mem_config)?;
+    if lock {
+        mem_lock(block.host_address(), mem_config.size)?;
+    }
 
     let region = Region::init_ram_region(block, mem_config.id.as_str());
     Ok(region)
@@ -392,6 +497,8 @@ pub struct HostMemMapping {
     file_back: Option<FileBackend>,
     /// share mem flag
     is_share: bool,
+    /// Transparent huge page is advised for this mapping or not, `None` means the host default.
+    thp: Option<bool>,
 }
 
 // Send and Sync is not auto-implemented for raw pointer type
@@ -443,9 +550,42 @@ impl HostMemMapping {
             host_addr: host_addr as *mut u8,
             file_back,
             is_share,
+            thp: None,
         })
     }
 
+    /// Advise transparent huge page for the mapping or not by `madvise`.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Use transparent huge page or not.
+    pub fn set_thp(&mut self, enabled: bool) -> Result<()> {
+        let advice = if enabled {
+            libc::MADV_HUGEPAGE
+        } else {
+            libc::MADV_NOHUGEPAGE
+        };
+        // SAFETY: The memory is mapped with the size.
+        let ret = unsafe {
+            libc::madvise(
+                self.host_addr as *mut libc::c_void,
+                self.size() as libc::size_t,
+                advice,
+            )
+        };
+        if ret != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to set transparent huge page to {}", enabled));
+        }
+        self.thp = Some(enabled);
+        Ok(())
+    }
+
+    /// Get whether transparent huge page is advised, `None` means the host default.
+    pub fn thp(&self) -> Option<bool> {
+        self.thp
+    }
+
     /// Get size of mapped memory.
     pub fn size(&self) -> u64 {
         self.address_range.size
@@ -471,6 +611,56 @@ impl HostMemMapping {
     pub fn mem_shared(&self) -> bool {
         self.is_share
     }
+
+    /// Get the size of memory which is resident in host, by `mincore` the pages
+    /// in the range. The range is extended to page boundaries.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Offset of the range in this mapping.
+    /// * `size` - Size of the range.
+    pub fn resident_size(&self, offset: u64, size: u64) -> Result<u64> {
+        if offset
+            .checked_add(size)
+            .filter(|end| *end <= self.size())
+            .is_none()
+        {
+            bail!(
+                "Range 0x{:x} size 0x{:x} is out of memory mapping",
+                offset,
+                size
+            );
+        }
+        let page_size = host_page_size();
+        let start = (self.host_address() + offset) & !(page_size - 1);
+        let end = (self.host_address() + offset + size + page_size - 1) & !(page_size - 1);
+
+        let mut resident = 0_u64;
+        let mut addr = start;
+        let mut vec = vec![0_u8; MINCORE_PAGES_PER_CALL];
+        while addr < end {
+            let pages = min((end - addr) / page_size, MINCORE_PAGES_PER_CALL as u64);
+            // SAFETY: The range is in this mapping, and vec is large enough for the pages.
+            let ret = unsafe {
+                libc::mincore(
+                    addr as *mut libc::c_void,
+                    (pages * page_size) as libc::size_t,
+                    vec.as_mut_ptr(),
+                )
+            };
+            if ret < 0 {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Failed to mincore memory at 0x{:x}", addr));
+            }
+            resident += vec[..pages as usize]
+                .iter()
+                .filter(|page| *page & 1 != 0)
+                .count() as u64
+                * page_size;
+            addr += pages * page_size;
+        }
+        Ok(resident)
+    }
 }
 
 impl Drop for HostMemMapping {
@@ -586,6 +776,63 @@ mod test {
         std::fs::remove_file(file_path).unwrap();
     }
 
+    #[test]
+    fn test_resident_size() {
+        let page_size = host_page_size();
+        let ram = HostMemMapping::new(
+            GuestAddress(0),
+            None,
+            page_size * 16,
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+        assert_eq!(ram.resident_size(0, ram.size()).unwrap(), 0);
+
+        // Touch the 2nd and the 5th page.
+        let host_addr = ram.host_address();
+        unsafe {
+            *((host_addr + page_size) as *mut u8) = 1;
+            *((host_addr + page_size * 4 + 8) as *mut u8) = 1;
+        }
+        assert_eq!(ram.resident_size(0, ram.size()).unwrap(), page_size * 2);
+        assert_eq!(ram.resident_size(page_size + 8, 16).unwrap(), page_size);
+        assert_eq!(ram.resident_size(page_size * 2, page_size * 2).unwrap(), 0);
+        assert!(ram.resident_size(page_size, page_size * 16).is_err());
+    }
+
+    #[test]
+    fn test_set_thp() {
+        let mut ram =
+            HostMemMapping::new(GuestAddress(0), None, 0x40_0000, None, false, false, false)
+                .unwrap();
+        assert_eq!(ram.thp(), None);
+        assert!(ram.set_thp(false).is_ok());
+        assert_eq!(ram.thp(), Some(false));
+    }
+
+    #[test]
+    fn test_mem_lock() {
+        let page_size = host_page_size();
+        let ram = HostMemMapping::new(GuestAddress(0), None, page_size, None, false, false, false)
+            .unwrap();
+        assert!(mem_lock(ram.host_address(), page_size).is_ok());
+        assert_eq!(ram.resident_size(0, page_size).unwrap(), page_size);
+    }
+
+    #[test]
+    fn test_memfd_seal() {
+        let f_back = create_memfd(0x10_0000, None, true).unwrap();
+        assert_eq!(f_back.file.metadata().unwrap().len(), 0x10_0000);
+        assert!(f_back.file.set_len(0x20_0000).is_err());
+        assert!(f_back.file.set_len(0x8_0000).is_err());
+
+        let f_back = create_memfd(0x10_0000, None, false).unwrap();
+        assert!(f_back.file.set_len(0x20_0000).is_ok());
+    }
+
     #[test]
     fn test_memory_prealloc() {
         // Mmap and prealloc with anonymous memory.
@@ -82,10 +82,11 @@ mod address_space;
 pub mod error;
 mod host_mmap;
 mod listener;
+mod protected;
 mod region;
 mod state;
 
-pub use crate::address_space::{AddressSpace, RegionCache};
+pub use crate::address_space::{AddressSpace, RamResidentInfo, RegionCache};
 pub use address::{AddressRange, GuestAddress};
 pub use anyhow::Result;
 pub use error::AddressSpaceError;
@@ -94,6 +95,7 @@ pub use host_mmap::{create_backend_mem, create_default_mem, FileBackend, HostMem
 pub use listener::KvmIoListener;
 pub use listener::KvmMemoryListener;
 pub use listener::{Listener, ListenerReqType};
+pub use protected::ProtectedMemory;
 pub use region::{FlatRange, Region, RegionIoEventFd, RegionType};
 
 /// Read data from Region to argument `data`,
@@ -63,10 +63,20 @@ pub trait Listener: Send + Sync {
     ) -> Result<()> {
         Ok(())
     }
+
+    /// Function called after all the region changes of a topology update are
+    /// handled, so that the listener can apply the deltas to its backend at once.
+    fn commit(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
+/// Max size of a KVM memory slot. Larger Ram range is split into multiple slots, which
+/// keeps the slot within the limit of KVM and bounds the dirty bitmap of each slot.
+const MAX_SLOT_SIZE: u64 = 1 << 40;
+
 /// Records information that manage the slot resource and current usage.
-#[derive(Default, Copy, Clone)]
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
 struct MemSlot {
     /// Index of a memory slot.
     index: u32,
@@ -77,6 +87,57 @@ struct MemSlot {
     size: u64,
     /// Host address.
     host_addr: u64,
+    /// Flags of KVM memory slot.
+    flags: u32,
+}
+
+impl MemSlot {
+    fn end_addr(&self) -> u64 {
+        self.guest_addr + self.size
+    }
+
+    /// Whether the slot directly follows `prev` in both guest and host address space.
+    fn follows(&self, prev: &MemSlot) -> bool {
+        prev.end_addr() == self.guest_addr
+            && prev.host_addr + prev.size == self.host_addr
+            && prev.flags == self.flags
+    }
+
+    /// Part of the slot in the guest address range `[start, end)`.
+    fn sub_slot(&self, start: u64, end: u64) -> MemSlot {
+        MemSlot {
+            index: self.index,
+            guest_addr: start,
+            size: end - start,
+            host_addr: self.host_addr + (start - self.guest_addr),
+            flags: self.flags,
+        }
+    }
+}
+
+/// Merge the slots which are contiguous in both guest and host address space.
+fn coalesce_slots(mut slots: Vec<MemSlot>) -> Vec<MemSlot> {
+    slots.sort_by_key(|s| s.guest_addr);
+    let mut merged: Vec<MemSlot> = Vec::with_capacity(slots.len());
+    for slot in slots {
+        match merged.last_mut() {
+            Some(last) if slot.follows(last) => last.size += slot.size,
+            _ => merged.push(slot),
+        }
+    }
+    merged
+}
+
+/// Split the slot into slots no larger than `max_size`.
+fn split_slot(slot: &MemSlot, max_size: u64) -> Vec<MemSlot> {
+    let mut slots = Vec::new();
+    let mut start = slot.guest_addr;
+    while start < slot.end_addr() {
+        let end = std::cmp::min(start + max_size, slot.end_addr());
+        slots.push(slot.sub_slot(start, end));
+        start = end;
+    }
+    slots
 }
 
 /// Kvm memory listener.
@@ -84,8 +145,13 @@ struct MemSlot {
 pub struct KvmMemoryListener {
     /// Id of AddressSpace.
     as_id: Arc<AtomicU32>,
-    /// Record all MemSlots.
+    /// Max number of slots supported by host.
+    nr_slots: u32,
+    /// Record all MemSlots, which grows as slots are used until `nr_slots`.
     slots: Arc<Mutex<Vec<MemSlot>>>,
+    /// Ram ranges added in the current topology update, which are coalesced and
+    /// registered to KVM when the update is committed.
+    pending_slots: Arc<Mutex<Vec<MemSlot>>>,
     /// Whether enabled as a memory listener.
     enabled: bool,
 }
@@ -99,7 +165,9 @@ impl KvmMemoryListener {
     pub fn new(nr_slots: u32) -> KvmMemoryListener {
         KvmMemoryListener {
             as_id: Arc::new(AtomicU32::new(0)),
-            slots: Arc::new(Mutex::new(vec![MemSlot::default(); nr_slots as usize])),
+            nr_slots,
+            slots: Arc::new(Mutex::new(Vec::new())),
+            pending_slots: Arc::new(Mutex::new(Vec::new())),
             enabled: false,
         }
     }
@@ -119,8 +187,31 @@ impl KvmMemoryListener {
     /// * Given memory slot overlap with existed one.
     fn get_free_slot(&self, guest_addr: u64, size: u64, host_addr: u64) -> Result<u32> {
         let mut slots = self.slots.lock().unwrap();
+        Self::check_overlap(&slots, guest_addr, size)?;
+
+        if let Some(slot) = slots.iter_mut().find(|s| s.size == 0) {
+            slot.guest_addr = guest_addr;
+            slot.size = size;
+            slot.host_addr = host_addr;
+            return Ok(slot.index);
+        }
+        if slots.len() < self.nr_slots as usize {
+            let index = slots.len() as u32;
+            slots.push(MemSlot {
+                index,
+                guest_addr,
+                size,
+                host_addr,
+                flags: 0,
+            });
+            return Ok(index);
+        }
+
+        Err(anyhow!(AddressSpaceError::NoAvailKvmSlot(slots.len())))
+    }
 
-        // check if the given address range overlaps with exist ones
+    /// Check if the given address range overlaps with the slots.
+    fn check_overlap(slots: &[MemSlot], guest_addr: u64, size: u64) -> Result<()> {
         let range = AddressRange::from((guest_addr, size));
         slots.iter().try_for_each::<_, Result<()>>(|s| {
             if AddressRange::from((s.guest_addr, s.size))
@@ -133,19 +224,14 @@ impl KvmMemoryListener {
                 }));
             }
             Ok(())
-        })?;
-
-        for (index, slot) in slots.iter_mut().enumerate() {
-            if slot.size == 0 {
-                slot.index = index as u32;
-                slot.guest_addr = guest_addr;
-                slot.size = size;
-                slot.host_addr = host_addr;
-                return Ok(slot.index);
-            }
-        }
+        })
+    }
 
-        Err(anyhow!(AddressSpaceError::NoAvailKvmSlot(slots.len())))
+    /// Number of slots which are not used.
+    fn nr_free_slots(&self) -> usize {
+        let slots = self.slots.lock().unwrap();
+        let nr_used = slots.iter().filter(|s| s.size != 0).count();
+        self.nr_slots as usize - nr_used
     }
 
     /// Delete a slot after finding it according to the given arguments.
@@ -201,7 +287,87 @@ impl KvmMemoryListener {
         Ok(AddressRange::new(aligned_addr, aligned_size))
     }
 
+    /// Register a slot to KVM.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The slot to register, whose index is allocated here.
+    fn register_slot(&self, slot: &MemSlot) -> Result<()> {
+        let slot_idx = self
+            .get_free_slot(slot.guest_addr, slot.size, slot.host_addr)
+            .with_context(|| "Failed to get available KVM mem slot")?;
+        self.slots.lock().unwrap()[slot_idx as usize].flags = slot.flags;
+
+        let kvm_region = kvm_userspace_memory_region {
+            slot: slot_idx | (self.as_id.load(Ordering::SeqCst) << 16),
+            guest_phys_addr: slot.guest_addr,
+            memory_size: slot.size,
+            userspace_addr: slot.host_addr,
+            flags: slot.flags,
+        };
+        unsafe {
+            KVM_FDS
+                .load()
+                .add_mem_slot(kvm_region)
+                .with_context(|| "Failed to add memory slot to kvm")?;
+
+            KVM_FDS
+                .load()
+                .vm_fd
+                .as_ref()
+                .unwrap()
+                .set_user_memory_region(kvm_region)
+                .or_else(|e| {
+                    self.delete_slot(slot.guest_addr, slot.size)
+                        .with_context(|| "Failed to delete Kvm mem slot")?;
+                    Err(e).with_context(|| {
+                        format!(
+                            "KVM register memory region failed: addr 0x{:X}, size 0x{:X}",
+                            slot.guest_addr, slot.size
+                        )
+                    })
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Unregister a slot from KVM.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem_slot` - The slot deleted from `slots`.
+    fn unregister_slot(&self, mem_slot: &MemSlot) -> Result<()> {
+        let kvm_region = kvm_userspace_memory_region {
+            slot: mem_slot.index | (self.as_id.load(Ordering::SeqCst) << 16),
+            guest_phys_addr: mem_slot.guest_addr,
+            memory_size: 0_u64,
+            userspace_addr: mem_slot.host_addr,
+            flags: 0,
+        };
+        unsafe {
+            KVM_FDS
+                .load()
+                .remove_mem_slot(kvm_region)
+                .with_context(|| "Failed to remove memory slot to kvm")?;
+
+            KVM_FDS
+                .load()
+                .vm_fd
+                .as_ref()
+                .unwrap()
+                .set_user_memory_region(kvm_region)
+                .with_context(|| {
+                    format!(
+                        "KVM unregister memory region failed: addr 0x{:X}",
+                        mem_slot.guest_addr,
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
     /// Callback function for adding Region, which only care about Ram-type Region yet.
+    /// The Ram range is registered to KVM when the topology update is committed.
     ///
     /// # Arguments
     ///
@@ -241,49 +407,27 @@ impl KvmMemoryListener {
             + flat_range.offset_in_region
             + align_adjust;
 
-        let slot_idx = self
-            .get_free_slot(aligned_addr.raw_value(), aligned_size, aligned_hva)
-            .with_context(|| "Failed to get available KVM mem slot")?;
-
         let mut flags = 0_u32;
         if flat_range.owner.get_rom_device_romd().unwrap_or(false) {
             flags |= KVM_MEM_READONLY;
         }
-        let kvm_region = kvm_userspace_memory_region {
-            slot: slot_idx | (self.as_id.load(Ordering::SeqCst) << 16),
-            guest_phys_addr: aligned_addr.raw_value(),
-            memory_size: aligned_size,
-            userspace_addr: aligned_hva,
-            flags,
-        };
-        unsafe {
-            KVM_FDS
-                .load()
-                .add_mem_slot(kvm_region)
-                .with_context(|| "Failed to add memory slot to kvm")?;
 
-            KVM_FDS
-                .load()
-                .vm_fd
-                .as_ref()
-                .unwrap()
-                .set_user_memory_region(kvm_region)
-                .or_else(|e| {
-                    self.delete_slot(aligned_addr.raw_value(), aligned_size)
-                        .with_context(|| "Failed to delete Kvm mem slot")?;
-                    Err(e).with_context(|| {
-                        format!(
-                            "KVM register memory region failed: addr 0x{:X}, size 0x{:X}",
-                            aligned_addr.raw_value(),
-                            aligned_size
-                        )
-                    })
-                })?;
-        }
+        let mut pending_slots = self.pending_slots.lock().unwrap();
+        let guest_addr = aligned_addr.raw_value();
+        Self::check_overlap(&self.slots.lock().unwrap(), guest_addr, aligned_size)?;
+        Self::check_overlap(&pending_slots, guest_addr, aligned_size)?;
+        pending_slots.push(MemSlot {
+            index: 0,
+            guest_addr,
+            size: aligned_size,
+            host_addr: aligned_hva,
+            flags,
+        });
         Ok(())
     }
 
     /// Callback function for deleting Region, which only care about Ram-type Region yet.
+    /// Part of the coalesced slots out of the region is registered to KVM again.
     ///
     /// # Arguments
     ///
@@ -296,49 +440,62 @@ impl KvmMemoryListener {
             return Ok(());
         }
 
-        let (aligned_addr, aligned_size) =
-            Self::align_mem_slot(flat_range.addr_range, host_page_size())
-                .map(|r| (r.base, r.size))
-                .with_context(|| "Failed to align mem slot")?;
+        let (start, end) = Self::align_mem_slot(flat_range.addr_range, host_page_size())
+            .map(|r| (r.base.raw_value(), r.end_addr().raw_value()))
+            .with_context(|| "Failed to align mem slot")?;
 
-        let mem_slot = match self.delete_slot(aligned_addr.raw_value(), aligned_size) {
-            Ok(m) => m,
-            Err(_) => {
-                debug!("no match mem slot registered to KVM, just return");
-                return Ok(());
-            }
-        };
+        self.pending_slots
+            .lock()
+            .unwrap()
+            .retain(|s| s.end_addr() <= start || s.guest_addr >= end);
 
-        let kvm_region = kvm_userspace_memory_region {
-            slot: mem_slot.index | (self.as_id.load(Ordering::SeqCst) << 16),
-            guest_phys_addr: mem_slot.guest_addr,
-            memory_size: 0_u64,
-            userspace_addr: mem_slot.host_addr,
-            flags: 0,
-        };
-        unsafe {
-            KVM_FDS
-                .load()
-                .remove_mem_slot(kvm_region)
-                .with_context(|| "Failed to remove memory slot to kvm")?;
+        let mut deleted = Vec::new();
+        for slot in self.slots.lock().unwrap().iter_mut() {
+            if slot.size != 0 && slot.guest_addr < end && slot.end_addr() > start {
+                deleted.push(*slot);
+                // set slot size to zero, so it can be reused later
+                slot.size = 0;
+            }
+        }
+        if deleted.is_empty() {
+            debug!("no match mem slot registered to KVM, just return");
+            return Ok(());
+        }
 
-            KVM_FDS
-                .load()
-                .vm_fd
-                .as_ref()
-                .unwrap()
-                .set_user_memory_region(kvm_region)
-                .with_context(|| {
-                    format!(
-                        "KVM unregister memory region failed: addr 0x{:X}",
-                        aligned_addr.raw_value(),
-                    )
-                })?;
+        for slot in deleted.iter() {
+            self.unregister_slot(slot)?;
+            if slot.guest_addr < start {
+                self.register_slot(&slot.sub_slot(slot.guest_addr, start))?;
+            }
+            if slot.end_addr() > end {
+                self.register_slot(&slot.sub_slot(end, slot.end_addr()))?;
+            }
         }
 
         Ok(())
     }
 
+    /// Register the Ram ranges added in the topology update to KVM, the contiguous
+    /// ones are coalesced to save slots, and the large ones are split.
+    fn commit_slots(&self) -> Result<()> {
+        let pending_slots = std::mem::take(&mut *self.pending_slots.lock().unwrap());
+        let slots: Vec<MemSlot> = coalesce_slots(pending_slots)
+            .iter()
+            .flat_map(|s| split_slot(s, MAX_SLOT_SIZE))
+            .collect();
+
+        let nr_free = self.nr_free_slots();
+        if slots.len() > nr_free {
+            bail!(
+                "{} KVM mem slots are needed, but only {} of total {} are available",
+                slots.len(),
+                nr_free,
+                self.nr_slots
+            );
+        }
+        slots.iter().try_for_each(|s| self.register_slot(s))
+    }
+
     /// Register a IoEvent to `/dev/kvm`.
     ///
     /// # Arguments
@@ -473,6 +630,11 @@ impl Listener for KvmMemoryListener {
 
         req_ret.with_context(|| AddressSpaceError::ListenerRequest(req_type))
     }
+
+    /// Register the Ram ranges added in the topology update to KVM.
+    fn commit(&self) -> Result<()> {
+        self.commit_slots()
+    }
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -726,6 +888,104 @@ mod test {
             .is_err());
     }
 
+    #[test]
+    fn test_coalesce_split_slots() {
+        let slot = |guest_addr: u64, size: u64, host_addr: u64, flags: u32| MemSlot {
+            index: 0,
+            guest_addr,
+            size,
+            host_addr,
+            flags,
+        };
+
+        // Contiguous slots are merged, out of order is fine.
+        let slots = vec![
+            slot(0x2000, 0x1000, 0x12000, 0),
+            slot(0, 0x2000, 0x10000, 0),
+            // Not contiguous in host address space.
+            slot(0x3000, 0x1000, 0x20000, 0),
+            // Different flags.
+            slot(0x4000, 0x1000, 0x21000, KVM_MEM_READONLY),
+        ];
+        let merged = coalesce_slots(slots);
+        assert_eq!(
+            merged,
+            vec![
+                slot(0, 0x3000, 0x10000, 0),
+                slot(0x3000, 0x1000, 0x20000, 0),
+                slot(0x4000, 0x1000, 0x21000, KVM_MEM_READONLY),
+            ]
+        );
+
+        let split = split_slot(&slot(0x1000, 0x2800, 0x10000, 0), 0x1000);
+        assert_eq!(
+            split,
+            vec![
+                slot(0x1000, 0x1000, 0x10000, 0),
+                slot(0x2000, 0x1000, 0x11000, 0),
+                slot(0x3000, 0x800, 0x12000, 0),
+            ]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_commit_ram_region() {
+        let kvm_fds = KVMFds::new();
+        if kvm_fds.vm_fd.is_none() {
+            return;
+        }
+        KVM_FDS.store(Arc::new(kvm_fds));
+
+        let kml = KvmMemoryListener::new(2);
+        let page_size = host_page_size();
+        let mem_mapping = Arc::new(
+            HostMemMapping::new(
+                GuestAddress(0),
+                None,
+                4 * page_size,
+                None,
+                false,
+                false,
+                false,
+            )
+            .unwrap(),
+        );
+        let region = Region::init_ram_region(mem_mapping, "ram");
+        let flat_range = |offset: u64, size: u64| FlatRange {
+            addr_range: AddressRange::from((offset, size)),
+            owner: region.clone(),
+            offset_in_region: offset,
+            rom_dev_romd: None,
+        };
+
+        // Contiguous ranges are registered in one slot.
+        for i in 0..4 {
+            kml.handle_request(
+                Some(&flat_range(i * page_size, page_size)),
+                None,
+                ListenerReqType::AddRegion,
+            )
+            .unwrap();
+        }
+        kml.commit().unwrap();
+        assert_eq!(kml.nr_free_slots(), 1);
+
+        // Deleting the middle of the slot leaves two slots.
+        kml.handle_request(
+            Some(&flat_range(page_size, page_size)),
+            None,
+            ListenerReqType::DeleteRegion,
+        )
+        .unwrap();
+        assert_eq!(kml.nr_free_slots(), 0);
+        let slots = kml.slots.lock().unwrap();
+        assert_eq!(slots[0].guest_addr, 0);
+        assert_eq!(slots[0].size, page_size);
+        assert_eq!(slots[1].guest_addr, 2 * page_size);
+        assert_eq!(slots[1].size, 2 * page_size);
+    }
+
     #[test]
     #[serial]
     fn test_add_del_ioeventfd() {
@@ -0,0 +1,125 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Guest memory of a protected VM. The registered memory is private to guest and
+/// inaccessible to host, except the parts guest shares for device I/O. Memory never
+/// registered, such as MMIO regions, stays shared.
+#[derive(Default)]
+pub struct ProtectedMemory {
+    /// Private ranges, key is the start address and value is the end address (exclusive).
+    private: Mutex<BTreeMap<u64, u64>>,
+}
+
+impl ProtectedMemory {
+    /// Register guest memory as protected, which is private until guest shares it.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Start guest address.
+    /// * `size` - Size of the memory.
+    pub fn protect(&self, addr: u64, size: u64) {
+        self.set_shared(addr, size, false);
+    }
+
+    /// Convert guest memory between shared and private, as requested by guest.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Start guest address.
+    /// * `size` - Size of the memory.
+    /// * `shared` - Whether the memory is shared with host.
+    pub fn set_shared(&self, addr: u64, size: u64, shared: bool) {
+        if size == 0 {
+            return;
+        }
+        let mut private = self.private.lock().unwrap();
+        let mut start = addr;
+        let mut end = addr.saturating_add(size);
+
+        // Remove all ranges overlapping or adjacent to the given one.
+        let overlaps: Vec<(u64, u64)> = private
+            .range(..=end)
+            .rev()
+            .take_while(|(_, &e)| e >= start)
+            .map(|(&s, &e)| (s, e))
+            .collect();
+        for (s, e) in overlaps {
+            private.remove(&s);
+            if shared {
+                if s < start {
+                    private.insert(s, start);
+                }
+                if e > end {
+                    private.insert(end, e);
+                }
+            } else {
+                start = start.min(s);
+                end = end.max(e);
+            }
+        }
+        if !shared {
+            private.insert(start, end);
+        }
+    }
+
+    /// Whether the given guest memory is all shared, and thus accessible to host.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Start guest address.
+    /// * `size` - Size of the memory.
+    pub fn is_shared(&self, addr: u64, size: u64) -> bool {
+        let end = addr.saturating_add(size);
+        let private = self.private.lock().unwrap();
+        !matches!(private.range(..end).next_back(), Some((_, &e)) if e > addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protected_memory() {
+        let mem = ProtectedMemory::default();
+        assert!(mem.is_shared(0, 0x10000));
+
+        mem.protect(0x1000, 0x4000);
+        assert!(mem.is_shared(0, 0x1000));
+        assert!(!mem.is_shared(0, 0x1001));
+        assert!(!mem.is_shared(0x4fff, 1));
+        assert!(mem.is_shared(0x5000, 0x1000));
+
+        // Share the middle of the private range.
+        mem.set_shared(0x2000, 0x1000, true);
+        assert!(mem.is_shared(0x2000, 0x1000));
+        assert!(!mem.is_shared(0x1fff, 2));
+        assert!(!mem.is_shared(0x2fff, 2));
+        assert_eq!(mem.private.lock().unwrap().len(), 2);
+
+        // Converting back merges the ranges.
+        mem.set_shared(0x2000, 0x1000, false);
+        assert!(!mem.is_shared(0x2000, 1));
+        assert_eq!(mem.private.lock().unwrap().len(), 1);
+
+        // Share across the boundary of the private range.
+        mem.set_shared(0x4000, 0x2000, true);
+        assert!(mem.is_shared(0x4000, 0x2000));
+        assert!(!mem.is_shared(0x3fff, 1));
+        mem.set_shared(0, 0x10000, true);
+        assert!(mem.is_shared(0, 0x10000));
+        assert!(mem.private.lock().unwrap().is_empty());
+    }
+}
@@ -330,6 +330,29 @@ impl Region {
         region
     }
 
+    /// Initialize read-only memory region, which is a RomDevice-type region in read-only
+    /// mode whose writes are dropped. It's mapped to KVM read-only, so guest writes to it
+    /// trap to StratoVirt instead of modifying the memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem_mapping` - Mapped memory of this region.
+    pub fn init_rom_region(mem_mapping: Arc<HostMemMapping>, name: &str) -> Region {
+        let rom_name = name.to_string();
+        let ops = RegionOps {
+            read: Arc::new(|_: &mut [u8], _: GuestAddress, _: u64| -> bool { false }),
+            write: Arc::new(move |_: &[u8], base: GuestAddress, offset: u64| -> bool {
+                debug!(
+                    "Drop the write to rom {} at 0x{:x}",
+                    rom_name,
+                    base.raw_value() + offset
+                );
+                true
+            }),
+        };
+        Region::init_rom_device_region(mem_mapping, ops, name)
+    }
+
     /// Initialize RamDevice-type region.
     ///
     /// # Arguments
@@ -478,6 +501,46 @@ impl Region {
         self.mem_mapping.as_ref().map(|r| r.host_address())
     }
 
+    /// Get the size of memory which is resident in host within the range of this region.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Offset of the range in this region.
+    /// * `size` - Size of the range.
+    ///
+    /// # Errors
+    ///
+    /// Return Error if this region isn't backed by host memory, or the range is invalid.
+    pub fn resident_size(&self, offset: u64, size: u64) -> Result<u64> {
+        match self.mem_mapping.as_ref() {
+            Some(mem_mapping) => mem_mapping.resident_size(offset, size),
+            None => Err(anyhow!(AddressSpaceError::RegionType(self.region_type))),
+        }
+    }
+
+    /// Load the content of RomDevice-type region, which writes the host memory directly
+    /// regardless of the mode of region.
+    ///
+    /// # Arguments
+    ///
+    /// * `src` - Source data.
+    /// * `offset` - Offset in this region.
+    /// * `count` - Size of data.
+    pub fn load_rom(&self, src: &mut dyn std::io::Read, offset: u64, count: u64) -> Result<()> {
+        if self.region_type != RegionType::RomDevice {
+            return Err(anyhow!(AddressSpaceError::RegionType(self.region_type)));
+        }
+        self.check_valid_offset(offset, count)
+            .with_context(|| AddressSpaceError::InvalidOffset(offset, count, self.size()))?;
+        let host_addr = self.mem_mapping.as_ref().unwrap().host_address();
+        // SAFETY: The range is checked to be in the mapped memory.
+        let slice = unsafe {
+            std::slice::from_raw_parts_mut((host_addr + offset) as *mut u8, count as usize)
+        };
+        src.read_exact(slice)
+            .with_context(|| "Failed to load the content of rom")
+    }
+
     pub fn get_host_share(&self) -> Option<bool> {
         if self.region_type != RegionType::Ram
             && self.region_type != RegionType::RamDevice
@@ -488,6 +551,12 @@ impl Region {
         self.mem_mapping.as_ref().map(|r| r.mem_shared())
     }
 
+    /// Get whether transparent huge page is advised for the host memory of this region.
+    /// Return `None` if it is not backed by host memory or uses the host default.
+    pub fn get_host_thp(&self) -> Option<bool> {
+        self.mem_mapping.as_ref().and_then(|r| r.thp())
+    }
+
     /// Get the file information if this region is backed by host-memory.
     /// Return `None` if it is not a Ram-type region.
     pub fn get_file_backend(&self) -> Option<FileBackend> {
@@ -1194,6 +1263,34 @@ mod test {
         assert_eq!(&slice, &mut res_slice2);
     }
 
+    #[test]
+    fn test_rom_region() {
+        let mem_mapping = Arc::new(
+            HostMemMapping::new(GuestAddress(0), None, 1024, None, false, false, false).unwrap(),
+        );
+        let rom_region = Region::init_rom_region(mem_mapping, "rom");
+        assert_eq!(rom_region.region_type(), RegionType::RomDevice);
+        assert_eq!(rom_region.get_rom_device_romd(), Some(true));
+
+        let data: [u8; 8] = [0x5a; 8];
+        assert!(rom_region.load_rom(&mut data.as_ref(), 16, 8).is_ok());
+        assert!(rom_region.load_rom(&mut data.as_ref(), 1020, 8).is_err());
+
+        // Writes to rom are dropped.
+        let zeros: [u8; 8] = [0; 8];
+        assert!(rom_region
+            .write(&mut zeros.as_ref(), GuestAddress(0), 16, 8)
+            .is_ok());
+        let mut res_data: [u8; 8] = [0; 8];
+        assert!(rom_region
+            .read(&mut res_data.as_mut(), GuestAddress(0), 16, 8)
+            .is_ok());
+        assert_eq!(res_data, data);
+
+        let container = Region::init_container_region(1024, "container");
+        assert!(container.load_rom(&mut data.as_ref(), 0, 8).is_err());
+    }
+
     #[test]
     fn test_io_region() {
         let test_dev = Arc::new(Mutex::new(TestDevice::default()));
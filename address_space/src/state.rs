@@ -11,7 +11,7 @@
 // See the Mulan PSL v2 for more details.
 
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::mem::size_of;
 use std::sync::Arc;
 
@@ -137,6 +137,25 @@ impl MigrationHook for AddressSpace {
         Ok(())
     }
 
+    fn load_memory(&self, memory: &mut File, state: &[u8]) -> Result<()> {
+        let address_space_state: &AddressSpaceState =
+            AddressSpaceState::from_bytes(&state[0..size_of::<AddressSpaceState>()])
+                .with_context(|| MigrationError::FromBytesError("MEMORY"))?;
+
+        for ram_state in address_space_state.ram_region_state
+            [0..address_space_state.nr_ram_region as usize]
+            .iter()
+        {
+            memory
+                .seek(SeekFrom::Start(ram_state.offset))
+                .map_err(|e| MigrationError::RestoreVmMemoryErr(e.to_string()))?;
+            self.write(memory, GuestAddress(ram_state.base_address), ram_state.size)
+                .map_err(|e| MigrationError::RestoreVmMemoryErr(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
     fn send_memory(&self, fd: &mut dyn Write, range: MemBlock) -> Result<()> {
         self.read(fd, GuestAddress(range.gpa), range.len)
             .map_err(|e| MigrationError::SendVmMemoryErr(e.to_string()))?;
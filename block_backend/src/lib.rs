@@ -10,6 +10,8 @@
 // NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
 // See the Mulan PSL v2 for more details.
 
+pub mod mirror;
+pub mod nbd;
 pub mod qcow2;
 
 mod file;
@@ -22,6 +24,7 @@ use std::{
 
 use anyhow::{bail, Context, Result};
 use log::{error, info};
+use mirror::{DirtyLog, DIRTY_LOG_LIST};
 
 use machine_manager::{
     config::DiskFormat,
@@ -90,11 +93,20 @@ pub trait BlockDriverOps<T: Clone>: Send {
 
 pub fn create_block_backend<T: Clone + 'static + Send + Sync>(
     file: File,
-    aio: Aio<T>,
+    mut aio: Aio<T>,
     prop: BlockProperty,
 ) -> Result<Arc<Mutex<dyn BlockDriverOps<T>>>> {
     match prop.format {
         DiskFormat::Raw => {
+            // Track the data written by guest, so that the drive can be mirrored
+            // during storage migration.
+            let dirty_log = Arc::new(DirtyLog::new(aio.incomplete_cnt.clone()));
+            let cloned_log = dirty_log.clone();
+            aio.set_write_notifier(Arc::new(move |offset, len| cloned_log.mark(offset, len)));
+            DIRTY_LOG_LIST
+                .lock()
+                .unwrap()
+                .insert(prop.id.clone(), dirty_log);
             let mut raw_file = RawDriver::new(file, aio, prop.clone());
             let file_size = raw_file.disk_size()?;
             if file_size & (prop.req_align as u64 - 1) != 0 {
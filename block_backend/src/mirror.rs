@@ -0,0 +1,312 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::os::unix::fs::FileExt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use once_cell::sync::Lazy;
+
+use crate::nbd::NbdClient;
+
+/// Granularity of tracking the data written to drive, in bytes.
+pub const DIRTY_LOG_GRANULARITY: u64 = 1 << 20;
+
+/// Dirty logs of all drives supporting storage migration, indexed by drive id.
+pub static DIRTY_LOG_LIST: Lazy<Mutex<HashMap<String, Arc<DirtyLog>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Log of the data written to drive by guest.
+pub struct DirtyLog {
+    enabled: AtomicBool,
+    /// Index of the chunks written since last synchronization.
+    chunks: Mutex<BTreeSet<u64>>,
+    /// Count of the in-flight requests of the drive.
+    incomplete: Arc<AtomicU64>,
+}
+
+impl DirtyLog {
+    pub fn new(incomplete: Arc<AtomicU64>) -> Self {
+        DirtyLog {
+            enabled: AtomicBool::new(false),
+            chunks: Mutex::new(BTreeSet::new()),
+            incomplete,
+        }
+    }
+
+    /// Mark the data written by a completed request.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Offset of the data in drive.
+    /// * `len` - Length of the data.
+    pub fn mark(&self, offset: u64, len: u64) {
+        if !self.enabled.load(Ordering::Acquire) || len == 0 {
+            return;
+        }
+        let start = offset / DIRTY_LOG_GRANULARITY;
+        let end = (offset + len - 1) / DIRTY_LOG_GRANULARITY;
+        let mut chunks = self.chunks.lock().unwrap();
+        for chunk in start..=end {
+            chunks.insert(chunk);
+        }
+    }
+
+    fn start(&self) {
+        self.chunks.lock().unwrap().clear();
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    fn stop(&self) {
+        self.enabled.store(false, Ordering::Release);
+        self.chunks.lock().unwrap().clear();
+    }
+
+    fn take(&self) -> BTreeSet<u64> {
+        std::mem::take(&mut *self.chunks.lock().unwrap())
+    }
+
+    /// Wait for all in-flight requests to complete, so that they are logged.
+    fn drain(&self) {
+        while self.incomplete.load(Ordering::Acquire) != 0 {
+            continue;
+        }
+    }
+}
+
+/// Mirror of drive, which copies the whole drive and then the data written by
+/// guest to the NBD export with the same name as drive id.
+pub struct BlockMirror {
+    id: String,
+    file: File,
+    size: u64,
+    log: Arc<DirtyLog>,
+    client: NbdClient,
+}
+
+impl BlockMirror {
+    /// Create mirror of drive, data written by guest is logged from now on.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of drive.
+    /// * `path` - Path of drive on host.
+    /// * `uri` - Address of NBD server, `tcp:<ip>:<port>` or `unix:<path>`.
+    pub fn new(id: &str, path: &str, uri: &str) -> Result<Self> {
+        let log = DIRTY_LOG_LIST
+            .lock()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .with_context(|| {
+                format!(
+                    "Drive {} doesn't support storage migration, only raw format is supported",
+                    id
+                )
+            })?;
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open drive file {}", path))?;
+        let size = file.seek(SeekFrom::End(0))?;
+        let client = NbdClient::connect(uri, id)?;
+        if client.size < size {
+            bail!(
+                "Size {} of target drive {} is smaller than source drive {}",
+                client.size,
+                id,
+                size
+            );
+        }
+        log.start();
+        info!("Start mirroring drive {} to {}", id, uri);
+
+        Ok(BlockMirror {
+            id: id.to_string(),
+            file,
+            size,
+            log,
+            client,
+        })
+    }
+
+    /// Copy a chunk of drive, return the length of data copied.
+    fn copy_chunk(&mut self, chunk: u64, buf: &mut Vec<u8>) -> Result<u64> {
+        let offset = chunk * DIRTY_LOG_GRANULARITY;
+        if offset >= self.size {
+            return Ok(0);
+        }
+        let len = std::cmp::min(DIRTY_LOG_GRANULARITY, self.size - offset);
+        buf.resize(len as usize, 0);
+        self.file
+            .read_exact_at(buf, offset)
+            .with_context(|| format!("Failed to read drive {} at {}", self.id, offset))?;
+        if self.client.support_write_zeroes() && buf.iter().all(|b| *b == 0) {
+            self.client.write_zeroes(offset, len as u32)?;
+        } else {
+            self.client.write(offset, buf)?;
+        }
+        Ok(len)
+    }
+
+    /// Copy the whole drive.
+    ///
+    /// # Arguments
+    ///
+    /// * `check` - Callback with the length of data copied, which aborts copying if it fails.
+    pub fn copy_all(&mut self, check: &mut dyn FnMut(u64) -> Result<()>) -> Result<()> {
+        let mut buf = Vec::new();
+        for offset in (0..self.size).step_by(DIRTY_LOG_GRANULARITY as usize) {
+            let len = self.copy_chunk(offset / DIRTY_LOG_GRANULARITY, &mut buf)?;
+            check(len)?;
+        }
+        Ok(())
+    }
+
+    /// Copy the data written by guest since last copying, return whether any
+    /// data is copied.
+    ///
+    /// # Arguments
+    ///
+    /// * `check` - Callback with the length of data copied, which aborts copying if it fails.
+    pub fn sync(&mut self, check: &mut dyn FnMut(u64) -> Result<()>) -> Result<bool> {
+        let chunks = self.log.take();
+        let mut buf = Vec::new();
+        for chunk in chunks.iter() {
+            let len = self.copy_chunk(*chunk, &mut buf)?;
+            check(len)?;
+        }
+        Ok(!chunks.is_empty())
+    }
+
+    /// Copy the remaining data and disconnect from target, the guest must be
+    /// paused before it.
+    pub fn complete(&mut self) -> Result<()> {
+        self.log.drain();
+        self.sync(&mut |_| Ok(()))?;
+        self.client.flush()?;
+        self.client.disconnect()?;
+        self.log.stop();
+        info!("Mirroring drive {} is completed", self.id);
+        Ok(())
+    }
+}
+
+impl Drop for BlockMirror {
+    fn drop(&mut self) {
+        self.log.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs::{remove_file, OpenOptions};
+
+    use crate::nbd::NbdServer;
+    use machine_manager::config::MigrateMode;
+
+    fn create_file(path: &str, len: u64) -> File {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+        file.set_len(len).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_dirty_log() {
+        let log = DirtyLog::new(Arc::new(AtomicU64::new(0)));
+        log.mark(0, 4096);
+        assert!(log.take().is_empty());
+
+        log.start();
+        log.mark(DIRTY_LOG_GRANULARITY - 512, 1024);
+        log.mark(3 * DIRTY_LOG_GRANULARITY, DIRTY_LOG_GRANULARITY);
+        log.mark(5 * DIRTY_LOG_GRANULARITY, 0);
+        assert_eq!(log.take().into_iter().collect::<Vec<u64>>(), vec![0, 1, 3]);
+        assert!(log.take().is_empty());
+
+        log.mark(0, 512);
+        log.stop();
+        assert!(log.take().is_empty());
+    }
+
+    #[test]
+    fn test_block_mirror() {
+        let src = "/tmp/test_mirror_src.img";
+        let dst = "/tmp/test_mirror_dst.img";
+        let sock = "/tmp/test_mirror_nbd.sock";
+        let size = 3 * DIRTY_LOG_GRANULARITY + 4096;
+        let src_file = create_file(src, size);
+        let dst_file = create_file(dst, size);
+        src_file.write_all_at(&[1_u8; 4096], 0).unwrap();
+        src_file
+            .write_all_at(&[2_u8; 4096], 3 * DIRTY_LOG_GRANULARITY)
+            .unwrap();
+        let mut exports = HashMap::new();
+        exports.insert("mirror-drive".to_string(), dst_file.try_clone().unwrap());
+        let _server = NbdServer::start(MigrateMode::Unix, sock, exports).unwrap();
+
+        let uri = format!("unix:{}", sock);
+        assert!(BlockMirror::new("mirror-drive", src, &uri).is_err());
+        let log = Arc::new(DirtyLog::new(Arc::new(AtomicU64::new(0))));
+        DIRTY_LOG_LIST
+            .lock()
+            .unwrap()
+            .insert("mirror-drive".to_string(), log.clone());
+
+        let mut mirror = BlockMirror::new("mirror-drive", src, &uri).unwrap();
+        let mut copied = 0;
+        mirror
+            .copy_all(&mut |len| {
+                copied += len;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(copied, size);
+
+        // Data written by guest is copied by sync.
+        src_file
+            .write_all_at(&[3_u8; 4096], DIRTY_LOG_GRANULARITY)
+            .unwrap();
+        log.mark(DIRTY_LOG_GRANULARITY, 4096);
+        assert!(mirror.sync(&mut |_| Ok(())).unwrap());
+        assert!(!mirror.sync(&mut |_| Ok(())).unwrap());
+        src_file.write_all_at(&[4_u8; 4096], 0).unwrap();
+        log.mark(0, 4096);
+        mirror.complete().unwrap();
+
+        let mut buf = [0_u8; 4096];
+        for (offset, val) in [
+            (0, 4_u8),
+            (DIRTY_LOG_GRANULARITY, 3_u8),
+            (2 * DIRTY_LOG_GRANULARITY, 0_u8),
+            (3 * DIRTY_LOG_GRANULARITY, 2_u8),
+        ] {
+            dst_file.read_exact_at(&mut buf, offset).unwrap();
+            assert_eq!(buf, [val; 4096]);
+        }
+
+        DIRTY_LOG_LIST.lock().unwrap().remove("mirror-drive");
+        remove_file(src).unwrap();
+        remove_file(dst).unwrap();
+    }
+}
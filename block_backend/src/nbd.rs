@@ -0,0 +1,491 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+//! Minimal implementation of the NBD (Network Block Device) protocol, which
+//! is used to transfer drive data during storage migration.
+//!
+//! Only the fixed newstyle negotiation with `NBD_OPT_EXPORT_NAME` and simple
+//! replies are supported.
+
+use std::collections::HashMap;
+use std::fs::{remove_file, File};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::FileExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use log::{error, info, warn};
+
+use machine_manager::config::{parse_incoming_uri, MigrateMode};
+
+const NBD_MAGIC: u64 = 0x4e42_444d_4147_4943;
+const NBD_IHAVEOPT: u64 = 0x4948_4156_454f_5054;
+const NBD_REP_MAGIC: u64 = 0x0003_e889_0455_65a9;
+const NBD_REQUEST_MAGIC: u32 = 0x2560_9513;
+const NBD_SIMPLE_REPLY_MAGIC: u32 = 0x6744_6698;
+
+const NBD_FLAG_FIXED_NEWSTYLE: u16 = 1 << 0;
+const NBD_FLAG_NO_ZEROES: u16 = 1 << 1;
+const NBD_FLAG_C_FIXED_NEWSTYLE: u32 = 1 << 0;
+const NBD_FLAG_C_NO_ZEROES: u32 = 1 << 1;
+
+const NBD_FLAG_HAS_FLAGS: u16 = 1 << 0;
+const NBD_FLAG_READ_ONLY: u16 = 1 << 1;
+const NBD_FLAG_SEND_FLUSH: u16 = 1 << 2;
+const NBD_FLAG_SEND_WRITE_ZEROES: u16 = 1 << 6;
+
+const NBD_OPT_EXPORT_NAME: u32 = 1;
+const NBD_REP_ERR_UNSUP: u32 = (1 << 31) + 1;
+
+const NBD_CMD_READ: u16 = 0;
+const NBD_CMD_WRITE: u16 = 1;
+const NBD_CMD_DISC: u16 = 2;
+const NBD_CMD_FLUSH: u16 = 3;
+const NBD_CMD_WRITE_ZEROES: u16 = 6;
+
+const NBD_EIO: u32 = 5;
+const NBD_EINVAL: u32 = 22;
+const NBD_ENOSPC: u32 = 28;
+
+/// Padding sent after export info if `NBD_FLAG_NO_ZEROES` is not negotiated.
+const EXPORT_INFO_PADDING: usize = 124;
+/// Max length of export name and data of a single request.
+const NBD_MAX_LENGTH: u32 = 32 * 1024 * 1024;
+/// Interval of checking whether the server is stopped while waiting for connections.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+trait NbdStream: Read + Write + Send {}
+
+impl NbdStream for TcpStream {}
+impl NbdStream for UnixStream {}
+
+/// Client of NBD server, which writes data to a single export.
+pub struct NbdClient {
+    stream: Box<dyn NbdStream>,
+    /// Size of the export in bytes.
+    pub size: u64,
+    /// Transmission flags of the export.
+    flags: u16,
+    /// Handle of the next request.
+    handle: u64,
+}
+
+impl NbdClient {
+    /// Connect to NBD server and negotiate the export.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - Address of NBD server, `tcp:<ip>:<port>` or `unix:<path>`.
+    /// * `export` - Name of the export.
+    pub fn connect(uri: &str, export: &str) -> Result<Self> {
+        let (mode, path) = parse_incoming_uri(uri)?;
+        let mut stream: Box<dyn NbdStream> = match mode {
+            MigrateMode::Tcp => Box::new(
+                TcpStream::connect(&path)
+                    .with_context(|| format!("Failed to connect to NBD server {}", uri))?,
+            ),
+            MigrateMode::Unix => Box::new(
+                UnixStream::connect(&path)
+                    .with_context(|| format!("Failed to connect to NBD server {}", uri))?,
+            ),
+            _ => bail!("Only tcp and unix socket are supported by NBD client"),
+        };
+
+        if stream.read_u64::<BigEndian>()? != NBD_MAGIC
+            || stream.read_u64::<BigEndian>()? != NBD_IHAVEOPT
+        {
+            bail!("Server {} doesn't support NBD newstyle negotiation", uri);
+        }
+        let handshake_flags = stream.read_u16::<BigEndian>()?;
+        if handshake_flags & NBD_FLAG_FIXED_NEWSTYLE == 0 {
+            bail!(
+                "Server {} doesn't support NBD fixed newstyle negotiation",
+                uri
+            );
+        }
+        let no_zeroes = handshake_flags & NBD_FLAG_NO_ZEROES != 0;
+        let mut client_flags = NBD_FLAG_C_FIXED_NEWSTYLE;
+        if no_zeroes {
+            client_flags |= NBD_FLAG_C_NO_ZEROES;
+        }
+        stream.write_u32::<BigEndian>(client_flags)?;
+
+        stream.write_u64::<BigEndian>(NBD_IHAVEOPT)?;
+        stream.write_u32::<BigEndian>(NBD_OPT_EXPORT_NAME)?;
+        stream.write_u32::<BigEndian>(export.len() as u32)?;
+        stream.write_all(export.as_bytes())?;
+        stream.flush()?;
+
+        // The server closes the connection if the export doesn't exist.
+        let size = stream
+            .read_u64::<BigEndian>()
+            .with_context(|| format!("Export {} is not found in NBD server {}", export, uri))?;
+        let flags = stream.read_u16::<BigEndian>()?;
+        if !no_zeroes {
+            let mut padding = [0_u8; EXPORT_INFO_PADDING];
+            stream.read_exact(&mut padding)?;
+        }
+        if flags & NBD_FLAG_READ_ONLY != 0 {
+            bail!("Export {} of NBD server {} is read-only", export, uri);
+        }
+
+        Ok(NbdClient {
+            stream,
+            size,
+            flags,
+            handle: 0,
+        })
+    }
+
+    /// Whether the export supports `NBD_CMD_WRITE_ZEROES`.
+    pub fn support_write_zeroes(&self) -> bool {
+        self.flags & NBD_FLAG_SEND_WRITE_ZEROES != 0
+    }
+
+    fn send_request(&mut self, cmd: u16, offset: u64, len: u32, data: &[u8]) -> Result<u64> {
+        self.handle += 1;
+        self.stream.write_u32::<BigEndian>(NBD_REQUEST_MAGIC)?;
+        self.stream.write_u16::<BigEndian>(0)?;
+        self.stream.write_u16::<BigEndian>(cmd)?;
+        self.stream.write_u64::<BigEndian>(self.handle)?;
+        self.stream.write_u64::<BigEndian>(offset)?;
+        self.stream.write_u32::<BigEndian>(len)?;
+        self.stream.write_all(data)?;
+        self.stream.flush()?;
+        Ok(self.handle)
+    }
+
+    fn recv_reply(&mut self, handle: u64) -> Result<()> {
+        if self.stream.read_u32::<BigEndian>()? != NBD_SIMPLE_REPLY_MAGIC {
+            bail!("Invalid magic of NBD reply");
+        }
+        let err = self.stream.read_u32::<BigEndian>()?;
+        if self.stream.read_u64::<BigEndian>()? != handle {
+            bail!("Unexpected handle of NBD reply");
+        }
+        if err != 0 {
+            bail!("NBD request failed with error {}", err);
+        }
+        Ok(())
+    }
+
+    /// Write data to the export at `offset`.
+    pub fn write(&mut self, offset: u64, data: &[u8]) -> Result<()> {
+        if data.len() as u64 > NBD_MAX_LENGTH as u64 {
+            bail!("Length {} of NBD write is too large", data.len());
+        }
+        let handle = self.send_request(NBD_CMD_WRITE, offset, data.len() as u32, data)?;
+        self.recv_reply(handle)
+            .with_context(|| format!("Failed to write NBD export at offset {}", offset))
+    }
+
+    /// Write zeroes to the export at `offset`.
+    pub fn write_zeroes(&mut self, offset: u64, len: u32) -> Result<()> {
+        let handle = self.send_request(NBD_CMD_WRITE_ZEROES, offset, len, &[])?;
+        self.recv_reply(handle)
+            .with_context(|| format!("Failed to write zeroes to NBD export at offset {}", offset))
+    }
+
+    /// Flush the data written to the export to disk.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.flags & NBD_FLAG_SEND_FLUSH == 0 {
+            return Ok(());
+        }
+        let handle = self.send_request(NBD_CMD_FLUSH, 0, 0, &[])?;
+        self.recv_reply(handle)
+            .with_context(|| "Failed to flush NBD export")
+    }
+
+    /// Disconnect from the server, there's no reply for it.
+    pub fn disconnect(&mut self) -> Result<()> {
+        self.send_request(NBD_CMD_DISC, 0, 0, &[])?;
+        Ok(())
+    }
+}
+
+/// NBD server exporting files, the export name is the id of drive.
+pub struct NbdServer {
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    /// Path of unix socket, which is removed when the server stops.
+    sock_path: Option<String>,
+}
+
+impl NbdServer {
+    /// Start NBD server in a new thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - Tcp or unix socket.
+    /// * `path` - Address of tcp socket or path of unix socket.
+    /// * `exports` - Files exported by the server, with their names.
+    pub fn start(mode: MigrateMode, path: &str, exports: HashMap<String, File>) -> Result<Self> {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let exports = Arc::new(exports);
+        let mut sock_path = None;
+        let accept: Box<dyn Fn() -> std::io::Result<Box<dyn NbdStream>> + Send> = match mode {
+            MigrateMode::Tcp => {
+                let listener = TcpListener::bind(path)
+                    .with_context(|| format!("Failed to bind NBD server to {}", path))?;
+                listener.set_nonblocking(true)?;
+                Box::new(move || {
+                    let (stream, _) = listener.accept()?;
+                    stream.set_nonblocking(false)?;
+                    Ok(Box::new(stream) as Box<dyn NbdStream>)
+                })
+            }
+            MigrateMode::Unix => {
+                let _ = remove_file(path);
+                let listener = UnixListener::bind(path)
+                    .with_context(|| format!("Failed to bind NBD server to {}", path))?;
+                listener.set_nonblocking(true)?;
+                sock_path = Some(path.to_string());
+                Box::new(move || {
+                    let (stream, _) = listener.accept()?;
+                    stream.set_nonblocking(false)?;
+                    Ok(Box::new(stream) as Box<dyn NbdStream>)
+                })
+            }
+            _ => bail!("Only tcp and unix socket are supported by NBD server"),
+        };
+
+        let cloned_stopped = stopped.clone();
+        let thread = thread::Builder::new()
+            .name("nbd-server".to_string())
+            .spawn(move || {
+                while !cloned_stopped.load(Ordering::Acquire) {
+                    match accept() {
+                        Ok(stream) => {
+                            let exports = exports.clone();
+                            let ret = thread::Builder::new().name("nbd-conn".to_string()).spawn(
+                                move || {
+                                    if let Err(e) = handle_connection(stream, &exports) {
+                                        error!("NBD connection failed: {:?}", e);
+                                    }
+                                },
+                            );
+                            if let Err(e) = ret {
+                                error!("Failed to create thread for NBD connection: {:?}", e);
+                            }
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                            thread::sleep(ACCEPT_POLL_INTERVAL);
+                        }
+                        Err(e) => {
+                            error!("Failed to accept NBD connection: {:?}", e);
+                            break;
+                        }
+                    }
+                }
+            })
+            .with_context(|| "Failed to create thread for NBD server")?;
+        info!("NBD server is listening on {}", path);
+
+        Ok(NbdServer {
+            stopped,
+            thread: Some(thread),
+            sock_path,
+        })
+    }
+
+    /// Stop accepting new connections.
+    pub fn stop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!("NBD server thread panicked");
+            }
+        }
+        if let Some(path) = self.sock_path.take() {
+            let _ = remove_file(path);
+        }
+    }
+}
+
+impl Drop for NbdServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn negotiate(stream: &mut dyn NbdStream, exports: &HashMap<String, File>) -> Result<Option<File>> {
+    stream.write_u64::<BigEndian>(NBD_MAGIC)?;
+    stream.write_u64::<BigEndian>(NBD_IHAVEOPT)?;
+    stream.write_u16::<BigEndian>(NBD_FLAG_FIXED_NEWSTYLE | NBD_FLAG_NO_ZEROES)?;
+    stream.flush()?;
+
+    let client_flags = stream.read_u32::<BigEndian>()?;
+    if client_flags & NBD_FLAG_C_FIXED_NEWSTYLE == 0 {
+        bail!("NBD client doesn't support fixed newstyle negotiation");
+    }
+    let no_zeroes = client_flags & NBD_FLAG_C_NO_ZEROES != 0;
+
+    loop {
+        if stream.read_u64::<BigEndian>()? != NBD_IHAVEOPT {
+            bail!("Invalid magic of NBD option");
+        }
+        let option = stream.read_u32::<BigEndian>()?;
+        let len = stream.read_u32::<BigEndian>()?;
+        if len > NBD_MAX_LENGTH {
+            bail!("Length {} of NBD option is too large", len);
+        }
+        let mut data = vec![0_u8; len as usize];
+        stream.read_exact(&mut data)?;
+
+        if option != NBD_OPT_EXPORT_NAME {
+            stream.write_u64::<BigEndian>(NBD_REP_MAGIC)?;
+            stream.write_u32::<BigEndian>(option)?;
+            stream.write_u32::<BigEndian>(NBD_REP_ERR_UNSUP)?;
+            stream.write_u32::<BigEndian>(0)?;
+            stream.flush()?;
+            continue;
+        }
+
+        let name = String::from_utf8_lossy(&data).to_string();
+        let file = match exports.get(&name) {
+            Some(file) => file.try_clone()?,
+            None => {
+                // Close the connection as there's no way to report error of
+                // NBD_OPT_EXPORT_NAME.
+                warn!("NBD export {} is not found", name);
+                return Ok(None);
+            }
+        };
+        let size = file.metadata()?.len();
+        stream.write_u64::<BigEndian>(size)?;
+        stream.write_u16::<BigEndian>(
+            NBD_FLAG_HAS_FLAGS | NBD_FLAG_SEND_FLUSH | NBD_FLAG_SEND_WRITE_ZEROES,
+        )?;
+        if !no_zeroes {
+            stream.write_all(&[0_u8; EXPORT_INFO_PADDING])?;
+        }
+        stream.flush()?;
+        info!("NBD export {} is connected", name);
+        return Ok(Some(file));
+    }
+}
+
+fn handle_connection(
+    mut stream: Box<dyn NbdStream>,
+    exports: &HashMap<String, File>,
+) -> Result<()> {
+    let file = match negotiate(stream.as_mut(), exports)? {
+        Some(file) => file,
+        None => return Ok(()),
+    };
+    let size = file.metadata()?.len();
+
+    loop {
+        if stream.read_u32::<BigEndian>()? != NBD_REQUEST_MAGIC {
+            bail!("Invalid magic of NBD request");
+        }
+        let _flags = stream.read_u16::<BigEndian>()?;
+        let cmd = stream.read_u16::<BigEndian>()?;
+        let handle = stream.read_u64::<BigEndian>()?;
+        let offset = stream.read_u64::<BigEndian>()?;
+        let len = stream.read_u32::<BigEndian>()?;
+        if len > NBD_MAX_LENGTH {
+            bail!("Length {} of NBD request is too large", len);
+        }
+        let out_of_range = !matches!(offset.checked_add(len as u64), Some(end) if end <= size);
+
+        let mut data = Vec::new();
+        let err = match cmd {
+            NBD_CMD_READ => {
+                if out_of_range {
+                    NBD_EINVAL
+                } else {
+                    data.resize(len as usize, 0);
+                    file.read_exact_at(&mut data, offset).map_or(NBD_EIO, |_| 0)
+                }
+            }
+            NBD_CMD_WRITE => {
+                let mut buf = vec![0_u8; len as usize];
+                stream.read_exact(&mut buf)?;
+                if out_of_range {
+                    NBD_ENOSPC
+                } else {
+                    file.write_all_at(&buf, offset).map_or(NBD_EIO, |_| 0)
+                }
+            }
+            NBD_CMD_WRITE_ZEROES => {
+                if out_of_range {
+                    NBD_ENOSPC
+                } else {
+                    let buf = vec![0_u8; len as usize];
+                    file.write_all_at(&buf, offset).map_or(NBD_EIO, |_| 0)
+                }
+            }
+            NBD_CMD_FLUSH => file.sync_data().map_or(NBD_EIO, |_| 0),
+            NBD_CMD_DISC => return Ok(()),
+            _ => NBD_EINVAL,
+        };
+
+        stream.write_u32::<BigEndian>(NBD_SIMPLE_REPLY_MAGIC)?;
+        stream.write_u32::<BigEndian>(err)?;
+        stream.write_u64::<BigEndian>(handle)?;
+        if err == 0 {
+            stream.write_all(&data)?;
+        }
+        stream.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{remove_file, OpenOptions};
+
+    #[test]
+    fn test_nbd_client_and_server() {
+        let img = "/tmp/test_nbd_export.img";
+        let sock = "/tmp/test_nbd_server.sock";
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(img)
+            .unwrap();
+        file.set_len(1 << 20).unwrap();
+        let mut exports = HashMap::new();
+        exports.insert("drive-0".to_string(), file.try_clone().unwrap());
+        let mut server = NbdServer::start(MigrateMode::Unix, sock, exports).unwrap();
+
+        let uri = format!("unix:{}", sock);
+        assert!(NbdClient::connect(&uri, "drive-1").is_err());
+
+        let mut client = NbdClient::connect(&uri, "drive-0").unwrap();
+        assert_eq!(client.size, 1 << 20);
+        assert!(client.support_write_zeroes());
+        client.write(4096, &[0xa5_u8; 512]).unwrap();
+        client.write_zeroes(4096, 256).unwrap();
+        assert!(client.write((1 << 20) - 256, &[1_u8; 512]).is_err());
+        client.flush().unwrap();
+        client.disconnect().unwrap();
+
+        let mut buf = [0xff_u8; 512];
+        file.read_exact_at(&mut buf, 4096).unwrap();
+        assert_eq!(buf[..256], [0_u8; 256]);
+        assert_eq!(buf[256..], [0xa5_u8; 256]);
+
+        server.stop();
+        assert!(NbdClient::connect(&uri, "drive-0").is_err());
+        remove_file(img).unwrap();
+    }
+}
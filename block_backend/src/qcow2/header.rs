@@ -24,6 +24,7 @@ const MIN_CLUSTER_BIT: u32 = 9;
 const MAX_CLUSTER_BIT: u32 = 21;
 const MAX_REFTABLE_SIZE: u64 = 8 * (1 << 20);
 const MAX_L1TABLE_SIZE: u64 = 32 * (1 << 20);
+const MAX_BACKING_FILE_NAME: u32 = 1023;
 
 #[repr(C)]
 #[derive(Clone, Debug, Default)]
@@ -146,11 +147,19 @@ impl QcowHeader {
                 self.cluster_size()
             );
         }
-        // NOTE: not support backing file now.
-        if self.backing_file_offset != 0 {
+        // The backing file name must be stored in the first cluster.
+        if self.backing_file_offset != 0
+            && (self.backing_file_size == 0
+                || self.backing_file_size > MAX_BACKING_FILE_NAME
+                || self
+                    .backing_file_offset
+                    .checked_add(self.backing_file_size as u64)
+                    .map_or(true, |end| end > self.cluster_size()))
+        {
             bail!(
-                "Don't support backing file offset, {}",
-                self.backing_file_offset
+                "Invalid backing file offset {} or size {}",
+                self.backing_file_offset,
+                self.backing_file_size
             );
         }
         // NOTE: only support refcount_order == 4.
@@ -360,7 +369,13 @@ mod test {
         // Invalid backing file offset.
         let mut buf = valid_header_v3();
         BigEndian::write_u32(&mut buf[8..16], 0x2000);
-        list.push((buf, format!("Don't support backing file offset")));
+        BigEndian::write_u32(&mut buf[16..20], 16);
+        list.push((buf, format!("Invalid backing file offset")));
+        // Backing file name is too long.
+        let mut buf = valid_header_v3();
+        BigEndian::write_u64(&mut buf[8..16], 0x200);
+        BigEndian::write_u32(&mut buf[16..20], 1024);
+        list.push((buf, format!("Invalid backing file offset")));
         // Invalid refcount order.
         let mut buf = valid_header_v3();
         BigEndian::write_u32(&mut buf[96..100], 5);
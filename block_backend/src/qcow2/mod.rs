@@ -21,7 +21,11 @@ use std::{
     collections::HashMap,
     fs::File,
     mem::size_of,
-    os::unix::io::{AsRawFd, RawFd},
+    os::unix::{
+        fs::FileExt,
+        io::{AsRawFd, RawFd},
+    },
+    path::{Path, PathBuf},
     rc::Rc,
     sync::{atomic::AtomicBool, Arc, Mutex},
 };
@@ -87,9 +91,17 @@ pub static QCOW2_LIST: Qcow2ListType = Lazy::new(|| Arc::new(Mutex::new(HashMap:
 
 pub enum HostOffset {
     DataNotInit,
+    /// The cluster is not allocated, its data comes from the backing file if any.
+    DataUnallocated,
     DataAddress(u64),
 }
 
+/// Raw backing file of qcow2 image, which is never written.
+struct BackingFile {
+    file: File,
+    len: u64,
+}
+
 pub struct SyncAioInfo {
     /// Aio for sync read/write metadata.
     aio: Aio<()>,
@@ -190,6 +202,7 @@ pub struct Qcow2Driver<T: Clone + 'static> {
     refcount: RefCount,
     snapshot: InternalSnapshot,
     status: Arc<Mutex<BlockStatus>>,
+    backing_file: Option<BackingFile>,
 }
 
 impl<T: Clone + 'static> Drop for Qcow2Driver<T> {
@@ -211,11 +224,15 @@ impl<T: Clone + 'static> Qcow2Driver<T> {
             refcount: RefCount::new(sync_aio.clone()),
             snapshot: InternalSnapshot::new(sync_aio),
             status: Arc::new(Mutex::new(BlockStatus::Init)),
+            backing_file: None,
         };
         qcow2
             .load_header()
             .with_context(|| "Failed to load header")?;
         qcow2.check().with_context(|| "Invalid header")?;
+        qcow2
+            .load_backing_file(fd)
+            .with_context(|| "Failed to load backing file")?;
         qcow2
             .table
             .init_table(&qcow2.header, &conf)
@@ -253,6 +270,58 @@ impl<T: Clone + 'static> Qcow2Driver<T> {
         Ok(())
     }
 
+    /// Open the backing file recorded in header. The relative path of backing file is
+    /// relative to the directory of the image.
+    fn load_backing_file(&mut self, fd: RawFd) -> Result<()> {
+        if self.header.backing_file_offset == 0 {
+            return Ok(());
+        }
+        let mut buf = vec![0_u8; self.header.backing_file_size as usize];
+        self.sync_aio
+            .borrow_mut()
+            .read_buffer(self.header.backing_file_offset, &mut buf)?;
+        let name = String::from_utf8(buf).with_context(|| "Invalid backing file name")?;
+        let mut path = PathBuf::from(&name);
+        if path.is_relative() {
+            let image_path = std::fs::read_link(format!("/proc/self/fd/{}", fd))
+                .with_context(|| "Failed to get the path of image")?;
+            path = image_path
+                .parent()
+                .unwrap_or_else(|| Path::new("/"))
+                .join(&name);
+        }
+
+        let file =
+            File::open(&path).with_context(|| format!("Failed to open backing file {:?}", path))?;
+        let len = file.metadata()?.len();
+        let mut magic = [0_u8; 4];
+        if len >= magic.len() as u64 {
+            file.read_exact_at(&mut magic, 0)?;
+            if BigEndian::read_u32(&magic) == header::QCOW_MAGIC {
+                bail!("Only raw backing file is supported, {:?} is qcow2", path);
+            }
+        }
+        info!("Qcow2 image uses backing file {:?}", path);
+        self.backing_file = Some(BackingFile { file, len });
+        Ok(())
+    }
+
+    /// Read data of guest offset from backing file, the range beyond backing file
+    /// or without backing file is read as zero.
+    fn read_backing_file(&self, guest_offset: u64, buf: &mut [u8]) -> Result<()> {
+        buf.fill(0);
+        if let Some(backing) = &self.backing_file {
+            if guest_offset < backing.len {
+                let cnt = std::cmp::min(buf.len() as u64, backing.len - guest_offset) as usize;
+                backing
+                    .file
+                    .read_exact_at(&mut buf[..cnt], guest_offset)
+                    .with_context(|| "Failed to read backing file")?;
+            }
+        }
+        Ok(())
+    }
+
     fn load_refcount_table(&mut self) -> Result<()> {
         let sz = self.header.refcount_table_clusters as u64
             * (self.header.cluster_size() / ENTRY_SIZE as u64);
@@ -266,7 +335,7 @@ impl<T: Clone + 'static> Qcow2Driver<T> {
     fn host_offset_for_read(&mut self, guest_offset: u64) -> Result<HostOffset> {
         let l2_address = self.table.get_l1_table_entry(guest_offset) & L1_TABLE_OFFSET_MASK;
         if l2_address == 0 {
-            return Ok(HostOffset::DataNotInit);
+            return Ok(HostOffset::DataUnallocated);
         }
 
         let cluster_addr: u64;
@@ -289,7 +358,9 @@ impl<T: Clone + 'static> Qcow2Driver<T> {
             self.table.update_l2_table(l2_table)?;
         }
 
-        if cluster_addr == 0 || cluster_type.is_read_zero() {
+        if cluster_type == Qcow2ClusterType::Unallocated {
+            Ok(HostOffset::DataUnallocated)
+        } else if cluster_addr == 0 || cluster_type.is_read_zero() {
             Ok(HostOffset::DataNotInit)
         } else {
             Ok(HostOffset::DataAddress(
@@ -302,9 +373,21 @@ impl<T: Clone + 'static> Qcow2Driver<T> {
         let l2_index = self.table.get_l2_table_index(guest_offset);
         let l2_table = self.get_table_cluster(guest_offset)?;
         let mut l2_entry = l2_table.borrow_mut().get_entry_map(l2_index as usize)?;
+        let cluster_type = Qcow2ClusterType::get_cluster_type(l2_entry);
         l2_entry &= !QCOW2_OFLAG_ZERO;
         let mut cluster_addr = l2_entry & L2_TABLE_OFFSET_MASK;
-        if cluster_addr == 0 {
+        if cluster_addr == 0 && cluster_type == Qcow2ClusterType::Unallocated {
+            // Copy on write from backing file, the cluster is zero without backing file.
+            let new_addr = self.alloc_cluster(1, self.backing_file.is_none())?;
+            if self.backing_file.is_some() {
+                let cluster_start = guest_offset - self.offset_into_cluster(guest_offset);
+                let mut data = vec![0_u8; self.header.cluster_size() as usize];
+                self.read_backing_file(cluster_start, &mut data)?;
+                self.sync_aio.borrow_mut().write_buffer(new_addr, &data)?;
+            }
+            l2_entry = new_addr | QCOW2_OFFSET_COPIED;
+            cluster_addr = new_addr & L2_TABLE_OFFSET_MASK;
+        } else if cluster_addr == 0 {
             let new_addr = self.alloc_cluster(1, true)?;
             l2_entry = new_addr | QCOW2_OFFSET_COPIED;
             cluster_addr = new_addr & L2_TABLE_OFFSET_MASK;
@@ -1340,15 +1423,23 @@ impl<T: Clone + Send + Sync> BlockDriverOps<T> for Qcow2Driver<T> {
             let count = self.cluster_aligned_bytes(pos, total - copyed);
             let (begin, end) = iovecs_split(left, count);
             left = end;
-            if let HostOffset::DataAddress(host_offset) = self.host_offset_for_read(pos)? {
-                let nbytes = get_iov_size(&begin);
-                req_list.push(CombineRequest {
-                    iov: begin,
-                    offset: host_offset,
-                    nbytes,
-                });
-            } else {
-                iov_from_buf_direct(&begin, &vec![0_u8; count as usize])?;
+            match self.host_offset_for_read(pos)? {
+                HostOffset::DataAddress(host_offset) => {
+                    let nbytes = get_iov_size(&begin);
+                    req_list.push(CombineRequest {
+                        iov: begin,
+                        offset: host_offset,
+                        nbytes,
+                    });
+                }
+                HostOffset::DataUnallocated if self.backing_file.is_some() => {
+                    let mut data = vec![0_u8; count as usize];
+                    self.read_backing_file(pos, &mut data)?;
+                    iov_from_buf_direct(&begin, &data)?;
+                }
+                _ => {
+                    iov_from_buf_direct(&begin, &vec![0_u8; count as usize])?;
+                }
             }
             copyed += count;
         }
@@ -2045,4 +2136,72 @@ mod test {
         assert!(qcow2_read(&mut qcow2_driver, &mut test_buf, offset_start).is_ok());
         assert!(vec_is_zero(&test_buf));
     }
+
+    #[test]
+    fn test_backing_file() {
+        let backing_path = "/tmp/block_backend_test_backing_file.raw";
+        let path = "/tmp/block_backend_test_backing_file.qcow2";
+        let mut backing = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_CREAT | libc::O_TRUNC)
+            .open(backing_path)
+            .unwrap();
+        let backing_data = vec![0x5a_u8; CLUSTER_SIZE as usize * 2];
+        backing.write_all(&backing_data).unwrap();
+
+        // Record the backing file name after the header in the first cluster.
+        let mut image = TestImage::new(path, 30, 16);
+        image.file.seek(SeekFrom::Start(0x200)).unwrap();
+        image.file.write_all(backing_path.as_bytes()).unwrap();
+        let mut buf = [0_u8; 12];
+        BigEndian::write_u64(&mut buf[0..8], 0x200);
+        BigEndian::write_u32(&mut buf[8..12], backing_path.len() as u32);
+        image.file.seek(SeekFrom::Start(8)).unwrap();
+        image.file.write_all(&buf).unwrap();
+
+        let (req_align, buf_align) = get_file_alignment(&image.file, true);
+        let conf = BlockProperty {
+            id: path.to_string(),
+            format: DiskFormat::Qcow2,
+            iothread: None,
+            direct: true,
+            req_align,
+            buf_align,
+            discard: false,
+            write_zeroes: WriteZeroesState::Off,
+            l2_cache_size: None,
+            refcount_cache_size: None,
+        };
+        let mut qcow2_driver = image.create_qcow2_driver(conf);
+
+        // Unallocated clusters are read from backing file, and zero beyond it.
+        let mut buf = vec![0_u8; CLUSTER_SIZE as usize * 3];
+        assert!(qcow2_read(&mut qcow2_driver, &mut buf, 0).is_ok());
+        assert_eq!(buf[..CLUSTER_SIZE as usize * 2], backing_data);
+        assert!(vec_is_zero(&buf[CLUSTER_SIZE as usize * 2..]));
+
+        // Partial write copies the rest of cluster from backing file.
+        let data = vec![1_u8; 4096];
+        assert!(qcow2_write(&mut qcow2_driver, &data, 4096).is_ok());
+        let mut buf = vec![0_u8; CLUSTER_SIZE as usize];
+        assert!(qcow2_read(&mut qcow2_driver, &mut buf, 0).is_ok());
+        assert_eq!(buf[..4096], backing_data[..4096]);
+        assert_eq!(buf[4096..8192], data);
+        assert_eq!(buf[8192..], backing_data[8192..CLUSTER_SIZE as usize]);
+
+        // Zeroed cluster isn't read from backing file.
+        assert!(qcow2_driver
+            .write_zeroes(CLUSTER_SIZE as usize, CLUSTER_SIZE, (), true)
+            .is_ok());
+        let mut buf = vec![1_u8; CLUSTER_SIZE as usize];
+        assert!(qcow2_read(&mut qcow2_driver, &mut buf, CLUSTER_SIZE as usize).is_ok());
+        assert!(vec_is_zero(&buf));
+
+        // Backing file is never written.
+        let mut buf = vec![0_u8; CLUSTER_SIZE as usize * 2];
+        backing.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, backing_data);
+        remove_file(backing_path).unwrap();
+    }
 }
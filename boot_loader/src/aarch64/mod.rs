@@ -11,19 +11,32 @@
 // See the Mulan PSL v2 for more details.
 
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use crate::error::BootLoaderError;
+use crate::initrd_load_addr;
 use address_space::{AddressSpace, GuestAddress};
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use devices::legacy::{error::LegacyError as FwcfgErrorKind, FwCfgEntryType, FwCfgOps};
 use log::info;
+use machine_manager::config::InitrdPlacement;
 use util::byte_code::ByteCode;
 
 const AARCH64_KERNEL_OFFSET: u64 = 0x8_0000;
 
+// Gzip format, see RFC 1952.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const GZIP_CM_DEFLATE: u8 = 8;
+const GZIP_FLG_FHCRC: u8 = 0x02;
+const GZIP_FLG_FEXTRA: u8 = 0x04;
+const GZIP_FLG_FNAME: u8 = 0x08;
+const GZIP_FLG_FCOMMENT: u8 = 0x10;
+const GZIP_HEADER_LEN: usize = 10;
+// CRC32 and ISIZE.
+const GZIP_TRAILER_LEN: usize = 8;
+
 /// Boot loader config used for aarch64.
 #[derive(Default, Debug)]
 pub struct AArch64BootLoaderConfig {
@@ -31,6 +44,8 @@ pub struct AArch64BootLoaderConfig {
     pub kernel: Option<PathBuf>,
     /// Path of initrd image.
     pub initrd: Option<PathBuf>,
+    /// Where to load the initrd image.
+    pub initrd_placement: InitrdPlacement,
     /// Start address of guest memory.
     pub mem_start: u64,
 }
@@ -47,6 +62,73 @@ pub struct AArch64BootLoader {
     pub dtb_start: u64,
 }
 
+/// Decompress gzip data, e.g. `Image.gz` kernel.
+///
+/// # Arguments
+///
+/// * `data` - Gzip-compressed data.
+/// * `max_size` - Max size of the decompressed data.
+fn decompress_gzip(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    if data.len() < GZIP_HEADER_LEN + GZIP_TRAILER_LEN || data[0..2] != GZIP_MAGIC {
+        bail!("Invalid gzip header");
+    }
+    if data[2] != GZIP_CM_DEFLATE {
+        bail!("Unsupported gzip compression method {}", data[2]);
+    }
+
+    let flags = data[3];
+    let mut offset = GZIP_HEADER_LEN;
+    if flags & GZIP_FLG_FEXTRA != 0 {
+        let xlen = data
+            .get(offset..offset + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .with_context(|| "Truncated gzip extra field")?;
+        offset += 2 + xlen;
+    }
+    for flag in [GZIP_FLG_FNAME, GZIP_FLG_FCOMMENT] {
+        if flags & flag != 0 {
+            let len = data
+                .get(offset..)
+                .and_then(|b| b.iter().position(|&c| c == 0))
+                .with_context(|| "Truncated gzip file name or comment")?;
+            offset += len + 1;
+        }
+    }
+    if flags & GZIP_FLG_FHCRC != 0 {
+        offset += 2;
+    }
+    if offset + GZIP_TRAILER_LEN > data.len() {
+        bail!("Truncated gzip data");
+    }
+
+    miniz_oxide::inflate::decompress_to_vec_with_limit(
+        &data[offset..data.len() - GZIP_TRAILER_LEN],
+        max_size,
+    )
+    .map_err(|e| anyhow!("Failed to decompress gzip data: {:?}", e))
+}
+
+/// Read the kernel image, which is decompressed if it's gzip-compressed.
+fn read_kernel(kernel_image: &mut File, max_size: u64) -> Result<Option<Vec<u8>>> {
+    let mut magic = [0_u8; 2];
+    let is_gzip = kernel_image.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+    kernel_image.seek(SeekFrom::Start(0))?;
+    if !is_gzip {
+        return Ok(None);
+    }
+
+    let mut data = Vec::new();
+    kernel_image.read_to_end(&mut data)?;
+    let kernel_data = decompress_gzip(&data, max_size as usize)
+        .with_context(|| "Failed to decompress gzip kernel image")?;
+    info!(
+        "Decompressed gzip kernel image from {} to {} bytes",
+        data.len(),
+        kernel_data.len()
+    );
+    Ok(Some(kernel_data))
+}
+
 fn load_kernel(
     fwcfg: Option<&Arc<Mutex<dyn FwCfgOps>>>,
     kernel_start: u64,
@@ -55,12 +137,26 @@ fn load_kernel(
 ) -> Result<u64> {
     let mut kernel_image =
         File::open(kernel_path).with_context(|| BootLoaderError::BootLoaderOpenKernel)?;
-    let kernel_size = kernel_image.metadata().unwrap().len();
+    let max_size = sys_mem
+        .memory_end_address()
+        .raw_value()
+        .saturating_sub(kernel_start);
+    let decompressed = read_kernel(&mut kernel_image, max_size)?;
+    let kernel_size = match decompressed.as_ref() {
+        Some(data) => data.len() as u64,
+        None => kernel_image.metadata().unwrap().len(),
+    };
     let kernel_end = kernel_start + kernel_size;
 
     if let Some(fw_cfg) = fwcfg {
-        let mut kernel_data = Vec::new();
-        kernel_image.read_to_end(&mut kernel_data)?;
+        let kernel_data = match decompressed {
+            Some(data) => data,
+            None => {
+                let mut data = Vec::new();
+                kernel_image.read_to_end(&mut data)?;
+                data
+            }
+        };
         let mut lock_dev = fw_cfg.lock().unwrap();
         lock_dev
             .add_data_entry(
@@ -83,29 +179,37 @@ fn load_kernel(
                 kernel_size
             )));
         }
-        sys_mem
-            .write(&mut kernel_image, GuestAddress(kernel_start), kernel_size)
-            .with_context(|| "Fail to write kernel to guest memory")?;
+        match decompressed {
+            Some(data) => sys_mem.write(
+                &mut data.as_slice(),
+                GuestAddress(kernel_start),
+                kernel_size,
+            ),
+            None => sys_mem.write(&mut kernel_image, GuestAddress(kernel_start), kernel_size),
+        }
+        .with_context(|| "Fail to write kernel to guest memory")?;
     }
     Ok(kernel_end)
 }
 
 fn load_initrd(
     fwcfg: Option<&Arc<Mutex<dyn FwCfgOps>>>,
-    initrd_path: &Path,
+    config: &AArch64BootLoaderConfig,
     sys_mem: &Arc<AddressSpace>,
     kernel_end: u64,
 ) -> Result<(u64, u64)> {
+    let initrd_path = config.initrd.as_ref().unwrap();
     let mut initrd_image =
         File::open(initrd_path).with_context(|| BootLoaderError::BootLoaderOpenInitrd)?;
     let initrd_size = initrd_image.metadata().unwrap().len();
 
-    let initrd_start = sys_mem
-        .memory_end_address()
-        .raw_value()
-        .checked_sub(initrd_size)
-        .filter(|addr| addr >= &kernel_end)
-        .with_context(|| BootLoaderError::InitrdOverflow(kernel_end, initrd_size))?;
+    let initrd_start = initrd_load_addr(
+        config.initrd_placement,
+        initrd_size,
+        kernel_end,
+        sys_mem.memory_end_address().raw_value(),
+        sys_mem,
+    )?;
 
     if let Some(fw_cfg) = fwcfg {
         let mut initrd_data = Vec::new();
@@ -135,7 +239,8 @@ fn load_initrd(
     Ok((initrd_start, initrd_size))
 }
 
-/// Load PE(vmlinux.bin) linux kernel and other boot source to Guest Memory.
+/// Load PE(vmlinux.bin) linux kernel, which may be gzip-compressed (Image.gz), and
+/// other boot source to Guest Memory.
 ///
 /// # Steps
 ///
@@ -197,7 +302,7 @@ pub fn load_linux(
     let mut initrd_start = 0_u64;
     let mut initrd_size = 0_u64;
     if config.initrd.is_some() {
-        let initrd_tuple = load_initrd(fwcfg, config.initrd.as_ref().unwrap(), sys_mem, kernel_end)
+        let initrd_tuple = load_initrd(fwcfg, config, sys_mem, kernel_end)
             .with_context(|| "Fail to load initrd")?;
         initrd_start = initrd_tuple.0;
         initrd_size = initrd_tuple.1;
@@ -212,3 +317,46 @@ pub fn load_linux(
         dtb_start: dtb_addr,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn gzip_stored(payload: &[u8], flags: u8) -> Vec<u8> {
+        let mut data = vec![0x1f, 0x8b, 8, flags, 0, 0, 0, 0, 0, 3];
+        if flags & GZIP_FLG_FEXTRA != 0 {
+            data.extend_from_slice(&[2, 0, 0xaa, 0xbb]);
+        }
+        if flags & GZIP_FLG_FNAME != 0 {
+            data.extend_from_slice(b"Image\0");
+        }
+        // A final deflate block which is stored without compression.
+        let len = payload.len() as u16;
+        data.push(1);
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&(!len).to_le_bytes());
+        data.extend_from_slice(payload);
+        // CRC32 is not checked, ISIZE.
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_decompress_gzip() {
+        let payload = b"arm64 kernel image";
+        let data = gzip_stored(payload, 0);
+        assert_eq!(decompress_gzip(&data, 0x1000).unwrap(), payload.to_vec());
+
+        let data = gzip_stored(payload, GZIP_FLG_FEXTRA | GZIP_FLG_FNAME);
+        assert_eq!(decompress_gzip(&data, 0x1000).unwrap(), payload.to_vec());
+
+        // Decompressed data exceeds the limit.
+        assert!(decompress_gzip(&data, 4).is_err());
+        // Not gzip.
+        assert!(decompress_gzip(payload, 0x1000).is_err());
+        // Truncated gzip data.
+        let data = [0x1f, 0x8b, 8, GZIP_FLG_FNAME, 0, 0, 0, 0, 0, 3, b'a', b'b'];
+        assert!(decompress_gzip(&data, 0x1000).is_err());
+    }
+}
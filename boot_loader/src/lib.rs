@@ -45,6 +45,7 @@
 //!     let bootloader_config = BootLoaderConfig {
 //!         kernel: Some(kernel_file),
 //!         initrd: None,
+//!         initrd_placement: Default::default(),
 //!         kernel_cmdline: String::new(),
 //!         cpu_count: 0,
 //!         gap_range: (0xC000_0000, 0x4000_0000),
@@ -65,6 +66,7 @@
 //!     let bootloader_config = BootLoaderConfig {
 //!         kernel: Some(kernel_file),
 //!         initrd: None,
+//!         initrd_placement: Default::default(),
 //!         mem_start: 0x4000_0000,
 //!     };
 //!
@@ -94,3 +96,57 @@ pub use x86_64::load_linux;
 pub use x86_64::X86BootLoader as BootLoader;
 #[cfg(target_arch = "x86_64")]
 pub use x86_64::X86BootLoaderConfig as BootLoaderConfig;
+
+use std::sync::Arc;
+
+use address_space::{AddressSpace, GuestAddress};
+use anyhow::{anyhow, Result};
+use machine_manager::config::InitrdPlacement;
+
+/// Initrd is loaded at the address aligned to page size.
+const INITRD_ALIGN: u64 = 0x1000;
+const SIZE_4G: u64 = 0x1_0000_0000;
+
+/// Get the guest address to load initrd image at.
+///
+/// # Arguments
+///
+/// * `placement` - Where to load the initrd image.
+/// * `initrd_size` - Size of the initrd image.
+/// * `addr_min` - The lowest address initrd can be loaded at, e.g. the end of kernel.
+/// * `addr_max` - The highest end address of initrd.
+/// * `sys_mem` - Guest memory.
+fn initrd_load_addr(
+    placement: InitrdPlacement,
+    initrd_size: u64,
+    addr_min: u64,
+    addr_max: u64,
+    sys_mem: &Arc<AddressSpace>,
+) -> Result<u64> {
+    let addr = match placement {
+        InitrdPlacement::Fixed(addr) => {
+            let valid = matches!(addr.checked_add(initrd_size), Some(end) if end <= addr_max)
+                && addr >= addr_min
+                && sys_mem.address_in_memory(GuestAddress(addr), initrd_size);
+            if !valid {
+                return Err(anyhow!(BootLoaderError::InvalidInitrdAddr(
+                    addr,
+                    initrd_size
+                )));
+            }
+            addr
+        }
+        InitrdPlacement::End | InitrdPlacement::Below4G => {
+            let end = if placement == InitrdPlacement::Below4G {
+                std::cmp::min(addr_max, SIZE_4G)
+            } else {
+                addr_max
+            };
+            end.checked_sub(initrd_size)
+                .map(|addr| addr & !(INITRD_ALIGN - 1))
+                .filter(|addr| *addr >= addr_min)
+                .ok_or_else(|| anyhow!(BootLoaderError::InitrdOverflow(addr_min, initrd_size)))?
+        }
+    };
+    Ok(addr)
+}
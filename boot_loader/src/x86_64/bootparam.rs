@@ -16,8 +16,8 @@ use address_space::AddressSpace;
 use util::byte_code::ByteCode;
 
 use super::{
-    X86BootLoaderConfig, EBDA_START, MB_BIOS_BEGIN, REAL_MODE_IVT_BEGIN, VGA_RAM_BEGIN,
-    VMLINUX_RAM_START,
+    X86BootLoaderConfig, EBDA_START, INITRD_ADDR_MAX, MB_BIOS_BEGIN, REAL_MODE_IVT_BEGIN,
+    VGA_RAM_BEGIN, VMLINUX_RAM_START,
 };
 use crate::error::BootLoaderError;
 use anyhow::{anyhow, Result};
@@ -30,6 +30,10 @@ pub const HDRS: u32 = 0x5372_6448;
 pub const UNDEFINED_ID: u8 = 0xFF;
 // Loader type ID: OVMF UEFI virtualization stack.
 pub const UEFI_OVMF_ID: u8 = 0xB;
+// The kernel has the legacy 64-bit entry point at 0x200.
+pub const XLF_KERNEL_64: u16 = 0x1;
+// Type of setup_data which carries a random seed for the kernel.
+pub const SETUP_RNG_SEED: u32 = 9;
 
 // Structures below sourced from:
 // https://www.kernel.org/doc/html/latest/x86/boot.html
@@ -103,6 +107,61 @@ impl RealModeKernelHeader {
         Ok(())
     }
 
+    /// Size of the real-mode setup code, including the boot sector.
+    pub fn setup_size(&self) -> u64 {
+        let setup_sects = if self.setup_sects == 0 {
+            4
+        } else {
+            self.setup_sects as u64
+        };
+        (setup_sects + 1) << 9
+    }
+
+    /// Whether the protected-mode kernel can be entered in 64-bit mode at offset 0x200.
+    /// Kernels older than boot protocol 2.12 do not report it, assume they can.
+    pub fn has_64bit_entry(&self) -> bool {
+        self.version < 0x20c || (self.xloadflags & XLF_KERNEL_64) != 0
+    }
+
+    /// Highest address the initrd can occupy.
+    pub fn initrd_addr_max(&self) -> u64 {
+        if self.version >= 0x203 && self.initrd_addr_max != 0 {
+            self.initrd_addr_max as u64
+        } else {
+            INITRD_ADDR_MAX
+        }
+    }
+
+    /// Max length of the kernel cmdline without the tailing `\0`, if the kernel reports it.
+    pub fn cmdline_size_max(&self) -> Option<u32> {
+        if self.version >= 0x206 {
+            Some(self.cmdline_size)
+        } else {
+            None
+        }
+    }
+
+    /// Memory needed by the kernel from its load address to boot, including decompression.
+    pub fn init_size(&self) -> u64 {
+        if self.version >= 0x20a {
+            self.init_size as u64
+        } else {
+            0
+        }
+    }
+
+    /// Whether the kernel accepts a `setup_data` linked list.
+    pub fn support_setup_data(&self) -> bool {
+        self.version >= 0x209
+    }
+
+    /// Insert a `setup_data` node at the head of the linked list, returns the old head.
+    pub fn push_setup_data(&mut self, addr: u64) -> u64 {
+        let next = self.setup_data;
+        self.setup_data = addr;
+        next
+    }
+
     pub fn set_cmdline(&mut self, cmdline_addr: u32, cmdline_size: u32) {
         self.cmdline_ptr = cmdline_addr;
         self.cmdline_size = cmdline_size;
@@ -114,12 +173,23 @@ impl RealModeKernelHeader {
     }
 }
 
+/// Header of a node in the `setup_data` linked list, followed by `len` bytes of data.
+#[repr(C, packed)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SetupDataHeader {
+    pub next: u64,
+    pub type_: u32,
+    pub len: u32,
+}
+
+impl ByteCode for SetupDataHeader {}
+
 #[repr(C, packed)]
 #[derive(Debug, Default, Copy, Clone)]
 pub struct E820Entry {
-    addr: u64,
-    size: u64,
-    type_: u32,
+    pub(crate) addr: u64,
+    pub(crate) size: u64,
+    pub(crate) type_: u32,
 }
 
 impl E820Entry {
@@ -193,32 +263,46 @@ impl BootParams {
         config: &X86BootLoaderConfig,
         sys_mem: &Arc<AddressSpace>,
     ) {
-        self.add_e820_entry(
+        for entry in e820_entries(config, sys_mem) {
+            self.add_e820_entry(entry.addr, entry.size, entry.type_);
+        }
+    }
+}
+
+/// Memory map of guest for direct boot.
+pub fn e820_entries(config: &X86BootLoaderConfig, sys_mem: &Arc<AddressSpace>) -> Vec<E820Entry> {
+    let mut entries = vec![
+        E820Entry::new(
             REAL_MODE_IVT_BEGIN,
             EBDA_START - REAL_MODE_IVT_BEGIN,
             E820_RAM,
-        );
-        self.add_e820_entry(EBDA_START, VGA_RAM_BEGIN - EBDA_START, E820_RESERVED);
-        self.add_e820_entry(MB_BIOS_BEGIN, 0, E820_RESERVED);
-
-        let high_memory_start = VMLINUX_RAM_START;
-        let layout_32bit_gap_end = config.gap_range.0 + config.gap_range.1;
-        let mem_end = sys_mem.memory_end_address().raw_value();
-        if mem_end < layout_32bit_gap_end {
-            self.add_e820_entry(high_memory_start, mem_end - high_memory_start, E820_RAM);
-        } else {
-            self.add_e820_entry(
-                high_memory_start,
-                config.gap_range.0 - high_memory_start,
-                E820_RAM,
-            );
-            self.add_e820_entry(
-                layout_32bit_gap_end,
-                mem_end - layout_32bit_gap_end,
-                E820_RAM,
-            );
-        }
+        ),
+        E820Entry::new(EBDA_START, VGA_RAM_BEGIN - EBDA_START, E820_RESERVED),
+        E820Entry::new(MB_BIOS_BEGIN, 0, E820_RESERVED),
+    ];
+
+    let high_memory_start = VMLINUX_RAM_START;
+    let layout_32bit_gap_end = config.gap_range.0 + config.gap_range.1;
+    let mem_end = sys_mem.memory_end_address().raw_value();
+    if mem_end < layout_32bit_gap_end {
+        entries.push(E820Entry::new(
+            high_memory_start,
+            mem_end - high_memory_start,
+            E820_RAM,
+        ));
+    } else {
+        entries.push(E820Entry::new(
+            high_memory_start,
+            config.gap_range.0 - high_memory_start,
+            E820_RAM,
+        ));
+        entries.push(E820Entry::new(
+            layout_32bit_gap_end,
+            mem_end - layout_32bit_gap_end,
+            E820_RAM,
+        ));
     }
+    entries
 }
 
 #[cfg(test)]
@@ -227,6 +311,7 @@ mod test {
     use std::sync::Arc;
 
     use address_space::{AddressSpace, GuestAddress, HostMemMapping, Region};
+    use machine_manager::config::InitrdPlacement;
 
     use super::super::X86BootLoaderConfig;
     use super::*;
@@ -254,6 +339,7 @@ mod test {
         let config = X86BootLoaderConfig {
             kernel: Some(PathBuf::new()),
             initrd: Some(PathBuf::new()),
+            initrd_placement: InitrdPlacement::End,
             kernel_cmdline: String::from("this_is_a_piece_of_test_string"),
             cpu_count: 2,
             gap_range: (0xC000_0000, 0x4000_0000),
@@ -108,12 +108,19 @@ fn write_idt_value(val: u64, guest_mem: &Arc<AddressSpace>) -> Result<()> {
     Ok(())
 }
 
-pub fn setup_gdt(guest_mem: &Arc<AddressSpace>) -> Result<BootGdtSegment> {
+/// Setup GDT with flat code and data segments.
+///
+/// # Arguments
+///
+/// * `guest_mem` - Guest memory.
+/// * `long_mode` - Use 64-bit code segment, otherwise 32-bit.
+pub fn setup_gdt(guest_mem: &Arc<AddressSpace>, long_mode: bool) -> Result<BootGdtSegment> {
+    let code_flags = if long_mode { 0xa09b } else { 0xc09b };
     let gdt_table: [u64; BOOT_GDT_MAX] = [
-        GdtEntry::new(0, 0, 0).into(),            // NULL
-        GdtEntry::new(0, 0, 0).into(),            // NULL
-        GdtEntry::new(0xa09b, 0, 0xfffff).into(), // CODE
-        GdtEntry::new(0xc093, 0, 0xfffff).into(), // DATA
+        GdtEntry::new(0, 0, 0).into(),                // NULL
+        GdtEntry::new(0, 0, 0).into(),                // NULL
+        GdtEntry::new(code_flags, 0, 0xfffff).into(), // CODE
+        GdtEntry::new(0xc093, 0, 0xfffff).into(),     // DATA
     ];
 
     let mut code_seg: kvm_segment = GdtEntry(gdt_table[GDT_ENTRY_BOOT_CS as usize]).into();
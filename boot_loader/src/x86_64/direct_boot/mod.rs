@@ -12,26 +12,34 @@
 
 mod gdt;
 mod mptable;
+mod multiboot2;
+mod pvh;
 
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
-use log::info;
+use anyhow::{anyhow, bail, Context, Result};
+use log::{info, warn};
 
 use address_space::{AddressSpace, GuestAddress};
 use util::byte_code::ByteCode;
 
 use self::gdt::setup_gdt;
 use self::mptable::setup_isa_mptable;
-use super::bootparam::{BootParams, RealModeKernelHeader, UNDEFINED_ID};
+use self::multiboot2::{find_multiboot2_header, load_multiboot2_kernel, setup_boot_info};
+use self::pvh::setup_start_info;
+use super::bootparam::{
+    BootParams, RealModeKernelHeader, SetupDataHeader, SETUP_RNG_SEED, UNDEFINED_ID,
+};
+use super::elf::{is_elf_kernel, load_elf_kernel};
 use super::{X86BootLoader, X86BootLoaderConfig};
 use super::{
-    BOOT_HDR_START, BOOT_LOADER_SP, BZIMAGE_BOOT_OFFSET, CMDLINE_START, EBDA_START,
-    INITRD_ADDR_MAX, PDE_START, PDPTE_START, PML4_START, VMLINUX_STARTUP, ZERO_PAGE_START,
+    BOOT_HDR_START, BOOT_LOADER_SP, BZIMAGE_BOOT_OFFSET, CMDLINE_START, EBDA_START, PDE_START,
+    PDPTE_START, PML4_START, SETUP_DATA_START, VMLINUX_STARTUP, ZERO_PAGE_START,
 };
 use crate::error::BootLoaderError;
+use crate::initrd_load_addr;
 
 /// Load bzImage linux kernel to Guest Memory.
 ///
@@ -42,8 +50,9 @@ use crate::error::BootLoaderError;
 /// The setup `RealModeKernelHeader` can be load at offset `0x01f1` in bzImage kernel image.
 /// The compressed kernel will be loaded into guest memory at `code32_start` in
 /// `RealModeKernelHeader`.
-/// The start address of compressed kernel is the loader address + 0x200. It will be
-/// set in `kernel_start` in `BootLoader` structure set.
+/// The 64-bit entry of compressed kernel is the loader address + 0x200. It will be
+/// set in `kernel_start` in `BootLoader` structure set, so the kernel must report
+/// `XLF_KERNEL_64` in `xloadflags`.
 ///
 /// # Arguments
 ///
@@ -52,7 +61,7 @@ use crate::error::BootLoaderError;
 /// # Errors
 ///
 /// * Invalid BzImage header or version.
-/// * Failed to write bzImage linux kernel to guest memory.
+/// * BzImage kernel without 64-bit entry.
 fn load_bzimage(kernel_image: &mut File) -> Result<RealModeKernelHeader> {
     let mut boot_hdr = RealModeKernelHeader::new();
 
@@ -66,13 +75,11 @@ fn load_bzimage(kernel_image: &mut File) -> Result<RealModeKernelHeader> {
         kernel_image.seek(SeekFrom::Start(0))?;
         return Err(e);
     }
-
-    let mut setup_size = boot_hdr.setup_sects as u64;
-    if setup_size == 0 {
-        setup_size = 4;
+    if !boot_hdr.has_64bit_entry() {
+        bail!("The bzImage kernel has no 64-bit entry, please boot it with firmware");
     }
-    setup_size = (setup_size + 1) << 9;
-    kernel_image.seek(SeekFrom::Start(setup_size))?;
+
+    kernel_image.seek(SeekFrom::Start(boot_hdr.setup_size()))?;
 
     Ok(boot_hdr)
 }
@@ -105,20 +112,57 @@ fn load_kernel_image(
     let mut kernel_image =
         File::open(kernel_path).with_context(|| BootLoaderError::BootLoaderOpenKernel)?;
 
-    let (boot_hdr, kernel_start, vmlinux_start) = if let Ok(hdr) = load_bzimage(&mut kernel_image) {
-        (
+    let (boot_hdr, kernel_start, vmlinux_start) = match load_bzimage(&mut kernel_image) {
+        Ok(hdr) => (
             hdr,
             hdr.code32_start as u64 + BZIMAGE_BOOT_OFFSET,
             hdr.code32_start as u64,
-        )
-    } else {
-        (
-            RealModeKernelHeader::new(),
-            VMLINUX_STARTUP,
-            VMLINUX_STARTUP,
-        )
+        ),
+        Err(e) => {
+            if !matches!(
+                e.downcast_ref::<BootLoaderError>(),
+                Some(BootLoaderError::ElfKernel)
+            ) {
+                return Err(e);
+            }
+            if let Some(multiboot2) = find_multiboot2_header(&mut kernel_image)? {
+                boot_layout.boot_ip =
+                    load_multiboot2_kernel(&mut kernel_image, &multiboot2, sys_mem)
+                        .with_context(|| "Failed to load multiboot2 kernel")?;
+                boot_layout.multiboot2 = true;
+                return Ok(RealModeKernelHeader::new());
+            }
+            if is_elf_kernel(&mut kernel_image)? {
+                let elf = load_elf_kernel(&mut kernel_image, sys_mem)
+                    .with_context(|| "Failed to load ELF kernel")?;
+                // Prefer PVH entry, which skips the 64-bit setup of kernel.
+                if let Some(pvh_entry) = elf.pvh_entry {
+                    boot_layout.boot_ip = pvh_entry;
+                    boot_layout.pvh = true;
+                } else {
+                    boot_layout.boot_ip = elf.entry;
+                }
+                return Ok(RealModeKernelHeader::new());
+            }
+            (
+                RealModeKernelHeader::new(),
+                VMLINUX_STARTUP,
+                VMLINUX_STARTUP,
+            )
+        }
     };
 
+    // The bzImage kernel decompresses itself, make sure there is enough room for it.
+    let image_size = kernel_image.metadata()?.len() - kernel_image.stream_position()?;
+    let kernel_size = std::cmp::max(image_size, boot_hdr.init_size());
+    let mem_end = sys_mem.memory_end_address().raw_value();
+    if vmlinux_start + kernel_size > mem_end {
+        return Err(anyhow!(BootLoaderError::KernelOverflow(
+            vmlinux_start,
+            kernel_size
+        )));
+    }
+
     load_image(&mut kernel_image, vmlinux_start, sys_mem)
         .with_context(|| "Failed to load image")?;
 
@@ -131,27 +175,33 @@ fn load_initrd(
     config: &X86BootLoaderConfig,
     sys_mem: &Arc<AddressSpace>,
     header: &mut RealModeKernelHeader,
-) -> Result<()> {
+) -> Result<Option<(u64, u64)>> {
     if config.initrd.is_none() {
         info!("No initrd image file.");
-        return Ok(());
+        return Ok(None);
     };
 
-    let mut initrd_addr_max = INITRD_ADDR_MAX;
-    if initrd_addr_max > sys_mem.memory_end_address().raw_value() {
-        initrd_addr_max = sys_mem.memory_end_address().raw_value();
-    };
+    let initrd_addr_max = std::cmp::min(
+        header.initrd_addr_max(),
+        sys_mem.memory_end_address().raw_value(),
+    );
 
     let mut initrd_image = File::open(config.initrd.as_ref().unwrap())
         .with_context(|| BootLoaderError::BootLoaderOpenInitrd)?;
     let initrd_size = initrd_image.metadata().unwrap().len();
-    let initrd_addr = (initrd_addr_max - initrd_size) & !0xfff_u64;
+    let initrd_addr = initrd_load_addr(
+        config.initrd_placement,
+        initrd_size,
+        0,
+        initrd_addr_max,
+        sys_mem,
+    )?;
 
     load_image(&mut initrd_image, initrd_addr, sys_mem).with_context(|| "Failed to load image")?;
 
     header.set_ramdisk(initrd_addr as u32, initrd_size as u32);
 
-    Ok(())
+    Ok(Some((initrd_addr, initrd_size)))
 }
 
 /// Initial pagetables.
@@ -205,19 +255,62 @@ fn setup_kernel_cmdline(
     boot_hdr: &mut RealModeKernelHeader,
 ) -> Result<()> {
     let cmdline_len = config.kernel_cmdline.len() as u32;
+    if let Some(max_len) = boot_hdr.cmdline_size_max() {
+        if cmdline_len > max_len {
+            bail!(
+                "Kernel cmdline length {} exceeds the max length {} of kernel",
+                cmdline_len,
+                max_len
+            );
+        }
+    }
     boot_hdr.set_cmdline(CMDLINE_START as u32, cmdline_len);
 
+    // The tailing `\0` is needed by PVH kernel, which has no cmdline length.
     sys_mem.write(
         &mut config.kernel_cmdline.as_bytes(),
         GuestAddress(CMDLINE_START),
         cmdline_len as u64,
     )?;
+    sys_mem.write_object(&0_u8, GuestAddress(CMDLINE_START + cmdline_len as u64))?;
 
     Ok(())
 }
 
-/// Load PE(vmlinux.bin) linux kernel / bzImage linux kernel and
-/// other boot source to Guest Memory.
+/// Pass a random seed to kernel through `setup_data`, so that it has entropy early.
+fn setup_rng_seed(sys_mem: &Arc<AddressSpace>, boot_hdr: &mut RealModeKernelHeader) -> Result<()> {
+    if !boot_hdr.support_setup_data() {
+        return Ok(());
+    }
+
+    let mut seed = [0_u8; 32];
+    if let Err(e) = File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut seed)) {
+        warn!("Failed to get random seed for kernel: {:?}", e);
+        return Ok(());
+    }
+
+    let setup_data = SetupDataHeader {
+        next: boot_hdr.push_setup_data(SETUP_DATA_START),
+        type_: SETUP_RNG_SEED,
+        len: seed.len() as u32,
+    };
+    let data_addr = SETUP_DATA_START + setup_data.as_bytes().len() as u64;
+    sys_mem
+        .write_object(&setup_data, GuestAddress(SETUP_DATA_START))
+        .with_context(|| format!("Failed to load setup data to 0x{:x}", SETUP_DATA_START))?;
+    sys_mem
+        .write(
+            &mut seed.as_ref(),
+            GuestAddress(data_addr),
+            seed.len() as u64,
+        )
+        .with_context(|| format!("Failed to load random seed to 0x{:x}", data_addr))?;
+
+    Ok(())
+}
+
+/// Load PE(vmlinux.bin) linux kernel / bzImage linux kernel / ELF(vmlinux) linux kernel
+/// and other boot source to Guest Memory.
 ///
 /// # Steps
 ///
@@ -225,6 +318,7 @@ fn setup_kernel_cmdline(
 /// 2. According guest memory layout, load linux kernel to guest memory.
 /// 3. According guest memory layout, load initrd image to guest memory.
 /// 4. Inject cmdline to guest memory.
+/// 5. Setup zero page for linux boot protocol, or start info for PVH ELF kernel.
 ///
 /// # Arguments
 ///
@@ -250,14 +344,30 @@ pub fn load_linux(
     };
     let mut boot_header = load_kernel_image(kernel_path, sys_mem, &mut boot_loader_layout)?;
 
-    load_initrd(config, sys_mem, &mut boot_header)
+    let initrd = load_initrd(config, sys_mem, &mut boot_header)
         .with_context(|| "Failed to load initrd to vm memory")?;
 
     setup_kernel_cmdline(config, sys_mem, &mut boot_header)
         .with_context(|| "Failed to setup kernel cmdline")?;
 
-    setup_boot_params(config, sys_mem, &boot_header)
-        .with_context(|| "Failed to setup boot params")?;
+    if boot_loader_layout.pvh {
+        setup_start_info(config, sys_mem, ZERO_PAGE_START, CMDLINE_START, initrd)
+            .with_context(|| "Failed to setup PVH start info")?;
+    } else if boot_loader_layout.multiboot2 {
+        setup_boot_info(
+            config,
+            sys_mem,
+            ZERO_PAGE_START,
+            BOOT_LOADER_SP - ZERO_PAGE_START,
+            initrd,
+        )
+        .with_context(|| "Failed to setup multiboot2 boot information")?;
+    } else {
+        setup_rng_seed(sys_mem, &mut boot_header).with_context(|| "Failed to setup setup_data")?;
+
+        setup_boot_params(config, sys_mem, &boot_header)
+            .with_context(|| "Failed to setup boot params")?;
+    }
 
     setup_isa_mptable(
         sys_mem,
@@ -267,9 +377,14 @@ pub fn load_linux(
         config.lapic_addr,
     )?;
 
-    boot_loader_layout.boot_pml4_addr =
-        setup_page_table(sys_mem).with_context(|| "Failed to setup page table")?;
-    boot_loader_layout.segments = setup_gdt(sys_mem).with_context(|| "Failed to setup gdt")?;
+    // PVH and multiboot2 kernel start in 32-bit protected mode without paging.
+    let prot32_mode = boot_loader_layout.pvh || boot_loader_layout.multiboot2;
+    if !prot32_mode {
+        boot_loader_layout.boot_pml4_addr =
+            setup_page_table(sys_mem).with_context(|| "Failed to setup page table")?;
+    }
+    boot_loader_layout.segments =
+        setup_gdt(sys_mem, !prot32_mode).with_context(|| "Failed to setup gdt")?;
 
     Ok(boot_loader_layout)
 }
@@ -283,6 +398,7 @@ mod test {
     use super::super::BOOT_GDT_MAX;
     use address_space::*;
     use kvm_bindings::kvm_segment;
+    use machine_manager::config::InitrdPlacement;
 
     #[test]
     fn test_x86_bootloader_and_kernel_cmdline() {
@@ -326,6 +442,7 @@ mod test {
         let config = X86BootLoaderConfig {
             kernel: Some(PathBuf::new()),
             initrd: Some(PathBuf::new()),
+            initrd_placement: InitrdPlacement::End,
             kernel_cmdline: String::from("this_is_a_piece_of_test_string"),
             cpu_count: 2,
             gap_range: (0xC000_0000, 0x4000_0000),
@@ -369,7 +486,7 @@ mod test {
             padding: 0,
         };
 
-        let boot_gdt_seg = setup_gdt(&space).unwrap();
+        let boot_gdt_seg = setup_gdt(&space, true).unwrap();
 
         assert_eq!(boot_gdt_seg.code_segment, c_seg);
         assert_eq!(boot_gdt_seg.data_segment, d_seg);
@@ -400,4 +517,310 @@ mod test {
         let s = String::from_utf8(read_buffer.to_vec()).unwrap();
         assert_eq!(s, "this_is_a_piece_of_test_string".to_string());
     }
+
+    fn bzimage_with_header(path: &str, version: u16, xloadflags: u16) {
+        let mut image = vec![0_u8; 0x600];
+        // setup_sects
+        image[0x1f1] = 1;
+        // header "HdrS"
+        image[0x202..0x206].copy_from_slice(&0x5372_6448_u32.to_le_bytes());
+        image[0x206..0x208].copy_from_slice(&version.to_le_bytes());
+        // loadflags: LOADED_HIGH
+        image[0x211] = 1;
+        image[0x214..0x218].copy_from_slice(&0x10_0000_u32.to_le_bytes());
+        image[0x22c..0x230].copy_from_slice(&0x7fff_ffff_u32.to_le_bytes());
+        image[0x236..0x238].copy_from_slice(&xloadflags.to_le_bytes());
+        image[0x238..0x23c].copy_from_slice(&16_u32.to_le_bytes());
+        image[0x260..0x264].copy_from_slice(&0x40_0000_u32.to_le_bytes());
+        // Protected-mode kernel after the setup sectors.
+        image[0x400] = 0xaa;
+        std::fs::write(path, image).unwrap();
+    }
+
+    #[test]
+    fn test_load_bzimage() {
+        let root = Region::init_container_region(0x2000_0000, "root");
+        let space = AddressSpace::new(root.clone(), "space").unwrap();
+        let ram = Arc::new(
+            HostMemMapping::new(
+                GuestAddress(0),
+                None,
+                0x1000_0000,
+                None,
+                false,
+                false,
+                false,
+            )
+            .unwrap(),
+        );
+        let region = Region::init_ram_region(ram.clone(), "region");
+        root.add_subregion(region, ram.start_address().raw_value())
+            .unwrap();
+
+        let kernel_path = "/tmp/stratovirt_test_bzimage";
+        let mut config = X86BootLoaderConfig {
+            kernel: Some(PathBuf::from(kernel_path)),
+            initrd: None,
+            initrd_placement: InitrdPlacement::End,
+            kernel_cmdline: String::from("console=ttyS0"),
+            cpu_count: 1,
+            gap_range: (0xC000_0000, 0x4000_0000),
+            ioapic_addr: 0xFEC0_0000,
+            lapic_addr: 0xFEE0_0000,
+            prot64_mode: true,
+            ident_tss_range: None,
+        };
+
+        bzimage_with_header(kernel_path, 0x20f, 0x1);
+        let layout = load_linux(&config, &space).unwrap();
+        assert_eq!(layout.boot_ip, 0x10_0200);
+        assert_eq!(
+            space.read_object::<u8>(GuestAddress(0x10_0000)).unwrap(),
+            0xaa
+        );
+        assert_eq!(
+            space
+                .read_object::<u8>(GuestAddress(ZERO_PAGE_START + 0x210))
+                .unwrap(),
+            UNDEFINED_ID
+        );
+        assert_eq!(
+            space
+                .read_object::<u64>(GuestAddress(ZERO_PAGE_START + 0x250))
+                .unwrap(),
+            SETUP_DATA_START
+        );
+        let setup_data = space
+            .read_object::<SetupDataHeader>(GuestAddress(SETUP_DATA_START))
+            .unwrap();
+        let (next, type_, len) = (setup_data.next, setup_data.type_, setup_data.len);
+        assert_eq!((next, type_, len), (0, SETUP_RNG_SEED, 32));
+
+        // Kernel cmdline exceeds the cmdline_size of kernel.
+        config.kernel_cmdline = String::from("console=ttyS0 reboot=k panic=1");
+        assert!(load_linux(&config, &space).is_err());
+
+        // 32-bit bzImage kernel has no 64-bit entry.
+        config.kernel_cmdline = String::from("console=ttyS0");
+        bzimage_with_header(kernel_path, 0x20f, 0);
+        assert!(load_linux(&config, &space).is_err());
+
+        std::fs::remove_file(kernel_path).unwrap();
+    }
+
+    fn elf_kernel(path: &str, pvh: bool) {
+        // ELF header, a PT_LOAD and a PT_NOTE program header with PVH entry.
+        let mut image = vec![0_u8; 0x210];
+        image[0..6].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1]);
+        image[0x12..0x14].copy_from_slice(&62_u16.to_le_bytes());
+        image[0x18..0x20].copy_from_slice(&0xffff_ffff_8100_0000_u64.to_le_bytes());
+        image[0x20..0x28].copy_from_slice(&64_u64.to_le_bytes());
+        let phnum: u16 = if pvh { 2 } else { 1 };
+        image[0x38..0x3a].copy_from_slice(&phnum.to_le_bytes());
+        let load_phdr: [u64; 7] = [
+            0x5_0000_0001,
+            0x200,
+            0xffff_ffff_8100_0000,
+            0x100_0000,
+            0x10,
+            0x20,
+            0x1000,
+        ];
+        let note_phdr: [u64; 7] = [0x4_0000_0004, 0x180, 0, 0, 24, 24, 4];
+        for (i, val) in load_phdr.iter().chain(note_phdr.iter()).enumerate() {
+            image[64 + i * 8..72 + i * 8].copy_from_slice(&val.to_le_bytes());
+        }
+        image[0x180..0x18c].copy_from_slice(&[4, 0, 0, 0, 8, 0, 0, 0, 0x12, 0, 0, 0]);
+        image[0x18c..0x190].copy_from_slice(b"Xen\0");
+        image[0x190..0x198].copy_from_slice(&0x100_0008_u64.to_le_bytes());
+        image[0x200] = 0xaa;
+        std::fs::write(path, image).unwrap();
+    }
+
+    #[test]
+    fn test_load_pvh_kernel() {
+        let root = Region::init_container_region(0x2000_0000, "root");
+        let space = AddressSpace::new(root.clone(), "space").unwrap();
+        let ram = Arc::new(
+            HostMemMapping::new(
+                GuestAddress(0),
+                None,
+                0x1000_0000,
+                None,
+                false,
+                false,
+                false,
+            )
+            .unwrap(),
+        );
+        let region = Region::init_ram_region(ram.clone(), "region");
+        root.add_subregion(region, ram.start_address().raw_value())
+            .unwrap();
+
+        let kernel_path = "/tmp/stratovirt_test_elf_kernel";
+        elf_kernel(kernel_path, true);
+
+        let config = X86BootLoaderConfig {
+            kernel: Some(PathBuf::from(kernel_path)),
+            initrd: None,
+            initrd_placement: InitrdPlacement::End,
+            kernel_cmdline: String::from("console=ttyS0"),
+            cpu_count: 1,
+            gap_range: (0xC000_0000, 0x4000_0000),
+            ioapic_addr: 0xFEC0_0000,
+            lapic_addr: 0xFEE0_0000,
+            prot64_mode: true,
+            ident_tss_range: None,
+        };
+        let layout = load_linux(&config, &space).unwrap();
+
+        assert!(layout.pvh);
+        assert_eq!(layout.boot_ip, 0x100_0008);
+        assert_eq!(layout.zero_page_addr, ZERO_PAGE_START);
+        assert_eq!(layout.segments.code_segment.db, 1);
+        assert_eq!(layout.segments.code_segment.l, 0);
+        assert_eq!(
+            space.read_object::<u8>(GuestAddress(0x100_0000)).unwrap(),
+            0xaa
+        );
+
+        let read_u32 = |offset: u64| {
+            space
+                .read_object::<u32>(GuestAddress(ZERO_PAGE_START + offset))
+                .unwrap()
+        };
+        let read_u64 = |offset: u64| {
+            space
+                .read_object::<u64>(GuestAddress(ZERO_PAGE_START + offset))
+                .unwrap()
+        };
+        // magic, version, nr_modules
+        assert_eq!(read_u32(0), 0x336e_c578);
+        assert_eq!(read_u32(4), 1);
+        assert_eq!(read_u32(12), 0);
+        // cmdline_paddr, memmap_paddr, memmap_entries
+        assert_eq!(read_u64(24), CMDLINE_START);
+        assert_eq!(read_u64(40), ZERO_PAGE_START + 56);
+        assert_eq!(read_u32(48), 4);
+        let mut cmdline = [0_u8; 14];
+        space
+            .read(&mut cmdline.as_mut(), GuestAddress(CMDLINE_START), 14)
+            .unwrap();
+        assert_eq!(&cmdline, b"console=ttyS0\0");
+
+        // ELF kernel without PVH entry boots from e_entry with linux boot protocol,
+        // which is translated to physical address.
+        elf_kernel(kernel_path, false);
+        space
+            .write_object(&0xff_u8, GuestAddress(0x100_0010))
+            .unwrap();
+        let layout = load_linux(&config, &space).unwrap();
+        std::fs::remove_file(kernel_path).unwrap();
+
+        assert!(!layout.pvh);
+        assert_eq!(layout.boot_ip, 0x100_0000);
+        assert_eq!(layout.boot_pml4_addr, PML4_START);
+        assert_eq!(layout.segments.code_segment.l, 1);
+        // bss is cleared.
+        assert_eq!(
+            space.read_object::<u8>(GuestAddress(0x100_0010)).unwrap(),
+            0
+        );
+        // cmd_line_ptr in zero page.
+        assert_eq!(read_u32(0x228) as u64, CMDLINE_START);
+    }
+
+    #[test]
+    fn test_load_multiboot2_kernel() {
+        let root = Region::init_container_region(0x2000_0000, "root");
+        let space = AddressSpace::new(root.clone(), "space").unwrap();
+        let ram = Arc::new(
+            HostMemMapping::new(
+                GuestAddress(0),
+                None,
+                0x1000_0000,
+                None,
+                false,
+                false,
+                false,
+            )
+            .unwrap(),
+        );
+        let region = Region::init_ram_region(ram.clone(), "region");
+        root.add_subregion(region, ram.start_address().raw_value())
+            .unwrap();
+
+        // Multiboot2 header with address tag, entry address tag and end tag.
+        let header: [u32; 16] = [
+            0xe852_50d6,
+            0,
+            64,
+            0_u32.wrapping_sub(0xe852_50d6 + 64),
+            0x0018_0002,
+            0x10_0000,
+            0x10_0000,
+            0,
+            0x10_1000,
+            0,
+            0x000c_0003,
+            0x10_0040,
+            0,
+            0,
+            0x0008_0000,
+            0,
+        ];
+        let mut image: Vec<u8> = header.iter().flat_map(|val| val.to_le_bytes()).collect();
+        image.resize(0x100, 0);
+        image[0x40] = 0xaa;
+        let kernel_path = "/tmp/stratovirt_test_multiboot2_kernel";
+        std::fs::write(kernel_path, image).unwrap();
+        space
+            .write_object(&0xff_u8, GuestAddress(0x10_0800))
+            .unwrap();
+
+        let config = X86BootLoaderConfig {
+            kernel: Some(PathBuf::from(kernel_path)),
+            initrd: None,
+            initrd_placement: InitrdPlacement::End,
+            kernel_cmdline: String::from("console=ttyS0"),
+            cpu_count: 1,
+            gap_range: (0xC000_0000, 0x4000_0000),
+            ioapic_addr: 0xFEC0_0000,
+            lapic_addr: 0xFEE0_0000,
+            prot64_mode: true,
+            ident_tss_range: None,
+        };
+        let layout = load_linux(&config, &space).unwrap();
+        std::fs::remove_file(kernel_path).unwrap();
+
+        assert!(layout.multiboot2);
+        assert_eq!(layout.boot_ip, 0x10_0040);
+        assert_eq!(layout.zero_page_addr, ZERO_PAGE_START);
+        assert_eq!(layout.segments.code_segment.l, 0);
+        assert_eq!(
+            space.read_object::<u8>(GuestAddress(0x10_0040)).unwrap(),
+            0xaa
+        );
+        // bss is cleared.
+        assert_eq!(space.read_object::<u8>(GuestAddress(0x10_0800)).unwrap(), 0);
+
+        let read_u32 = |offset: u64| {
+            space
+                .read_object::<u32>(GuestAddress(ZERO_PAGE_START + offset))
+                .unwrap()
+        };
+        // total_size, then the cmdline tag.
+        assert_eq!(read_u32(0) % 8, 0);
+        assert_eq!(read_u32(8), 1);
+        assert_eq!(read_u32(12), 22);
+        let mut cmdline = [0_u8; 14];
+        space
+            .read(
+                &mut cmdline.as_mut(),
+                GuestAddress(ZERO_PAGE_START + 16),
+                14,
+            )
+            .unwrap();
+        assert_eq!(&cmdline, b"console=ttyS0\0");
+    }
 }
@@ -0,0 +1,529 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+//! Load kernel according to
+//! [`Multiboot2 specification`](https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html).
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use address_space::{AddressSpace, GuestAddress};
+use util::byte_code::ByteCode;
+
+use super::super::bootparam::{e820_entries, E820_RAM};
+use super::super::elf::{is_elf_kernel, load_elf_kernel};
+use super::super::{X86BootLoaderConfig, VMLINUX_RAM_START};
+use crate::error::BootLoaderError;
+
+const MULTIBOOT2_HEADER_MAGIC: u32 = 0xe852_50d6;
+const MULTIBOOT2_ARCHITECTURE_I386: u32 = 0;
+/// The header must be contained completely within the first 32768 bytes of kernel image.
+const MULTIBOOT2_SEARCH: u64 = 0x8000;
+/// Both the header and the tags are 8-byte aligned.
+const MULTIBOOT2_ALIGN: usize = 8;
+/// Kernel may ignore the tag if bootloader doesn't support it.
+const MULTIBOOT2_TAG_OPTIONAL: u16 = 1;
+
+// Tags of multiboot2 header.
+const MULTIBOOT2_HEADER_TAG_END: u16 = 0;
+const MULTIBOOT2_HEADER_TAG_INFORMATION_REQUEST: u16 = 1;
+const MULTIBOOT2_HEADER_TAG_ADDRESS: u16 = 2;
+const MULTIBOOT2_HEADER_TAG_ENTRY_ADDRESS: u16 = 3;
+const MULTIBOOT2_HEADER_TAG_CONSOLE_FLAGS: u16 = 4;
+const MULTIBOOT2_HEADER_TAG_MODULE_ALIGN: u16 = 6;
+
+// Tags of multiboot2 boot information.
+const MULTIBOOT2_TAG_TYPE_END: u32 = 0;
+const MULTIBOOT2_TAG_TYPE_CMDLINE: u32 = 1;
+const MULTIBOOT2_TAG_TYPE_BOOT_LOADER_NAME: u32 = 2;
+const MULTIBOOT2_TAG_TYPE_MODULE: u32 = 3;
+const MULTIBOOT2_TAG_TYPE_BASIC_MEMINFO: u32 = 4;
+const MULTIBOOT2_TAG_TYPE_MMAP: u32 = 6;
+
+const MULTIBOOT2_BOOT_LOADER_NAME: &str = "StratoVirt";
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct Multiboot2Header {
+    magic: u32,
+    architecture: u32,
+    header_length: u32,
+    checksum: u32,
+}
+
+impl ByteCode for Multiboot2Header {}
+
+impl Multiboot2Header {
+    fn is_valid(&self) -> bool {
+        self.magic == MULTIBOOT2_HEADER_MAGIC
+            && self.architecture == MULTIBOOT2_ARCHITECTURE_I386
+            && self
+                .magic
+                .wrapping_add(self.architecture)
+                .wrapping_add(self.header_length)
+                .wrapping_add(self.checksum)
+                == 0
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct Multiboot2HeaderTag {
+    type_: u16,
+    flags: u16,
+    size: u32,
+}
+
+impl ByteCode for Multiboot2HeaderTag {}
+
+/// Payload of the address tag, which tells where to load the kernel image if it's
+/// not in ELF format.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct Multiboot2AddressTag {
+    header_addr: u32,
+    load_addr: u32,
+    load_end_addr: u32,
+    bss_end_addr: u32,
+}
+
+impl ByteCode for Multiboot2AddressTag {}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct Multiboot2ModuleTag {
+    mod_start: u32,
+    mod_end: u32,
+}
+
+impl ByteCode for Multiboot2ModuleTag {}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct Multiboot2BasicMeminfoTag {
+    mem_lower: u32,
+    mem_upper: u32,
+}
+
+impl ByteCode for Multiboot2BasicMeminfoTag {}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct Multiboot2MmapEntry {
+    addr: u64,
+    len: u64,
+    type_: u32,
+    reserved: u32,
+}
+
+impl ByteCode for Multiboot2MmapEntry {}
+
+/// Multiboot2 header found in kernel image.
+pub struct Multiboot2Kernel {
+    /// Offset of the header in kernel image.
+    offset: u64,
+    /// Load address from the address tag.
+    address: Option<Multiboot2AddressTag>,
+    /// Entry address from the entry address tag.
+    entry: Option<u32>,
+}
+
+fn read_object<T: ByteCode + Default>(buf: &[u8], offset: usize) -> Option<T> {
+    let mut obj = T::default();
+    let len = std::mem::size_of::<T>();
+    obj.as_mut_bytes()
+        .copy_from_slice(buf.get(offset..offset.checked_add(len)?)?);
+    Some(obj)
+}
+
+fn align_up(offset: usize) -> usize {
+    (offset + MULTIBOOT2_ALIGN - 1) & !(MULTIBOOT2_ALIGN - 1)
+}
+
+/// Search for the multiboot2 header in the first 32768 bytes of kernel image,
+/// and parse its tags.
+///
+/// # Arguments
+///
+/// * `kernel_image` - Guest kernel image.
+///
+/// # Errors
+///
+/// * The header is broken, or requires features not supported.
+pub fn find_multiboot2_header(kernel_image: &mut File) -> Result<Option<Multiboot2Kernel>> {
+    let len = std::cmp::min(kernel_image.metadata()?.len(), MULTIBOOT2_SEARCH);
+    let mut buf = vec![0_u8; len as usize];
+    kernel_image.seek(SeekFrom::Start(0))?;
+    kernel_image.read_exact(&mut buf)?;
+    kernel_image.seek(SeekFrom::Start(0))?;
+
+    let header_size = std::mem::size_of::<Multiboot2Header>();
+    let offset = (0..buf.len()).step_by(MULTIBOOT2_ALIGN).find(|offset| {
+        matches!(read_object::<Multiboot2Header>(&buf, *offset), Some(hdr) if hdr.is_valid())
+    });
+    let offset = match offset {
+        Some(offset) => offset,
+        None => return Ok(None),
+    };
+    let header = read_object::<Multiboot2Header>(&buf, offset).unwrap();
+    let header_end = offset + header.header_length as usize;
+    if header_end > buf.len() {
+        bail!(
+            "Multiboot2 header of length 0x{:x} at offset 0x{:x} exceeds the image",
+            header.header_length,
+            offset
+        );
+    }
+
+    let mut kernel = Multiboot2Kernel {
+        offset: offset as u64,
+        address: None,
+        entry: None,
+    };
+    let tag_size = std::mem::size_of::<Multiboot2HeaderTag>();
+    let mut tag_offset = offset + header_size;
+    loop {
+        let tag = read_object::<Multiboot2HeaderTag>(&buf, tag_offset)
+            .filter(|tag| {
+                tag.size as usize >= tag_size && tag_offset + tag.size as usize <= header_end
+            })
+            .with_context(|| format!("Invalid multiboot2 header tag at 0x{:x}", tag_offset))?;
+        let payload = tag_offset + tag_size;
+        match tag.type_ {
+            MULTIBOOT2_HEADER_TAG_END => break,
+            MULTIBOOT2_HEADER_TAG_INFORMATION_REQUEST => {
+                let requests = (payload..tag_offset + tag.size as usize)
+                    .step_by(std::mem::size_of::<u32>())
+                    .filter_map(|offset| read_object::<u32>(&buf, offset));
+                for request in requests {
+                    if tag.flags & MULTIBOOT2_TAG_OPTIONAL == 0
+                        && !matches!(
+                            request,
+                            MULTIBOOT2_TAG_TYPE_CMDLINE
+                                | MULTIBOOT2_TAG_TYPE_BOOT_LOADER_NAME
+                                | MULTIBOOT2_TAG_TYPE_MODULE
+                                | MULTIBOOT2_TAG_TYPE_BASIC_MEMINFO
+                                | MULTIBOOT2_TAG_TYPE_MMAP
+                        )
+                    {
+                        bail!(
+                            "Multiboot2 information of type {} is not supported",
+                            request
+                        );
+                    }
+                }
+            }
+            MULTIBOOT2_HEADER_TAG_ADDRESS => {
+                kernel.address = Some(
+                    read_object::<Multiboot2AddressTag>(&buf, payload)
+                        .with_context(|| "Invalid multiboot2 address tag")?,
+                );
+            }
+            MULTIBOOT2_HEADER_TAG_ENTRY_ADDRESS => {
+                kernel.entry = Some(
+                    read_object::<u32>(&buf, payload)
+                        .with_context(|| "Invalid multiboot2 entry address tag")?,
+                );
+            }
+            // There is no console for kernel to choose, and modules are always page aligned.
+            MULTIBOOT2_HEADER_TAG_CONSOLE_FLAGS | MULTIBOOT2_HEADER_TAG_MODULE_ALIGN => {}
+            type_ => {
+                if tag.flags & MULTIBOOT2_TAG_OPTIONAL == 0 {
+                    bail!("Multiboot2 header tag of type {} is not supported", type_);
+                }
+            }
+        }
+        tag_offset = align_up(tag_offset + tag.size as usize);
+    }
+
+    Ok(Some(kernel))
+}
+
+/// Load multiboot2 kernel to guest memory, return the entry address of kernel.
+///
+/// Kernel image is loaded according to the address tag if there is one, otherwise
+/// it must be an ELF image.
+///
+/// # Arguments
+///
+/// * `kernel_image` - Guest kernel image.
+/// * `kernel` - Multiboot2 header of kernel.
+/// * `sys_mem` - Guest memory.
+pub fn load_multiboot2_kernel(
+    kernel_image: &mut File,
+    kernel: &Multiboot2Kernel,
+    sys_mem: &Arc<AddressSpace>,
+) -> Result<u64> {
+    let address = match kernel.address {
+        Some(address) => address,
+        None => {
+            if !is_elf_kernel(kernel_image)? {
+                bail!("Multiboot2 kernel is neither in ELF format nor has an address tag");
+            }
+            let elf = load_elf_kernel(kernel_image, sys_mem)?;
+            return Ok(kernel.entry.map_or(elf.entry, u64::from));
+        }
+    };
+
+    let entry = kernel
+        .entry
+        .with_context(|| "Multiboot2 kernel with address tag has no entry address tag")?;
+    let load_addr = address.load_addr as u64;
+    let header_addr = address.header_addr as u64;
+    if header_addr < load_addr || header_addr - load_addr > kernel.offset {
+        bail!(
+            "Invalid multiboot2 address tag: header_addr 0x{:x}, load_addr 0x{:x}",
+            header_addr,
+            load_addr
+        );
+    }
+    let load_offset = kernel.offset - (header_addr - load_addr);
+    let image_size = kernel_image.metadata()?.len() - load_offset;
+    let load_size = match address.load_end_addr as u64 {
+        0 => image_size,
+        load_end if load_end >= load_addr && load_end - load_addr <= image_size => {
+            load_end - load_addr
+        }
+        load_end => bail!(
+            "Invalid multiboot2 address tag: load_addr 0x{:x}, load_end_addr 0x{:x}",
+            load_addr,
+            load_end
+        ),
+    };
+    let mem_size = match address.bss_end_addr as u64 {
+        0 => load_size,
+        bss_end => std::cmp::max(bss_end.saturating_sub(load_addr), load_size),
+    };
+    let mem_end = sys_mem.memory_end_address().raw_value();
+    if load_addr < VMLINUX_RAM_START || load_addr + mem_size > mem_end {
+        return Err(anyhow!(BootLoaderError::KernelOverflow(
+            load_addr, mem_size
+        )));
+    }
+
+    kernel_image.seek(SeekFrom::Start(load_offset))?;
+    sys_mem.write(kernel_image, GuestAddress(load_addr), load_size)?;
+    // Clear the bss, which may contain data of last boot.
+    if mem_size > load_size {
+        let zeros = vec![0_u8; (mem_size - load_size) as usize];
+        sys_mem.write(
+            &mut zeros.as_slice(),
+            GuestAddress(load_addr + load_size),
+            mem_size - load_size,
+        )?;
+    }
+
+    Ok(entry as u64)
+}
+
+fn push_info_tag(info: &mut Vec<u8>, type_: u32, payload: &[u8]) {
+    let size = (2 * std::mem::size_of::<u32>() + payload.len()) as u32;
+    info.extend_from_slice(type_.as_bytes());
+    info.extend_from_slice(size.as_bytes());
+    info.extend_from_slice(payload);
+    info.resize(align_up(info.len()), 0);
+}
+
+/// Setup multiboot2 boot information, the address of it is passed to kernel in %ebx.
+///
+/// # Arguments
+///
+/// * `config` - Boot source config.
+/// * `sys_mem` - Guest memory.
+/// * `info_addr` - Guest address of boot information.
+/// * `info_max` - Max size of boot information.
+/// * `initrd` - Guest address and size of initrd, loaded as the only module.
+pub fn setup_boot_info(
+    config: &X86BootLoaderConfig,
+    sys_mem: &Arc<AddressSpace>,
+    info_addr: u64,
+    info_max: u64,
+    initrd: Option<(u64, u64)>,
+) -> Result<()> {
+    // Fixed part of boot information: total_size and reserved.
+    let mut info = vec![0_u8; 2 * std::mem::size_of::<u32>()];
+
+    let mut cmdline = config.kernel_cmdline.as_bytes().to_vec();
+    cmdline.push(0);
+    push_info_tag(&mut info, MULTIBOOT2_TAG_TYPE_CMDLINE, &cmdline);
+
+    let mut name = MULTIBOOT2_BOOT_LOADER_NAME.as_bytes().to_vec();
+    name.push(0);
+    push_info_tag(&mut info, MULTIBOOT2_TAG_TYPE_BOOT_LOADER_NAME, &name);
+
+    if let Some((addr, size)) = initrd {
+        let module = Multiboot2ModuleTag {
+            mod_start: addr as u32,
+            mod_end: (addr + size) as u32,
+        };
+        // Module has an empty cmdline.
+        let mut payload = module.as_bytes().to_vec();
+        payload.push(0);
+        push_info_tag(&mut info, MULTIBOOT2_TAG_TYPE_MODULE, &payload);
+    }
+
+    let memmap = e820_entries(config, sys_mem);
+    let ram_size_at = |start: u64| {
+        memmap
+            .iter()
+            .find(|entry| entry.addr == start && entry.type_ == E820_RAM)
+            .map_or(0, |entry| entry.size)
+    };
+    let meminfo = Multiboot2BasicMeminfoTag {
+        mem_lower: (ram_size_at(0) >> 10) as u32,
+        mem_upper: (ram_size_at(VMLINUX_RAM_START) >> 10) as u32,
+    };
+    push_info_tag(
+        &mut info,
+        MULTIBOOT2_TAG_TYPE_BASIC_MEMINFO,
+        meminfo.as_bytes(),
+    );
+
+    // entry_size and entry_version, followed by the entries.
+    let mut mmap = Vec::new();
+    mmap.extend_from_slice((std::mem::size_of::<Multiboot2MmapEntry>() as u32).as_bytes());
+    mmap.extend_from_slice(0_u32.as_bytes());
+    for entry in memmap.iter().filter(|entry| entry.size != 0) {
+        let mmap_entry = Multiboot2MmapEntry {
+            addr: entry.addr,
+            len: entry.size,
+            type_: entry.type_,
+            reserved: 0,
+        };
+        mmap.extend_from_slice(mmap_entry.as_bytes());
+    }
+    push_info_tag(&mut info, MULTIBOOT2_TAG_TYPE_MMAP, &mmap);
+
+    push_info_tag(&mut info, MULTIBOOT2_TAG_TYPE_END, &[]);
+
+    let total_size = info.len() as u32;
+    info[0..4].copy_from_slice(total_size.as_bytes());
+    if total_size as u64 > info_max {
+        bail!(
+            "Multiboot2 boot information of size 0x{:x} exceeds the max size 0x{:x}",
+            total_size,
+            info_max
+        );
+    }
+
+    sys_mem
+        .write(
+            &mut info.as_slice(),
+            GuestAddress(info_addr),
+            info.len() as u64,
+        )
+        .with_context(|| {
+            format!(
+                "Failed to load multiboot2 boot information to 0x{:x}",
+                info_addr
+            )
+        })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn multiboot2_header(tags: &[(u16, u16, Vec<u8>)]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for (type_, flags, payload) in tags {
+            buf.extend_from_slice(type_.as_bytes());
+            buf.extend_from_slice(flags.as_bytes());
+            buf.extend_from_slice((8 + payload.len() as u32).as_bytes());
+            buf.extend_from_slice(payload);
+            buf.resize(align_up(buf.len()), 0);
+        }
+        buf.extend_from_slice(&[0, 0, 0, 0, 8, 0, 0, 0]);
+
+        let header_length = 16 + buf.len() as u32;
+        let checksum = 0_u32
+            .wrapping_sub(MULTIBOOT2_HEADER_MAGIC)
+            .wrapping_sub(header_length);
+        let mut header = Vec::new();
+        header.extend_from_slice(MULTIBOOT2_HEADER_MAGIC.as_bytes());
+        header.extend_from_slice(MULTIBOOT2_ARCHITECTURE_I386.as_bytes());
+        header.extend_from_slice(header_length.as_bytes());
+        header.extend_from_slice(checksum.as_bytes());
+        header.extend_from_slice(&buf);
+        header
+    }
+
+    fn find_header(path: &str, image: &[u8]) -> Result<Option<Multiboot2Kernel>> {
+        std::fs::write(path, image).unwrap();
+        let mut file = File::open(path).unwrap();
+        let kernel = find_multiboot2_header(&mut file);
+        std::fs::remove_file(path).unwrap();
+        kernel
+    }
+
+    #[test]
+    fn test_find_multiboot2_header() {
+        let path = "/tmp/stratovirt_test_multiboot2_header";
+
+        // No header.
+        assert!(find_header(path, &[0_u8; 0x100]).unwrap().is_none());
+
+        // Header at offset 0x48 with address and entry tags.
+        let address = Multiboot2AddressTag {
+            header_addr: 0x10_0048,
+            load_addr: 0x10_0000,
+            load_end_addr: 0,
+            bss_end_addr: 0x10_2000,
+        };
+        let mut image = vec![0_u8; 0x48];
+        image.extend(multiboot2_header(&[
+            (
+                MULTIBOOT2_HEADER_TAG_ADDRESS,
+                0,
+                address.as_bytes().to_vec(),
+            ),
+            (
+                MULTIBOOT2_HEADER_TAG_ENTRY_ADDRESS,
+                0,
+                0x10_0100_u32.as_bytes().to_vec(),
+            ),
+        ]));
+        let kernel = find_header(path, &image).unwrap().unwrap();
+        assert_eq!(kernel.offset, 0x48);
+        assert_eq!(kernel.entry, Some(0x10_0100));
+        assert_eq!(kernel.address.unwrap().bss_end_addr, 0x10_2000);
+
+        // Header with bad checksum is ignored.
+        image[0x48 + 12] ^= 0xff;
+        assert!(find_header(path, &image).unwrap().is_none());
+
+        // Unsupported tag is fine only if it's optional.
+        let image = multiboot2_header(&[(5, 1, vec![0_u8; 12])]);
+        assert!(find_header(path, &image).unwrap().is_some());
+        let image = multiboot2_header(&[(5, 0, vec![0_u8; 12])]);
+        assert!(find_header(path, &image).is_err());
+
+        // Required information must be supported.
+        let image = multiboot2_header(&[(
+            MULTIBOOT2_HEADER_TAG_INFORMATION_REQUEST,
+            0,
+            [1_u32, 6].iter().flat_map(|t| t.to_le_bytes()).collect(),
+        )]);
+        assert!(find_header(path, &image).unwrap().is_some());
+        let image = multiboot2_header(&[(
+            MULTIBOOT2_HEADER_TAG_INFORMATION_REQUEST,
+            0,
+            8_u32.as_bytes().to_vec(),
+        )]);
+        assert!(find_header(path, &image).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+
+use address_space::{AddressSpace, GuestAddress};
+use util::byte_code::ByteCode;
+
+use super::super::bootparam::{e820_entries, E820Entry};
+use super::super::X86BootLoaderConfig;
+
+const XEN_HVM_START_MAGIC_VALUE: u32 = 0x336e_c578;
+// Version 1 of start info has the memory map.
+const XEN_HVM_START_INFO_VERSION: u32 = 1;
+
+// Structures below sourced from:
+// xen/include/public/arch-x86/hvm/start_info.h
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct HvmStartInfo {
+    magic: u32,
+    version: u32,
+    flags: u32,
+    nr_modules: u32,
+    modlist_paddr: u64,
+    cmdline_paddr: u64,
+    rsdp_paddr: u64,
+    memmap_paddr: u64,
+    memmap_entries: u32,
+    reserved: u32,
+}
+
+impl ByteCode for HvmStartInfo {}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct HvmModlistEntry {
+    paddr: u64,
+    size: u64,
+    cmdline_paddr: u64,
+    reserved: u64,
+}
+
+impl ByteCode for HvmModlistEntry {}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct HvmMemmapTableEntry {
+    addr: u64,
+    size: u64,
+    type_: u32,
+    reserved: u32,
+}
+
+impl ByteCode for HvmMemmapTableEntry {}
+
+impl From<E820Entry> for HvmMemmapTableEntry {
+    fn from(entry: E820Entry) -> Self {
+        HvmMemmapTableEntry {
+            addr: entry.addr,
+            size: entry.size,
+            type_: entry.type_,
+            reserved: 0,
+        }
+    }
+}
+
+/// Setup `hvm_start_info` for PVH kernel, which is followed by the memory map and the
+/// module list. The address of `hvm_start_info` is passed to kernel in %rbx.
+///
+/// # Arguments
+///
+/// * `config` - Boot source config.
+/// * `sys_mem` - Guest memory.
+/// * `start_info_addr` - Guest address of `hvm_start_info`.
+/// * `cmdline_addr` - Guest address of kernel cmdline.
+/// * `initrd` - Guest address and size of initrd, loaded as the only module.
+pub fn setup_start_info(
+    config: &X86BootLoaderConfig,
+    sys_mem: &Arc<AddressSpace>,
+    start_info_addr: u64,
+    cmdline_addr: u64,
+    initrd: Option<(u64, u64)>,
+) -> Result<()> {
+    let memmap: Vec<HvmMemmapTableEntry> = e820_entries(config, sys_mem)
+        .into_iter()
+        .map(HvmMemmapTableEntry::from)
+        .collect();
+    let memmap_addr = start_info_addr + std::mem::size_of::<HvmStartInfo>() as u64;
+    let modlist_addr =
+        memmap_addr + (memmap.len() * std::mem::size_of::<HvmMemmapTableEntry>()) as u64;
+
+    let mut start_info = HvmStartInfo {
+        magic: XEN_HVM_START_MAGIC_VALUE,
+        version: XEN_HVM_START_INFO_VERSION,
+        cmdline_paddr: cmdline_addr,
+        memmap_paddr: memmap_addr,
+        memmap_entries: memmap.len() as u32,
+        ..Default::default()
+    };
+
+    for (index, entry) in memmap.iter().enumerate() {
+        let addr = memmap_addr + (index * std::mem::size_of::<HvmMemmapTableEntry>()) as u64;
+        sys_mem
+            .write_object(entry, GuestAddress(addr))
+            .with_context(|| format!("Failed to load PVH memory map to 0x{:x}", addr))?;
+    }
+
+    if let Some((paddr, size)) = initrd {
+        let module = HvmModlistEntry {
+            paddr,
+            size,
+            ..Default::default()
+        };
+        sys_mem
+            .write_object(&module, GuestAddress(modlist_addr))
+            .with_context(|| format!("Failed to load PVH module list to 0x{:x}", modlist_addr))?;
+        start_info.nr_modules = 1;
+        start_info.modlist_paddr = modlist_addr;
+    }
+
+    sys_mem
+        .write_object(&start_info, GuestAddress(start_info_addr))
+        .with_context(|| format!("Failed to load PVH start info to 0x{:x}", start_info_addr))?;
+
+    Ok(())
+}
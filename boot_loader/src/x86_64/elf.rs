@@ -14,13 +14,16 @@ use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::sync::Arc;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 
 use address_space::{AddressSpace, GuestAddress};
-use devices::legacy::{FwCfgEntryType, FwCfgOps};
 use util::byte_code::ByteCode;
 use util::num_ops::round_up;
 
+use crate::error::BootLoaderError;
+
+const EM_X86_64: u16 = 62;
+
 const EI_MAG0: usize = 0;
 const EI_MAG3: usize = 3;
 const EI_CLASS: usize = 4;
@@ -121,18 +124,35 @@ struct Elf64NoteHeader {
 
 impl ByteCode for Elf64NoteHeader {}
 
-/// Parse ELF_format kernel file and find the PVH entry.
+/// Check whether the kernel file is in ELF format.
+pub fn is_elf_kernel(kernel_image: &mut File) -> Result<bool> {
+    let mut magic = [0_u8; EI_MAG3 + 1];
+    kernel_image.seek(SeekFrom::Start(0))?;
+    let is_elf = kernel_image.read_exact(&mut magic).is_ok()
+        && magic == [ELFMAG0, ELFMAG1, ELFMAG2, ELFMAG3];
+    kernel_image.seek(SeekFrom::Start(0))?;
+    Ok(is_elf)
+}
+
+/// ELF-format kernel loaded to guest memory.
+pub struct ElfKernel {
+    /// Physical address of the 64-bit entry.
+    pub entry: u64,
+    /// PVH entry address, found in the `XEN_ELFNOTE_PHYS32_ENTRY` note.
+    pub pvh_entry: Option<u64>,
+    /// Lowest guest address of the loadable segments.
+    pub addr_low: u64,
+    /// End guest address of the loadable segments.
+    pub addr_high: u64,
+}
+
+/// Parse ELF_format kernel file, load it to guest memory and find the entries.
 ///
 /// # Arguments
 ///
 /// `kernel_image` - ELF-format kernel file.
 /// `sys_mem` - Guest memory.
-/// `fwcfg` - FwCfg device.
-pub fn load_elf_kernel(
-    kernel_image: &mut File,
-    sys_mem: &Arc<AddressSpace>,
-    fwcfg: &mut dyn FwCfgOps,
-) -> Result<()> {
+pub fn load_elf_kernel(kernel_image: &mut File, sys_mem: &Arc<AddressSpace>) -> Result<ElfKernel> {
     kernel_image.seek(SeekFrom::Start(0))?;
     let kernel_length = kernel_image.metadata().map(|m| m.len())?;
 
@@ -141,6 +161,10 @@ pub fn load_elf_kernel(
     elf_header
         .is_valid()
         .with_context(|| "ELF header is invalid")?;
+    if elf_header.e_machine != EM_X86_64 {
+        let machine = elf_header.e_machine;
+        bail!("ELF kernel of machine {} is not supported", machine);
+    }
 
     let ep_hdrs = elf_header
         .parse_prog_hdrs(kernel_image)
@@ -148,7 +172,7 @@ pub fn load_elf_kernel(
 
     let mut pvh_start_addr: Option<u64> = None;
     let mut addr_low = u64::MAX;
-    let mut addr_max = 0_u64;
+    let mut addr_high = 0_u64;
     for ph in &ep_hdrs {
         let ph_offset = ph.p_offset;
         let ph_size = ph.p_filesz;
@@ -162,11 +186,25 @@ pub fn load_elf_kernel(
         }
 
         if ph.p_type == PT_LOAD {
+            let (paddr, filesz, memsz) = (ph.p_paddr, ph.p_filesz, ph.p_memsz);
+            let mem_end = sys_mem.memory_end_address().raw_value();
+            if filesz > memsz || !matches!(paddr.checked_add(memsz), Some(end) if end <= mem_end) {
+                return Err(anyhow!(BootLoaderError::KernelOverflow(paddr, memsz)));
+            }
             kernel_image.seek(SeekFrom::Start(ph.p_offset))?;
-            sys_mem.write(kernel_image, GuestAddress(ph.p_paddr), ph.p_filesz)?;
+            sys_mem.write(kernel_image, GuestAddress(paddr), filesz)?;
+            // Clear the bss, which may contain data of last boot.
+            if memsz > filesz {
+                let zeros = vec![0_u8; (memsz - filesz) as usize];
+                sys_mem.write(
+                    &mut zeros.as_slice(),
+                    GuestAddress(paddr + filesz),
+                    memsz - filesz,
+                )?;
+            }
 
             addr_low = std::cmp::min(addr_low, ph.p_paddr);
-            addr_max = std::cmp::max(addr_max, ph.p_paddr);
+            addr_high = std::cmp::max(addr_high, ph.p_paddr + ph.p_memsz);
         }
         if ph.p_type == PT_NOTE {
             kernel_image.seek(SeekFrom::Start(ph.p_offset))?;
@@ -212,19 +250,32 @@ pub fn load_elf_kernel(
         }
     }
 
-    let pvh_start_addr = pvh_start_addr
-        .with_context(|| "No Note header contains PVH entry info in ELF kernel image.")?;
-    fwcfg.add_data_entry(
-        FwCfgEntryType::KernelEntry,
-        (pvh_start_addr as u32).as_bytes().to_vec(),
-    )?;
-    fwcfg.add_data_entry(
-        FwCfgEntryType::KernelAddr,
-        (addr_low as u32).as_bytes().to_vec(),
-    )?;
-    fwcfg.add_data_entry(
-        FwCfgEntryType::KernelSize,
-        (addr_max as u32 - addr_low as u32).as_bytes().to_vec(),
-    )?;
-    Ok(())
+    if addr_low > addr_high {
+        bail!("No loadable segment in ELF kernel image");
+    }
+
+    // Linux kernel sets physical address to e_entry, translate it from virtual
+    // address in case it doesn't.
+    let e_entry = elf_header.e_entry;
+    let entry = ep_hdrs
+        .iter()
+        .filter(|ph| ph.p_type == PT_LOAD)
+        .find_map(|ph| {
+            let (paddr, vaddr, memsz) = (ph.p_paddr, ph.p_vaddr, ph.p_memsz);
+            if e_entry >= paddr && e_entry < paddr + memsz {
+                Some(e_entry)
+            } else if e_entry >= vaddr && e_entry - vaddr < memsz {
+                Some(e_entry - vaddr + paddr)
+            } else {
+                None
+            }
+        })
+        .with_context(|| format!("ELF entry 0x{:x} is out of loadable segments", e_entry))?;
+
+    Ok(ElfKernel {
+        entry,
+        pvh_entry: pvh_start_addr,
+        addr_low,
+        addr_high,
+    })
 }
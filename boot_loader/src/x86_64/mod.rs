@@ -11,7 +11,9 @@
 // See the Mulan PSL v2 for more details.
 
 //! Boot Loader load PE and bzImage linux kernel image to guest memory according
-//! [`x86 boot protocol`](https://www.kernel.org/doc/Documentation/x86/boot.txt).
+//! [`x86 boot protocol`](https://www.kernel.org/doc/Documentation/x86/boot.txt),
+//! or ELF linux kernel with PVH entry according to
+//! [`PVH boot protocol`](https://xenbits.xen.org/docs/unstable/misc/pvh.html).
 //!
 //! Below is x86_64 bootloader memory layout:
 //!
@@ -32,6 +34,9 @@
 //!   0x0000_b000   +------------------------+
 //!                 |  Page Directory Entry  |
 //!                 |                        |
+//!   0x0000_c000   +------------------------+
+//!                 |  Setup Data            |
+//!                 |                        |
 //!   0x0002_0000   +------------------------+
 //!                 |  Kernel Cmdline        |
 //!                 |                        |
@@ -54,6 +59,8 @@
 
 mod bootparam;
 mod direct_boot;
+#[allow(non_camel_case_types)]
+mod elf;
 mod standard_boot;
 
 use std::path::PathBuf;
@@ -64,12 +71,14 @@ use kvm_bindings::kvm_segment;
 
 use address_space::AddressSpace;
 use devices::legacy::FwCfgOps;
+use machine_manager::config::InitrdPlacement;
 
 const ZERO_PAGE_START: u64 = 0x0000_7000;
 const PML4_START: u64 = 0x0000_9000;
 const PDPTE_START: u64 = 0x0000_a000;
 const PDE_START: u64 = 0x0000_b000;
 const SETUP_START: u64 = 0x0001_0000;
+const SETUP_DATA_START: u64 = 0x0000_c000;
 const CMDLINE_START: u64 = 0x0002_0000;
 const BOOT_HDR_START: u64 = 0x0000_01F1;
 const BZIMAGE_BOOT_OFFSET: u64 = 0x0200;
@@ -98,6 +107,8 @@ pub struct X86BootLoaderConfig {
     pub kernel: Option<std::path::PathBuf>,
     /// Path of the initrd image.
     pub initrd: Option<PathBuf>,
+    /// Where to load the initrd image.
+    pub initrd_placement: InitrdPlacement,
     /// Kernel cmdline parameters.
     pub kernel_cmdline: String,
     /// VM's CPU count.
@@ -123,6 +134,10 @@ pub struct X86BootLoader {
     pub boot_pml4_addr: u64,
     pub zero_page_addr: u64,
     pub segments: BootGdtSegment,
+    /// Boot with PVH entry, `zero_page_addr` is the address of `hvm_start_info`.
+    pub pvh: bool,
+    /// Boot multiboot2 kernel, `zero_page_addr` is the address of boot information.
+    pub multiboot2: bool,
 }
 
 #[derive(Debug, Default, Copy, Clone)]
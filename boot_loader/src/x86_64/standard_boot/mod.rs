@@ -10,9 +10,6 @@
 // NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
 // See the Mulan PSL v2 for more details.
 
-#[allow(non_camel_case_types)]
-mod elf;
-
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::sync::Arc;
@@ -22,11 +19,12 @@ use devices::legacy::{FwCfgEntryType, FwCfgOps};
 use log::{error, info};
 use util::byte_code::ByteCode;
 
-use self::elf::load_elf_kernel;
 use super::bootparam::RealModeKernelHeader;
+use super::elf::load_elf_kernel;
 use super::X86BootLoaderConfig;
 use super::{BOOT_HDR_START, CMDLINE_START};
 use crate::error::BootLoaderError;
+use crate::initrd_load_addr;
 use crate::x86_64::bootparam::{E820Entry, E820_RAM, E820_RESERVED, UEFI_OVMF_ID};
 use crate::x86_64::{INITRD_ADDR_MAX, SETUP_START};
 use anyhow::{bail, Context, Result};
@@ -87,6 +85,31 @@ fn load_kernel_image(
     Ok(setup_data)
 }
 
+fn load_pvh_kernel(
+    kernel_image: &mut File,
+    sys_mem: &Arc<AddressSpace>,
+    fwcfg: &mut dyn FwCfgOps,
+) -> Result<()> {
+    let elf = load_elf_kernel(kernel_image, sys_mem)?;
+    let pvh_entry = elf
+        .pvh_entry
+        .with_context(|| "No Note header contains PVH entry info in ELF kernel image.")?;
+
+    fwcfg.add_data_entry(
+        FwCfgEntryType::KernelEntry,
+        (pvh_entry as u32).as_bytes().to_vec(),
+    )?;
+    fwcfg.add_data_entry(
+        FwCfgEntryType::KernelAddr,
+        (elf.addr_low as u32).as_bytes().to_vec(),
+    )?;
+    fwcfg.add_data_entry(
+        FwCfgEntryType::KernelSize,
+        ((elf.addr_high - elf.addr_low) as u32).as_bytes().to_vec(),
+    )?;
+    Ok(())
+}
+
 fn load_initrd(
     config: &X86BootLoaderConfig,
     sys_mem: &Arc<AddressSpace>,
@@ -105,7 +128,13 @@ fn load_initrd(
     let mut initrd_image = File::open(config.initrd.as_ref().unwrap())
         .with_context(|| BootLoaderError::BootLoaderOpenInitrd)?;
     let initrd_size = initrd_image.metadata().unwrap().len();
-    let initrd_addr = (initrd_addr_max - initrd_size) & !0xfff_u64;
+    let initrd_addr = initrd_load_addr(
+        config.initrd_placement,
+        initrd_size,
+        0,
+        initrd_addr_max,
+        sys_mem,
+    )?;
 
     load_image(&mut initrd_image, 0, FwCfgEntryType::InitrdData, fwcfg)
         .with_context(|| "Failed to load initrd")?;
@@ -222,7 +251,7 @@ pub fn load_linux(
         if let Some(err) = e.downcast_ref::<BootLoaderError>() {
             match err {
                 BootLoaderError::ElfKernel => {
-                    load_elf_kernel(&mut kernel_image, sys_mem, fwcfg)?;
+                    load_pvh_kernel(&mut kernel_image, sys_mem, fwcfg)?;
                     return Ok(());
                 }
                 _ => return Err(e),
@@ -10,12 +10,18 @@
 // NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
 // See the Mulan PSL v2 for more details.
 
+use std::os::raw::c_ulong;
+
+use anyhow::{bail, Result as AnyResult};
+use hypervisor::kvm::KVM_CHECK_EXTENSION;
 use kvm_bindings::{
+    KVM_CAP_ARM_PTRAUTH_ADDRESS, KVM_CAP_ARM_PTRAUTH_GENERIC, KVM_CAP_ARM_SVE, KVM_CAP_STEAL_TIME,
     KVM_REG_ARM_COPROC_MASK, KVM_REG_ARM_CORE, KVM_REG_SIZE_MASK, KVM_REG_SIZE_U32,
     KVM_REG_SIZE_U64,
 };
 use kvm_ioctls::{Cap, Kvm, VcpuFd};
 use machine_manager::config::{CpuConfig, PmuConfig};
+use vmm_sys_util::ioctl::ioctl_with_val;
 
 use super::core_regs::Result;
 
@@ -28,6 +34,9 @@ pub struct ArmCPUCaps {
     pub user_mem: bool,
     pub psci02: bool,
     pub mp_state: bool,
+    pub sve: bool,
+    pub pauth: bool,
+    pub steal_time: bool,
 }
 
 impl ArmCPUCaps {
@@ -42,13 +51,49 @@ impl ArmCPUCaps {
             user_mem: kvm.check_extension(Cap::UserMemory),
             psci02: kvm.check_extension(Cap::ArmPsci02),
             mp_state: kvm.check_extension(Cap::MpState),
+            sve: check_extension_raw(&kvm, KVM_CAP_ARM_SVE),
+            pauth: check_extension_raw(&kvm, KVM_CAP_ARM_PTRAUTH_ADDRESS)
+                && check_extension_raw(&kvm, KVM_CAP_ARM_PTRAUTH_GENERIC),
+            steal_time: check_extension_raw(&kvm, KVM_CAP_STEAL_TIME),
         }
     }
 }
 
+/// Check the capabilities which are not listed in `kvm_ioctls::Cap`.
+fn check_extension_raw(kvm: &Kvm, cap: u32) -> bool {
+    // SAFETY: The kvm fd is valid and the capability is defined by kernel.
+    unsafe { ioctl_with_val(kvm, KVM_CHECK_EXTENSION(), cap as c_ulong) > 0 }
+}
+
 #[derive(Copy, Clone, Debug, Default)]
 pub struct ArmCPUFeatures {
     pub pmu: bool,
+    pub sve: bool,
+    /// Max SVE vector length in quadwords, `0` means the max length supported by host.
+    pub sve_max_vq: u32,
+    pub pauth: bool,
+    /// Report stolen time to guest by paravirtual time.
+    pub steal_time: bool,
+}
+
+impl ArmCPUFeatures {
+    /// Check whether the features are supported by host.
+    ///
+    /// # Arguments
+    ///
+    /// * `caps` - Capabilities of host kvm.
+    pub fn check(&self, caps: &ArmCPUCaps) -> AnyResult<()> {
+        if self.sve && !caps.sve {
+            bail!("SVE is not supported by host kvm");
+        }
+        if self.pauth && !caps.pauth {
+            bail!("Pointer authentication is not supported by host kvm");
+        }
+        if self.steal_time && !caps.steal_time {
+            bail!("Steal time is not supported by host kvm");
+        }
+        Ok(())
+    }
 }
 
 impl From<&CpuConfig> for ArmCPUFeatures {
@@ -58,6 +103,10 @@ impl From<&CpuConfig> for ArmCPUFeatures {
                 PmuConfig::On => true,
                 PmuConfig::Off => false,
             },
+            sve: conf.sve,
+            sve_max_vq: conf.sve_max_vq.unwrap_or_default(),
+            pauth: conf.pauth,
+            steal_time: conf.steal_time,
         }
     }
 }
@@ -132,7 +132,8 @@ impl From<Arm64CoreRegs> for u64 {
 /// # Arguments
 ///
 /// * `vcpu_fd` - the VcpuFd in KVM mod.
-pub fn get_core_regs(vcpu_fd: &VcpuFd) -> Result<kvm_regs> {
+/// * `sve` - Whether SVE is enabled, the FPSIMD vregs can't be accessed if it is.
+pub fn get_core_regs(vcpu_fd: &VcpuFd, sve: bool) -> Result<kvm_regs> {
     let mut core_regs = kvm_regs::default();
 
     core_regs.regs.sp = vcpu_fd.get_one_reg(Arm64CoreRegs::UserPTRegSp.into())? as u64;
@@ -150,9 +151,11 @@ pub fn get_core_regs(vcpu_fd: &VcpuFd) -> Result<kvm_regs> {
         core_regs.spsr[i] = vcpu_fd.get_one_reg(Arm64CoreRegs::KvmSpsr(i).into())? as u64;
     }
 
-    for i in 0..KVM_NR_FP_REGS as usize {
-        core_regs.fp_regs.vregs[i] =
-            vcpu_fd.get_one_reg(Arm64CoreRegs::UserFPSIMDStateVregs(i).into())?;
+    if !sve {
+        for i in 0..KVM_NR_FP_REGS as usize {
+            core_regs.fp_regs.vregs[i] =
+                vcpu_fd.get_one_reg(Arm64CoreRegs::UserFPSIMDStateVregs(i).into())?;
+        }
     }
 
     core_regs.fp_regs.fpsr = vcpu_fd.get_one_reg(Arm64CoreRegs::UserFPSIMDStateFpsr.into())? as u32;
@@ -169,7 +172,8 @@ pub fn get_core_regs(vcpu_fd: &VcpuFd) -> Result<kvm_regs> {
 ///
 /// * `vcpu_fd` - the VcpuFd in KVM mod.
 /// * `core_regs` - kvm_regs state to be written.
-pub fn set_core_regs(vcpu_fd: &VcpuFd, core_regs: kvm_regs) -> Result<()> {
+/// * `sve` - Whether SVE is enabled, the FPSIMD vregs can't be accessed if it is.
+pub fn set_core_regs(vcpu_fd: &VcpuFd, core_regs: kvm_regs, sve: bool) -> Result<()> {
     vcpu_fd.set_one_reg(Arm64CoreRegs::UserPTRegSp.into(), core_regs.regs.sp as u128)?;
     vcpu_fd.set_one_reg(Arm64CoreRegs::KvmSpEl1.into(), core_regs.sp_el1 as u128)?;
     vcpu_fd.set_one_reg(
@@ -190,11 +194,13 @@ pub fn set_core_regs(vcpu_fd: &VcpuFd, core_regs: kvm_regs) -> Result<()> {
         vcpu_fd.set_one_reg(Arm64CoreRegs::KvmSpsr(i).into(), core_regs.spsr[i] as u128)?;
     }
 
-    for i in 0..KVM_NR_FP_REGS as usize {
-        vcpu_fd.set_one_reg(
-            Arm64CoreRegs::UserFPSIMDStateVregs(i).into(),
-            core_regs.fp_regs.vregs[i],
-        )?;
+    if !sve {
+        for i in 0..KVM_NR_FP_REGS as usize {
+            vcpu_fd.set_one_reg(
+                Arm64CoreRegs::UserFPSIMDStateVregs(i).into(),
+                core_regs.fp_regs.vregs[i],
+            )?;
+        }
     }
 
     vcpu_fd.set_one_reg(
@@ -19,19 +19,22 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use anyhow::{Context, Result};
-use hypervisor::kvm::KVM_FDS;
+use anyhow::{bail, Context, Result};
+use hypervisor::kvm::{KVM_ARM_VCPU_FINALIZE, KVM_FDS, KVM_GET_ONE_REG, KVM_SET_ONE_REG};
 use kvm_bindings::{
-    kvm_device_attr, kvm_mp_state, kvm_regs, kvm_vcpu_events, kvm_vcpu_init, RegList,
+    kvm_device_attr, kvm_mp_state, kvm_one_reg, kvm_regs, kvm_vcpu_events, kvm_vcpu_init, RegList,
     KVM_ARM_VCPU_PMU_V3_CTRL, KVM_ARM_VCPU_PMU_V3_INIT, KVM_ARM_VCPU_PMU_V3_IRQ,
-    KVM_MP_STATE_RUNNABLE, KVM_MP_STATE_STOPPED,
+    KVM_ARM_VCPU_PTRAUTH_ADDRESS, KVM_ARM_VCPU_PTRAUTH_GENERIC, KVM_ARM_VCPU_PVTIME_CTRL,
+    KVM_ARM_VCPU_PVTIME_IPA, KVM_ARM_VCPU_SVE, KVM_MP_STATE_RUNNABLE, KVM_MP_STATE_STOPPED,
+    KVM_REG_ARM64, KVM_REG_ARM64_SVE, KVM_REG_SIZE_U512,
 };
 use kvm_ioctls::{DeviceFd, VcpuFd};
+use vmm_sys_util::ioctl::{ioctl_with_mut_ref, ioctl_with_ref};
 
 use self::caps::CpregListEntry;
 pub use self::caps::{ArmCPUCaps, ArmCPUFeatures};
-use self::core_regs::{get_core_regs, set_core_regs};
-use crate::CPU;
+use self::core_regs::{get_core_regs, set_core_regs, Arm64CoreRegs};
+use crate::{CpuError, CPU};
 
 use migration::{
     DeviceStateDesc, FieldDesc, MigrationError, MigrationHook, MigrationManager, StateTransfer,
@@ -57,15 +60,29 @@ const SYS_MPIDR_EL1: u64 = 0x6030_0000_0013_c005;
 // Counter-timer Virtual Count register: Due to the API interface problem, the encode of
 // this register is SYS_CNTV_CVAL_EL0.
 const SYS_CNTV_CNT_EL0: u64 = 0x6030_0000_0013_df1a;
+// The pseudo-register which holds the set of vector lengths supported by SVE.
+// See: https://elixir.bootlin.com/linux/v5.10/source/arch/arm64/include/uapi/asm/kvm.h#L260
+const KVM_REG_ARM64_SVE_VLS: u64 =
+    KVM_REG_ARM64 | KVM_REG_ARM64_SVE as u64 | KVM_REG_SIZE_U512 | 0xffff;
 
 const KVM_MAX_CPREG_ENTRIES: usize = 500;
 
+/// System event type of PSCI SYSTEM_SUSPEND, it's reported by kvm when
+/// `KVM_CAP_ARM_SYSTEM_SUSPEND` is enabled.
+pub const KVM_SYSTEM_EVENT_SUSPEND: u32 = 5;
+// PSCI return value which means the call is denied.
+const PSCI_RET_DENIED: i64 = -3;
+
 /// Interrupt ID for pmu.
 /// See: https://developer.arm.com/documentation/den0094/b/
 /// And: https://developer.arm.com/documentation/dai0492/b/
 pub const PPI_BASE: u32 = 16;
 pub const PMU_INTR: u32 = 7;
 
+/// Size of the stolen time structure of each vcpu, which is shared with guest.
+/// See: https://developer.arm.com/documentation/den0057/a/
+pub const PVTIME_SIZE_PER_CPU: u64 = 64;
+
 /// AArch64 CPU booting configure information
 ///
 /// Before jumping into the kernel, primary CPU general-purpose
@@ -189,12 +206,26 @@ impl ArmCPUState {
         if vcpu_config.pmu {
             self.kvi.features[0] |= 1 << kvm_bindings::KVM_ARM_VCPU_PMU_V3;
         }
+        // Enable SVE from config.
+        if vcpu_config.sve {
+            self.kvi.features[0] |= 1 << KVM_ARM_VCPU_SVE;
+        }
+        // Enable pointer authentication from config, both address and generic
+        // authentication are required by kvm.
+        if vcpu_config.pauth {
+            self.kvi.features[0] |=
+                1 << KVM_ARM_VCPU_PTRAUTH_ADDRESS | 1 << KVM_ARM_VCPU_PTRAUTH_GENERIC;
+        }
 
         self.set_core_reg(boot_config);
 
         vcpu_fd
             .vcpu_init(&self.kvi)
             .with_context(|| "Failed to init kvm vcpu")?;
+        if vcpu_config.sve {
+            finalize_sve(vcpu_fd, vcpu_config.sve_max_vq)
+                .with_context(|| format!("Failed to init SVE for CPU {}", self.apic_id))?;
+        }
         self.mpidr = vcpu_fd
             .get_one_reg(SYS_MPIDR_EL1)
             .with_context(|| "Failed to get mpidr")? as u64;
@@ -219,7 +250,7 @@ impl ArmCPUState {
     ///
     /// * `vcpu_fd` - Vcpu file descriptor in kvm.
     pub fn reset_vcpu(&self, vcpu_fd: &Arc<VcpuFd>) -> Result<()> {
-        set_core_regs(vcpu_fd, self.core_regs)
+        set_core_regs(vcpu_fd, self.core_regs, self.features.sve)
             .with_context(|| format!("Failed to set core register for CPU {}", self.apic_id))?;
         vcpu_fd
             .set_mp_state(self.mp_state)
@@ -236,6 +267,20 @@ impl ArmCPUState {
         Ok(())
     }
 
+    /// Set the state of vcpu which resumes from PSCI SYSTEM_SUSPEND, vcpu
+    /// starts at `entry` with `context_id` in x0.
+    pub fn set_resume_point(&mut self, entry: u64, context_id: u64) {
+        self.core_regs.regs.pstate = PSR_D_BIT | PSR_A_BIT | PSR_I_BIT | PSR_F_BIT | PSR_MODE_EL1h;
+        self.core_regs.regs.regs[0] = context_id;
+        self.core_regs.regs.pc = entry;
+        self.mp_state.mp_state = KVM_MP_STATE_RUNNABLE;
+    }
+
+    /// Set vcpu powered off, it can be powered on by guest with PSCI CPU_ON.
+    pub fn set_powered_off(&mut self) {
+        self.mp_state.mp_state = KVM_MP_STATE_STOPPED;
+    }
+
     /// Get mpidr value.
     pub fn mpidr(&self) -> u64 {
         self.mpidr
@@ -289,7 +334,116 @@ impl ArmCPUState {
     }
 }
 
+/// Limit the vector lengths of SVE and finalize the SVE configuration of vcpu.
+///
+/// # Arguments
+///
+/// * `vcpu_fd` - Vcpu file descriptor in kvm.
+/// * `max_vq` - Max vector length in quadwords, `0` means the max length supported by host.
+fn finalize_sve(vcpu_fd: &VcpuFd, max_vq: u32) -> Result<()> {
+    if max_vq != 0 {
+        // Bit `vq - 1` of the bitmap is set if vector length `vq` is supported.
+        let mut vls = [0_u64; 8];
+        let mut reg = kvm_one_reg {
+            id: KVM_REG_ARM64_SVE_VLS,
+            addr: vls.as_mut_ptr() as u64,
+        };
+        // SAFETY: The vcpu fd is valid and the buffer is large enough for a 512-bit register.
+        let ret = unsafe { ioctl_with_mut_ref(vcpu_fd, KVM_GET_ONE_REG(), &mut reg) };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| "Failed to get SVE vector lengths");
+        }
+
+        let index = (max_vq - 1) as usize;
+        if vls[index / 64] & (1 << (index % 64)) == 0 {
+            bail!("SVE vector length {} is not supported by host", max_vq);
+        }
+        vls[index / 64] &= u64::MAX >> (63 - index % 64);
+        for word in vls.iter_mut().skip(index / 64 + 1) {
+            *word = 0;
+        }
+
+        // SAFETY: The vcpu fd is valid and the buffer is large enough for a 512-bit register.
+        let ret = unsafe { ioctl_with_ref(vcpu_fd, KVM_SET_ONE_REG(), &reg) };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| "Failed to set SVE vector lengths");
+        }
+    }
+
+    let feature = KVM_ARM_VCPU_SVE as i32;
+    // SAFETY: The vcpu fd is valid and the argument is a feature of vcpu.
+    let ret = unsafe { ioctl_with_ref(vcpu_fd, KVM_ARM_VCPU_FINALIZE(), &feature) };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| "Failed to finalize SVE");
+    }
+    Ok(())
+}
+
 impl CPU {
+    /// Handle PSCI SYSTEM_SUSPEND from guest. The resume point is recorded and VM is
+    /// suspended until it's woken up.
+    pub(crate) fn guest_suspend(&self) -> Result<()> {
+        let entry =
+            self.fd
+                .get_one_reg(Arm64CoreRegs::UserPTRegRegs(1).into())
+                .with_context(|| "Failed to get entry point of SYSTEM_SUSPEND")? as u64;
+        let context_id =
+            self.fd
+                .get_one_reg(Arm64CoreRegs::UserPTRegRegs(2).into())
+                .with_context(|| "Failed to get context id of SYSTEM_SUSPEND")? as u64;
+        *self.suspend_entry.lock().unwrap() = Some((entry, context_id));
+
+        let vm = self
+            .vm
+            .upgrade()
+            .with_context(|| CpuError::NoMachineInterface)?;
+        if !vm.lock().unwrap().suspend() {
+            // Guest goes on running if the request is denied.
+            self.suspend_entry.lock().unwrap().take();
+            self.fd
+                .set_one_reg(
+                    Arm64CoreRegs::UserPTRegRegs(0).into(),
+                    PSCI_RET_DENIED as u64 as u128,
+                )
+                .with_context(|| "Failed to set return value of SYSTEM_SUSPEND")?;
+        }
+        Ok(())
+    }
+
+    /// Get the program counter of the crashed vcpu.
+    pub(crate) fn crash_pc(&self) -> Option<u64> {
+        self.fd
+            .get_one_reg(Arm64CoreRegs::UserPTRegPc.into())
+            .ok()
+            .map(|pc| pc as u64)
+    }
+
+    /// Dump the core registers of the vcpu.
+    pub(crate) fn dump_regs(&self) -> String {
+        let sve = self.arch_cpu.lock().unwrap().features.sve;
+        match get_core_regs(&self.fd, sve) {
+            Ok(regs) => format!("{:#x?}", regs),
+            Err(e) => format!("failed to get registers: {:?}", e),
+        }
+    }
+
+    /// Set the state of vcpu to wake up from PSCI SYSTEM_SUSPEND. The vcpu which called
+    /// SYSTEM_SUSPEND resumes at the entry point it provided, others are powered off.
+    pub fn set_to_wakeup_state(&self) -> Result<()> {
+        self.set_to_boot_state();
+        let mut arch_cpu = self.arch_cpu.lock().unwrap();
+        self.fd
+            .vcpu_init(&arch_cpu.kvi())
+            .with_context(|| "Failed to init vcpu fd")?;
+        match self.suspend_entry.lock().unwrap().take() {
+            Some((entry, context_id)) => arch_cpu.set_resume_point(entry, context_id),
+            None => arch_cpu.set_powered_off(),
+        }
+        Ok(())
+    }
+
     /// Init PMU for ARM CPU
     pub fn init_pmu(&self) -> Result<()> {
         let pmu_attr = kvm_device_attr {
@@ -323,13 +477,37 @@ impl CPU {
 
         Ok(())
     }
+
+    /// Init paravirtual time for ARM CPU, the stolen time is reported to guest
+    /// through the structure at `ipa`.
+    pub fn init_pvtime(&self, ipa: u64) -> Result<()> {
+        let pvtime_attr = kvm_device_attr {
+            group: KVM_ARM_VCPU_PVTIME_CTRL,
+            attr: KVM_ARM_VCPU_PVTIME_IPA as u64,
+            addr: &ipa as *const u64 as u64,
+            flags: 0,
+        };
+        let vcpu_device = unsafe { DeviceFd::from_raw_fd(self.fd.as_raw_fd()) };
+        let ret = vcpu_device
+            .has_device_attr(&pvtime_attr)
+            .with_context(|| "Kernel does not support PV time for vCPU")
+            .and_then(|_| {
+                vcpu_device
+                    .set_device_attr(&pvtime_attr)
+                    .with_context(|| format!("Failed to set PV time ipa {:#x}", ipa))
+            });
+        // forget `vcpu_device` to avoid fd close on exit, as DeviceFd is backed by File.
+        forget(vcpu_device);
+
+        ret
+    }
 }
 
 impl StateTransfer for CPU {
     fn get_state_vec(&self) -> migration::Result<Vec<u8>> {
         let mut cpu_state_locked = self.arch_cpu.lock().unwrap();
 
-        cpu_state_locked.core_regs = get_core_regs(&self.fd)?;
+        cpu_state_locked.core_regs = get_core_regs(&self.fd, cpu_state_locked.features.sve)?;
         if self.caps.mp_state {
             let mut mp_state = self.fd.get_mp_state()?;
             if mp_state.mp_state != KVM_MP_STATE_STOPPED {
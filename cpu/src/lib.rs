@@ -52,32 +52,46 @@ pub use aarch64::ArmCPUTopology as CPUTopology;
 pub use aarch64::PMU_INTR;
 #[cfg(target_arch = "aarch64")]
 pub use aarch64::PPI_BASE;
+#[cfg(target_arch = "aarch64")]
+pub use aarch64::PVTIME_SIZE_PER_CPU;
 use machine_manager::qmp::qmp_schema;
 #[cfg(target_arch = "x86_64")]
 use x86_64::caps::X86CPUCaps as CPUCaps;
 #[cfg(target_arch = "x86_64")]
 pub use x86_64::X86CPUBootConfig as CPUBootConfig;
 #[cfg(target_arch = "x86_64")]
+pub use x86_64::X86CPUFeatures as CPUFeatures;
+#[cfg(target_arch = "x86_64")]
 pub use x86_64::X86CPUState as ArchCPU;
 #[cfg(target_arch = "x86_64")]
 pub use x86_64::X86CPUTopology as CPUTopology;
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::{GuestDebug, MAX_HW_BREAKPOINTS};
 
 use std::cell::RefCell;
-use std::sync::atomic::{fence, AtomicBool, Ordering};
+use std::fmt;
+use std::sync::atomic::{fence, AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Barrier, Condvar, Mutex, Weak};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use address_space::{AddressSpace, GuestAddress};
+use hypervisor::kvm::{vcpu_internal_error, KvmStats, KVM_EXIT_DIRTY_RING_FULL, KVM_FDS};
+use kvm_bindings::kvm_debug_exit_arch;
 use kvm_ioctls::{VcpuExit, VcpuFd};
 use libc::{c_int, c_void, siginfo_t};
 use log::{error, info, warn};
 use machine_manager::config::ShutdownAction::{ShutdownActionPause, ShutdownActionPoweroff};
+use machine_manager::config::{PanicAction, VcpuSched};
 use machine_manager::event;
-use machine_manager::machine::MachineInterface;
+use machine_manager::machine::{set_stop_reason, MachineInterface, StopReason};
 use machine_manager::{qmp::qmp_schema as schema, qmp::QmpChannel};
 
+use util::cgroup::CpuCgroup;
+use util::seccomp::{apply_thread_filter, ThreadKind};
 #[cfg(not(test))]
 use util::test_helper::is_test_enabled;
+use util::trace::{is_trace_event_enabled, write_trace_event};
 use vmm_sys_util::signal::{register_signal_handler, Killable};
 
 // SIGRTMIN = 34 (GNU, in MUSL is 35) and SIGRTMAX = 64  in linux, VCPU signal
@@ -103,6 +117,8 @@ const MAGIC_VALUE_SIGNAL_GUEST_BOOT_START: u8 = 0x01;
 /// The boot complete value can be verified before init guest userspace.
 #[cfg(feature = "boot_time")]
 const MAGIC_VALUE_SIGNAL_GUEST_BOOT_COMPLETE: u8 = 0x02;
+/// Linux limits thread name to 16 bytes, including the trailing NUL.
+const MAX_THREAD_NAME_LEN: usize = 15;
 
 /// State for `CPU` lifecycle.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -119,6 +135,10 @@ pub enum CpuLifecycleState {
     Stopped = 5,
 }
 
+/// Handler called in the vcpu thread when the vcpu traps for guest debug, such
+/// as breakpoint or single step.
+pub type DebugExitHandler = Arc<dyn Fn(&CPU, &kvm_debug_exit_arch) + Send + Sync>;
+
 /// Trait to handle `CPU` lifetime.
 #[allow(clippy::upper_case_acronyms)]
 pub trait CPUInterface {
@@ -127,7 +147,7 @@ pub trait CPUInterface {
         &self,
         boot: &CPUBootConfig,
         topology: &CPUTopology,
-        #[cfg(target_arch = "aarch64")] features: &CPUFeatures,
+        features: &CPUFeatures,
     ) -> Result<()>;
 
     /// Start `CPU` thread and run virtual CPU in kvm.
@@ -162,10 +182,87 @@ pub trait CPUInterface {
     /// Make `CPU` destroy because of guest inner reset.
     fn guest_reset(&self) -> Result<()>;
 
+    /// Handle guest crash reported by `kvm` according to the panic action.
+    fn guest_panic(&self) -> Result<()>;
+
     /// Handle vcpu event from `kvm`.
     fn kvm_vcpu_exec(&self) -> Result<bool>;
 }
 
+/// The MMIO or PIO access of `CPU`, the last one is kept to diagnose failed vcpu runs.
+#[derive(Clone, Copy, Debug)]
+struct IoAccess {
+    /// One of "mmio-read", "mmio-write", "pio-in" and "pio-out".
+    kind: &'static str,
+    addr: u64,
+    len: usize,
+}
+
+impl fmt::Display for IoAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} 0x{:x} len {}", self.kind, self.addr, self.len)
+    }
+}
+
+/// Reasons of the exits handled in userspace, which are counted in `VcpuStats`.
+#[derive(Clone, Copy, Debug)]
+enum ExitReason {
+    Io = 0,
+    Mmio,
+    Hlt,
+    Shutdown,
+    SystemEvent,
+    Debug,
+    Intr,
+    Other,
+}
+
+const NR_EXIT_REASONS: usize = ExitReason::Other as usize + 1;
+
+impl ExitReason {
+    fn from_exit(exit: &VcpuExit) -> Self {
+        match exit {
+            VcpuExit::IoIn(..) | VcpuExit::IoOut(..) => ExitReason::Io,
+            VcpuExit::MmioRead(..) | VcpuExit::MmioWrite(..) => ExitReason::Mmio,
+            VcpuExit::Hlt => ExitReason::Hlt,
+            VcpuExit::Shutdown => ExitReason::Shutdown,
+            VcpuExit::SystemEvent(..) => ExitReason::SystemEvent,
+            VcpuExit::Debug(..) => ExitReason::Debug,
+            _ => ExitReason::Other,
+        }
+    }
+}
+
+/// Runtime statistics of `CPU`.
+#[derive(Default)]
+struct VcpuStats {
+    /// Number of the exits handled in userspace, indexed by `ExitReason`.
+    exits: [AtomicU64; NR_EXIT_REASONS],
+    /// Time in nanoseconds spent in `KVM_RUN`.
+    run_time_ns: AtomicU64,
+    /// Binary statistics provided by kvm, `None` if it's not supported by host.
+    kvm_stats: Option<KvmStats>,
+}
+
+impl VcpuStats {
+    fn count_exit(&self, reason: ExitReason) {
+        self.exits[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn exits(&self, reason: ExitReason) -> u64 {
+        self.exits[reason as usize].load(Ordering::Relaxed)
+    }
+
+    /// Time in nanoseconds the vcpu is halted in kvm, including the time of polling.
+    fn halt_time_ns(&self) -> Option<u64> {
+        let kvm_stats = self.kvm_stats.as_ref()?;
+        let wait = kvm_stats.get("halt_wait_ns")?;
+        let poll = kvm_stats.get("halt_poll_success_ns").unwrap_or(0)
+            + kvm_stats.get("halt_poll_fail_ns").unwrap_or(0);
+        Some(wait + poll)
+    }
+}
+
 /// `CPU` is a wrapper around creating and using a kvm-based VCPU.
 #[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
@@ -189,6 +286,25 @@ pub struct CPU {
     boot_state: Arc<Mutex<ArchCPU>>,
     /// Sync the pause state of vCPU in kvm and userspace.
     pause_signal: Arc<AtomicBool>,
+    /// The host CPU which the thread of this VCPU is pinned to.
+    affinity: Arc<Mutex<Option<usize>>>,
+    /// Name of the VM, used to name the thread of this VCPU.
+    vm_name: String,
+    /// Scheduling class of the thread of this VCPU.
+    sched: Option<VcpuSched>,
+    /// The cpu cgroup which the thread of this VCPU joins.
+    cgroup: Option<Arc<CpuCgroup>>,
+    /// Address space which MMIO accesses are dispatched to without the lock of VM.
+    mmio_space: Option<Arc<AddressSpace>>,
+    /// The last MMIO or PIO access of this VCPU.
+    last_access: Arc<Mutex<Option<IoAccess>>>,
+    /// Handler of debug exits, which is set by the debugger.
+    debug_handler: Arc<Mutex<Option<DebugExitHandler>>>,
+    /// Runtime statistics of this VCPU.
+    stats: Arc<VcpuStats>,
+    /// The entry point and context id to resume from PSCI SYSTEM_SUSPEND.
+    #[cfg(target_arch = "aarch64")]
+    suspend_entry: Arc<Mutex<Option<(u64, u64)>>>,
 }
 
 impl CPU {
@@ -206,6 +322,10 @@ impl CPU {
         arch_cpu: Arc<Mutex<ArchCPU>>,
         vm: Arc<Mutex<dyn MachineInterface + Send + Sync>>,
     ) -> Self {
+        let stats = VcpuStats {
+            kvm_stats: KvmStats::new(vcpu_fd.as_ref()).ok(),
+            ..Default::default()
+        };
         CPU {
             id,
             fd: vcpu_fd,
@@ -217,6 +337,16 @@ impl CPU {
             caps: CPUCaps::init_capabilities(),
             boot_state: Arc::new(Mutex::new(ArchCPU::default())),
             pause_signal: Arc::new(AtomicBool::new(false)),
+            affinity: Arc::new(Mutex::new(None)),
+            vm_name: String::new(),
+            sched: None,
+            cgroup: None,
+            mmio_space: None,
+            last_access: Arc::new(Mutex::new(None)),
+            debug_handler: Arc::new(Mutex::new(None)),
+            stats: Arc::new(stats),
+            #[cfg(target_arch = "aarch64")]
+            suspend_entry: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -234,6 +364,43 @@ impl CPU {
         &self.fd
     }
 
+    /// Inject a non-maskable interrupt to this `CPU`.
+    #[cfg(target_arch = "x86_64")]
+    pub fn inject_nmi(&self) -> Result<()> {
+        use hypervisor::kvm::KVM_NMI;
+        use vmm_sys_util::ioctl::ioctl;
+
+        // SAFETY: the vcpu fd is valid and KVM_NMI has no argument.
+        let ret = unsafe { ioctl(self.fd.as_ref(), KVM_NMI()) };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to inject NMI to vcpu{}", self.id));
+        }
+        Ok(())
+    }
+
+    /// Get the runtime statistics of this `CPU`.
+    pub fn stats(&self) -> qmp_schema::VcpuStats {
+        let stats = &self.stats;
+        qmp_schema::VcpuStats {
+            cpu_index: isize::from(self.id),
+            thread_id: self.tid() as isize,
+            run_time_ns: stats.run_time_ns.load(Ordering::Relaxed),
+            halt_time_ns: stats.halt_time_ns(),
+            kvm_exits: stats.kvm_stats.as_ref().and_then(|s| s.get("exits")),
+            exits: qmp_schema::VcpuExitStats {
+                io: stats.exits(ExitReason::Io),
+                mmio: stats.exits(ExitReason::Mmio),
+                hlt: stats.exits(ExitReason::Hlt),
+                shutdown: stats.exits(ExitReason::Shutdown),
+                system_event: stats.exits(ExitReason::SystemEvent),
+                debug: stats.exits(ExitReason::Debug),
+                intr: stats.exits(ExitReason::Intr),
+                other: stats.exits(ExitReason::Other),
+            },
+        }
+    }
+
     /// Get this `CPU`'s state.
     pub fn state(&self) -> &(Mutex<CpuLifecycleState>, Condvar) {
         self.state.as_ref()
@@ -260,6 +427,145 @@ impl CPU {
     fn set_tid(&self) {
         *self.tid.lock().unwrap() = Some(util::unix::gettid());
     }
+
+    /// Pin the thread of this `CPU` to the host CPU. If the thread is not started,
+    /// it's pinned when started.
+    ///
+    /// # Arguments
+    ///
+    /// * `host_cpu` - Index of the host CPU.
+    pub fn set_affinity(&self, host_cpu: usize) -> Result<()> {
+        // SAFETY: sysconf has no side effect.
+        let nr_host_cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) };
+        if host_cpu as i64 >= nr_host_cpus {
+            return Err(anyhow!(CpuError::PinVcpu(self.id, host_cpu)));
+        }
+        if let Some(tid) = *self.tid.lock().unwrap() {
+            util::unix::set_thread_affinity(tid, &[host_cpu])?;
+        }
+        *self.affinity.lock().unwrap() = Some(host_cpu);
+        Ok(())
+    }
+
+    /// Get the host CPU which the thread of this `CPU` is pinned to.
+    pub fn affinity(&self) -> Option<usize> {
+        *self.affinity.lock().unwrap()
+    }
+
+    /// Set the VM name, scheduling class and cpu cgroup used by the thread of
+    /// this `CPU`, they take effect when the thread is started.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm_name` - Name of the VM.
+    /// * `sched` - Scheduling class, `None` means inherited from the main thread.
+    /// * `cgroup` - Cpu cgroup, `None` means staying in the cgroup of the main thread.
+    pub fn set_thread_config(
+        &mut self,
+        vm_name: &str,
+        sched: Option<VcpuSched>,
+        cgroup: Option<Arc<CpuCgroup>>,
+    ) {
+        self.vm_name = vm_name.to_string();
+        self.sched = sched;
+        self.cgroup = cgroup;
+    }
+
+    /// Set the address space which the MMIO accesses of this `CPU` are dispatched to.
+    /// They are dispatched by the lock-free flat view of it, instead of going through
+    /// `MachineAddressInterface` with the lock of VM held.
+    pub fn set_mmio_space(&mut self, space: Arc<AddressSpace>) {
+        self.mmio_space = Some(space);
+    }
+
+    /// Set the handler of debug exits of this `CPU`, `None` to remove it.
+    pub fn set_debug_handler(&self, handler: Option<DebugExitHandler>) {
+        *self.debug_handler.lock().unwrap() = handler;
+    }
+
+    /// Record the MMIO or PIO access going to be handled.
+    fn record_access(&self, kind: &'static str, addr: u64, len: usize) {
+        *self.last_access.lock().unwrap() = Some(IoAccess { kind, addr, len });
+    }
+
+    /// Trace the MMIO or PIO access handled, if its trace event is enabled, e.g. by
+    /// `-trace-mmio`.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm` - The machine handling the access.
+    /// * `event` - Trace event of the access.
+    /// * `addr` - Address of the access.
+    /// * `data` - Data read or written.
+    fn trace_access(
+        &self,
+        vm: &Arc<Mutex<dyn MachineInterface + Send + Sync>>,
+        event: &str,
+        addr: u64,
+        data: &[u8],
+    ) {
+        if !is_trace_event_enabled(event) {
+            return;
+        }
+
+        let pio = event.starts_with("pio");
+        let device = match &self.mmio_space {
+            Some(space) if !pio => space.get_region_name(GuestAddress(addr)),
+            _ => vm.lock().unwrap().io_device_name(addr, pio),
+        };
+        let mut value = [0_u8; 8];
+        let len = std::cmp::min(data.len(), value.len());
+        value[..len].copy_from_slice(&data[..len]);
+        write_trace_event(
+            event,
+            &format!(
+                "vcpu {} addr 0x{:x} size {} value 0x{:x} device {}",
+                self.id,
+                addr,
+                data.len(),
+                u64::from_le_bytes(value),
+                device.as_deref().unwrap_or("none")
+            ),
+        );
+    }
+
+    /// Report the failed vcpu run with the registers, the last MMIO/PIO access and
+    /// the data from kvm, to the log and `VCPU_EXIT_FAILURE` event.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - "internal-error" or "fail-entry".
+    /// * `suberror` - Suberror of KVM_EXIT_INTERNAL_ERROR.
+    /// * `data` - Data reported by kvm.
+    fn report_exit_failure(&self, reason: &str, suberror: Option<u32>, data: Vec<u64>) {
+        let last_access = self.last_access.lock().unwrap().map(|a| a.to_string());
+        error!(
+            "Vcpu{} failed to run with {}, suberror {:?}, data {:x?}, last access {}",
+            self.id,
+            reason,
+            suberror,
+            data,
+            last_access.as_deref().unwrap_or("none")
+        );
+        error!("Vcpu{} registers:\n{}", self.id, self.dump_regs());
+
+        if QmpChannel::is_connected() {
+            let failure_msg = schema::VcpuExitFailure {
+                cpu: self.id,
+                reason: reason.to_string(),
+                suberror,
+                data,
+                pc: self.crash_pc(),
+                last_access,
+            };
+            event!(VcpuExitFailure; failure_msg);
+        }
+    }
+
+    /// Get the name of the thread of this `CPU`, as `vcpu-<id>/<vm name>`.
+    pub fn thread_name(&self) -> String {
+        vcpu_thread_name(self.id, &self.vm_name)
+    }
 }
 
 impl CPUInterface for CPU {
@@ -267,7 +573,7 @@ impl CPUInterface for CPU {
         &self,
         boot: &CPUBootConfig,
         topology: &CPUTopology,
-        #[cfg(target_arch = "aarch64")] config: &CPUFeatures,
+        config: &CPUFeatures,
     ) -> Result<()> {
         trace_cpu_boot_config(boot);
         let (cpu_state, _) = &*self.state;
@@ -281,12 +587,7 @@ impl CPUInterface for CPU {
         self.arch_cpu
             .lock()
             .unwrap()
-            .set_boot_config(
-                &self.fd,
-                boot,
-                #[cfg(target_arch = "aarch64")]
-                config,
-            )
+            .set_boot_config(&self.fd, boot, config)
             .with_context(|| "Failed to realize arch cpu")?;
 
         self.arch_cpu
@@ -335,8 +636,9 @@ impl CPUInterface for CPU {
 
         let local_cpu = cpu.clone();
         let cpu_thread_worker = CPUThreadWorker::new(cpu);
+        let thread_name = local_cpu.thread_name();
         let handle = thread::Builder::new()
-            .name(format!("CPU {}/KVM", local_cpu.id))
+            .name(thread_name.clone())
             .spawn(move || {
                 if let Err(e) = cpu_thread_worker.handle(thread_barrier) {
                     error!(
@@ -345,7 +647,7 @@ impl CPUInterface for CPU {
                     );
                 }
             })
-            .with_context(|| format!("Failed to create thread for CPU {}/KVM", local_cpu.id()))?;
+            .with_context(|| format!("Failed to create thread {}", thread_name))?;
         local_cpu.set_task(Some(handle));
         Ok(())
     }
@@ -450,6 +752,7 @@ impl CPUInterface for CPU {
                     vm.lock().unwrap().destroy();
                 }
                 ShutdownActionPause => {
+                    set_stop_reason(StopReason::GuestShutdown);
                     vm.lock().unwrap().pause();
                 }
             }
@@ -478,96 +781,220 @@ impl CPUInterface for CPU {
         Ok(())
     }
 
-    fn kvm_vcpu_exec(&self) -> Result<bool> {
+    fn guest_panic(&self) -> Result<()> {
         let vm = self
             .vm
             .upgrade()
             .with_context(|| CpuError::NoMachineInterface)?;
+        let mut action = vm.lock().unwrap().get_panic_action();
+        if vm.lock().unwrap().in_crash_loop() {
+            warn!("Guest is crash looping, shut down VM");
+            action = PanicAction::Shutdown;
+        }
 
-        match self.fd.run() {
-            Ok(run) => match run {
-                #[cfg(target_arch = "x86_64")]
-                VcpuExit::IoIn(addr, data) => {
-                    vm.lock().unwrap().pio_in(u64::from(addr), data);
-                }
-                #[cfg(target_arch = "x86_64")]
-                VcpuExit::IoOut(addr, data) => {
-                    #[cfg(feature = "boot_time")]
-                    capture_boot_signal(addr as u64, data);
+        if QmpChannel::is_connected() {
+            let panicked_msg = schema::GuestPanicked {
+                action: action.name().to_string(),
+                info: Some(schema::GuestPanicInformation {
+                    panic_type: "kvm-crash".to_string(),
+                    cpu: Some(self.id()),
+                    pc: self.crash_pc(),
+                }),
+            };
+            event!(GuestPanicked; panicked_msg);
+        }
 
-                    vm.lock().unwrap().pio_out(u64::from(addr), data);
-                }
-                VcpuExit::MmioRead(addr, data) => {
-                    vm.lock().unwrap().mmio_read(addr, data);
-                }
-                VcpuExit::MmioWrite(addr, data) => {
-                    #[cfg(all(target_arch = "aarch64", feature = "boot_time"))]
-                    capture_boot_signal(addr, data);
+        match action {
+            PanicAction::Pause => {
+                set_stop_reason(StopReason::GuestPanicked);
+                vm.lock().unwrap().pause();
+            }
+            PanicAction::Shutdown => {
+                let (cpu_state, _) = &*self.state;
+                *cpu_state.lock().unwrap() = CpuLifecycleState::Stopped;
+                vm.lock().unwrap().destroy();
+            }
+            PanicAction::Reset => {
+                self.guest_reset()?;
+            }
+            PanicAction::None => {}
+        }
 
-                    vm.lock().unwrap().mmio_write(addr, data);
-                }
-                #[cfg(target_arch = "x86_64")]
-                VcpuExit::Hlt => {
-                    info!("Vcpu{} received KVM_EXIT_HLT signal", self.id());
-                    return Err(anyhow!(CpuError::VcpuHltEvent(self.id())));
-                }
-                #[cfg(target_arch = "x86_64")]
-                VcpuExit::Shutdown => {
-                    info!("Vcpu{} received an KVM_EXIT_SHUTDOWN signal", self.id());
-                    self.guest_shutdown()?;
+        Ok(())
+    }
 
-                    return Ok(false);
-                }
-                #[cfg(target_arch = "aarch64")]
-                VcpuExit::SystemEvent(event, flags) => {
-                    if event == kvm_bindings::KVM_SYSTEM_EVENT_SHUTDOWN {
-                        info!(
-                            "Vcpu{} received an KVM_SYSTEM_EVENT_SHUTDOWN signal",
-                            self.id()
-                        );
-                        self.guest_shutdown()
-                            .with_context(|| "Some error occurred in guest shutdown")?;
+    fn kvm_vcpu_exec(&self) -> Result<bool> {
+        let vm = self
+            .vm
+            .upgrade()
+            .with_context(|| CpuError::NoMachineInterface)?;
+
+        let start = Instant::now();
+        let ret = self.fd.run();
+        let run_time_ns = start.elapsed().as_nanos() as u64;
+        self.stats
+            .run_time_ns
+            .fetch_add(run_time_ns, Ordering::Relaxed);
+        match ret {
+            Ok(run) => {
+                let reason = ExitReason::from_exit(&run);
+                self.stats.count_exit(reason);
+                util::trace_event!(
+                    vcpu_exit,
+                    "vcpu {} reason {:?} run_time_ns {}",
+                    self.id,
+                    reason,
+                    run_time_ns
+                );
+                match run {
+                    #[cfg(target_arch = "x86_64")]
+                    VcpuExit::IoIn(addr, data) => {
+                        self.record_access("pio-in", u64::from(addr), data.len());
+                        vm.lock().unwrap().pio_in(u64::from(addr), data);
+                        self.trace_access(&vm, "pio_read", u64::from(addr), data);
+                    }
+                    #[cfg(target_arch = "x86_64")]
+                    VcpuExit::IoOut(addr, data) => {
+                        self.record_access("pio-out", u64::from(addr), data.len());
+                        #[cfg(feature = "boot_time")]
+                        capture_boot_signal(addr as u64, data);
+
+                        vm.lock().unwrap().pio_out(u64::from(addr), data);
+                        self.trace_access(&vm, "pio_write", u64::from(addr), data);
+                    }
+                    VcpuExit::MmioRead(addr, mut data) => {
+                        self.record_access("mmio-read", addr, data.len());
+                        match &self.mmio_space {
+                            Some(space) => {
+                                let len = data.len() as u64;
+                                let _ = space.read(&mut data, GuestAddress(addr), len);
+                            }
+                            None => {
+                                vm.lock().unwrap().mmio_read(addr, data);
+                            }
+                        }
+                        self.trace_access(&vm, "mmio_read", addr, data);
+                    }
+                    VcpuExit::MmioWrite(addr, mut data) => {
+                        self.record_access("mmio-write", addr, data.len());
+                        #[cfg(all(target_arch = "aarch64", feature = "boot_time"))]
+                        capture_boot_signal(addr, data);
+
+                        match &self.mmio_space {
+                            Some(space) => {
+                                let len = data.len() as u64;
+                                let _ = space.write(&mut data, GuestAddress(addr), len);
+                            }
+                            None => {
+                                vm.lock().unwrap().mmio_write(addr, data);
+                            }
+                        }
+                        self.trace_access(&vm, "mmio_write", addr, data);
+                    }
+                    #[cfg(target_arch = "x86_64")]
+                    VcpuExit::Hlt => {
+                        info!("Vcpu{} received KVM_EXIT_HLT signal", self.id());
+                        return Err(anyhow!(CpuError::VcpuHltEvent(self.id())));
+                    }
+                    #[cfg(target_arch = "x86_64")]
+                    VcpuExit::Shutdown => {
+                        info!("Vcpu{} received an KVM_EXIT_SHUTDOWN signal", self.id());
+                        self.guest_shutdown()?;
+
+                        return Ok(false);
+                    }
+                    VcpuExit::SystemEvent(event, flags) => {
+                        match event {
+                            kvm_bindings::KVM_SYSTEM_EVENT_SHUTDOWN => {
+                                info!(
+                                    "Vcpu{} received an KVM_SYSTEM_EVENT_SHUTDOWN signal",
+                                    self.id()
+                                );
+                                self.guest_shutdown()
+                                    .with_context(|| "Some error occurred in guest shutdown")?;
+                            }
+                            kvm_bindings::KVM_SYSTEM_EVENT_RESET => {
+                                info!(
+                                    "Vcpu{} received an KVM_SYSTEM_EVENT_RESET signal",
+                                    self.id()
+                                );
+                                self.guest_reset()
+                                    .with_context(|| "Some error occurred in guest reset")?;
+                            }
+                            kvm_bindings::KVM_SYSTEM_EVENT_CRASH => {
+                                info!(
+                                    "Vcpu{} received an KVM_SYSTEM_EVENT_CRASH signal",
+                                    self.id()
+                                );
+                                self.guest_panic()
+                                    .with_context(|| "Some error occurred in guest panic")?;
+                            }
+                            #[cfg(target_arch = "aarch64")]
+                            aarch64::KVM_SYSTEM_EVENT_SUSPEND => {
+                                info!(
+                                    "Vcpu{} received an KVM_SYSTEM_EVENT_SUSPEND signal",
+                                    self.id()
+                                );
+                                self.guest_suspend()
+                                    .with_context(|| "Some error occurred in guest suspend")?;
+                            }
+                            _ => {
+                                error!(
+                                "Vcpu{} received unexpected system event with type 0x{:x}, flags 0x{:x}",
+                                self.id(),
+                                event,
+                                flags
+                            );
+                                return Ok(false);
+                            }
+                        }
                         return Ok(true);
-                    } else if event == kvm_bindings::KVM_SYSTEM_EVENT_RESET {
-                        info!(
-                            "Vcpu{} received an KVM_SYSTEM_EVENT_RESET signal",
-                            self.id()
+                    }
+                    VcpuExit::FailEntry(reason, host_cpu) => {
+                        self.report_exit_failure(
+                            "fail-entry",
+                            None,
+                            vec![reason, u64::from(host_cpu)],
                         );
-                        self.guest_reset()
-                            .with_context(|| "Some error occurred in guest reset")?;
-                        return Ok(true);
-                    } else {
-                        error!(
-                            "Vcpu{} received unexpected system event with type 0x{:x}, flags 0x{:x}",
+                        return Ok(false);
+                    }
+                    VcpuExit::InternalError => {
+                        let (suberror, data) = match vcpu_internal_error(&self.fd) {
+                            Ok((suberror, data)) => (Some(suberror), data),
+                            Err(e) => {
+                                error!("Vcpu{} failed to get internal error: {:?}", self.id(), e);
+                                (None, Vec::new())
+                            }
+                        };
+                        self.report_exit_failure("internal-error", suberror, data);
+                        return Ok(false);
+                    }
+                    VcpuExit::Debug(debug) => {
+                        let handler = self.debug_handler.lock().unwrap().clone();
+                        match handler {
+                            Some(handler) => handler(self, &debug),
+                            None => warn!("Vcpu{} received unexpected debug exit", self.id()),
+                        }
+                    }
+                    VcpuExit::Unsupported(KVM_EXIT_DIRTY_RING_FULL) => {
+                        // Harvest the dirty rings to make room for the vcpu.
+                        KVM_FDS.load().harvest_dirty_rings().with_context(|| {
+                            format!("Vcpu{} failed to harvest full dirty ring", self.id())
+                        })?;
+                    }
+                    r => {
+                        return Err(anyhow!(CpuError::VcpuExitReason(
                             self.id(),
-                            event,
-                            flags
-                        );
+                            format!("{:?}", r)
+                        )));
                     }
-                    return Ok(false);
                 }
-                VcpuExit::FailEntry(reason, cpuid) => {
-                    info!(
-                        "Vcpu{} received KVM_EXIT_FAIL_ENTRY signal. the vcpu could not be run due to unknown reasons({})",
-                        cpuid, reason
-                    );
-                    return Ok(false);
-                }
-                VcpuExit::InternalError => {
-                    info!("Vcpu{} received KVM_EXIT_INTERNAL_ERROR signal", self.id());
-                    return Ok(false);
-                }
-                r => {
-                    return Err(anyhow!(CpuError::VcpuExitReason(
-                        self.id(),
-                        format!("{:?}", r)
-                    )));
-                }
-            },
+            }
             Err(ref e) => {
                 match e.errno() {
-                    libc::EAGAIN => {}
+                    libc::EAGAIN => self.stats.count_exit(ExitReason::Intr),
                     libc::EINTR => {
+                        self.stats.count_exit(ExitReason::Intr);
                         self.fd.set_kvm_immediate_exit(0);
                     }
                     _ => {
@@ -690,6 +1117,30 @@ impl CPUThreadWorker {
         }
 
         self.thread_cpu.set_tid();
+        if let Some(host_cpu) = self.thread_cpu.affinity() {
+            if let Err(e) = util::unix::set_thread_affinity(0, &[host_cpu]) {
+                error!("Failed to pin cpu{}: {:?}", self.thread_cpu.id, e);
+            }
+        }
+        if let Some(cgroup) = &self.thread_cpu.cgroup {
+            if let Err(e) = cgroup.add_current_thread() {
+                error!(
+                    "Failed to move cpu{} into cpu cgroup: {:?}",
+                    self.thread_cpu.id, e
+                );
+            }
+        }
+        let sched_ret = match self.thread_cpu.sched {
+            Some(VcpuSched::Nice(nice)) => util::unix::set_thread_nice(0, nice),
+            Some(VcpuSched::Fifo(priority)) => util::unix::set_thread_fifo(0, priority),
+            None => Ok(()),
+        };
+        if let Err(e) = sched_ret {
+            error!(
+                "Failed to set scheduling class of cpu{}: {:?}",
+                self.thread_cpu.id, e
+            );
+        }
 
         // The vcpu thread is going to run,
         // reset its running environment.
@@ -711,6 +1162,12 @@ impl CPUThreadWorker {
 
         info!("vcpu{} start running", self.thread_cpu.id);
         while let Ok(true) = self.ready_for_running() {
+            if let Err(e) = apply_thread_filter(ThreadKind::Vcpu) {
+                error!(
+                    "Failed to apply seccomp filter of cpu{}: {:?}",
+                    self.thread_cpu.id, e
+                );
+            }
             #[cfg(not(test))]
             {
                 if is_test_enabled() {
@@ -840,8 +1297,21 @@ impl CpuTopology {
     }
 }
 
+/// Name of the thread of vCPU `id`, truncated to the length limit of the host.
+fn vcpu_thread_name(id: u8, vm_name: &str) -> String {
+    let mut name = if vm_name.is_empty() {
+        format!("vcpu-{}", id)
+    } else {
+        format!("vcpu-{}/{}", id, vm_name)
+    };
+    while name.len() > MAX_THREAD_NAME_LEN {
+        name.pop();
+    }
+    name
+}
+
 fn trace_cpu_boot_config(cpu_boot_config: &CPUBootConfig) {
-    util::ftrace!(trace_CPU_boot_config, "{:#?}", cpu_boot_config);
+    util::trace_event!(trace_CPU_boot_config, "{:#?}", cpu_boot_config);
 }
 
 /// Capture the boot signal that trap from guest kernel, and then record
@@ -1018,6 +1488,13 @@ mod tests {
         drop(cpu_state);
     }
 
+    #[test]
+    fn test_vcpu_thread_name() {
+        assert_eq!(vcpu_thread_name(3, ""), "vcpu-3");
+        assert_eq!(vcpu_thread_name(3, "vm1"), "vcpu-3/vm1");
+        assert_eq!(vcpu_thread_name(12, "stratovirt-vm"), "vcpu-12/stratov");
+    }
+
     #[test]
     fn test_cpu_get_topu() {
         let test_nr_cpus: u8 = 16;
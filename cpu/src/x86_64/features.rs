@@ -0,0 +1,873 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use anyhow::{anyhow, bail, Context, Result};
+use kvm_bindings::{kvm_cpuid_entry2, CpuId, KVM_MAX_CPUID_ENTRIES};
+use kvm_ioctls::Kvm;
+use machine_manager::config::CpuConfig;
+
+/// The cpuid registers which hold feature bits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FeatureWord {
+    /// CPUID[1].ECX
+    Leaf1Ecx = 0,
+    /// CPUID[1].EDX
+    Leaf1Edx,
+    /// CPUID[7,0].EBX
+    Leaf7Ebx,
+    /// CPUID[7,0].ECX
+    Leaf7Ecx,
+    /// CPUID[7,0].EDX
+    Leaf7Edx,
+    /// CPUID[0xD,1].EAX
+    LeafDEax,
+    /// CPUID[0x8000_0001].ECX
+    Ext1Ecx,
+    /// CPUID[0x8000_0001].EDX
+    Ext1Edx,
+    /// CPUID[0x8000_0007].EDX
+    Ext7Edx,
+    /// CPUID[0x4000_0001].EAX, the KVM paravirtual features.
+    KvmEax,
+}
+
+const FEATURE_WORDS: usize = 10;
+
+const ALL_FEATURE_WORDS: [FeatureWord; FEATURE_WORDS] = [
+    FeatureWord::Leaf1Ecx,
+    FeatureWord::Leaf1Edx,
+    FeatureWord::Leaf7Ebx,
+    FeatureWord::Leaf7Ecx,
+    FeatureWord::Leaf7Edx,
+    FeatureWord::LeafDEax,
+    FeatureWord::Ext1Ecx,
+    FeatureWord::Ext1Edx,
+    FeatureWord::Ext7Edx,
+    FeatureWord::KvmEax,
+];
+
+impl FeatureWord {
+    /// Returns the function and index of cpuid leaf which holds this word.
+    fn leaf(&self) -> (u32, u32) {
+        match self {
+            FeatureWord::Leaf1Ecx | FeatureWord::Leaf1Edx => (1, 0),
+            FeatureWord::Leaf7Ebx | FeatureWord::Leaf7Ecx | FeatureWord::Leaf7Edx => (7, 0),
+            FeatureWord::LeafDEax => (0xd, 1),
+            FeatureWord::Ext1Ecx | FeatureWord::Ext1Edx => (0x8000_0001, 0),
+            FeatureWord::Ext7Edx => (0x8000_0007, 0),
+            FeatureWord::KvmEax => (0x4000_0001, 0),
+        }
+    }
+
+    fn reg<'a>(&self, entry: &'a mut kvm_cpuid_entry2) -> &'a mut u32 {
+        match self {
+            FeatureWord::LeafDEax | FeatureWord::KvmEax => &mut entry.eax,
+            FeatureWord::Leaf7Ebx => &mut entry.ebx,
+            FeatureWord::Leaf1Ecx | FeatureWord::Leaf7Ecx | FeatureWord::Ext1Ecx => &mut entry.ecx,
+            FeatureWord::Leaf1Edx
+            | FeatureWord::Leaf7Edx
+            | FeatureWord::Ext1Edx
+            | FeatureWord::Ext7Edx => &mut entry.edx,
+        }
+    }
+}
+
+/// Names of cpu features, and the bits of them in cpuid. A feature may occupy more than one bit.
+/// See: https://elixir.bootlin.com/linux/v5.10/source/arch/x86/include/asm/cpufeatures.h
+/// and https://elixir.bootlin.com/linux/v5.10/source/arch/x86/include/uapi/asm/kvm_para.h
+const FEATURES: &[(&str, FeatureWord, u32)] = &[
+    ("sse3", FeatureWord::Leaf1Ecx, 0),
+    ("pclmulqdq", FeatureWord::Leaf1Ecx, 1),
+    ("vmx", FeatureWord::Leaf1Ecx, 5),
+    ("ssse3", FeatureWord::Leaf1Ecx, 9),
+    ("fma", FeatureWord::Leaf1Ecx, 12),
+    ("cx16", FeatureWord::Leaf1Ecx, 13),
+    ("pcid", FeatureWord::Leaf1Ecx, 17),
+    ("sse4.1", FeatureWord::Leaf1Ecx, 19),
+    ("sse4.2", FeatureWord::Leaf1Ecx, 20),
+    ("x2apic", FeatureWord::Leaf1Ecx, 21),
+    ("movbe", FeatureWord::Leaf1Ecx, 22),
+    ("popcnt", FeatureWord::Leaf1Ecx, 23),
+    ("tsc-deadline", FeatureWord::Leaf1Ecx, 24),
+    ("aes", FeatureWord::Leaf1Ecx, 25),
+    ("xsave", FeatureWord::Leaf1Ecx, 26),
+    ("avx", FeatureWord::Leaf1Ecx, 28),
+    ("f16c", FeatureWord::Leaf1Ecx, 29),
+    ("rdrand", FeatureWord::Leaf1Ecx, 30),
+    ("hypervisor", FeatureWord::Leaf1Ecx, 31),
+    ("fpu", FeatureWord::Leaf1Edx, 0),
+    ("vme", FeatureWord::Leaf1Edx, 1),
+    ("de", FeatureWord::Leaf1Edx, 2),
+    ("pse", FeatureWord::Leaf1Edx, 3),
+    ("tsc", FeatureWord::Leaf1Edx, 4),
+    ("msr", FeatureWord::Leaf1Edx, 5),
+    ("pae", FeatureWord::Leaf1Edx, 6),
+    ("mce", FeatureWord::Leaf1Edx, 7),
+    ("cx8", FeatureWord::Leaf1Edx, 8),
+    ("apic", FeatureWord::Leaf1Edx, 9),
+    ("sep", FeatureWord::Leaf1Edx, 11),
+    ("mtrr", FeatureWord::Leaf1Edx, 12),
+    ("pge", FeatureWord::Leaf1Edx, 13),
+    ("mca", FeatureWord::Leaf1Edx, 14),
+    ("cmov", FeatureWord::Leaf1Edx, 15),
+    ("pat", FeatureWord::Leaf1Edx, 16),
+    ("pse36", FeatureWord::Leaf1Edx, 17),
+    ("clflush", FeatureWord::Leaf1Edx, 19),
+    ("mmx", FeatureWord::Leaf1Edx, 23),
+    ("fxsr", FeatureWord::Leaf1Edx, 24),
+    ("sse", FeatureWord::Leaf1Edx, 25),
+    ("sse2", FeatureWord::Leaf1Edx, 26),
+    ("ss", FeatureWord::Leaf1Edx, 27),
+    ("ht", FeatureWord::Leaf1Edx, 28),
+    ("fsgsbase", FeatureWord::Leaf7Ebx, 0),
+    ("bmi1", FeatureWord::Leaf7Ebx, 3),
+    ("hle", FeatureWord::Leaf7Ebx, 4),
+    ("avx2", FeatureWord::Leaf7Ebx, 5),
+    ("smep", FeatureWord::Leaf7Ebx, 7),
+    ("bmi2", FeatureWord::Leaf7Ebx, 8),
+    ("erms", FeatureWord::Leaf7Ebx, 9),
+    ("invpcid", FeatureWord::Leaf7Ebx, 10),
+    ("rtm", FeatureWord::Leaf7Ebx, 11),
+    ("mpx", FeatureWord::Leaf7Ebx, 14),
+    ("avx512f", FeatureWord::Leaf7Ebx, 16),
+    ("avx512dq", FeatureWord::Leaf7Ebx, 17),
+    ("rdseed", FeatureWord::Leaf7Ebx, 18),
+    ("adx", FeatureWord::Leaf7Ebx, 19),
+    ("smap", FeatureWord::Leaf7Ebx, 20),
+    ("clflushopt", FeatureWord::Leaf7Ebx, 23),
+    ("clwb", FeatureWord::Leaf7Ebx, 24),
+    ("avx512cd", FeatureWord::Leaf7Ebx, 28),
+    ("sha-ni", FeatureWord::Leaf7Ebx, 29),
+    ("avx512bw", FeatureWord::Leaf7Ebx, 30),
+    ("avx512vl", FeatureWord::Leaf7Ebx, 31),
+    ("avx512vbmi", FeatureWord::Leaf7Ecx, 1),
+    ("umip", FeatureWord::Leaf7Ecx, 2),
+    ("pku", FeatureWord::Leaf7Ecx, 3),
+    ("avx512-vnni", FeatureWord::Leaf7Ecx, 11),
+    ("la57", FeatureWord::Leaf7Ecx, 16),
+    ("rdpid", FeatureWord::Leaf7Ecx, 22),
+    ("md-clear", FeatureWord::Leaf7Edx, 10),
+    ("spec-ctrl", FeatureWord::Leaf7Edx, 26),
+    ("arch-capabilities", FeatureWord::Leaf7Edx, 29),
+    ("ssbd", FeatureWord::Leaf7Edx, 31),
+    ("xsaveopt", FeatureWord::LeafDEax, 0),
+    ("xsavec", FeatureWord::LeafDEax, 1),
+    ("xgetbv1", FeatureWord::LeafDEax, 2),
+    ("xsaves", FeatureWord::LeafDEax, 3),
+    ("lahf-lm", FeatureWord::Ext1Ecx, 0),
+    ("svm", FeatureWord::Ext1Ecx, 2),
+    ("abm", FeatureWord::Ext1Ecx, 5),
+    ("sse4a", FeatureWord::Ext1Ecx, 6),
+    ("3dnowprefetch", FeatureWord::Ext1Ecx, 8),
+    ("topoext", FeatureWord::Ext1Ecx, 22),
+    ("syscall", FeatureWord::Ext1Edx, 11),
+    ("nx", FeatureWord::Ext1Edx, 20),
+    ("pdpe1gb", FeatureWord::Ext1Edx, 26),
+    ("rdtscp", FeatureWord::Ext1Edx, 27),
+    ("lm", FeatureWord::Ext1Edx, 29),
+    ("invtsc", FeatureWord::Ext7Edx, 8),
+    ("kvmclock", FeatureWord::KvmEax, 0),
+    ("kvm-nopiodelay", FeatureWord::KvmEax, 1),
+    ("kvmclock", FeatureWord::KvmEax, 3),
+    ("kvm-asyncpf", FeatureWord::KvmEax, 4),
+    ("kvm-steal-time", FeatureWord::KvmEax, 5),
+    ("kvm-pv-eoi", FeatureWord::KvmEax, 6),
+    ("kvm-pv-unhalt", FeatureWord::KvmEax, 7),
+    ("kvm-pv-tlb-flush", FeatureWord::KvmEax, 9),
+    ("kvm-pv-ipi", FeatureWord::KvmEax, 11),
+    ("kvm-poll-control", FeatureWord::KvmEax, 12),
+    ("kvm-pv-sched-yield", FeatureWord::KvmEax, 13),
+    ("kvm-asyncpf-int", FeatureWord::KvmEax, 14),
+    ("kvmclock-stable-bit", FeatureWord::KvmEax, 24),
+];
+
+const BASE_FEATURES: &[&str] = &[
+    "fpu",
+    "de",
+    "pse",
+    "tsc",
+    "msr",
+    "pae",
+    "mce",
+    "cx8",
+    "apic",
+    "sep",
+    "mtrr",
+    "pge",
+    "mca",
+    "cmov",
+    "pat",
+    "pse36",
+    "clflush",
+    "mmx",
+    "fxsr",
+    "sse",
+    "sse2",
+    "sse3",
+    "cx16",
+    "x2apic",
+    "hypervisor",
+    "syscall",
+    "nx",
+    "lm",
+    "lahf-lm",
+];
+/// Bit of invariant TSC in CPUID[0x8000_0007].EDX.
+const INVTSC_BIT: u32 = 8;
+
+/// KVM paravirtual features enabled by all named models.
+const KVM_FEATURES: &[&str] = &[
+    "kvmclock",
+    "kvm-nopiodelay",
+    "kvm-asyncpf",
+    "kvm-steal-time",
+    "kvm-pv-eoi",
+    "kvm-asyncpf-int",
+    "kvmclock-stable-bit",
+];
+const WESTMERE_FEATURES: &[&str] = &[
+    "ssse3",
+    "sse4.1",
+    "sse4.2",
+    "popcnt",
+    "aes",
+    "pclmulqdq",
+    "rdtscp",
+];
+const SANDYBRIDGE_FEATURES: &[&str] = &["avx", "xsave", "xsaveopt", "tsc-deadline"];
+const HASWELL_FEATURES: &[&str] = &[
+    "fma", "pcid", "movbe", "f16c", "rdrand", "fsgsbase", "bmi1", "hle", "avx2", "smep", "bmi2",
+    "erms", "invpcid", "rtm", "abm",
+];
+const SKYLAKE_CLIENT_FEATURES: &[&str] = &[
+    "rdseed",
+    "adx",
+    "smap",
+    "clflushopt",
+    "xsavec",
+    "xgetbv1",
+    "3dnowprefetch",
+];
+const SKYLAKE_SERVER_FEATURES: &[&str] = &[
+    "pku", "clwb", "avx512f", "avx512dq", "avx512cd", "avx512bw", "avx512vl", "pdpe1gb",
+];
+const CASCADELAKE_SERVER_FEATURES: &[&str] = &["avx512-vnni"];
+const EPYC_FEATURES: &[&str] = &[
+    "ssse3",
+    "sse4.1",
+    "sse4.2",
+    "popcnt",
+    "aes",
+    "pclmulqdq",
+    "movbe",
+    "avx",
+    "xsave",
+    "xsaveopt",
+    "xsavec",
+    "xgetbv1",
+    "f16c",
+    "rdrand",
+    "fma",
+    "fsgsbase",
+    "bmi1",
+    "avx2",
+    "smep",
+    "bmi2",
+    "rdseed",
+    "adx",
+    "smap",
+    "clflushopt",
+    "sha-ni",
+    "abm",
+    "sse4a",
+    "3dnowprefetch",
+    "pdpe1gb",
+    "rdtscp",
+];
+
+/// Named x86 CPU models.
+///
+/// The features of a named model don't change with host, which makes the vcpu
+/// presented to guest stable across hosts, e.g. for migration.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum X86CPUModel {
+    /// Pass all features supported by host kvm through to guest.
+    #[default]
+    Host,
+    Qemu64,
+    Westmere,
+    SandyBridge,
+    Haswell,
+    SkylakeClient,
+    SkylakeServer,
+    CascadelakeServer,
+    Epyc,
+}
+
+/// Definition of a named CPU model.
+struct CPUModelDef {
+    name: &'static str,
+    family: u32,
+    model: u32,
+    stepping: u32,
+    model_id: &'static str,
+    features: &'static [&'static [&'static str]],
+}
+
+const CPU_MODELS: &[(X86CPUModel, CPUModelDef)] = &[
+    (
+        X86CPUModel::Qemu64,
+        CPUModelDef {
+            name: "qemu64",
+            family: 15,
+            model: 107,
+            stepping: 1,
+            model_id: "QEMU Virtual CPU version 2.5+",
+            features: &[BASE_FEATURES, KVM_FEATURES],
+        },
+    ),
+    (
+        X86CPUModel::Westmere,
+        CPUModelDef {
+            name: "Westmere",
+            family: 6,
+            model: 44,
+            stepping: 1,
+            model_id: "Westmere E56xx/L56xx/X56xx (Nehalem-C)",
+            features: &[BASE_FEATURES, KVM_FEATURES, WESTMERE_FEATURES],
+        },
+    ),
+    (
+        X86CPUModel::SandyBridge,
+        CPUModelDef {
+            name: "SandyBridge",
+            family: 6,
+            model: 42,
+            stepping: 1,
+            model_id: "Intel Xeon E312xx (Sandy Bridge)",
+            features: &[
+                BASE_FEATURES,
+                KVM_FEATURES,
+                WESTMERE_FEATURES,
+                SANDYBRIDGE_FEATURES,
+            ],
+        },
+    ),
+    (
+        X86CPUModel::Haswell,
+        CPUModelDef {
+            name: "Haswell",
+            family: 6,
+            model: 60,
+            stepping: 4,
+            model_id: "Intel Core Processor (Haswell)",
+            features: &[
+                BASE_FEATURES,
+                KVM_FEATURES,
+                WESTMERE_FEATURES,
+                SANDYBRIDGE_FEATURES,
+                HASWELL_FEATURES,
+            ],
+        },
+    ),
+    (
+        X86CPUModel::SkylakeClient,
+        CPUModelDef {
+            name: "Skylake-Client",
+            family: 6,
+            model: 94,
+            stepping: 3,
+            model_id: "Intel Core Processor (Skylake)",
+            features: &[
+                BASE_FEATURES,
+                KVM_FEATURES,
+                WESTMERE_FEATURES,
+                SANDYBRIDGE_FEATURES,
+                HASWELL_FEATURES,
+                SKYLAKE_CLIENT_FEATURES,
+            ],
+        },
+    ),
+    (
+        X86CPUModel::SkylakeServer,
+        CPUModelDef {
+            name: "Skylake-Server",
+            family: 6,
+            model: 85,
+            stepping: 4,
+            model_id: "Intel Xeon Processor (Skylake)",
+            features: &[
+                BASE_FEATURES,
+                KVM_FEATURES,
+                WESTMERE_FEATURES,
+                SANDYBRIDGE_FEATURES,
+                HASWELL_FEATURES,
+                SKYLAKE_CLIENT_FEATURES,
+                SKYLAKE_SERVER_FEATURES,
+            ],
+        },
+    ),
+    (
+        X86CPUModel::CascadelakeServer,
+        CPUModelDef {
+            name: "Cascadelake-Server",
+            family: 6,
+            model: 85,
+            stepping: 6,
+            model_id: "Intel Xeon Processor (Cascadelake)",
+            features: &[
+                BASE_FEATURES,
+                KVM_FEATURES,
+                WESTMERE_FEATURES,
+                SANDYBRIDGE_FEATURES,
+                HASWELL_FEATURES,
+                SKYLAKE_CLIENT_FEATURES,
+                SKYLAKE_SERVER_FEATURES,
+                CASCADELAKE_SERVER_FEATURES,
+            ],
+        },
+    ),
+    (
+        X86CPUModel::Epyc,
+        CPUModelDef {
+            name: "EPYC",
+            family: 23,
+            model: 1,
+            stepping: 2,
+            model_id: "AMD EPYC Processor",
+            features: &[BASE_FEATURES, KVM_FEATURES, EPYC_FEATURES],
+        },
+    ),
+];
+
+impl X86CPUModel {
+    fn from_name(name: &str) -> Result<Self> {
+        if name == "host" {
+            return Ok(X86CPUModel::Host);
+        }
+        CPU_MODELS
+            .iter()
+            .find(|(_, def)| def.name.eq_ignore_ascii_case(name))
+            .map(|(model, _)| *model)
+            .ok_or_else(|| anyhow!("Unknown cpu model: {}", name))
+    }
+
+    fn def(&self) -> Option<&'static CPUModelDef> {
+        CPU_MODELS
+            .iter()
+            .find(|(model, _)| model == self)
+            .map(|(_, def)| def)
+    }
+}
+
+/// Find the feature word and the mask of bits of the feature.
+fn find_feature(name: &str) -> Result<(FeatureWord, u32)> {
+    let name = name.replace('_', "-");
+    let mut found = None;
+    for (feature, word, bit) in FEATURES.iter() {
+        if *feature == name {
+            let (_, mask) = found.get_or_insert((*word, 0_u32));
+            *mask |= 1 << bit;
+        }
+    }
+    found.ok_or_else(|| anyhow!("Unknown cpu feature: {}", name))
+}
+
+fn feature_name(word: FeatureWord, bit: u32) -> String {
+    FEATURES
+        .iter()
+        .find(|(_, w, b)| *w == word && *b == bit)
+        .map_or_else(
+            || format!("{:?}[{}]", word, bit),
+            |(name, _, _)| name.to_string(),
+        )
+}
+
+/// CPU model and feature flags of x86 vcpu.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct X86CPUFeatures {
+    pub model: X86CPUModel,
+    /// Feature bits enabled by `+feature`, indexed by feature word.
+    plus: [u32; FEATURE_WORDS],
+    /// Feature bits disabled by `-feature`, indexed by feature word.
+    minus: [u32; FEATURE_WORDS],
+    /// Guest TSC frequency in kHz, 0 means the frequency of host.
+    pub tsc_khz: u32,
+}
+
+impl TryFrom<&CpuConfig> for X86CPUFeatures {
+    type Error = anyhow::Error;
+
+    fn try_from(conf: &CpuConfig) -> Result<Self> {
+        let mut features = X86CPUFeatures {
+            model: X86CPUModel::from_name(conf.model.as_deref().unwrap_or("host"))?,
+            tsc_khz: conf.tsc_khz.unwrap_or(0),
+            ..Default::default()
+        };
+        for (name, enable) in conf.flags.iter() {
+            let (word, mask) = find_feature(name)?;
+            if *enable {
+                features.plus[word as usize] |= mask;
+                features.minus[word as usize] &= !mask;
+            } else {
+                features.minus[word as usize] |= mask;
+                features.plus[word as usize] &= !mask;
+            }
+        }
+        Ok(features)
+    }
+}
+
+impl X86CPUFeatures {
+    /// Filter the cpuid supported by kvm with the CPU model and feature flags.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpuid` - The cpuid supported by kvm, which will be set to vcpu.
+    pub fn filter_cpuid(&self, cpuid: &mut CpuId) -> Result<()> {
+        let model_def = self.model.def();
+        let mut model_mask = [0_u32; FEATURE_WORDS];
+        if let Some(def) = model_def {
+            for name in def.features.iter().flat_map(|features| features.iter()) {
+                let (word, mask) = find_feature(name)?;
+                model_mask[word as usize] |= mask;
+            }
+        }
+
+        let entries = cpuid.as_mut_slice();
+        for word in ALL_FEATURE_WORDS.iter() {
+            let (function, index) = word.leaf();
+            let mut entry = entries
+                .iter_mut()
+                .find(|entry| entry.function == function && entry.index == index);
+            let supported = entry.as_mut().map_or(0, |entry| *word.reg(entry));
+
+            let mut required = self.plus[*word as usize];
+            // KVM paravirtual features of named models are enabled only if kvm supports them.
+            if model_def.is_some() && *word != FeatureWord::KvmEax {
+                required |= model_mask[*word as usize] & !self.minus[*word as usize];
+            }
+            let missing = required & !supported;
+            if missing != 0 {
+                bail!(
+                    "CPU feature {} is not supported by host",
+                    feature_name(*word, missing.trailing_zeros())
+                );
+            }
+
+            if let Some(entry) = entry {
+                let reg = word.reg(entry);
+                if model_def.is_some() {
+                    *reg &= model_mask[*word as usize];
+                }
+                // Invariant TSC keeps its rate only if the TSC frequency is fixed, otherwise
+                // it changes when guest is migrated to a host with different frequency.
+                if *word == FeatureWord::Ext7Edx && self.tsc_khz == 0 {
+                    *reg &= !(1 << INVTSC_BIT);
+                }
+                *reg |= self.plus[*word as usize];
+                *reg &= !self.minus[*word as usize];
+            }
+        }
+
+        if let Some(def) = model_def {
+            set_model_identity(entries, def);
+        }
+        Ok(())
+    }
+
+    /// Get the names of the known CPU features exposed to guest, which are
+    /// compared with the destination before migration.
+    pub fn guest_features(&self) -> Result<Vec<String>> {
+        let mut cpuid = Kvm::new()
+            .with_context(|| "Failed to open /dev/kvm")?
+            .get_supported_cpuid(KVM_MAX_CPUID_ENTRIES)
+            .with_context(|| "Failed to get supported cpuid")?;
+        self.filter_cpuid(&mut cpuid)?;
+        Ok(enabled_features(&mut cpuid))
+    }
+}
+
+/// Get the names of the known CPU features which are set in cpuid.
+fn enabled_features(cpuid: &mut CpuId) -> Vec<String> {
+    let entries = cpuid.as_mut_slice();
+    let mut names = Vec::new();
+    for (name, word, bit) in FEATURES.iter() {
+        let (function, index) = word.leaf();
+        if let Some(entry) = entries
+            .iter_mut()
+            .find(|entry| entry.function == function && entry.index == index)
+        {
+            if *word.reg(entry) & (1 << bit) != 0 && !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Set family/model/stepping and model id string of the named model to cpuid.
+fn set_model_identity(entries: &mut [kvm_cpuid_entry2], def: &CPUModelDef) {
+    let mut model_id = [0_u8; 48];
+    let len = def.model_id.len().min(model_id.len() - 1);
+    model_id[..len].copy_from_slice(&def.model_id.as_bytes()[..len]);
+
+    for entry in entries.iter_mut() {
+        match entry.function {
+            1 => entry.eax = cpu_signature(def.family, def.model, def.stepping),
+            0x8000_0002..=0x8000_0004 => {
+                let start = ((entry.function - 0x8000_0002) * 16) as usize;
+                let regs = [
+                    &mut entry.eax,
+                    &mut entry.ebx,
+                    &mut entry.ecx,
+                    &mut entry.edx,
+                ];
+                for (i, reg) in regs.into_iter().enumerate() {
+                    let bytes = &model_id[start + i * 4..start + i * 4 + 4];
+                    *reg = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Encode family/model/stepping to CPUID[1].EAX.
+fn cpu_signature(family: u32, model: u32, stepping: u32) -> u32 {
+    let (base_family, ext_family) = if family > 0xf {
+        (0xf, family - 0xf)
+    } else {
+        (family, 0)
+    };
+    (stepping & 0xf) | (model & 0xf) << 4 | base_family << 8 | (model >> 4) << 16 | ext_family << 20
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_signature() {
+        // Skylake-Server: family 6, model 85, stepping 4.
+        assert_eq!(cpu_signature(6, 85, 4), 0x0005_0654);
+        // EPYC: family 23, model 1, stepping 2.
+        assert_eq!(cpu_signature(23, 1, 2), 0x0080_0f12);
+    }
+
+    #[test]
+    fn test_filter_cpuid() {
+        let conf = CpuConfig {
+            model: Some("qemu64".to_string()),
+            flags: vec![("sse4.2".to_string(), true), ("nx".to_string(), false)],
+            ..Default::default()
+        };
+        let features = X86CPUFeatures::try_from(&conf).unwrap();
+        assert_eq!(features.model, X86CPUModel::Qemu64);
+
+        let mut cpuid = CpuId::new(0).unwrap();
+        for (function, index) in [(1, 0), (7, 0), (0xd, 1), (0x8000_0001, 0)] {
+            cpuid
+                .push(kvm_cpuid_entry2 {
+                    function,
+                    index,
+                    eax: u32::MAX,
+                    ebx: u32::MAX,
+                    ecx: u32::MAX,
+                    edx: u32::MAX,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        features.filter_cpuid(&mut cpuid).unwrap();
+        let entries = cpuid.as_slice();
+        // sse3, cx16, sse4.2, x2apic and hypervisor.
+        assert_eq!(entries[0].ecx, 1 | 1 << 13 | 1 << 20 | 1 << 21 | 1 << 31);
+        assert_eq!(entries[0].eax, cpu_signature(15, 107, 1));
+        assert_eq!(entries[1].ebx, 0);
+        assert_eq!(entries[2].eax, 0);
+        // syscall and lm, nx is disabled.
+        assert_eq!(entries[3].edx, 1 << 11 | 1 << 29);
+        let names = enabled_features(&mut cpuid);
+        assert!(names.contains(&"sse4.2".to_string()));
+        assert!(names.contains(&"lm".to_string()));
+        assert!(!names.contains(&"nx".to_string()));
+        assert!(!names.contains(&"avx".to_string()));
+
+        // Feature which is not supported by host.
+        let mut cpuid = CpuId::new(0).unwrap();
+        cpuid
+            .push(kvm_cpuid_entry2 {
+                function: 1,
+                ..Default::default()
+            })
+            .unwrap();
+        let conf = CpuConfig {
+            flags: vec![("avx".to_string(), true)],
+            ..Default::default()
+        };
+        let features = X86CPUFeatures::try_from(&conf).unwrap();
+        assert!(features.filter_cpuid(&mut cpuid).is_err());
+
+        let conf = CpuConfig {
+            model: Some("unknown".to_string()),
+            ..Default::default()
+        };
+        assert!(X86CPUFeatures::try_from(&conf).is_err());
+        let conf = CpuConfig {
+            flags: vec![("unknown".to_string(), true)],
+            ..Default::default()
+        };
+        assert!(X86CPUFeatures::try_from(&conf).is_err());
+    }
+
+    #[test]
+    fn test_kvm_features() {
+        let kvm_cpuid = || {
+            let mut cpuid = CpuId::new(0).unwrap();
+            for (function, index) in [(1, 0), (7, 0), (0xd, 1), (0x8000_0001, 0)] {
+                cpuid
+                    .push(kvm_cpuid_entry2 {
+                        function,
+                        index,
+                        eax: u32::MAX,
+                        ebx: u32::MAX,
+                        ecx: u32::MAX,
+                        edx: u32::MAX,
+                        ..Default::default()
+                    })
+                    .unwrap();
+            }
+            cpuid
+                .push(kvm_cpuid_entry2 {
+                    function: 0x4000_0001,
+                    eax: 1
+                        | 1 << 1
+                        | 1 << 3
+                        | 1 << 4
+                        | 1 << 5
+                        | 1 << 6
+                        | 1 << 7
+                        | 1 << 9
+                        | 1 << 11
+                        | 1 << 12
+                        | 1 << 13
+                        | 1 << 14
+                        | 1 << 24,
+                    ..Default::default()
+                })
+                .unwrap();
+            cpuid
+        };
+
+        // kvmclock occupies two bits.
+        let conf = CpuConfig {
+            flags: vec![("kvmclock".to_string(), false)],
+            ..Default::default()
+        };
+        let features = X86CPUFeatures::try_from(&conf).unwrap();
+        let mut cpuid = kvm_cpuid();
+        features.filter_cpuid(&mut cpuid).unwrap();
+        assert_eq!(cpuid.as_slice()[4].eax & (1 | 1 << 3), 0);
+        let names = enabled_features(&mut cpuid);
+        assert!(!names.contains(&"kvmclock".to_string()));
+        assert!(names.contains(&"kvmclock-stable-bit".to_string()));
+
+        // Host model passes paravirtual features through unless they are disabled.
+        let conf = CpuConfig {
+            flags: vec![
+                ("kvm-pv-ipi".to_string(), false),
+                ("kvm-pv-sched-yield".to_string(), false),
+            ],
+            ..Default::default()
+        };
+        let features = X86CPUFeatures::try_from(&conf).unwrap();
+        let mut cpuid = kvm_cpuid();
+        features.filter_cpuid(&mut cpuid).unwrap();
+        assert_eq!(cpuid.as_slice()[4].eax & (1 << 11 | 1 << 13), 0);
+        let names = enabled_features(&mut cpuid);
+        assert!(names.contains(&"kvm-pv-tlb-flush".to_string()));
+        assert!(names.contains(&"kvm-poll-control".to_string()));
+        assert!(!names.contains(&"kvm-pv-ipi".to_string()));
+
+        // Named models don't enable kvm-pv-unhalt, kvm-pv-tlb-flush and kvm-pv-ipi.
+        let conf = CpuConfig {
+            model: Some("qemu64".to_string()),
+            ..Default::default()
+        };
+        let features = X86CPUFeatures::try_from(&conf).unwrap();
+        let mut cpuid = kvm_cpuid();
+        features.filter_cpuid(&mut cpuid).unwrap();
+        assert_eq!(cpuid.as_slice()[4].eax & (1 << 7 | 1 << 9 | 1 << 11), 0);
+        let names = enabled_features(&mut cpuid);
+        assert_eq!(names.iter().filter(|n| *n == "kvmclock").count(), 1);
+        assert!(names.contains(&"kvm-asyncpf-int".to_string()));
+
+        // But they can be enabled explicitly.
+        let conf = CpuConfig {
+            model: Some("qemu64".to_string()),
+            flags: vec![
+                ("kvm-pv-unhalt".to_string(), true),
+                ("kvm-pv-ipi".to_string(), true),
+            ],
+            ..Default::default()
+        };
+        let features = X86CPUFeatures::try_from(&conf).unwrap();
+        let mut cpuid = kvm_cpuid();
+        features.filter_cpuid(&mut cpuid).unwrap();
+        assert_eq!(
+            cpuid.as_slice()[4].eax & (1 << 7 | 1 << 11),
+            1 << 7 | 1 << 11
+        );
+        assert_eq!(cpuid.as_slice()[4].eax & 1 << 9, 0);
+    }
+
+    #[test]
+    fn test_invtsc() {
+        let invtsc_cpuid = || {
+            let mut cpuid = CpuId::new(0).unwrap();
+            cpuid
+                .push(kvm_cpuid_entry2 {
+                    function: 0x8000_0007,
+                    edx: 1 << INVTSC_BIT,
+                    ..Default::default()
+                })
+                .unwrap();
+            cpuid
+        };
+
+        // Invariant TSC is hidden if the TSC frequency is not fixed.
+        let features = X86CPUFeatures::try_from(&CpuConfig::default()).unwrap();
+        let mut cpuid = invtsc_cpuid();
+        features.filter_cpuid(&mut cpuid).unwrap();
+        assert_eq!(cpuid.as_slice()[0].edx, 0);
+
+        let conf = CpuConfig {
+            tsc_khz: Some(2_400_000),
+            ..Default::default()
+        };
+        let features = X86CPUFeatures::try_from(&conf).unwrap();
+        assert_eq!(features.tsc_khz, 2_400_000);
+        let mut cpuid = invtsc_cpuid();
+        features.filter_cpuid(&mut cpuid).unwrap();
+        assert!(enabled_features(&mut cpuid).contains(&"invtsc".to_string()));
+
+        let conf = CpuConfig {
+            flags: vec![("invtsc".to_string(), true)],
+            ..Default::default()
+        };
+        let features = X86CPUFeatures::try_from(&conf).unwrap();
+        let mut cpuid = invtsc_cpuid();
+        features.filter_cpuid(&mut cpuid).unwrap();
+        assert_eq!(cpuid.as_slice()[0].edx, 1 << INVTSC_BIT);
+    }
+}
@@ -12,15 +12,17 @@
 
 pub mod caps;
 mod cpuid;
+mod features;
 
 use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, Context, Result};
 use kvm_bindings::{
-    kvm_cpuid_entry2, kvm_debugregs, kvm_fpu, kvm_lapic_state, kvm_mp_state, kvm_msr_entry,
-    kvm_regs, kvm_segment, kvm_sregs, kvm_vcpu_events, kvm_xcrs, kvm_xsave, CpuId, Msrs,
-    KVM_CPUID_FLAG_SIGNIFCANT_INDEX, KVM_MAX_CPUID_ENTRIES, KVM_MP_STATE_RUNNABLE,
-    KVM_MP_STATE_UNINITIALIZED,
+    kvm_cpuid_entry2, kvm_debugregs, kvm_fpu, kvm_guest_debug, kvm_lapic_state, kvm_mp_state,
+    kvm_msr_entry, kvm_regs, kvm_segment, kvm_sregs, kvm_vcpu_events, kvm_xcrs, kvm_xsave, CpuId,
+    Msrs, KVM_CPUID_FLAG_SIGNIFCANT_INDEX, KVM_GUESTDBG_ENABLE, KVM_GUESTDBG_INJECT_BP,
+    KVM_GUESTDBG_SINGLESTEP, KVM_GUESTDBG_USE_HW_BP, KVM_GUESTDBG_USE_SW_BP, KVM_MAX_CPUID_ENTRIES,
+    KVM_MP_STATE_RUNNABLE, KVM_MP_STATE_UNINITIALIZED,
 };
 use kvm_ioctls::{Kvm, VcpuFd};
 use migration::{
@@ -30,6 +32,7 @@ use migration_derive::{ByteCode, Desc};
 use util::byte_code::ByteCode;
 
 use self::cpuid::host_cpuid;
+pub use self::features::{X86CPUFeatures, X86CPUModel};
 use crate::CPU;
 
 const ECX_EPB_SHIFT: u32 = 3;
@@ -48,9 +51,32 @@ const MSR_LIST: &[u32] = &[
     0x0010,      // MSR_IA32_TSC,
     0x01a0,      // MSR_IA32_MISC_ENABLE,
     0x2ff,       // MSR_MTRRdefType
+    0x0011,      // MSR_KVM_WALL_CLOCK
+    0x0012,      // MSR_KVM_SYSTEM_TIME
+    0x4b56_4d00, // MSR_KVM_WALL_CLOCK_NEW
+    0x4b56_4d01, // MSR_KVM_SYSTEM_TIME_NEW
+    0x4b56_4d03, // MSR_KVM_STEAL_TIME
+    0x4b56_4d06, // MSR_KVM_ASYNC_PF_INT, set before MSR_KVM_ASYNC_PF_EN
+    0x4b56_4d02, // MSR_KVM_ASYNC_PF_EN
+    0x4b56_4d05, // MSR_KVM_POLL_CONTROL
 ];
 
+/// Number of hardware breakpoints, which use debug registers DR0-DR3.
+pub const MAX_HW_BREAKPOINTS: usize = 4;
 const MSR_IA32_MISC_ENABLE: u32 = 0x01a0;
+const MSR_KVM_POLL_CONTROL: u32 = 0x4b56_4d05;
+
+/// Guest debug state of vcpu. Software breakpoints (`int3`) always trap to
+/// userspace once guest debug is enabled.
+#[derive(Clone, Debug, Default)]
+pub struct GuestDebug {
+    /// Trap after executing one instruction.
+    pub single_step: bool,
+    /// Addresses of hardware breakpoints, at most `MAX_HW_BREAKPOINTS`.
+    pub hw_breakpoints: Vec<u64>,
+    /// Reinject the `#BP` exception which is not caused by the debugger to guest.
+    pub inject_bp: bool,
+}
 const MSR_IA32_MISC_ENABLE_FAST_STRING: u64 = 0x1;
 
 const ECX_INVALID: u32 = 0u32 << 8;
@@ -58,6 +84,9 @@ const ECX_THREAD: u32 = 1u32 << 8;
 const ECX_CORE: u32 = 2u32 << 8;
 const ECX_DIE: u32 = 5u32 << 8;
 
+/// Passed in %eax to tell multiboot2 kernel that it's loaded by a compliant bootloader.
+const MULTIBOOT2_BOOTLOADER_MAGIC: u64 = 0x36d7_6289;
+
 /// X86 CPU booting configure information
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Default, Clone, Debug)]
@@ -79,6 +108,12 @@ pub struct X86CPUBootConfig {
     pub idt_base: u64,
     pub idt_size: u16,
     pub pml4_start: u64,
+    /// Boot from PVH entry in 32-bit protected mode, `zero_page` is the
+    /// address of `hvm_start_info` which is passed in %rbx.
+    pub pvh: bool,
+    /// Boot multiboot2 kernel in 32-bit protected mode, `zero_page` is the
+    /// address of boot information which is passed in %rbx.
+    pub multiboot2: bool,
 }
 
 #[allow(clippy::upper_case_acronyms)]
@@ -125,6 +160,9 @@ pub struct X86CPUState {
     xsave: kvm_xsave,
     xcrs: kvm_xcrs,
     debugregs: kvm_debugregs,
+    features: X86CPUFeatures,
+    /// TSC frequency of vcpu in kHz, which is carried to the destination of migration.
+    tsc_khz: u32,
 }
 
 impl X86CPUState {
@@ -169,6 +207,8 @@ impl X86CPUState {
         self.xsave = locked_cpu_state.xsave;
         self.xcrs = locked_cpu_state.xcrs;
         self.debugregs = locked_cpu_state.debugregs;
+        self.features = locked_cpu_state.features;
+        self.tsc_khz = locked_cpu_state.tsc_khz;
     }
 
     /// Set register value in `X86CPUState` according to `boot_config`.
@@ -177,16 +217,19 @@ impl X86CPUState {
     ///
     /// * `vcpu_fd` - Vcpu file descriptor in kvm.
     /// * `boot_config` - Boot message from boot_loader.
+    /// * `vcpu_config` - CPU model and features of vcpu.
     pub fn set_boot_config(
         &mut self,
         vcpu_fd: &Arc<VcpuFd>,
         boot_config: &X86CPUBootConfig,
+        vcpu_config: &X86CPUFeatures,
     ) -> Result<()> {
         self.setup_lapic(vcpu_fd)?;
         self.setup_regs(boot_config);
         self.setup_sregs(vcpu_fd, boot_config)?;
         self.setup_fpu();
         self.setup_msrs();
+        self.features = *vcpu_config;
 
         Ok(())
     }
@@ -212,6 +255,7 @@ impl X86CPUState {
     pub fn reset_vcpu(&self, vcpu_fd: &Arc<VcpuFd>, caps: &caps::X86CPUCaps) -> Result<()> {
         self.setup_cpuid(vcpu_fd)
             .with_context(|| format!("Failed to set cpuid for CPU {}", self.apic_id))?;
+        self.setup_tsc_khz(vcpu_fd)?;
 
         vcpu_fd
             .set_mp_state(self.mp_state)
@@ -252,6 +296,24 @@ impl X86CPUState {
         Ok(())
     }
 
+    /// Set the configured TSC frequency, or the one of migration source.
+    fn setup_tsc_khz(&self, vcpu_fd: &Arc<VcpuFd>) -> Result<()> {
+        let tsc_khz = if self.features.tsc_khz != 0 {
+            self.features.tsc_khz
+        } else {
+            self.tsc_khz
+        };
+        if tsc_khz == 0 || vcpu_fd.get_tsc_khz().ok() == Some(tsc_khz) {
+            return Ok(());
+        }
+        vcpu_fd.set_tsc_khz(tsc_khz).with_context(|| {
+            format!(
+                "Failed to set TSC frequency {} kHz for CPU {}, TSC scaling may be unsupported by host",
+                tsc_khz, self.apic_id
+            )
+        })
+    }
+
     fn setup_lapic(&mut self, vcpu_fd: &Arc<VcpuFd>) -> Result<()> {
         // Disable nmi and external interrupt before enter protected mode
         // See: https://elixir.bootlin.com/linux/v4.19.123/source/arch/x86/include/asm/apicdef.h
@@ -290,9 +352,16 @@ impl X86CPUState {
             rip: boot_config.boot_ip,
             rsp: boot_config.boot_sp,
             rbp: boot_config.boot_sp,
-            rsi: boot_config.zero_page,
             ..Default::default()
         };
+        if boot_config.multiboot2 {
+            self.regs.rax = MULTIBOOT2_BOOTLOADER_MAGIC;
+            self.regs.rbx = boot_config.zero_page;
+        } else if boot_config.pvh {
+            self.regs.rbx = boot_config.zero_page;
+        } else {
+            self.regs.rsi = boot_config.zero_page;
+        }
     }
 
     fn setup_sregs(&mut self, vcpu_fd: &Arc<VcpuFd>, boot_config: &X86CPUBootConfig) -> Result<()> {
@@ -351,9 +420,14 @@ impl X86CPUState {
         self.sregs.idt.base = boot_config.idt_base;
         self.sregs.idt.limit = boot_config.idt_size;
 
-        // Open 64-bit protected mode, include
-        // Protection enable, Long mode enable, Long mode active
         self.sregs.cr0 |= X86_CR0_PE;
+        // PVH and multiboot2 entry run in 32-bit protected mode with paging disabled.
+        if boot_config.pvh || boot_config.multiboot2 {
+            return;
+        }
+
+        // Open 64-bit protected mode, include
+        // Long mode enable, Long mode active
         self.sregs.efer |= EFER_LME | EFER_LMA;
 
         // Setup page table
@@ -380,6 +454,8 @@ impl X86CPUState {
         for (index, msr) in MSR_LIST.iter().enumerate() {
             let data = match *msr {
                 MSR_IA32_MISC_ENABLE => MSR_IA32_MISC_ENABLE_FAST_STRING,
+                // Host-side halt polling is enabled by default.
+                MSR_KVM_POLL_CONTROL => 1,
                 _ => 0u64,
             };
 
@@ -555,6 +631,7 @@ impl X86CPUState {
                 _ => (),
             }
         }
+        self.features.filter_cpuid(&mut cpuid)?;
 
         vcpu_fd
             .set_cpuid2(&cpuid)
@@ -563,6 +640,54 @@ impl X86CPUState {
     }
 }
 
+impl CPU {
+    /// Get the instruction pointer of the crashed vcpu.
+    pub(crate) fn crash_pc(&self) -> Option<u64> {
+        self.fd.get_regs().ok().map(|regs| regs.rip)
+    }
+
+    /// Dump the general and special registers of the vcpu.
+    pub(crate) fn dump_regs(&self) -> String {
+        match (self.fd.get_regs(), self.fd.get_sregs()) {
+            (Ok(regs), Ok(sregs)) => format!("{:#x?}\n{:#x?}", regs, sregs),
+            (Err(e), _) | (_, Err(e)) => format!("failed to get registers: {}", e),
+        }
+    }
+
+    /// Set the guest debug state of the vcpu, `None` to disable guest debug.
+    pub fn set_guest_debug(&self, guest_debug: Option<&GuestDebug>) -> Result<()> {
+        let mut debug = kvm_guest_debug::default();
+        if let Some(guest_debug) = guest_debug {
+            if guest_debug.hw_breakpoints.len() > MAX_HW_BREAKPOINTS {
+                bail!(
+                    "Too many hardware breakpoints {}, at most {}",
+                    guest_debug.hw_breakpoints.len(),
+                    MAX_HW_BREAKPOINTS
+                );
+            }
+
+            debug.control = KVM_GUESTDBG_ENABLE | KVM_GUESTDBG_USE_SW_BP;
+            if guest_debug.single_step {
+                debug.control |= KVM_GUESTDBG_SINGLESTEP;
+            }
+            if guest_debug.inject_bp {
+                debug.control |= KVM_GUESTDBG_INJECT_BP;
+            }
+            if !guest_debug.hw_breakpoints.is_empty() {
+                debug.control |= KVM_GUESTDBG_USE_HW_BP;
+            }
+            for (i, addr) in guest_debug.hw_breakpoints.iter().enumerate() {
+                debug.arch.debugreg[i] = *addr;
+                // Global enable bit in DR7, with the condition of instruction execution.
+                debug.arch.debugreg[7] |= 2 << (i * 2);
+            }
+        }
+        self.fd
+            .set_guest_debug(&debug)
+            .with_context(|| format!("Failed to set guest debug of vcpu{}", self.id))
+    }
+}
+
 impl StateTransfer for CPU {
     fn get_state_vec(&self) -> Result<Vec<u8>> {
         let mut msr_entries = self.caps.create_msr_entries()?;
@@ -586,6 +711,7 @@ impl StateTransfer for CPU {
             cpu_state_locked.msr_list[i] = *entry;
         }
         cpu_state_locked.cpu_events = self.fd.get_vcpu_events()?;
+        cpu_state_locked.tsc_khz = self.fd.get_tsc_khz().unwrap_or(0);
 
         Ok(cpu_state_locked.as_bytes().to_vec())
     }
@@ -667,6 +793,8 @@ mod test {
             idt_base: 0x520u64,
             idt_size: 8,
             pml4_start: 0x0000_9000,
+            pvh: false,
+            multiboot2: false,
         };
 
         // For `get_lapic` in realize function to work,
@@ -677,7 +805,9 @@ mod test {
         let vcpu = Arc::new(vm_fd.create_vcpu(0).unwrap());
         let mut x86_cpu = X86CPUState::new(0, 1);
         //test `set_boot_config` function
-        assert!(x86_cpu.set_boot_config(&vcpu, &cpu_config).is_ok());
+        assert!(x86_cpu
+            .set_boot_config(&vcpu, &cpu_config, &X86CPUFeatures::default())
+            .is_ok());
 
         // test setup special registers
         let cpu_caps = caps::X86CPUCaps::init_capabilities();
@@ -36,6 +36,8 @@ use std::sync::{Arc, Mutex};
 
 use vmm_sys_util::eventfd::EventFd;
 
+use crate::acpi::mem_hotplug::AML_MEM_HOTPLUG_SCAN;
+
 #[derive(Clone, Copy)]
 pub enum AcpiEvent {
     Nothing = 0,
@@ -43,6 +45,7 @@ pub enum AcpiEvent {
     AcadSt = 2,
     BatteryInf = 4,
     BatterySt = 8,
+    MemHotplug = 16,
 }
 
 const AML_GED_EVT_REG: &str = "EREG";
@@ -53,6 +56,7 @@ pub struct Ged {
     interrupt_evt: Arc<Option<EventFd>>,
     notification_type: Arc<AtomicU32>,
     battery_present: bool,
+    mem_hotplug: bool,
     /// System resource.
     res: SysRes,
 }
@@ -63,6 +67,7 @@ impl Default for Ged {
             interrupt_evt: Arc::new(None),
             notification_type: Arc::new(AtomicU32::new(AcpiEvent::Nothing as u32)),
             battery_present: false,
+            mem_hotplug: false,
             res: SysRes::default(),
         }
     }
@@ -74,6 +79,7 @@ impl Ged {
         sysbus: &mut SysBus,
         power_button: Arc<EventFd>,
         battery_present: bool,
+        mem_hotplug: bool,
         region_base: u64,
         region_size: u64,
     ) -> Result<Arc<Mutex<Ged>>> {
@@ -81,6 +87,7 @@ impl Ged {
         self.set_sys_resource(sysbus, region_base, region_size)
             .with_context(|| AcpiError::Alignment(region_size.try_into().unwrap()))?;
         self.battery_present = battery_present;
+        self.mem_hotplug = mem_hotplug;
 
         let dev = Arc::new(Mutex::new(self));
         sysbus.attach_device(&dev, region_base, region_size, "Ged")?;
@@ -226,6 +233,16 @@ impl AmlBuilder for Ged {
             method.append_child(if_scope);
         }
 
+        if self.mem_hotplug {
+            let evt = AcpiEvent::MemHotplug as u64;
+            let mut if_scope = AmlIf::new(AmlEqual::new(
+                AmlAnd::new(AmlLocal(0), AmlInteger(evt), AmlLocal(1)),
+                AmlInteger(evt),
+            ));
+            if_scope.append_child(AmlName(AML_MEM_HOTPLUG_SCAN.to_string()));
+            method.append_child(if_scope);
+        }
+
         acpi_dev.append_child(method);
 
         acpi_dev.aml_bytes()
@@ -0,0 +1,261 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use util::num_ops::write_data_u32;
+
+use crate::acpi::ged::{AcpiEvent, Ged};
+use acpi::{
+    AcpiError, AmlAddressSpaceDecode, AmlAddressSpaceType, AmlAnd, AmlBuilder, AmlCacheable,
+    AmlDevice, AmlEisaId, AmlEqual, AmlField, AmlFieldAccessType, AmlFieldLockRule, AmlFieldUnit,
+    AmlFieldUpdateRule, AmlIf, AmlInteger, AmlLocal, AmlMethod, AmlName, AmlNameDecl, AmlNotify,
+    AmlOpRegion, AmlQWordDesc, AmlReadAndWrite, AmlResTemplate, AmlReturn, AmlScopeBuilder,
+    AmlStore, AmlString,
+};
+use address_space::GuestAddress;
+use sysbus::{SysBus, SysBusDevOps, SysRes};
+
+/// Name of the memory hotplug controller in ACPI namespace.
+pub const AML_MEM_HOTPLUG_DEV: &str = "\\_SB.MHPC";
+/// Method of the memory hotplug controller to scan the inserted memory devices.
+pub const AML_MEM_HOTPLUG_SCAN: &str = "\\_SB.MHPC.MSCN";
+
+const AML_MEM_HOTPLUG_REG: &str = "MREG";
+const AML_MEM_PRESENT: &str = "MPRS";
+const AML_MEM_INSERTED: &str = "MINS";
+
+/// Register which contains the bitmap of slots with memory device plugged.
+const REG_PRESENT: u64 = 0;
+/// Register which contains the bitmap of slots with memory device inserted but not
+/// handled by guest yet, it's cleared after being read.
+const REG_INSERTED: u64 = 4;
+pub const MEM_HOTPLUG_REGS_SIZE: u64 = 8;
+
+/// ACPI device check notification.
+const ACPI_NOTIFY_DEVICE_CHECK: u64 = 1;
+
+/// Controller of pluggable memory slots, which reports the memory devices to guest by
+/// ACPI memory devices (PNP0C80) and GED notification.
+pub struct MemHotplugDev {
+    /// Guest physical address of the first memory slot.
+    base: u64,
+    /// Size of each memory slot.
+    slot_size: u64,
+    /// Id of the memory device plugged in each slot.
+    slots: Vec<Option<String>>,
+    /// Bitmap of slots with memory device plugged.
+    present: u32,
+    /// Bitmap of slots with memory device inserted but not handled by guest.
+    inserted: u32,
+    ged: Arc<Mutex<Ged>>,
+    /// System resource.
+    res: SysRes,
+}
+
+impl MemHotplugDev {
+    /// Create a memory hotplug controller.
+    ///
+    /// # Arguments
+    ///
+    /// * `ged_dev` - GED device to notify guest.
+    /// * `base` - Guest physical address of the first memory slot.
+    /// * `slot_size` - Size of each memory slot.
+    /// * `slots` - Number of memory slots.
+    pub fn new(ged_dev: Arc<Mutex<Ged>>, base: u64, slot_size: u64, slots: u8) -> Self {
+        Self {
+            base,
+            slot_size,
+            slots: vec![None; slots as usize],
+            present: 0,
+            inserted: 0,
+            ged: ged_dev,
+            res: SysRes::default(),
+        }
+    }
+
+    pub fn realize(
+        mut self,
+        sysbus: &mut SysBus,
+        region_base: u64,
+        region_size: u64,
+    ) -> Result<Arc<Mutex<MemHotplugDev>>> {
+        self.set_sys_resource(sysbus, region_base, region_size)
+            .with_context(|| AcpiError::Alignment(region_size.try_into().unwrap()))?;
+
+        let dev = Arc::new(Mutex::new(self));
+        sysbus.attach_device(&dev, region_base, region_size, "MemHotplug")?;
+        Ok(dev)
+    }
+
+    /// Get the guest physical address of the memory slot.
+    pub fn slot_addr(&self, slot: u8) -> u64 {
+        self.base + slot as u64 * self.slot_size
+    }
+
+    /// Get the size of each memory slot.
+    pub fn slot_size(&self) -> u64 {
+        self.slot_size
+    }
+
+    /// Find the slot for a new memory device.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The slot specified by user, the first free slot is used if it's `None`.
+    pub fn find_free_slot(&self, slot: Option<u8>) -> Result<u8> {
+        if let Some(slot) = slot {
+            match self.slots.get(slot as usize) {
+                Some(None) => return Ok(slot),
+                Some(Some(id)) => bail!("Memory slot {} is occupied by {}", slot, id),
+                None => bail!("Memory slot {} does not exist", slot),
+            }
+        }
+        self.slots
+            .iter()
+            .position(|s| s.is_none())
+            .map(|s| s as u8)
+            .with_context(|| "No free memory slot")
+    }
+
+    /// Return true if the memory device is plugged.
+    pub fn contains(&self, id: &str) -> bool {
+        self.slots.iter().flatten().any(|s| s == id)
+    }
+
+    /// Mark the memory device as plugged into the slot.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the memory device.
+    /// * `slot` - Slot of the memory device.
+    /// * `notify` - Whether to notify the guest, it's false for cold plugged device.
+    pub fn plug(&mut self, id: &str, slot: u8, notify: bool) -> Result<()> {
+        let free_slot = self.find_free_slot(Some(slot))?;
+        self.slots[free_slot as usize] = Some(id.to_string());
+        self.present |= 1 << slot;
+        if notify {
+            self.inserted |= 1 << slot;
+            self.ged
+                .lock()
+                .unwrap()
+                .inject_acpi_event(AcpiEvent::MemHotplug);
+        }
+        Ok(())
+    }
+}
+
+impl SysBusDevOps for MemHotplugDev {
+    fn read(&mut self, data: &mut [u8], _base: GuestAddress, offset: u64) -> bool {
+        let value = match offset {
+            REG_PRESENT => self.present,
+            REG_INSERTED => std::mem::take(&mut self.inserted),
+            _ => return false,
+        };
+        write_data_u32(data, value)
+    }
+
+    fn write(&mut self, _data: &[u8], _base: GuestAddress, _offset: u64) -> bool {
+        true
+    }
+
+    fn get_sys_resource(&mut self) -> Option<&mut SysRes> {
+        Some(&mut self.res)
+    }
+}
+
+impl AmlBuilder for MemHotplugDev {
+    fn aml_bytes(&self) -> Vec<u8> {
+        let mut ctrl_dev = AmlDevice::new(AML_MEM_HOTPLUG_DEV);
+        ctrl_dev.append_child(AmlNameDecl::new("_HID", AmlString("PNP0A06".to_string())));
+        ctrl_dev.append_child(AmlNameDecl::new(
+            "_UID",
+            AmlString("Memory hotplug resources".to_string()),
+        ));
+
+        ctrl_dev.append_child(AmlOpRegion::new(
+            AML_MEM_HOTPLUG_REG,
+            AmlAddressSpaceType::SystemMemory,
+            self.res.region_base,
+            self.res.region_size,
+        ));
+        let mut field = AmlField::new(
+            AML_MEM_HOTPLUG_REG,
+            AmlFieldAccessType::DWord,
+            AmlFieldLockRule::NoLock,
+            AmlFieldUpdateRule::WriteAsZeros,
+        );
+        field.append_child(AmlFieldUnit::new(Some(AML_MEM_PRESENT), 32));
+        field.append_child(AmlFieldUnit::new(Some(AML_MEM_INSERTED), 32));
+        ctrl_dev.append_child(field);
+
+        // Notify the memory devices which are inserted.
+        let mut scan = AmlMethod::new("MSCN", 0, true);
+        scan.append_child(AmlStore::new(
+            AmlName(AML_MEM_INSERTED.to_string()),
+            AmlLocal(0),
+        ));
+        for slot in 0..self.slots.len() {
+            let bit = 1_u64 << slot;
+            let mut if_scope = AmlIf::new(AmlEqual::new(
+                AmlAnd::new(AmlLocal(0), AmlInteger(bit), AmlLocal(1)),
+                AmlInteger(bit),
+            ));
+            if_scope.append_child(AmlNotify::new(
+                AmlName(format!("MP{:02X}", slot)),
+                AmlInteger(ACPI_NOTIFY_DEVICE_CHECK),
+            ));
+            scan.append_child(if_scope);
+        }
+        ctrl_dev.append_child(scan);
+
+        for slot in 0..self.slots.len() {
+            let mut mem_dev = AmlDevice::new(format!("MP{:02X}", slot).as_str());
+            mem_dev.append_child(AmlNameDecl::new("_HID", AmlEisaId::new("PNP0C80")));
+            mem_dev.append_child(AmlNameDecl::new("_UID", AmlInteger(slot as u64)));
+
+            let addr = self.slot_addr(slot as u8);
+            let mut crs = AmlResTemplate::new();
+            crs.append_child(AmlQWordDesc::new_memory(
+                AmlAddressSpaceDecode::Positive,
+                AmlCacheable::Cacheable,
+                AmlReadAndWrite::ReadWrite,
+                0,
+                addr,
+                addr + self.slot_size - 1,
+                0,
+                self.slot_size,
+            ));
+            mem_dev.append_child(AmlNameDecl::new("_CRS", crs));
+
+            let bit = 1_u64 << slot;
+            let mut sta = AmlMethod::new("_STA", 0, false);
+            let mut if_scope = AmlIf::new(AmlEqual::new(
+                AmlAnd::new(
+                    AmlName(AML_MEM_PRESENT.to_string()),
+                    AmlInteger(bit),
+                    AmlLocal(0),
+                ),
+                AmlInteger(bit),
+            ));
+            if_scope.append_child(AmlReturn::with_value(AmlInteger(0x0F)));
+            sta.append_child(if_scope);
+            sta.append_child(AmlReturn::with_value(AmlInteger(0)));
+            mem_dev.append_child(sta);
+
+            ctrl_dev.append_child(mem_dev);
+        }
+
+        ctrl_dev.aml_bytes()
+    }
+}
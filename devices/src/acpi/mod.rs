@@ -11,4 +11,5 @@
 // See the Mulan PSL v2 for more details.
 
 pub mod ged;
+pub mod mem_hotplug;
 pub mod power;
@@ -28,6 +28,8 @@
 #[cfg(target_arch = "aarch64")]
 mod aarch64;
 mod error;
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
 
 #[cfg(target_arch = "aarch64")]
 pub use aarch64::GICConfig as ICGICConfig;
@@ -43,3 +45,5 @@ pub use aarch64::GIC_IRQ_INTERNAL;
 pub use aarch64::GIC_IRQ_MAX;
 pub use anyhow::Result;
 pub use error::InterruptError;
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::{Ioapic, IOAPIC_REGION_SIZE};
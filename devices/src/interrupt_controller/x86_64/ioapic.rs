@@ -0,0 +1,295 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use log::error;
+
+use acpi::AmlBuilder;
+use address_space::GuestAddress;
+use hypervisor::kvm::{MsiVector, IOAPIC_NUM_PINS, KVM_FDS};
+use sysbus::{SysBus, SysBusDevOps, SysRes};
+
+/// Size of the register region of IOAPIC.
+pub const IOAPIC_REGION_SIZE: u64 = 0x1000;
+
+/// Offset of the index register.
+const IOAPIC_IOREGSEL: u64 = 0x00;
+/// Offset of the data register.
+const IOAPIC_IOWIN: u64 = 0x10;
+
+/// Indirect registers selected by IOREGSEL.
+const IOAPIC_REG_ID: u8 = 0x00;
+const IOAPIC_REG_VER: u8 = 0x01;
+const IOAPIC_REG_ARB: u8 = 0x02;
+const IOAPIC_REG_REDTBL_BASE: u8 = 0x10;
+
+const IOAPIC_VERSION: u32 = 0x11;
+const IOAPIC_ID_SHIFT: u32 = 24;
+const IOAPIC_ID_MASK: u32 = 0xf;
+
+/// Fields of the redirection table entry.
+const IOAPIC_RTE_VECTOR_MASK: u64 = 0xff;
+const IOAPIC_RTE_DELIVERY_MODE_SHIFT: u64 = 8;
+const IOAPIC_RTE_DELIVERY_MODE_MASK: u64 = 0x7;
+const IOAPIC_RTE_DEST_MODE_SHIFT: u64 = 11;
+const IOAPIC_RTE_DELIVERY_STATUS: u64 = 1 << 12;
+const IOAPIC_RTE_REMOTE_IRR: u64 = 1 << 14;
+const IOAPIC_RTE_MASKED: u64 = 1 << 16;
+const IOAPIC_RTE_DEST_SHIFT: u64 = 56;
+const IOAPIC_RTE_RO_BITS: u64 = IOAPIC_RTE_DELIVERY_STATUS | IOAPIC_RTE_REMOTE_IRR;
+
+/// MSI address of the local APIC.
+const MSI_ADDR_BASE: u32 = 0xfee0_0000;
+const MSI_ADDR_DEST_ID_SHIFT: u32 = 12;
+const MSI_ADDR_DEST_MODE_SHIFT: u32 = 2;
+const MSI_DATA_DELIVERY_MODE_SHIFT: u32 = 8;
+
+/// Translate the redirection table entry to the MSI message which is delivered to local APIC.
+///
+/// Interrupts of devices are injected by irqfd as pulses, so the entry is always delivered as
+/// an edge-triggered message, and no EOI is required to be broadcast from KVM.
+fn rte_to_msi(entry: u64) -> MsiVector {
+    let vector = (entry & IOAPIC_RTE_VECTOR_MASK) as u32;
+    let delivery_mode =
+        ((entry >> IOAPIC_RTE_DELIVERY_MODE_SHIFT) & IOAPIC_RTE_DELIVERY_MODE_MASK) as u32;
+    let dest_mode = ((entry >> IOAPIC_RTE_DEST_MODE_SHIFT) & 0x1) as u32;
+    let dest = ((entry >> IOAPIC_RTE_DEST_SHIFT) & 0xff) as u32;
+
+    MsiVector {
+        msg_addr_lo: MSI_ADDR_BASE
+            | (dest << MSI_ADDR_DEST_ID_SHIFT)
+            | (dest_mode << MSI_ADDR_DEST_MODE_SHIFT),
+        msg_addr_hi: 0,
+        msg_data: vector | (delivery_mode << MSI_DATA_DELIVERY_MODE_SHIFT),
+        masked: false,
+    }
+}
+
+/// IOAPIC emulated in userspace, which is used with the split irqchip of KVM.
+///
+/// The pins of IOAPIC are mapped to gsi 0-23 of KVM. Instead of injecting interrupts
+/// by itself, it translates the redirection table entries to MSI routes of these gsi,
+/// so that the irqfd of devices can still be used.
+pub struct Ioapic {
+    /// IOAPIC id.
+    id: u32,
+    /// Index of the register selected by IOREGSEL.
+    ioregsel: u8,
+    /// Redirection table.
+    redtbl: [u64; IOAPIC_NUM_PINS as usize],
+    /// System resource.
+    res: SysRes,
+}
+
+impl Default for Ioapic {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            ioregsel: 0,
+            redtbl: [IOAPIC_RTE_MASKED; IOAPIC_NUM_PINS as usize],
+            res: SysRes::default(),
+        }
+    }
+}
+
+impl Ioapic {
+    pub fn realize(mut self, sysbus: &mut SysBus, region_base: u64) -> Result<()> {
+        // IOAPIC does not raise interrupt itself, so no irq is allocated.
+        self.res.region_base = region_base;
+        self.res.region_size = IOAPIC_REGION_SIZE;
+
+        let dev = Arc::new(Mutex::new(self));
+        sysbus
+            .attach_device(&dev, region_base, IOAPIC_REGION_SIZE, "IOAPIC")
+            .with_context(|| "Failed to attach IOAPIC to system bus")?;
+        Ok(())
+    }
+
+    fn read_reg(&self) -> u32 {
+        match self.ioregsel {
+            IOAPIC_REG_ID | IOAPIC_REG_ARB => self.id << IOAPIC_ID_SHIFT,
+            IOAPIC_REG_VER => IOAPIC_VERSION | ((IOAPIC_NUM_PINS - 1) << 16),
+            sel if sel >= IOAPIC_REG_REDTBL_BASE => {
+                let index = ((sel - IOAPIC_REG_REDTBL_BASE) >> 1) as usize;
+                match self.redtbl.get(index) {
+                    Some(entry) if sel & 1 == 0 => *entry as u32,
+                    Some(entry) => (*entry >> 32) as u32,
+                    None => 0,
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_reg(&mut self, value: u32) -> Result<()> {
+        match self.ioregsel {
+            IOAPIC_REG_ID => self.id = (value >> IOAPIC_ID_SHIFT) & IOAPIC_ID_MASK,
+            sel if sel >= IOAPIC_REG_REDTBL_BASE => {
+                let index = ((sel - IOAPIC_REG_REDTBL_BASE) >> 1) as usize;
+                if index >= self.redtbl.len() {
+                    return Ok(());
+                }
+                let old = self.redtbl[index];
+                let new = if sel & 1 == 0 {
+                    (old & !0xffff_ffff) | u64::from(value)
+                } else {
+                    (old & 0xffff_ffff) | (u64::from(value) << 32)
+                };
+                self.redtbl[index] = (new & !IOAPIC_RTE_RO_BITS) | (old & IOAPIC_RTE_RO_BITS);
+                if self.redtbl[index] != old {
+                    self.update_route(index)?;
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Update the MSI route of the pin according to its redirection table entry.
+    fn update_route(&self, pin: usize) -> Result<()> {
+        let entry = self.redtbl[pin];
+        let kvm_fds = KVM_FDS.load();
+        let mut irq_route_table = kvm_fds.irq_route_table.lock().unwrap();
+        if entry & IOAPIC_RTE_MASKED != 0 {
+            irq_route_table.remove_irq_route(pin as u32);
+        } else {
+            irq_route_table
+                .update_msi_route(pin as u32, rte_to_msi(entry))
+                .with_context(|| format!("Failed to update route of IOAPIC pin {}", pin))?;
+        }
+        drop(irq_route_table);
+        kvm_fds.commit_irq_routing()
+    }
+}
+
+impl SysBusDevOps for Ioapic {
+    fn read(&mut self, data: &mut [u8], _base: GuestAddress, offset: u64) -> bool {
+        let value = match offset {
+            IOAPIC_IOREGSEL => u32::from(self.ioregsel),
+            IOAPIC_IOWIN => self.read_reg(),
+            _ => 0,
+        };
+        match data.len() {
+            1 => data[0] = value as u8,
+            4 => LittleEndian::write_u32(data, value),
+            n => {
+                error!("Invalid IOAPIC read size {}", n);
+                return false;
+            }
+        }
+        true
+    }
+
+    fn write(&mut self, data: &[u8], _base: GuestAddress, offset: u64) -> bool {
+        let value = match data.len() {
+            1 => u32::from(data[0]),
+            4 => LittleEndian::read_u32(data),
+            n => {
+                error!("Invalid IOAPIC write size {}", n);
+                return false;
+            }
+        };
+        match offset {
+            IOAPIC_IOREGSEL => self.ioregsel = value as u8,
+            IOAPIC_IOWIN => {
+                if let Err(e) = self.write_reg(value) {
+                    error!("Failed to write IOAPIC register: {:?}", e);
+                    return false;
+                }
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn get_sys_resource(&mut self) -> Option<&mut SysRes> {
+        Some(&mut self.res)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.id = 0;
+        self.ioregsel = 0;
+        for pin in 0..self.redtbl.len() {
+            if self.redtbl[pin] != IOAPIC_RTE_MASKED {
+                self.redtbl[pin] = IOAPIC_RTE_MASKED;
+                self.update_route(pin)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AmlBuilder for Ioapic {
+    fn aml_bytes(&self) -> Vec<u8> {
+        // IOAPIC is described in MADT.
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ioapic_regs() {
+        let mut ioapic = Ioapic::default();
+        let mut data = [0_u8; 4];
+
+        ioapic.write(&[IOAPIC_REG_VER], GuestAddress(0), IOAPIC_IOREGSEL);
+        assert!(ioapic.read(&mut data, GuestAddress(0), IOAPIC_IOWIN));
+        assert_eq!(LittleEndian::read_u32(&data), 0x0017_0011);
+
+        ioapic.write(&[IOAPIC_REG_ID], GuestAddress(0), IOAPIC_IOREGSEL);
+        ioapic.write(
+            &0x0200_0000_u32.to_le_bytes(),
+            GuestAddress(0),
+            IOAPIC_IOWIN,
+        );
+        assert!(ioapic.read(&mut data, GuestAddress(0), IOAPIC_IOWIN));
+        assert_eq!(LittleEndian::read_u32(&data), 0x0200_0000);
+
+        // All the pins are masked after reset.
+        ioapic.write(
+            &[IOAPIC_REG_REDTBL_BASE + 4],
+            GuestAddress(0),
+            IOAPIC_IOREGSEL,
+        );
+        assert!(ioapic.read(&mut data, GuestAddress(0), IOAPIC_IOWIN));
+        assert_eq!(LittleEndian::read_u32(&data), IOAPIC_RTE_MASKED as u32);
+        ioapic.write(
+            &[IOAPIC_REG_REDTBL_BASE + 5],
+            GuestAddress(0),
+            IOAPIC_IOREGSEL,
+        );
+        assert!(ioapic.read(&mut data, GuestAddress(0), IOAPIC_IOWIN));
+        assert_eq!(LittleEndian::read_u32(&data), 0);
+    }
+
+    #[test]
+    fn test_ioapic_rte_to_msi() {
+        // Vector 0x30, fixed delivery, physical destination 1, level-triggered.
+        let entry = 0x30 | (1 << 15) | (1_u64 << IOAPIC_RTE_DEST_SHIFT);
+        let msi = rte_to_msi(entry);
+        assert_eq!(msi.msg_addr_lo, 0xfee0_1000);
+        assert_eq!(msi.msg_addr_hi, 0);
+        assert_eq!(msi.msg_data, 0x30);
+
+        // Vector 0x41, lowest priority delivery, logical destination 0x3.
+        let entry = 0x41 | (1 << 8) | (1 << 11) | (3_u64 << IOAPIC_RTE_DEST_SHIFT);
+        let msi = rte_to_msi(entry);
+        assert_eq!(msi.msg_addr_lo, 0xfee0_3004);
+        assert_eq!(msi.msg_data, 0x141);
+    }
+}
@@ -12,18 +12,21 @@
 
 use std::fs::{read_link, File, OpenOptions};
 use std::io::{Stdin, Stdout};
-use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::{bail, Context, Result};
 use libc::{cfmakeraw, tcgetattr, tcsetattr, termios};
-use log::{error, info};
+use log::{error, info, warn};
 use machine_manager::machine::{PathInfo, PTY_PATH};
 use machine_manager::{
     config::{ChardevConfig, ChardevType},
+    event_loop::EventLoop,
     temp_cleaner::TempCleaner,
 };
 use util::file::clear_file;
@@ -53,14 +56,29 @@ pub enum ChardevStatus {
 
 type ReceFn = Option<Arc<dyn Fn(&[u8]) + Send + Sync>>;
 
+/// Listener of server-mode socket chardev.
+pub enum SocketListener {
+    Unix(UnixListener),
+    Tcp(TcpListener),
+}
+
+impl AsRawFd for SocketListener {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            SocketListener::Unix(listener) => listener.as_raw_fd(),
+            SocketListener::Tcp(listener) => listener.as_raw_fd(),
+        }
+    }
+}
+
 /// Character device structure.
 pub struct Chardev {
     /// Id of chardev.
     pub id: String,
     /// Type of backend device.
     pub backend: ChardevType,
-    /// UnixListener for socket-type chardev.
-    pub listener: Option<UnixListener>,
+    /// Listener for server-mode socket-type chardev.
+    pub listener: Option<SocketListener>,
     /// Chardev input.
     pub input: Option<Arc<Mutex<dyn CommunicatInInterface>>>,
     /// Chardev output.
@@ -75,6 +93,8 @@ pub struct Chardev {
     get_remain_space_size: Option<Arc<dyn Fn() -> usize + Send + Sync>>,
     /// Used to notify device the socket is opened or closed.
     dev: Option<Arc<Mutex<dyn ChardevNotifyDevice>>>,
+    /// Seconds to wait before reconnecting the client-mode socket, 0 means no reconnecting.
+    reconnect: u64,
 }
 
 impl Chardev {
@@ -90,11 +110,12 @@ impl Chardev {
             receive: None,
             get_remain_space_size: None,
             dev: None,
+            reconnect: chardev_cfg.reconnect,
         }
     }
 
     pub fn realize(&mut self) -> Result<()> {
-        match &self.backend {
+        match self.backend.clone() {
             ChardevType::Stdio => {
                 set_termi_raw_mode().with_context(|| "Failed to set terminal to raw mode")?;
                 self.input = Some(Arc::new(Mutex::new(std::io::stdin())));
@@ -119,25 +140,49 @@ impl Chardev {
                 server,
                 nowait,
             } => {
-                if !*server || !*nowait {
+                if !server {
+                    return self.realize_client();
+                }
+                if !nowait {
                     bail!(
-                        "Argument \'server\' and \'nowait\' are both required for chardev \'{}\'",
+                        "Argument \'nowait\' is required for server-mode chardev \'{}\'",
                         path
                     );
                 }
                 clear_file(path.clone())?;
                 let sock = UnixListener::bind(path.clone())
                     .with_context(|| format!("Failed to bind socket for chardev, path:{}", path))?;
-                self.listener = Some(sock);
+                self.listener = Some(SocketListener::Unix(sock));
                 // add file to temporary pool, so it could be cleaned when vm exit.
                 TempCleaner::add_path(path.clone());
-                limit_permission(path).with_context(|| {
+                limit_permission(&path).with_context(|| {
                     format!(
                         "Failed to change file permission for chardev, path:{}",
                         path
                     )
                 })?;
             }
+            ChardevType::TcpSocket {
+                host,
+                port,
+                server,
+                nowait,
+            } => {
+                if !server {
+                    return self.realize_client();
+                }
+                if !nowait {
+                    bail!(
+                        "Argument \'nowait\' is required for server-mode chardev \'{}:{}\'",
+                        host,
+                        port
+                    );
+                }
+                let sock = TcpListener::bind((host.as_str(), port)).with_context(|| {
+                    format!("Failed to bind socket for chardev, addr:{}:{}", host, port)
+                })?;
+                self.listener = Some(SocketListener::Tcp(sock));
+            }
             ChardevType::File(path) => {
                 let file = Arc::new(Mutex::new(
                     OpenOptions::new()
@@ -164,8 +209,88 @@ impl Chardev {
     }
 
     pub fn set_device(&mut self, dev: Arc<Mutex<dyn ChardevNotifyDevice>>) {
+        // Client-mode socket may have been connected in realize.
+        if self.backend.is_socket_client() && self.stream_fd.is_some() {
+            dev.lock().unwrap().chardev_notify(ChardevStatus::Open);
+        }
         self.dev = Some(dev.clone());
     }
+
+    /// Connect the client-mode socket. If it fails and reconnecting is enabled,
+    /// connecting is retried later in main loop.
+    fn realize_client(&mut self) -> Result<()> {
+        if let Err(e) = self.connect() {
+            if self.reconnect == 0 {
+                return Err(e);
+            }
+            warn!(
+                "Chardev {} failed to connect, retry in {}s: {:?}",
+                self.id, self.reconnect, e
+            );
+        }
+        Ok(())
+    }
+
+    fn connect(&mut self) -> Result<RawFd> {
+        match self.backend.clone() {
+            ChardevType::Socket { path, .. } => {
+                let stream = UnixStream::connect(&path).with_context(|| {
+                    format!("Failed to connect socket for chardev, path:{}", path)
+                })?;
+                Ok(self.set_stream(stream))
+            }
+            ChardevType::TcpSocket { host, port, .. } => {
+                let stream = TcpStream::connect((host.as_str(), port)).with_context(|| {
+                    format!(
+                        "Failed to connect socket for chardev, addr:{}:{}",
+                        host, port
+                    )
+                })?;
+                Ok(self.set_stream(stream))
+            }
+            _ => bail!("Chardev {} is not socket type", self.id),
+        }
+    }
+
+    fn accept(&mut self) -> Result<RawFd> {
+        match self.listener.as_ref() {
+            Some(SocketListener::Unix(listener)) => {
+                let (stream, _) = listener.accept()?;
+                Ok(self.set_stream(stream))
+            }
+            Some(SocketListener::Tcp(listener)) => {
+                let (stream, _) = listener.accept()?;
+                Ok(self.set_stream(stream))
+            }
+            None => bail!("Chardev {} has no listener", self.id),
+        }
+    }
+
+    /// Use the connected socket stream as input and output.
+    fn set_stream<S>(&mut self, stream: S) -> RawFd
+    where
+        S: CommunicatInInterface + CommunicatOutInterface + 'static,
+    {
+        let stream_fd = stream.as_raw_fd();
+        let stream_arc = Arc::new(Mutex::new(stream));
+        self.stream_fd = Some(stream_fd);
+        self.input = Some(stream_arc.clone());
+        self.output = Some(stream_arc);
+        stream_fd
+    }
+
+    /// Deliver data to the receiver as if it was read from the backend.
+    pub fn inject_input(&self, data: &[u8]) -> Result<()> {
+        if self.deactivated {
+            bail!("Chardev {} is deactivated", self.id);
+        }
+        let receive = self
+            .receive
+            .as_ref()
+            .with_context(|| format!("Chardev {} has no receiver", self.id))?;
+        receive(data);
+        Ok(())
+    }
 }
 
 fn set_pty_raw_mode() -> Result<(i32, PathBuf)> {
@@ -250,74 +375,129 @@ fn get_notifier_handler(
             }
             None
         }),
-        ChardevType::Socket { .. } => Rc::new(move |_, _| {
+        ChardevType::Socket { .. } | ChardevType::TcpSocket { .. } => Rc::new(move |_, _| {
             let mut locked_chardev = chardev.lock().unwrap();
             if locked_chardev.deactivated {
                 return None;
             }
-            let (stream, _) = locked_chardev.listener.as_ref().unwrap().accept().unwrap();
+            let stream_fd = match locked_chardev.accept() {
+                Ok(stream_fd) => stream_fd,
+                Err(e) => {
+                    error!("Chardev {} failed to accept: {:?}", locked_chardev.id, e);
+                    return None;
+                }
+            };
             let listener_fd = locked_chardev.listener.as_ref().unwrap().as_raw_fd();
-            let stream_fd = stream.as_raw_fd();
-            locked_chardev.stream_fd = Some(stream_fd);
-            let stream_arc = Arc::new(Mutex::new(stream));
-            locked_chardev.input = Some(stream_arc.clone());
-            locked_chardev.output = Some(stream_arc);
 
             if let Some(dev) = &locked_chardev.dev {
                 dev.lock().unwrap().chardev_notify(ChardevStatus::Open);
             }
 
-            let cloned_chardev = chardev.clone();
-            let inner_handler: Rc<NotifierCallback> = Rc::new(move |event, _| {
-                let mut locked_chardev = cloned_chardev.lock().unwrap();
-                if event == EventSet::IN {
-                    let get_remain_space_size = locked_chardev
-                        .get_remain_space_size
-                        .as_ref()
-                        .unwrap()
-                        .clone();
-                    drop(locked_chardev);
-                    let buff_size = get_remain_space_size();
-                    let locked_chardev = cloned_chardev.lock().unwrap();
-                    if locked_chardev.deactivated {
-                        return None;
-                    }
-                    let mut buffer = vec![0_u8; buff_size];
-                    if let Some(input) = locked_chardev.input.clone() {
-                        if let Ok(index) = input.lock().unwrap().chr_read_raw(&mut buffer) {
-                            locked_chardev.receive.as_ref().unwrap()(&mut buffer[..index]);
-                        } else {
-                            error!("Failed to read input data");
-                        }
-                    } else {
-                        error!("Failed to get chardev input fd");
-                    }
-                    None
-                } else if event & EventSet::HANG_UP == EventSet::HANG_UP {
-                    // Always allow disconnect even if has deactivated.
-                    if let Some(dev) = &locked_chardev.dev {
-                        dev.lock().unwrap().chardev_notify(ChardevStatus::Close);
-                    }
-                    locked_chardev.input = None;
-                    locked_chardev.output = None;
-                    locked_chardev.stream_fd = None;
-                    Some(gen_delete_notifiers(&[stream_fd]))
-                } else {
-                    None
-                }
-            });
-            Some(vec![EventNotifier::new(
-                NotifierOperation::AddShared,
+            Some(vec![get_stream_notifier(
+                chardev.clone(),
                 stream_fd,
                 Some(listener_fd),
-                EventSet::IN | EventSet::HANG_UP,
-                vec![inner_handler],
             )])
         }),
         ChardevType::File(_) => Rc::new(move |_, _| None),
     }
 }
 
+fn get_stream_notifier(
+    chardev: Arc<Mutex<Chardev>>,
+    stream_fd: RawFd,
+    listener_fd: Option<RawFd>,
+) -> EventNotifier {
+    let inner_handler: Rc<NotifierCallback> = Rc::new(move |event, _| {
+        let mut locked_chardev = chardev.lock().unwrap();
+        if event == EventSet::IN {
+            let get_remain_space_size = locked_chardev
+                .get_remain_space_size
+                .as_ref()
+                .unwrap()
+                .clone();
+            drop(locked_chardev);
+            let buff_size = get_remain_space_size();
+            let locked_chardev = chardev.lock().unwrap();
+            if locked_chardev.deactivated {
+                return None;
+            }
+            let mut buffer = vec![0_u8; buff_size];
+            if let Some(input) = locked_chardev.input.clone() {
+                if let Ok(index) = input.lock().unwrap().chr_read_raw(&mut buffer) {
+                    locked_chardev.receive.as_ref().unwrap()(&mut buffer[..index]);
+                } else {
+                    error!("Failed to read input data");
+                }
+            } else {
+                error!("Failed to get chardev input fd");
+            }
+            None
+        } else if event & EventSet::HANG_UP == EventSet::HANG_UP {
+            // Always allow disconnect even if has deactivated.
+            if let Some(dev) = &locked_chardev.dev {
+                dev.lock().unwrap().chardev_notify(ChardevStatus::Close);
+            }
+            locked_chardev.input = None;
+            locked_chardev.output = None;
+            locked_chardev.stream_fd = None;
+            if locked_chardev.backend.is_socket_client() && locked_chardev.reconnect != 0 {
+                info!(
+                    "Chardev {} is disconnected, reconnect in {}s",
+                    locked_chardev.id, locked_chardev.reconnect
+                );
+                schedule_reconnect(chardev.clone(), locked_chardev.reconnect);
+            }
+            Some(gen_delete_notifiers(&[stream_fd]))
+        } else {
+            None
+        }
+    });
+    EventNotifier::new(
+        NotifierOperation::AddShared,
+        stream_fd,
+        listener_fd,
+        EventSet::IN | EventSet::HANG_UP,
+        vec![inner_handler],
+    )
+}
+
+/// Try to connect the client-mode socket chardev again after `delay` seconds.
+fn schedule_reconnect(chardev: Arc<Mutex<Chardev>>, delay: u64) {
+    let reconnect = Box::new(move || reconnect_socket(chardev.clone()));
+    if let Some(ctx) = EventLoop::get_ctx(None) {
+        ctx.timer_add(reconnect, Duration::from_secs(delay));
+    }
+}
+
+fn reconnect_socket(chardev: Arc<Mutex<Chardev>>) {
+    let mut locked_chardev = chardev.lock().unwrap();
+    if locked_chardev.stream_fd.is_some() {
+        return;
+    }
+    let stream_fd = match locked_chardev.connect() {
+        Ok(stream_fd) => stream_fd,
+        Err(e) => {
+            warn!(
+                "Chardev {} failed to reconnect, retry in {}s: {:?}",
+                locked_chardev.id, locked_chardev.reconnect, e
+            );
+            schedule_reconnect(chardev.clone(), locked_chardev.reconnect);
+            return;
+        }
+    };
+    info!("Chardev {} is reconnected", locked_chardev.id);
+    if let Some(dev) = &locked_chardev.dev {
+        dev.lock().unwrap().chardev_notify(ChardevStatus::Open);
+    }
+    drop(locked_chardev);
+
+    let notifier = get_stream_notifier(chardev, stream_fd, None);
+    if let Err(e) = EventLoop::update_event(vec![notifier], None) {
+        error!("Failed to add reconnected chardev to main loop: {:?}", e);
+    }
+}
+
 impl EventNotifierHelper for Chardev {
     fn internal_notifiers(chardev: Arc<Mutex<Self>>) -> Vec<EventNotifier> {
         let mut notifiers = Vec::new();
@@ -335,7 +515,17 @@ impl EventNotifierHelper for Chardev {
                     ));
                 }
             }
-            ChardevType::Socket { .. } => {
+            ChardevType::Socket { .. } | ChardevType::TcpSocket { .. }
+                if backend.is_socket_client() =>
+            {
+                let locked_chardev = chardev.lock().unwrap();
+                if let Some(stream_fd) = locked_chardev.stream_fd {
+                    notifiers.push(get_stream_notifier(cloned_chardev, stream_fd, None));
+                } else if locked_chardev.reconnect != 0 {
+                    schedule_reconnect(cloned_chardev, locked_chardev.reconnect);
+                }
+            }
+            ChardevType::Socket { .. } | ChardevType::TcpSocket { .. } => {
                 if chardev.lock().unwrap().stream_fd.is_some() {
                     notifiers.push(EventNotifier::new(
                         NotifierOperation::Resume,
@@ -377,9 +567,11 @@ pub trait CommunicatInInterface: std::marker::Send + std::os::unix::io::AsRawFd
 pub trait CommunicatOutInterface: std::io::Write + std::marker::Send {}
 
 impl CommunicatInInterface for UnixStream {}
+impl CommunicatInInterface for TcpStream {}
 impl CommunicatInInterface for File {}
 impl CommunicatInInterface for Stdin {}
 
 impl CommunicatOutInterface for UnixStream {}
+impl CommunicatOutInterface for TcpStream {}
 impl CommunicatOutInterface for File {}
 impl CommunicatOutInterface for Stdout {}
@@ -1181,6 +1181,17 @@ pub trait FwCfgOps {
             .add_entry(key, None, None, bytes, false)
     }
 
+    /// Modify an entry of FwCfg device, with Vector content.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - FwCfgEntryType
+    /// * `data` - Raw data bytes of the entry to be modified
+    fn modify_data_entry(&mut self, key: FwCfgEntryType, data: Vec<u8>) -> Result<()> {
+        self.fw_cfg_common()
+            .update_entry_data((key as u16) & FW_CFG_ENTRY_MASK, data)
+    }
+
     /// Add a file entry to FwCfg device, with select callback function and
     /// write callback function.
     ///
@@ -1369,6 +1380,18 @@ mod test {
             cmdline.as_bytes().to_vec()
         );
 
+        let cmdline = "console=ttyAMA0 quiet".to_string();
+        fwcfg_common
+            .update_entry_data(
+                FwCfgEntryType::CmdlineData as u16,
+                cmdline.as_bytes().to_vec(),
+            )
+            .unwrap();
+        assert_eq!(
+            fwcfg_common.entries[FwCfgEntryType::CmdlineData as usize].data,
+            cmdline.as_bytes().to_vec()
+        );
+
         let boot_order = Vec::<u8>::new();
         fwcfg_common
             .add_entry(FwCfgEntryType::FileDir, None, None, boot_order, false)
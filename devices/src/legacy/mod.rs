@@ -33,7 +33,7 @@ mod pflash;
 mod pl011;
 #[cfg(target_arch = "aarch64")]
 mod pl031;
-#[cfg(all(not(target_env = "musl"), target_arch = "aarch64"))]
+#[cfg(not(target_env = "musl"))]
 mod ramfb;
 #[cfg(target_arch = "x86_64")]
 mod rtc;
@@ -53,7 +53,6 @@ pub use pflash::PFlash;
 pub use pl011::PL011;
 #[cfg(target_arch = "aarch64")]
 pub use pl031::{PL031, RTC_CR, RTC_DR, RTC_IMSC, RTC_LR};
-#[cfg(target_arch = "aarch64")]
 #[cfg(not(target_env = "musl"))]
 pub use ramfb::Ramfb;
 pub use serial::{Serial, SERIAL_ADDR};
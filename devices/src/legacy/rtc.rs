@@ -40,12 +40,15 @@ const RTC_REG_A: u8 = 0x0A;
 const RTC_REG_B: u8 = 0x0B;
 const RTC_REG_C: u8 = 0x0C;
 const RTC_REG_D: u8 = 0x0D;
+const RTC_SHUTDOWN_STATUS: u8 = 0x0F;
 const RTC_CENTURY_BCD: u8 = 0x32;
 
 // Update in progress (UIP) bit.
 const REG_A_UIP: u8 = 0x80;
 // UIP bit held for last 244 us of every second.
 const UIP_HOLD_LENGTH: u64 = 8 * NANOSECONDS_PER_SECOND / 32768;
+// Shutdown status which tells firmware to resume from S3.
+const SHUTDOWN_STATUS_S3_RESUME: u8 = 0xFE;
 
 // Index of memory data in RTC static RAM.
 // 0x15/0x16 stores low/high byte below 1MB, range is [0, 640KB].
@@ -186,6 +189,11 @@ impl RTC {
         }
     }
 
+    /// Set shutdown status in CMOS, so that firmware resumes guest from S3 rather than boots it.
+    pub fn set_s3_resume(&mut self) {
+        self.cmos_data[RTC_SHUTDOWN_STATUS as usize] = SHUTDOWN_STATUS_S3_RESUME;
+    }
+
     fn init_rtc_reg(&mut self) {
         // Set Time frequency divider and Rate selection frequency in Register-A.
         // Bits 6-4 = Time frequency divider (010 = 32.768KHz).
@@ -467,6 +467,7 @@ mod test {
         let chardev_cfg = ChardevConfig {
             id: "chardev".to_string(),
             backend: ChardevType::Stdio,
+            reconnect: 0,
         };
         let mut usart = Serial::new(SerialConfig {
             chardev: chardev_cfg.clone(),
@@ -522,6 +523,7 @@ mod test {
         let chardev_cfg = ChardevConfig {
             id: "chardev".to_string(),
             backend: ChardevType::Stdio,
+            reconnect: 0,
         };
         let mut usart = Serial::new(SerialConfig {
             chardev: chardev_cfg,
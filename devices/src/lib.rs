@@ -30,6 +30,8 @@ pub use interrupt_controller::{
     ICGICConfig, ICGICv2Config, ICGICv3Config, InterruptController, InterruptError as IntCtrlErrs,
     GIC_IRQ_INTERNAL, GIC_IRQ_MAX,
 };
+#[cfg(target_arch = "x86_64")]
+pub use interrupt_controller::{Ioapic, IOAPIC_REGION_SIZE};
 pub use legacy::error::LegacyError as LegacyErrs;
 pub use scsi::bus as ScsiBus;
 pub use scsi::disk as ScsiDisk;
@@ -0,0 +1,518 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::sync::{
+    atomic::{AtomicU16, Ordering},
+    Arc, Mutex, Weak,
+};
+use std::time::Duration;
+
+use anyhow::bail;
+use log::{error, info, warn};
+use vmm_sys_util::eventfd::EventFd;
+
+use address_space::{GuestAddress, Region, RegionOps};
+use machine_manager::event_loop::EventLoop;
+use pci::{
+    config::{
+        PciConfig, RegionType, DEVICE_ID, PCI_CONFIG_SPACE_SIZE, REVISION_ID, SUB_CLASS_CODE,
+        VENDOR_ID,
+    },
+    le_read_u16, le_write_u16, ranges_overlap, PciBus, PciDevOps,
+};
+
+const PCI_VENDOR_ID_INTEL: u16 = 0x8086;
+const PCI_DEVICE_ID_ESB: u16 = 0x25ab;
+const PCI_CLASS_SYSTEM_OTHER: u16 = 0x0880;
+
+const PCI_BAR_MAX_ESB: u8 = 1;
+const ESB_REG_BAR_SIZE: u64 = 0x10;
+
+/// Watchdog configuration register in PCI config space.
+const ESB_CONFIG_REG: usize = 0x60;
+/// Watchdog lock register in PCI config space.
+const ESB_LOCK_REG: usize = 0x68;
+
+/// Bits of the configuration register.
+const ESB_WDT_REBOOT: u16 = 0x01 << 5;
+const ESB_WDT_FREQ: u16 = 0x01 << 2;
+const ESB_WDT_INTTYPE: u16 = 0x03;
+
+/// Bits of the lock register.
+const ESB_WDT_FUNC: u8 = 0x01 << 2;
+const ESB_WDT_ENABLE: u8 = 0x01 << 1;
+const ESB_WDT_LOCK: u8 = 0x01;
+
+/// Registers in BAR0.
+const ESB_TIMER1_REG: u64 = 0x00;
+const ESB_TIMER2_REG: u64 = 0x04;
+const ESB_GINTSR_REG: u64 = 0x08;
+const ESB_RELOAD_REG: u64 = 0x0c;
+
+/// Bits of the reload register.
+const ESB_WDT_RELOAD: u16 = 0x01 << 8;
+const ESB_WDT_TIMEOUT: u16 = 0x01 << 9;
+/// Value read from the reload register besides the timeout bit.
+const ESB_RELOAD_DEFAULT: u16 = 0x1000;
+
+/// Magic values to unlock the registers in BAR0.
+const ESB_UNLOCK1: u16 = 0x80;
+const ESB_UNLOCK2: u16 = 0x86;
+
+/// Preload value is 20 bits wide.
+const ESB_PRELOAD_MASK: u32 = 0xfffff;
+/// One clock tick of the 33MHz PCI clock, in nanoseconds.
+const ESB_CLOCK_TICK_NS: u64 = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WatchdogStage {
+    First,
+    Second,
+}
+
+/// Runtime state of the watchdog.
+struct EsbState {
+    /// Whether the watchdog fires the expiry action when stage 2 times out.
+    reboot_enabled: bool,
+    /// Whether the watchdog runs at 1MHz, otherwise at 1KHz.
+    clock_scale: bool,
+    /// Interrupt type of stage 1, only "none" is supported.
+    int_type: u16,
+    /// Whether the watchdog is free running, otherwise it stops after stage 2.
+    free_run: bool,
+    /// Whether the lock register is locked.
+    locked: bool,
+    /// Whether the watchdog is enabled.
+    enabled: bool,
+    /// Unlock sequence of the registers in BAR0.
+    unlock_state: u8,
+    /// Whether the last timeout caused a reboot.
+    previous_reboot_flag: bool,
+    /// Preload value of stage 1.
+    timer1_preload: u32,
+    /// Preload value of stage 2.
+    timer2_preload: u32,
+    stage: WatchdogStage,
+    /// Timer of the current stage.
+    timer_id: Option<u64>,
+    /// Eventfd to notify the machine that the watchdog has expired.
+    watchdog_evt: Arc<EventFd>,
+}
+
+impl EsbState {
+    fn new(watchdog_evt: Arc<EventFd>) -> Self {
+        Self {
+            reboot_enabled: true,
+            clock_scale: false,
+            int_type: 0,
+            free_run: false,
+            locked: false,
+            enabled: false,
+            unlock_state: 0,
+            previous_reboot_flag: false,
+            timer1_preload: ESB_PRELOAD_MASK,
+            timer2_preload: ESB_PRELOAD_MASK,
+            stage: WatchdogStage::First,
+            timer_id: None,
+            watchdog_evt,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.disable_timer();
+        let watchdog_evt = self.watchdog_evt.clone();
+        *self = Self::new(watchdog_evt);
+    }
+
+    fn timeout(&self) -> Duration {
+        let preload = match self.stage {
+            WatchdogStage::First => self.timer1_preload,
+            WatchdogStage::Second => self.timer2_preload,
+        } as u64;
+        // The preload value is compared against bits [34:15] of the counter
+        // at 1KHz, and bits [24:5] at 1MHz.
+        let ticks = if self.clock_scale {
+            preload << 5
+        } else {
+            preload << 15
+        };
+        Duration::from_nanos(ticks * ESB_CLOCK_TICK_NS)
+    }
+
+    fn disable_timer(&mut self) {
+        if let Some(id) = self.timer_id.take() {
+            if let Some(ctx) = EventLoop::get_ctx(None) {
+                ctx.timer_del(id);
+            }
+        }
+    }
+
+    /// Restart the timer of the current stage. If the watchdog is disabled,
+    /// only the existing timer is removed.
+    fn restart_timer(state: &Arc<Mutex<EsbState>>) {
+        let mut locked_state = state.lock().unwrap();
+        locked_state.disable_timer();
+        if !locked_state.enabled {
+            return;
+        }
+
+        let timeout = locked_state.timeout();
+        let cloned_state = state.clone();
+        let expire_func = Box::new(move || {
+            EsbState::timer_expired(&cloned_state);
+        });
+        if let Some(ctx) = EventLoop::get_ctx(None) {
+            locked_state.timer_id = Some(ctx.timer_add(expire_func, timeout));
+        }
+    }
+
+    fn timer_expired(state: &Arc<Mutex<EsbState>>) {
+        let mut locked_state = state.lock().unwrap();
+        // The timer has fired and its id may be reused by others.
+        locked_state.timer_id = None;
+        match locked_state.stage {
+            WatchdogStage::First => {
+                // Interrupt of stage 1 is not supported, move on to stage 2.
+                if locked_state.int_type != 0 {
+                    warn!(
+                        "i6300esb: interrupt type {} is not supported",
+                        locked_state.int_type
+                    );
+                }
+                locked_state.stage = WatchdogStage::Second;
+                drop(locked_state);
+                EsbState::restart_timer(state);
+            }
+            WatchdogStage::Second => {
+                if locked_state.reboot_enabled {
+                    info!("i6300esb: watchdog timer expired");
+                    locked_state.previous_reboot_flag = true;
+                    if let Err(e) = locked_state.watchdog_evt.write(1) {
+                        error!("Failed to notify watchdog expiry: {:?}", e);
+                    }
+                }
+                if locked_state.free_run {
+                    locked_state.stage = WatchdogStage::First;
+                    drop(locked_state);
+                    EsbState::restart_timer(state);
+                }
+            }
+        }
+    }
+
+    fn read_bar(&self, data: &mut [u8], offset: u64) -> bool {
+        data.fill(0);
+        if offset == ESB_RELOAD_REG && data.len() >= 2 {
+            let mut val = ESB_RELOAD_DEFAULT;
+            if self.previous_reboot_flag {
+                val |= ESB_WDT_TIMEOUT;
+            }
+            data[0..2].copy_from_slice(&val.to_le_bytes());
+        }
+        true
+    }
+
+    fn write_bar(state: &Arc<Mutex<EsbState>>, data: &[u8], offset: u64) -> bool {
+        let mut locked_state = state.lock().unwrap();
+        match (offset, data.len()) {
+            (ESB_RELOAD_REG, 2) => {
+                let val = u16::from_le_bytes([data[0], data[1]]);
+                if locked_state.unlock_state == 1 && val == ESB_UNLOCK2 {
+                    locked_state.unlock_state = 2;
+                } else if val == ESB_UNLOCK1 {
+                    locked_state.unlock_state = 1;
+                } else if locked_state.unlock_state == 2 {
+                    if val & ESB_WDT_RELOAD != 0 {
+                        locked_state.stage = WatchdogStage::First;
+                        drop(locked_state);
+                        EsbState::restart_timer(state);
+                        locked_state = state.lock().unwrap();
+                    }
+                    if val & ESB_WDT_TIMEOUT != 0 {
+                        locked_state.previous_reboot_flag = false;
+                    }
+                    locked_state.unlock_state = 0;
+                }
+            }
+            (ESB_TIMER1_REG, 4) | (ESB_TIMER2_REG, 4) => {
+                if locked_state.unlock_state == 2 {
+                    let val = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+                    if offset == ESB_TIMER1_REG {
+                        locked_state.timer1_preload = val & ESB_PRELOAD_MASK;
+                    } else {
+                        locked_state.timer2_preload = val & ESB_PRELOAD_MASK;
+                    }
+                    locked_state.unlock_state = 0;
+                }
+            }
+            (ESB_GINTSR_REG, _) => {}
+            _ => {
+                warn!(
+                    "i6300esb: unsupported write to offset {:#x}, size {}",
+                    offset,
+                    data.len()
+                );
+            }
+        }
+        true
+    }
+}
+
+/// Intel 6300ESB watchdog timer device structure.
+pub struct I6300Esb {
+    config: PciConfig,
+    devfn: u8,
+    dev_id: Arc<AtomicU16>,
+    name: String,
+    parent_bus: Weak<Mutex<PciBus>>,
+    state: Arc<Mutex<EsbState>>,
+}
+
+impl I6300Esb {
+    pub fn new(
+        name: String,
+        devfn: u8,
+        parent_bus: Weak<Mutex<PciBus>>,
+        watchdog_evt: Arc<EventFd>,
+    ) -> Self {
+        Self {
+            config: PciConfig::new(PCI_CONFIG_SPACE_SIZE, PCI_BAR_MAX_ESB),
+            devfn,
+            dev_id: Arc::new(AtomicU16::new(0)),
+            name,
+            parent_bus,
+            state: Arc::new(Mutex::new(EsbState::new(watchdog_evt))),
+        }
+    }
+
+    fn register_bars(&mut self) -> pci::Result<()> {
+        let read_state = self.state.clone();
+        let reg_read = move |data: &mut [u8], _: GuestAddress, offset: u64| -> bool {
+            read_state.lock().unwrap().read_bar(data, offset)
+        };
+        let write_state = self.state.clone();
+        let reg_write = move |data: &[u8], _: GuestAddress, offset: u64| -> bool {
+            EsbState::write_bar(&write_state, data, offset)
+        };
+        let reg_region_ops = RegionOps {
+            read: Arc::new(reg_read),
+            write: Arc::new(reg_write),
+        };
+
+        self.config.register_bar(
+            0,
+            Region::init_io_region(ESB_REG_BAR_SIZE, reg_region_ops, "I6300EsbIo"),
+            RegionType::Mem32Bit,
+            false,
+            ESB_REG_BAR_SIZE,
+        )
+    }
+
+    /// Update the watchdog state after the guest writes the watchdog registers
+    /// in PCI config space.
+    fn update_config_regs(&mut self, old_lock: u8) {
+        let cfg = le_read_u16(&self.config.config, ESB_CONFIG_REG).unwrap();
+        let mut locked_state = self.state.lock().unwrap();
+        locked_state.reboot_enabled = cfg & ESB_WDT_REBOOT == 0;
+        locked_state.clock_scale = cfg & ESB_WDT_FREQ != 0;
+        locked_state.int_type = cfg & ESB_WDT_INTTYPE;
+
+        if locked_state.locked {
+            // Lock register can not be changed until the device is reset.
+            self.config.config[ESB_LOCK_REG] = old_lock;
+            return;
+        }
+        let lock = self.config.config[ESB_LOCK_REG];
+        locked_state.free_run = lock & ESB_WDT_FUNC != 0;
+        locked_state.locked = lock & ESB_WDT_LOCK != 0;
+        let enabled = lock & ESB_WDT_ENABLE != 0;
+        if enabled != locked_state.enabled {
+            locked_state.enabled = enabled;
+            locked_state.stage = WatchdogStage::First;
+            drop(locked_state);
+            EsbState::restart_timer(&self.state);
+        }
+    }
+}
+
+impl PciDevOps for I6300Esb {
+    fn realize(mut self) -> pci::Result<()> {
+        self.init_write_mask()?;
+        self.init_write_clear_mask()?;
+        le_write_u16(
+            &mut self.config.config,
+            VENDOR_ID as usize,
+            PCI_VENDOR_ID_INTEL,
+        )?;
+        le_write_u16(
+            &mut self.config.config,
+            DEVICE_ID as usize,
+            PCI_DEVICE_ID_ESB,
+        )?;
+        self.config.config[REVISION_ID] = 0;
+        le_write_u16(
+            &mut self.config.config,
+            SUB_CLASS_CODE as usize,
+            PCI_CLASS_SYSTEM_OTHER,
+        )?;
+
+        self.register_bars()?;
+
+        // Attach to the PCI bus.
+        let pci_bus = self.parent_bus.upgrade().unwrap();
+        let mut locked_pci_bus = pci_bus.lock().unwrap();
+        let pci_device = locked_pci_bus.devices.get(&self.devfn);
+        match pci_device {
+            Some(device) => bail!(
+                "Devfn {:?} has been used by {:?}",
+                &self.devfn,
+                device.lock().unwrap().name()
+            ),
+            None => locked_pci_bus
+                .devices
+                .insert(self.devfn, Arc::new(Mutex::new(self))),
+        };
+        Ok(())
+    }
+
+    fn init_write_mask(&mut self) -> pci::Result<()> {
+        self.config.init_common_write_mask()?;
+        le_write_u16(
+            &mut self.config.write_mask,
+            ESB_CONFIG_REG,
+            ESB_WDT_REBOOT | ESB_WDT_FREQ | ESB_WDT_INTTYPE,
+        )?;
+        self.config.write_mask[ESB_LOCK_REG] = ESB_WDT_FUNC | ESB_WDT_ENABLE | ESB_WDT_LOCK;
+        Ok(())
+    }
+
+    fn init_write_clear_mask(&mut self) -> pci::Result<()> {
+        self.config.init_common_write_clear_mask()
+    }
+
+    fn read_config(&mut self, offset: usize, data: &mut [u8]) {
+        self.config.read(offset, data);
+    }
+
+    fn write_config(&mut self, offset: usize, data: &[u8]) {
+        let parent_bus = self.parent_bus.upgrade().unwrap();
+        let locked_parent_bus = parent_bus.lock().unwrap();
+
+        let old_lock = self.config.config[ESB_LOCK_REG];
+        self.config.write(
+            offset,
+            data,
+            self.dev_id.load(Ordering::Acquire),
+            #[cfg(target_arch = "x86_64")]
+            Some(&locked_parent_bus.io_region),
+            Some(&locked_parent_bus.mem_region),
+        );
+        drop(locked_parent_bus);
+
+        if ranges_overlap(offset, data.len(), ESB_CONFIG_REG, 2)
+            || ranges_overlap(offset, data.len(), ESB_LOCK_REG, 1)
+        {
+            self.update_config_regs(old_lock);
+        }
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_pci_config(&self) -> Option<&PciConfig> {
+        Some(&self.config)
+    }
+
+    fn reset(&mut self, _reset_child_device: bool) -> pci::Result<()> {
+        self.state.lock().unwrap().reset();
+        le_write_u16(&mut self.config.config, ESB_CONFIG_REG, 0)?;
+        self.config.config[ESB_LOCK_REG] = 0;
+        self.config.reset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_i6300esb_bar_rw() {
+        let evt = Arc::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        let state = Arc::new(Mutex::new(EsbState::new(evt)));
+
+        // Preload registers can not be written without the unlock sequence.
+        assert!(EsbState::write_bar(
+            &state,
+            &0x100_u32.to_le_bytes(),
+            ESB_TIMER1_REG
+        ));
+        assert_eq!(state.lock().unwrap().timer1_preload, ESB_PRELOAD_MASK);
+
+        assert!(EsbState::write_bar(
+            &state,
+            &ESB_UNLOCK1.to_le_bytes(),
+            ESB_RELOAD_REG
+        ));
+        assert!(EsbState::write_bar(
+            &state,
+            &ESB_UNLOCK2.to_le_bytes(),
+            ESB_RELOAD_REG
+        ));
+        assert!(EsbState::write_bar(
+            &state,
+            &0xfff_ffff_u32.to_le_bytes(),
+            ESB_TIMER2_REG
+        ));
+        let locked_state = state.lock().unwrap();
+        assert_eq!(locked_state.timer2_preload, ESB_PRELOAD_MASK);
+        assert_eq!(locked_state.unlock_state, 0);
+        drop(locked_state);
+
+        // Previous reboot flag is reported and cleared by the reload register.
+        state.lock().unwrap().previous_reboot_flag = true;
+        let mut data = [0_u8; 2];
+        state.lock().unwrap().read_bar(&mut data, ESB_RELOAD_REG);
+        assert_ne!(u16::from_le_bytes(data) & ESB_WDT_TIMEOUT, 0);
+        EsbState::write_bar(&state, &ESB_UNLOCK1.to_le_bytes(), ESB_RELOAD_REG);
+        EsbState::write_bar(&state, &ESB_UNLOCK2.to_le_bytes(), ESB_RELOAD_REG);
+        EsbState::write_bar(&state, &ESB_WDT_TIMEOUT.to_le_bytes(), ESB_RELOAD_REG);
+        assert!(!state.lock().unwrap().previous_reboot_flag);
+    }
+
+    #[test]
+    fn test_i6300esb_timeout() {
+        let evt = Arc::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        let mut state = EsbState::new(evt.clone());
+        state.timer1_preload = 1;
+        assert_eq!(
+            state.timeout(),
+            Duration::from_nanos((1 << 15) * ESB_CLOCK_TICK_NS)
+        );
+        state.clock_scale = true;
+        assert_eq!(
+            state.timeout(),
+            Duration::from_nanos((1 << 5) * ESB_CLOCK_TICK_NS)
+        );
+
+        // Stage 2 expiry notifies the machine if reboot is enabled.
+        let state = Arc::new(Mutex::new(state));
+        state.lock().unwrap().stage = WatchdogStage::Second;
+        EsbState::timer_expired(&state);
+        assert_eq!(evt.read().unwrap(), 1);
+        assert!(state.lock().unwrap().previous_reboot_flag);
+
+        state.lock().unwrap().reboot_enabled = false;
+        EsbState::timer_expired(&state);
+        assert!(evt.read().is_err());
+    }
+}
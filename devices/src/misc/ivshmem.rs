@@ -161,4 +161,8 @@ impl PciDevOps for Ivshmem {
     fn name(&self) -> String {
         self.name.clone()
     }
+
+    fn get_pci_config(&self) -> Option<&PciConfig> {
+        Some(&self.config)
+    }
 }
@@ -10,7 +10,10 @@
 // NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
 // See the Mulan PSL v2 for more details.
 
+pub mod i6300esb;
 #[cfg(not(target_env = "musl"))]
 mod ivshmem;
+pub mod pvpanic;
 #[cfg(not(target_env = "musl"))]
 pub mod scream;
+pub mod tpm;
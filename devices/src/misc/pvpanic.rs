@@ -0,0 +1,157 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use log::{error, info};
+use vmm_sys_util::eventfd::EventFd;
+
+use acpi::{
+    AmlBuilder, AmlDevice, AmlInteger, AmlNameDecl, AmlResTemplate, AmlScopeBuilder, AmlString,
+};
+#[cfg(target_arch = "x86_64")]
+use acpi::{AmlIoDecode, AmlIoResource};
+#[cfg(target_arch = "aarch64")]
+use acpi::{AmlMemory32Fixed, AmlReadAndWrite};
+use address_space::GuestAddress;
+use sysbus::{SysBus, SysBusDevOps, SysBusDevType, SysRes};
+
+/// The I/O port of pvpanic device on x86_64.
+#[cfg(target_arch = "x86_64")]
+pub const PVPANIC_PORT: u64 = 0x505;
+/// Size of the register region of pvpanic device.
+pub const PVPANIC_REG_SIZE: u64 = 2;
+
+/// Guest kernel has panicked.
+const PVPANIC_PANICKED: u8 = 1 << 0;
+/// Guest kernel has loaded the crash kernel, kdump will handle the panic.
+const PVPANIC_CRASH_LOADED: u8 = 1 << 1;
+/// Events supported by the device, which are read by guest.
+const PVPANIC_EVENTS: u8 = PVPANIC_PANICKED | PVPANIC_CRASH_LOADED;
+
+/// Paravirtualized panic device, by which guest kernel reports panic to VMM.
+pub struct PvPanic {
+    /// Eventfd to notify the machine that guest has panicked.
+    panic_evt: Arc<EventFd>,
+    /// System resource.
+    res: SysRes,
+}
+
+impl PvPanic {
+    pub fn new(panic_evt: Arc<EventFd>) -> Self {
+        Self {
+            panic_evt,
+            res: SysRes::default(),
+        }
+    }
+
+    pub fn realize(
+        mut self,
+        sysbus: &mut SysBus,
+        region_base: u64,
+        region_size: u64,
+    ) -> Result<()> {
+        self.set_sys_resource(sysbus, region_base, region_size)
+            .with_context(|| "Failed to allocate system resource for pvpanic.")?;
+
+        let dev = Arc::new(Mutex::new(self));
+        sysbus.attach_device(&dev, region_base, region_size, "PvPanic")?;
+        Ok(())
+    }
+}
+
+impl SysBusDevOps for PvPanic {
+    fn read(&mut self, data: &mut [u8], _base: GuestAddress, _offset: u64) -> bool {
+        if data.is_empty() {
+            return false;
+        }
+        data.fill(0);
+        data[0] = PVPANIC_EVENTS;
+        true
+    }
+
+    fn write(&mut self, data: &[u8], _base: GuestAddress, _offset: u64) -> bool {
+        if data.is_empty() {
+            return false;
+        }
+        let event = data[0] & PVPANIC_EVENTS;
+        if event & PVPANIC_CRASH_LOADED != 0 {
+            info!("Guest has loaded crash kernel");
+        }
+        if event & PVPANIC_PANICKED != 0 {
+            error!("Guest has panicked");
+            if let Err(e) = self.panic_evt.write(1) {
+                error!("Failed to notify guest panic: {:?}", e);
+            }
+        }
+        true
+    }
+
+    fn get_sys_resource(&mut self) -> Option<&mut SysRes> {
+        Some(&mut self.res)
+    }
+
+    fn get_type(&self) -> SysBusDevType {
+        SysBusDevType::PvPanic
+    }
+}
+
+impl AmlBuilder for PvPanic {
+    fn aml_bytes(&self) -> Vec<u8> {
+        let mut acpi_dev = AmlDevice::new("PEVT");
+        acpi_dev.append_child(AmlNameDecl::new("_HID", AmlString("QEMU0001".to_string())));
+        acpi_dev.append_child(AmlNameDecl::new("_UID", AmlInteger(0)));
+        acpi_dev.append_child(AmlNameDecl::new("_STA", AmlInteger(0xB)));
+
+        let mut res = AmlResTemplate::new();
+        #[cfg(target_arch = "x86_64")]
+        res.append_child(AmlIoResource::new(
+            AmlIoDecode::Decode16,
+            self.res.region_base as u16,
+            self.res.region_base as u16,
+            0x01,
+            self.res.region_size as u8,
+        ));
+        #[cfg(target_arch = "aarch64")]
+        res.append_child(AmlMemory32Fixed::new(
+            AmlReadAndWrite::ReadWrite,
+            self.res.region_base as u32,
+            self.res.region_size as u32,
+        ));
+        acpi_dev.append_child(AmlNameDecl::new("_CRS", res));
+
+        acpi_dev.aml_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pvpanic_rw() {
+        let panic_evt = Arc::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        let mut pvpanic = PvPanic::new(panic_evt.clone());
+
+        let mut data = [0_u8; 1];
+        assert!(pvpanic.read(&mut data, GuestAddress(0), 0));
+        assert_eq!(data[0], PVPANIC_EVENTS);
+
+        // Crash loaded does not notify the machine.
+        assert!(pvpanic.write(&[PVPANIC_CRASH_LOADED], GuestAddress(0), 0));
+        assert!(panic_evt.read().is_err());
+
+        assert!(pvpanic.write(&[PVPANIC_PANICKED], GuestAddress(0), 0));
+        assert_eq!(panic_evt.read().unwrap(), 1);
+    }
+}
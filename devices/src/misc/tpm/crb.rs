@@ -0,0 +1,372 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use log::{error, warn};
+
+use super::emulator::TpmEmulator;
+use acpi::{
+    AmlBuilder, AmlDevice, AmlInteger, AmlMemory32Fixed, AmlNameDecl, AmlReadAndWrite,
+    AmlResTemplate, AmlScopeBuilder, AmlString,
+};
+use address_space::GuestAddress;
+use sysbus::{SysBus, SysBusDevOps, SysBusDevType, SysRes};
+
+/// Base address of TPM CRB registers on x86_64.
+#[cfg(target_arch = "x86_64")]
+pub const TPM_CRB_ADDR_BASE: u64 = 0xFED4_0000;
+/// Size of TPM CRB registers, only locality 0 is supported.
+pub const TPM_CRB_ADDR_SIZE: u64 = 0x1000;
+/// Offset of the CRB control area, which is reported in ACPI TPM2 table.
+pub const TPM_CRB_CTRL_AREA_OFFSET: u64 = CRB_CTRL_REQ;
+
+/// Registers of CRB interface.
+const CRB_LOC_STATE: u64 = 0x00;
+const CRB_LOC_CTRL: u64 = 0x08;
+const CRB_LOC_STS: u64 = 0x0C;
+const CRB_INTF_ID: u64 = 0x30;
+const CRB_INTF_ID2: u64 = 0x34;
+const CRB_CTRL_REQ: u64 = 0x40;
+const CRB_CTRL_STS: u64 = 0x44;
+const CRB_CTRL_CANCEL: u64 = 0x48;
+const CRB_CTRL_START: u64 = 0x4C;
+const CRB_CTRL_CMD_SIZE: u64 = 0x58;
+const CRB_CTRL_CMD_LADDR: u64 = 0x5C;
+const CRB_CTRL_CMD_HADDR: u64 = 0x60;
+const CRB_CTRL_RSP_SIZE: u64 = 0x64;
+const CRB_CTRL_RSP_ADDR: u64 = 0x68;
+const CRB_DATA_BUFFER: u64 = 0x80;
+const CRB_DATA_BUFFER_SIZE: u64 = TPM_CRB_ADDR_SIZE - CRB_DATA_BUFFER;
+
+/// Bits of LOC_STATE.
+const LOC_STATE_TPM_ESTABLISHED: u32 = 1;
+const LOC_STATE_LOC_ASSIGNED: u32 = 1 << 1;
+const LOC_STATE_REG_VALID_STS: u32 = 1 << 7;
+/// Bits of LOC_CTRL.
+const LOC_CTRL_REQUEST_ACCESS: u32 = 1;
+const LOC_CTRL_RELINQUISH: u32 = 1 << 1;
+/// Bits of LOC_STS.
+const LOC_STS_GRANTED: u32 = 1;
+/// Bits of CTRL_REQ.
+const CTRL_REQ_CMD_READY: u32 = 1;
+const CTRL_REQ_GO_IDLE: u32 = 1 << 1;
+/// Bits of CTRL_STS.
+const CTRL_STS_TPM_IDLE: u32 = 1 << 1;
+/// Value of CTRL_START and CTRL_CANCEL to start and cancel a command.
+const CRB_START_INVOKE: u32 = 1;
+const CRB_CANCEL_INVOKE: u32 = 1;
+
+/// InterfaceType: CRB, InterfaceVersion: CRB, DataTransferSizeSupport: 64 bytes,
+/// CapCRB: supported, InterfaceSelector: CRB.
+const CRB_INTF_ID_VALUE: u32 = 0x1 | (0x1 << 4) | (0x3 << 11) | (0x1 << 14) | (0x1 << 17);
+/// Vendor id and device id of the interface.
+const CRB_INTF_ID2_VALUE: u32 = 0x1014 | (0x1 << 16);
+
+/// TPM 2.0 device with Command Response Buffer interface.
+pub struct TpmCrb {
+    /// Register space, including the data buffer.
+    regs: Vec<u8>,
+    /// Backend of TPM.
+    backend: TpmEmulator,
+    /// System resource.
+    res: SysRes,
+}
+
+impl TpmCrb {
+    pub fn new(backend: TpmEmulator) -> Self {
+        let mut crb = Self {
+            regs: vec![0; TPM_CRB_ADDR_SIZE as usize],
+            backend,
+            res: SysRes::default(),
+        };
+        crb.reset_regs();
+        crb
+    }
+
+    pub fn realize(
+        mut self,
+        sysbus: &mut SysBus,
+        region_base: u64,
+        region_size: u64,
+    ) -> Result<()> {
+        self.backend
+            .realize()
+            .with_context(|| "Failed to realize TPM backend")?;
+        self.set_sys_resource(sysbus, region_base, region_size)
+            .with_context(|| "Failed to allocate system resource for TPM.")?;
+        self.reset_regs();
+
+        let dev = Arc::new(Mutex::new(self));
+        sysbus.attach_device(&dev, region_base, region_size, "TpmCrb")?;
+        Ok(())
+    }
+
+    fn reset_regs(&mut self) {
+        self.regs.fill(0);
+        let base = self.res.region_base;
+        self.set_reg(
+            CRB_LOC_STATE,
+            LOC_STATE_TPM_ESTABLISHED | LOC_STATE_REG_VALID_STS,
+        );
+        self.set_reg(CRB_INTF_ID, CRB_INTF_ID_VALUE);
+        self.set_reg(CRB_INTF_ID2, CRB_INTF_ID2_VALUE);
+        self.set_reg(CRB_CTRL_STS, CTRL_STS_TPM_IDLE);
+        self.set_reg(CRB_CTRL_CMD_SIZE, CRB_DATA_BUFFER_SIZE as u32);
+        self.set_reg(CRB_CTRL_CMD_LADDR, (base + CRB_DATA_BUFFER) as u32);
+        self.set_reg(CRB_CTRL_CMD_HADDR, ((base + CRB_DATA_BUFFER) >> 32) as u32);
+        self.set_reg(CRB_CTRL_RSP_SIZE, CRB_DATA_BUFFER_SIZE as u32);
+        self.regs[CRB_CTRL_RSP_ADDR as usize..CRB_CTRL_RSP_ADDR as usize + 8]
+            .copy_from_slice(&(base + CRB_DATA_BUFFER).to_le_bytes());
+    }
+
+    fn get_reg(&self, offset: u64) -> u32 {
+        let off = offset as usize;
+        u32::from_le_bytes([
+            self.regs[off],
+            self.regs[off + 1],
+            self.regs[off + 2],
+            self.regs[off + 3],
+        ])
+    }
+
+    fn set_reg(&mut self, offset: u64, val: u32) {
+        let off = offset as usize;
+        self.regs[off..off + 4].copy_from_slice(&val.to_le_bytes());
+    }
+
+    fn locality_assigned(&self) -> bool {
+        self.get_reg(CRB_LOC_STATE) & LOC_STATE_LOC_ASSIGNED != 0
+    }
+
+    fn execute_cmd(&mut self) {
+        let buf_start = CRB_DATA_BUFFER as usize;
+        let cmd_len = u32::from_be_bytes([
+            self.regs[buf_start + 2],
+            self.regs[buf_start + 3],
+            self.regs[buf_start + 4],
+            self.regs[buf_start + 5],
+        ]) as usize;
+        let cmd_len = cmd_len.min(CRB_DATA_BUFFER_SIZE as usize);
+        let cmd = self.regs[buf_start..buf_start + cmd_len].to_vec();
+
+        let mut resp = vec![0_u8; CRB_DATA_BUFFER_SIZE as usize];
+        let resp_len = self.backend.handle_request(0, &cmd, &mut resp);
+        self.regs[buf_start..buf_start + resp_len].copy_from_slice(&resp[..resp_len]);
+    }
+
+    fn write_reg(&mut self, offset: u64, val: u32) {
+        match offset {
+            CRB_CTRL_REQ => match val {
+                CTRL_REQ_CMD_READY => {
+                    let sts = self.get_reg(CRB_CTRL_STS) & !CTRL_STS_TPM_IDLE;
+                    self.set_reg(CRB_CTRL_STS, sts);
+                }
+                CTRL_REQ_GO_IDLE => {
+                    let sts = self.get_reg(CRB_CTRL_STS) | CTRL_STS_TPM_IDLE;
+                    self.set_reg(CRB_CTRL_STS, sts);
+                }
+                _ => {}
+            },
+            CRB_CTRL_CANCEL => {
+                if val == CRB_CANCEL_INVOKE && self.get_reg(CRB_CTRL_START) & CRB_START_INVOKE != 0
+                {
+                    if let Err(e) = self.backend.cancel_cmd() {
+                        warn!("{:?}", e);
+                    }
+                }
+            }
+            CRB_CTRL_START => {
+                if val == CRB_START_INVOKE
+                    && self.get_reg(CRB_CTRL_START) & CRB_START_INVOKE == 0
+                    && self.locality_assigned()
+                {
+                    self.set_reg(CRB_CTRL_START, CRB_START_INVOKE);
+                    self.execute_cmd();
+                    // Command is completed synchronously.
+                    self.set_reg(CRB_CTRL_START, 0);
+                }
+            }
+            CRB_LOC_CTRL => match val {
+                LOC_CTRL_REQUEST_ACCESS => {
+                    let state = self.get_reg(CRB_LOC_STATE) | LOC_STATE_LOC_ASSIGNED;
+                    self.set_reg(CRB_LOC_STATE, state);
+                    self.set_reg(CRB_LOC_STS, LOC_STS_GRANTED);
+                }
+                LOC_CTRL_RELINQUISH => {
+                    let state = self.get_reg(CRB_LOC_STATE) & !LOC_STATE_LOC_ASSIGNED;
+                    self.set_reg(CRB_LOC_STATE, state);
+                    self.set_reg(CRB_LOC_STS, 0);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+impl SysBusDevOps for TpmCrb {
+    fn read(&mut self, data: &mut [u8], _base: GuestAddress, offset: u64) -> bool {
+        let start = offset as usize;
+        let end = start + data.len();
+        if end > self.regs.len() {
+            error!(
+                "TPM CRB read out of range: offset {:#x}, size {}",
+                offset,
+                data.len()
+            );
+            return false;
+        }
+        data.copy_from_slice(&self.regs[start..end]);
+        true
+    }
+
+    fn write(&mut self, data: &[u8], _base: GuestAddress, offset: u64) -> bool {
+        let start = offset as usize;
+        let end = start + data.len();
+        if end > self.regs.len() {
+            error!(
+                "TPM CRB write out of range: offset {:#x}, size {}",
+                offset,
+                data.len()
+            );
+            return false;
+        }
+
+        if offset >= CRB_DATA_BUFFER {
+            self.regs[start..end].copy_from_slice(data);
+            return true;
+        }
+        if data.len() != 4 {
+            warn!(
+                "Unsupported TPM CRB register write: offset {:#x}, size {}",
+                offset,
+                data.len()
+            );
+            return true;
+        }
+        let val = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+        self.write_reg(offset, val);
+        true
+    }
+
+    fn get_sys_resource(&mut self) -> Option<&mut SysRes> {
+        Some(&mut self.res)
+    }
+
+    fn get_type(&self) -> SysBusDevType {
+        SysBusDevType::Tpm
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.reset_regs();
+        self.backend
+            .stop()
+            .with_context(|| "Failed to stop TPM backend")?;
+        self.backend
+            .startup()
+            .with_context(|| "Failed to startup TPM backend")
+    }
+}
+
+impl AmlBuilder for TpmCrb {
+    fn aml_bytes(&self) -> Vec<u8> {
+        let mut acpi_dev = AmlDevice::new("TPM");
+        acpi_dev.append_child(AmlNameDecl::new("_HID", AmlString("MSFT0101".to_string())));
+        acpi_dev.append_child(AmlNameDecl::new(
+            "_STR",
+            AmlString("TPM 2.0 Device".to_string()),
+        ));
+        acpi_dev.append_child(AmlNameDecl::new("_UID", AmlInteger(0)));
+        acpi_dev.append_child(AmlNameDecl::new("_STA", AmlInteger(0xF)));
+
+        let mut res = AmlResTemplate::new();
+        res.append_child(AmlMemory32Fixed::new(
+            AmlReadAndWrite::ReadWrite,
+            self.res.region_base as u32,
+            self.res.region_size as u32,
+        ));
+        acpi_dev.append_child(AmlNameDecl::new("_CRS", res));
+
+        acpi_dev.aml_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_reg(crb: &mut TpmCrb, offset: u64) -> u32 {
+        let mut data = [0_u8; 4];
+        assert!(crb.read(&mut data, GuestAddress(0), offset));
+        u32::from_le_bytes(data)
+    }
+
+    #[test]
+    fn test_tpm_crb_regs() {
+        let mut crb = TpmCrb::new(TpmEmulator::new("/tmp/swtpm-test.sock"));
+        assert_eq!(read_reg(&mut crb, CRB_INTF_ID), CRB_INTF_ID_VALUE);
+        assert_eq!(
+            read_reg(&mut crb, CRB_CTRL_CMD_SIZE),
+            CRB_DATA_BUFFER_SIZE as u32
+        );
+        assert_eq!(
+            read_reg(&mut crb, CRB_CTRL_STS) & CTRL_STS_TPM_IDLE,
+            CTRL_STS_TPM_IDLE
+        );
+
+        // Request and relinquish locality 0.
+        assert!(crb.write(
+            &LOC_CTRL_REQUEST_ACCESS.to_le_bytes(),
+            GuestAddress(0),
+            CRB_LOC_CTRL
+        ));
+        assert_ne!(
+            read_reg(&mut crb, CRB_LOC_STATE) & LOC_STATE_LOC_ASSIGNED,
+            0
+        );
+        assert_eq!(read_reg(&mut crb, CRB_LOC_STS), LOC_STS_GRANTED);
+        assert!(crb.write(
+            &LOC_CTRL_RELINQUISH.to_le_bytes(),
+            GuestAddress(0),
+            CRB_LOC_CTRL
+        ));
+        assert_eq!(
+            read_reg(&mut crb, CRB_LOC_STATE) & LOC_STATE_LOC_ASSIGNED,
+            0
+        );
+
+        // Leave idle state.
+        assert!(crb.write(
+            &CTRL_REQ_CMD_READY.to_le_bytes(),
+            GuestAddress(0),
+            CRB_CTRL_REQ
+        ));
+        assert_eq!(read_reg(&mut crb, CRB_CTRL_STS) & CTRL_STS_TPM_IDLE, 0);
+
+        // Command is not started without locality.
+        assert!(crb.write(
+            &CRB_START_INVOKE.to_le_bytes(),
+            GuestAddress(0),
+            CRB_CTRL_START
+        ));
+        assert_eq!(read_reg(&mut crb, CRB_CTRL_START), 0);
+
+        // Data buffer is readable and writable.
+        assert!(crb.write(&[0x80, 0x01], GuestAddress(0), CRB_DATA_BUFFER));
+        let mut data = [0_u8; 2];
+        assert!(crb.read(&mut data, GuestAddress(0), CRB_DATA_BUFFER));
+        assert_eq!(data, [0x80, 0x01]);
+        assert!(!crb.read(&mut data, GuestAddress(0), TPM_CRB_ADDR_SIZE - 1));
+    }
+}
@@ -0,0 +1,300 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+
+use anyhow::{bail, Context, Result};
+use libc::{c_void, iovec};
+use log::{error, info};
+
+use util::unix::UnixSock;
+
+/// Commands of the swtpm control channel.
+const CMD_GET_CAPABILITY: u32 = 1;
+const CMD_INIT: u32 = 2;
+const CMD_SHUTDOWN: u32 = 3;
+const CMD_SET_LOCALITY: u32 = 5;
+const CMD_CANCEL_TPM_CMD: u32 = 9;
+const CMD_STOP: u32 = 14;
+const CMD_SET_DATAFD: u32 = 16;
+
+/// Capabilities reported by swtpm.
+const PTM_CAP_INIT: u64 = 1;
+const PTM_CAP_SHUTDOWN: u64 = 1 << 1;
+const PTM_CAP_SET_LOCALITY: u64 = 1 << 3;
+const PTM_CAP_CANCEL_TPM_CMD: u64 = 1 << 5;
+const PTM_CAP_STOP: u64 = 1 << 10;
+const PTM_CAP_SET_DATAFD: u64 = 1 << 12;
+const PTM_CAP_REQUIRED: u64 =
+    PTM_CAP_INIT | PTM_CAP_SHUTDOWN | PTM_CAP_SET_LOCALITY | PTM_CAP_STOP | PTM_CAP_SET_DATAFD;
+
+/// Size of the header of TPM command and response: tag(u16), size(u32), code(u32).
+pub const TPM_HEADER_SIZE: usize = 10;
+/// TPM_RC_FAILURE, returned to guest if the backend fails.
+const TPM_RC_FAILURE: u32 = 0x101;
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+
+/// Backend of TPM which talks to an external swtpm process.
+///
+/// The control channel is the unix socket created by `swtpm socket --ctrl type=unixio`,
+/// and the data channel is a socketpair whose one end is passed to swtpm by `CMD_SET_DATAFD`.
+pub struct TpmEmulator {
+    /// Control channel of swtpm.
+    ctrl: UnixSock,
+    /// Data channel of swtpm.
+    data: Option<UnixStream>,
+    /// Capabilities of swtpm.
+    caps: u64,
+    /// Current locality of TPM command.
+    locality: Option<u8>,
+}
+
+impl TpmEmulator {
+    pub fn new(path: &str) -> Self {
+        Self {
+            ctrl: UnixSock::new(path),
+            data: None,
+            caps: 0,
+            locality: None,
+        }
+    }
+
+    /// Connect to swtpm and initialize the TPM.
+    pub fn realize(&mut self) -> Result<()> {
+        self.ctrl
+            .connect()
+            .with_context(|| "Failed to connect to swtpm control socket")?;
+
+        let mut caps = [0_u8; 8];
+        self.ctrl_cmd(CMD_GET_CAPABILITY, &[], &mut caps)?;
+        self.caps = u64::from_be_bytes(caps);
+        if self.caps & PTM_CAP_REQUIRED != PTM_CAP_REQUIRED {
+            bail!(
+                "swtpm capabilities {:#x} are not sufficient, required {:#x}",
+                self.caps,
+                PTM_CAP_REQUIRED
+            );
+        }
+
+        let (local, remote) =
+            UnixStream::pair().with_context(|| "Failed to create swtpm data channel")?;
+        self.ctrl_cmd_with_fd(CMD_SET_DATAFD, remote.as_raw_fd())?;
+        // The remote end has been passed to swtpm, close our copy.
+        drop(remote);
+        self.data = Some(local);
+
+        self.startup()
+    }
+
+    /// Initialize the TPM, it's called at realize and at reset of VM.
+    pub fn startup(&mut self) -> Result<()> {
+        // Init flags is zero, which means TPM state is not deleted.
+        let mut resp = [0_u8; 4];
+        self.ctrl_cmd(CMD_INIT, &0_u32.to_be_bytes(), &mut resp)?;
+        check_ptm_result(CMD_INIT, &resp)?;
+        self.locality = None;
+        info!("swtpm is initialized");
+        Ok(())
+    }
+
+    /// Stop the TPM, the TPM can be initialized again by `startup`.
+    pub fn stop(&mut self) -> Result<()> {
+        let mut resp = [0_u8; 4];
+        self.ctrl_cmd(CMD_STOP, &[], &mut resp)?;
+        check_ptm_result(CMD_STOP, &resp)
+    }
+
+    /// Shutdown the TPM, swtpm saves its state and exits.
+    pub fn shutdown(&mut self) -> Result<()> {
+        let mut resp = [0_u8; 4];
+        self.ctrl_cmd(CMD_SHUTDOWN, &[], &mut resp)?;
+        check_ptm_result(CMD_SHUTDOWN, &resp)
+    }
+
+    /// Cancel the TPM command which is in progress.
+    pub fn cancel_cmd(&mut self) -> Result<()> {
+        if self.caps & PTM_CAP_CANCEL_TPM_CMD == 0 {
+            bail!("swtpm does not support cancelling command");
+        }
+        let mut resp = [0_u8; 4];
+        self.ctrl_cmd(CMD_CANCEL_TPM_CMD, &[], &mut resp)?;
+        check_ptm_result(CMD_CANCEL_TPM_CMD, &resp)
+    }
+
+    fn set_locality(&mut self, locality: u8) -> Result<()> {
+        if self.locality == Some(locality) {
+            return Ok(());
+        }
+        let mut resp = [0_u8; 4];
+        self.ctrl_cmd(CMD_SET_LOCALITY, &[locality], &mut resp)?;
+        check_ptm_result(CMD_SET_LOCALITY, &resp)?;
+        self.locality = Some(locality);
+        Ok(())
+    }
+
+    /// Deliver TPM command to swtpm and get the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `locality` - Locality of the command.
+    /// * `cmd` - TPM command.
+    /// * `resp` - Buffer to store the TPM response.
+    ///
+    /// Returns the length of response. If the command fails, a TPM response with
+    /// error code is returned to guest instead.
+    pub fn handle_request(&mut self, locality: u8, cmd: &[u8], resp: &mut [u8]) -> usize {
+        match self.do_request(locality, cmd, resp) {
+            Ok(len) => len,
+            Err(e) => {
+                error!("Failed to handle TPM command: {:?}", e);
+                write_failure_response(resp)
+            }
+        }
+    }
+
+    fn do_request(&mut self, locality: u8, cmd: &[u8], resp: &mut [u8]) -> Result<usize> {
+        if resp.len() < TPM_HEADER_SIZE {
+            bail!("TPM response buffer is too small");
+        }
+        self.set_locality(locality)?;
+
+        let data = self
+            .data
+            .as_mut()
+            .with_context(|| "swtpm data channel is not connected")?;
+        data.write_all(cmd)
+            .with_context(|| "Failed to send TPM command to swtpm")?;
+        data.read_exact(&mut resp[..TPM_HEADER_SIZE])
+            .with_context(|| "Failed to read TPM response header from swtpm")?;
+
+        let size = u32::from_be_bytes([resp[2], resp[3], resp[4], resp[5]]) as usize;
+        if size < TPM_HEADER_SIZE || size > resp.len() {
+            bail!("Invalid TPM response size {}", size);
+        }
+        data.read_exact(&mut resp[TPM_HEADER_SIZE..size])
+            .with_context(|| "Failed to read TPM response from swtpm")?;
+        Ok(size)
+    }
+
+    fn ctrl_cmd(&mut self, cmd: u32, payload: &[u8], resp: &mut [u8]) -> Result<()> {
+        let mut req = cmd.to_be_bytes().to_vec();
+        req.extend_from_slice(payload);
+        self.send_ctrl(&mut req, &[])?;
+        self.recv_ctrl(cmd, resp)
+    }
+
+    fn ctrl_cmd_with_fd(&mut self, cmd: u32, fd: i32) -> Result<()> {
+        let mut req = cmd.to_be_bytes().to_vec();
+        self.send_ctrl(&mut req, &[fd])?;
+        let mut resp = [0_u8; 4];
+        self.recv_ctrl(cmd, &mut resp)?;
+        check_ptm_result(cmd, &resp)
+    }
+
+    fn send_ctrl(&mut self, req: &mut [u8], fds: &[i32]) -> Result<()> {
+        let mut iovecs = [iovec {
+            iov_base: req.as_mut_ptr() as *mut c_void,
+            iov_len: req.len(),
+        }];
+        let len = self
+            .ctrl
+            .send_msg(&mut iovecs, fds)
+            .with_context(|| "Failed to send swtpm control command")?;
+        if len != req.len() {
+            bail!(
+                "Partial swtpm control command is sent: {}/{}",
+                len,
+                req.len()
+            );
+        }
+        Ok(())
+    }
+
+    fn recv_ctrl(&mut self, cmd: u32, resp: &mut [u8]) -> Result<()> {
+        let mut iovecs = [iovec {
+            iov_base: resp.as_mut_ptr() as *mut c_void,
+            iov_len: resp.len(),
+        }];
+        let (len, _) = self
+            .ctrl
+            .recv_msg(&mut iovecs, &mut [])
+            .with_context(|| format!("Failed to receive swtpm response of command {}", cmd))?;
+        if len != resp.len() {
+            bail!(
+                "Invalid length of swtpm response of command {}: {}",
+                cmd,
+                len
+            );
+        }
+        Ok(())
+    }
+}
+
+fn check_ptm_result(cmd: u32, resp: &[u8; 4]) -> Result<()> {
+    let res = u32::from_be_bytes(*resp);
+    if res != 0 {
+        bail!("swtpm command {} failed with result {:#x}", cmd, res);
+    }
+    Ok(())
+}
+
+/// Write a TPM_RC_FAILURE response to `resp`, returns the length of response.
+fn write_failure_response(resp: &mut [u8]) -> usize {
+    if resp.len() < TPM_HEADER_SIZE {
+        return 0;
+    }
+    resp[0..2].copy_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+    resp[2..6].copy_from_slice(&(TPM_HEADER_SIZE as u32).to_be_bytes());
+    resp[6..10].copy_from_slice(&TPM_RC_FAILURE.to_be_bytes());
+    TPM_HEADER_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tpm_failure_response() {
+        let mut resp = [0_u8; 16];
+        assert_eq!(write_failure_response(&mut resp), TPM_HEADER_SIZE);
+        assert_eq!(&resp[0..2], &[0x80, 0x01]);
+        assert_eq!(&resp[2..6], &[0, 0, 0, 10]);
+        assert_eq!(&resp[6..10], &[0, 0, 0x01, 0x01]);
+
+        let mut resp = [0_u8; 4];
+        assert_eq!(write_failure_response(&mut resp), 0);
+    }
+
+    #[test]
+    fn test_tpm_data_channel() {
+        let (local, mut remote) = UnixStream::pair().unwrap();
+        let mut tpm = TpmEmulator::new("/tmp/swtpm-test.sock");
+        tpm.data = Some(local);
+        // Locality is set, no control command is needed.
+        tpm.locality = Some(0);
+
+        let handle = std::thread::spawn(move || {
+            let mut cmd = [0_u8; 12];
+            remote.read_exact(&mut cmd).unwrap();
+            let resp = [0x80, 0x01, 0, 0, 0, 12, 0, 0, 0, 0, 0xab, 0xcd];
+            remote.write_all(&resp).unwrap();
+        });
+
+        let cmd = [0x80, 0x01, 0, 0, 0, 12, 0, 0, 0x01, 0x44, 0, 0];
+        let mut resp = [0_u8; 32];
+        assert_eq!(tpm.handle_request(0, &cmd, &mut resp), 12);
+        assert_eq!(&resp[10..12], &[0xab, 0xcd]);
+        handle.join().unwrap();
+    }
+}
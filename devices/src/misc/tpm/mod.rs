@@ -0,0 +1,22 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+pub mod crb;
+pub mod emulator;
+
+pub use crb::*;
+pub use emulator::TpmEmulator;
+
+/// Name of fw_cfg file which holds the TCG event log of measured boot.
+pub const TPM_LOG_FILE: &str = "etc/tpm/log";
+/// Minimum size of the TCG event log area.
+pub const TPM_LOG_AREA_MIN_SIZE: u32 = 64 * 1024;
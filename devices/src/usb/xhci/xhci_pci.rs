@@ -334,6 +334,10 @@ impl PciDevOps for XhciPciDevice {
         self.name.clone()
     }
 
+    fn get_pci_config(&self) -> Option<&PciConfig> {
+        Some(&self.pci_config)
+    }
+
     fn reset(&mut self, _reset_child_device: bool) -> pci::Result<()> {
         self.xhci.lock().unwrap().reset();
 
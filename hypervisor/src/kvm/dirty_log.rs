@@ -0,0 +1,94 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::cmp::{max, min};
+use std::time::Duration;
+
+/// Max pages cleared by each `KVM_CLEAR_DIRTY_LOG`, which is 1GiB of 4KiB pages.
+const MAX_CHUNK_PAGES: u64 = 1 << 18;
+/// Min pages cleared by each `KVM_CLEAR_DIRTY_LOG`, which is 2MiB of 4KiB pages.
+const MIN_CHUNK_PAGES: u64 = 1 << 9;
+/// Pages cleared by the first `KVM_CLEAR_DIRTY_LOG`, which is 64MiB of 4KiB pages.
+const INIT_CHUNK_PAGES: u64 = 1 << 14;
+/// Expected time of each `KVM_CLEAR_DIRTY_LOG`, during which vcpus may wait for
+/// the mmu lock of kvm.
+const CHUNK_TARGET_TIME: Duration = Duration::from_micros(500);
+
+/// Pacing of clearing the dirty log of memory slots in chunks. The number of pages
+/// in a chunk is adapted to the time clearing takes, and the caller pauses between
+/// chunks so that vcpus writing to the protected pages get the mmu lock in time.
+pub struct DirtyLogPacer {
+    /// Number of pages in the next chunk, which is a multiple of 64 as required by kvm.
+    chunk_pages: u64,
+}
+
+impl Default for DirtyLogPacer {
+    fn default() -> Self {
+        DirtyLogPacer {
+            chunk_pages: INIT_CHUNK_PAGES,
+        }
+    }
+}
+
+impl DirtyLogPacer {
+    /// Number of pages to clear in the next chunk.
+    pub fn chunk_pages(&self) -> u64 {
+        self.chunk_pages
+    }
+
+    /// Record the time clearing a chunk takes, and returns how long to pause
+    /// before clearing the next chunk.
+    pub fn record(&mut self, elapsed: Duration) -> Duration {
+        if elapsed > CHUNK_TARGET_TIME {
+            self.chunk_pages = max(self.chunk_pages / 2, MIN_CHUNK_PAGES);
+        } else if elapsed < CHUNK_TARGET_TIME / 2 {
+            self.chunk_pages = min(self.chunk_pages * 2, MAX_CHUNK_PAGES);
+        }
+        // Vcpus may have been blocked as long as the clearing takes.
+        min(elapsed, CHUNK_TARGET_TIME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dirty_log_pacer() {
+        let mut pacer = DirtyLogPacer::default();
+        assert_eq!(pacer.chunk_pages(), INIT_CHUNK_PAGES);
+
+        // Fast clearing grows the chunk up to the max.
+        assert_eq!(
+            pacer.record(Duration::from_micros(10)),
+            Duration::from_micros(10)
+        );
+        assert_eq!(pacer.chunk_pages(), INIT_CHUNK_PAGES * 2);
+        for _ in 0..10 {
+            pacer.record(Duration::from_micros(10));
+        }
+        assert_eq!(pacer.chunk_pages(), MAX_CHUNK_PAGES);
+
+        // The chunk is kept if clearing takes about the target time.
+        pacer.record(Duration::from_micros(400));
+        assert_eq!(pacer.chunk_pages(), MAX_CHUNK_PAGES);
+
+        // Slow clearing shrinks the chunk down to the min, and the pause is bounded.
+        assert_eq!(pacer.record(Duration::from_millis(20)), CHUNK_TARGET_TIME);
+        assert_eq!(pacer.chunk_pages(), MAX_CHUNK_PAGES / 2);
+        for _ in 0..20 {
+            pacer.record(Duration::from_millis(20));
+        }
+        assert_eq!(pacer.chunk_pages(), MIN_CHUNK_PAGES);
+        assert_eq!(pacer.chunk_pages() % 64, 0);
+    }
+}
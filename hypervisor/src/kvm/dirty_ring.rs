@@ -0,0 +1,182 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::mem::size_of;
+use std::os::unix::io::RawFd;
+use std::ptr::{read_volatile, write_volatile};
+use std::sync::atomic::{fence, Ordering};
+
+use anyhow::{bail, Result};
+use util::unix::host_page_size;
+
+// See: https://elixir.bootlin.com/linux/v6.1/source/include/uapi/linux/kvm.h
+pub const KVM_CAP_DIRTY_LOG_RING: u32 = 192;
+pub const KVM_CAP_DIRTY_LOG_RING_ACQ_REL: u32 = 223;
+pub const KVM_EXIT_DIRTY_RING_FULL: u32 = 31;
+/// The page offset of dirty ring in the mmap area of vcpu fd.
+const KVM_DIRTY_LOG_PAGE_OFFSET: u64 = 64;
+/// The gfn is dirtied by kvm and not harvested.
+const KVM_DIRTY_GFN_F_DIRTY: u32 = 1;
+/// The gfn has been harvested and can be recycled by kvm.
+const KVM_DIRTY_GFN_F_RESET: u32 = 2;
+
+/// Entry of kvm dirty ring.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct KvmDirtyGfn {
+    flags: u32,
+    /// Address space id in the high 16 bits, and memory slot id in the low 16 bits.
+    slot: u32,
+    /// Page offset in the memory slot.
+    offset: u64,
+}
+
+/// The dirty ring shared with kvm for a vcpu.
+pub struct DirtyRing {
+    /// Host address of the ring.
+    gfns: *mut KvmDirtyGfn,
+    /// Number of entries in the ring, must be power of 2.
+    size: u32,
+    /// Index of the next entry to harvest.
+    fetch_index: u32,
+}
+
+// SAFETY: The ring is only accessed with the lock of `KVMFds::dirty_rings`.
+unsafe impl Send for DirtyRing {}
+
+impl DirtyRing {
+    /// Map the dirty ring of vcpu.
+    ///
+    /// # Arguments
+    ///
+    /// * `vcpu_fd` - Raw fd of the vcpu.
+    /// * `size` - Number of entries in the ring.
+    pub fn new(vcpu_fd: RawFd, size: u32) -> Result<Self> {
+        let len = size as usize * size_of::<KvmDirtyGfn>();
+        let offset = KVM_DIRTY_LOG_PAGE_OFFSET * host_page_size();
+        // SAFETY: The vcpu fd is valid, and the result is checked.
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                vcpu_fd,
+                offset as libc::off_t,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            bail!(
+                "Failed to map kvm dirty ring, error is {}",
+                std::io::Error::last_os_error()
+            );
+        }
+
+        Ok(DirtyRing {
+            gfns: addr as *mut KvmDirtyGfn,
+            size,
+            fetch_index: 0,
+        })
+    }
+
+    /// Collect the dirty gfns in the ring, and mark them as harvested.
+    /// Returns the number of harvested gfns.
+    ///
+    /// # Arguments
+    ///
+    /// * `mark` - Callback with slot and page offset of each dirty gfn.
+    pub fn harvest<F: FnMut(u32, u64)>(&mut self, mut mark: F) -> u32 {
+        let mut count = 0;
+        loop {
+            let index = (self.fetch_index & (self.size - 1)) as usize;
+            // SAFETY: The index is in range of the ring mapped in `new`.
+            let gfn = unsafe { self.gfns.add(index) };
+            // SAFETY: The gfn is in range of the ring.
+            let flags = unsafe { read_volatile(&(*gfn).flags) };
+            if flags & KVM_DIRTY_GFN_F_DIRTY == 0 {
+                break;
+            }
+            // Read slot and offset after the dirty flag is observed.
+            fence(Ordering::Acquire);
+            // SAFETY: The gfn is in range of the ring.
+            let (slot, offset) =
+                unsafe { (read_volatile(&(*gfn).slot), read_volatile(&(*gfn).offset)) };
+            mark(slot, offset);
+
+            // Make sure kvm sees the reset flag after the entry is consumed.
+            fence(Ordering::Release);
+            // SAFETY: The gfn is in range of the ring.
+            unsafe { write_volatile(&mut (*gfn).flags, KVM_DIRTY_GFN_F_RESET) };
+            self.fetch_index = self.fetch_index.wrapping_add(1);
+            count += 1;
+        }
+        count
+    }
+}
+
+impl Drop for DirtyRing {
+    fn drop(&mut self) {
+        let len = self.size as usize * size_of::<KvmDirtyGfn>();
+        // SAFETY: The ring is mapped in `new` with the same length.
+        unsafe {
+            libc::munmap(self.gfns as *mut libc::c_void, len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dirty_ring_harvest() {
+        let mut gfns = vec![KvmDirtyGfn::default(); 4];
+        gfns[0] = KvmDirtyGfn {
+            flags: KVM_DIRTY_GFN_F_DIRTY,
+            slot: 1,
+            offset: 10,
+        };
+        gfns[1] = KvmDirtyGfn {
+            flags: KVM_DIRTY_GFN_F_DIRTY,
+            slot: 2,
+            offset: 20,
+        };
+        let mut ring = DirtyRing {
+            gfns: gfns.as_mut_ptr(),
+            size: 4,
+            fetch_index: 0,
+        };
+
+        let mut dirty = Vec::new();
+        assert_eq!(ring.harvest(|slot, offset| dirty.push((slot, offset))), 2);
+        assert_eq!(dirty, vec![(1, 10), (2, 20)]);
+        assert_eq!(ring.fetch_index, 2);
+        // Nothing new is dirtied.
+        assert_eq!(ring.harvest(|_, _| {}), 0);
+
+        // The ring wraps around.
+        for (i, index) in [2_usize, 3, 0].iter().enumerate() {
+            gfns[*index] = KvmDirtyGfn {
+                flags: KVM_DIRTY_GFN_F_DIRTY,
+                slot: 1,
+                offset: i as u64,
+            };
+        }
+        dirty.clear();
+        assert_eq!(ring.harvest(|slot, offset| dirty.push((slot, offset))), 3);
+        assert_eq!(dirty, vec![(1, 0), (1, 1), (1, 2)]);
+        assert_eq!(gfns[0].flags, KVM_DIRTY_GFN_F_RESET);
+
+        // The memory is not mapped by `new`, don't unmap it.
+        std::mem::forget(ring);
+    }
+}
@@ -25,7 +25,7 @@ type IrqChip = kvm_bindings::kvm_irq_routing_entry__bindgen_ty_1;
 ioctl_io_nr!(KVM_CHECK_EXTENSION, KVMIO, 0x03);
 
 #[cfg(target_arch = "x86_64")]
-const IOAPIC_NUM_PINS: u32 = 24;
+pub const IOAPIC_NUM_PINS: u32 = 24;
 #[cfg(target_arch = "x86_64")]
 const PIC_MASTER_PINS: u32 = 8;
 #[cfg(target_arch = "x86_64")]
@@ -117,6 +117,19 @@ impl IrqRouteTable {
         }
     }
 
+    /// Init irq route table in arch x86_64 with split irqchip.
+    ///
+    /// The PIC and IOAPIC are not emulated in kernel, so gsi 0-23 are only reserved
+    /// for the IOAPIC pins. Their routes are set by the userspace IOAPIC as MSI routes
+    /// according to its redirection table.
+    #[cfg(target_arch = "x86_64")]
+    pub fn init_split_irq_route_table(&mut self) {
+        for i in 0..IOAPIC_NUM_PINS {
+            // This unwrap() will never fail, it is safe.
+            self.gsi_bitmap.set(i as usize).unwrap();
+        }
+    }
+
     /// Init irq route table in arch aarch64.
     #[cfg(target_arch = "aarch64")]
     pub fn init_irq_route_table(&mut self) {
@@ -149,7 +162,8 @@ impl IrqRouteTable {
         Ok(())
     }
 
-    fn remove_irq_route(&mut self, gsi: u32) {
+    /// Remove all the irq routes of the given gsi, the gsi is still allocated.
+    pub fn remove_irq_route(&mut self, gsi: u32) {
         while let Some((index, _)) = self
             .irq_routes
             .iter()
@@ -12,34 +12,67 @@
 
 use std::collections::HashMap;
 use std::mem::{align_of, size_of};
+use std::os::raw::c_ulong;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use arc_swap::ArcSwap;
 use kvm_bindings::kvm_userspace_memory_region as MemorySlot;
 use kvm_bindings::*;
-use kvm_ioctls::{Kvm, VmFd};
-use log::error;
+use kvm_ioctls::{Kvm, VcpuFd, VmFd};
+use log::{error, info};
 use once_cell::sync::Lazy;
+use util::unix::host_page_size;
 use vmm_sys_util::{
-    eventfd::EventFd, ioctl_io_nr, ioctl_ioc_nr, ioctl_ior_nr, ioctl_iow_nr, ioctl_iowr_nr,
+    eventfd::EventFd,
+    ioctl::{ioctl, ioctl_with_mut_ref, ioctl_with_ref, ioctl_with_val},
+    ioctl_io_nr, ioctl_ioc_nr, ioctl_ior_nr, ioctl_iow_nr, ioctl_iowr_nr,
 };
 
 use anyhow::{bail, Context, Result};
+use dirty_log::DirtyLogPacer;
+pub use dirty_ring::KVM_EXIT_DIRTY_RING_FULL;
+use dirty_ring::{DirtyRing, KvmDirtyGfn};
 pub use interrupt::MsiVector;
+#[cfg(target_arch = "x86_64")]
+pub use interrupt::IOAPIC_NUM_PINS;
 use interrupt::{IrqRoute, IrqRouteEntry, IrqRouteTable};
+pub use stats::KvmStats;
 
+mod dirty_log;
+mod dirty_ring;
 mod interrupt;
+mod stats;
 
 // See: https://elixir.bootlin.com/linux/v4.19.123/source/include/uapi/asm-generic/kvm.h
 pub const KVM_SET_DEVICE_ATTR: u32 = 0x4018_aee1;
 pub const KVM_SET_USER_MEMORY_REGION: u32 = 0x4020_ae46;
 pub const KVM_IOEVENTFD: u32 = 0x4040_ae79;
 pub const KVM_SIGNAL_MSI: u32 = 0x4020_aea5;
+// See: https://elixir.bootlin.com/linux/v5.19/source/include/uapi/linux/kvm.h#L1167
+#[cfg(target_arch = "aarch64")]
+const KVM_CAP_ARM_SYSTEM_SUSPEND: u32 = 216;
+// See: https://elixir.bootlin.com/linux/v6.8/source/include/uapi/linux/kvm.h
+const KVM_CAP_MEMORY_ATTRIBUTES: u32 = 233;
+const KVM_MEMORY_ATTRIBUTE_PRIVATE: u64 = 1 << 3;
+
+/// Argument of `KVM_SET_MEMORY_ATTRIBUTES`.
+#[repr(C)]
+#[derive(Default)]
+struct KvmMemoryAttributes {
+    address: u64,
+    size: u64,
+    attributes: u64,
+    flags: u64,
+}
 
 // See: https://elixir.bootlin.com/linux/v4.19.123/source/include/uapi/linux/kvm.h
 ioctl_iow_nr!(KVM_SET_GSI_ROUTING, KVMIO, 0x6a, kvm_irq_routing);
 ioctl_iow_nr!(KVM_IRQFD, KVMIO, 0x76, kvm_irqfd);
 ioctl_io_nr!(KVM_GET_API_VERSION, KVMIO, 0x00);
+ioctl_io_nr!(KVM_GET_STATS_FD, KVMIO, 0xce);
 ioctl_ior_nr!(KVM_GET_MP_STATE, KVMIO, 0x98, kvm_mp_state);
 ioctl_ior_nr!(KVM_GET_VCPU_EVENTS, KVMIO, 0x9f, kvm_vcpu_events);
 #[cfg(target_arch = "x86_64")]
@@ -49,6 +82,8 @@ ioctl_iow_nr!(KVM_SET_CPUID2, KVMIO, 0x90, kvm_cpuid2);
 #[cfg(target_arch = "x86_64")]
 ioctl_iow_nr!(KVM_SET_MP_STATE, KVMIO, 0x99, kvm_mp_state);
 #[cfg(target_arch = "x86_64")]
+ioctl_io_nr!(KVM_NMI, KVMIO, 0x9a);
+#[cfg(target_arch = "x86_64")]
 ioctl_iow_nr!(KVM_SET_SREGS, KVMIO, 0x84, kvm_sregs);
 #[cfg(target_arch = "x86_64")]
 ioctl_iow_nr!(KVM_SET_REGS, KVMIO, 0x82, kvm_regs);
@@ -67,6 +102,18 @@ ioctl_iow_nr!(KVM_SET_VCPU_EVENTS, KVMIO, 0xa0, kvm_vcpu_events);
 #[cfg(target_arch = "x86_64")]
 ioctl_ior_nr!(KVM_GET_PIT2, KVMIO, 0x9f, kvm_pit_state2);
 ioctl_ior_nr!(KVM_GET_CLOCK, KVMIO, 0x7c, kvm_clock_data);
+#[cfg(target_arch = "x86_64")]
+ioctl_iow_nr!(KVM_SET_CLOCK, KVMIO, 0x7b, kvm_clock_data);
+#[cfg(target_arch = "x86_64")]
+ioctl_io_nr!(KVM_KVMCLOCK_CTRL, KVMIO, 0xad);
+#[cfg(target_arch = "x86_64")]
+ioctl_io_nr!(KVM_SET_TSC_KHZ, KVMIO, 0xa2);
+#[cfg(target_arch = "x86_64")]
+ioctl_io_nr!(KVM_GET_TSC_KHZ, KVMIO, 0xa3);
+#[cfg(target_arch = "x86_64")]
+ioctl_iowr_nr!(KVM_TRANSLATE, KVMIO, 0x85, kvm_translation);
+#[cfg(target_arch = "x86_64")]
+ioctl_iow_nr!(KVM_SET_GUEST_DEBUG, KVMIO, 0x9b, kvm_guest_debug);
 ioctl_iowr_nr!(KVM_GET_IRQCHIP, KVMIO, 0x62, kvm_irqchip);
 ioctl_ior_nr!(KVM_GET_REGS, KVMIO, 0x81, kvm_regs);
 ioctl_ior_nr!(KVM_GET_SREGS, KVMIO, 0x83, kvm_sregs);
@@ -92,8 +139,14 @@ ioctl_iow_nr!(KVM_GET_DEVICE_ATTR, KVMIO, 0xe2, kvm_device_attr);
 ioctl_iowr_nr!(KVM_GET_REG_LIST, KVMIO, 0xb0, kvm_reg_list);
 #[cfg(target_arch = "aarch64")]
 ioctl_iow_nr!(KVM_ARM_VCPU_INIT, KVMIO, 0xae, kvm_vcpu_init);
+#[cfg(target_arch = "aarch64")]
+ioctl_iow_nr!(KVM_ARM_VCPU_FINALIZE, KVMIO, 0xc2, std::os::raw::c_int);
+ioctl_io_nr!(KVM_CHECK_EXTENSION, KVMIO, 0x03);
+ioctl_io_nr!(KVM_RESET_DIRTY_RINGS, KVMIO, 0xc7);
 ioctl_iow_nr!(KVM_GET_DIRTY_LOG, KVMIO, 0x42, kvm_dirty_log);
+ioctl_iowr_nr!(KVM_CLEAR_DIRTY_LOG, KVMIO, 0xc0, kvm_clear_dirty_log);
 ioctl_iow_nr!(KVM_IRQ_LINE, KVMIO, 0x61, kvm_irq_level);
+ioctl_iow_nr!(KVM_SET_MEMORY_ATTRIBUTES, KVMIO, 0xd2, KvmMemoryAttributes);
 
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Default)]
@@ -102,6 +155,20 @@ pub struct KVMFds {
     pub vm_fd: Option<VmFd>,
     pub irq_route_table: Mutex<IrqRouteTable>,
     pub mem_slots: Arc<Mutex<HashMap<u32, MemorySlot>>>,
+    /// Number of entries of the per-vcpu dirty ring, 0 means the dirty ring is disabled.
+    dirty_ring_size: AtomicU32,
+    /// Dirty rings of all vcpus.
+    dirty_rings: Mutex<Vec<DirtyRing>>,
+    /// Dirty pages harvested from dirty rings, key is slot id and value is dirty bitmap.
+    dirty_pages: Mutex<HashMap<u32, Vec<u64>>>,
+    /// The dirty log got from kvm is cleared by `KVM_CLEAR_DIRTY_LOG` in chunks, instead
+    /// of being cleared for the whole slot at once by `KVM_GET_DIRTY_LOG`.
+    manual_dirty_protect: AtomicBool,
+    /// Pacing of clearing the dirty log in chunks.
+    dirty_log_pacer: Mutex<DirtyLogPacer>,
+    /// The kvm clock saved when VM is paused, guest time goes on from it when resumed.
+    #[cfg(target_arch = "x86_64")]
+    paused_clock: Mutex<Option<kvm_clock_data>>,
 }
 
 impl KVMFds {
@@ -121,6 +188,7 @@ impl KVMFds {
                     vm_fd: Some(vm_fd),
                     irq_route_table,
                     mem_slots: Arc::new(Mutex::new(HashMap::new())),
+                    ..Default::default()
                 }
             }
             Err(e) => {
@@ -162,6 +230,59 @@ impl KVMFds {
         }
     }
 
+    /// Create split irqchip, only LAPIC is emulated in kernel.
+    ///
+    /// # Arguments
+    ///
+    /// * `ioapic_pins` - The number of gsi reserved for the userspace IOAPIC.
+    #[cfg(target_arch = "x86_64")]
+    pub fn create_split_irqchip(&self, ioapic_pins: u32) -> Result<()> {
+        let mut cap = kvm_bindings::kvm_enable_cap {
+            cap: kvm_bindings::KVM_CAP_SPLIT_IRQCHIP,
+            ..Default::default()
+        };
+        cap.args[0] = u64::from(ioapic_pins);
+        self.vm_fd
+            .as_ref()
+            .unwrap()
+            .enable_cap(&cap)
+            .with_context(|| "Failed to create split irqchip")
+    }
+
+    /// Let kvm exit to userspace when guest calls PSCI SYSTEM_SUSPEND.
+    #[cfg(target_arch = "aarch64")]
+    pub fn enable_system_suspend(&self) -> Result<()> {
+        let cap = kvm_bindings::kvm_enable_cap {
+            cap: KVM_CAP_ARM_SYSTEM_SUSPEND,
+            ..Default::default()
+        };
+        self.vm_fd
+            .as_ref()
+            .unwrap()
+            .enable_cap(&cap)
+            .with_context(|| "Failed to enable PSCI SYSTEM_SUSPEND")
+    }
+
+    /// Set the max time in nanoseconds to poll before a halted vcpu of this VM
+    /// yields the host cpu, it overrides the `halt_poll_ns` parameter of kvm module.
+    pub fn set_halt_poll_ns(&self, ns: u32) -> Result<()> {
+        let vm_fd = self.vm_fd.as_ref().unwrap();
+        // SAFETY: The vm fd is valid and the capability is defined by kernel.
+        let ret =
+            unsafe { ioctl_with_val(vm_fd, KVM_CHECK_EXTENSION(), KVM_CAP_HALT_POLL as c_ulong) };
+        if ret <= 0 {
+            bail!("Per-VM halt polling is not supported by host kvm");
+        }
+        let mut cap = kvm_bindings::kvm_enable_cap {
+            cap: KVM_CAP_HALT_POLL,
+            ..Default::default()
+        };
+        cap.args[0] = u64::from(ns);
+        vm_fd
+            .enable_cap(&cap)
+            .with_context(|| format!("Failed to set halt poll time {}ns", ns))
+    }
+
     pub fn register_irqfd(&self, fd: &EventFd, gsi: u32) -> Result<()> {
         self.vm_fd
             .as_ref()
@@ -188,6 +309,7 @@ impl KVMFds {
 
     /// Start dirty page tracking in kvm.
     pub fn start_dirty_log(&self) -> Result<()> {
+        self.clear_dirty_pages()?;
         for (_, region) in self.mem_slots.lock().unwrap().iter_mut() {
             region.flags = KVM_MEM_LOG_DIRTY_PAGES;
             // Safe because region from `KVMFds` is reliable.
@@ -210,6 +332,7 @@ impl KVMFds {
 
     /// Stop dirty page tracking in kvm.
     pub fn stop_dirty_log(&self) -> Result<()> {
+        self.clear_dirty_pages()?;
         for (_, region) in self.mem_slots.lock().unwrap().iter_mut() {
             region.flags = 0;
             // Safe because region from `KVMFds` is reliable.
@@ -230,8 +353,207 @@ impl KVMFds {
         Ok(())
     }
 
+    /// Enable the dirty ring of kvm, it must be called before creating vcpus.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Number of entries of the per-vcpu dirty ring.
+    pub fn enable_dirty_ring(&self, size: u32) -> Result<()> {
+        if size == 0 {
+            return Ok(());
+        }
+
+        // Only the dirty ring with acquire/release ordering is supported on aarch64.
+        #[cfg(target_arch = "x86_64")]
+        let cap = dirty_ring::KVM_CAP_DIRTY_LOG_RING;
+        #[cfg(target_arch = "aarch64")]
+        let cap = dirty_ring::KVM_CAP_DIRTY_LOG_RING_ACQ_REL;
+
+        let vm_fd = self.vm_fd.as_ref().unwrap();
+        // SAFETY: The vm fd is valid and the capability is defined by kernel.
+        let max_bytes = unsafe { ioctl_with_val(vm_fd, KVM_CHECK_EXTENSION(), cap as c_ulong) };
+        if max_bytes <= 0 {
+            bail!("KVM dirty ring is not supported by host");
+        }
+        let bytes = u64::from(size) * size_of::<KvmDirtyGfn>() as u64;
+        if bytes > max_bytes as u64 {
+            bail!(
+                "Dirty ring size {} exceeds the max size {} supported by host",
+                size,
+                max_bytes as u64 / size_of::<KvmDirtyGfn>() as u64
+            );
+        }
+
+        let mut enable_cap = kvm_bindings::kvm_enable_cap {
+            cap,
+            ..Default::default()
+        };
+        enable_cap.args[0] = bytes;
+        vm_fd
+            .enable_cap(&enable_cap)
+            .with_context(|| "Failed to enable kvm dirty ring")?;
+        self.dirty_ring_size.store(size, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Let the dirty log of memory slots be cleared by `KVM_CLEAR_DIRTY_LOG` in chunks,
+    /// so that getting the dirty log of large slots doesn't block vcpus for long. It's
+    /// skipped if the dirty ring is enabled or kvm doesn't support it.
+    pub fn enable_manual_dirty_protect(&self) -> Result<()> {
+        if self.dirty_ring_enabled() {
+            return Ok(());
+        }
+
+        let vm_fd = self.vm_fd.as_ref().unwrap();
+        let cap = KVM_CAP_MANUAL_DIRTY_LOG_PROTECT2;
+        // SAFETY: The vm fd is valid and the capability is defined by kernel.
+        let flags = unsafe { ioctl_with_val(vm_fd, KVM_CHECK_EXTENSION(), cap as c_ulong) };
+        if flags <= 0 || flags as u64 & u64::from(KVM_DIRTY_LOG_MANUAL_PROTECT_ENABLE) == 0 {
+            info!("KVM manual dirty log protect is not supported by host");
+            return Ok(());
+        }
+
+        let mut enable_cap = kvm_bindings::kvm_enable_cap {
+            cap,
+            ..Default::default()
+        };
+        enable_cap.args[0] = u64::from(KVM_DIRTY_LOG_MANUAL_PROTECT_ENABLE);
+        vm_fd
+            .enable_cap(&enable_cap)
+            .with_context(|| "Failed to enable kvm manual dirty log protect")?;
+        self.manual_dirty_protect.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Check that guest memory can be made private to guest, which is required by
+    /// protected VM.
+    pub fn check_private_memory(&self) -> Result<()> {
+        let vm_fd = self.vm_fd.as_ref().unwrap();
+        // SAFETY: The vm fd is valid and the capability is defined by kernel.
+        let attributes = unsafe {
+            ioctl_with_val(
+                vm_fd,
+                KVM_CHECK_EXTENSION(),
+                KVM_CAP_MEMORY_ATTRIBUTES as c_ulong,
+            )
+        };
+        if attributes <= 0 || attributes as u64 & KVM_MEMORY_ATTRIBUTE_PRIVATE == 0 {
+            bail!("Private guest memory is not supported by host, protected VM can't be created");
+        }
+        Ok(())
+    }
+
+    /// Convert guest memory between private and shared for protected VM. Private
+    /// memory can't be accessed by host.
+    ///
+    /// # Arguments
+    ///
+    /// * `gpa` - Start guest physical address, aligned to page size.
+    /// * `size` - Size of the memory, aligned to page size.
+    /// * `private` - Whether the memory is private to guest.
+    pub fn set_memory_private(&self, gpa: u64, size: u64, private: bool) -> Result<()> {
+        let attr = KvmMemoryAttributes {
+            address: gpa,
+            size,
+            attributes: if private {
+                KVM_MEMORY_ATTRIBUTE_PRIVATE
+            } else {
+                0
+            },
+            flags: 0,
+        };
+        // SAFETY: The vm fd is valid and the attr is defined as kernel requires.
+        let ret = unsafe {
+            ioctl_with_ref(
+                self.vm_fd.as_ref().unwrap(),
+                KVM_SET_MEMORY_ATTRIBUTES(),
+                &attr,
+            )
+        };
+        if ret < 0 {
+            bail!(
+                "Failed to set memory attributes of gpa 0x{:x} size 0x{:x}: {}",
+                gpa,
+                size,
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether the dirty ring is enabled.
+    pub fn dirty_ring_enabled(&self) -> bool {
+        self.dirty_ring_size.load(Ordering::SeqCst) != 0
+    }
+
+    /// Map the dirty ring of vcpu if the dirty ring is enabled.
+    pub fn register_dirty_ring(&self, vcpu_fd: &VcpuFd) -> Result<()> {
+        let size = self.dirty_ring_size.load(Ordering::SeqCst);
+        if size == 0 {
+            return Ok(());
+        }
+
+        let ring = DirtyRing::new(vcpu_fd.as_raw_fd(), size)?;
+        self.dirty_rings.lock().unwrap().push(ring);
+        Ok(())
+    }
+
+    /// Harvest the dirty rings of all vcpus, and recycle the harvested entries in kvm.
+    /// Returns the number of harvested dirty pages.
+    pub fn harvest_dirty_rings(&self) -> Result<u64> {
+        let mut rings = self.dirty_rings.lock().unwrap();
+        let slots = self.mem_slots.lock().unwrap();
+        let mut dirty_pages = self.dirty_pages.lock().unwrap();
+        let page_size = host_page_size();
+
+        let mut count = 0_u64;
+        for ring in rings.iter_mut() {
+            count += u64::from(ring.harvest(|slot, offset| {
+                // Only address space 0 is used.
+                if slot >> 16 != 0 {
+                    return;
+                }
+                if let Some(region) = slots.get(&slot) {
+                    let pages = region.memory_size / page_size;
+                    if offset >= pages {
+                        return;
+                    }
+                    let bitmap = dirty_pages
+                        .entry(slot)
+                        .or_insert_with(|| vec![0_u64; ((pages + 63) / 64) as usize]);
+                    bitmap[(offset / 64) as usize] |= 1 << (offset % 64);
+                }
+            }));
+        }
+
+        if count != 0 {
+            // SAFETY: The vm fd is valid and KVM_RESET_DIRTY_RINGS has no argument.
+            let ret = unsafe { ioctl(self.vm_fd.as_ref().unwrap(), KVM_RESET_DIRTY_RINGS()) };
+            if ret < 0 {
+                bail!(
+                    "Failed to reset dirty rings, error is {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+        Ok(count)
+    }
+
     /// Get dirty page bitmap in kvm.
-    pub fn get_dirty_log(&self, slot: u32, mem_size: u64) -> Result<Vec<u64>> {
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The id of memory slot.
+    /// * `mem_size` - The size of memory slot.
+    /// * `paced` - Whether to pause between the chunks of clearing the dirty log,
+    ///   which is needless if vcpus are paused.
+    pub fn get_dirty_log(&self, slot: u32, mem_size: u64, paced: bool) -> Result<Vec<u64>> {
+        if self.dirty_ring_enabled() {
+            // KVM_GET_DIRTY_LOG is not allowed if the dirty ring is enabled.
+            self.harvest_dirty_rings()?;
+            return Ok(self.take_dirty_pages(slot, mem_size));
+        }
+
         let res = self
             .vm_fd
             .as_ref()
@@ -243,10 +565,93 @@ impl KVMFds {
                     std::io::Error::last_os_error()
                 )
             })?;
+        if self.manual_dirty_protect.load(Ordering::SeqCst) {
+            self.clear_dirty_log(slot, mem_size, &res, paced)?;
+        }
 
         Ok(res)
     }
 
+    /// Clear the dirty log got from kvm and write protect the dirty pages again,
+    /// in chunks of adaptive size.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The id of memory slot.
+    /// * `mem_size` - The size of memory slot.
+    /// * `bitmap` - The dirty bitmap of memory slot.
+    /// * `paced` - Whether to pause between the chunks.
+    fn clear_dirty_log(&self, slot: u32, mem_size: u64, bitmap: &[u64], paced: bool) -> Result<()> {
+        let pages = mem_size / host_page_size();
+        let mut pacer = self.dirty_log_pacer.lock().unwrap();
+        let mut first_page = 0_u64;
+        while first_page < pages {
+            let num_pages = std::cmp::min(pacer.chunk_pages(), pages - first_page);
+            let words =
+                &bitmap[(first_page / 64) as usize..((first_page + num_pages + 63) / 64) as usize];
+            if words.iter().all(|word| *word == 0) {
+                first_page += num_pages;
+                continue;
+            }
+
+            let mut clear_log = kvm_clear_dirty_log {
+                slot,
+                num_pages: num_pages as u32,
+                first_page,
+                __bindgen_anon_1: kvm_clear_dirty_log__bindgen_ty_1 {
+                    dirty_bitmap: words.as_ptr() as *mut libc::c_void,
+                },
+            };
+            let start = Instant::now();
+            // SAFETY: The vm fd is valid, and the bitmap covers `num_pages` from `first_page`
+            // which is a multiple of 64.
+            let ret = unsafe {
+                ioctl_with_mut_ref(
+                    self.vm_fd.as_ref().unwrap(),
+                    KVM_CLEAR_DIRTY_LOG(),
+                    &mut clear_log,
+                )
+            };
+            if ret < 0 {
+                bail!(
+                    "Failed to clear dirty log, error is {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+            let pause = pacer.record(start.elapsed());
+            if paced {
+                std::thread::sleep(pause);
+            }
+            first_page += num_pages;
+        }
+        Ok(())
+    }
+
+    /// Take the dirty page bitmap of slot which has been harvested from dirty rings,
+    /// without harvesting the rings again.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The id of memory slot.
+    /// * `mem_size` - The size of memory slot.
+    pub fn take_dirty_pages(&self, slot: u32, mem_size: u64) -> Vec<u64> {
+        let pages = mem_size / host_page_size();
+        self.dirty_pages
+            .lock()
+            .unwrap()
+            .remove(&slot)
+            .unwrap_or_else(|| vec![0_u64; ((pages + 63) / 64) as usize])
+    }
+
+    /// Drop the dirty pages harvested from dirty rings.
+    fn clear_dirty_pages(&self) -> Result<()> {
+        if self.dirty_ring_enabled() {
+            self.harvest_dirty_rings()?;
+            self.dirty_pages.lock().unwrap().clear();
+        }
+        Ok(())
+    }
+
     /// Add ram memory region to `KVMFds` structure.
     pub fn add_mem_slot(&self, mem_slot: MemorySlot) -> Result<()> {
         if mem_slot.flags & KVM_MEM_READONLY != 0 {
@@ -271,6 +676,98 @@ impl KVMFds {
     pub fn get_mem_slots(&self) -> Arc<Mutex<HashMap<u32, MemorySlot>>> {
         self.mem_slots.clone()
     }
+
+    /// Save the kvm clock when VM is paused, so that the time spent in pause is
+    /// invisible to guest.
+    #[cfg(target_arch = "x86_64")]
+    pub fn save_clock(&self) -> Result<()> {
+        let mut clock = self
+            .vm_fd
+            .as_ref()
+            .unwrap()
+            .get_clock()
+            .with_context(|| "Failed to get kvm clock")?;
+        // Flags returned by KVM_GET_CLOCK are not accepted by KVM_SET_CLOCK.
+        clock.flags = 0;
+        *self.paused_clock.lock().unwrap() = Some(clock);
+        Ok(())
+    }
+
+    /// Restore the kvm clock saved when VM is paused.
+    #[cfg(target_arch = "x86_64")]
+    pub fn restore_clock(&self) -> Result<()> {
+        if let Some(clock) = self.paused_clock.lock().unwrap().take() {
+            self.vm_fd
+                .as_ref()
+                .unwrap()
+                .set_clock(&clock)
+                .with_context(|| "Failed to set kvm clock")?;
+        }
+        Ok(())
+    }
+
+    /// Get the kvm clock, which is the one saved if VM is paused.
+    #[cfg(target_arch = "x86_64")]
+    pub fn get_clock(&self) -> Result<kvm_clock_data> {
+        if let Some(clock) = *self.paused_clock.lock().unwrap() {
+            return Ok(clock);
+        }
+        let mut clock = self
+            .vm_fd
+            .as_ref()
+            .unwrap()
+            .get_clock()
+            .with_context(|| "Failed to get kvm clock")?;
+        clock.flags = 0;
+        Ok(clock)
+    }
+
+    /// Set the kvm clock, e.g. restored from migration. If VM is paused, it
+    /// also replaces the saved one to be restored when resumed.
+    #[cfg(target_arch = "x86_64")]
+    pub fn set_clock(&self, clock: &kvm_clock_data) -> Result<()> {
+        self.vm_fd
+            .as_ref()
+            .unwrap()
+            .set_clock(clock)
+            .with_context(|| "Failed to set kvm clock")?;
+        let mut paused_clock = self.paused_clock.lock().unwrap();
+        if paused_clock.is_some() {
+            *paused_clock = Some(*clock);
+        }
+        Ok(())
+    }
+}
+
+/// Get the suberror and data of `KVM_EXIT_INTERNAL_ERROR` from the `kvm_run` area
+/// of the vcpu, which is only readable through the mmap of vcpu fd.
+pub fn vcpu_internal_error(vcpu_fd: &VcpuFd) -> Result<(u32, Vec<u64>)> {
+    let len = size_of::<kvm_run>();
+    // SAFETY: The vcpu fd is valid, and the result is checked.
+    let addr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            vcpu_fd.as_raw_fd(),
+            0,
+        )
+    };
+    if addr == libc::MAP_FAILED {
+        bail!(
+            "Failed to map kvm_run of vcpu, error is {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    // SAFETY: The kvm_run area is mapped above, and `internal` is valid for
+    // KVM_EXIT_INTERNAL_ERROR.
+    let internal = unsafe { (*(addr as *const kvm_run)).__bindgen_anon_1.internal };
+    // SAFETY: The area is mapped above with the length.
+    unsafe { libc::munmap(addr, len) };
+
+    let ndata = std::cmp::min(internal.ndata as usize, internal.data.len());
+    Ok((internal.suberror, internal.data[..ndata].to_vec()))
 }
 
 pub static KVM_FDS: Lazy<ArcSwap<KVMFds>> = Lazy::new(|| ArcSwap::from(Arc::new(KVMFds::new())));
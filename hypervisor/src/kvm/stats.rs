@@ -0,0 +1,136 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+
+use anyhow::{bail, Context, Result};
+use vmm_sys_util::ioctl::ioctl;
+
+use super::KVM_GET_STATS_FD;
+
+// See: https://elixir.bootlin.com/linux/v5.14/source/include/uapi/linux/kvm.h#L1948
+/// Size of `struct kvm_stats_header`.
+const STATS_HEADER_SIZE: usize = 24;
+/// Size of `struct kvm_stats_desc` without the name.
+const STATS_DESC_SIZE: usize = 16;
+
+/// The binary statistics of a kvm vcpu, which are read from the file descriptor
+/// returned by `KVM_GET_STATS_FD`.
+pub struct KvmStats {
+    file: File,
+    /// Offset in the file of each statistic with a single value, indexed by name.
+    offsets: HashMap<String, u64>,
+}
+
+impl KvmStats {
+    /// Open the binary statistics of the kvm object `fd`, which requires
+    /// `KVM_CAP_BINARY_STATS_FD`.
+    pub fn new<F: AsRawFd>(fd: &F) -> Result<Self> {
+        // SAFETY: The fd is a valid kvm fd and KVM_GET_STATS_FD has no argument.
+        let ret = unsafe { ioctl(fd, KVM_GET_STATS_FD()) };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| "Failed to get kvm stats fd");
+        }
+        // SAFETY: The fd is just returned by kvm and owned by nobody else.
+        let file = unsafe { File::from_raw_fd(ret) };
+
+        let mut header = [0_u8; STATS_HEADER_SIZE];
+        file.read_exact_at(&mut header, 0)
+            .with_context(|| "Failed to read kvm stats header")?;
+        let field = |index: usize| {
+            u32::from_ne_bytes(header[index * 4..index * 4 + 4].try_into().unwrap()) as usize
+        };
+        let (name_size, num_desc, desc_offset, data_offset) =
+            (field(1), field(2), field(4), field(5));
+
+        let mut descs = vec![0_u8; num_desc * (STATS_DESC_SIZE + name_size)];
+        file.read_exact_at(&mut descs, desc_offset as u64)
+            .with_context(|| "Failed to read kvm stats descriptors")?;
+        let offsets = parse_stats_descs(&descs, name_size, data_offset as u64)?;
+
+        Ok(KvmStats { file, offsets })
+    }
+
+    /// Get the value of statistic `name`, `None` if it's not provided by kvm.
+    pub fn get(&self, name: &str) -> Option<u64> {
+        let offset = self.offsets.get(name)?;
+        let mut data = [0_u8; 8];
+        self.file.read_exact_at(&mut data, *offset).ok()?;
+        Some(u64::from_ne_bytes(data))
+    }
+}
+
+/// Parse the descriptors of kvm binary statistics, and return the offsets of
+/// the statistics with a single value.
+fn parse_stats_descs(
+    descs: &[u8],
+    name_size: usize,
+    data_offset: u64,
+) -> Result<HashMap<String, u64>> {
+    let descs = descs.chunks_exact(STATS_DESC_SIZE + name_size);
+    if name_size == 0 || !descs.remainder().is_empty() {
+        bail!("Invalid kvm stats descriptors");
+    }
+
+    let mut offsets = HashMap::new();
+    for desc in descs {
+        let size = u16::from_ne_bytes(desc[6..8].try_into().unwrap());
+        let offset = u32::from_ne_bytes(desc[8..12].try_into().unwrap());
+        // Histograms have more than one value.
+        if size != 1 {
+            continue;
+        }
+        let name = &desc[STATS_DESC_SIZE..];
+        let len = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+        offsets.insert(
+            String::from_utf8_lossy(&name[..len]).to_string(),
+            data_offset + u64::from(offset),
+        );
+    }
+    Ok(offsets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_desc(size: u16, offset: u32, name: &str, name_size: usize) -> Vec<u8> {
+        let mut desc = Vec::new();
+        desc.extend_from_slice(&0_u32.to_ne_bytes());
+        desc.extend_from_slice(&0_i16.to_ne_bytes());
+        desc.extend_from_slice(&size.to_ne_bytes());
+        desc.extend_from_slice(&offset.to_ne_bytes());
+        desc.extend_from_slice(&0_u32.to_ne_bytes());
+        desc.extend_from_slice(name.as_bytes());
+        desc.resize(STATS_DESC_SIZE + name_size, 0);
+        desc
+    }
+
+    #[test]
+    fn test_parse_stats_descs() {
+        let mut descs = stats_desc(1, 0, "exits", 48);
+        descs.extend(stats_desc(32, 8, "halt_poll_hist_ns", 48));
+        descs.extend(stats_desc(1, 264, "halt_wait_ns", 48));
+        let offsets = parse_stats_descs(&descs, 48, 0x1000).unwrap();
+        assert_eq!(offsets.len(), 2);
+        assert_eq!(offsets.get("exits"), Some(&0x1000));
+        assert_eq!(offsets.get("halt_wait_ns"), Some(&0x1108));
+        assert!(!offsets.contains_key("halt_poll_hist_ns"));
+
+        assert!(parse_stats_descs(&descs[..100], 48, 0x1000).is_err());
+        assert!(parse_stats_descs(&descs, 0, 0x1000).is_err());
+    }
+}
@@ -0,0 +1,862 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+//! Server of GDB remote serial protocol, to debug guest with gdb.
+//!
+//! Each vcpu is a thread of gdb, whose thread id is the vcpu index plus 1.
+//! Memory is accessed with guest virtual address, translated by the page
+//! table of the selected vcpu. Software breakpoints are `int3` written to guest
+//! memory, and hardware breakpoints use debug registers.
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+
+use anyhow::{anyhow, bail, Context, Result};
+use kvm_bindings::kvm_debug_exit_arch;
+use log::{error, info, warn};
+use vmm_sys_util::eventfd::EventFd;
+
+use address_space::{AddressSpace, GuestAddress};
+use cpu::{DebugExitHandler, GuestDebug, CPU, MAX_HW_BREAKPOINTS};
+use machine_manager::machine::{get_stop_reason, set_stop_reason, MachineInterface, StopReason};
+
+/// Signal numbers reported to gdb in stop reply.
+const GDB_SIGINT: u8 = 2;
+const GDB_SIGTRAP: u8 = 5;
+/// Byte sent by gdb to interrupt the running guest.
+const GDB_INTERRUPT: u8 = 0x03;
+/// Instruction of software breakpoint.
+const INT3: u8 = 0xcc;
+/// Vector of `#BP` exception.
+const BP_VECTOR: u32 = 3;
+const PAGE_SIZE: u64 = 4096;
+/// General registers (rax-r15, rip), eflags and segment selectors (cs, ss, ds,
+/// es, fs, gs), in the order of gdb `i386:x86-64` core registers.
+const GDB_NUM_GP_REGS: usize = 17;
+const GDB_NUM_REGS: usize = 24;
+const GDB_REGS_SIZE: usize = GDB_NUM_GP_REGS * 8 + (GDB_NUM_REGS - GDB_NUM_GP_REGS) * 4;
+
+/// Debug state shared by gdbstub and vcpu threads.
+#[derive(Default)]
+struct DebugState {
+    /// Software breakpoints, with the original byte at the address.
+    sw_breakpoints: HashMap<u64, u8>,
+    /// Addresses of hardware breakpoints.
+    hw_breakpoints: Vec<u64>,
+    /// Index of the vcpu to single step.
+    step_cpu: Option<usize>,
+    /// Index of the vcpu which trapped for debug last time.
+    stopped_cpu: Option<usize>,
+}
+
+impl DebugState {
+    fn guest_debug(&self, cpu_index: usize) -> GuestDebug {
+        GuestDebug {
+            single_step: self.step_cpu == Some(cpu_index),
+            hw_breakpoints: self.hw_breakpoints.clone(),
+            inject_bp: false,
+        }
+    }
+}
+
+/// What to do after handling a command from gdb.
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Reply(String),
+    Resume,
+    Detach,
+    Kill,
+}
+
+/// Packet received from gdb.
+#[derive(Debug, PartialEq, Eq)]
+enum Packet {
+    Command(String),
+    Interrupt,
+}
+
+/// Connection with gdb, which buffers the received data.
+struct Connection<T: Read + Write> {
+    stream: T,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<T: Read + Write> Connection<T> {
+    fn new(stream: T) -> Self {
+        Connection {
+            stream,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    fn has_buffered(&self) -> bool {
+        self.pos < self.buf.len()
+    }
+
+    /// Read one byte, `None` if the connection is closed.
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        if !self.has_buffered() {
+            self.buf.resize(4096, 0);
+            let len = loop {
+                match self.stream.read(&mut self.buf) {
+                    Ok(len) => break len,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e).with_context(|| "Failed to read from gdb"),
+                }
+            };
+            self.buf.truncate(len);
+            self.pos = 0;
+            if len == 0 {
+                return Ok(None);
+            }
+        }
+        self.pos += 1;
+        Ok(Some(self.buf[self.pos - 1]))
+    }
+
+    /// Read the next packet, `None` if the connection is closed. Packets with
+    /// bad checksum are dropped and requested to be retransmitted.
+    fn read_packet(&mut self) -> Result<Option<Packet>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(GDB_INTERRUPT) => return Ok(Some(Packet::Interrupt)),
+                Some(b'$') => {}
+                // Acks and other noise.
+                Some(_) => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(b) => data.push(b),
+                }
+            }
+            let mut sum = [0_u8; 2];
+            for b in sum.iter_mut() {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(v) => *b = v,
+                }
+            }
+
+            let sum = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+            if sum != Some(checksum(&data)) {
+                warn!("Drop gdb packet with bad checksum");
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+            self.stream.write_all(b"+")?;
+            return Ok(Some(Packet::Command(
+                String::from_utf8_lossy(&data).to_string(),
+            )));
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> Result<()> {
+        self.stream
+            .write_all(encode_packet(data).as_bytes())
+            .with_context(|| "Failed to send packet to gdb")
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0_u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn encode_packet(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum(data.as_bytes()))
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|b| {
+            std::str::from_utf8(b)
+                .ok()
+                .filter(|b| b.len() == 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+                .with_context(|| format!("Invalid hex string {}", s))
+        })
+        .collect()
+}
+
+fn parse_u64(s: &str) -> Result<u64> {
+    u64::from_str_radix(s, 16).with_context(|| format!("Invalid hex number {}", s))
+}
+
+/// Parse `<addr>,<length>` of memory access and breakpoint commands.
+fn parse_addr_len(s: &str) -> Result<(u64, u64)> {
+    let (addr, len) = s
+        .split_once(',')
+        .with_context(|| format!("Invalid address and length {}", s))?;
+    Ok((parse_u64(addr)?, parse_u64(len)?))
+}
+
+/// Parse thread id, `None` means all threads (-1) or any thread (0).
+fn parse_thread_id(s: &str) -> Result<Option<usize>> {
+    if s == "-1" || s == "0" {
+        return Ok(None);
+    }
+    let id = usize::from_str_radix(s, 16).with_context(|| format!("Invalid thread id {}", s))?;
+    Ok(Some(id - 1))
+}
+
+fn stop_reply(signal: u8, cpu_index: usize) -> String {
+    format!("T{:02x}thread:{:x};", signal, cpu_index + 1)
+}
+
+/// Get the offset and size of register `reg` in the data of `g` packet.
+fn register_range(reg: usize) -> Option<(usize, usize)> {
+    if reg < GDB_NUM_GP_REGS {
+        Some((reg * 8, 8))
+    } else if reg < GDB_NUM_REGS {
+        Some((GDB_NUM_GP_REGS * 8 + (reg - GDB_NUM_GP_REGS) * 4, 4))
+    } else {
+        None
+    }
+}
+
+fn read_registers(cpu: &CPU) -> Result<Vec<u8>> {
+    let regs = cpu.fd().get_regs()?;
+    let sregs = cpu.fd().get_sregs()?;
+    let mut data = Vec::with_capacity(GDB_REGS_SIZE);
+    for reg in [
+        regs.rax, regs.rbx, regs.rcx, regs.rdx, regs.rsi, regs.rdi, regs.rbp, regs.rsp, regs.r8,
+        regs.r9, regs.r10, regs.r11, regs.r12, regs.r13, regs.r14, regs.r15, regs.rip,
+    ] {
+        data.extend_from_slice(&reg.to_le_bytes());
+    }
+    data.extend_from_slice(&(regs.rflags as u32).to_le_bytes());
+    for seg in [sregs.cs, sregs.ss, sregs.ds, sregs.es, sregs.fs, sregs.gs] {
+        data.extend_from_slice(&u32::from(seg.selector).to_le_bytes());
+    }
+    Ok(data)
+}
+
+/// Write the general registers, rip and eflags. Segment registers can't be
+/// changed by gdb.
+fn write_registers(cpu: &CPU, data: &[u8]) -> Result<()> {
+    if data.len() < GDB_NUM_GP_REGS * 8 + 4 {
+        bail!("Too short register data with length {}", data.len());
+    }
+    let reg = |n: usize| {
+        let mut bytes = [0_u8; 8];
+        bytes.copy_from_slice(&data[n * 8..n * 8 + 8]);
+        u64::from_le_bytes(bytes)
+    };
+
+    let mut regs = cpu.fd().get_regs()?;
+    for (i, r) in [
+        &mut regs.rax,
+        &mut regs.rbx,
+        &mut regs.rcx,
+        &mut regs.rdx,
+        &mut regs.rsi,
+        &mut regs.rdi,
+        &mut regs.rbp,
+        &mut regs.rsp,
+        &mut regs.r8,
+        &mut regs.r9,
+        &mut regs.r10,
+        &mut regs.r11,
+        &mut regs.r12,
+        &mut regs.r13,
+        &mut regs.r14,
+        &mut regs.r15,
+        &mut regs.rip,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        *r = reg(i);
+    }
+    let mut eflags = [0_u8; 4];
+    eflags.copy_from_slice(&data[GDB_NUM_GP_REGS * 8..GDB_NUM_GP_REGS * 8 + 4]);
+    regs.rflags = u64::from(u32::from_le_bytes(eflags));
+    cpu.fd().set_regs(&regs)?;
+    Ok(())
+}
+
+/// Pause VM for debugging. The stop reason is kept if VM is not running.
+fn pause_for_debug(vm: &Weak<Mutex<dyn MachineInterface + Send + Sync>>) {
+    if let Some(vm) = vm.upgrade() {
+        let reason = get_stop_reason();
+        set_stop_reason(StopReason::Debug);
+        if !vm.lock().unwrap().pause() {
+            set_stop_reason(reason);
+        }
+    }
+}
+
+struct GdbStub {
+    vm: Weak<Mutex<dyn MachineInterface + Send + Sync>>,
+    cpus: Vec<Arc<CPU>>,
+    sys_mem: Arc<AddressSpace>,
+    state: Arc<Mutex<DebugState>>,
+    /// Notified by vcpu when it traps for debug.
+    stop_evt: Arc<EventFd>,
+    /// Index of the vcpu to access registers and memory, selected by `Hg`.
+    cur_cpu: usize,
+}
+
+impl GdbStub {
+    fn debug_handler(&self) -> DebugExitHandler {
+        let vm = self.vm.clone();
+        let state = self.state.clone();
+        let stop_evt = self.stop_evt.clone();
+        Arc::new(move |cpu: &CPU, debug: &kvm_debug_exit_arch| {
+            let cpu_index = cpu.id() as usize;
+            let mut locked_state = state.lock().unwrap();
+            if debug.exception == BP_VECTOR && !locked_state.sw_breakpoints.contains_key(&debug.pc)
+            {
+                // The `int3` is used by guest itself.
+                let mut guest_debug = locked_state.guest_debug(cpu_index);
+                drop(locked_state);
+                guest_debug.inject_bp = true;
+                if let Err(e) = cpu.set_guest_debug(Some(&guest_debug)) {
+                    error!("Failed to reinject #BP to vcpu{}: {:?}", cpu_index, e);
+                }
+                return;
+            }
+            locked_state.stopped_cpu = Some(cpu_index);
+            drop(locked_state);
+
+            pause_for_debug(&vm);
+            if let Err(e) = stop_evt.write(1) {
+                error!("Failed to notify gdbstub: {:?}", e);
+            }
+        })
+    }
+
+    fn cpu(&self, cpu_index: usize) -> Result<&Arc<CPU>> {
+        self.cpus
+            .get(cpu_index)
+            .with_context(|| format!("Invalid vcpu index {}", cpu_index))
+    }
+
+    fn update_guest_debug(&self) -> Result<()> {
+        let locked_state = self.state.lock().unwrap();
+        for (index, cpu) in self.cpus.iter().enumerate() {
+            cpu.set_guest_debug(Some(&locked_state.guest_debug(index)))?;
+        }
+        Ok(())
+    }
+
+    /// Translate guest virtual address to guest physical address.
+    fn translate(&self, gva: u64) -> Result<u64> {
+        let tr = self.cpu(self.cur_cpu)?.fd().translate_gva(gva)?;
+        if tr.valid == 0 {
+            bail!("Guest virtual address 0x{:x} is not mapped", gva);
+        }
+        Ok(tr.physical_address)
+    }
+
+    fn read_memory(&self, addr: u64, len: u64) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(len as usize);
+        let mut offset = 0;
+        while offset < len {
+            let gva = addr.wrapping_add(offset);
+            let count = std::cmp::min(len - offset, PAGE_SIZE - (gva & (PAGE_SIZE - 1)));
+            let mut chunk = vec![0_u8; count as usize];
+            self.sys_mem.read(
+                &mut chunk.as_mut_slice(),
+                GuestAddress(self.translate(gva)?),
+                count,
+            )?;
+            data.append(&mut chunk);
+            offset += count;
+        }
+        Ok(data)
+    }
+
+    fn write_memory(&self, addr: u64, data: &[u8]) -> Result<()> {
+        let mut offset = 0;
+        while offset < data.len() {
+            let gva = addr.wrapping_add(offset as u64);
+            let count = std::cmp::min(
+                (data.len() - offset) as u64,
+                PAGE_SIZE - (gva & (PAGE_SIZE - 1)),
+            );
+            let mut chunk = &data[offset..offset + count as usize];
+            self.sys_mem
+                .write(&mut chunk, GuestAddress(self.translate(gva)?), count)?;
+            offset += count as usize;
+        }
+        Ok(())
+    }
+
+    fn insert_breakpoint(&self, kind: &str, addr: u64) -> Result<()> {
+        let mut locked_state = self.state.lock().unwrap();
+        match kind {
+            "0" => {
+                if locked_state.sw_breakpoints.contains_key(&addr) {
+                    return Ok(());
+                }
+                let orig = self.read_memory(addr, 1)?[0];
+                self.write_memory(addr, &[INT3])?;
+                locked_state.sw_breakpoints.insert(addr, orig);
+            }
+            "1" => {
+                if locked_state.hw_breakpoints.contains(&addr) {
+                    return Ok(());
+                }
+                if locked_state.hw_breakpoints.len() >= MAX_HW_BREAKPOINTS {
+                    bail!("No free hardware breakpoint");
+                }
+                locked_state.hw_breakpoints.push(addr);
+            }
+            _ => bail!("Unsupported breakpoint type {}", kind),
+        }
+        Ok(())
+    }
+
+    fn remove_breakpoint(&self, kind: &str, addr: u64) -> Result<()> {
+        let mut locked_state = self.state.lock().unwrap();
+        match kind {
+            "0" => {
+                if let Some(orig) = locked_state.sw_breakpoints.remove(&addr) {
+                    self.write_memory(addr, &[orig])?;
+                }
+            }
+            "1" => locked_state.hw_breakpoints.retain(|a| *a != addr),
+            _ => bail!("Unsupported breakpoint type {}", kind),
+        }
+        Ok(())
+    }
+
+    /// Handle a command, the errors are replied to gdb.
+    fn handle_command(&mut self, cmd: &str) -> Action {
+        match self.do_command(cmd) {
+            Ok(action) => action,
+            Err(e) => {
+                warn!("Failed to handle gdb command {}: {:?}", cmd, e);
+                Action::Reply("E01".to_string())
+            }
+        }
+    }
+
+    fn do_command(&mut self, cmd: &str) -> Result<Action> {
+        let op = match cmd.chars().next() {
+            Some(op) => op,
+            None => return Ok(Action::Reply(String::new())),
+        };
+        let args = &cmd[op.len_utf8()..];
+        let reply = match op {
+            '?' => stop_reply(GDB_SIGTRAP, self.cur_cpu),
+            'g' => encode_hex(&read_registers(self.cpu(self.cur_cpu)?)?),
+            'G' => {
+                write_registers(self.cpu(self.cur_cpu)?, &decode_hex(args)?)?;
+                "OK".to_string()
+            }
+            'p' => {
+                let reg = usize::from_str_radix(args, 16)?;
+                match register_range(reg) {
+                    Some((offset, size)) => {
+                        let data = read_registers(self.cpu(self.cur_cpu)?)?;
+                        encode_hex(&data[offset..offset + size])
+                    }
+                    // Tell gdb the register is unavailable.
+                    None => String::new(),
+                }
+            }
+            'P' => {
+                let (reg, value) = args
+                    .split_once('=')
+                    .with_context(|| format!("Invalid register write {}", args))?;
+                let (offset, size) = register_range(usize::from_str_radix(reg, 16)?)
+                    .with_context(|| format!("Invalid register {}", reg))?;
+                let value = decode_hex(value)?;
+                if value.len() != size {
+                    bail!("Invalid size of register {}", reg);
+                }
+                let cpu = self.cpu(self.cur_cpu)?;
+                let mut data = read_registers(cpu)?;
+                data[offset..offset + size].copy_from_slice(&value);
+                write_registers(cpu, &data)?;
+                "OK".to_string()
+            }
+            'm' => {
+                let (addr, len) = parse_addr_len(args)?;
+                encode_hex(&self.read_memory(addr, len)?)
+            }
+            'M' => {
+                let (range, data) = args
+                    .split_once(':')
+                    .with_context(|| format!("Invalid memory write {}", args))?;
+                let (addr, len) = parse_addr_len(range)?;
+                let data = decode_hex(data)?;
+                if data.len() as u64 != len {
+                    bail!("Memory write length {} mismatches data", len);
+                }
+                self.write_memory(addr, &data)?;
+                "OK".to_string()
+            }
+            'c' | 's' => {
+                if !args.is_empty() {
+                    let cpu = self.cpu(self.cur_cpu)?;
+                    let mut regs = cpu.fd().get_regs()?;
+                    regs.rip = parse_u64(args)?;
+                    cpu.fd().set_regs(&regs)?;
+                }
+                let mut locked_state = self.state.lock().unwrap();
+                if op == 's' {
+                    locked_state.step_cpu = Some(locked_state.step_cpu.unwrap_or(self.cur_cpu));
+                } else {
+                    locked_state.step_cpu = None;
+                }
+                return Ok(Action::Resume);
+            }
+            'Z' | 'z' => {
+                let mut fields = args.split(';').next().unwrap_or("").splitn(2, ',');
+                let kind = fields.next().unwrap_or("");
+                let addr = fields
+                    .next()
+                    .with_context(|| format!("Invalid breakpoint {}", args))?;
+                let (addr, _) = parse_addr_len(addr)?;
+                if kind != "0" && kind != "1" {
+                    // Watchpoints are not supported.
+                    return Ok(Action::Reply(String::new()));
+                }
+                if op == 'Z' {
+                    self.insert_breakpoint(kind, addr)?;
+                } else {
+                    self.remove_breakpoint(kind, addr)?;
+                }
+                "OK".to_string()
+            }
+            'H' => {
+                let kind = args.get(..1).unwrap_or("");
+                let id = args.get(1..).unwrap_or("");
+                let cpu_index = parse_thread_id(id)?;
+                if let Some(index) = cpu_index {
+                    self.cpu(index)?;
+                }
+                match kind {
+                    "g" => self.cur_cpu = cpu_index.unwrap_or(self.cur_cpu),
+                    "c" => self.state.lock().unwrap().step_cpu = cpu_index,
+                    _ => bail!("Invalid thread operation {}", kind),
+                }
+                "OK".to_string()
+            }
+            'T' => {
+                let index = parse_thread_id(args)?.with_context(|| "Invalid thread")?;
+                self.cpu(index)?;
+                "OK".to_string()
+            }
+            'q' => match args.split(':').next().unwrap_or("") {
+                "Supported" => "PacketSize=1000".to_string(),
+                "Attached" => "1".to_string(),
+                "C" => format!("QC{:x}", self.cur_cpu + 1),
+                "fThreadInfo" => {
+                    let ids: Vec<String> = (1..=self.cpus.len())
+                        .map(|id| format!("{:x}", id))
+                        .collect();
+                    format!("m{}", ids.join(","))
+                }
+                "sThreadInfo" => "l".to_string(),
+                _ => String::new(),
+            },
+            'D' => return Ok(Action::Detach),
+            'k' => return Ok(Action::Kill),
+            _ => String::new(),
+        };
+        Ok(Action::Reply(reply))
+    }
+
+    /// Stop VM and enable guest debug when gdb is connected.
+    fn attach(&mut self) -> Result<()> {
+        pause_for_debug(&self.vm);
+        *self.state.lock().unwrap() = DebugState::default();
+        self.cur_cpu = 0;
+        let handler = self.debug_handler();
+        for cpu in self.cpus.iter() {
+            cpu.set_debug_handler(Some(handler.clone()));
+        }
+        self.update_guest_debug()
+    }
+
+    /// Remove breakpoints and disable guest debug when gdb is disconnected. VM
+    /// continues if it's stopped by debugger.
+    fn detach(&mut self) {
+        pause_for_debug(&self.vm);
+        let sw_breakpoints: Vec<u64> = self
+            .state
+            .lock()
+            .unwrap()
+            .sw_breakpoints
+            .keys()
+            .copied()
+            .collect();
+        for addr in sw_breakpoints {
+            if let Err(e) = self.remove_breakpoint("0", addr) {
+                error!("Failed to remove breakpoint at 0x{:x}: {:?}", addr, e);
+            }
+        }
+        for cpu in self.cpus.iter() {
+            cpu.set_debug_handler(None);
+            if let Err(e) = cpu.set_guest_debug(None) {
+                error!("Failed to disable guest debug: {:?}", e);
+            }
+        }
+
+        if get_stop_reason() == StopReason::Debug {
+            if let Some(vm) = self.vm.upgrade() {
+                vm.lock().unwrap().resume();
+            }
+        }
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        self.state.lock().unwrap().stopped_cpu = None;
+        // Drop the stale notification, the eventfd is nonblocking.
+        let _ = self.stop_evt.read();
+        self.update_guest_debug()?;
+        if let Some(vm) = self.vm.upgrade() {
+            vm.lock().unwrap().resume();
+        }
+        Ok(())
+    }
+
+    /// Wait for the vcpu to trap for debug or gdb to interrupt, return the stop
+    /// reply, `None` if gdb is disconnected.
+    fn wait_for_stop(&mut self, conn: &mut Connection<TcpStream>) -> Result<Option<String>> {
+        loop {
+            if !conn.has_buffered() {
+                let mut fds = [
+                    libc::pollfd {
+                        fd: conn.stream.as_raw_fd(),
+                        events: libc::POLLIN,
+                        revents: 0,
+                    },
+                    libc::pollfd {
+                        fd: self.stop_evt.as_raw_fd(),
+                        events: libc::POLLIN,
+                        revents: 0,
+                    },
+                ];
+                // SAFETY: fds is valid during the call.
+                let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+                if ret < 0 {
+                    let e = std::io::Error::last_os_error();
+                    if e.kind() == ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(anyhow!(e)).with_context(|| "Failed to wait for guest stop");
+                }
+                if fds[1].revents & libc::POLLIN != 0 {
+                    let _ = self.stop_evt.read();
+                    if let Some(index) = self.state.lock().unwrap().stopped_cpu.take() {
+                        self.cur_cpu = index;
+                        return Ok(Some(stop_reply(GDB_SIGTRAP, index)));
+                    }
+                }
+                if fds[0].revents == 0 {
+                    continue;
+                }
+            }
+
+            match conn.read_byte()? {
+                None => return Ok(None),
+                Some(GDB_INTERRUPT) => {
+                    pause_for_debug(&self.vm);
+                    return Ok(Some(stop_reply(GDB_SIGINT, self.cur_cpu)));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    fn serve(&mut self, conn: &mut Connection<TcpStream>) -> Result<()> {
+        while let Some(packet) = conn.read_packet()? {
+            let cmd = match packet {
+                Packet::Command(cmd) => cmd,
+                // The guest has been stopped.
+                Packet::Interrupt => continue,
+            };
+            match self.handle_command(&cmd) {
+                Action::Reply(reply) => conn.send_packet(&reply)?,
+                Action::Resume => {
+                    self.resume()?;
+                    match self.wait_for_stop(conn)? {
+                        Some(reply) => conn.send_packet(&reply)?,
+                        None => break,
+                    }
+                }
+                Action::Detach => {
+                    conn.send_packet("OK")?;
+                    break;
+                }
+                Action::Kill => {
+                    if let Some(vm) = self.vm.upgrade() {
+                        vm.lock().unwrap().destroy();
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_connection(&mut self, stream: TcpStream) {
+        let _ = stream.set_nodelay(true);
+        let mut conn = Connection::new(stream);
+        let ret = self.attach().and_then(|_| self.serve(&mut conn));
+        if let Err(e) = ret {
+            error!("Gdb connection failed: {:?}", e);
+        }
+        self.detach();
+        info!("Gdb disconnected");
+    }
+}
+
+/// Start gdbstub in a new thread, which serves one gdb connection at a time.
+///
+/// # Arguments
+///
+/// * `addr` - Tcp address to listen on.
+/// * `vm` - The VM to debug.
+/// * `cpus` - Vcpus of the VM.
+/// * `sys_mem` - System address space of the VM.
+pub fn start_gdbstub(
+    addr: &str,
+    vm: Arc<Mutex<dyn MachineInterface + Send + Sync>>,
+    cpus: &[Arc<CPU>],
+    sys_mem: &Arc<AddressSpace>,
+) -> Result<()> {
+    let listener =
+        TcpListener::bind(addr).with_context(|| format!("Failed to bind gdbstub to {}", addr))?;
+    let mut gdbstub = GdbStub {
+        vm: Arc::downgrade(&vm),
+        cpus: cpus.to_vec(),
+        sys_mem: sys_mem.clone(),
+        state: Arc::new(Mutex::new(DebugState::default())),
+        stop_evt: Arc::new(EventFd::new(libc::EFD_NONBLOCK)?),
+        cur_cpu: 0,
+    };
+
+    thread::Builder::new()
+        .name("gdbstub".to_string())
+        .spawn(move || loop {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    info!("Gdb connected from {}", peer);
+                    gdbstub.handle_connection(stream);
+                }
+                Err(e) => {
+                    error!("Failed to accept gdb connection: {:?}", e);
+                    break;
+                }
+            }
+        })
+        .with_context(|| "Failed to create thread for gdbstub")?;
+    info!("Gdbstub is listening on {}", addr);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockStream {
+        input: Vec<u8>,
+        output: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = self.input.len().min(buf.len());
+            buf[..len].copy_from_slice(&self.input[..len]);
+            self.input.drain(..len);
+            Ok(len)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_gdb_packet() {
+        assert_eq!(encode_packet("OK"), "$OK#9a");
+        assert_eq!(encode_packet(""), "$#00");
+
+        let input = b"+$qSupported:swbreak+#8b\x03$g#00$?#3f".to_vec();
+        let mut conn = Connection::new(MockStream {
+            input,
+            output: Vec::new(),
+        });
+        assert_eq!(
+            conn.read_packet().unwrap(),
+            Some(Packet::Command("qSupported:swbreak+".to_string()))
+        );
+        assert_eq!(conn.read_packet().unwrap(), Some(Packet::Interrupt));
+        // The packet with bad checksum is dropped.
+        assert_eq!(
+            conn.read_packet().unwrap(),
+            Some(Packet::Command("?".to_string()))
+        );
+        assert_eq!(conn.read_packet().unwrap(), None);
+        assert_eq!(conn.stream.output, b"+-+");
+
+        conn.send_packet("T05thread:1;").unwrap();
+        assert_eq!(&conn.stream.output[3..], b"$T05thread:1;#d7");
+    }
+
+    #[test]
+    fn test_gdb_parse() {
+        assert_eq!(encode_hex(&[0x0, 0xcc, 0x1f]), "00cc1f");
+        assert_eq!(decode_hex("00cc1f").unwrap(), vec![0x0, 0xcc, 0x1f]);
+        assert!(decode_hex("00c").is_err());
+        assert!(decode_hex("zz").is_err());
+
+        assert_eq!(
+            parse_addr_len("ffffffff81000000,40").unwrap(),
+            (0xffff_ffff_8100_0000, 0x40)
+        );
+        assert!(parse_addr_len("1000").is_err());
+
+        assert_eq!(parse_thread_id("-1").unwrap(), None);
+        assert_eq!(parse_thread_id("0").unwrap(), None);
+        assert_eq!(parse_thread_id("a").unwrap(), Some(9));
+        assert!(parse_thread_id("x").is_err());
+
+        assert_eq!(stop_reply(GDB_SIGTRAP, 1), "T05thread:2;");
+        assert_eq!(register_range(0), Some((0, 8)));
+        assert_eq!(register_range(16), Some((128, 8)));
+        assert_eq!(register_range(17), Some((136, 4)));
+        assert_eq!(register_range(23), Some((160, 4)));
+        assert_eq!(register_range(24), None);
+        assert_eq!(GDB_REGS_SIZE, 164);
+    }
+}
@@ -11,6 +11,8 @@
 // See the Mulan PSL v2 for more details.
 
 pub mod error;
+#[cfg(target_arch = "x86_64")]
+mod gdbstub;
 mod micro_vm;
 pub mod standard_vm;
 #[cfg(target_arch = "x86_64")]
@@ -21,7 +23,8 @@ use std::collections::{BTreeMap, HashMap};
 use std::fs::{remove_file, File};
 use std::net::TcpListener;
 use std::ops::Deref;
-use std::os::unix::net::UnixListener;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::Path;
 use std::sync::{Arc, Barrier, Condvar, Mutex, Weak};
 #[cfg(not(target_env = "musl"))]
@@ -29,13 +32,14 @@ use std::time::Duration;
 
 #[cfg(not(target_env = "musl"))]
 use devices::misc::scream::Scream;
-use log::warn;
+use log::{error, warn};
 #[cfg(not(target_env = "musl"))]
 use machine_manager::config::scream::parse_scream;
 #[cfg(not(target_env = "musl"))]
 use machine_manager::event_loop::EventLoop;
 #[cfg(not(target_env = "musl"))]
 use ui::console::{get_run_stage, VmRunningStage};
+use util::cgroup::CpuCgroup;
 use util::file::{clear_file, lock_file, unlock_file};
 #[cfg(not(target_env = "musl"))]
 use vmm_sys_util::eventfd::EventFd;
@@ -45,13 +49,17 @@ pub use micro_vm::LightMachine;
 #[cfg(target_arch = "x86_64")]
 use address_space::KvmIoListener;
 use address_space::{
-    create_backend_mem, create_default_mem, AddressSpace, KvmMemoryListener, Region,
+    create_backend_mem, create_default_mem, AddressSpace, KvmMemoryListener, ProtectedMemory,
+    Region,
 };
+#[cfg(target_arch = "aarch64")]
+use address_space::{GuestAddress, HostMemMapping};
 pub use anyhow::Result;
 use anyhow::{anyhow, bail, Context};
-#[cfg(target_arch = "aarch64")]
-use cpu::CPUFeatures;
+use block_backend::nbd::NbdServer;
 use cpu::{ArchCPU, CPUBootConfig, CPUInterface, CPUTopology, CPU};
+#[cfg(target_arch = "aarch64")]
+use cpu::{CPUCaps, CPUFeatures, PVTIME_SIZE_PER_CPU};
 use devices::legacy::FwCfgOps;
 #[cfg(target_arch = "aarch64")]
 use devices::InterruptController;
@@ -63,48 +71,63 @@ use devices::usb::{
 };
 use devices::ScsiDisk::{ScsiDevice, SCSI_TYPE_DISK, SCSI_TYPE_ROM};
 use hypervisor::kvm::KVM_FDS;
+#[cfg(target_arch = "aarch64")]
+use machine_manager::config::BootSource;
 use machine_manager::config::{
     complete_numa_node, get_multi_function, get_pci_bdf, parse_balloon, parse_blk, parse_demo_dev,
-    parse_device_id, parse_fs, parse_net, parse_numa_distance, parse_numa_mem, parse_rng_dev,
-    parse_root_port, parse_scsi_controller, parse_scsi_device, parse_vfio,
+    parse_device_id, parse_fs, parse_hmat_lb, parse_net, parse_numa_distance, parse_numa_mem,
+    parse_rng_dev, parse_root_port, parse_scsi_controller, parse_scsi_device, parse_vfio,
     parse_vhost_user_blk_pci, parse_virtio_serial, parse_virtserialport, parse_vsock,
-    BootIndexInfo, DriveFile, Incoming, MachineMemConfig, MigrateMode, NumaConfig, NumaDistance,
-    NumaNode, NumaNodes, PFlashConfig, PciBdf, SerialConfig, VfioConfig, VmConfig, FAST_UNPLUG_ON,
-    MAX_VIRTIO_QUEUE,
+    BootIndexInfo, DiskFormat, DriveFile, Incoming, MachineMemConfig, MigrateMode, NumaConfig,
+    NumaDistance, NumaNode, NumaNodes, PFlashConfig, PciBdf, SerialConfig, VfioConfig, VmConfig,
+    FAST_UNPLUG_ON, MAX_VIRTIO_QUEUE,
 };
 #[cfg(not(target_env = "musl"))]
 use machine_manager::config::{
     parse_gpu, parse_usb_camera, parse_usb_host, parse_usb_keyboard, parse_usb_storage,
     parse_usb_tablet, parse_xhci,
 };
-use machine_manager::machine::{KvmVmState, MachineInterface};
-use migration::MigrationManager;
+use machine_manager::machine::{
+    get_stop_reason, set_stop_reason, KvmVmState, MachineInterface, MachineLifecycle, StopReason,
+};
+use machine_manager::qmp::{qmp_schema, Response};
+use migration::{rdma::RdmaListener, MigrationManager};
 use pci::{demo_dev::DemoDev, PciBus, PciDevOps, PciHost, RootPort};
 use smbios::smbios_table::{build_smbios_ep30, SmbiosTable};
 use smbios::{SMBIOS_ANCHOR_FILE, SMBIOS_TABLE_FILE};
 use standard_vm::Result as StdResult;
 pub use standard_vm::StdMachine;
 use sysbus::{SysBus, SysBusDevOps, SysBusDevType};
+#[cfg(target_arch = "aarch64")]
+use util::device_tree::{self, CompileFDT, DeviceTree, FdtBuilder};
 use util::{
     arg_parser,
-    seccomp::{BpfRule, SeccompOpt, SyscallFilter},
+    seccomp::{BpfRule, SeccompOpt, SyscallFilter, ThreadKind},
+    unix::raise_memlock_limit,
 };
 use vfio::{VfioDevice, VfioPciDevice};
 #[cfg(not(target_env = "musl"))]
 use virtio::Gpu;
 use virtio::{
-    balloon_allow_list, find_port_by_nr, vhost, Balloon, Block, BlockState, Rng, RngState,
+    balloon_allow_list, find_port_by_nr, set_guest_agent_port, vhost, Balloon, BalloonState, Block,
+    BlockState, Rng, RngState,
     ScsiCntlr::{scsi_cntlr_create_scsi_bus, ScsiCntlr},
     Serial, SerialPort, VhostKern, VhostUser, VirtioDevice, VirtioMmioDevice, VirtioMmioState,
     VirtioNetState, VirtioPciDevice, VirtioSerialState, VIRTIO_TYPE_CONSOLE,
 };
 
+/// The writable property of virtio net device to set its link status.
+const QOM_LINK_PROPERTY: &str = "link";
+
 pub trait MachineOps {
     fn build_smbios(&self, fw_cfg: &Arc<Mutex<dyn FwCfgOps>>) -> Result<()> {
-        let smbioscfg = self.get_vm_config().lock().unwrap().smbios.clone();
+        let vm_config = self.get_vm_config();
+        let locked_vm_config = vm_config.lock().unwrap();
+        let smbioscfg = locked_vm_config.smbios.clone();
 
         let mut smbios = SmbiosTable::new();
-        let table = smbios.build_smbios_tables(smbioscfg);
+        let table = smbios.build_smbios_tables(smbioscfg, &locked_vm_config.machine_config);
+        drop(locked_vm_config);
         let ep = build_smbios_ep30(table.len() as u32);
 
         let mut locked_fw_cfg = fw_cfg.lock().unwrap();
@@ -118,11 +141,75 @@ pub trait MachineOps {
         Ok(())
     }
 
+    /// Add the user-defined fw_cfg file entries configured by `-fw_cfg`.
+    ///
+    /// # Arguments
+    ///
+    /// * `fw_cfg` - The fw_cfg device which the file entries are added to.
+    fn add_fw_cfg_files(&self, fw_cfg: &Arc<Mutex<dyn FwCfgOps>>) -> Result<()> {
+        let fw_cfg_configs = self.get_vm_config().lock().unwrap().fw_cfg.clone();
+
+        let mut locked_fw_cfg = fw_cfg.lock().unwrap();
+        for config in fw_cfg_configs {
+            let data = match (&config.file, &config.string) {
+                (Some(file), _) => std::fs::read(file)
+                    .with_context(|| format!("Failed to read fw_cfg file {}", file))?,
+                (None, Some(string)) => string.as_bytes().to_vec(),
+                (None, None) => bail!("No data is provided for fw_cfg {}", config.name),
+            };
+            locked_fw_cfg
+                .add_file_entry(&config.name, data)
+                .with_context(|| format!("Failed to add fw_cfg file entry {}", config.name))?;
+        }
+
+        Ok(())
+    }
+
     fn load_boot_source(&self, fwcfg: Option<&Arc<Mutex<dyn FwCfgOps>>>) -> Result<CPUBootConfig>;
 
     #[cfg(target_arch = "aarch64")]
     fn load_cpu_features(&self, vmcfg: &VmConfig) -> Result<CPUFeatures> {
-        Ok((&vmcfg.machine_config.cpu_config).into())
+        let features: CPUFeatures = (&vmcfg.machine_config.cpu_config).into();
+        features.check(&CPUCaps::init_capabilities())?;
+        Ok(features)
+    }
+
+    /// Map the shared memory of paravirtual time at `range`, and register the
+    /// stolen time structure of each vcpu to kvm.
+    #[cfg(target_arch = "aarch64")]
+    fn init_pvtime(
+        &self,
+        cpus: &[Arc<CPU>],
+        sys_mem: &Arc<AddressSpace>,
+        range: (u64, u64),
+    ) -> Result<()> {
+        if cpus.len() as u64 * PVTIME_SIZE_PER_CPU > range.1 {
+            bail!("Too many vcpus for pv time region");
+        }
+        let pvtime_ram = Arc::new(HostMemMapping::new(
+            GuestAddress(range.0),
+            None,
+            range.1,
+            None,
+            false,
+            false,
+            false,
+        )?);
+        sys_mem
+            .root()
+            .add_subregion(Region::init_ram_region(pvtime_ram, "PvTime"), range.0)
+            .with_context(|| "Failed to add pv time region")?;
+        for cpu in cpus.iter() {
+            cpu.init_pvtime(range.0 + u64::from(cpu.id()) * PVTIME_SIZE_PER_CPU)
+                .with_context(|| format!("Failed to init pv time for vcpu{}", cpu.id()))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn load_cpu_features(&self, vmcfg: &VmConfig) -> Result<CPUFeatures> {
+        CPUFeatures::try_from(&vmcfg.machine_config.cpu_config)
+            .with_context(|| "Failed to load cpu model and features")
     }
 
     /// Init memory of vm to architecture.
@@ -146,7 +233,7 @@ pub trait MachineOps {
         for (_, node) in numa_nodes.as_ref().unwrap().iter().enumerate() {
             for zone in zones.iter() {
                 if zone.id.eq(&node.1.mem_dev) {
-                    let ram = create_backend_mem(zone, thread_num)?;
+                    let ram = create_backend_mem(zone, thread_num, mem_config.mem_lock)?;
                     root.add_subregion_not_update(ram, offset)?;
                     offset += zone.size;
                     break;
@@ -175,9 +262,26 @@ pub trait MachineOps {
         // doing memory prealloc.To avoid affecting memory prealloc performance, create_host_mmaps
         // needs to be invoked first.
         let migrate_info = self.get_migrate_info();
+        if mem_config.mem_lock {
+            let size = std::cmp::max(mem_config.mem_size, mem_config.max_size);
+            if let Some(limit) = raise_memlock_limit(size)? {
+                if limit < size {
+                    warn!(
+                        "The limit of locked memory 0x{:x} is less than guest memory size 0x{:x}, locking may fail without CAP_IPC_LOCK",
+                        limit, size
+                    );
+                }
+            }
+        }
         if migrate_info.0 != MigrateMode::File {
             self.create_machine_ram(mem_config, nr_cpus)?;
         }
+        // The dirty ring must be enabled before any vcpu is created.
+        KVM_FDS
+            .load()
+            .enable_dirty_ring(mem_config.dirty_ring_size)
+            .with_context(|| "Failed to enable KVM dirty ring")?;
+        KVM_FDS.load().enable_manual_dirty_protect()?;
 
         sys_mem
             .register_listener(Arc::new(Mutex::new(KvmMemoryListener::new(
@@ -203,20 +307,45 @@ pub trait MachineOps {
     /// # Arguments
     ///
     /// * `vm` - `MachineInterface` to obtain functions cpu can use.
+    /// * `sys_mem` - System memory which MMIO accesses of vcpus are dispatched to.
     /// * `nr_cpus` - The number of vcpus.
     /// * `boot_cfg` - Boot message generated by reading boot source to guest memory.
+    /// * `vm_config` - VM configuration, for name, pinning, scheduling and cgroup of vcpu threads.
     fn init_vcpu(
         vm: Arc<Mutex<dyn MachineInterface + Send + Sync>>,
+        sys_mem: &Arc<AddressSpace>,
         nr_cpus: u8,
         topology: &CPUTopology,
         boot_cfg: &Option<CPUBootConfig>,
-        #[cfg(target_arch = "aarch64")] vcpu_cfg: &Option<CPUFeatures>,
+        vcpu_cfg: &Option<CPUFeatures>,
+        vm_config: &VmConfig,
     ) -> Result<Vec<Arc<CPU>>>
     where
         Self: Sized,
     {
-        let mut cpus = Vec::<Arc<CPU>>::new();
+        if let Some(ns) = vm_config.machine_config.halt_poll_ns {
+            KVM_FDS.load().set_halt_poll_ns(ns)?;
+        }
+        // Boot source has been loaded, guest memory becomes private before guest runs.
+        if vm_config.machine_config.protected {
+            protect_guest_memory(sys_mem).with_context(|| "Failed to protect guest memory")?;
+        }
+
+        let cpu_cgroup = match vm_config.machine_config.cpu_quota {
+            Some(quota) => {
+                let name = if vm_config.guest_name.is_empty() {
+                    format!("stratovirt-{}", std::process::id())
+                } else {
+                    vm_config.guest_name.clone()
+                };
+                let cgroup = CpuCgroup::new(&name, quota.quota, quota.period, quota.shares)
+                    .with_context(|| "Failed to create cpu cgroup of vcpus")?;
+                Some(Arc::new(cgroup))
+            }
+            None => None,
+        };
 
+        let mut cpus = Vec::<Arc<CPU>>::new();
         for vcpu_id in 0..nr_cpus {
             let vcpu_fd = KVM_FDS
                 .load()
@@ -225,17 +354,33 @@ pub trait MachineOps {
                 .unwrap()
                 .create_vcpu(vcpu_id as u64)
                 .with_context(|| "Create vcpu failed")?;
+            KVM_FDS.load().register_dirty_ring(&vcpu_fd)?;
             #[cfg(target_arch = "aarch64")]
             let arch_cpu = ArchCPU::new(u32::from(vcpu_id));
             #[cfg(target_arch = "x86_64")]
             let arch_cpu = ArchCPU::new(u32::from(vcpu_id), u32::from(nr_cpus));
 
-            let cpu = Arc::new(CPU::new(
+            let mut cpu = CPU::new(
                 Arc::new(vcpu_fd),
                 vcpu_id,
                 Arc::new(Mutex::new(arch_cpu)),
                 vm.clone(),
-            ));
+            );
+            cpu.set_thread_config(
+                &vm_config.guest_name,
+                vm_config.machine_config.cpu_sched,
+                cpu_cgroup.clone(),
+            );
+            cpu.set_mmio_space(sys_mem.clone());
+            let cpu = Arc::new(cpu);
+            if let Some((_, host_cpu)) = vm_config
+                .machine_config
+                .cpu_pin
+                .iter()
+                .find(|(vcpu, _)| *vcpu == vcpu_id)
+            {
+                cpu.set_affinity(*host_cpu)?;
+            }
             cpus.push(cpu.clone());
 
             MigrationManager::register_cpu_instance(cpu::ArchCPU::descriptor(), cpu, vcpu_id);
@@ -243,21 +388,25 @@ pub trait MachineOps {
 
         if let Some(boot_config) = boot_cfg {
             for (cpu_index, cpu) in cpus.iter().enumerate() {
-                cpu.realize(
-                    boot_config,
-                    topology,
-                    #[cfg(target_arch = "aarch64")]
-                    &vcpu_cfg.unwrap_or_default(),
-                )
-                .with_context(|| {
-                    format!(
-                        "Failed to realize arch cpu register/features for CPU {}/KVM",
-                        cpu_index
-                    )
-                })?;
+                cpu.realize(boot_config, topology, &vcpu_cfg.unwrap_or_default())
+                    .with_context(|| {
+                        format!(
+                            "Failed to realize arch cpu register/features for CPU {}/KVM",
+                            cpu_index
+                        )
+                    })?;
             }
         }
 
+        // The CPU features are compared with the destination before migration.
+        #[cfg(target_arch = "x86_64")]
+        MigrationManager::register_cpu_features(
+            vcpu_cfg
+                .unwrap_or_default()
+                .guest_features()
+                .with_context(|| "Failed to get CPU features of guest")?,
+        );
+
         Ok(cpus)
     }
 
@@ -373,20 +522,31 @@ pub trait MachineOps {
         let balloon = Arc::new(Mutex::new(Balloon::new(&device_cfg, sys_mem.clone())));
         Balloon::object_init(balloon.clone());
         if cfg_args.contains("virtio-balloon-device") {
-            let device = VirtioMmioDevice::new(sys_mem, balloon);
+            let device = VirtioMmioDevice::new(sys_mem, balloon.clone());
             self.realize_virtio_mmio_device(device)?;
         } else {
-            let name = device_cfg.id;
+            let name = device_cfg.id.clone();
             let bdf = get_pci_bdf(cfg_args)?;
             let multi_func = get_multi_function(cfg_args)?;
             let (devfn, parent_bus) = self.get_devfn_and_parent_bus(&bdf)?;
             let sys_mem = self.get_sys_mem().clone();
-            let virtio_pci_device =
-                VirtioPciDevice::new(name, devfn, sys_mem, balloon, parent_bus, multi_func);
+            let virtio_pci_device = VirtioPciDevice::new(
+                name,
+                devfn,
+                sys_mem,
+                balloon.clone(),
+                parent_bus,
+                multi_func,
+            );
             virtio_pci_device
                 .realize()
                 .with_context(|| "Failed to add virtio pci balloon device")?;
         }
+        MigrationManager::register_device_instance(
+            BalloonState::descriptor(),
+            balloon,
+            &device_cfg.id,
+        );
 
         Ok(())
     }
@@ -504,12 +664,16 @@ pub trait MachineOps {
             bail!("Repetitive virtio serial port nr {}.", serialport_cfg.nr,);
         }
 
+        let is_guest_agent = serialport_cfg.is_guest_agent();
         let mut serial_port = SerialPort::new(serialport_cfg);
         let port = Arc::new(Mutex::new(serial_port.clone()));
         serial_port.realize()?;
         if !is_console {
             serial_port.chardev.lock().unwrap().set_device(port.clone());
         }
+        if is_guest_agent {
+            set_guest_agent_port(port.clone())?;
+        }
         serial.ports.lock().unwrap().push(port);
 
         Ok(())
@@ -636,7 +800,7 @@ pub trait MachineOps {
         let vm_config = self.get_vm_config();
         let locked_vmconfig = vm_config.lock().unwrap();
         let parent_dev = self
-            .get_pci_dev_by_id_and_type(&locked_vmconfig, None, "nec-usb-xhci")
+            .get_xhci_controller(&locked_vmconfig)
             .with_context(|| "Can not find parent device from pci bus")?;
         let locked_parent_dev = parent_dev.lock().unwrap();
         let xhci_pci = locked_parent_dev
@@ -880,6 +1044,10 @@ pub trait MachineOps {
         let bdf = get_pci_bdf(cfg_args)?;
         let multi_func = get_multi_function(cfg_args)?;
         let device_cfg = parse_net(vm_config, cfg_args)?;
+        if let Some(bootindex) = device_cfg.boot_index {
+            self.check_bootindex(bootindex)
+                .with_context(|| "Fail to add virtio pci net device for invalid bootindex")?;
+        }
         let mut need_irqfd = false;
         let device: Arc<Mutex<dyn VirtioDevice>> = if device_cfg.vhost_type.is_some() {
             need_irqfd = true;
@@ -889,10 +1057,16 @@ pub trait MachineOps {
                     self.get_sys_mem(),
                 )))
             } else {
-                Arc::new(Mutex::new(VhostUser::Net::new(
+                let device = Arc::new(Mutex::new(VhostUser::Net::new(
                     &device_cfg,
                     self.get_sys_mem(),
-                )))
+                )));
+                MigrationManager::register_device_instance(
+                    VhostUser::VhostUserState::descriptor(),
+                    device.clone(),
+                    &device_cfg.id,
+                );
+                device
             }
         } else {
             let device = Arc::new(Mutex::new(virtio::Net::new(device_cfg.clone())));
@@ -903,7 +1077,20 @@ pub trait MachineOps {
             );
             device
         };
-        self.add_virtio_pci_device(&device_cfg.id, &bdf, device, multi_func, need_irqfd)?;
+        let pci_dev =
+            self.add_virtio_pci_device(&device_cfg.id, &bdf, device, multi_func, need_irqfd)?;
+        if let Some(bootindex) = device_cfg.boot_index {
+            // Eg: OpenFirmware device path(virtio-net):
+            // /pci@i0cf8/ethernet@3[,1]/ethernet-phy@0
+            //   |                 |  |               |
+            //   |                 |  |               |
+            //   |                 |  |          fixed 0.
+            //   |              PCI slot,[function] holding net.
+            //  PCI root as system bus port.
+            if let Some(dev_path) = pci_dev.lock().unwrap().get_dev_path() {
+                self.add_bootindex_devices(bootindex, &dev_path, &device_cfg.id);
+            }
+        }
         self.reset_bus(&device_cfg.id)?;
         Ok(())
     }
@@ -917,10 +1104,15 @@ pub trait MachineOps {
             MAX_VIRTIO_QUEUE,
         ));
         let device_cfg = parse_vhost_user_blk_pci(vm_config, cfg_args, queues_auto)?;
-        let device: Arc<Mutex<dyn VirtioDevice>> = Arc::new(Mutex::new(VhostUser::Block::new(
+        let device = Arc::new(Mutex::new(VhostUser::Block::new(
             &device_cfg,
             self.get_sys_mem(),
         )));
+        MigrationManager::register_device_instance(
+            VhostUser::VhostUserState::descriptor(),
+            device.clone(),
+            &device_cfg.id,
+        );
         let pci_dev = self
             .add_virtio_pci_device(&device_cfg.id, &bdf, device.clone(), multi_func, true)
             .with_context(|| {
@@ -966,8 +1158,11 @@ pub trait MachineOps {
         Ok(())
     }
 
-    fn add_vfio_device(&mut self, cfg_args: &str) -> Result<()> {
+    fn add_vfio_device(&mut self, vm_config: &VmConfig, cfg_args: &str) -> Result<()> {
         let device_cfg: VfioConfig = parse_vfio(cfg_args)?;
+        if let Some(pair_id) = &device_cfg.failover_pair_id {
+            vm_config.check_failover_pair(pair_id)?;
+        }
         let bdf = get_pci_bdf(cfg_args)?;
         let multifunc = get_multi_function(cfg_args)?;
         self.create_vfio_pci_device(
@@ -1105,6 +1300,7 @@ pub trait MachineOps {
             .map_or(false, |val| val == FAST_UNPLUG_ON);
 
         RootPort::set_fast_unplug_feature(fast_unplug);
+        MigrationManager::register_machine_version(vm_config.machine_config.mach_version);
         Ok(())
     }
 
@@ -1165,6 +1361,30 @@ pub trait MachineOps {
                         n.distances.insert(dist.1.destination, dist.1.distance);
                     }
                 }
+                "hmat-lb" => {
+                    let lb = parse_hmat_lb(numa.1.as_str())?;
+                    if !numa_nodes.contains_key(&lb.target) {
+                        bail!("Numa node id is not found {}", lb.target);
+                    }
+                    let node = numa_nodes
+                        .get_mut(&lb.initiator)
+                        .with_context(|| format!("Numa node id is not found {}", lb.initiator))?;
+                    if node.cpus.is_empty() {
+                        bail!("Numa node {} without cpus can't be initiator", lb.initiator);
+                    }
+                    if node
+                        .hmat_lb
+                        .insert((lb.data_type, lb.target), lb.value)
+                        .is_some()
+                    {
+                        bail!(
+                            "Numa hmat-lb {:?} from {} to {} repeat settings",
+                            lb.data_type,
+                            lb.initiator,
+                            lb.target
+                        );
+                    }
+                }
                 _ => {
                     bail!("Unsupported args for NUMA node: {}", numa.0.as_str());
                 }
@@ -1278,6 +1498,17 @@ pub trait MachineOps {
     ///
     /// * `vm_config` - VM configuration.
     /// * `usb_dev` - Usb device.
+    /// Get the xhci controller, which can be configured as `nec-usb-xhci` or `qemu-xhci`.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm_config` - VM configuration.
+    #[cfg(not(target_env = "musl"))]
+    fn get_xhci_controller(&mut self, vm_config: &VmConfig) -> Option<Arc<Mutex<dyn PciDevOps>>> {
+        self.get_pci_dev_by_id_and_type(vm_config, None, "nec-usb-xhci")
+            .or_else(|| self.get_pci_dev_by_id_and_type(vm_config, None, "qemu-xhci"))
+    }
+
     #[cfg(not(target_env = "musl"))]
     fn attach_usb_to_xhci_controller(
         &mut self,
@@ -1285,7 +1516,7 @@ pub trait MachineOps {
         usb_dev: Arc<Mutex<dyn UsbDeviceOps>>,
     ) -> Result<()> {
         let parent_dev = self
-            .get_pci_dev_by_id_and_type(vm_config, None, "nec-usb-xhci")
+            .get_xhci_controller(vm_config)
             .with_context(|| "Can not find parent device from pci bus")?;
         let locked_parent_dev = parent_dev.lock().unwrap();
         let xhci_pci = locked_parent_dev
@@ -1310,7 +1541,7 @@ pub trait MachineOps {
         id: String,
     ) -> Result<()> {
         let parent_dev = self
-            .get_pci_dev_by_id_and_type(vm_config, None, "nec-usb-xhci")
+            .get_xhci_controller(vm_config)
             .with_context(|| "Can not find parent device from pci bus")?;
         let locked_parent_dev = parent_dev.lock().unwrap();
         let xhci_pci = locked_parent_dev
@@ -1444,6 +1675,10 @@ pub trait MachineOps {
             let id = parse_device_id(cfg_args)?;
             self.check_device_id_existed(&id)
                 .with_context(|| format!("Failed to check device id: config {}", cfg_args))?;
+            let netdev = cfg_args
+                .split(',')
+                .find_map(|param| param.strip_prefix("netdev="));
+            check_protected_device(vm_config, dev.0.as_str(), netdev)?;
             match dev.0.as_str() {
                 "virtio-blk-device" => {
                     self.add_virtio_mmio_block(vm_config, cfg_args)?;
@@ -1488,7 +1723,7 @@ pub trait MachineOps {
                     self.add_virtio_rng(vm_config, cfg_args)?;
                 }
                 "vfio-pci" => {
-                    self.add_vfio_device(cfg_args)?;
+                    self.add_vfio_device(vm_config, cfg_args)?;
                 }
                 "vhost-user-blk-pci" => {
                     self.add_vhost_user_blk_pci(vm_config, cfg_args)?;
@@ -1497,7 +1732,7 @@ pub trait MachineOps {
                     self.add_virtio_fs(vm_config, cfg_args)?;
                 }
                 #[cfg(not(target_env = "musl"))]
-                "nec-usb-xhci" => {
+                "nec-usb-xhci" | "qemu-xhci" => {
                     self.add_usb_xhci(cfg_args)?;
                 }
                 #[cfg(not(target_env = "musl"))]
@@ -1528,6 +1763,18 @@ pub trait MachineOps {
                 "ramfb" => {
                     self.add_ramfb(cfg_args)?;
                 }
+                "pc-dimm" => {
+                    self.add_pc_dimm(vm_config, cfg_args)?;
+                }
+                "pvpanic" => {
+                    self.add_pvpanic(cfg_args)?;
+                }
+                "i6300esb" => {
+                    self.add_watchdog(cfg_args)?;
+                }
+                "tpm-crb" => {
+                    self.add_tpm(vm_config, cfg_args)?;
+                }
                 "pcie-demo-dev" => {
                     self.add_demo_dev(vm_config, cfg_args)?;
                 }
@@ -1548,6 +1795,44 @@ pub trait MachineOps {
         bail!("Pflash device is not supported!");
     }
 
+    /// Add pluggable memory device.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm_config` - VM configuration.
+    /// * `cfg_args` - Device configuration args.
+    fn add_pc_dimm(&mut self, _vm_config: &VmConfig, _cfg_args: &str) -> Result<()> {
+        bail!("Pluggable memory device is not supported!");
+    }
+
+    /// Add pvpanic device.
+    ///
+    /// # Arguments
+    ///
+    /// * `cfg_args` - Device configuration args.
+    fn add_pvpanic(&mut self, _cfg_args: &str) -> Result<()> {
+        bail!("pvpanic device is not supported!");
+    }
+
+    /// Add watchdog device.
+    ///
+    /// # Arguments
+    ///
+    /// * `cfg_args` - Device configuration args.
+    fn add_watchdog(&mut self, _cfg_args: &str) -> Result<()> {
+        bail!("Watchdog device is not supported!");
+    }
+
+    /// Add TPM device.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm_config` - VM configuration.
+    /// * `cfg_args` - Device configuration args.
+    fn add_tpm(&mut self, _vm_config: &mut VmConfig, _cfg_args: &str) -> Result<()> {
+        bail!("TPM device is not supported!");
+    }
+
     fn add_ramfb(&mut self, _cfg_args: &str) -> Result<()> {
         bail!("ramfb device is not supported!");
     }
@@ -1572,26 +1857,51 @@ pub trait MachineOps {
     /// Return the syscall whitelist for seccomp.
     fn syscall_whitelist(&self) -> Vec<BpfRule>;
 
-    /// Register seccomp rules in syscall whitelist to seccomp.
-    fn register_seccomp(&self, balloon_enable: bool) -> Result<()> {
-        let mut seccomp_filter = SyscallFilter::new(SeccompOpt::Trap);
+    /// Register seccomp rules in syscall whitelist to seccomp. The whitelist applies to
+    /// the whole process, and vCPU threads and io-threads have narrower ones stacked on it.
+    ///
+    /// # Arguments
+    ///
+    /// * `balloon_enable` - Whether balloon device is configured.
+    /// * `seccomp_opt` - Action for the syscalls not in whitelist, `Trap` or `Log`.
+    fn register_seccomp(&self, balloon_enable: bool, seccomp_opt: SeccompOpt) -> Result<()> {
+        if !seccomp_opt.is_available() {
+            bail!(
+                "Seccomp action {:?} is not supported by kernel",
+                seccomp_opt
+            );
+        }
+
         let mut bpf_rules = self.syscall_whitelist();
+        let mut vcpu_rules = thread_allow_list(&bpf_rules, VCPU_SYSCALLS);
+        let mut iothread_rules = thread_allow_list(&bpf_rules, IOTHREAD_SYSCALLS);
         if balloon_enable {
             balloon_allow_list(&mut bpf_rules);
+            balloon_allow_list(&mut iothread_rules);
         }
 
         if let Ok(cov_enable) = std::env::var("STRATOVIRT_COV") {
             if cov_enable.eq("on") {
                 coverage_allow_list(&mut bpf_rules);
+                coverage_allow_list(&mut vcpu_rules);
+                coverage_allow_list(&mut iothread_rules);
             }
         }
 
-        for bpf_rule in &mut bpf_rules {
-            seccomp_filter.push(bpf_rule);
-        }
-        seccomp_filter
+        let new_filter = |rules: &mut Vec<BpfRule>| {
+            let mut seccomp_filter = SyscallFilter::new(seccomp_opt);
+            for bpf_rule in rules {
+                seccomp_filter.push(bpf_rule);
+            }
+            seccomp_filter
+        };
+        new_filter(&mut bpf_rules)
             .realize()
             .with_context(|| "Failed to init seccomp filter.")?;
+        new_filter(&mut vcpu_rules).register_thread_filter(ThreadKind::Vcpu);
+        new_filter(&mut iothread_rules).register_thread_filter(ThreadKind::Iothread);
+        // Io-threads may be blocked in epoll, kick them to install their filter.
+        EventLoop::kick_iothreads();
         Ok(())
     }
 
@@ -1701,6 +2011,7 @@ pub trait MachineOps {
         }
 
         if paused {
+            set_stop_reason(StopReason::Prelaunch);
             *vm_state = KvmVmState::Paused;
         } else {
             *vm_state = KvmVmState::Running;
@@ -1735,6 +2046,19 @@ pub trait MachineOps {
         // SAFETY: ARM architecture must have interrupt controllers in user mode.
         irq_chip.as_ref().unwrap().stop();
 
+        #[cfg(target_arch = "x86_64")]
+        {
+            // Guest time stops while paused, and guest is told that the vcpus are
+            // stopped by host to avoid soft lockup warnings.
+            if let Err(e) = KVM_FDS.load().save_clock() {
+                warn!("Failed to save kvm clock on pause: {:?}", e);
+            }
+            for cpu in cpus.iter() {
+                // It fails if guest doesn't use kvmclock, which is harmless.
+                let _ = cpu.fd().kvmclock_ctrl();
+            }
+        }
+
         *vm_state = KvmVmState::Paused;
 
         Ok(())
@@ -1749,6 +2073,11 @@ pub trait MachineOps {
     fn vm_resume(&self, cpus: &[Arc<CPU>], vm_state: &mut KvmVmState) -> Result<()> {
         self.active_drive_files()?;
 
+        #[cfg(target_arch = "x86_64")]
+        if let Err(e) = KVM_FDS.load().restore_clock() {
+            warn!("Failed to restore kvm clock on resume: {:?}", e);
+        }
+
         for (cpu_index, cpu) in cpus.iter().enumerate() {
             if let Err(e) = cpu.resume() {
                 self.deactive_drive_files()?;
@@ -1761,6 +2090,23 @@ pub trait MachineOps {
         Ok(())
     }
 
+    /// Suspend VM as `Suspended` state, all vcpus are paused while devices keep running.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpus` - Cpus vector restore cpu structure.
+    /// * `vm_state` - Vm kvm vm state.
+    fn vm_suspend(&self, cpus: &[Arc<CPU>], vm_state: &mut KvmVmState) -> Result<()> {
+        for (cpu_index, cpu) in cpus.iter().enumerate() {
+            cpu.pause()
+                .with_context(|| format!("Failed to pause vcpu{}", cpu_index))?;
+        }
+
+        *vm_state = KvmVmState::Suspended;
+
+        Ok(())
+    }
+
     /// Destroy VM as `Shutdown` state, destroy vcpu thread.
     ///
     /// # Arguments
@@ -1815,6 +2161,12 @@ pub trait MachineOps {
             (Paused, Running) => self
                 .vm_resume(cpus, vm_state)
                 .with_context(|| "Failed to resume vm.")?,
+            (Running, Suspended) => self
+                .vm_suspend(cpus, vm_state)
+                .with_context(|| "Failed to suspend vm.")?,
+            (Suspended, Running) => self
+                .vm_resume(cpus, vm_state)
+                .with_context(|| "Failed to wake up vm.")?,
             (_, Shutdown) => self
                 .vm_destroy(cpus, vm_state)
                 .with_context(|| "Failed to destroy vm.")?,
@@ -1830,6 +2182,9 @@ pub trait MachineOps {
                 new_state
             );
         }
+        if new_state == Running {
+            set_stop_reason(StopReason::User);
+        }
 
         Ok(())
     }
@@ -1858,9 +2213,42 @@ pub fn vm_run(
     Ok(())
 }
 
+/// Start NBD server exporting writable raw drives, which are the target of storage
+/// migration, if it's configured.
+fn start_nbd_server(vm: &Arc<Mutex<dyn MachineOps + Send + Sync>>) -> Result<Option<NbdServer>> {
+    let vm_config = vm.lock().unwrap().get_vm_config();
+    let locked_config = vm_config.lock().unwrap();
+    let (mode, path) = match locked_config.nbd_server.as_ref() {
+        Some(server) => server.clone(),
+        None => return Ok(None),
+    };
+
+    let mut exports = HashMap::new();
+    for drive in locked_config.drives.values() {
+        if drive.read_only || drive.format != DiskFormat::Raw || drive.path_on_host.is_empty() {
+            continue;
+        }
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&drive.path_on_host)
+            .with_context(|| format!("Failed to open drive file {}", drive.path_on_host))?;
+        exports.insert(drive.id.clone(), file);
+    }
+
+    let server = NbdServer::start(mode, &path, exports)?;
+    Ok(Some(server))
+}
+
 /// Start incoming migration from destination.
 fn start_incoming_migration(vm: &Arc<Mutex<dyn MachineOps + Send + Sync>>) -> Result<()> {
     let (mode, path) = vm.lock().unwrap().get_migrate_info();
+    // Drives are written by source VM through NBD server before the VM state is received.
+    let nbd_server = if mode == MigrateMode::File {
+        None
+    } else {
+        start_nbd_server(vm)?
+    };
     match mode {
         MigrateMode::File => {
             MigrationManager::restore_snapshot(&path)
@@ -1873,11 +2261,17 @@ fn start_incoming_migration(vm: &Arc<Mutex<dyn MachineOps + Send + Sync>>) -> Re
         MigrateMode::Unix => {
             clear_file(path.clone())?;
             let listener = UnixListener::bind(&path)?;
-            let (mut sock, _) = listener.accept()?;
+            // Connections only checking compatibility are served until migration comes.
+            let mut sock = loop {
+                let (mut sock, _) = listener.accept()?;
+                if MigrationManager::recv_migration(&mut sock)
+                    .with_context(|| "Failed to receive migration with unix mode")?
+                {
+                    break sock;
+                }
+            };
             remove_file(&path)?;
-
-            MigrationManager::recv_migration(&mut sock)
-                .with_context(|| "Failed to receive migration with unix mode")?;
+            drop(nbd_server);
             vm.lock()
                 .unwrap()
                 .run(false)
@@ -1887,10 +2281,54 @@ fn start_incoming_migration(vm: &Arc<Mutex<dyn MachineOps + Send + Sync>>) -> Re
         }
         MigrateMode::Tcp => {
             let listener = TcpListener::bind(&path)?;
-            let mut sock = listener.accept().map(|(stream, _)| stream)?;
-
-            MigrationManager::recv_migration(&mut sock)
-                .with_context(|| "Failed to receive migration with tcp mode")?;
+            let mut sock = loop {
+                let mut sock = listener.accept().map(|(stream, _)| stream)?;
+                if MigrationManager::recv_migration(&mut sock)
+                    .with_context(|| "Failed to receive migration with tcp mode")?
+                {
+                    break sock;
+                }
+            };
+            drop(nbd_server);
+            vm.lock()
+                .unwrap()
+                .run(false)
+                .with_context(|| "Failed to start VM.")?;
+            MigrationManager::finish_migration(&mut sock)
+                .with_context(|| "Failed to finish migraton.")?;
+        }
+        MigrateMode::Fd => {
+            let fd = path
+                .parse::<RawFd>()
+                .with_context(|| format!("Invalid fd {} for incoming migration", path))?;
+            // SAFETY: the fd is inherited from parent process and only used by migration.
+            let mut sock = unsafe { UnixStream::from_raw_fd(fd) };
+
+            if !MigrationManager::recv_migration(&mut sock)
+                .with_context(|| "Failed to receive migration with fd mode")?
+            {
+                bail!("Only compatibility is checked with fd mode, no migration is received");
+            }
+            drop(nbd_server);
+            vm.lock()
+                .unwrap()
+                .run(false)
+                .with_context(|| "Failed to start VM.")?;
+            MigrationManager::finish_migration(&mut sock)
+                .with_context(|| "Failed to finish migraton.")?;
+        }
+        MigrateMode::Rdma => {
+            let listener = RdmaListener::bind(&path)?;
+            let mut sock = loop {
+                let mut sock = listener.accept()?;
+                if MigrationManager::recv_migration(&mut sock)
+                    .with_context(|| "Failed to receive migration with rdma mode")?
+                {
+                    break sock;
+                }
+            };
+            drop(listener);
+            drop(nbd_server);
             vm.lock()
                 .unwrap()
                 .run(false)
@@ -1913,6 +2351,523 @@ fn start_incoming_migration(vm: &Arc<Mutex<dyn MachineOps + Send + Sync>>) -> Re
     Ok(())
 }
 
+/// Handle `savevm`, the running vm is paused during saving snapshot.
+fn qmp_savevm<T: MachineOps + MachineLifecycle>(
+    vm: &T,
+    path: String,
+    single_file: bool,
+) -> Response {
+    let state = *vm.get_vm_state().0.lock().unwrap();
+    if state != KvmVmState::Running && state != KvmVmState::Paused {
+        return Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(format!(
+                "Can't save snapshot in vm state {:?}",
+                state
+            )),
+            None,
+        );
+    }
+    let running = state == KvmVmState::Running;
+    if running && !vm.pause() {
+        return Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError("Failed to pause vm".to_string()),
+            None,
+        );
+    }
+
+    let result = if single_file {
+        MigrationManager::save_snapshot_file(&path)
+    } else {
+        MigrationManager::save_snapshot(&path)
+    };
+    if running && !vm.resume() {
+        error!("Failed to resume vm after saving snapshot");
+    }
+    match result {
+        Ok(()) => Response::create_empty_response(),
+        Err(e) => {
+            error!("Failed to save snapshot to {}: {:?}", path, e);
+            let _ = MigrationManager::set_status(migration::MigrationStatus::Failed);
+            Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                None,
+            )
+        }
+    }
+}
+
+/// Handle `loadvm`, which is only allowed before the vm starts running.
+fn qmp_loadvm<T: MachineOps + MachineLifecycle>(
+    vm: &T,
+    cpus: &[Arc<CPU>],
+    path: String,
+) -> Response {
+    let state = *vm.get_vm_state().0.lock().unwrap();
+    if state != KvmVmState::Paused || get_stop_reason() != StopReason::Prelaunch {
+        return Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(
+                "Snapshot can only be loaded before vm starts running, launch it with -S"
+                    .to_string(),
+            ),
+            None,
+        );
+    }
+
+    if let Err(e) = MigrationManager::load_snapshot(&path) {
+        error!("Failed to load snapshot from {}: {:?}", path, e);
+        let _ = MigrationManager::set_status(migration::MigrationStatus::Failed);
+        return Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+            None,
+        );
+    }
+    // Apply the restored register state to vcpus before they run.
+    for cpu in cpus {
+        if let Err(e) = cpu.reset() {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                None,
+            );
+        }
+    }
+    if !vm.resume() {
+        return Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError("Failed to resume vm".to_string()),
+            None,
+        );
+    }
+    Response::create_empty_response()
+}
+
+/// Handle `query-vcpu-stats`, which returns the runtime statistics of vcpus.
+fn qmp_query_vcpu_stats(cpus: &[Arc<CPU>]) -> Response {
+    let stats: Vec<qmp_schema::VcpuStats> = cpus.iter().map(|cpu| cpu.stats()).collect();
+    Response::create_response(serde_json::to_value(stats).unwrap(), None)
+}
+
+/// Handle `query-memory-rss`, which returns the host memory resident in each range of guest RAM.
+fn qmp_query_memory_rss(sys_mem: &Arc<AddressSpace>) -> Response {
+    match sys_mem.get_ram_resident() {
+        Ok(infos) => {
+            let rss: Vec<qmp_schema::MemoryRss> = infos
+                .into_iter()
+                .map(|info| qmp_schema::MemoryRss {
+                    name: info.name,
+                    guest_addr: info.addr_range.base.raw_value(),
+                    size: info.addr_range.size,
+                    resident: info.resident,
+                })
+                .collect();
+            Response::create_response(serde_json::to_value(rss).unwrap(), None)
+        }
+        Err(e) => Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(format!("{:?}", e)),
+            None,
+        ),
+    }
+}
+
+/// Make all guest RAM private to guest, so that it's inaccessible to host except
+/// the pages guest shares for device I/O.
+fn protect_guest_memory(sys_mem: &Arc<AddressSpace>) -> Result<()> {
+    let kvm_fds = KVM_FDS.load();
+    kvm_fds.check_private_memory()?;
+
+    let memory = Arc::new(ProtectedMemory::default());
+    for range in sys_mem.get_ram_ranges() {
+        let (base, size) = (range.base.raw_value(), range.size);
+        kvm_fds.set_memory_private(base, size, true)?;
+        memory.protect(base, size);
+    }
+    sys_mem.set_protected(memory);
+    Ok(())
+}
+
+/// Check that the device works with protected VM. Devices accessing guest memory
+/// through a mapping of all guest memory, rather than the pages shared by guest,
+/// can't be used, as well as devices adding memory which can't be protected.
+///
+/// # Arguments
+///
+/// * `vm_config` - VM configuration.
+/// * `driver` - Driver name of the device.
+/// * `netdev` - Id of the netdev used by network device.
+pub(crate) fn check_protected_device(
+    vm_config: &VmConfig,
+    driver: &str,
+    netdev: Option<&str>,
+) -> Result<()> {
+    if !vm_config.machine_config.protected {
+        return Ok(());
+    }
+
+    let unsupported = match driver {
+        "vfio-pci"
+        | "vhost-user-blk-pci"
+        | "vhost-user-fs-pci"
+        | "vhost-user-fs-device"
+        | "vhost-vsock-pci"
+        | "vhost-vsock-device"
+        | "virtio-balloon-device"
+        | "virtio-balloon-pci"
+        | "pc-dimm"
+        | "ramfb"
+        | "ivshmem-scream" => true,
+        "virtio-net-pci" | "virtio-net-device" => netdev
+            .and_then(|id| vm_config.netdevs.get(id))
+            .and_then(|netdev| netdev.vhost_type.as_ref())
+            .is_some(),
+        _ => false,
+    };
+    if unsupported {
+        bail!("Device {} is not supported by protected VM", driver);
+    }
+    Ok(())
+}
+
+/// Compile the flattened device tree of VM. The device tree blob given by user replaces
+/// the generated one, with its `/chosen` node updated for kernel cmdline and initrd.
+///
+/// # Arguments
+///
+/// * `vm` - VM which generates device tree nodes.
+/// * `boot_source` - Boot source of VM.
+#[cfg(target_arch = "aarch64")]
+pub(crate) fn compile_fdt(vm: &dyn CompileFDT, boot_source: &Mutex<BootSource>) -> Result<Vec<u8>> {
+    let dtb_file = boot_source.lock().unwrap().dtb_file.clone();
+    let dtb_file = match dtb_file {
+        Some(file) => file,
+        None => {
+            let mut fdt_helper = FdtBuilder::new();
+            vm.generate_fdt_node(&mut fdt_helper)
+                .with_context(|| MachineError::GenFdtErr)?;
+            return fdt_helper.finish();
+        }
+    };
+
+    let dtb = std::fs::read(&dtb_file)
+        .with_context(|| format!("Failed to read device tree blob {:?}", dtb_file))?;
+    let mut device_tree = DeviceTree::from_dtb(&dtb)
+        .with_context(|| format!("Failed to parse device tree blob {:?}", dtb_file))?;
+
+    let boot_source = boot_source.lock().unwrap();
+    let chosen = device_tree.root.child_mut("chosen");
+    chosen.set_property_string("bootargs", &boot_source.kernel_cmdline.to_string());
+    match &boot_source.initrd {
+        Some(initrd) => {
+            chosen.set_property_u64("linux,initrd-start", initrd.initrd_addr);
+            chosen.set_property_u64("linux,initrd-end", initrd.initrd_addr + initrd.initrd_size);
+        }
+        None => {
+            chosen.remove_property("linux,initrd-start");
+            chosen.remove_property("linux,initrd-end");
+        }
+    }
+
+    fdt_to_dtb(&device_tree)
+}
+
+/// Set the kernel cmdline in `/chosen` node of the device tree blob.
+///
+/// # Arguments
+///
+/// * `dtb` - Device tree blob of VM.
+/// * `cmdline` - Kernel cmdline.
+#[cfg(target_arch = "aarch64")]
+pub(crate) fn fdt_set_bootargs(dtb: &[u8], cmdline: &str) -> Result<Vec<u8>> {
+    let mut device_tree = DeviceTree::from_dtb(dtb)?;
+    device_tree
+        .root
+        .child_mut("chosen")
+        .set_property_string("bootargs", cmdline);
+    fdt_to_dtb(&device_tree)
+}
+
+#[cfg(target_arch = "aarch64")]
+fn fdt_to_dtb(device_tree: &DeviceTree) -> Result<Vec<u8>> {
+    let fdt_vec = device_tree.to_dtb()?;
+    if fdt_vec.len() > device_tree::FDT_MAX_SIZE as usize {
+        bail!(
+            "Device tree blob size 0x{:x} exceeds the max size 0x{:x}",
+            fdt_vec.len(),
+            device_tree::FDT_MAX_SIZE
+        );
+    }
+    Ok(fdt_vec)
+}
+
+/// Handle `set-vcpu-affinity`, which pins the thread of vcpu to the host CPU.
+fn qmp_set_vcpu_affinity(cpus: &[Arc<CPU>], cpu_index: usize, host_cpu: usize) -> Response {
+    let cpu = match cpus.get(cpu_index) {
+        Some(cpu) => cpu,
+        None => {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(format!("Invalid cpu index {}", cpu_index)),
+                None,
+            );
+        }
+    };
+    if let Err(e) = cpu.set_affinity(host_cpu) {
+        return Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(format!("{:?}", e)),
+            None,
+        );
+    }
+    Response::create_empty_response()
+}
+
+/// Handle `qom-list` with the object tree built from the configuration of vm. The
+/// writable `link` property is listed additionally for virtio net device.
+fn qmp_qom_list(
+    vm_config: &VmConfig,
+    net: Option<Arc<Mutex<dyn VirtioDevice>>>,
+    path: &str,
+) -> Response {
+    let props = match (vm_config.qom_properties(path), &net) {
+        (Ok(props), _) => props,
+        // The hotplugged device is not recorded in the configuration.
+        (Err(_), Some(_)) => Vec::new(),
+        (Err(e), None) => {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::DeviceNotFound(e.to_string()),
+                None,
+            )
+        }
+    };
+    let mut prop_list: Vec<qmp_schema::PropList> = props
+        .into_iter()
+        .map(|prop| qmp_schema::PropList {
+            name: prop.name,
+            prop_type: prop.prop_type,
+        })
+        .collect();
+    if net.is_some() {
+        prop_list.push(qmp_schema::PropList {
+            name: QOM_LINK_PROPERTY.to_string(),
+            prop_type: "bool".to_string(),
+        });
+    }
+    Response::create_response(serde_json::to_value(prop_list).unwrap(), None)
+}
+
+/// Handle `qom-get` with the object tree built from the configuration of vm.
+fn qmp_qom_get(
+    vm_config: &VmConfig,
+    net: Option<Arc<Mutex<dyn VirtioDevice>>>,
+    path: &str,
+    property: &str,
+) -> Response {
+    if let (Some(net), QOM_LINK_PROPERTY) = (net, property) {
+        let locked_net = net.lock().unwrap();
+        let link_up = locked_net
+            .as_any()
+            .downcast_ref::<virtio::Net>()
+            .unwrap()
+            .link_up();
+        return Response::create_response(qmp_schema::Any::Bool(link_up), None);
+    }
+
+    let value = vm_config.qom_properties(path).and_then(|props| {
+        props
+            .into_iter()
+            .find(|prop| prop.name == property)
+            .map(|prop| prop.value)
+            .with_context(|| format!("Property '{}' not found", property))
+    });
+    match value {
+        Ok(value) => Response::create_response(value, None),
+        Err(e) => Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+            None,
+        ),
+    }
+}
+
+/// Handle `qom-set`, only the `link` property of virtio net device is writable now.
+fn qmp_qom_set(
+    vm_config: &VmConfig,
+    net: Option<Arc<Mutex<dyn VirtioDevice>>>,
+    path: &str,
+    property: &str,
+    value: qmp_schema::Any,
+) -> Response {
+    let result = if let (Some(net), QOM_LINK_PROPERTY) = (net, property) {
+        match value.as_bool() {
+            Some(link_up) => {
+                let mut locked_net = net.lock().unwrap();
+                let net = locked_net
+                    .as_any_mut()
+                    .downcast_mut::<virtio::Net>()
+                    .unwrap();
+                net.set_link_up(link_up)
+            }
+            None => Err(anyhow!(
+                "Invalid value of property '{}', expected: bool",
+                property
+            )),
+        }
+    } else {
+        vm_config.qom_properties(path).and_then(|props| {
+            if props.iter().any(|prop| prop.name == property) {
+                bail!("Property '{}' is read-only", property);
+            }
+            bail!("Property '{}' not found", property);
+        })
+    };
+    match result {
+        Ok(()) => Response::create_empty_response(),
+        Err(e) => Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+            None,
+        ),
+    }
+}
+
+/// Syscalls used by vCPU threads: running vcpu, emulating MMIO/PIO accesses and
+/// activating devices, besides memory management, signals and logging.
+const VCPU_SYSCALLS: &[i64] = &[
+    libc::SYS_read,
+    libc::SYS_readv,
+    libc::SYS_write,
+    libc::SYS_writev,
+    libc::SYS_ioctl,
+    libc::SYS_eventfd2,
+    libc::SYS_epoll_ctl,
+    libc::SYS_dup,
+    libc::SYS_close,
+    libc::SYS_fcntl,
+    libc::SYS_io_setup,
+    libc::SYS_io_destroy,
+    libc::SYS_io_uring_setup,
+    libc::SYS_io_uring_register,
+    libc::SYS_recvmsg,
+    libc::SYS_sendmsg,
+    libc::SYS_recvfrom,
+    libc::SYS_sendto,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_msync,
+    libc::SYS_lseek,
+    libc::SYS_fstat,
+    libc::SYS_statx,
+    libc::SYS_newfstatat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_stat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_open,
+    libc::SYS_openat,
+    libc::SYS_renameat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_unlink,
+    libc::SYS_unlinkat,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_brk,
+    libc::SYS_madvise,
+    libc::SYS_futex,
+    libc::SYS_getrandom,
+    libc::SYS_clone,
+    libc::SYS_clone3,
+    libc::SYS_set_robust_list,
+    #[cfg(target_env = "gnu")]
+    libc::SYS_rseq,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_gettid,
+    libc::SYS_getpid,
+    libc::SYS_tkill,
+    libc::SYS_tgkill,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+];
+
+/// Syscalls used by io-threads: polling events and handling device requests, besides
+/// memory management, signals and logging.
+const IOTHREAD_SYSCALLS: &[i64] = &[
+    libc::SYS_read,
+    libc::SYS_readv,
+    libc::SYS_write,
+    libc::SYS_writev,
+    libc::SYS_ioctl,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_epoll_wait,
+    libc::SYS_epoll_pwait,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+    libc::SYS_ppoll,
+    libc::SYS_io_getevents,
+    libc::SYS_io_submit,
+    libc::SYS_io_uring_enter,
+    libc::SYS_io_setup,
+    libc::SYS_io_destroy,
+    libc::SYS_io_uring_setup,
+    libc::SYS_io_uring_register,
+    libc::SYS_eventfd2,
+    libc::SYS_epoll_ctl,
+    libc::SYS_dup,
+    libc::SYS_close,
+    libc::SYS_fcntl,
+    libc::SYS_fdatasync,
+    libc::SYS_fallocate,
+    libc::SYS_recvmsg,
+    libc::SYS_sendmsg,
+    libc::SYS_recvfrom,
+    libc::SYS_sendto,
+    libc::SYS_pread64,
+    libc::SYS_preadv,
+    libc::SYS_pwrite64,
+    libc::SYS_pwritev,
+    libc::SYS_lseek,
+    libc::SYS_fstat,
+    libc::SYS_statx,
+    libc::SYS_newfstatat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_stat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_open,
+    libc::SYS_openat,
+    libc::SYS_renameat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_unlink,
+    libc::SYS_unlinkat,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_brk,
+    libc::SYS_madvise,
+    libc::SYS_futex,
+    libc::SYS_getrandom,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_gettid,
+    libc::SYS_getpid,
+    libc::SYS_tkill,
+    libc::SYS_tgkill,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+];
+
+/// Get the rules of the given syscalls from the process-wide allowlist, so that the
+/// argument constraints and the order by frequency are kept.
+fn thread_allow_list(bpf_rules: &[BpfRule], syscalls: &[i64]) -> Vec<BpfRule> {
+    bpf_rules
+        .iter()
+        .filter(|rule| syscalls.contains(&rule.syscall_num()))
+        .cloned()
+        .collect()
+}
+
 fn coverage_allow_list(syscall_allow_list: &mut Vec<BpfRule>) {
     syscall_allow_list.extend(vec![
         BpfRule::new(libc::SYS_fcntl),
@@ -20,6 +20,8 @@ pub enum LayoutEntryType {
     GicRedist,
     Uart,
     Rtc,
+    FwCfg,
+    PvTime,
     Mmio,
     Mem,
     HighGicRedist,
@@ -34,6 +36,8 @@ pub const MEM_LAYOUT: &[(u64, u64)] = &[
     (0x080A_0000, 0x00F6_0000),    // GicRedist (max 123 redistributors)
     (0x0900_0000, 0x0000_1000),    // Uart
     (0x0901_0000, 0x0000_1000),    // Rtc
+    (0x0902_0000, 0x0000_0018),    // FwCfg
+    (0x0903_0000, 0x0001_0000),    // PvTime
     (0x0A00_0000, 0x0000_0200),    // Mmio
     (0x4000_0000, 0x80_0000_0000), // Mem
     (256 << 30, 0x200_0000),       // HighGicRedist, (where remaining redistributors locates)
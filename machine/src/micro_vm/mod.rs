@@ -55,11 +55,15 @@ use cpu::CPUFeatures;
 #[cfg(target_arch = "aarch64")]
 use cpu::PMU_INTR;
 use cpu::{CPUBootConfig, CPUTopology, CpuLifecycleState, CpuTopology, CPU};
+#[cfg(target_arch = "x86_64")]
+use devices::legacy::FwCfgIO;
+#[cfg(target_arch = "aarch64")]
+use devices::legacy::FwCfgMem;
 #[cfg(target_arch = "aarch64")]
 use devices::legacy::PL031;
 #[cfg(target_arch = "x86_64")]
 use devices::legacy::SERIAL_ADDR;
-use devices::legacy::{FwCfgOps, Serial};
+use devices::legacy::{FwCfgEntryType, FwCfgOps, LegacyError as DevErrorKind, Serial};
 #[cfg(target_arch = "aarch64")]
 use devices::{ICGICConfig, ICGICv2Config, ICGICv3Config, InterruptController, GIC_IRQ_MAX};
 #[cfg(target_arch = "x86_64")]
@@ -68,34 +72,40 @@ use hypervisor::kvm::KVM_FDS;
 use kvm_bindings::{kvm_pit_config, KVM_PIT_SPEAKER_DUMMY};
 use machine_manager::{
     config::{
-        parse_blk, parse_incoming_uri, parse_net, BlkDevConfig, BootSource, ConfigCheck, DriveFile,
-        Incoming, MigrateMode, NetworkInterfaceConfig, NumaNodes, SerialConfig, VmConfig,
-        DEFAULT_VIRTQUEUE_SIZE,
+        parse_blk, parse_incoming_uri, parse_net, qom_peripheral_id, BlkDevConfig, BootSource,
+        ConfigCheck, DriveFile, Incoming, MigrateMode, NetworkInterfaceConfig, NumaNodes,
+        PanicAction, SerialConfig, VmConfig, DEFAULT_VIRTQUEUE_SIZE,
     },
     event,
     machine::{
-        DeviceInterface, KvmVmState, MachineAddressInterface, MachineExternalInterface,
-        MachineInterface, MachineLifecycle, MigrateInterface,
+        detect_crash_loop, vm_status_info, DeviceInterface, KvmVmState, MachineAddressInterface,
+        MachineExternalInterface, MachineInterface, MachineLifecycle, MigrateInterface,
     },
     qmp::{qmp_schema, QmpChannel, Response},
 };
 use mem_layout::{LayoutEntryType, MEM_LAYOUT};
 use migration::{MigrationManager, MigrationStatus};
-use sysbus::{SysBus, IRQ_BASE, IRQ_MAX};
+use sysbus::{SysBus, SysBusDevOps, IRQ_BASE, IRQ_MAX};
 #[cfg(target_arch = "aarch64")]
 use sysbus::{SysBusDevType, SysRes};
 use syscall::syscall_whitelist;
 #[cfg(target_arch = "aarch64")]
 use util::device_tree::{self, CompileFDT, FdtBuilder};
 use util::{
-    loop_context::EventLoopManager, num_ops::str_to_usize, seccomp::BpfRule, set_termi_canon_mode,
+    byte_code::ByteCode, loop_context::EventLoopManager, num_ops::str_to_usize, seccomp::BpfRule,
+    set_termi_canon_mode,
 };
 use virtio::{
-    create_tap, qmp_balloon, qmp_query_balloon, Block, BlockState, Net, VhostKern, VirtioDevice,
-    VirtioMmioDevice, VirtioMmioState, VirtioNetState,
+    create_tap, qmp_balloon, qmp_balloon_set_stats_interval, qmp_guest_agent_command,
+    qmp_query_balloon, Block, BlockState, Net, VhostKern, VirtioDevice, VirtioMmioDevice,
+    VirtioMmioState, VirtioNetState, VIRTIO_TYPE_BLOCK, VIRTIO_TYPE_NET,
 };
 
 use super::{error::MachineError, MachineOps};
+#[cfg(target_arch = "aarch64")]
+use crate::compile_fdt;
+#[cfg(target_arch = "x86_64")]
+use crate::gdbstub::start_gdbstub;
 #[cfg(target_arch = "x86_64")]
 use crate::vm_state;
 use anyhow::{anyhow, bail, Context, Result};
@@ -104,6 +114,8 @@ use anyhow::{anyhow, bail, Context, Result};
 const MMIO_REPLACEABLE_BLK_NR: usize = 4;
 // The replaceable network device maximum count.
 const MMIO_REPLACEABLE_NET_NR: usize = 2;
+// The maximum count of device hotplugged beyond the replaceable slots.
+const MMIO_HOTPLUG_DEV_NR: usize = 8;
 
 // The config of replaceable device.
 #[derive(Debug)]
@@ -191,6 +203,8 @@ pub struct LightMachine {
     drive_files: Arc<Mutex<HashMap<String, DriveFile>>>,
     // All backend memory region tree.
     machine_ram: Arc<Region>,
+    // Fw_cfg device.
+    fwcfg_dev: Option<Arc<Mutex<dyn FwCfgOps>>>,
 }
 
 impl LightMachine {
@@ -250,6 +264,7 @@ impl LightMachine {
             numa_nodes: None,
             drive_files: Arc::new(Mutex::new(vm_config.init_drive_files()?)),
             machine_ram: Arc::new(Region::init_container_region(u64::max_value(), "pc.ram")),
+            fwcfg_dev: None,
         })
     }
 
@@ -281,6 +296,87 @@ impl LightMachine {
         machine_ram.mtree(0_u32);
     }
 
+    /// Add fw_cfg device, and pass kernel, initrd and kernel cmdline to guest through it.
+    ///
+    /// The kernel cmdline may be modified by the virtio-mmio devices on x86_64, so this
+    /// function should be called after all devices are added.
+    fn add_fwcfg_device(&mut self) -> Result<Arc<Mutex<dyn FwCfgOps>>> {
+        let nr_cpus = self.cpu_topo.nrcpus;
+        #[cfg(target_arch = "x86_64")]
+        let mut fwcfg = FwCfgIO::new(self.sys_mem.clone());
+        #[cfg(target_arch = "aarch64")]
+        let mut fwcfg = FwCfgMem::new(self.sys_mem.clone());
+        fwcfg
+            .add_data_entry(FwCfgEntryType::NbCpus, nr_cpus.as_bytes().to_vec())
+            .with_context(|| DevErrorKind::AddEntryErr("NbCpus".to_string()))?;
+        fwcfg
+            .add_data_entry(FwCfgEntryType::MaxCpus, nr_cpus.as_bytes().to_vec())
+            .with_context(|| DevErrorKind::AddEntryErr("MaxCpus".to_string()))?;
+
+        let boot_source = self.boot_source.lock().unwrap();
+        if let Some(kernel) = &boot_source.kernel_file {
+            let kernel_data = std::fs::read(kernel)
+                .with_context(|| format!("Failed to read kernel file {:?}", kernel))?;
+            fwcfg
+                .add_data_entry(
+                    FwCfgEntryType::KernelSize,
+                    (kernel_data.len() as u32).as_bytes().to_vec(),
+                )
+                .with_context(|| DevErrorKind::AddEntryErr("KernelSize".to_string()))?;
+            fwcfg
+                .add_data_entry(FwCfgEntryType::KernelData, kernel_data)
+                .with_context(|| DevErrorKind::AddEntryErr("KernelData".to_string()))?;
+        }
+        if let Some(initrd) = &boot_source.initrd {
+            let initrd_data = std::fs::read(&initrd.initrd_file)
+                .with_context(|| format!("Failed to read initrd file {:?}", initrd.initrd_file))?;
+            fwcfg
+                .add_data_entry(
+                    FwCfgEntryType::InitrdSize,
+                    (initrd_data.len() as u32).as_bytes().to_vec(),
+                )
+                .with_context(|| DevErrorKind::AddEntryErr("InitrdSize".to_string()))?;
+            fwcfg
+                .add_data_entry(FwCfgEntryType::InitrdData, initrd_data)
+                .with_context(|| DevErrorKind::AddEntryErr("InitrdData".to_string()))?;
+        }
+
+        let cmdline = boot_source.kernel_cmdline.to_string();
+        fwcfg
+            .add_data_entry(
+                FwCfgEntryType::CmdlineSize,
+                ((cmdline.len() + 1) as u32).as_bytes().to_vec(),
+            )
+            .with_context(|| DevErrorKind::AddEntryErr("CmdlineSize".to_string()))?;
+        fwcfg
+            .add_string_entry(FwCfgEntryType::CmdlineData, cmdline.as_str())
+            .with_context(|| DevErrorKind::AddEntryErr("CmdlineData".to_string()))?;
+        drop(boot_source);
+
+        let boot_order = Vec::<u8>::new();
+        fwcfg
+            .add_file_entry("bootorder", boot_order)
+            .with_context(|| DevErrorKind::AddEntryErr("bootorder".to_string()))?;
+
+        #[cfg(target_arch = "x86_64")]
+        let fwcfg_dev = FwCfgIO::realize(fwcfg, &mut self.sysbus)
+            .with_context(|| "Failed to realize fwcfg device")?;
+        #[cfg(target_arch = "aarch64")]
+        let fwcfg_dev = FwCfgMem::realize(
+            fwcfg,
+            &mut self.sysbus,
+            MEM_LAYOUT[LayoutEntryType::FwCfg as usize].0,
+            MEM_LAYOUT[LayoutEntryType::FwCfg as usize].1,
+        )
+        .with_context(|| "Failed to realize fwcfg device")?;
+        self.fwcfg_dev = Some(fwcfg_dev.clone());
+
+        self.add_fw_cfg_files(&fwcfg_dev)
+            .with_context(|| "Failed to add user-defined fw_cfg files")?;
+
+        Ok(fwcfg_dev)
+    }
+
     fn create_replaceable_devices(&mut self) -> Result<()> {
         let mut rpl_devs: Vec<VirtioMmioDevice> = Vec::new();
         for id in 0..MMIO_REPLACEABLE_BLK_NR {
@@ -369,7 +465,7 @@ impl LightMachine {
 
     fn add_replaceable_config(&self, id: &str, dev_config: Arc<dyn ConfigCheck>) -> Result<()> {
         let mut configs_lock = self.replaceable_info.configs.lock().unwrap();
-        let limit = MMIO_REPLACEABLE_BLK_NR + MMIO_REPLACEABLE_NET_NR;
+        let limit = MMIO_REPLACEABLE_BLK_NR + MMIO_REPLACEABLE_NET_NR + MMIO_HOTPLUG_DEV_NR;
         if configs_lock.len() >= limit {
             return Err(anyhow!(MicroVmError::RplDevLmtErr("".to_string(), limit)));
         }
@@ -390,7 +486,21 @@ impl LightMachine {
         Ok(())
     }
 
-    fn add_replaceable_device(&self, id: &str, driver: &str, slot: usize) -> Result<()> {
+    /// Get the first unused replaceable slot for the driver. If all the slots are
+    /// used, the slot beyond them is returned, so that the device is hotplugged.
+    fn free_replaceable_slot(&self, driver: &str) -> usize {
+        let (start, nr) = if driver.contains("net") {
+            (MMIO_REPLACEABLE_BLK_NR, MMIO_REPLACEABLE_NET_NR)
+        } else {
+            (0, MMIO_REPLACEABLE_BLK_NR)
+        };
+        let devices = self.replaceable_info.devices.lock().unwrap();
+        (0..nr)
+            .find(|slot| devices.get(start + slot).map_or(false, |info| !info.used))
+            .unwrap_or(nr)
+    }
+
+    fn add_replaceable_device(&mut self, id: &str, driver: &str, slot: usize) -> Result<()> {
         // Find the configuration by id.
         let configs_lock = self.replaceable_info.configs.lock().unwrap();
         let mut dev_config = None;
@@ -399,33 +509,26 @@ impl LightMachine {
                 dev_config = Some(config.dev_config.clone());
             }
         }
-        if dev_config.is_none() {
-            bail!("Failed to find device configuration.");
-        }
+        drop(configs_lock);
+        let dev_config = dev_config.with_context(|| "Failed to find device configuration.")?;
 
         // Sanity check for config, driver and slot.
-        let cfg_any = dev_config.as_ref().unwrap().as_any();
+        let cfg_any = dev_config.as_any();
         let index = if driver.contains("net") {
-            if slot >= MMIO_REPLACEABLE_NET_NR {
-                return Err(anyhow!(MicroVmError::RplDevLmtErr(
-                    "net".to_string(),
-                    MMIO_REPLACEABLE_NET_NR
-                )));
-            }
             if cfg_any.downcast_ref::<NetworkInterfaceConfig>().is_none() {
                 return Err(anyhow!(MicroVmError::DevTypeErr("net".to_string())));
             }
+            if slot >= MMIO_REPLACEABLE_NET_NR {
+                return self.hotplug_mmio_device(id, dev_config, VIRTIO_TYPE_NET);
+            }
             slot + MMIO_REPLACEABLE_BLK_NR
         } else if driver.contains("blk") {
-            if slot >= MMIO_REPLACEABLE_BLK_NR {
-                return Err(anyhow!(MicroVmError::RplDevLmtErr(
-                    "block".to_string(),
-                    MMIO_REPLACEABLE_BLK_NR
-                )));
-            }
             if cfg_any.downcast_ref::<BlkDevConfig>().is_none() {
                 return Err(anyhow!(MicroVmError::DevTypeErr("blk".to_string())));
             }
+            if slot >= MMIO_REPLACEABLE_BLK_NR {
+                return self.hotplug_mmio_device(id, dev_config, VIRTIO_TYPE_BLOCK);
+            }
             slot
         } else {
             bail!("Unsupported replaceable device type.");
@@ -444,8 +547,88 @@ impl LightMachine {
                 .device
                 .lock()
                 .unwrap()
-                .update_config(dev_config)
+                .update_config(Some(dev_config))
+                .with_context(|| MicroVmError::UpdCfgErr(id.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Hotplug the device beyond the replaceable slots. The unused device which is hotplugged
+    /// before is reused, otherwise a new virtio mmio device is created with a new MMIO region.
+    fn hotplug_mmio_device(
+        &mut self,
+        id: &str,
+        dev_config: Arc<dyn ConfigCheck>,
+        device_type: u32,
+    ) -> Result<()> {
+        let rpl_nr = MMIO_REPLACEABLE_BLK_NR + MMIO_REPLACEABLE_NET_NR;
+        let mut replaceable_devices = self.replaceable_info.devices.lock().unwrap();
+        if let Some(device_info) = replaceable_devices
+            .iter_mut()
+            .skip(rpl_nr)
+            .find(|info| !info.used && info.device.lock().unwrap().device_type() == device_type)
+        {
+            device_info
+                .device
+                .lock()
+                .unwrap()
+                .update_config(Some(dev_config))
                 .with_context(|| MicroVmError::UpdCfgErr(id.to_string()))?;
+            device_info.id = id.to_string();
+            device_info.used = true;
+            return Ok(());
+        }
+        if replaceable_devices.len() >= rpl_nr + MMIO_HOTPLUG_DEV_NR {
+            bail!(
+                "A maximum of {} devices beyond the replaceable slots are supported.",
+                MMIO_HOTPLUG_DEV_NR
+            );
+        }
+        drop(replaceable_devices);
+
+        let cfg_any = dev_config.as_any();
+        let device: Arc<Mutex<dyn VirtioDevice>> = if device_type == VIRTIO_TYPE_NET {
+            let net_cfg = cfg_any.downcast_ref::<NetworkInterfaceConfig>().unwrap();
+            let net = Arc::new(Mutex::new(Net::new(net_cfg.clone())));
+            MigrationManager::register_device_instance(
+                VirtioNetState::descriptor(),
+                net.clone(),
+                id,
+            );
+            net
+        } else {
+            let blk_cfg = cfg_any.downcast_ref::<BlkDevConfig>().unwrap();
+            let block = Arc::new(Mutex::new(Block::new(
+                blk_cfg.clone(),
+                self.get_drive_files(),
+            )));
+            MigrationManager::register_device_instance(BlockState::descriptor(), block.clone(), id);
+            block
+        };
+
+        let virtio_mmio = VirtioMmioDevice::new(&self.sys_mem, device.clone());
+        let mmio_dev = self.realize_virtio_mmio_device(virtio_mmio)?;
+        MigrationManager::register_transport_instance(
+            VirtioMmioState::descriptor(),
+            mmio_dev.clone(),
+            id,
+        );
+        self.replaceable_info
+            .devices
+            .lock()
+            .unwrap()
+            .push(MmioReplaceableDevInfo {
+                device,
+                id: id.to_string(),
+                used: true,
+            });
+
+        // The guest can't find the new device by itself, report the resource to probe it.
+        if let Some(res) = mmio_dev.lock().unwrap().get_sys_resource() {
+            info!(
+                "Device {} is hotplugged, probe it in guest by virtio_mmio.device={}@0x{:08x}:{}",
+                id, res.region_size, res.region_base, res.irq
+            );
         }
         Ok(())
     }
@@ -486,6 +669,20 @@ impl LightMachine {
         Ok(id.to_string())
     }
 
+    /// Find the virtio net device by its qom path.
+    fn find_qom_virtio_net(&self, path: &str) -> Option<Arc<Mutex<dyn VirtioDevice>>> {
+        let id = qom_peripheral_id(path)?;
+        let devices = self.replaceable_info.devices.lock().unwrap();
+        devices
+            .iter()
+            .find(|dev_info| {
+                dev_info.used
+                    && dev_info.id == id
+                    && dev_info.device.lock().unwrap().as_any().is::<Net>()
+            })
+            .map(|dev_info| dev_info.device.clone())
+    }
+
     /// Must be called after the CPUs have been realized and GIC has been created.
     #[cfg(target_arch = "aarch64")]
     fn cpu_post_init(&self, vcpu_cfg: &Option<CPUFeatures>) -> Result<()> {
@@ -495,6 +692,13 @@ impl LightMachine {
                 cpu.init_pmu()?;
             }
         }
+        if features.steal_time {
+            self.init_pvtime(
+                &self.cpus,
+                &self.sys_mem,
+                MEM_LAYOUT[LayoutEntryType::PvTime as usize],
+            )?;
+        }
         Ok(())
     }
 }
@@ -547,6 +751,10 @@ impl MachineOps for LightMachine {
 
     #[cfg(target_arch = "x86_64")]
     fn init_interrupt_controller(&mut self, _vcpu_count: u64) -> MachineResult<()> {
+        let kernel_irqchip = self.vm_config.lock().unwrap().machine_config.kernel_irqchip;
+        if kernel_irqchip == machine_manager::config::KernelIrqchip::Split {
+            bail!("kernel-irqchip=split is not supported by microvm");
+        }
         KVM_FDS
             .load()
             .vm_fd
@@ -596,6 +804,11 @@ impl MachineOps for LightMachine {
     ) -> MachineResult<CPUBootConfig> {
         let boot_source = self.boot_source.lock().unwrap();
         let initrd = boot_source.initrd.as_ref().map(|b| b.initrd_file.clone());
+        let initrd_placement = boot_source
+            .initrd
+            .as_ref()
+            .map(|b| b.placement)
+            .unwrap_or_default();
 
         let gap_start = MEM_LAYOUT[LayoutEntryType::MemBelow4g as usize].0
             + MEM_LAYOUT[LayoutEntryType::MemBelow4g as usize].1;
@@ -603,6 +816,7 @@ impl MachineOps for LightMachine {
         let bootloader_config = BootLoaderConfig {
             kernel: boot_source.kernel_file.clone(),
             initrd,
+            initrd_placement,
             kernel_cmdline: boot_source.kernel_cmdline.to_string(),
             cpu_count: self.cpu_topo.nrcpus,
             gap_range: (gap_start, gap_end - gap_start),
@@ -627,6 +841,8 @@ impl MachineOps for LightMachine {
             idt_base: layout.segments.idt_base,
             idt_size: layout.segments.idt_limit,
             pml4_start: layout.boot_pml4_addr,
+            pvh: layout.pvh,
+            multiboot2: layout.multiboot2,
         })
     }
 
@@ -637,10 +853,16 @@ impl MachineOps for LightMachine {
     ) -> MachineResult<CPUBootConfig> {
         let mut boot_source = self.boot_source.lock().unwrap();
         let initrd = boot_source.initrd.as_ref().map(|b| b.initrd_file.clone());
+        let initrd_placement = boot_source
+            .initrd
+            .as_ref()
+            .map(|b| b.placement)
+            .unwrap_or_default();
 
         let bootloader_config = BootLoaderConfig {
             kernel: boot_source.kernel_file.clone(),
             initrd,
+            initrd_placement,
             mem_start: MEM_LAYOUT[LayoutEntryType::Mem as usize].0,
         };
         let layout = load_linux(&bootloader_config, &self.sys_mem, fwcfg)
@@ -805,6 +1027,7 @@ impl MachineOps for LightMachine {
 
     fn realize(vm: &Arc<Mutex<Self>>, vm_config: &mut VmConfig) -> MachineResult<()> {
         let mut locked_vm = vm.lock().unwrap();
+        MigrationManager::register_machine_version(vm_config.machine_config.mach_version);
 
         //trace for lightmachine
         trace_sysbus(&locked_vm.sysbus);
@@ -838,20 +1061,32 @@ impl MachineOps for LightMachine {
                 .with_context(|| "Failed to create replaceable devices.")?;
             locked_vm.add_devices(vm_config)?;
             trace_replaceable_info(&locked_vm.replaceable_info);
+            locked_vm.add_fwcfg_device()?;
 
-            let boot_config = if migrate_info.0 == MigrateMode::Unknown {
-                Some(locked_vm.load_boot_source(None)?)
+            let (boot_config, cpu_config) = if migrate_info.0 == MigrateMode::Unknown {
+                (
+                    Some(locked_vm.load_boot_source(None)?),
+                    Some(locked_vm.load_cpu_features(vm_config)?),
+                )
             } else {
-                None
+                (None, None)
             };
 
-            // vCPUs init
-            locked_vm.cpus.extend(<Self as MachineOps>::init_vcpu(
+            // vCPUs init, and apply CPU model and features
+            let cpus = <Self as MachineOps>::init_vcpu(
                 vm.clone(),
+                &locked_vm.sys_mem,
                 vm_config.machine_config.nr_cpus,
                 &topology,
                 &boot_config,
-            )?);
+                &cpu_config,
+                vm_config,
+            )?;
+            locked_vm.cpus.extend(cpus);
+
+            if let Some(addr) = vm_config.gdb.as_ref() {
+                start_gdbstub(addr, vm.clone(), &locked_vm.cpus, &locked_vm.sys_mem)?;
+            }
         }
 
         #[cfg(target_arch = "aarch64")]
@@ -866,13 +1101,16 @@ impl MachineOps for LightMachine {
             };
 
             // vCPUs init,and apply CPU features (for aarch64)
-            locked_vm.cpus.extend(<Self as MachineOps>::init_vcpu(
+            let cpus = <Self as MachineOps>::init_vcpu(
                 vm.clone(),
+                &locked_vm.sys_mem,
                 vm_config.machine_config.nr_cpus,
                 &topology,
                 &boot_config,
                 &cpu_config,
-            )?);
+                vm_config,
+            )?;
+            locked_vm.cpus.extend(cpus);
 
             locked_vm.init_interrupt_controller(u64::from(vm_config.machine_config.nr_cpus))?;
 
@@ -884,13 +1122,10 @@ impl MachineOps for LightMachine {
                 .with_context(|| "Failed to create replaceable devices.")?;
             locked_vm.add_devices(vm_config)?;
             trace_replaceable_info(&locked_vm.replaceable_info);
+            locked_vm.add_fwcfg_device()?;
 
             if let Some(boot_cfg) = boot_config {
-                let mut fdt_helper = FdtBuilder::new();
-                locked_vm
-                    .generate_fdt_node(&mut fdt_helper)
-                    .with_context(|| MachineError::GenFdtErr)?;
-                let fdt_vec = fdt_helper.finish()?;
+                let fdt_vec = compile_fdt(&*locked_vm, &locked_vm.boot_source)?;
                 locked_vm
                     .sys_mem
                     .write(
@@ -956,6 +1191,18 @@ impl MachineLifecycle for LightMachine {
         true
     }
 
+    fn get_panic_action(&self) -> PanicAction {
+        self.vm_config.lock().unwrap().machine_config.panic_action
+    }
+
+    fn in_crash_loop(&self) -> bool {
+        let vm_config = self.vm_config.lock().unwrap();
+        detect_crash_loop(
+            vm_config.machine_config.crash_limit,
+            vm_config.machine_config.crash_interval,
+        )
+    }
+
     fn reset(&mut self) -> bool {
         // For micro vm, the reboot command is equivalent to the shutdown command.
         for cpu in self.cpus.iter() {
@@ -963,7 +1210,15 @@ impl MachineLifecycle for LightMachine {
             *cpu_state.lock().unwrap() = CpuLifecycleState::Stopped;
         }
 
-        self.destroy()
+        if !self.destroy() {
+            return false;
+        }
+        let shutdown_msg = qmp_schema::Shutdown {
+            guest: true,
+            reason: "guest-reset".to_string(),
+        };
+        event!(Shutdown; shutdown_msg);
+        true
     }
 
     fn notify_lifecycle(&self, old: KvmVmState, new: KvmVmState) -> bool {
@@ -1019,24 +1274,23 @@ impl MachineAddressInterface for LightMachine {
             .write(&mut data, GuestAddress(addr), count)
             .is_ok()
     }
+
+    #[cfg(target_arch = "x86_64")]
+    fn io_device_name(&self, addr: u64, pio: bool) -> Option<String> {
+        let space = if pio { &self.sys_io } else { &self.sys_mem };
+        space.get_region_name(GuestAddress(addr))
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    fn io_device_name(&self, addr: u64, _pio: bool) -> Option<String> {
+        self.sys_mem.get_region_name(GuestAddress(addr))
+    }
 }
 
 impl DeviceInterface for LightMachine {
     fn query_status(&self) -> Response {
         let vmstate = self.get_vm_state().deref().0.lock().unwrap();
-        let qmp_state = match *vmstate {
-            KvmVmState::Running => qmp_schema::StatusInfo {
-                singlestep: false,
-                running: true,
-                status: qmp_schema::RunState::running,
-            },
-            KvmVmState::Paused => qmp_schema::StatusInfo {
-                singlestep: false,
-                running: false,
-                status: qmp_schema::RunState::paused,
-            },
-            _ => Default::default(),
-        };
+        let qmp_state = vm_status_info(*vmstate);
 
         Response::create_response(serde_json::to_value(&qmp_state).unwrap(), None)
     }
@@ -1078,6 +1332,22 @@ impl DeviceInterface for LightMachine {
         Response::create_response(cpu_vec.into(), None)
     }
 
+    fn query_cpus_fast(&self) -> Response {
+        let mut cpu_vec: Vec<qmp_schema::CpuInfoFast> = Vec::new();
+        for cpu_index in 0..self.cpu_topo.max_cpus {
+            if self.cpu_topo.get_mask(cpu_index as usize) == 1 {
+                cpu_vec.push(qmp_schema::CpuInfoFast {
+                    cpu_index: cpu_index as isize,
+                    qom_path: format!("/machine/unattached/device[{}]", cpu_index),
+                    thread_id: self.cpus[cpu_index as usize].tid() as isize,
+                    props: Some(self.cpu_topo.get_topo_instance_for_qmp(cpu_index as usize)),
+                    target: std::env::consts::ARCH.to_string(),
+                });
+            }
+        }
+        Response::create_response(serde_json::to_value(cpu_vec).unwrap(), None)
+    }
+
     fn query_hotpluggable_cpus(&self) -> Response {
         let mut hotplug_vec: Vec<serde_json::Value> = Vec::new();
         #[cfg(target_arch = "x86_64")]
@@ -1111,6 +1381,14 @@ impl DeviceInterface for LightMachine {
         Response::create_response(hotplug_vec.into(), None)
     }
 
+    fn query_vcpu_stats(&self) -> Response {
+        crate::qmp_query_vcpu_stats(&self.cpus)
+    }
+
+    fn set_vcpu_affinity(&self, cpu_index: usize, host_cpu: usize) -> Response {
+        crate::qmp_set_vcpu_affinity(&self.cpus, cpu_index, host_cpu)
+    }
+
     fn balloon(&self, value: u64) -> Response {
         if qmp_balloon(value) {
             return Response::create_empty_response();
@@ -1123,9 +1401,18 @@ impl DeviceInterface for LightMachine {
         )
     }
 
+    fn balloon_set_stats_interval(&self, interval: u64) -> Response {
+        match qmp_balloon_set_stats_interval(interval) {
+            Ok(()) => Response::create_empty_response(),
+            Err(e) => Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                None,
+            ),
+        }
+    }
+
     fn query_balloon(&self) -> Response {
-        if let Some(actual) = qmp_query_balloon() {
-            let ret = qmp_schema::BalloonInfo { actual };
+        if let Some(ret) = qmp_query_balloon() {
             return Response::create_response(serde_json::to_value(&ret).unwrap(), None);
         }
         Response::create_error_response(
@@ -1136,11 +1423,51 @@ impl DeviceInterface for LightMachine {
         )
     }
 
+    fn guest_agent_command(&self, args: qmp_schema::GuestAgentCmdArgument) -> Response {
+        match qmp_guest_agent_command(&args.command) {
+            Ok(()) => Response::create_empty_response(),
+            Err(e) => Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                None,
+            ),
+        }
+    }
+
+    fn query_block(&self) -> Response {
+        let block_info = self.get_vm_config().lock().unwrap().get_block_info();
+        Response::create_response(serde_json::to_value(block_info).unwrap(), None)
+    }
+
     fn query_mem(&self) -> Response {
         self.mem_show();
         Response::create_empty_response()
     }
 
+    fn query_memory_rss(&self) -> Response {
+        crate::qmp_query_memory_rss(&self.sys_mem)
+    }
+
+    fn qom_list(&mut self, path: String) -> Response {
+        let net = self.find_qom_virtio_net(&path);
+        let vm_config = self.get_vm_config();
+        let locked_vmconfig = vm_config.lock().unwrap();
+        crate::qmp_qom_list(&locked_vmconfig, net, &path)
+    }
+
+    fn qom_get(&mut self, path: String, property: String) -> Response {
+        let net = self.find_qom_virtio_net(&path);
+        let vm_config = self.get_vm_config();
+        let locked_vmconfig = vm_config.lock().unwrap();
+        crate::qmp_qom_get(&locked_vmconfig, net, &path, &property)
+    }
+
+    fn qom_set(&mut self, path: String, property: String, value: qmp_schema::Any) -> Response {
+        let net = self.find_qom_virtio_net(&path);
+        let vm_config = self.get_vm_config();
+        let locked_vmconfig = vm_config.lock().unwrap();
+        crate::qmp_qom_set(&locked_vmconfig, net, &path, &property, value)
+    }
+
     /// VNC is not supported by light machine currently.
     fn query_vnc(&self) -> Response {
         Response::create_error_response(
@@ -1153,10 +1480,9 @@ impl DeviceInterface for LightMachine {
 
     fn device_add(&mut self, args: Box<qmp_schema::DeviceAddArgument>) -> Response {
         // get slot of bus by addr or lun
-        let mut slot = 0;
-        if let Some(addr) = args.addr {
+        let slot = if let Some(addr) = args.addr {
             if let Ok(num) = str_to_usize(addr) {
-                slot = num;
+                num
             } else {
                 return Response::create_error_response(
                     qmp_schema::QmpErrorClass::GenericError(format!(
@@ -1167,8 +1493,10 @@ impl DeviceInterface for LightMachine {
                 );
             }
         } else if let Some(lun) = args.lun {
-            slot = lun + 1;
-        }
+            lun + 1
+        } else {
+            self.free_replaceable_slot(&args.driver)
+        };
 
         match self.add_replaceable_device(&args.id, &args.driver, slot) {
             Ok(()) => Response::create_empty_response(),
@@ -1286,6 +1614,18 @@ impl DeviceInterface for LightMachine {
             mq: false,
             socket_path: None,
             queue_size: DEFAULT_VIRTQUEUE_SIZE,
+            failover: false,
+            coalesce_frames: 0,
+            coalesce_usecs: 0,
+            status: self
+                .vm_config
+                .lock()
+                .unwrap()
+                .machine_config
+                .mach_version
+                .compat_prop("virtio-net", "status")
+                .map_or(true, |status| status == "on"),
+            boot_index: None,
         };
 
         if let Some(fds) = args.fds {
@@ -1407,14 +1747,15 @@ impl MigrateInterface for LightMachine {
     fn migrate(&self, uri: String) -> Response {
         match parse_incoming_uri(&uri) {
             Ok((MigrateMode::File, path)) => migration::snapshot(path),
-            Ok((MigrateMode::Unix, _)) | Ok((MigrateMode::Tcp, _)) => {
-                Response::create_error_response(
-                    qmp_schema::QmpErrorClass::GenericError(
-                        "MicroVM does not support migration".to_string(),
-                    ),
-                    None,
-                )
-            }
+            Ok((MigrateMode::Unix, _))
+            | Ok((MigrateMode::Tcp, _))
+            | Ok((MigrateMode::Fd, _))
+            | Ok((MigrateMode::Rdma, _)) => Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(
+                    "MicroVM does not support migration".to_string(),
+                ),
+                None,
+            ),
             _ => Response::create_error_response(
                 qmp_schema::QmpErrorClass::GenericError(format!("Invalid uri: {}", uri)),
                 None,
@@ -1425,6 +1766,14 @@ impl MigrateInterface for LightMachine {
     fn query_migrate(&self) -> Response {
         migration::query_migrate()
     }
+
+    fn savevm(&self, path: String, single_file: Option<bool>) -> Response {
+        crate::qmp_savevm(self, path, single_file.unwrap_or(false))
+    }
+
+    fn loadvm(&self, path: String) -> Response {
+        crate::qmp_loadvm(self, &self.cpus, path)
+    }
 }
 
 impl MachineInterface for LightMachine {}
@@ -1496,6 +1845,23 @@ fn generate_rtc_device_node(fdt: &mut FdtBuilder, res: &SysRes) -> util::Result<
     Ok(())
 }
 
+// Function that helps to generate fw-cfg node in device-tree.
+//
+// # Arguments
+//
+// * `dev_info` - Device resource info of fw-cfg device.
+// * `fdt` - Flatted device-tree blob where fw-cfg node will be filled into.
+#[cfg(target_arch = "aarch64")]
+fn generate_fwcfg_device_node(fdt: &mut FdtBuilder, res: &SysRes) -> util::Result<()> {
+    let node = format!("fw-cfg@{:x}", res.region_base);
+    let fwcfg_node_dep = fdt.begin_node(&node)?;
+    fdt.set_property_string("compatible", "qemu,fw-cfg-mmio")?;
+    fdt.set_property_array_u64("reg", &[res.region_base, res.region_size])?;
+    fdt.end_node(fwcfg_node_dep)?;
+
+    Ok(())
+}
+
 // Function that helps to generate Virtio-Mmio device's node in device-tree.
 //
 // # Arguments
@@ -1680,6 +2046,7 @@ impl CompileFDTHelper for LightMachine {
             match dev_type {
                 SysBusDevType::Serial => generate_serial_device_node(fdt, sys_res)?,
                 SysBusDevType::Rtc => generate_rtc_device_node(fdt, sys_res)?,
+                SysBusDevType::FwCfg => generate_fwcfg_device_node(fdt, sys_res)?,
                 SysBusDevType::VirtioMmio => generate_virtio_devices_node(fdt, sys_res)?,
                 _ => (),
             }
@@ -1734,21 +2101,21 @@ impl device_tree::CompileFDT for LightMachine {
 
 /// Trace descriptions for some devices at stratovirt startup.
 fn trace_cpu_topo(cpu_topo: &CPUTopology) {
-    util::ftrace!(trace_cpu_topo, "{:#?}", cpu_topo);
+    util::trace_event!(trace_cpu_topo, "{:#?}", cpu_topo);
 }
 
 fn trace_sysbus(sysbus: &SysBus) {
-    util::ftrace!(trace_sysbus, "{:?}", sysbus);
+    util::trace_event!(trace_sysbus, "{:?}", sysbus);
 }
 
 fn trace_replaceable_info(replaceable_info: &MmioReplaceableInfo) {
-    util::ftrace!(trace_replaceable_info, "{:?}", replaceable_info);
+    util::trace_event!(trace_replaceable_info, "{:?}", replaceable_info);
 }
 
 fn trace_vm_state(vm_state: &Arc<(Mutex<KvmVmState>, Condvar)>) {
-    util::ftrace!(trace_vm_state, "{:#?}", vm_state);
+    util::trace_event!(trace_vm_state, "{:#?}", vm_state);
 }
 
 fn trace_mmio_replaceable_config(config: &MmioReplaceableConfig) {
-    util::ftrace!(trace_mmio_replaceable_config, "{:#?}", config);
+    util::trace_event!(trace_mmio_replaceable_config, "{:#?}", config);
 }
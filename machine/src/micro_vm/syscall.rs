@@ -33,6 +33,8 @@ const FUTEX_WAIT_BITSET_PRIVATE: u32 = FUTEX_WAIT_BITSET | FUTEX_PRIVATE_FLAG;
 /// See: https://elixir.bootlin.com/linux/v4.19.123/source/include/uapi/linux/fcntl.h
 const F_GETFD: u32 = 1;
 const F_SETFD: u32 = 2;
+const F_GETFL: u32 = 3;
+const F_SETFL: u32 = 4;
 const F_LINUX_SPECIFIC_BASE: u32 = 1024;
 const F_DUPFD_CLOEXEC: u32 = F_LINUX_SPECIFIC_BASE + 6;
 
@@ -78,13 +80,18 @@ pub fn syscall_whitelist() -> Vec<BpfRule> {
         BpfRule::new(libc::SYS_recvmsg),
         BpfRule::new(libc::SYS_sendmsg),
         BpfRule::new(libc::SYS_recvfrom),
+        BpfRule::new(libc::SYS_sendto),
+        #[cfg(target_arch = "x86_64")]
+        BpfRule::new(libc::SYS_poll),
         BpfRule::new(libc::SYS_mremap),
         BpfRule::new(libc::SYS_io_setup),
         BpfRule::new(libc::SYS_brk),
         BpfRule::new(libc::SYS_fcntl)
             .add_constraint(SeccompCmpOpt::Eq, 1, F_DUPFD_CLOEXEC)
             .add_constraint(SeccompCmpOpt::Eq, 1, F_SETFD)
-            .add_constraint(SeccompCmpOpt::Eq, 1, F_GETFD),
+            .add_constraint(SeccompCmpOpt::Eq, 1, F_GETFD)
+            .add_constraint(SeccompCmpOpt::Eq, 1, F_GETFL)
+            .add_constraint(SeccompCmpOpt::Eq, 1, F_SETFL),
         BpfRule::new(libc::SYS_flock),
         BpfRule::new(libc::SYS_rt_sigprocmask),
         #[cfg(target_arch = "x86_64")]
@@ -94,6 +101,8 @@ pub fn syscall_whitelist() -> Vec<BpfRule> {
         BpfRule::new(libc::SYS_mmap),
         BpfRule::new(libc::SYS_munmap),
         BpfRule::new(libc::SYS_accept4),
+        BpfRule::new(libc::SYS_setsockopt),
+        BpfRule::new(libc::SYS_getsockopt),
         BpfRule::new(libc::SYS_lseek),
         futex_rule(),
         BpfRule::new(libc::SYS_exit),
@@ -104,6 +113,7 @@ pub fn syscall_whitelist() -> Vec<BpfRule> {
         #[cfg(target_env = "gnu")]
         BpfRule::new(libc::SYS_tgkill),
         BpfRule::new(libc::SYS_gettid),
+        BpfRule::new(libc::SYS_sched_setaffinity),
         BpfRule::new(libc::SYS_getpid),
         BpfRule::new(libc::SYS_fstat),
         BpfRule::new(libc::SYS_pread64),
@@ -171,6 +181,13 @@ fn ioctl_arch_allow_list(bpf_rule: BpfRule) -> BpfRule {
     bpf_rule
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_PIT2() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_CLOCK() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_CLOCK() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_KVMCLOCK_CTRL() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_TSC_KHZ() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_TSC_KHZ() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_TRANSLATE() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_GUEST_DEBUG() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_REGS() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_IRQCHIP() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_REGS() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_SREGS() as u32)
@@ -15,11 +15,17 @@ mod syscall;
 
 pub use crate::error::MachineError;
 use devices::acpi::ged::{acpi_dsdt_add_power_button, Ged};
+use devices::acpi::mem_hotplug::{MemHotplugDev, MEM_HOTPLUG_REGS_SIZE};
 use devices::acpi::power::PowerDev;
+use devices::misc::{
+    i6300esb::I6300Esb,
+    pvpanic::PvPanic,
+    tpm::{TpmCrb, TpmEmulator, TPM_CRB_ADDR_SIZE},
+};
 use log::{error, info, warn};
-use machine_manager::config::ShutdownAction;
 #[cfg(not(target_env = "musl"))]
 use machine_manager::config::UiContext;
+use machine_manager::config::{PanicAction, RebootAction, ShutdownAction};
 use machine_manager::event_loop::EventLoop;
 use std::borrow::Borrow;
 use std::collections::HashMap;
@@ -44,7 +50,7 @@ use acpi::{
     ARCH_GIC_MAINT_IRQ, ID_MAPPING_ENTRY_SIZE, INTERRUPT_PPIS_COUNT, INTERRUPT_SGIS_COUNT,
     ROOT_COMPLEX_ENTRY_SIZE,
 };
-use address_space::{AddressSpace, GuestAddress, Region};
+use address_space::{create_backend_mem, create_default_mem, AddressSpace, GuestAddress, Region};
 use boot_loader::{load_linux, BootLoaderConfig};
 use cpu::{
     CPUBootConfig, CPUFeatures, CPUInterface, CPUTopology, CpuTopology, CPU, PMU_INTR, PPI_BASE,
@@ -60,17 +66,18 @@ use hypervisor::kvm::KVM_FDS;
 #[cfg(not(target_env = "musl"))]
 use machine_manager::config::parse_ramfb;
 use machine_manager::config::{
-    parse_incoming_uri, BootIndexInfo, BootSource, DriveFile, Incoming, MigrateMode, NumaNode,
-    NumaNodes, PFlashConfig, SerialConfig, VmConfig,
+    get_pci_bdf, parse_dimm, parse_incoming_uri, parse_pvpanic, parse_tpm, parse_watchdog,
+    BootIndexInfo, BootSource, ConfigCheck, DimmConfig, DriveFile, Incoming, MigrateMode, NumaNode,
+    NumaNodes, PFlashConfig, SerialConfig, VmConfig, G,
 };
 use machine_manager::event;
 use machine_manager::machine::{
-    KvmVmState, MachineAddressInterface, MachineExternalInterface, MachineInterface,
-    MachineLifecycle, MachineTestInterface, MigrateInterface,
+    detect_crash_loop, KvmVmState, MachineAddressInterface, MachineExternalInterface,
+    MachineInterface, MachineLifecycle, MachineTestInterface, MigrateInterface,
 };
 use machine_manager::qmp::{qmp_schema, QmpChannel, Response};
 use migration::{MigrationManager, MigrationStatus};
-use pci::{InterruptHandler, PciDevOps, PciHost, PciIntxState};
+use pci::{swizzle_map_irq, InterruptHandler, PciDevOps, PciHost, PciIntxState, PCI_PIN_NUM};
 use pci_host_root::PciHostRoot;
 use sysbus::{SysBus, SysBusDevType, SysRes};
 use syscall::syscall_whitelist;
@@ -81,7 +88,7 @@ use util::seccomp::BpfRule;
 use util::set_termi_canon_mode;
 
 use super::{AcpiBuilder, Result as StdResult, StdMachineOps};
-use crate::MachineOps;
+use crate::{compile_fdt, fdt_set_bootargs, MachineOps};
 use anyhow::{bail, Context, Result};
 
 /// The type of memory layout entry on aarch64
@@ -95,6 +102,10 @@ pub enum LayoutEntryType {
     FwCfg,
     Ged,
     PowerDev,
+    MemHotplug,
+    PvPanic,
+    Tpm,
+    PvTime,
     Mmio,
     PcieMmio,
     PciePio,
@@ -115,6 +126,10 @@ pub const MEM_LAYOUT: &[(u64, u64)] = &[
     (0x0902_0000, 0x0000_0018),    // FwCfg
     (0x0908_0000, 0x0000_0004),    // Ged
     (0x0909_0000, 0x0000_1000),    // PowerDev
+    (0x090A_0000, 0x0000_0008),    // MemHotplug
+    (0x090B_0000, 0x0000_0002),    // PvPanic
+    (0x090C_0000, 0x0000_1000),    // Tpm
+    (0x090D_0000, 0x0001_0000),    // PvTime
     (0x0A00_0000, 0x0000_0200),    // Mmio
     (0x1000_0000, 0x2EFF_0000),    // PcieMmio
     (0x3EFF_0000, 0x0001_0000),    // PciePio
@@ -167,6 +182,12 @@ pub struct StdMachine {
     pause_req: Arc<EventFd>,
     /// Resume request, handle VM `Resume` event.
     resume_req: Arc<EventFd>,
+    /// Panic request, handle guest panic reported by pvpanic device.
+    panic_req: Arc<EventFd>,
+    /// Watchdog request, handle expiry of the watchdog device.
+    watchdog_req: Arc<EventFd>,
+    /// Base address of TPM CRB registers.
+    tpm_base: Option<u64>,
     /// Device Tree Blob.
     dtb_vec: Vec<u8>,
     /// List of guest NUMA nodes information.
@@ -179,9 +200,68 @@ pub struct StdMachine {
     drive_files: Arc<Mutex<HashMap<String, DriveFile>>>,
     /// machine all backend memory region tree
     machine_ram: Arc<Region>,
+    /// Controller of pluggable memory slots.
+    mem_hotplug: Option<Arc<Mutex<MemHotplugDev>>>,
 }
 
 impl StdMachine {
+    /// Plug memory device into a pluggable memory slot, return the slot index.
+    ///
+    /// # Arguments
+    ///
+    /// * `dimm_cfg` - Config of the memory device.
+    /// * `notify` - Whether to notify the guest, it's false for cold plugged device.
+    fn plug_dimm(&mut self, dimm_cfg: &DimmConfig, notify: bool) -> Result<u8> {
+        let mem_hotplug = self
+            .mem_hotplug
+            .as_ref()
+            .with_context(|| "Memory slots are not configured")?
+            .clone();
+        let mut locked_hotplug = mem_hotplug.lock().unwrap();
+        if locked_hotplug.contains(&dimm_cfg.id) {
+            bail!("Device id {} existed", dimm_cfg.id);
+        }
+        let slot = locked_hotplug.find_free_slot(dimm_cfg.slot)?;
+        let slot_size = locked_hotplug.slot_size();
+
+        let locked_config = self.vm_config.lock().unwrap();
+        let nr_cpus = locked_config.machine_config.nr_cpus;
+        let ram = if let Some(memdev) = dimm_cfg.memdev.as_ref() {
+            let zone = locked_config
+                .object
+                .mem_object
+                .get(memdev)
+                .with_context(|| {
+                    format!("Object for memory-backend {} config not found", memdev)
+                })?;
+            if zone.size != slot_size {
+                bail!(
+                    "Size of memory-backend {} should be equal to memory slot size {}",
+                    memdev,
+                    slot_size
+                );
+            }
+            create_backend_mem(
+                zone,
+                nr_cpus,
+                locked_config.machine_config.mem_config.mem_lock,
+            )?
+        } else {
+            let mut mem_config = locked_config.machine_config.mem_config.clone();
+            mem_config.mem_size = slot_size;
+            mem_config.mem_path = None;
+            create_default_mem(&mem_config, nr_cpus)?
+        };
+        drop(locked_config);
+
+        self.sys_mem
+            .root()
+            .add_subregion(ram, locked_hotplug.slot_addr(slot))
+            .with_context(|| format!("Failed to map memory device {}", dimm_cfg.id))?;
+        locked_hotplug.plug(&dimm_cfg.id, slot, notify)?;
+        Ok(slot)
+    }
+
     pub fn new(vm_config: &VmConfig) -> Result<Self> {
         let cpu_topo = CpuTopology::new(
             vm_config.machine_config.nr_cpus,
@@ -247,6 +327,15 @@ impl StdMachine {
                 EventFd::new(libc::EFD_NONBLOCK)
                     .with_context(|| MachineError::InitEventFdErr("resume_req".to_string()))?,
             ),
+            panic_req: Arc::new(
+                EventFd::new(libc::EFD_NONBLOCK)
+                    .with_context(|| MachineError::InitEventFdErr("panic_req".to_string()))?,
+            ),
+            watchdog_req: Arc::new(
+                EventFd::new(libc::EFD_NONBLOCK)
+                    .with_context(|| MachineError::InitEventFdErr("watchdog_req".to_string()))?,
+            ),
+            tpm_base: None,
             dtb_vec: Vec::new(),
             numa_nodes: None,
             boot_order_list: Arc::new(Mutex::new(Vec::new())),
@@ -256,6 +345,7 @@ impl StdMachine {
                 u64::max_value(),
                 "MachineRam",
             )),
+            mem_hotplug: None,
         })
     }
 
@@ -374,6 +464,13 @@ impl StdMachine {
                 cpu.init_pmu()?;
             }
         }
+        if features.steal_time {
+            self.init_pvtime(
+                &self.cpus,
+                &self.sys_mem,
+                MEM_LAYOUT[LayoutEntryType::PvTime as usize],
+            )?;
+        }
         Ok(())
     }
 
@@ -409,6 +506,21 @@ impl StdMachineOps for StdMachine {
         Ok(())
     }
 
+    fn plug_pc_dimm(&mut self, args: &qmp_schema::DeviceAddArgument) -> Result<()> {
+        let dimm_cfg = DimmConfig {
+            id: args.id.clone(),
+            memdev: args.memdev.clone(),
+            slot: args.slot,
+        };
+        dimm_cfg.check()?;
+        let slot = self.plug_dimm(&dimm_cfg, true)?;
+        self.vm_config
+            .lock()
+            .unwrap()
+            .add_dimm_device_config(args, slot);
+        Ok(())
+    }
+
     fn add_fwcfg_device(&mut self, nr_cpus: u8) -> StdResult<Option<Arc<Mutex<dyn FwCfgOps>>>> {
         if self.vm_config.lock().unwrap().pflashs.is_none() {
             return Ok(None);
@@ -463,6 +575,33 @@ impl StdMachineOps for StdMachine {
     fn get_guest_numa(&self) -> &Option<NumaNodes> {
         &self.numa_nodes
     }
+
+    fn get_tpm_base(&self) -> Option<u64> {
+        self.tpm_base
+    }
+
+    fn get_boot_source(&self) -> &Arc<Mutex<BootSource>> {
+        &self.boot_source
+    }
+
+    fn update_kernel_cmdline(&mut self) -> StdResult<()> {
+        let cmdline = self.boot_source.lock().unwrap().kernel_cmdline.to_string();
+        if let Some(fwcfg) = &self.fwcfg_dev {
+            let mut locked_fwcfg = fwcfg.lock().unwrap();
+            locked_fwcfg.modify_data_entry(
+                FwCfgEntryType::CmdlineSize,
+                (cmdline.len() + 1).as_bytes().to_vec(),
+            )?;
+            let mut cmdline_data = cmdline.as_bytes().to_vec();
+            cmdline_data.push(0_u8);
+            locked_fwcfg.modify_data_entry(FwCfgEntryType::CmdlineData, cmdline_data)?;
+        }
+        // The device tree blob is written to guest memory again at reset.
+        if !self.dtb_vec.is_empty() {
+            self.dtb_vec = fdt_set_bootargs(&self.dtb_vec, &cmdline)?;
+        }
+        Ok(())
+    }
 }
 
 impl MachineOps for StdMachine {
@@ -533,10 +672,16 @@ impl MachineOps for StdMachine {
     fn load_boot_source(&self, fwcfg: Option<&Arc<Mutex<dyn FwCfgOps>>>) -> Result<CPUBootConfig> {
         let mut boot_source = self.boot_source.lock().unwrap();
         let initrd = boot_source.initrd.as_ref().map(|b| b.initrd_file.clone());
+        let initrd_placement = boot_source
+            .initrd
+            .as_ref()
+            .map(|b| b.placement)
+            .unwrap_or_default();
 
         let bootloader_config = BootLoaderConfig {
             kernel: boot_source.kernel_file.clone(),
             initrd,
+            initrd_placement,
             mem_start: MEM_LAYOUT[LayoutEntryType::Mem as usize].0,
         };
         let layout = load_linux(&bootloader_config, &self.sys_mem, fwcfg)
@@ -566,16 +711,48 @@ impl MachineOps for StdMachine {
 
     fn add_ged_device(&mut self) -> Result<()> {
         let battery_present = self.vm_config.lock().unwrap().machine_config.battery;
+        let mem_config = self
+            .vm_config
+            .lock()
+            .unwrap()
+            .machine_config
+            .mem_config
+            .clone();
         let ged = Ged::default();
         let ged_dev = ged
             .realize(
                 &mut self.sysbus,
                 self.power_button.clone(),
                 battery_present,
+                mem_config.slots > 0,
                 MEM_LAYOUT[LayoutEntryType::Ged as usize].0,
                 MEM_LAYOUT[LayoutEntryType::Ged as usize].1,
             )
             .with_context(|| "Failed to realize Ged")?;
+        if mem_config.slots > 0 {
+            // Pluggable memory slots locate after the guest RAM, aligned to 1GiB.
+            let ram_end = MEM_LAYOUT[LayoutEntryType::Mem as usize].0 + mem_config.mem_size;
+            let base = (ram_end + G - 1) / G * G;
+            let size = mem_config.slot_size() * mem_config.slots as u64;
+            if base + size > MEM_LAYOUT[LayoutEntryType::HighGicRedist as usize].0 {
+                bail!("Pluggable memory exceeds the memory layout, please reduce maxmem");
+            }
+            let mem_hotplug = MemHotplugDev::new(
+                ged_dev.clone(),
+                base,
+                mem_config.slot_size(),
+                mem_config.slots,
+            );
+            self.mem_hotplug = Some(
+                mem_hotplug
+                    .realize(
+                        &mut self.sysbus,
+                        MEM_LAYOUT[LayoutEntryType::MemHotplug as usize].0,
+                        MEM_HOTPLUG_REGS_SIZE,
+                    )
+                    .with_context(|| "Failed to realize memory hotplug controller")?,
+            );
+        }
         if battery_present {
             let pdev = PowerDev::new(ged_dev);
             pdev.realize(
@@ -618,6 +795,9 @@ impl MachineOps for StdMachine {
         let nr_cpus = vm_config.machine_config.nr_cpus;
         let mut locked_vm = vm.lock().unwrap();
         locked_vm.init_global_config(vm_config)?;
+        if let Err(e) = KVM_FDS.load().enable_system_suspend() {
+            warn!("Guest suspend is not supported: {:?}", e);
+        }
         locked_vm
             .register_shutdown_event(locked_vm.shutdown_req.clone(), vm.clone())
             .with_context(|| "Fail to register shutdown event")?;
@@ -630,6 +810,12 @@ impl MachineOps for StdMachine {
         locked_vm
             .register_resume_event(locked_vm.resume_req.clone(), vm.clone())
             .with_context(|| "Fail to register resume event")?;
+        locked_vm
+            .register_panic_event(locked_vm.panic_req.clone(), vm.clone())
+            .with_context(|| "Fail to register panic event")?;
+        locked_vm
+            .register_watchdog_event(locked_vm.watchdog_req.clone(), vm.clone())
+            .with_context(|| "Fail to register watchdog event")?;
 
         locked_vm.numa_nodes = locked_vm.add_numa_nodes(vm_config)?;
         locked_vm.init_memory(
@@ -655,13 +841,16 @@ impl MachineOps for StdMachine {
             None
         };
 
-        locked_vm.cpus.extend(<Self as MachineOps>::init_vcpu(
+        let cpus = <Self as MachineOps>::init_vcpu(
             vm.clone(),
+            &locked_vm.sys_mem,
             nr_cpus,
             &CPUTopology::new(),
             &boot_config,
             &cpu_config,
-        )?);
+            vm_config,
+        )?;
+        locked_vm.cpus.extend(cpus);
 
         // Interrupt Controller Chip init
         locked_vm.init_interrupt_controller(u64::from(nr_cpus))?;
@@ -673,11 +862,7 @@ impl MachineOps for StdMachine {
             .with_context(|| "Failed to add devices")?;
 
         if let Some(boot_cfg) = boot_config {
-            let mut fdt_helper = FdtBuilder::new();
-            locked_vm
-                .generate_fdt_node(&mut fdt_helper)
-                .with_context(|| MachineError::GenFdtErr)?;
-            let fdt_vec = fdt_helper.finish()?;
+            let fdt_vec = compile_fdt(&*locked_vm, &locked_vm.boot_source)?;
             locked_vm.dtb_vec = fdt_vec.clone();
             locked_vm
                 .sys_mem
@@ -698,6 +883,9 @@ impl MachineOps for StdMachine {
                 locked_vm
                     .build_smbios(&fw_cfg)
                     .with_context(|| "Failed to create smbios tables")?;
+                locked_vm
+                    .add_fw_cfg_files(&fw_cfg)
+                    .with_context(|| "Failed to add user-defined fw_cfg files")?;
             }
         }
 
@@ -725,6 +913,12 @@ impl MachineOps for StdMachine {
         Ok(())
     }
 
+    fn add_pc_dimm(&mut self, vm_config: &VmConfig, cfg_args: &str) -> Result<()> {
+        let dimm_cfg = parse_dimm(vm_config, cfg_args)?;
+        self.plug_dimm(&dimm_cfg, false)?;
+        Ok(())
+    }
+
     fn add_pflash_device(&mut self, configs: &[PFlashConfig]) -> Result<()> {
         use super::error::StandardVmError as StdErrorKind;
         let mut configs_vec = configs.to_vec();
@@ -777,6 +971,53 @@ impl MachineOps for StdMachine {
         Ok(())
     }
 
+    fn add_pvpanic(&mut self, cfg_args: &str) -> Result<()> {
+        parse_pvpanic(cfg_args)?;
+        let pvpanic = PvPanic::new(self.panic_req.clone());
+        pvpanic
+            .realize(
+                &mut self.sysbus,
+                MEM_LAYOUT[LayoutEntryType::PvPanic as usize].0,
+                MEM_LAYOUT[LayoutEntryType::PvPanic as usize].1,
+            )
+            .with_context(|| "Failed to realize pvpanic device")
+    }
+
+    fn add_watchdog(&mut self, cfg_args: &str) -> Result<()> {
+        let nr_watchdog = self
+            .vm_config
+            .lock()
+            .unwrap()
+            .devices
+            .iter()
+            .filter(|(driver, _)| driver == "i6300esb")
+            .count();
+        if nr_watchdog > 1 {
+            bail!("Only one watchdog device is supported");
+        }
+
+        let bdf = get_pci_bdf(cfg_args)?;
+        let (devfn, parent_bus) = self.get_devfn_and_parent_bus(&bdf)?;
+        let dev_cfg = parse_watchdog(cfg_args)?;
+        let watchdog = I6300Esb::new(dev_cfg.id, devfn, parent_bus, self.watchdog_req.clone());
+        watchdog
+            .realize()
+            .with_context(|| "Failed to realize i6300esb device")
+    }
+
+    fn add_tpm(&mut self, vm_config: &mut VmConfig, cfg_args: &str) -> Result<()> {
+        if self.tpm_base.is_some() {
+            bail!("Only one TPM device is supported");
+        }
+        let tpm_cfg = parse_tpm(vm_config, cfg_args)?;
+        let tpm = TpmCrb::new(TpmEmulator::new(&tpm_cfg.socket_path));
+        let region_base = MEM_LAYOUT[LayoutEntryType::Tpm as usize].0;
+        tpm.realize(&mut self.sysbus, region_base, TPM_CRB_ADDR_SIZE)
+            .with_context(|| "Failed to realize TPM device")?;
+        self.tpm_base = Some(region_base);
+        Ok(())
+    }
+
     #[cfg(not(target_env = "musl"))]
     fn add_ramfb(&mut self, cfg_args: &str) -> Result<()> {
         let install = parse_ramfb(cfg_args)?;
@@ -1025,7 +1266,9 @@ impl AcpiBuilder for StdMachine {
             gic_cpu.flags = 5;
             gic_cpu.mpidr = mpidr & mpidr_mask;
             gic_cpu.vgic_interrupt = ARCH_GIC_MAINT_IRQ + INTERRUPT_PPIS_COUNT;
-            gic_cpu.perf_interrupt = PMU_INTR + PPI_BASE;
+            if self.cpu_features.pmu {
+                gic_cpu.perf_interrupt = PMU_INTR + PPI_BASE;
+            }
             madt.append_child(&gic_cpu.aml_bytes());
         }
 
@@ -1185,6 +1428,22 @@ impl MachineLifecycle for StdMachine {
             .shutdown_action
     }
 
+    fn get_panic_action(&self) -> PanicAction {
+        self.vm_config.lock().unwrap().machine_config.panic_action
+    }
+
+    fn get_reboot_action(&self) -> RebootAction {
+        self.vm_config.lock().unwrap().machine_config.reboot_action
+    }
+
+    fn in_crash_loop(&self) -> bool {
+        let vm_config = self.vm_config.lock().unwrap();
+        detect_crash_loop(
+            vm_config.machine_config.crash_limit,
+            vm_config.machine_config.crash_interval,
+        )
+    }
+
     fn reset(&mut self) -> bool {
         if self.reset_req.write(1).is_err() {
             error!("ARM standard vm write reset req failed");
@@ -1193,6 +1452,32 @@ impl MachineLifecycle for StdMachine {
         true
     }
 
+    fn suspend(&self) -> bool {
+        if !self.notify_lifecycle(KvmVmState::Running, KvmVmState::Suspended) {
+            return false;
+        }
+        event!(Suspend);
+        true
+    }
+
+    fn wakeup(&mut self) -> bool {
+        if *self.vm_state.0.lock().unwrap() != KvmVmState::Suspended {
+            error!("Unable to wake up: guest is not in suspended state");
+            return false;
+        }
+        for (cpu_index, cpu) in self.cpus.iter().enumerate() {
+            if let Err(e) = cpu.set_to_wakeup_state().and_then(|_| cpu.reset()) {
+                error!("Failed to wake up vcpu{}: {:?}", cpu_index, e);
+                return false;
+            }
+        }
+        if !self.notify_lifecycle(KvmVmState::Suspended, KvmVmState::Running) {
+            return false;
+        }
+        event!(Wakeup);
+        true
+    }
+
     fn notify_lifecycle(&self, old: KvmVmState, new: KvmVmState) -> bool {
         if let Err(e) = self.vm_state_transfer(
             &self.cpus,
@@ -1222,14 +1507,33 @@ impl MachineAddressInterface for StdMachine {
             .write(&mut data, GuestAddress(addr), count)
             .is_ok()
     }
+
+    fn io_device_name(&self, addr: u64, _pio: bool) -> Option<String> {
+        self.sys_mem.get_region_name(GuestAddress(addr))
+    }
 }
 
 impl MigrateInterface for StdMachine {
     fn migrate(&self, uri: String) -> Response {
+        // The primary device of failover can't be migrated, it should be unplugged
+        // before migration and guest will switch datapath to the standby device.
+        let primaries = self.vm_config.lock().unwrap().get_failover_primaries();
+        if !primaries.is_empty() {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(format!(
+                    "Failover primary devices {:?} should be unplugged before migration",
+                    primaries
+                )),
+                None,
+            );
+        }
+
         match parse_incoming_uri(&uri) {
             Ok((MigrateMode::File, path)) => migration::snapshot(path),
             Ok((MigrateMode::Unix, path)) => migration::migration_unix_mode(path),
             Ok((MigrateMode::Tcp, path)) => migration::migration_tcp_mode(path),
+            Ok((MigrateMode::Fd, name)) => migration::migration_fd_mode(name),
+            Ok((MigrateMode::Rdma, path)) => migration::migration_rdma_mode(path),
             _ => Response::create_error_response(
                 qmp_schema::QmpErrorClass::GenericError(format!("Invalid uri: {}", uri)),
                 None,
@@ -1237,13 +1541,37 @@ impl MigrateInterface for StdMachine {
         }
     }
 
+    fn migrate_check(&self, uri: String) -> Response {
+        migration::check_migration(uri)
+    }
+
     fn query_migrate(&self) -> Response {
         migration::query_migrate()
     }
 
+    fn savevm(&self, path: String, single_file: Option<bool>) -> Response {
+        crate::qmp_savevm(self, path, single_file.unwrap_or(false))
+    }
+
+    fn loadvm(&self, path: String) -> Response {
+        crate::qmp_loadvm(self, &self.cpus, path)
+    }
+
     fn cancel_migrate(&self) -> Response {
         migration::cancel_migrate()
     }
+
+    fn migrate_set_capabilities(&self, args: qmp_schema::migrate_set_capabilities) -> Response {
+        migration::set_migrate_capabilities(args.capabilities)
+    }
+
+    fn migrate_set_parameters(&self, args: qmp_schema::migrate_set_parameters) -> Response {
+        migration::set_migrate_parameters(args)
+    }
+
+    fn query_migrate_parameters(&self) -> Response {
+        migration::query_migrate_parameters()
+    }
 }
 
 impl MachineInterface for StdMachine {}
@@ -1333,6 +1661,31 @@ fn generate_pci_host_node(fdt: &mut FdtBuilder) -> util::Result<()> {
     )?;
 
     fdt.set_property_u32("msi-parent", device_tree::GIC_ITS_PHANDLE)?;
+
+    // INTx of devices on root bus is swizzled by slot, only the lowest two bits of
+    // slot number are concerned.
+    let pcie_irq_base = IRQ_MAP[IrqEntryType::Pcie as usize].0 as u32;
+    let mut irq_map = Vec::new();
+    for slot in 0..PCI_PIN_NUM {
+        for pin in 0..PCI_PIN_NUM {
+            let devfn = slot << 3;
+            irq_map.extend_from_slice(&[
+                (devfn as u32) << 8,
+                0,
+                0,
+                pin as u32 + 1,
+                device_tree::GIC_PHANDLE,
+                0,
+                0,
+                device_tree::GIC_FDT_IRQ_TYPE_SPI,
+                pcie_irq_base + swizzle_map_irq(devfn, pin),
+                device_tree::IRQ_TYPE_LEVEL_HIGH,
+            ]);
+        }
+    }
+    fdt.set_property_u32("#interrupt-cells", 1)?;
+    fdt.set_property_array_u32("interrupt-map", &irq_map)?;
+    fdt.set_property_array_u32("interrupt-map-mask", &[0x1800, 0, 0, 0x7])?;
     fdt.end_node(pci_node_dep)?;
     Ok(())
 }
@@ -1398,6 +1751,22 @@ fn generate_fwcfg_device_node(fdt: &mut FdtBuilder, res: &SysRes) -> util::Resul
     Ok(())
 }
 
+// Function that helps to generate pvpanic node in device-tree.
+//
+// # Arguments
+//
+// * `dev_info` - Device resource info of pvpanic device.
+// * `fdt` - Flatted device-tree blob where pvpanic node will be filled into.
+fn generate_pvpanic_device_node(fdt: &mut FdtBuilder, res: &SysRes) -> util::Result<()> {
+    let node = format!("pvpanic@{:x}", res.region_base);
+    let pvpanic_node_dep = fdt.begin_node(&node)?;
+    fdt.set_property_string("compatible", "qemu,pvpanic-mmio")?;
+    fdt.set_property_array_u64("reg", &[res.region_base, res.region_size])?;
+    fdt.end_node(pvpanic_node_dep)?;
+
+    Ok(())
+}
+
 // Function that helps to generate serial node in device-tree.
 //
 // # Arguments
@@ -1545,10 +1914,9 @@ impl CompileFDTHelper for StdMachine {
             fdt.set_property_u32("phandle", device_tree::FIRST_VCPU_PHANDLE)?;
 
             if let Some(numa_nodes) = &self.numa_nodes {
-                for numa_index in 0..numa_nodes.len() {
-                    let numa_node = numa_nodes.get(&(numa_index as u32));
-                    if numa_node.unwrap().cpus.contains(&(cpu_index as u8)) {
-                        fdt.set_property_u32("numa-node-id", numa_index as u32)?;
+                for (id, node) in numa_nodes.iter() {
+                    if node.cpus.contains(&(cpu_index as u8)) {
+                        fdt.set_property_u32("numa-node-id", *id)?;
                     }
                 }
             }
@@ -1581,13 +1949,13 @@ impl CompileFDTHelper for StdMachine {
 
         // Set NUMA node information.
         let mut mem_base = MEM_LAYOUT[LayoutEntryType::Mem as usize].0;
-        for (id, node) in self.numa_nodes.as_ref().unwrap().iter().enumerate() {
-            let mem_size = node.1.size;
+        for (id, node) in self.numa_nodes.as_ref().unwrap().iter() {
+            let mem_size = node.size;
             let node = format!("memory@{:x}", mem_base);
             let memory_node_dep = fdt.begin_node(&node)?;
             fdt.set_property_string("device_type", "memory")?;
             fdt.set_property_array_u64("reg", &[mem_base, mem_size as u64])?;
-            fdt.set_property_u32("numa-node-id", id as u32)?;
+            fdt.set_property_u32("numa-node-id", *id)?;
             fdt.end_node(memory_node_dep)?;
             mem_base += mem_size;
         }
@@ -1646,6 +2014,10 @@ impl CompileFDTHelper for StdMachine {
                     // SAFETY: Legacy devices guarantee is not empty.
                     generate_fwcfg_device_node(fdt, locked_dev.get_sys_resource().unwrap())?;
                 }
+                SysBusDevType::PvPanic => {
+                    // SAFETY: Legacy devices guarantee is not empty.
+                    generate_pvpanic_device_node(fdt, locked_dev.get_sys_resource().unwrap())?;
+                }
                 _ => (),
             }
         }
@@ -1692,12 +2064,12 @@ impl CompileFDTHelper for StdMachine {
         let mut matrix = Vec::new();
         let numa_nodes = self.numa_nodes.as_ref().unwrap();
         let existing_nodes: Vec<u32> = numa_nodes.keys().cloned().collect();
-        for (id, node) in numa_nodes.iter().enumerate() {
-            let distances = &node.1.distances;
+        for (id, node) in numa_nodes.iter() {
+            let distances = &node.distances;
             for i in existing_nodes.iter() {
-                matrix.push(id as u32);
-                matrix.push(*i as u32);
-                let dist: u32 = if id as u32 == *i {
+                matrix.push(*id);
+                matrix.push(*i);
+                let dist: u32 = if *id == *i {
                     10
                 } else if let Some(distance) = distances.get(i) {
                     *distance as u32
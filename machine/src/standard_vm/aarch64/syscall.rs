@@ -44,6 +44,7 @@ const FUTEX_WAIT_BITSET_PRIVATE: u32 = FUTEX_WAIT_BITSET | FUTEX_PRIVATE_FLAG;
 /// See: https://elixir.bootlin.com/linux/v4.19.123/source/include/uapi/linux/fcntl.h
 const F_GETFD: u32 = 1;
 const F_SETFD: u32 = 2;
+const F_GETFL: u32 = 3;
 const F_SETFL: u32 = 4;
 const F_LINUX_SPECIFIC_BASE: u32 = 1024;
 const F_DUPFD_CLOEXEC: u32 = F_LINUX_SPECIFIC_BASE + 6;
@@ -61,8 +62,8 @@ const KVM_RUN: u32 = 0xae80;
 ///
 /// # Notes
 /// This allowlist limit syscall with:
-/// * aarch64-unknown-gnu: 98 syscalls
-/// * aarch64-unknown-musl: 60 syscalls
+/// * aarch64-unknown-gnu: 99 syscalls
+/// * aarch64-unknown-musl: 61 syscalls
 /// To reduce performance losses, the syscall rules is ordered by frequency.
 pub fn syscall_whitelist() -> Vec<BpfRule> {
     vec![
@@ -91,12 +92,15 @@ pub fn syscall_whitelist() -> Vec<BpfRule> {
         BpfRule::new(libc::SYS_sendmmsg),
         BpfRule::new(libc::SYS_recvfrom),
         BpfRule::new(libc::SYS_mremap),
+        // Lock the memory of hotplugged memory device.
+        BpfRule::new(libc::SYS_mlock),
         BpfRule::new(libc::SYS_io_setup),
         BpfRule::new(libc::SYS_brk),
         BpfRule::new(libc::SYS_fcntl)
             .add_constraint(SeccompCmpOpt::Eq, 1, F_DUPFD_CLOEXEC)
             .add_constraint(SeccompCmpOpt::Eq, 1, F_SETFD)
             .add_constraint(SeccompCmpOpt::Eq, 1, F_GETFD)
+            .add_constraint(SeccompCmpOpt::Eq, 1, F_GETFL)
             .add_constraint(SeccompCmpOpt::Eq, 1, F_SETFL),
         BpfRule::new(libc::SYS_flock),
         BpfRule::new(libc::SYS_rt_sigprocmask),
@@ -177,6 +181,7 @@ pub fn syscall_whitelist() -> Vec<BpfRule> {
         BpfRule::new(libc::SYS_set_robust_list),
         #[cfg(target_env = "gnu")]
         BpfRule::new(libc::SYS_sched_getaffinity),
+        BpfRule::new(libc::SYS_sched_setaffinity),
         #[cfg(target_env = "gnu")]
         BpfRule::new(libc::SYS_rseq),
         #[cfg(target_env = "gnu")]
@@ -272,6 +277,8 @@ fn ioctl_allow_list() -> BpfRule {
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_REG_LIST() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_ARM_VCPU_INIT() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_DIRTY_LOG() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_RESET_DIRTY_RINGS() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_CLEAR_DIRTY_LOG() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_IRQ_LINE() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_ONE_REG() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, VIDIOC_QUERYCAP() as u32)
@@ -20,7 +20,8 @@ pub use error::StandardVmError;
 
 #[cfg(target_arch = "aarch64")]
 pub use aarch64::StdMachine;
-use log::error;
+use log::{error, info, warn};
+use machine_manager::event;
 use machine_manager::event_loop::EventLoop;
 use machine_manager::qmp::qmp_schema::{BlockDevAddArgument, UpdateRegionArgument};
 use machine_manager::{config::get_cameradev_config, machine::MachineLifecycle};
@@ -30,7 +31,9 @@ use ui::{
     vnc::qmp_query_vnc,
 };
 use util::aio::{AioEngine, WriteZeroesState};
-use util::loop_context::{read_fd, EventNotifier, NotifierCallback, NotifierOperation};
+use util::loop_context::{
+    gen_delete_notifiers, read_fd, EventNotifier, NotifierCallback, NotifierOperation,
+};
 use vmm_sys_util::epoll::EventSet;
 use vmm_sys_util::eventfd::EventFd;
 #[cfg(target_arch = "x86_64")]
@@ -45,7 +48,7 @@ use std::string::String;
 use std::sync::{Arc, Mutex};
 
 use super::Result as MachineResult;
-use crate::MachineOps;
+use crate::{check_protected_device, MachineOps};
 #[cfg(target_arch = "x86_64")]
 use acpi::AcpiGenericAddress;
 use acpi::{
@@ -60,19 +63,22 @@ use anyhow::{bail, Context};
 use block_backend::{qcow2::QCOW2_LIST, BlockStatus};
 use cpu::{CpuTopology, CPU};
 use devices::legacy::FwCfgOps;
+use devices::misc::tpm::{TPM_CRB_CTRL_AREA_OFFSET, TPM_LOG_AREA_MIN_SIZE, TPM_LOG_FILE};
 use machine_manager::config::{
-    get_chardev_config, get_netdev_config, get_pci_df, memory_unit_conversion, BlkDevConfig,
-    ChardevType, ConfigCheck, DiskFormat, DriveConfig, ExBool, NetworkInterfaceConfig, NumaNode,
-    NumaNodes, PciBdf, ScsiCntlrConfig, VmConfig, DEFAULT_VIRTQUEUE_SIZE, MAX_VIRTIO_QUEUE,
+    get_chardev_config, get_netdev_config, get_pci_df, memory_unit_conversion, qom_peripheral_id,
+    BlkDevConfig, BootSource, ChardevType, ConfigCheck, DiskFormat, DriveConfig, ExBool,
+    HmatDataType, NetworkInterfaceConfig, NumaNode, NumaNodes, PanicAction, PciBdf, RebootAction,
+    ScsiCntlrConfig, VmConfig, WatchdogAction, DEFAULT_VIRTQUEUE_SIZE, MAX_VIRTIO_QUEUE,
 };
-use machine_manager::machine::{DeviceInterface, KvmVmState};
+use machine_manager::machine::{set_stop_reason, vm_status_info, DeviceInterface, StopReason};
 use machine_manager::qmp::{qmp_schema, QmpChannel, Response};
 use migration::MigrationManager;
 use pci::hotplug::{handle_plug, handle_unplug_pci_request};
 use pci::PciBus;
 use util::byte_code::ByteCode;
 use virtio::{
-    qmp_balloon, qmp_query_balloon, Block, BlockState,
+    qmp_balloon, qmp_balloon_set_stats_interval, qmp_guest_agent_command, qmp_query_balloon, Block,
+    BlockState,
     ScsiCntlr::{scsi_cntlr_create_scsi_bus, ScsiCntlr},
     VhostKern, VhostUser, VirtioDevice, VirtioNetState, VirtioPciDevice,
 };
@@ -83,7 +89,9 @@ use aarch64::{LayoutEntryType, MEM_LAYOUT};
 use x86_64::{LayoutEntryType, MEM_LAYOUT};
 
 #[cfg(target_arch = "x86_64")]
-use self::x86_64::ich9_lpc::{PM_CTRL_OFFSET, PM_EVENT_OFFSET, RST_CTRL_OFFSET, SLEEP_CTRL_OFFSET};
+use self::x86_64::ich9_lpc::{
+    PM_CTRL_OFFSET, PM_EVENT_OFFSET, RST_CTRL_OFFSET, SCI_INT, SLEEP_CTRL_OFFSET,
+};
 
 trait StdMachineOps: AcpiBuilder {
     fn init_pci_host(&self) -> Result<()>;
@@ -153,6 +161,20 @@ trait StdMachineOps: AcpiBuilder {
             let slit_addr = Self::build_slit_table(numa_nodes, &acpi_tables, &mut loader)
                 .with_context(|| "Failed to build ACPI SLIT table")?;
             xsdt_entries.push(slit_addr);
+
+            if numa_nodes.values().any(|node| !node.hmat_lb.is_empty()) {
+                let hmat_addr = Self::build_hmat_table(numa_nodes, &acpi_tables, &mut loader)
+                    .with_context(|| "Failed to build ACPI HMAT table")?;
+                xsdt_entries.push(hmat_addr);
+            }
+        }
+
+        let mut tpm_log = None;
+        if let Some(tpm_base) = self.get_tpm_base() {
+            let (tpm2_addr, log) = Self::build_tpm2_table(tpm_base, &acpi_tables, &mut loader)
+                .with_context(|| "Failed to build ACPI TPM2 table")?;
+            xsdt_entries.push(tpm2_addr);
+            tpm_log = Some(log);
         }
 
         #[cfg(target_arch = "aarch64")]
@@ -179,6 +201,11 @@ trait StdMachineOps: AcpiBuilder {
         locked_fw_cfg
             .add_file_entry(ACPI_TABLE_FILE, acpi_tables.lock().unwrap().to_vec())
             .with_context(|| "Failed to add ACPI-tables file entry")?;
+        if let Some(log) = tpm_log {
+            locked_fw_cfg
+                .add_file_entry(TPM_LOG_FILE, log.lock().unwrap().to_vec())
+                .with_context(|| "Failed to add TPM log file entry")?;
+        }
 
         Ok(())
     }
@@ -187,12 +214,29 @@ trait StdMachineOps: AcpiBuilder {
         bail!("Not implemented");
     }
 
+    /// Hotplug memory device.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - Device arguments of `device_add`.
+    fn plug_pc_dimm(&mut self, _args: &qmp_schema::DeviceAddArgument) -> Result<()> {
+        bail!("Memory hotplug is not supported");
+    }
+
     fn get_cpu_topo(&self) -> &CpuTopology;
 
     fn get_cpus(&self) -> &Vec<Arc<CPU>>;
 
     fn get_guest_numa(&self) -> &Option<NumaNodes>;
 
+    /// Get the base address of TPM CRB registers, returns None if TPM is not configured.
+    fn get_tpm_base(&self) -> Option<u64>;
+
+    fn get_boot_source(&self) -> &Arc<Mutex<BootSource>>;
+
+    /// Pass the kernel cmdline of boot source to guest at the next boot.
+    fn update_kernel_cmdline(&mut self) -> Result<()>;
+
     /// Register event notifier for reset of standard machine.
     ///
     /// # Arguments
@@ -207,6 +251,18 @@ trait StdMachineOps: AcpiBuilder {
         let reset_req_fd = reset_req.as_raw_fd();
         let reset_req_handler: Rc<NotifierCallback> = Rc::new(move |_, _| {
             read_fd(reset_req_fd);
+            if clone_vm.lock().unwrap().get_reboot_action() == RebootAction::Shutdown {
+                info!("Guest requested reboot, shut down VM as the reboot action");
+                if !clone_vm.lock().unwrap().destroy() {
+                    return None;
+                }
+                let shutdown_msg = qmp_schema::Shutdown {
+                    guest: true,
+                    reason: "guest-reset".to_string(),
+                };
+                event!(Shutdown; shutdown_msg);
+                return Some(gen_delete_notifiers(&[reset_req_fd]));
+            }
             if let Err(e) = StdMachine::handle_reset_request(&clone_vm) {
                 error!("Fail to reboot standard VM, {:?}", e);
             }
@@ -277,13 +333,172 @@ trait StdMachineOps: AcpiBuilder {
         Ok(())
     }
 
+    /// Register event notifier for guest panic reported by pvpanic device.
+    ///
+    /// # Arguments
+    ///
+    /// * `panic_req` - Eventfd of the guest panic.
+    /// * `clone_vm` - Reference of the StdMachine.
+    fn register_panic_event(
+        &self,
+        panic_req: Arc<EventFd>,
+        clone_vm: Arc<Mutex<StdMachine>>,
+    ) -> MachineResult<()> {
+        let panic_req_fd = panic_req.as_raw_fd();
+        let panic_req_handler: Rc<NotifierCallback> = Rc::new(move |_, _| {
+            let _ret = panic_req.read();
+            let mut action = clone_vm.lock().unwrap().get_panic_action();
+            if clone_vm.lock().unwrap().in_crash_loop() {
+                warn!("Guest is crash looping, shut down VM");
+                action = PanicAction::Shutdown;
+            }
+            info!("Guest panicked, action: {:?}", action);
+            let panicked_msg = qmp_schema::GuestPanicked {
+                action: action.name().to_string(),
+                info: Some(qmp_schema::GuestPanicInformation {
+                    panic_type: "pvpanic".to_string(),
+                    ..Default::default()
+                }),
+            };
+            event!(GuestPanicked; panicked_msg);
+            match action {
+                PanicAction::Pause => {
+                    set_stop_reason(StopReason::GuestPanicked);
+                    if !clone_vm.lock().unwrap().pause() {
+                        error!("Failed to pause VM after guest panicked");
+                    }
+                }
+                PanicAction::Shutdown => {
+                    if clone_vm.lock().unwrap().destroy() {
+                        return Some(gen_delete_notifiers(&[panic_req_fd]));
+                    }
+                }
+                PanicAction::Reset => {
+                    clone_vm.lock().unwrap().reset();
+                }
+                PanicAction::None => {}
+            }
+            None
+        });
+
+        let notifier = EventNotifier::new(
+            NotifierOperation::AddShared,
+            panic_req_fd,
+            None,
+            EventSet::IN,
+            vec![panic_req_handler],
+        );
+        EventLoop::update_event(vec![notifier], None)
+            .with_context(|| "Failed to register event notifier.")?;
+        Ok(())
+    }
+
+    /// Register event notifier for expiry of the watchdog device.
+    ///
+    /// # Arguments
+    ///
+    /// * `watchdog_req` - Eventfd of the watchdog expiry.
+    /// * `clone_vm` - Reference of the StdMachine.
+    fn register_watchdog_event(
+        &self,
+        watchdog_req: Arc<EventFd>,
+        clone_vm: Arc<Mutex<StdMachine>>,
+    ) -> MachineResult<()> {
+        let watchdog_req_fd = watchdog_req.as_raw_fd();
+        let watchdog_req_handler: Rc<NotifierCallback> = Rc::new(move |_, _| {
+            let _ret = watchdog_req.read();
+            let vm_config = clone_vm.lock().unwrap().get_vm_config();
+            let mut action = vm_config.lock().unwrap().machine_config.watchdog_action;
+            if clone_vm.lock().unwrap().in_crash_loop() {
+                warn!("Guest is crash looping, shut down VM");
+                action = WatchdogAction::Shutdown;
+            }
+            info!("Watchdog timer expired, action: {:?}", action);
+            let watchdog_msg = qmp_schema::Watchdog {
+                action: action.name().to_string(),
+            };
+            event!(Watchdog; watchdog_msg);
+            match action {
+                WatchdogAction::Reset => {
+                    if let Err(e) = StdMachine::handle_reset_request(&clone_vm) {
+                        error!("Fail to reset standard VM after watchdog expired, {:?}", e);
+                    }
+                }
+                WatchdogAction::Shutdown => {
+                    if clone_vm.lock().unwrap().destroy() {
+                        return Some(gen_delete_notifiers(&[watchdog_req_fd]));
+                    }
+                }
+                WatchdogAction::Pause => {
+                    set_stop_reason(StopReason::Watchdog);
+                    if !clone_vm.lock().unwrap().pause() {
+                        error!("Failed to pause VM after watchdog expired");
+                    }
+                }
+                WatchdogAction::InjectNmi => {
+                    #[cfg(target_arch = "x86_64")]
+                    for cpu in clone_vm.lock().unwrap().get_cpus() {
+                        if let Err(e) = cpu.inject_nmi() {
+                            error!("{:?}", e);
+                        }
+                    }
+                    #[cfg(target_arch = "aarch64")]
+                    error!("Injecting NMI is not supported on aarch64");
+                }
+                WatchdogAction::None => {}
+            }
+            None
+        });
+
+        let notifier = EventNotifier::new(
+            NotifierOperation::AddShared,
+            watchdog_req_fd,
+            None,
+            EventSet::IN,
+            vec![watchdog_req_handler],
+        );
+        EventLoop::update_event(vec![notifier], None)
+            .with_context(|| "Failed to register event notifier.")?;
+        Ok(())
+    }
+
+    /// Register event notifier for guest suspend request.
+    ///
+    /// # Arguments
+    ///
+    /// * `suspend_req` - Eventfd of the suspend request.
+    /// * `clone_vm` - Reference of the StdMachine.
+    fn register_suspend_event(
+        &self,
+        suspend_req: Arc<EventFd>,
+        clone_vm: Arc<Mutex<StdMachine>>,
+    ) -> MachineResult<()> {
+        let suspend_req_fd = suspend_req.as_raw_fd();
+        let suspend_req_handler: Rc<NotifierCallback> = Rc::new(move |_, _| {
+            let _ret = suspend_req.read();
+            if !clone_vm.lock().unwrap().suspend() {
+                error!("VM suspend failed");
+            }
+            None
+        });
+
+        let notifier = EventNotifier::new(
+            NotifierOperation::AddShared,
+            suspend_req_fd,
+            None,
+            EventSet::IN,
+            vec![suspend_req_handler],
+        );
+        EventLoop::update_event(vec![notifier], None)
+            .with_context(|| "Failed to register event notifier.")?;
+        Ok(())
+    }
+
     fn register_shutdown_event(
         &self,
         shutdown_req: Arc<EventFd>,
         clone_vm: Arc<Mutex<StdMachine>>,
     ) -> MachineResult<()> {
-        use util::loop_context::gen_delete_notifiers;
-
         let shutdown_req_fd = shutdown_req.as_raw_fd();
         let shutdown_req_handler: Rc<NotifierCallback> = Rc::new(move |_, _| {
             let _ret = shutdown_req.read();
@@ -511,6 +726,9 @@ trait AcpiBuilder {
         let mut fadt = AcpiTable::new(*b"FACP", 6, *b"STRATO", *b"VIRTFACP", 1);
 
         fadt.set_table_len(208_usize);
+        // SCI_INT, offset is 46.
+        #[cfg(target_arch = "x86_64")]
+        fadt.set_field(46, SCI_INT as u16);
         // PM1A_EVENT bit, offset is 56.
         #[cfg(target_arch = "x86_64")]
         fadt.set_field(56, 0x600);
@@ -661,6 +879,62 @@ trait AcpiBuilder {
         loader: &mut TableLoader,
     ) -> Result<u64>;
 
+    /// Build ACPI TPM2 table, returns the offset of ACPI TPM2 table in `acpi_data`
+    /// and the TCG event log area which firmware uses for measured boot.
+    ///
+    /// # Arguments
+    ///
+    /// `tpm_base` - Base address of TPM CRB registers.
+    /// `acpi_data` - Bytes streams that ACPI tables converts to.
+    /// `loader` - ACPI table loader.
+    fn build_tpm2_table(
+        tpm_base: u64,
+        acpi_data: &Arc<Mutex<Vec<u8>>>,
+        loader: &mut TableLoader,
+    ) -> Result<(u64, Arc<Mutex<Vec<u8>>>)> {
+        // Offset of Log Area Start Address in TPM2 table.
+        const TPM2_LASA_OFFSET: u32 = 68;
+        // Start method: Command Response Buffer.
+        const TPM2_START_METHOD_CRB: u32 = 7;
+
+        let tpm_log = Arc::new(Mutex::new(vec![0_u8; TPM_LOG_AREA_MIN_SIZE as usize]));
+        loader.add_alloc_entry(TPM_LOG_FILE, tpm_log.clone(), 1, false)?;
+
+        let mut tpm2 = AcpiTable::new(*b"TPM2", 4, *b"STRATO", *b"VIRTTPM2", 1);
+        // Platform Class: client, and Reserved.
+        tpm2.append_child(0_u16.as_bytes());
+        tpm2.append_child(0_u16.as_bytes());
+        // Address of CRB Control Area.
+        tpm2.append_child((tpm_base + TPM_CRB_CTRL_AREA_OFFSET).as_bytes());
+        tpm2.append_child(TPM2_START_METHOD_CRB.as_bytes());
+        // Start Method Specific Parameters.
+        tpm2.append_child(&[0_u8; 12]);
+        // Log Area Minimum Length and Log Area Start Address.
+        tpm2.append_child(TPM_LOG_AREA_MIN_SIZE.as_bytes());
+        tpm2.append_child(0_u64.as_bytes());
+
+        let mut acpi_data_locked = acpi_data.lock().unwrap();
+        let tpm2_begin = acpi_data_locked.len() as u32;
+        acpi_data_locked.extend(tpm2.aml_bytes());
+        let tpm2_end = acpi_data_locked.len() as u32;
+        drop(acpi_data_locked);
+
+        loader.add_pointer_entry(
+            ACPI_TABLE_FILE,
+            tpm2_begin + TPM2_LASA_OFFSET,
+            8,
+            TPM_LOG_FILE,
+            0,
+        )?;
+        loader.add_cksum_entry(
+            ACPI_TABLE_FILE,
+            tpm2_begin + TABLE_CHECKSUM_OFFSET,
+            tpm2_begin,
+            tpm2_end - tpm2_begin,
+        )?;
+        Ok((tpm2_begin as u64, tpm_log))
+    }
+
     /// Build ACPI SLIT table, returns the offset of ACPI SLIT table in `acpi_data`.
     ///
     /// # Arguments
@@ -677,10 +951,10 @@ trait AcpiBuilder {
         slit.append_child((numa_nodes.len() as u64).as_bytes());
 
         let existing_nodes: Vec<u32> = numa_nodes.keys().cloned().collect();
-        for (id, node) in numa_nodes.iter().enumerate() {
-            let distances = &node.1.distances;
+        for (id, node) in numa_nodes.iter() {
+            let distances = &node.distances;
             for i in existing_nodes.iter() {
-                let dist: u8 = if id as u32 == *i {
+                let dist: u8 = if *id == *i {
                     10
                 } else if let Some(distance) = distances.get(i) {
                     *distance
@@ -696,6 +970,116 @@ trait AcpiBuilder {
         Ok(slit_begin)
     }
 
+    /// Build ACPI HMAT table, returns the offset of ACPI HMAT table in `acpi_data`.
+    ///
+    /// # Arguments
+    ///
+    /// `numa_nodes` - The information of NUMA nodes.
+    /// `acpi_data` - Bytes streams that ACPI tables converts to.
+    /// `loader` - ACPI table loader.
+    fn build_hmat_table(
+        numa_nodes: &NumaNodes,
+        acpi_data: &Arc<Mutex<Vec<u8>>>,
+        loader: &mut TableLoader,
+    ) -> Result<u64> {
+        // Length of Memory Proximity Domain Attributes Structure.
+        const HMAT_MPDA_LEN: u32 = 40;
+        // Length of System Locality Latency and Bandwidth Information Structure
+        // without the domain lists and entries.
+        const HMAT_LB_HDR_LEN: u32 = 32;
+        // The max valid entry, 0xFFFF means the target is unreachable.
+        const HMAT_LB_MAX_ENTRY: u64 = 0xFFFE;
+
+        let mut hmat = AcpiTable::new(*b"HMAT", 2, *b"STRATO", *b"VIRTHMAT", 1);
+        // Reserved.
+        hmat.append_child(&[0_u8; 4]);
+
+        // The nodes with cpus are the initiators, and the node itself is the
+        // attached initiator of its memory.
+        let initiators: Vec<u32> = numa_nodes
+            .iter()
+            .filter(|(_, node)| !node.cpus.is_empty())
+            .map(|(id, _)| *id)
+            .collect();
+        let targets: Vec<u32> = numa_nodes.keys().cloned().collect();
+        for (id, node) in numa_nodes.iter() {
+            hmat.append_child(0_u16.as_bytes());
+            hmat.append_child(0_u16.as_bytes());
+            hmat.append_child(HMAT_MPDA_LEN.as_bytes());
+            // Flags: attached initiator proximity domain is valid.
+            let flags = u16::from(!node.cpus.is_empty());
+            hmat.append_child(flags.as_bytes());
+            hmat.append_child(0_u16.as_bytes());
+            hmat.append_child(id.as_bytes());
+            hmat.append_child(id.as_bytes());
+            hmat.append_child(&[0_u8; 20]);
+        }
+
+        let mut data_types: Vec<HmatDataType> = numa_nodes
+            .values()
+            .flat_map(|node| node.hmat_lb.keys().map(|(data_type, _)| *data_type))
+            .collect();
+        data_types.sort_unstable();
+        data_types.dedup();
+        for data_type in data_types {
+            let value = |initiator: &u32, target: &u32| {
+                numa_nodes
+                    .get(initiator)
+                    .and_then(|node| node.hmat_lb.get(&(data_type, *target)))
+                    .copied()
+                    .unwrap_or_default()
+            };
+            let max_value = initiators
+                .iter()
+                .flat_map(|i| targets.iter().map(move |t| (i, t)))
+                .map(|(i, t)| value(i, t))
+                .max()
+                .unwrap_or_default();
+            let mut base = 1_u64;
+            while max_value / base > HMAT_LB_MAX_ENTRY {
+                base *= 10;
+            }
+
+            let len = HMAT_LB_HDR_LEN
+                + 4 * (initiators.len() + targets.len()) as u32
+                + 2 * (initiators.len() * targets.len()) as u32;
+            hmat.append_child(1_u16.as_bytes());
+            hmat.append_child(0_u16.as_bytes());
+            hmat.append_child(len.as_bytes());
+            // Flags: memory hierarchy, data type, min transfer size and reserved.
+            hmat.append_child(&[0_u8, data_type as u8, 0_u8, 0_u8]);
+            hmat.append_child((initiators.len() as u32).as_bytes());
+            hmat.append_child((targets.len() as u32).as_bytes());
+            hmat.append_child(0_u32.as_bytes());
+            // Latency is in picoseconds and bandwidth is in MB/s.
+            let base_unit = if data_type.is_latency() {
+                base * 1000
+            } else {
+                base
+            };
+            hmat.append_child(base_unit.as_bytes());
+            for id in initiators.iter().chain(targets.iter()) {
+                hmat.append_child(id.as_bytes());
+            }
+            for initiator in initiators.iter() {
+                for target in targets.iter() {
+                    // 0 means the value is not provided, so small values are rounded up.
+                    let value = value(initiator, target);
+                    let entry = if value == 0 {
+                        0_u16
+                    } else {
+                        std::cmp::max(value / base, 1) as u16
+                    };
+                    hmat.append_child(entry.as_bytes());
+                }
+            }
+        }
+
+        let hmat_begin = StdMachine::add_table_to_loader(acpi_data, loader, &hmat)
+            .with_context(|| "Fail to add HMAT table to loader")?;
+        Ok(hmat_begin)
+    }
+
     /// Build ACPI XSDT table, returns the offset of ACPI XSDT table in `acpi_data`.
     ///
     /// # Arguments
@@ -919,8 +1303,13 @@ impl StdMachine {
         drop(locked_vmconfig);
 
         let blk = Arc::new(Mutex::new(VhostUser::Block::new(&dev, self.get_sys_mem())));
-        self.add_virtio_pci_device(&args.id, pci_bdf, blk, multifunction, true)
+        self.add_virtio_pci_device(&args.id, pci_bdf, blk.clone(), multifunction, true)
             .with_context(|| "Failed to add vhost user blk pci device")?;
+        MigrationManager::register_device_instance(
+            VhostUser::VhostUserState::descriptor(),
+            blk,
+            &args.id,
+        );
 
         Ok(())
     }
@@ -982,6 +1371,15 @@ impl StdMachine {
                 mq: conf.queues > 2,
                 socket_path,
                 queue_size,
+                failover: args.failover.unwrap_or(false),
+                coalesce_frames: args.coalesce_frames.unwrap_or_default(),
+                coalesce_usecs: args.coalesce_usecs.unwrap_or_default(),
+                status: locked_vmconfig
+                    .machine_config
+                    .mach_version
+                    .compat_prop("virtio-net", "status")
+                    .map_or(true, |status| status == "on"),
+                boot_index: args.boot_index,
             };
             dev.check()?;
             dev
@@ -991,21 +1389,40 @@ impl StdMachine {
         locked_vmconfig.add_net_device_config(args);
         drop(locked_vmconfig);
 
-        if dev.vhost_type.is_some() {
-            let net: Arc<Mutex<dyn VirtioDevice>> =
-                if dev.vhost_type == Some(String::from("vhost-kernel")) {
-                    Arc::new(Mutex::new(VhostKern::Net::new(&dev, self.get_sys_mem())))
-                } else {
-                    Arc::new(Mutex::new(VhostUser::Net::new(&dev, self.get_sys_mem())))
-                };
+        if let Some(bootindex) = args.boot_index {
+            self.check_bootindex(bootindex)
+                .with_context(|| "Fail to add virtio pci net device for invalid bootindex")?;
+        }
+
+        let pci_dev = if dev.vhost_type == Some(String::from("vhost-kernel")) {
+            let net = Arc::new(Mutex::new(VhostKern::Net::new(&dev, self.get_sys_mem())));
             self.add_virtio_pci_device(&args.id, pci_bdf, net, multifunction, true)
-                .with_context(|| "Failed to add vhost-kernel/vhost-user net device")?;
+                .with_context(|| "Failed to add vhost-kernel net device")?
+        } else if dev.vhost_type.is_some() {
+            let net = Arc::new(Mutex::new(VhostUser::Net::new(&dev, self.get_sys_mem())));
+            let pci_dev = self
+                .add_virtio_pci_device(&args.id, pci_bdf, net.clone(), multifunction, true)
+                .with_context(|| "Failed to add vhost-user net device")?;
+            MigrationManager::register_device_instance(
+                VhostUser::VhostUserState::descriptor(),
+                net,
+                &args.id,
+            );
+            pci_dev
         } else {
             let net_id = dev.id.clone();
             let net = Arc::new(Mutex::new(virtio::Net::new(dev)));
-            self.add_virtio_pci_device(&args.id, pci_bdf, net.clone(), multifunction, false)
+            let pci_dev = self
+                .add_virtio_pci_device(&args.id, pci_bdf, net.clone(), multifunction, false)
                 .with_context(|| "Failed to add virtio net device")?;
             MigrationManager::register_device_instance(VirtioNetState::descriptor(), net, &net_id);
+            pci_dev
+        };
+
+        if let Some(bootindex) = args.boot_index {
+            if let Some(dev_path) = pci_dev.lock().unwrap().get_dev_path() {
+                self.add_bootindex_devices(bootindex, &dev_path, &args.id);
+            }
         }
 
         Ok(())
@@ -1085,11 +1502,17 @@ impl StdMachine {
             bail!("Both option \"host\" and \"sysfsdev\" was provided.");
         }
 
+        let vm_config = self.get_vm_config();
+        if let Some(pair_id) = &args.failover_pair_id {
+            vm_config.lock().unwrap().check_failover_pair(pair_id)?;
+        }
+
         let host = args.host.as_ref().map_or("", String::as_str);
         let sysfsdev = args.sysfsdev.as_ref().map_or("", String::as_str);
         let multifunc = args.multifunction.unwrap_or(false);
         self.create_vfio_pci_device(&args.id, bdf, host, sysfsdev, multifunc)
             .with_context(|| "Failed to plug vfio-pci device.")?;
+        vm_config.lock().unwrap().add_vfio_device_config(args);
 
         Ok(())
     }
@@ -1113,25 +1536,28 @@ impl StdMachine {
             shutdown_req,
         );
     }
+
+    /// Find the virtio net device by its qom path.
+    fn find_qom_virtio_net(&mut self, path: &str) -> Option<Arc<Mutex<dyn VirtioDevice>>> {
+        let id = qom_peripheral_id(path)?;
+        let root_bus = self.get_pci_host().ok()?.lock().unwrap().root_bus.clone();
+        let (_, dev) = PciBus::find_attached_bus(&root_bus, id)?;
+        let locked_dev = dev.lock().unwrap();
+        let virtio_dev = locked_dev
+            .as_any()
+            .downcast_ref::<VirtioPciDevice>()?
+            .get_virtio_device()
+            .clone();
+        let is_net = virtio_dev.lock().unwrap().as_any().is::<virtio::Net>();
+        is_net.then_some(virtio_dev)
+    }
 }
 
 impl DeviceInterface for StdMachine {
     fn query_status(&self) -> Response {
         let vm_state = self.get_vm_state();
         let vmstate = vm_state.deref().0.lock().unwrap();
-        let qmp_state = match *vmstate {
-            KvmVmState::Running => qmp_schema::StatusInfo {
-                singlestep: false,
-                running: true,
-                status: qmp_schema::RunState::running,
-            },
-            KvmVmState::Paused => qmp_schema::StatusInfo {
-                singlestep: false,
-                running: false,
-                status: qmp_schema::RunState::paused,
-            },
-            _ => Default::default(),
-        };
+        let qmp_state = vm_status_info(*vmstate);
 
         Response::create_response(serde_json::to_value(&qmp_state).unwrap(), None)
     }
@@ -1175,10 +1601,36 @@ impl DeviceInterface for StdMachine {
         Response::create_response(cpu_vec.into(), None)
     }
 
+    fn query_cpus_fast(&self) -> Response {
+        let mut cpu_vec: Vec<qmp_schema::CpuInfoFast> = Vec::new();
+        let cpu_topo = self.get_cpu_topo();
+        let cpus = self.get_cpus();
+        for cpu_index in 0..cpu_topo.max_cpus {
+            if cpu_topo.get_mask(cpu_index as usize) == 1 {
+                cpu_vec.push(qmp_schema::CpuInfoFast {
+                    cpu_index: cpu_index as isize,
+                    qom_path: format!("/machine/unattached/device[{}]", cpu_index),
+                    thread_id: cpus[cpu_index as usize].tid() as isize,
+                    props: Some(cpu_topo.get_topo_instance_for_qmp(cpu_index as usize)),
+                    target: std::env::consts::ARCH.to_string(),
+                });
+            }
+        }
+        Response::create_response(serde_json::to_value(cpu_vec).unwrap(), None)
+    }
+
     fn query_hotpluggable_cpus(&self) -> Response {
         Response::create_empty_response()
     }
 
+    fn query_vcpu_stats(&self) -> Response {
+        crate::qmp_query_vcpu_stats(self.get_cpus())
+    }
+
+    fn set_vcpu_affinity(&self, cpu_index: usize, host_cpu: usize) -> Response {
+        crate::qmp_set_vcpu_affinity(self.get_cpus(), cpu_index, host_cpu)
+    }
+
     fn balloon(&self, value: u64) -> Response {
         if qmp_balloon(value) {
             return Response::create_empty_response();
@@ -1191,9 +1643,18 @@ impl DeviceInterface for StdMachine {
         )
     }
 
+    fn balloon_set_stats_interval(&self, interval: u64) -> Response {
+        match qmp_balloon_set_stats_interval(interval) {
+            Ok(()) => Response::create_empty_response(),
+            Err(e) => Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                None,
+            ),
+        }
+    }
+
     fn query_balloon(&self) -> Response {
-        if let Some(actual) = qmp_query_balloon() {
-            let ret = qmp_schema::BalloonInfo { actual };
+        if let Some(ret) = qmp_query_balloon() {
             return Response::create_response(serde_json::to_value(&ret).unwrap(), None);
         }
         Response::create_error_response(
@@ -1204,11 +1665,55 @@ impl DeviceInterface for StdMachine {
         )
     }
 
+    fn guest_agent_command(&self, args: qmp_schema::GuestAgentCmdArgument) -> Response {
+        match qmp_guest_agent_command(&args.command) {
+            Ok(()) => Response::create_empty_response(),
+            Err(e) => Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                None,
+            ),
+        }
+    }
+
     fn query_mem(&self) -> Response {
         self.mem_show();
         Response::create_empty_response()
     }
 
+    fn query_memory_rss(&self) -> Response {
+        crate::qmp_query_memory_rss(&self.sys_mem)
+    }
+
+    fn query_block(&self) -> Response {
+        let block_info = self.get_vm_config().lock().unwrap().get_block_info();
+        Response::create_response(serde_json::to_value(block_info).unwrap(), None)
+    }
+
+    fn query_migrate_capabilities(&self) -> Response {
+        migration::query_migrate_capabilities()
+    }
+
+    fn qom_list(&mut self, path: String) -> Response {
+        let net = self.find_qom_virtio_net(&path);
+        let vm_config = self.get_vm_config();
+        let locked_vmconfig = vm_config.lock().unwrap();
+        crate::qmp_qom_list(&locked_vmconfig, net, &path)
+    }
+
+    fn qom_get(&mut self, path: String, property: String) -> Response {
+        let net = self.find_qom_virtio_net(&path);
+        let vm_config = self.get_vm_config();
+        let locked_vmconfig = vm_config.lock().unwrap();
+        crate::qmp_qom_get(&locked_vmconfig, net, &path, &property)
+    }
+
+    fn qom_set(&mut self, path: String, property: String, value: qmp_schema::Any) -> Response {
+        let net = self.find_qom_virtio_net(&path);
+        let vm_config = self.get_vm_config();
+        let locked_vmconfig = vm_config.lock().unwrap();
+        crate::qmp_qom_set(&locked_vmconfig, net, &path, &property, value)
+    }
+
     fn query_vnc(&self) -> Response {
         #[cfg(not(target_env = "musl"))]
         if let Some(vnc_info) = qmp_query_vnc() {
@@ -1222,6 +1727,62 @@ impl DeviceInterface for StdMachine {
         )
     }
 
+    fn query_kernel_cmdline(&self) -> Response {
+        let cmdline = self
+            .get_boot_source()
+            .lock()
+            .unwrap()
+            .kernel_cmdline
+            .to_string();
+        let cmdline_info = qmp_schema::KernelCmdlineInfo { cmdline };
+        Response::create_response(serde_json::to_value(cmdline_info).unwrap(), None)
+    }
+
+    fn set_kernel_cmdline(&mut self, cmdline: String, append: Option<bool>) -> Response {
+        let result = self
+            .get_boot_source()
+            .lock()
+            .unwrap()
+            .amend_kernel_cmdline(&cmdline, append.unwrap_or(false));
+        if let Err(e) = result.and_then(|_| self.update_kernel_cmdline()) {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                None,
+            );
+        }
+        Response::create_empty_response()
+    }
+
+    fn watchdog_set_action(&mut self, action: String) -> Response {
+        let vm_config = self.get_vm_config();
+        let mut locked_vmconfig = vm_config.lock().unwrap();
+        if let Err(e) = locked_vmconfig.add_watchdog_action(&action) {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                None,
+            );
+        }
+        Response::create_empty_response()
+    }
+
+    fn query_pci(&mut self) -> Response {
+        let root_bus = match self.get_pci_host() {
+            Ok(host) => host.lock().unwrap().root_bus.clone(),
+            Err(e) => {
+                return Response::create_error_response(
+                    qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                    None,
+                )
+            }
+        };
+
+        let pci_info = vec![qmp_schema::PciInfo {
+            bus: 0,
+            devices: PciBus::query_devices(&root_bus, 0),
+        }];
+        Response::create_response(serde_json::to_value(pci_info).unwrap(), None)
+    }
+
     fn device_add(&mut self, args: Box<qmp_schema::DeviceAddArgument>) -> Response {
         if let Err(e) = self.check_device_id_existed(&args.id) {
             return Response::create_error_response(
@@ -1242,6 +1803,16 @@ impl DeviceInterface for StdMachine {
         };
 
         let driver = args.driver.as_str();
+        let vm_config = self.get_vm_config();
+        let protected =
+            check_protected_device(&vm_config.lock().unwrap(), driver, args.netdev.as_deref());
+        if let Err(e) = protected {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                None,
+            );
+        }
+
         match driver {
             "virtio-blk-pci" => {
                 if let Err(e) = self.plug_virtio_pci_blk(&pci_bdf, args.as_ref()) {
@@ -1303,6 +1874,17 @@ impl DeviceInterface for StdMachine {
                 }
                 return Response::create_empty_response();
             }
+            "pc-dimm" => {
+                if let Err(e) = self.plug_pc_dimm(args.as_ref()) {
+                    error!("{:?}", e);
+                    let err_str = format!("Failed to add pc-dimm: {}", e);
+                    return Response::create_error_response(
+                        qmp_schema::QmpErrorClass::GenericError(err_str),
+                        None,
+                    );
+                }
+                return Response::create_empty_response();
+            }
             _ => {
                 let err_str = format!("Failed to add device: Driver {} is not support", driver);
                 return Response::create_error_response(
@@ -1529,6 +2111,63 @@ impl DeviceInterface for StdMachine {
         }
     }
 
+    fn object_add(&mut self, args: qmp_schema::object_add) -> Response {
+        let vm_config = self.get_vm_config();
+        let mut locked_vmconfig = vm_config.lock().unwrap();
+        if let Err(e) = locked_vmconfig.add_object_from_qmp(&args.qom_type, &args.id, &args.props) {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                None,
+            );
+        }
+
+        if args.qom_type == "iothread" {
+            let config = locked_vmconfig
+                .iothreads
+                .iter()
+                .flatten()
+                .find(|thr| thr.id == args.id)
+                .cloned()
+                .unwrap_or_default();
+            if let Err(e) = EventLoop::add_iothread(&config) {
+                if let Err(err) = locked_vmconfig.del_object(&args.id) {
+                    error!("Failed to remove iothread config {}: {:?}", args.id, err);
+                }
+                return Response::create_error_response(
+                    qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                    None,
+                );
+            }
+        }
+        Response::create_empty_response()
+    }
+
+    fn object_del(&mut self, id: String) -> Response {
+        let vm_config = self.get_vm_config();
+        let mut locked_vmconfig = vm_config.lock().unwrap();
+        let is_iothread = locked_vmconfig
+            .iothreads
+            .as_ref()
+            .map_or(false, |iothreads| iothreads.iter().any(|t| t.id == id));
+        if is_iothread {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(format!(
+                    "Iothread {} can't be deleted at runtime",
+                    id
+                )),
+                None,
+            );
+        }
+
+        match locked_vmconfig.del_object(&id) {
+            Ok(_) => Response::create_empty_response(),
+            Err(e) => Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                None,
+            ),
+        }
+    }
+
     fn cameradev_add(&mut self, args: qmp_schema::CameraDevAddArgument) -> Response {
         let config = match get_cameradev_config(args) {
             Ok(conf) => conf,
@@ -10,6 +10,8 @@
 // NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
 // See the Mulan PSL v2 for more details.
 
+use std::os::unix::prelude::AsRawFd;
+use std::rc::Rc;
 use std::sync::{
     atomic::{AtomicU8, Ordering},
     Arc, Mutex, Weak,
@@ -20,7 +22,11 @@ use crate::standard_vm::Result;
 use acpi::{AcpiPMTimer, AcpiPmCtrl, AcpiPmEvent};
 use address_space::{AddressSpace, GuestAddress, Region, RegionOps};
 use anyhow::Context;
+use hypervisor::kvm::KVM_FDS;
 use log::error;
+use machine_manager::event;
+use machine_manager::event_loop::EventLoop;
+use machine_manager::qmp::QmpChannel;
 use pci::config::CLASS_CODE_ISA_BRIDGE;
 use pci::config::{
     PciConfig, DEVICE_ID, HEADER_TYPE, HEADER_TYPE_BRIDGE, HEADER_TYPE_MULTIFUNC,
@@ -29,6 +35,8 @@ use pci::config::{
 use pci::Result as PciResult;
 use pci::{le_write_u16, le_write_u32, ranges_overlap, PciBus, PciDevOps};
 use util::byte_code::ByteCode;
+use util::loop_context::{read_fd, EventNotifier, NotifierCallback, NotifierOperation};
+use vmm_sys_util::epoll::EventSet;
 use vmm_sys_util::eventfd::EventFd;
 
 const DEVICE_ID_INTEL_ICH9: u16 = 0x2918;
@@ -39,6 +47,15 @@ pub const PM_EVENT_OFFSET: u16 = 0x600;
 pub const PM_CTRL_OFFSET: u16 = 0x604;
 pub const SLEEP_CTRL_OFFSET: u16 = 0xCE9;
 pub const RST_CTRL_OFFSET: u16 = 0xCF9;
+/// ISA IRQ of the ACPI System Control Interrupt, it's reported to guest by FADT.
+pub const SCI_INT: u32 = 9;
+
+/// SLP_TYP value of S3 (suspend to RAM) sleep state, it's reported to guest by `_S3` in DSDT.
+pub const SLEEP_TYPE_S3: u8 = 1;
+/// Bits of sleep control register.
+const SLEEP_CTRL_TYPE_SHIFT: u8 = 2;
+const SLEEP_CTRL_TYPE_MASK: u8 = 0x1C;
+const SLEEP_CTRL_ENABLE: u8 = 0x20;
 
 /// LPC bridge of ICH9 (IO controller hub 9), Device 1F : Function 0
 #[allow(clippy::upper_case_acronyms)]
@@ -53,25 +70,34 @@ pub struct LPCBridge {
     /// Reset request triggered by ACPI PM1 Control Registers.
     pub reset_req: Arc<EventFd>,
     pub shutdown_req: Arc<EventFd>,
+    /// Suspend request triggered when guest enters S3 state.
+    pub suspend_req: Arc<EventFd>,
+    /// Power button request, which is reported to guest as ACPI fixed event.
+    pub power_button: Arc<EventFd>,
 }
 
 impl LPCBridge {
     pub fn new(
         parent_bus: Weak<Mutex<PciBus>>,
         sys_io: Arc<AddressSpace>,
+        pm_evt: Arc<Mutex<AcpiPmEvent>>,
         reset_req: Arc<EventFd>,
         shutdown_req: Arc<EventFd>,
+        suspend_req: Arc<EventFd>,
+        power_button: Arc<EventFd>,
     ) -> Result<Self> {
         Ok(Self {
             config: PciConfig::new(PCI_CONFIG_SPACE_SIZE, 0),
             parent_bus,
             sys_io,
             pm_timer: Arc::new(Mutex::new(AcpiPMTimer::new())),
-            pm_evt: Arc::new(Mutex::new(AcpiPmEvent::new())),
+            pm_evt,
             pm_ctrl: Arc::new(Mutex::new(AcpiPmCtrl::new())),
             rst_ctrl: Arc::new(AtomicU8::new(0)),
             reset_req,
             shutdown_req,
+            suspend_req,
+            power_button,
         })
     }
 
@@ -151,9 +177,17 @@ impl LPCBridge {
         };
 
         let cloned_shutdown_fd = self.shutdown_req.clone();
-        let write_ops = move |_data: &[u8], _addr: GuestAddress, _offset: u64| -> bool {
-            if cloned_shutdown_fd.write(1).is_err() {
-                error!("X86 standard vm write shutdown fd failed");
+        let cloned_suspend_fd = self.suspend_req.clone();
+        let write_ops = move |data: &[u8], _addr: GuestAddress, _offset: u64| -> bool {
+            let value = data.first().copied().unwrap_or_default();
+            let sleep_type = (value & SLEEP_CTRL_TYPE_MASK) >> SLEEP_CTRL_TYPE_SHIFT;
+            let req_fd = if value & SLEEP_CTRL_ENABLE != 0 && sleep_type == SLEEP_TYPE_S3 {
+                &cloned_suspend_fd
+            } else {
+                &cloned_shutdown_fd
+            };
+            if req_fd.write(1).is_err() {
+                error!("X86 standard vm write sleep request fd failed");
                 return false;
             }
             true
@@ -201,11 +235,19 @@ impl LPCBridge {
 
         let clone_pmctrl = self.pm_ctrl.clone();
         let cloned_shutdown_fd = self.shutdown_req.clone();
+        let cloned_suspend_fd = self.suspend_req.clone();
         let write_ops = move |data: &[u8], addr: GuestAddress, offset: u64| -> bool {
-            if clone_pmctrl.lock().unwrap().write(data, addr, offset)
-                && cloned_shutdown_fd.write(1).is_err()
-            {
-                error!("X86 standard vm write shutdown fd failed");
+            let mut locked_pmctrl = clone_pmctrl.lock().unwrap();
+            if !locked_pmctrl.write(data, addr, offset) {
+                return true;
+            }
+            let req_fd = if locked_pmctrl.sleep_type() == SLEEP_TYPE_S3 {
+                &cloned_suspend_fd
+            } else {
+                &cloned_shutdown_fd
+            };
+            if req_fd.write(1).is_err() {
+                error!("X86 standard vm write sleep request fd failed");
                 return false;
             }
             true
@@ -222,6 +264,38 @@ impl LPCBridge {
 
         Ok(())
     }
+
+    fn init_power_button(&self) -> Result<()> {
+        let sci_evt = EventFd::new(libc::EFD_NONBLOCK)?;
+        KVM_FDS
+            .load()
+            .register_irqfd(&sci_evt, SCI_INT)
+            .with_context(|| "Failed to register irqfd for SCI")?;
+
+        let power_button_fd = self.power_button.as_raw_fd();
+        let cloned_pmevt = self.pm_evt.clone();
+        let power_button_handler: Rc<NotifierCallback> = Rc::new(move |_, _| {
+            read_fd(power_button_fd);
+            if cloned_pmevt.lock().unwrap().press_power_button() && sci_evt.write(1).is_err() {
+                error!("X86 standard vm write SCI eventfd failed");
+            }
+            if QmpChannel::is_connected() {
+                event!(Powerdown);
+            }
+            None
+        });
+
+        let notifier = EventNotifier::new(
+            NotifierOperation::AddShared,
+            power_button_fd,
+            None,
+            EventSet::IN,
+            vec![power_button_handler],
+        );
+        EventLoop::update_event(vec![notifier], None)
+            .with_context(|| "Failed to register power button notifier")?;
+        Ok(())
+    }
 }
 
 impl PciDevOps for LPCBridge {
@@ -265,6 +339,8 @@ impl PciDevOps for LPCBridge {
             .with_context(|| "Fail to init IO region for PM events register")?;
         self.init_pm_ctrl_reg()
             .with_context(|| "Fail to init IO region for PM control register")?;
+        self.init_power_button()
+            .with_context(|| "Fail to init ACPI power button")?;
 
         let parent_bus = self.parent_bus.clone();
         parent_bus
@@ -293,4 +369,8 @@ impl PciDevOps for LPCBridge {
     fn name(&self) -> String {
         "ICH9 LPC bridge".to_string()
     }
+
+    fn get_pci_config(&self) -> Option<&PciConfig> {
+        Some(&self.config)
+    }
 }
@@ -169,4 +169,8 @@ impl PciDevOps for Mch {
     fn name(&self) -> String {
         "Memory Controller Hub".to_string()
     }
+
+    fn get_pci_config(&self) -> Option<&PciConfig> {
+        Some(&self.config)
+    }
 }
@@ -24,44 +24,53 @@ use std::sync::{Arc, Condvar, Mutex};
 use vmm_sys_util::eventfd::EventFd;
 
 use acpi::{
-    AcpiIoApic, AcpiLocalApic, AcpiSratMemoryAffinity, AcpiSratProcessorAffinity, AcpiTable,
-    AmlBuilder, AmlDevice, AmlInteger, AmlNameDecl, AmlPackage, AmlScope, AmlScopeBuilder,
-    AmlString, TableLoader, IOAPIC_BASE_ADDR, LAPIC_BASE_ADDR,
+    AcpiInterruptSourceOverride, AcpiIoApic, AcpiLocalApic, AcpiPmEvent, AcpiSratMemoryAffinity,
+    AcpiSratProcessorAffinity, AcpiTable, AmlBuilder, AmlDevice, AmlInteger, AmlNameDecl,
+    AmlPackage, AmlScope, AmlScopeBuilder, AmlString, TableLoader, IOAPIC_BASE_ADDR,
+    LAPIC_BASE_ADDR,
 };
 use address_space::{AddressSpace, GuestAddress, HostMemMapping, Region};
 use boot_loader::{load_linux, BootLoaderConfig};
 use cpu::{CPUBootConfig, CPUInterface, CPUTopology, CpuTopology, CPU};
+#[cfg(not(target_env = "musl"))]
+use devices::legacy::Ramfb;
 use devices::legacy::{
     error::LegacyError as DevErrorKind, FwCfgEntryType, FwCfgIO, FwCfgOps, PFlash, Serial, RTC,
     SERIAL_ADDR,
 };
-use hypervisor::kvm::KVM_FDS;
+use devices::misc::i6300esb::I6300Esb;
+use devices::misc::pvpanic::{PvPanic, PVPANIC_PORT, PVPANIC_REG_SIZE};
+use devices::misc::tpm::{TpmCrb, TpmEmulator, TPM_CRB_ADDR_BASE, TPM_CRB_ADDR_SIZE};
+use devices::Ioapic;
+use hypervisor::kvm::{IOAPIC_NUM_PINS, KVM_FDS};
 use kvm_bindings::{kvm_pit_config, KVM_PIT_SPEAKER_DUMMY};
-#[cfg(not(target_env = "musl"))]
-use machine_manager::config::UiContext;
 use machine_manager::config::{
-    parse_incoming_uri, BootIndexInfo, BootSource, DriveFile, Incoming, MigrateMode, NumaNode,
-    NumaNodes, PFlashConfig, SerialConfig, VmConfig,
+    get_pci_bdf, parse_incoming_uri, parse_pvpanic, parse_tpm, parse_watchdog, BootIndexInfo,
+    BootSource, DriveFile, Incoming, KernelIrqchip, MigrateMode, NumaNode, NumaNodes, PFlashConfig,
+    PanicAction, RebootAction, SerialConfig, VmConfig,
 };
+#[cfg(not(target_env = "musl"))]
+use machine_manager::config::{parse_ramfb, UiContext};
 use machine_manager::event;
 use machine_manager::event_loop::EventLoop;
 use machine_manager::machine::{
-    KvmVmState, MachineAddressInterface, MachineExternalInterface, MachineInterface,
-    MachineLifecycle, MachineTestInterface, MigrateInterface,
+    detect_crash_loop, KvmVmState, MachineAddressInterface, MachineExternalInterface,
+    MachineInterface, MachineLifecycle, MachineTestInterface, MigrateInterface,
 };
 use machine_manager::qmp::{qmp_schema, QmpChannel, Response};
 use mch::Mch;
 use migration::{MigrationManager, MigrationStatus};
 use pci::{PciDevOps, PciHost};
-use sysbus::SysBus;
+use sysbus::{SysBus, SysBusDevType};
 use syscall::syscall_whitelist;
 use util::{
     byte_code::ByteCode, loop_context::EventLoopManager, seccomp::BpfRule, set_termi_canon_mode,
 };
 
-use self::ich9_lpc::SLEEP_CTRL_OFFSET;
+use self::ich9_lpc::{SCI_INT, SLEEP_CTRL_OFFSET, SLEEP_TYPE_S3};
 use super::error::StandardVmError;
 use super::{AcpiBuilder, StdMachineOps};
+use crate::gdbstub::start_gdbstub;
 use crate::{vm_state, MachineOps};
 use anyhow::{bail, Context, Result};
 #[cfg(not(target_env = "musl"))]
@@ -107,7 +116,7 @@ enum IrqEntryType {
 /// IRQ MAP of x86_64
 const IRQ_MAP: &[(i32, i32)] = &[
     (4, 4),   // Uart
-    (5, 15),  // Sysbus
+    (5, 8),   // Sysbus
     (16, 19), // Pcie
 ];
 
@@ -133,6 +142,18 @@ pub struct StdMachine {
     reset_req: Arc<EventFd>,
     /// Shutdown_req, handle VM 'ShutDown' event.
     shutdown_req: Arc<EventFd>,
+    /// Suspend request, handle guest entering S3 state.
+    suspend_req: Arc<EventFd>,
+    /// ACPI PM1 event registers.
+    pm_evt: Arc<Mutex<AcpiPmEvent>>,
+    /// Power button, handle `system_powerdown` request.
+    power_button: Arc<EventFd>,
+    /// Panic request, handle guest panic reported by pvpanic device.
+    panic_req: Arc<EventFd>,
+    /// Watchdog request, handle expiry of the watchdog device.
+    watchdog_req: Arc<EventFd>,
+    /// Base address of TPM CRB registers.
+    tpm_base: Option<u64>,
     /// All configuration information of virtual machine.
     vm_config: Arc<Mutex<VmConfig>>,
     /// List of guest NUMA nodes information.
@@ -204,6 +225,25 @@ impl StdMachine {
                     MachineError::InitEventFdErr("shutdown request".to_string())
                 })?,
             ),
+            suspend_req: Arc::new(
+                EventFd::new(libc::EFD_NONBLOCK)
+                    .with_context(|| MachineError::InitEventFdErr("suspend request".to_string()))?,
+            ),
+            pm_evt: Arc::new(Mutex::new(AcpiPmEvent::new())),
+            power_button: Arc::new(
+                EventFd::new(libc::EFD_NONBLOCK)
+                    .with_context(|| MachineError::InitEventFdErr("power button".to_string()))?,
+            ),
+            panic_req: Arc::new(
+                EventFd::new(libc::EFD_NONBLOCK)
+                    .with_context(|| MachineError::InitEventFdErr("panic request".to_string()))?,
+            ),
+            watchdog_req: Arc::new(
+                EventFd::new(libc::EFD_NONBLOCK).with_context(|| {
+                    MachineError::InitEventFdErr("watchdog request".to_string())
+                })?,
+            ),
+            tpm_base: None,
             vm_config: Arc::new(Mutex::new(vm_config.clone())),
             numa_nodes: None,
             boot_order_list: Arc::new(Mutex::new(Vec::new())),
@@ -248,7 +288,7 @@ impl StdMachine {
         Ok(())
     }
 
-    fn arch_init() -> Result<()> {
+    fn arch_init(kernel_irqchip: KernelIrqchip) -> Result<()> {
         let kvm_fds = KVM_FDS.load();
         let vm_fd = kvm_fds.vm_fd.as_ref().unwrap();
         let identity_addr: u64 = MEM_LAYOUT[LayoutEntryType::IdentTss as usize].0;
@@ -262,6 +302,11 @@ impl StdMachine {
             .set_tss_address((identity_addr + 0x1000) as usize)
             .with_context(|| MachineError::SetTssErr)?;
 
+        // The PIT of KVM relies on the in-kernel PIC to ack its interrupts, so it is
+        // not created with split irqchip.
+        if kernel_irqchip == KernelIrqchip::Split {
+            return Ok(());
+        }
         let pit_config = kvm_pit_config {
             flags: KVM_PIT_SPEAKER_DUMMY,
             pad: Default::default(),
@@ -278,17 +323,43 @@ impl StdMachine {
         let ich = ich9_lpc::LPCBridge::new(
             root_bus,
             self.sys_io.clone(),
+            self.pm_evt.clone(),
             self.reset_req.clone(),
             self.shutdown_req.clone(),
+            self.suspend_req.clone(),
+            self.power_button.clone(),
         )?;
-        self.register_reset_event(self.reset_req.clone(), vm)
+        self.register_reset_event(self.reset_req.clone(), vm.clone())
             .with_context(|| "Fail to register reset event in LPC")?;
         self.register_shutdown_event(ich.shutdown_req.clone(), clone_vm)
             .with_context(|| "Fail to register shutdown event in LPC")?;
+        self.register_suspend_event(ich.suspend_req.clone(), vm)
+            .with_context(|| "Fail to register suspend event in LPC")?;
         ich.realize()?;
         Ok(())
     }
 
+    /// Prepare to wake up VM from S3 state. vCPUs restart from firmware, which
+    /// finds the S3 resume flag in CMOS and jumps to the waking vector of guest.
+    fn prepare_wakeup(&self) -> Result<()> {
+        for (cpu_index, cpu) in self.cpus.iter().enumerate() {
+            cpu.set_to_boot_state();
+            cpu.reset()
+                .with_context(|| format!("Failed to reset vcpu{}", cpu_index))?;
+        }
+
+        for dev in self.sysbus.devices.iter() {
+            let mut locked_dev = dev.lock().unwrap();
+            if locked_dev.get_type() == SysBusDevType::Rtc {
+                let rtc = locked_dev.as_any_mut().downcast_mut::<RTC>().unwrap();
+                rtc.set_s3_resume();
+            }
+        }
+        self.pm_evt.lock().unwrap().set_wake_status();
+
+        Ok(())
+    }
+
     pub fn mem_show(&self) {
         self.sys_mem.memspace_show();
         self.sys_io.memspace_show();
@@ -338,6 +409,15 @@ impl StdMachineOps for StdMachine {
         fwcfg.add_data_entry(FwCfgEntryType::MaxCpus, nr_cpus.as_bytes().to_vec())?;
         fwcfg.add_data_entry(FwCfgEntryType::Irq0Override, 1_u32.as_bytes().to_vec())?;
 
+        // Tell firmware which sleep states are supported, index is the sleep state and
+        // value is 0x80 | SLP_TYP. Only S3 and S5 are supported.
+        let mut system_states = vec![0_u8; 6];
+        system_states[3] = 0x80 | SLEEP_TYPE_S3;
+        system_states[5] = 0x80 | 5;
+        fwcfg
+            .add_file_entry("etc/system-states", system_states)
+            .with_context(|| DevErrorKind::AddEntryErr("etc/system-states".to_string()))?;
+
         let boot_order = Vec::<u8>::new();
         fwcfg
             .add_file_entry("bootorder", boot_order)
@@ -361,6 +441,35 @@ impl StdMachineOps for StdMachine {
     fn get_guest_numa(&self) -> &Option<NumaNodes> {
         &self.numa_nodes
     }
+
+    fn get_tpm_base(&self) -> Option<u64> {
+        self.tpm_base
+    }
+
+    fn get_boot_source(&self) -> &Arc<Mutex<BootSource>> {
+        &self.boot_source
+    }
+
+    fn update_kernel_cmdline(&mut self) -> Result<()> {
+        let boot_source = self.boot_source.lock().unwrap();
+        // Firmware loads kernel cmdline from FwCfg only if kernel is given.
+        if boot_source.kernel_file.is_none() {
+            return Ok(());
+        }
+        if let Some(fwcfg) = &self.fwcfg_dev {
+            let cmdline = boot_source.kernel_cmdline.to_string();
+            let mut locked_fwcfg = fwcfg.lock().unwrap();
+            // The length of cmdline should add the tailing `\0`.
+            locked_fwcfg.modify_data_entry(
+                FwCfgEntryType::CmdlineSize,
+                (cmdline.len() as u32 + 1).as_bytes().to_vec(),
+            )?;
+            let mut cmdline_data = cmdline.into_bytes();
+            cmdline_data.push(0_u8);
+            locked_fwcfg.modify_data_entry(FwCfgEntryType::CmdlineData, cmdline_data)?;
+        }
+        Ok(())
+    }
 }
 
 impl MachineOps for StdMachine {
@@ -393,6 +502,25 @@ impl MachineOps for StdMachine {
     }
 
     fn init_interrupt_controller(&mut self, _vcpu_count: u64) -> Result<()> {
+        let kernel_irqchip = self.vm_config.lock().unwrap().machine_config.kernel_irqchip;
+        if kernel_irqchip == KernelIrqchip::Split {
+            KVM_FDS
+                .load()
+                .create_split_irqchip(IOAPIC_NUM_PINS)
+                .with_context(|| MachineError::CrtIrqchipErr)?;
+            KVM_FDS
+                .load()
+                .irq_route_table
+                .lock()
+                .unwrap()
+                .init_split_irq_route_table();
+            KVM_FDS.load().commit_irq_routing()?;
+            Ioapic::default()
+                .realize(&mut self.sysbus, u64::from(IOAPIC_BASE_ADDR))
+                .with_context(|| "Failed to realize IOAPIC")?;
+            return Ok(());
+        }
+
         KVM_FDS
             .load()
             .vm_fd
@@ -413,6 +541,11 @@ impl MachineOps for StdMachine {
     fn load_boot_source(&self, fwcfg: Option<&Arc<Mutex<dyn FwCfgOps>>>) -> Result<CPUBootConfig> {
         let boot_source = self.boot_source.lock().unwrap();
         let initrd = boot_source.initrd.as_ref().map(|b| b.initrd_file.clone());
+        let initrd_placement = boot_source
+            .initrd
+            .as_ref()
+            .map(|b| b.placement)
+            .unwrap_or_default();
 
         let gap_start = MEM_LAYOUT[LayoutEntryType::MemBelow4g as usize].0
             + MEM_LAYOUT[LayoutEntryType::MemBelow4g as usize].1;
@@ -420,6 +553,7 @@ impl MachineOps for StdMachine {
         let bootloader_config = BootLoaderConfig {
             kernel: boot_source.kernel_file.clone(),
             initrd,
+            initrd_placement,
             kernel_cmdline: boot_source.kernel_cmdline.to_string(),
             cpu_count: self.cpu_topo.nrcpus,
             gap_range: (gap_start, gap_end - gap_start),
@@ -462,6 +596,62 @@ impl MachineOps for StdMachine {
         Ok(())
     }
 
+    fn add_pvpanic(&mut self, cfg_args: &str) -> Result<()> {
+        parse_pvpanic(cfg_args)?;
+        let pvpanic = PvPanic::new(self.panic_req.clone());
+        pvpanic
+            .realize(&mut self.sysbus, PVPANIC_PORT, PVPANIC_REG_SIZE)
+            .with_context(|| "Failed to realize pvpanic device")
+    }
+
+    fn add_watchdog(&mut self, cfg_args: &str) -> Result<()> {
+        let nr_watchdog = self
+            .vm_config
+            .lock()
+            .unwrap()
+            .devices
+            .iter()
+            .filter(|(driver, _)| driver == "i6300esb")
+            .count();
+        if nr_watchdog > 1 {
+            bail!("Only one watchdog device is supported");
+        }
+
+        let bdf = get_pci_bdf(cfg_args)?;
+        let (devfn, parent_bus) = self.get_devfn_and_parent_bus(&bdf)?;
+        let dev_cfg = parse_watchdog(cfg_args)?;
+        let watchdog = I6300Esb::new(dev_cfg.id, devfn, parent_bus, self.watchdog_req.clone());
+        watchdog
+            .realize()
+            .with_context(|| "Failed to realize i6300esb device")
+    }
+
+    fn add_tpm(&mut self, vm_config: &mut VmConfig, cfg_args: &str) -> Result<()> {
+        if self.tpm_base.is_some() {
+            bail!("Only one TPM device is supported");
+        }
+        let tpm_cfg = parse_tpm(vm_config, cfg_args)?;
+        let tpm = TpmCrb::new(TpmEmulator::new(&tpm_cfg.socket_path));
+        tpm.realize(&mut self.sysbus, TPM_CRB_ADDR_BASE, TPM_CRB_ADDR_SIZE)
+            .with_context(|| "Failed to realize TPM device")?;
+        self.tpm_base = Some(TPM_CRB_ADDR_BASE);
+        Ok(())
+    }
+
+    #[cfg(not(target_env = "musl"))]
+    fn add_ramfb(&mut self, cfg_args: &str) -> Result<()> {
+        let install = parse_ramfb(cfg_args)?;
+        let fwcfg_dev = self
+            .get_fwcfg_dev()
+            .with_context(|| "Ramfb device must be used with FwCfg device")?;
+        let sys_mem = self.get_sys_mem();
+        let mut ramfb = Ramfb::new(sys_mem.clone(), install);
+
+        ramfb.ramfb_state.setup(&fwcfg_dev)?;
+        ramfb.realize(&mut self.sysbus)?;
+        Ok(())
+    }
+
     fn syscall_whitelist(&self) -> Vec<BpfRule> {
         syscall_whitelist()
     }
@@ -484,7 +674,7 @@ impl MachineOps for StdMachine {
         )?;
 
         locked_vm.init_interrupt_controller(u64::from(nr_cpus))?;
-        StdMachine::arch_init()?;
+        StdMachine::arch_init(vm_config.machine_config.kernel_irqchip)?;
 
         locked_vm
             .init_pci_host()
@@ -492,9 +682,15 @@ impl MachineOps for StdMachine {
         locked_vm
             .init_ich9_lpc(clone_vm)
             .with_context(|| "Fail to init LPC bridge")?;
-        locked_vm.add_devices(vm_config)?;
-
+        locked_vm
+            .register_panic_event(locked_vm.panic_req.clone(), vm.clone())
+            .with_context(|| "Fail to register panic event")?;
+        locked_vm
+            .register_watchdog_event(locked_vm.watchdog_req.clone(), vm.clone())
+            .with_context(|| "Fail to register watchdog event")?;
+        // FwCfg device is needed by ramfb, so add it before other devices.
         let fwcfg = locked_vm.add_fwcfg_device(nr_cpus)?;
+        locked_vm.add_devices(vm_config)?;
 
         let migrate = locked_vm.get_migrate_info();
         let boot_config = if migrate.0 == MigrateMode::Unknown {
@@ -502,17 +698,29 @@ impl MachineOps for StdMachine {
         } else {
             None
         };
+        let cpu_config = if migrate.0 == MigrateMode::Unknown {
+            Some(locked_vm.load_cpu_features(vm_config)?)
+        } else {
+            None
+        };
         let topology = CPUTopology::new().set_topology((
             vm_config.machine_config.nr_threads,
             vm_config.machine_config.nr_cores,
             vm_config.machine_config.nr_dies,
         ));
-        locked_vm.cpus.extend(<Self as MachineOps>::init_vcpu(
+        let cpus = <Self as MachineOps>::init_vcpu(
             vm.clone(),
+            &locked_vm.sys_mem,
             nr_cpus,
             &topology,
             &boot_config,
-        )?);
+            &cpu_config,
+            vm_config,
+        )?;
+        locked_vm.cpus.extend(cpus);
+        if let Some(addr) = vm_config.gdb.as_ref() {
+            start_gdbstub(addr, vm.clone(), &locked_vm.cpus, &locked_vm.sys_mem)?;
+        }
 
         if migrate.0 == MigrateMode::Unknown {
             if let Some(fw_cfg) = fwcfg {
@@ -522,6 +730,9 @@ impl MachineOps for StdMachine {
                 locked_vm
                     .build_smbios(&fw_cfg)
                     .with_context(|| "Failed to create smbios tables")?;
+                locked_vm
+                    .add_fw_cfg_files(&fw_cfg)
+                    .with_context(|| "Failed to add user-defined fw_cfg files")?;
             }
         }
 
@@ -537,7 +748,7 @@ impl MachineOps for StdMachine {
         #[cfg(not(target_env = "musl"))]
         locked_vm.watch_windows_emu_pid(
             vm_config,
-            locked_vm.shutdown_req.clone(),
+            locked_vm.power_button.clone(),
             locked_vm.shutdown_req.clone(),
         );
 
@@ -581,8 +792,9 @@ impl MachineOps for StdMachine {
                     false,
                     false,
                 )?);
-                let rom_region = Region::init_ram_region(ram1, "PflashRam");
-                rom_region.write(&mut fd, GuestAddress(rom_base), 0, rom_size)?;
+                // The BIOS code is read-only for guest, as it is in real hardware.
+                let rom_region = Region::init_rom_region(ram1, "BiosRom");
+                rom_region.load_rom(&mut fd, 0, rom_size)?;
                 rom_region.set_priority(10);
                 self.sys_mem.root().add_subregion(rom_region, rom_base)?;
 
@@ -622,7 +834,7 @@ impl MachineOps for StdMachine {
             Some(ref ds_cfg) if ds_cfg.gtk => {
                 let ui_context = UiContext {
                     vm_name: vm_config.guest_name.clone(),
-                    power_button: None,
+                    power_button: Some(self.power_button.clone()),
                     shutdown_req: Some(self.shutdown_req.clone()),
                     pause_req: None,
                     resume_req: None,
@@ -717,7 +929,14 @@ impl AcpiBuilder for StdMachine {
         // 3. Info of devices attached to system bus.
         dsdt.append_child(self.sysbus.aml_bytes().as_slice());
 
-        // 4. Add _S5 sleep state.
+        // 4. Add _S3 and _S5 sleep state.
+        let mut package = AmlPackage::new(4);
+        package.append_child(AmlInteger(SLEEP_TYPE_S3 as u64));
+        package.append_child(AmlInteger(0));
+        package.append_child(AmlInteger(0));
+        package.append_child(AmlInteger(0));
+        dsdt.append_child(AmlNameDecl::new("_S3", package).aml_bytes().as_slice());
+
         let mut package = AmlPackage::new(4);
         package.append_child(AmlInteger(5));
         package.append_child(AmlInteger(0));
@@ -738,8 +957,14 @@ impl AcpiBuilder for StdMachine {
         let mut madt = AcpiTable::new(*b"APIC", 5, *b"STRATO", *b"VIRTAPIC", 1);
 
         madt.append_child(LAPIC_BASE_ADDR.as_bytes());
-        // Flags: PC-AT-compatible dual-8259 setup
-        madt.append_child(1_u32.as_bytes());
+        // Flags: PC-AT-compatible dual-8259 setup, there is no 8259 with split irqchip.
+        let kernel_irqchip = self.vm_config.lock().unwrap().machine_config.kernel_irqchip;
+        let madt_flags = if kernel_irqchip == KernelIrqchip::Split {
+            0_u32
+        } else {
+            1_u32
+        };
+        madt.append_child(madt_flags.as_bytes());
 
         let ioapic = AcpiIoApic {
             type_id: 1_u8,
@@ -751,6 +976,17 @@ impl AcpiBuilder for StdMachine {
         };
         madt.append_child(ioapic.aml_bytes().as_ref());
 
+        // SCI is level triggered and active high.
+        let sci_override = AcpiInterruptSourceOverride {
+            type_id: 2_u8,
+            length: size_of::<AcpiInterruptSourceOverride>() as u8,
+            bus: 0,
+            source: SCI_INT as u8,
+            gsi: SCI_INT,
+            flags: 0xd,
+        };
+        madt.append_child(sci_override.aml_bytes().as_ref());
+
         self.cpus.iter().for_each(|cpu| {
             let lapic = AcpiLocalApic {
                 type_id: 0,
@@ -918,6 +1154,30 @@ impl MachineLifecycle for StdMachine {
         true
     }
 
+    fn powerdown(&self) -> bool {
+        if self.power_button.write(1).is_err() {
+            error!("X86 standard vm write power button failed");
+            return false;
+        }
+        true
+    }
+
+    fn get_panic_action(&self) -> PanicAction {
+        self.vm_config.lock().unwrap().machine_config.panic_action
+    }
+
+    fn get_reboot_action(&self) -> RebootAction {
+        self.vm_config.lock().unwrap().machine_config.reboot_action
+    }
+
+    fn in_crash_loop(&self) -> bool {
+        let vm_config = self.vm_config.lock().unwrap();
+        detect_crash_loop(
+            vm_config.machine_config.crash_limit,
+            vm_config.machine_config.crash_interval,
+        )
+    }
+
     fn reset(&mut self) -> bool {
         if self.reset_req.write(1).is_err() {
             error!("X86 standard vm write reset request failed");
@@ -926,6 +1186,30 @@ impl MachineLifecycle for StdMachine {
         true
     }
 
+    fn suspend(&self) -> bool {
+        if !self.notify_lifecycle(KvmVmState::Running, KvmVmState::Suspended) {
+            return false;
+        }
+        event!(Suspend);
+        true
+    }
+
+    fn wakeup(&mut self) -> bool {
+        if *self.vm_state.0.lock().unwrap() != KvmVmState::Suspended {
+            error!("Unable to wake up: guest is not in suspended state");
+            return false;
+        }
+        if let Err(e) = self.prepare_wakeup() {
+            error!("Failed to wake up guest: {:?}", e);
+            return false;
+        }
+        if !self.notify_lifecycle(KvmVmState::Suspended, KvmVmState::Running) {
+            return false;
+        }
+        event!(Wakeup);
+        true
+    }
+
     fn notify_lifecycle(&self, old: KvmVmState, new: KvmVmState) -> bool {
         if let Err(e) =
             self.vm_state_transfer(&self.cpus, &mut self.vm_state.0.lock().unwrap(), old, new)
@@ -985,14 +1269,34 @@ impl MachineAddressInterface for StdMachine {
             .write(&mut data, GuestAddress(addr), count)
             .is_ok()
     }
+
+    fn io_device_name(&self, addr: u64, pio: bool) -> Option<String> {
+        let space = if pio { &self.sys_io } else { &self.sys_mem };
+        space.get_region_name(GuestAddress(addr))
+    }
 }
 
 impl MigrateInterface for StdMachine {
     fn migrate(&self, uri: String) -> Response {
+        // The primary device of failover can't be migrated, it should be unplugged
+        // before migration and guest will switch datapath to the standby device.
+        let primaries = self.vm_config.lock().unwrap().get_failover_primaries();
+        if !primaries.is_empty() {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(format!(
+                    "Failover primary devices {:?} should be unplugged before migration",
+                    primaries
+                )),
+                None,
+            );
+        }
+
         match parse_incoming_uri(&uri) {
             Ok((MigrateMode::File, path)) => migration::snapshot(path),
             Ok((MigrateMode::Unix, path)) => migration::migration_unix_mode(path),
             Ok((MigrateMode::Tcp, path)) => migration::migration_tcp_mode(path),
+            Ok((MigrateMode::Fd, name)) => migration::migration_fd_mode(name),
+            Ok((MigrateMode::Rdma, path)) => migration::migration_rdma_mode(path),
             _ => Response::create_error_response(
                 qmp_schema::QmpErrorClass::GenericError(format!("Invalid uri: {}", uri)),
                 None,
@@ -1000,13 +1304,37 @@ impl MigrateInterface for StdMachine {
         }
     }
 
+    fn migrate_check(&self, uri: String) -> Response {
+        migration::check_migration(uri)
+    }
+
     fn query_migrate(&self) -> Response {
         migration::query_migrate()
     }
 
+    fn savevm(&self, path: String, single_file: Option<bool>) -> Response {
+        crate::qmp_savevm(self, path, single_file.unwrap_or(false))
+    }
+
+    fn loadvm(&self, path: String) -> Response {
+        crate::qmp_loadvm(self, &self.cpus, path)
+    }
+
     fn cancel_migrate(&self) -> Response {
         migration::cancel_migrate()
     }
+
+    fn migrate_set_capabilities(&self, args: qmp_schema::migrate_set_capabilities) -> Response {
+        migration::set_migrate_capabilities(args.capabilities)
+    }
+
+    fn migrate_set_parameters(&self, args: qmp_schema::migrate_set_parameters) -> Response {
+        migration::set_migrate_parameters(args)
+    }
+
+    fn query_migrate_parameters(&self) -> Response {
+        migration::query_migrate_parameters()
+    }
 }
 
 impl MachineInterface for StdMachine {}
@@ -44,6 +44,7 @@ const FUTEX_WAIT_BITSET_PRIVATE: u32 = FUTEX_WAIT_BITSET | FUTEX_PRIVATE_FLAG;
 /// See: https://elixir.bootlin.com/linux/v4.19.123/source/include/uapi/linux/fcntl.h
 const F_GETFD: u32 = 1;
 const F_SETFD: u32 = 2;
+const F_GETFL: u32 = 3;
 const F_SETFL: u32 = 4;
 const F_LINUX_SPECIFIC_BASE: u32 = 1024;
 const F_DUPFD_CLOEXEC: u32 = F_LINUX_SPECIFIC_BASE + 6;
@@ -99,6 +100,7 @@ pub fn syscall_whitelist() -> Vec<BpfRule> {
             .add_constraint(SeccompCmpOpt::Eq, 1, F_DUPFD_CLOEXEC)
             .add_constraint(SeccompCmpOpt::Eq, 1, F_SETFD)
             .add_constraint(SeccompCmpOpt::Eq, 1, F_GETFD)
+            .add_constraint(SeccompCmpOpt::Eq, 1, F_GETFL)
             .add_constraint(SeccompCmpOpt::Eq, 1, F_SETFL),
         BpfRule::new(libc::SYS_flock),
         BpfRule::new(libc::SYS_rt_sigprocmask),
@@ -187,6 +189,7 @@ pub fn syscall_whitelist() -> Vec<BpfRule> {
         BpfRule::new(libc::SYS_set_robust_list),
         #[cfg(target_env = "gnu")]
         BpfRule::new(libc::SYS_sched_getaffinity),
+        BpfRule::new(libc::SYS_sched_setaffinity),
         #[cfg(target_env = "gnu")]
         BpfRule::new(libc::SYS_pipe2),
         #[cfg(target_env = "gnu")]
@@ -271,6 +274,12 @@ fn ioctl_allow_list() -> BpfRule {
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_VCPU_EVENTS() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_PIT2() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_CLOCK() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_CLOCK() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_KVMCLOCK_CTRL() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_TSC_KHZ() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_TSC_KHZ() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_TRANSLATE() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_GUEST_DEBUG() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_IRQCHIP() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_REGS() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_SREGS() as u32)
@@ -283,6 +292,7 @@ fn ioctl_allow_list() -> BpfRule {
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_SUPPORTED_CPUID() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_CPUID2() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_MP_STATE() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_NMI() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_SREGS() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_REGS() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_XSAVE() as u32)
@@ -292,6 +302,8 @@ fn ioctl_allow_list() -> BpfRule {
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_MSRS() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_SET_VCPU_EVENTS() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, KVM_GET_DIRTY_LOG() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_RESET_DIRTY_RINGS() as u32)
+        .add_constraint(SeccompCmpOpt::Eq, 1, KVM_CLEAR_DIRTY_LOG() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, VIDIOC_QUERYCAP() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, VIDIOC_ENUM_FMT() as u32)
         .add_constraint(SeccompCmpOpt::Eq, 1, VIDIOC_G_FMT() as u32)
@@ -42,10 +42,8 @@ impl StateTransfer for KvmDevice {
         // save pit
         let pit_state = vm_fd.get_pit2()?;
 
-        // save kvm_clock
-        let mut kvm_clock = vm_fd.get_clock()?;
-        // Reset kvm clock flag.
-        kvm_clock.flags = 0;
+        // save kvm_clock, which is the one saved when VM is paused.
+        let kvm_clock = kvm_fds.get_clock()?;
 
         // save ioapic
         let mut ioapic = kvm_irqchip {
@@ -71,7 +69,7 @@ impl StateTransfer for KvmDevice {
             .with_context(|| MigrationError::FromBytesError("KVM_DEVICE"))?;
 
         vm_fd.set_pit2(&kvm_state.pit_state)?;
-        vm_fd.set_clock(&kvm_state.kvm_clock)?;
+        kvm_fds.set_clock(&kvm_state.kvm_clock)?;
         vm_fd.set_irqchip(&kvm_state.ioapic)?;
 
         Ok(())
@@ -10,16 +10,23 @@
 // NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
 // See the Mulan PSL v2 for more details.
 
+use std::net::TcpListener;
 use std::os::unix::net::UnixListener;
 
 use anyhow::{bail, Context, Result};
+use log::warn;
 use util::arg_parser::{Arg, ArgMatches, ArgParser};
 use util::file::clear_file;
+use util::trace::enable_io_access_trace;
 use util::unix::{limit_permission, parse_unix_uri};
 
 use crate::{
     config::{add_trace_events, ChardevType, CmdParser, MachineType, VmConfig},
+    qmp::audit::init_audit_log,
+    rest_api::{RestApiServer, RestApiVmInfo},
+    socket::{MonitorMode, Socket},
     temp_cleaner::TempCleaner,
+    tls::make_server_config,
 };
 
 /// This macro is to run struct $z 's function $s whose arg is $x 's inner member.
@@ -81,20 +88,29 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
             .help("set the name of the guest.")
             .takes_value(true),
         )
+        .arg(
+            Arg::with_name("config")
+            .long("config")
+            .value_name("<config_file>")
+            .help("read vm configuration from json file, options given by cmdline are added after it.")
+            .takes_value(true),
+        )
         .arg(
             Arg::with_name("machine")
             .long("machine")
-            .value_name("[type=]<name>[,dump_guest_core=on|off][,mem-share=on|off]")
+            .value_name("[type=]<name>[,dump_guest_core=on|off][,mem-share=on|off][,mem-lock=on|off][,protected=on|off]")
             .help("'type' selects emulated machine type and set properties. \
                    'dump_guest_core' includes guest memory in a core dump. \
-                   'mem-share' sets guest memory is shareable.")
+                   'mem-share' sets guest memory is shareable. \
+                   'mem-lock' locks guest memory in host RAM. \
+                   'protected' makes guest memory private to guest.")
             .takes_value(true),
         )
         .arg(
             Arg::with_name("accel")
             .long("accel")
-            .value_name("[accel]")
-            .help("select accelerator, only 'kvm' is supported now.")
+            .value_name("[accel][,dirty-ring-size=<entries>]")
+            .help("select accelerator, only 'kvm' is supported now. 'dirty-ring-size' enables KVM dirty ring with the number of entries per vcpu.")
             .takes_value(true),
         )
         .arg(
@@ -112,11 +128,32 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
         .arg(
             Arg::with_name("cpu")
             .long("cpu")
-            .value_name("host[,pmu=on|off]")
+            .value_name("<model>[,pmu=on|off][,sve=on|off][,sve-max-vq=<vq>][,pauth=on|off][,+feature][,-feature]")
             .help("set CPU model and features.")
             .can_no_value(false)
             .takes_value(true)
         )
+        .arg(
+            Arg::with_name("cpu-pin")
+            .long("cpu-pin")
+            .value_name("vcpu<index>=<host cpu>[,vcpu<index>=<host cpu>...]")
+            .help("pin the thread of each vCPU to a host CPU.")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("cpu-sched")
+            .long("cpu-sched")
+            .value_name("[policy=other,]nice=<value>|policy=fifo,priority=<value>")
+            .help("set the scheduling class of vCPU threads.")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("cpu-quota")
+            .long("cpu-quota")
+            .value_name("[quota=<us>][,period=<us>][,shares=<weight>]")
+            .help("limit the CPU usage of vCPU threads by a dedicated cpu cgroup.")
+            .takes_value(true)
+        )
         .arg(
             Arg::with_name("freeze_cpu")
             .short("S")
@@ -152,7 +189,17 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
             .long("numa")
             .value_name("<parameters>")
             .help("\n\t\tset numa node: -numa node,nodeid=<0>,cpus=<0-1>,memdev=<mem0>; \
-                   \n\t\tset numa distance: -numa dist,src=<0>,dst=<1>,val=<20> ")
+                   \n\t\tset numa distance: -numa dist,src=<0>,dst=<1>,val=<20>; \
+                   \n\t\tset numa memory latency or bandwidth: -numa hmat-lb,initiator=<0>,target=<1>,data-type=<access-latency>,latency=<200>")
+            .takes_values(true),
+        )
+        .arg(
+            Arg::with_name("fw_cfg")
+            .multiple(true)
+            .long("fw_cfg")
+            .value_name("<parameters>")
+            .help("\n\t\tadd fw_cfg file from host file: -fw_cfg name=<opt/name>,file=<path>; \
+                   \n\t\tadd fw_cfg file from string: -fw_cfg name=<opt/name>,string=<str>")
             .takes_values(true),
         )
         .arg(
@@ -163,6 +210,14 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
             .help("set cameradev: -cameradev v4l2,id=<testCam>,path=</dev/video0>")
             .takes_values(true),
         )
+        .arg(
+            Arg::with_name("tpmdev")
+            .multiple(true)
+            .long("tpmdev")
+            .value_name("<parameters>")
+            .help("set tpm backend: -tpmdev emulator,id=<tpmdev_id>,chardev=<chardev_id>")
+            .takes_values(true),
+        )
         .arg(
             Arg::with_name("kernel")
             .long("kernel")
@@ -178,6 +233,13 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
             .help("use 'cmdline' as kernel command line")
             .takes_values(true),
         )
+        .arg(
+            Arg::with_name("kernel-cmdline-file")
+            .long("append-file")
+            .value_name("<cmdline_path>")
+            .help("append the kernel command line parameters in file 'cmdline_path'")
+            .takes_value(true),
+        )
         .arg(
             Arg::with_name("initrd-file")
             .long("initrd")
@@ -185,11 +247,40 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
             .help("use 'initrd-file' as initial ram disk")
             .takes_value(true),
         )
+        .arg(
+            Arg::with_name("initrd-placement")
+            .long("initrd-placement")
+            .value_name("<end|below4g|addr>")
+            .help("load initrd at the end of memory (default), the end of memory below 4G, or the given address")
+            .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dtb")
+            .long("dtb")
+            .value_name("<dtb_path>")
+            .help("use 'dtb' as device tree blob instead of the generated one, only for aarch64")
+            .takes_value(true),
+        )
         .arg(
             Arg::with_name("qmp")
+            .multiple(true)
             .long("qmp")
-            .value_name("unix:<socket_path>")
-            .help("set QMP's unix socket path")
+            .value_name("unix:<socket_path> | tcp:<host>:<port>[,tls-creds=<tls_id>]")
+            .help("set QMP's unix socket path or TCP address, can be set multiple times for several clients")
+            .takes_values(true)
+        )
+        .arg(
+            Arg::with_name("api-sock")
+            .long("api-sock")
+            .value_name("<socket_path>")
+            .help("set unix socket path of Firecracker compatible REST API")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("qmp-audit-log")
+            .long("qmp-audit-log")
+            .value_name("<file>")
+            .help("append the records of received qmp and human monitor commands to the file")
             .takes_value(true)
         )
         .arg(
@@ -223,8 +314,8 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
             Arg::with_name("chardev")
             .multiple(true)
             .long("chardev")
-            .value_name("socket,id=<str>,path=<socket_path>")
-            .help("set char device virtio console for vm")
+            .value_name("<stdio|pty|file|socket>,id=<str>[,path=<path>][,host=<host>,port=<port>][,server][,nowait][,reconnect=<secs>]")
+            .help("set char device for serial and console of vm")
             .takes_values(true),
         )
         .arg(
@@ -249,7 +340,7 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
                    \n\t\tadd virtio pci rng: -device virtio-rng-pci,id=<rng_id>,rng=<objrng0>,max-bytes=<1234>,period=<1000>,bus=<pcie.0>,addr=<0x1>[,multifunction=on|off]; \
                    \n\t\tadd pcie root port: -device pcie-root-port,id=<pcie.1>,port=<0x1>,bus=<pcie.0>,addr=<0x1>[,multifunction=on|off]; \
                    \n\t\tadd vfio pci: -device vfio-pci,id=<vfio_id>,host=<0000:1a:00.3>,bus=<pcie.0>,addr=<0x03>[,multifunction=on|off]; \
-                   \n\t\tadd usb controller: -device nec-usb-xhci,id=<xhci>,bus=<pcie.0>,addr=<0xa>; \
+                   \n\t\tadd usb controller: -device {nec-usb-xhci|qemu-xhci},id=<xhci>,bus=<pcie.0>,addr=<0xa>; \
                    \n\t\tadd usb keyboard: -device usb-kbd,id=<kbd>; \
                    \n\t\tadd usb tablet: -device usb-tablet,id=<tablet>; \
                    \n\t\tadd usb storage: -device usb-storage,id=<storage>,drive=<drive_id>; \
@@ -273,6 +364,13 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
             .takes_value(true)
             .can_no_value(true),
         )
+        .arg(
+            Arg::with_name("log")
+            .long("log")
+            .value_name("[format=<text|json>][,rotate-size=<MiB>][,rotate-time=<never|daily|hourly>][,rotate-count=<num>]")
+            .help("set the format of log records, and the rotation policy of logfile")
+            .takes_value(true),
+        )
         .arg(
             Arg::with_name("pidfile")
             .long("pidfile")
@@ -296,15 +394,38 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
             .takes_value(false)
             .required(false),
         )
+        .arg(
+            Arg::with_name("seccomp-mode")
+            .long("seccomp-mode")
+            .value_name("<enforce|log>")
+            .help("kill StratoVirt (enforce, default) or only log (log) on the syscalls not allowed by seccomp")
+            .takes_value(true),
+        )
         .arg(
             Arg::with_name("incoming")
             .long("incoming")
             .value_name("<parameters>")
             .help("\n\t\tdo the migration using tcp socket: -incoming tcp:<ip>:<port>; \
                    \n\t\tdo the migration using unix socket: -incoming unix:<socket path>; \
+                   \n\t\tdo the migration using inherited socket fd: -incoming fd:<fd>; \
                    \n\t\tdo the virtual machine snapshot: -incoming file:<file path>")
             .takes_value(true),
         )
+        .arg(
+            Arg::with_name("nbd-server")
+            .long("nbd-server")
+            .value_name("<parameters>")
+            .help("\n\t\texport drives as the target of storage migration using tcp socket: -nbd-server tcp:<ip>:<port>; \
+                   \n\t\texport drives as the target of storage migration using unix socket: -nbd-server unix:<socket path>")
+            .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("gdb")
+            .long("gdb")
+            .value_name("tcp:[<ip>]:<port>")
+            .help("wait for gdb connection on tcp socket to debug guest, such as -gdb tcp::1234")
+            .takes_value(true),
+        )
         .arg(
             Arg::with_name("object")
             .multiple(true)
@@ -315,7 +436,7 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
                    \n\t\tadd memory backend file object: -object memory-backend-file,size=<size>,id=<memid>[,host-nodes=<0-1>] \
                    [,policy=bind][,mem-path=<path/to/file>][,dump-guest-core=<true|false>][,mem-prealloc=<true|false>][,share=<on|off>] \
                    \n\t\tadd memory backend memfd object: -object memory-backend-memfd,size=<size>,id=<memid>[,host-nodes=0-1][,policy=bind] \
-                   [,mem-prealloc=<true|false>][,dump-guest-core=<true|false>][,share=<on|off>]; \
+                   [,mem-prealloc=<true|false>][,dump-guest-core=<true|false>][,share=<on|off>][,hugetlb=<on|off>][,hugetlbsize=<size>][,hugetlb-fallback=<on|off>][,seal=<on|off>][,thp=<on|off>]; \
                    \n\t\tadd iothread object: -object iothread,id=<iothread_id>; \
                    \n\t\tadd rng object: -object rng-random,id=<rng_id>,filename=<file_path>; \
                    \n\t\tadd vnc tls object: -object tls-creds-x509,id=<vnc_id>,dir=</etc/pki/vnc>; \
@@ -324,10 +445,11 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
         )
         .arg(
             Arg::with_name("mon")
+            .multiple(true)
             .long("mon")
-            .value_name("chardev=<chardev_id>,id=<mon_id>[,mode=control]")
-            .help("-mon is another way to create qmp channel. To use it, the chardev should be specified")
-            .takes_value(true),
+            .value_name("chardev=<chardev_id>,id=<mon_id>[,mode=control|readline]")
+            .help("-mon is another way to create qmp channel, or human monitor with mode=readline. To use it, the chardev should be specified")
+            .takes_values(true),
         )
         .arg(
             Arg::with_name("overcommit")
@@ -390,6 +512,20 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
             .can_no_value(true)
             .takes_value(true),
         )
+        .arg(
+            Arg::with_name("watchdog-action")
+            .long("watchdog-action")
+            .value_name("reset|shutdown|pause|inject-nmi|none")
+            .help("set the action when the watchdog timer expires, default is reset")
+            .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("action")
+            .long("action")
+            .value_name("[reboot=<reset|shutdown>][,panic=<pause|shutdown|reset|none>][,watchdog=<action>][,crash-limit=<n>][,crash-interval=<secs>]")
+            .help("set the action taken on guest events, default panic action is pause")
+            .takes_value(true),
+        )
         .arg(
             Arg::with_name("battery")
             .long("battery")
@@ -440,10 +576,17 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
             Arg::with_name("trace")
             .multiple(false)
             .long("trace")
-            .value_name("events=<file>")
-            .help("specify the file lists trace events to enable")
+            .value_name("[events=<file>][,backend=<log|ftrace|lttng>]")
+            .help("specify the file lists trace events to enable, and the backend which trace records are written to")
             .takes_value(true),
         )
+        .arg(
+            Arg::with_name("trace-mmio")
+            .long("trace-mmio")
+            .help("trace all MMIO and PIO accesses handled by vcpus")
+            .takes_value(false)
+            .required(false),
+        )
         .arg(
             Arg::with_name("global")
             .multiple(true)
@@ -502,11 +645,11 @@ pub fn create_args_parser<'a>() -> ArgParser<'a> {
 /// Input arguments is illegal for `VmConfig` or `VmConfig`'s health check
 /// failed -- with this unhealthy `VmConfig`, VM will not boot successfully.
 pub fn create_vmconfig(args: &ArgMatches) -> Result<VmConfig> {
-    // Parse config-file json.
-    // VmConfig can be transformed by json file which described VmConfig
-    // directly.
     let mut vm_cfg = VmConfig::default();
 
+    // Parse config-file json first, cmdline args are added after it.
+    add_args_to_config!((args.value_of("config")), vm_cfg, add_config_file);
+
     // Parse cmdline args which need to set in VmConfig
     add_args_to_config!((args.value_of("name")), vm_cfg, add_name);
     add_args_to_config!((args.value_of("machine")), vm_cfg, add_machine);
@@ -515,10 +658,21 @@ pub fn create_vmconfig(args: &ArgMatches) -> Result<VmConfig> {
     add_args_to_config!((args.value_of("mem-path")), vm_cfg, add_mem_path);
     add_args_to_config!((args.value_of("smp")), vm_cfg, add_cpu);
     add_args_to_config!((args.value_of("cpu")), vm_cfg, add_cpu_feature);
+    add_args_to_config!((args.value_of("cpu-pin")), vm_cfg, add_cpu_pin);
+    add_args_to_config!((args.value_of("cpu-sched")), vm_cfg, add_cpu_sched);
+    add_args_to_config!((args.value_of("cpu-quota")), vm_cfg, add_cpu_quota);
     add_args_to_config!((args.value_of("kernel")), vm_cfg, add_kernel);
     add_args_to_config!((args.value_of("initrd-file")), vm_cfg, add_initrd);
+    add_args_to_config!(
+        (args.value_of("initrd-placement")),
+        vm_cfg,
+        add_initrd_placement
+    );
+    add_args_to_config!((args.value_of("dtb")), vm_cfg, add_dtb);
     add_args_to_config!((args.value_of("serial")), vm_cfg, add_serial);
     add_args_to_config!((args.value_of("incoming")), vm_cfg, add_incoming);
+    add_args_to_config!((args.value_of("nbd-server")), vm_cfg, add_nbd_server);
+    add_args_to_config!((args.value_of("gdb")), vm_cfg, add_gdb);
     add_args_to_config!((args.value_of("vnc")), vm_cfg, add_vnc);
     add_args_to_config!((args.value_of("display")), vm_cfg, add_display);
     add_args_to_config!(
@@ -533,6 +687,12 @@ pub fn create_vmconfig(args: &ArgMatches) -> Result<VmConfig> {
         bool
     );
     add_args_to_config!((args.is_present("battery")), vm_cfg, add_battery, bool);
+    add_args_to_config!(
+        (args.value_of("watchdog-action")),
+        vm_cfg,
+        add_watchdog_action
+    );
+    add_args_to_config!((args.value_of("action")), vm_cfg, add_action);
     add_args_to_config!(
         (args.is_present("mem-prealloc")),
         vm_cfg,
@@ -545,6 +705,11 @@ pub fn create_vmconfig(args: &ArgMatches) -> Result<VmConfig> {
         add_kernel_cmdline,
         vec
     );
+    add_args_to_config!(
+        (args.value_of("kernel-cmdline-file")),
+        vm_cfg,
+        add_kernel_cmdline_file
+    );
     add_args_to_config_multi!((args.values_of("drive")), vm_cfg, add_drive);
     add_args_to_config_multi!((args.values_of("object")), vm_cfg, add_object);
     add_args_to_config_multi!((args.values_of("netdev")), vm_cfg, add_netdev);
@@ -553,11 +718,16 @@ pub fn create_vmconfig(args: &ArgMatches) -> Result<VmConfig> {
     add_args_to_config_multi!((args.values_of("global")), vm_cfg, add_global_config);
     add_args_to_config_multi!((args.values_of("numa")), vm_cfg, add_numa);
     add_args_to_config_multi!((args.values_of("cameradev")), vm_cfg, add_camera_backend);
+    add_args_to_config_multi!((args.values_of("tpmdev")), vm_cfg, add_tpmdev);
+    add_args_to_config_multi!((args.values_of("fw_cfg")), vm_cfg, add_fw_cfg);
     add_args_to_config_multi!((args.values_of("smbios")), vm_cfg, add_smbios);
 
     if let Some(s) = args.value_of("trace") {
         add_trace_events(&s)?;
     }
+    if args.is_present("trace-mmio") {
+        enable_io_access_trace()?;
+    }
 
     // Check the mini-set for Vm to start is ok
     if vm_cfg.machine_config.mach_type != MachineType::None {
@@ -568,7 +738,8 @@ pub fn create_vmconfig(args: &ArgMatches) -> Result<VmConfig> {
     Ok(vm_cfg)
 }
 
-/// This function is to parse qmp socket path and type.
+/// This function is to parse qmp socket path and type, and create the sockets
+/// of api channel, whose performer should be set after vm is created.
 ///
 /// # Arguments
 ///
@@ -577,19 +748,32 @@ pub fn create_vmconfig(args: &ArgMatches) -> Result<VmConfig> {
 /// # Errors
 ///
 /// The value of `qmp` is illegel.
-pub fn check_api_channel(args: &ArgMatches, vm_config: &mut VmConfig) -> Result<Vec<UnixListener>> {
+pub fn check_api_channel(args: &ArgMatches, vm_config: &mut VmConfig) -> Result<Vec<Socket>> {
     let mut sock_paths = Vec::new();
-    if let Some(qmp_config) = args.value_of("qmp") {
+    let mut tcp_addrs = Vec::new();
+    for qmp_config in args.values_of("qmp").unwrap_or_default() {
         let mut cmd_parser = CmdParser::new("qmp");
-        cmd_parser.push("").push("server").push("nowait");
+        cmd_parser
+            .push("")
+            .push("server")
+            .push("nowait")
+            .push("tls-creds");
 
         cmd_parser.parse(&qmp_config)?;
-        if let Some(uri) = cmd_parser.get_value::<String>("")? {
-            let api_path =
-                parse_unix_uri(&uri).with_context(|| "Failed to parse qmp socket path")?;
-            sock_paths.push(api_path);
-        } else {
-            bail!("No uri found for qmp");
+        let tls_creds = cmd_parser.get_value::<String>("tls-creds")?;
+        match cmd_parser.get_value::<String>("")? {
+            Some(uri) if uri.starts_with("tcp:") => {
+                tcp_addrs.push((uri["tcp:".len()..].to_string(), tls_creds));
+            }
+            Some(uri) => {
+                if tls_creds.is_some() {
+                    bail!("Argument \'tls-creds\' is only supported by tcp qmp");
+                }
+                let api_path =
+                    parse_unix_uri(&uri).with_context(|| "Failed to parse qmp socket path")?;
+                sock_paths.push((api_path, MonitorMode::Control));
+            }
+            None => bail!("No uri found for qmp"),
         }
         if cmd_parser.get_value::<String>("server")?.is_none() {
             bail!("Argument \'server\' is needed for qmp");
@@ -598,7 +782,7 @@ pub fn check_api_channel(args: &ArgMatches, vm_config: &mut VmConfig) -> Result<
             bail!("Argument \'nowait\' is needed for qmp");
         }
     }
-    if let Some(mon_config) = args.value_of("mon") {
+    for mon_config in args.values_of("mon").unwrap_or_default() {
         let mut cmd_parser = CmdParser::new("monitor");
         cmd_parser.push("id").push("mode").push("chardev");
         cmd_parser.parse(&mon_config)?;
@@ -607,13 +791,14 @@ pub fn check_api_channel(args: &ArgMatches, vm_config: &mut VmConfig) -> Result<
             .get_value::<String>("chardev")?
             .with_context(|| "Argument \'chardev\' is missing for \'mon\'")?;
 
-        if let Some(mode) = cmd_parser.get_value::<String>("mode")? {
-            if mode != *"control" {
-                bail!("Invalid \'mode\' parameter: {:?} for monitor", &mode);
+        let mode = match cmd_parser.get_value::<String>("mode")?.as_deref() {
+            Some("control") => MonitorMode::Control,
+            Some("readline") => MonitorMode::Readline,
+            Some(mode) => bail!("Invalid \'mode\' parameter: {:?} for monitor", mode),
+            None => {
+                bail!("Argument \'mode\' of \'mon\' should be set to \'control\' or \'readline\'.")
             }
-        } else {
-            bail!("Argument \'mode\' of \'mon\' should be set to \'control\'.");
-        }
+        };
 
         if let Some(cfg) = vm_config.chardev.remove(&chardev) {
             if let ChardevType::Socket {
@@ -628,7 +813,7 @@ pub fn check_api_channel(args: &ArgMatches, vm_config: &mut VmConfig) -> Result<
                         path
                     );
                 }
-                sock_paths.push(path);
+                sock_paths.push((path, mode));
             } else {
                 bail!("Only socket-type of chardev can be used for monitor");
             }
@@ -637,18 +822,72 @@ pub fn check_api_channel(args: &ArgMatches, vm_config: &mut VmConfig) -> Result<
         }
     }
 
-    if sock_paths.is_empty() {
-        bail!("Please use \'-qmp\' or \'-mon\' to give a qmp path for Unix socket");
+    if let Some(path) = args.value_of("qmp-audit-log") {
+        init_audit_log(&path)?;
     }
-    let mut listeners = Vec::new();
-    for path in sock_paths {
-        listeners.push(
-            bind_socket(path.clone())
-                .with_context(|| format!("Failed to bind socket for path: {:?}", &path))?,
-        )
+    if sock_paths.is_empty() && tcp_addrs.is_empty() && !args.is_present("api-sock") {
+        bail!("Please use \'-qmp\' or \'-mon\' to give a qmp path for Unix or TCP socket");
+    }
+    for (i, (path, _)) in sock_paths.iter().enumerate() {
+        if sock_paths[..i].iter().any(|(p, _)| p == path) {
+            bail!("Socket path {} is used by more than one monitor", path);
+        }
     }
+    let mut sockets = Vec::new();
+    for (path, mode) in sock_paths {
+        let listener = bind_socket(path.clone())
+            .with_context(|| format!("Failed to bind socket for path: {:?}", &path))?;
+        let mut socket = Socket::from_unix_listener(listener, None);
+        socket.set_monitor_mode(mode);
+        sockets.push(socket);
+    }
+    for (addr, tls_creds) in tcp_addrs {
+        let listener = TcpListener::bind(&addr)
+            .with_context(|| format!("Failed to bind socket for address: {:?}", &addr))?;
+        let tls_config = match tls_creds {
+            Some(id) => {
+                let creds = vm_config
+                    .object
+                    .tls_object
+                    .get(&id)
+                    .with_context(|| format!("No tls-creds object found: {}", id))?;
+                Some(make_server_config(creds)?)
+            }
+            None => {
+                warn!("Qmp on TCP address {} is not encrypted by tls", addr);
+                None
+            }
+        };
+        sockets.push(Socket::from_tcp_listener(listener, tls_config, None));
+    }
+
+    Ok(sockets)
+}
 
-    Ok(listeners)
+/// This function is to create the server of REST API if `api-sock` is set, whose
+/// controller should be set after vm is created.
+///
+/// # Arguments
+///
+/// * `args` - The structure accepted input cmdline arguments.
+/// * `vm_config` - The configuration of vm.
+pub fn check_rest_api(args: &ArgMatches, vm_config: &VmConfig) -> Result<Option<RestApiServer>> {
+    let path = match args.value_of("api-sock") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let listener = bind_socket(path.clone())
+        .with_context(|| format!("Failed to bind socket for path: {:?}", &path))?;
+    let machine_config = &vm_config.machine_config;
+    let vm_info = RestApiVmInfo {
+        id: vm_config.guest_name.clone(),
+        vcpu_count: machine_config.nr_cpus,
+        mem_size_mib: machine_config.mem_config.mem_size >> 20,
+        smt: machine_config.nr_threads > 1,
+        micro_vm: machine_config.mach_type == MachineType::MicroVm,
+        freeze_cpu: args.is_present("freeze_cpu"),
+    };
+    Ok(Some(RestApiServer::new(listener, vm_info)))
 }
 
 fn bind_socket(path: String) -> Result<UnixListener> {
@@ -31,11 +31,18 @@ pub struct BalloonConfig {
     pub auto_balloon: bool,
     pub membuf_percent: u32,
     pub monitor_interval: u32,
+    /// Whether to offer the stats virtqueue to report guest memory statistics.
+    pub guest_stats: bool,
+    /// Interval(second) to request guest memory statistics, 0 means no polling.
+    pub stats_polling_interval: u32,
 }
 
 impl ConfigCheck for BalloonConfig {
     fn check(&self) -> Result<()> {
         check_arg_too_long(&self.id, "balloon id")?;
+        if self.stats_polling_interval != 0 && !self.guest_stats {
+            bail!("Balloon stats-polling-interval requires guest-stats to be enabled.");
+        }
 
         if !self.auto_balloon {
             return Ok(());
@@ -81,7 +88,9 @@ pub fn parse_balloon(vm_config: &mut VmConfig, balloon_config: &str) -> Result<B
         .push("free-page-reporting")
         .push("auto-balloon")
         .push("membuf-percent")
-        .push("monitor-interval");
+        .push("monitor-interval")
+        .push("guest-stats")
+        .push("stats-polling-interval");
     cmd_parser.parse(balloon_config)?;
 
     pci_args_check(&cmd_parser)?;
@@ -109,6 +118,12 @@ pub fn parse_balloon(vm_config: &mut VmConfig, balloon_config: &str) -> Result<B
     if let Some(monitor_interval) = cmd_parser.get_value::<u32>("monitor-interval")? {
         balloon.monitor_interval = monitor_interval;
     }
+    if let Some(default) = cmd_parser.get_value::<ExBool>("guest-stats")? {
+        balloon.guest_stats = default.into();
+    }
+    if let Some(interval) = cmd_parser.get_value::<u32>("stats-polling-interval")? {
+        balloon.stats_polling_interval = interval;
+    }
     balloon.check()?;
     vm_config.dev_name.insert("balloon".to_string(), 1);
     Ok(balloon)
@@ -225,4 +240,17 @@ mod tests {
         );
         assert!(bln_cfg_res6.is_err());
     }
+
+    #[test]
+    fn test_stats_balloon_config_cmdline_parser() {
+        let mut vm_config = VmConfig::default();
+        let bln_cfg = "virtio-balloon-device,guest-stats=true,stats-polling-interval=5,id=balloon0";
+        let balloon_config = parse_balloon(&mut vm_config, bln_cfg).unwrap();
+        assert!(balloon_config.guest_stats);
+        assert_eq!(balloon_config.stats_polling_interval, 5);
+
+        let mut vm_config = VmConfig::default();
+        let bln_cfg = "virtio-balloon-device,stats-polling-interval=5,id=balloon0";
+        assert!(parse_balloon(&mut vm_config, bln_cfg).is_err());
+    }
 }
@@ -12,14 +12,19 @@
 
 use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use super::error::ConfigError;
 use crate::config::{check_arg_too_long, ConfigCheck, VmConfig, MAX_PATH_LENGTH};
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use serde::{Deserialize, Serialize};
+use util::num_ops::str_to_usize;
+
+/// Alignment of the guest address to load initrd at.
+const INITRD_ADDR_ALIGN: u64 = 0x1000;
 
 /// Config struct for boot-source.
-/// Contains `kernel_file`, `kernel_cmdline` and `initrd`.
+/// Contains `kernel_file`, `kernel_cmdline`, `initrd` and `dtb_file`.
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct BootSource {
     /// Path of the kernel image.
@@ -28,6 +33,8 @@ pub struct BootSource {
     pub kernel_cmdline: KernelParams,
     /// Config of initrd.
     pub initrd: Option<InitrdConfig>,
+    /// Path of the device tree blob given by user, which replaces the generated one.
+    pub dtb_file: Option<PathBuf>,
 }
 
 impl BootSource {
@@ -35,6 +42,23 @@ impl BootSource {
     pub fn append_kernel_cmdline(&mut self, other: &mut Vec<Param>) {
         self.kernel_cmdline.append(other);
     }
+
+    /// Amend the kernel cmdline, which takes effect at the next boot of VM.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmdline` - Kernel cmdline parameters separated by whitespace.
+    /// * `append` - Append to the current kernel cmdline instead of replacing it.
+    pub fn amend_kernel_cmdline(&mut self, cmdline: &str, append: bool) -> Result<()> {
+        let mut params = KernelParams::parse(cmdline);
+        params.check()?;
+        if append {
+            self.kernel_cmdline.append(&mut params.params);
+        } else {
+            self.kernel_cmdline = params;
+        }
+        Ok(())
+    }
 }
 
 impl ConfigCheck for BootSource {
@@ -58,16 +82,59 @@ impl ConfigCheck for BootSource {
             self.initrd.as_ref().unwrap().check()?;
         }
 
+        if let Some(dtb_file) = &self.dtb_file {
+            check_arg_too_long(dtb_file.to_str().unwrap(), "dtb_file")?;
+            if !dtb_file.is_file() {
+                return Err(anyhow!(ConfigError::UnRegularFile(
+                    "Input dtb_file".to_string()
+                )));
+            }
+        }
+
         Ok(())
     }
 }
 
+/// Where to load the initrd image in guest memory.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InitrdPlacement {
+    /// At the end of guest memory.
+    #[default]
+    End,
+    /// At the end of guest memory below 4G.
+    Below4G,
+    /// At the given guest address.
+    Fixed(u64),
+}
+
+impl FromStr for InitrdPlacement {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "end" => Ok(InitrdPlacement::End),
+            "below4g" => Ok(InitrdPlacement::Below4G),
+            _ => {
+                let addr = str_to_usize(s.to_string()).map_err(|_| {
+                    anyhow!(ConfigError::InvalidParam(
+                        s.to_string(),
+                        "initrd-placement".to_string()
+                    ))
+                })?;
+                Ok(InitrdPlacement::Fixed(addr as u64))
+            }
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct InitrdConfig {
     /// Path of the initrd image
     pub initrd_file: PathBuf,
     pub initrd_addr: u64,
     pub initrd_size: u64,
+    /// Where to load the initrd image.
+    pub placement: InitrdPlacement,
 }
 
 impl InitrdConfig {
@@ -76,6 +143,7 @@ impl InitrdConfig {
             initrd_file: PathBuf::from(initrd),
             initrd_addr: 0,
             initrd_size: 0,
+            placement: InitrdPlacement::End,
         }
     }
 }
@@ -90,6 +158,16 @@ impl ConfigCheck for InitrdConfig {
             )));
         }
 
+        if let InitrdPlacement::Fixed(addr) = self.placement {
+            if addr & (INITRD_ADDR_ALIGN - 1) != 0 {
+                bail!(
+                    "Initrd address 0x{:x} is not aligned to 0x{:x}",
+                    addr,
+                    INITRD_ADDR_ALIGN
+                );
+            }
+        }
+
         Ok(())
     }
 }
@@ -126,6 +204,21 @@ impl KernelParams {
         KernelParams { params, length }
     }
 
+    /// Parse parameters separated by any whitespace, including line breaks. Lines
+    /// starting with `#` are comments.
+    fn parse(kernel_cmdline: &str) -> Self {
+        let mut params = KernelParams::default();
+        for line in kernel_cmdline.lines() {
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            for item in line.split_whitespace() {
+                params.push(Param::from_str(item));
+            }
+        }
+        params
+    }
+
     /// Push new `Param` to `KernelParams`.
     pub fn push(&mut self, item: Param) {
         self.params.push(item);
@@ -228,16 +321,44 @@ impl VmConfig {
         self.boot_source.kernel_cmdline = KernelParams::from_str(cmdline);
     }
 
+    /// Add `-append-file cmdline_path` config to `VmConfig`, the parameters in file are
+    /// appended to the ones given by `-append`.
+    pub fn add_kernel_cmdline_file(&mut self, cmdline_file: &str) -> Result<()> {
+        let cmdline = std::fs::read_to_string(cmdline_file)
+            .with_context(|| format!("Failed to read kernel cmdline file {}", cmdline_file))?;
+        self.boot_source.amend_kernel_cmdline(&cmdline, true)
+    }
+
     /// Add `-initrd initrd_path` config to `VmConfig`
     pub fn add_initrd(&mut self, initrd: &str) -> Result<()> {
         self.boot_source.initrd = Some(InitrdConfig::new(initrd));
         Ok(())
     }
+
+    /// Add `-initrd-placement placement` config to `VmConfig`
+    pub fn add_initrd_placement(&mut self, placement: &str) -> Result<()> {
+        let placement = InitrdPlacement::from_str(placement)?;
+        match self.boot_source.initrd.as_mut() {
+            Some(initrd) => initrd.placement = placement,
+            None => bail!("Initrd placement is given without initrd"),
+        }
+        Ok(())
+    }
+
+    /// Add `-dtb dtb_path` config to `VmConfig`
+    pub fn add_dtb(&mut self, dtb: &str) -> Result<()> {
+        if cfg!(not(target_arch = "aarch64")) {
+            bail!("Device tree blob is only supported on aarch64");
+        }
+        self.boot_source.dtb_file = Some(PathBuf::from(dtb));
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::MAX_STRING_LENGTH;
     use std::fs::File;
 
     #[test]
@@ -294,4 +415,91 @@ mod tests {
         std::fs::remove_file(&kernel_path).unwrap();
         std::fs::remove_file(&initrd_path).unwrap();
     }
+
+    #[test]
+    fn test_initrd_placement() {
+        let initrd_path = String::from("test_initrd_placement.img");
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_initrd_placement("below4g").is_err());
+
+        File::create(&initrd_path).unwrap();
+        assert!(vm_config.add_initrd(&initrd_path).is_ok());
+        let initrd = vm_config.boot_source.initrd.as_ref().unwrap();
+        assert_eq!(initrd.placement, InitrdPlacement::End);
+
+        assert!(vm_config.add_initrd_placement("below4g").is_ok());
+        let initrd = vm_config.boot_source.initrd.as_ref().unwrap();
+        assert_eq!(initrd.placement, InitrdPlacement::Below4G);
+        assert!(initrd.check().is_ok());
+
+        assert!(vm_config.add_initrd_placement("0x8000000").is_ok());
+        let initrd = vm_config.boot_source.initrd.as_ref().unwrap();
+        assert_eq!(initrd.placement, InitrdPlacement::Fixed(0x800_0000));
+        assert!(initrd.check().is_ok());
+
+        assert!(vm_config.add_initrd_placement("0x8000100").is_ok());
+        let initrd = vm_config.boot_source.initrd.as_ref().unwrap();
+        assert!(initrd.check().is_err());
+        assert!(vm_config.add_initrd_placement("high").is_err());
+        std::fs::remove_file(&initrd_path).unwrap();
+    }
+
+    #[test]
+    fn test_kernel_cmdline_file() {
+        let cmdline_path = String::from("test_kernel_cmdline.txt");
+        let mut vm_config = VmConfig::default();
+        vm_config.add_kernel_cmdline(&[String::from("console=ttyS0")]);
+        assert!(vm_config.add_kernel_cmdline_file(&cmdline_path).is_err());
+
+        std::fs::write(
+            &cmdline_path,
+            "# comment line\nreboot=k  panic=1\n\tpci=off\n  # another comment\nquiet\n",
+        )
+        .unwrap();
+        assert!(vm_config.add_kernel_cmdline_file(&cmdline_path).is_ok());
+        let kernel_cmdline = &vm_config.boot_source.kernel_cmdline;
+        assert_eq!(kernel_cmdline.length, 5);
+        assert_eq!(
+            kernel_cmdline.to_string(),
+            "console=ttyS0 reboot=k panic=1 pci=off quiet"
+        );
+        std::fs::remove_file(&cmdline_path).unwrap();
+    }
+
+    #[test]
+    fn test_amend_kernel_cmdline() {
+        let mut boot_source = BootSource::default();
+        assert!(boot_source
+            .amend_kernel_cmdline("console=ttyS0 reboot=k", false)
+            .is_ok());
+        assert!(boot_source.amend_kernel_cmdline("panic=1", true).is_ok());
+        assert_eq!(
+            boot_source.kernel_cmdline.to_string(),
+            "console=ttyS0 reboot=k panic=1"
+        );
+        assert!(boot_source.amend_kernel_cmdline(" quiet ", false).is_ok());
+        assert_eq!(boot_source.kernel_cmdline.length, 1);
+        assert_eq!(boot_source.kernel_cmdline.to_string(), "quiet");
+
+        let too_long = format!("root={}", "a".repeat(MAX_STRING_LENGTH + 1));
+        assert!(boot_source.amend_kernel_cmdline(&too_long, true).is_err());
+        assert_eq!(boot_source.kernel_cmdline.to_string(), "quiet");
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn test_bootsource_dtb() {
+        let dtb_path = String::from("test_bootsource.dtb");
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_dtb(&dtb_path).is_ok());
+        assert_eq!(
+            vm_config.boot_source.dtb_file,
+            Some(PathBuf::from(&dtb_path))
+        );
+        assert!(vm_config.boot_source.check().is_err());
+
+        File::create(&dtb_path).unwrap();
+        assert!(vm_config.boot_source.check().is_ok());
+        std::fs::remove_file(&dtb_path).unwrap();
+    }
 }
@@ -21,10 +21,14 @@ use crate::config::{
 use crate::qmp::qmp_schema;
 
 const MAX_GUEST_CID: u64 = 4_294_967_295;
+/// Max seconds to wait before reconnecting the socket chardev.
+const MAX_RECONNECT_SECS: u64 = 3600;
 const MIN_GUEST_CID: u64 = 3;
 
 /// Default value of max ports for virtio-serial.
 const DEFAULT_SERIAL_PORTS_NUMBER: u32 = 31;
+/// Port name used by qemu-guest-agent in guest.
+pub const GUEST_AGENT_PORT_NAME: &str = "org.qemu.guest_agent.0";
 
 /// Character device options.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -36,21 +40,51 @@ pub enum ChardevType {
         server: bool,
         nowait: bool,
     },
+    TcpSocket {
+        host: String,
+        port: u16,
+        server: bool,
+        nowait: bool,
+    },
     File(String),
 }
 
+impl ChardevType {
+    /// Whether the backend is a socket connecting to the server as client.
+    pub fn is_socket_client(&self) -> bool {
+        matches!(
+            self,
+            ChardevType::Socket { server: false, .. }
+                | ChardevType::TcpSocket { server: false, .. }
+        )
+    }
+}
+
 /// Config structure for virtio-serial-port.
 #[derive(Debug, Clone)]
 pub struct VirtioSerialPort {
     pub id: String,
+    /// Port name reported to guest, default is the port id.
+    pub name: Option<String>,
     pub chardev: ChardevConfig,
     pub nr: u32,
     pub is_console: bool,
 }
 
+impl VirtioSerialPort {
+    /// Whether this port is the channel of qemu-guest-agent.
+    pub fn is_guest_agent(&self) -> bool {
+        !self.is_console && self.name.as_deref() == Some(GUEST_AGENT_PORT_NAME)
+    }
+}
+
 impl ConfigCheck for VirtioSerialPort {
     fn check(&self) -> Result<()> {
-        check_arg_too_long(&self.id, "chardev id")
+        check_arg_too_long(&self.id, "chardev id")?;
+        if let Some(name) = &self.name {
+            check_arg_too_long(name, "serial port name")?;
+        }
+        Ok(())
     }
 }
 
@@ -59,11 +93,30 @@ impl ConfigCheck for VirtioSerialPort {
 pub struct ChardevConfig {
     pub id: String,
     pub backend: ChardevType,
+    /// Seconds to wait before reconnecting to the server after the client-mode socket
+    /// is disconnected or fails to connect, 0 means no reconnecting.
+    #[serde(default)]
+    pub reconnect: u64,
 }
 
 impl ConfigCheck for ChardevConfig {
     fn check(&self) -> Result<()> {
         check_arg_too_long(&self.id, "chardev id")?;
+        if let ChardevType::TcpSocket { host, .. } = &self.backend {
+            check_arg_too_long(host, "socket host")?;
+        }
+        if self.reconnect > MAX_RECONNECT_SECS {
+            return Err(anyhow!(ConfigError::IllegalValue(
+                "reconnect".to_string(),
+                0,
+                true,
+                MAX_RECONNECT_SECS,
+                true
+            )));
+        }
+        if self.reconnect != 0 && !self.backend.is_socket_client() {
+            bail!("Argument \'reconnect\' is only supported by client-mode socket chardev");
+        }
 
         let len = match &self.backend {
             ChardevType::Socket { path, .. } => path.len(),
@@ -88,6 +141,15 @@ fn check_chardev_args(cmd_parser: CmdParser) -> Result<()> {
         let nowait = cmd_parser.get_value::<String>("nowait")?;
         match chardev_str {
             "stdio" | "pty" | "file" => {
+                for arg in ["host", "port", "reconnect"] {
+                    if cmd_parser.get_value::<String>(arg)?.is_some() {
+                        bail!(
+                            "Chardev of {}-type does not support \'{}\' argument",
+                            chardev_str,
+                            arg
+                        );
+                    }
+                }
                 if server.is_some() {
                     bail!(
                         "Chardev of {}-type does not support \'server\' argument",
@@ -125,6 +187,9 @@ pub fn parse_chardev(cmd_parser: CmdParser) -> Result<ChardevConfig> {
         .with_context(|| ConfigError::FieldIsMissing("id".to_string(), "chardev".to_string()))?;
     let backend = cmd_parser.get_value::<String>("")?;
     let path = cmd_parser.get_value::<String>("path")?;
+    let host = cmd_parser.get_value::<String>("host")?;
+    let port = cmd_parser.get_value::<u16>("port")?;
+    let reconnect = cmd_parser.get_value::<u64>("reconnect")?.unwrap_or(0);
     let server = if let Some(server) = cmd_parser.get_value::<String>("server")? {
         if server.ne("") {
             bail!("No parameter needed for server");
@@ -147,17 +212,30 @@ pub fn parse_chardev(cmd_parser: CmdParser) -> Result<ChardevConfig> {
             "stdio" => ChardevType::Stdio,
             "pty" => ChardevType::Pty,
             "socket" => {
-                if let Some(path) = path {
-                    ChardevType::Socket {
+                match (path, host, port) {
+                    (Some(path), None, None) => ChardevType::Socket {
                         path,
                         server,
                         nowait,
+                    },
+                    (None, Some(host), Some(port)) => ChardevType::TcpSocket {
+                        host,
+                        port,
+                        server,
+                        nowait,
+                    },
+                    (Some(_), _, _) => {
+                        bail!("Argument \'path\' conflicts with \'host\' and \'port\' for socket-type chardev")
+                    }
+                    (None, None, None) => {
+                        return Err(anyhow!(ConfigError::FieldIsMissing(
+                            "path".to_string(),
+                            "socket-type chardev".to_string()
+                        )));
+                    }
+                    (None, _, _) => {
+                        bail!("Arguments \'host\' and \'port\' are both required for tcp socket chardev")
                     }
-                } else {
-                    return Err(anyhow!(ConfigError::FieldIsMissing(
-                        "path".to_string(),
-                        "socket-type chardev".to_string()
-                    )));
                 }
             }
             "file" => {
@@ -187,6 +265,7 @@ pub fn parse_chardev(cmd_parser: CmdParser) -> Result<ChardevConfig> {
     Ok(ChardevConfig {
         id: chardev_id,
         backend: chardev_type,
+        reconnect,
     })
 }
 
@@ -229,6 +308,7 @@ pub fn get_chardev_config(args: qmp_schema::CharDevAddArgument) -> Result<Charde
             server: data.server,
             nowait: false,
         },
+        reconnect: 0,
     })
 }
 
@@ -269,7 +349,12 @@ pub fn parse_virtserialport(
     is_console: bool,
 ) -> Result<VirtioSerialPort> {
     let mut cmd_parser = CmdParser::new("virtserialport");
-    cmd_parser.push("").push("id").push("chardev").push("nr");
+    cmd_parser
+        .push("")
+        .push("id")
+        .push("chardev")
+        .push("nr")
+        .push("name");
     cmd_parser.parse(config_args)?;
 
     let chardev_name = cmd_parser
@@ -283,10 +368,12 @@ pub fn parse_virtserialport(
     let nr = cmd_parser.get_value::<u32>("nr")?.with_context(|| {
         ConfigError::FieldIsMissing("nr".to_string(), "virtserialport".to_string())
     })?;
+    let name = cmd_parser.get_value::<String>("name")?;
 
     if let Some(chardev) = vm_config.chardev.remove(&chardev_name) {
         let port_cfg = VirtioSerialPort {
             id,
+            name,
             chardev,
             nr,
             is_console,
@@ -305,8 +392,11 @@ impl VmConfig {
             .push("")
             .push("id")
             .push("path")
+            .push("host")
+            .push("port")
             .push("server")
-            .push("nowait");
+            .push("nowait")
+            .push("reconnect");
 
         cmd_parser.parse(chardev_config)?;
 
@@ -613,6 +703,39 @@ mod tests {
         .is_ok());
     }
 
+    #[test]
+    fn test_guest_agent_port_config_cmdline_parser() {
+        let mut vm_config = VmConfig::default();
+        assert!(
+            parse_virtio_serial(&mut vm_config, "virtio-serial-pci,bus=pcie.0,addr=0x1.0x2")
+                .is_ok()
+        );
+        assert!(vm_config
+            .add_chardev("socket,id=qga0,path=/path/to/qga.sock,server,nowait")
+            .is_ok());
+        let port_cfg = parse_virtserialport(
+            &mut vm_config,
+            "virtserialport,chardev=qga0,id=channel0,nr=1,name=org.qemu.guest_agent.0",
+            false,
+        )
+        .unwrap();
+        assert_eq!(port_cfg.id, "channel0");
+        assert_eq!(port_cfg.name, Some(GUEST_AGENT_PORT_NAME.to_string()));
+        assert!(port_cfg.is_guest_agent());
+
+        assert!(vm_config
+            .add_chardev("socket,id=chardev1,path=/path/to/socket1,server,nowait")
+            .is_ok());
+        let port_cfg = parse_virtserialport(
+            &mut vm_config,
+            "virtserialport,chardev=chardev1,id=channel1,nr=2",
+            false,
+        )
+        .unwrap();
+        assert!(port_cfg.name.is_none());
+        assert!(!port_cfg.is_guest_agent());
+    }
+
     #[test]
     fn test_vsock_config_cmdline_parser() {
         let vsock_cfg_op = parse_vsock("vhost-vsock-device,id=test_vsock,guest-cid=3");
@@ -656,4 +779,51 @@ mod tests {
             assert!(false);
         }
     }
+
+    #[test]
+    fn test_chardev_tcp_reconnect_cmdline_parser() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config
+            .add_chardev("socket,id=tcp_server,host=127.0.0.1,port=4321,server,nowait")
+            .is_ok());
+        let char_dev = vm_config.chardev.remove("tcp_server").unwrap();
+        assert_eq!(
+            char_dev.backend,
+            ChardevType::TcpSocket {
+                host: "127.0.0.1".to_string(),
+                port: 4321,
+                server: true,
+                nowait: true,
+            }
+        );
+        assert_eq!(char_dev.reconnect, 0);
+
+        assert!(vm_config
+            .add_chardev("socket,id=tcp_client,host=127.0.0.1,port=4321,reconnect=5")
+            .is_ok());
+        let char_dev = vm_config.chardev.remove("tcp_client").unwrap();
+        assert!(char_dev.backend.is_socket_client());
+        assert_eq!(char_dev.reconnect, 5);
+
+        // Reconnecting is only supported by client-mode socket.
+        assert!(vm_config
+            .add_chardev("socket,id=test_id,path=/path/to/socket,server,nowait,reconnect=5")
+            .is_err());
+        assert!(vm_config
+            .add_chardev("stdio,id=test_id,reconnect=5")
+            .is_err());
+        // Both path and host are set, or port is missing.
+        assert!(vm_config
+            .add_chardev("socket,id=test_id,path=/path/to/socket,host=127.0.0.1,port=4321")
+            .is_err());
+        assert!(vm_config
+            .add_chardev("socket,id=test_id,host=127.0.0.1")
+            .is_err());
+        assert!(vm_config
+            .add_chardev("socket,id=test_id,host=127.0.0.1,port=65536")
+            .is_err());
+        assert!(vm_config
+            .add_chardev("socket,id=test_id,path=/path/to/socket,reconnect=3601")
+            .is_err());
+    }
 }
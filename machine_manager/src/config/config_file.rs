@@ -0,0 +1,243 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NO-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::config::VmConfig;
+
+/// Structure of the json file given by `-config`. Every entry is either the same
+/// string as its cmdline option, or an object whose members are its properties.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct VmConfigFile {
+    name: Option<String>,
+    machine: Option<Value>,
+    accel: Option<Value>,
+    memory: Option<Value>,
+    mem_path: Option<String>,
+    smp: Option<Value>,
+    cpu: Option<Value>,
+    kernel: Option<String>,
+    kernel_cmdline: Option<String>,
+    initrd: Option<String>,
+    dtb: Option<String>,
+    serial: Option<Value>,
+    objects: Vec<Value>,
+    numa: Vec<Value>,
+    drives: Vec<Value>,
+    netdevs: Vec<Value>,
+    chardevs: Vec<Value>,
+    devices: Vec<Value>,
+    global: Vec<Value>,
+}
+
+/// Convert one entry of config file to the format of cmdline option.
+///
+/// # Arguments
+///
+/// * `entry` - The entry in config file.
+/// * `positional` - The member used as the leading value without key, such as `driver` of device.
+fn entry_to_cmdline(entry: &Value, positional: Option<&str>) -> Result<String> {
+    let members = match entry {
+        Value::String(s) => return Ok(s.clone()),
+        Value::Number(n) => return Ok(n.to_string()),
+        Value::Object(members) => members,
+        _ => bail!("Config entry should be string or object: {}", entry),
+    };
+
+    let mut params = Vec::new();
+    if let Some(key) = positional {
+        match members.get(key) {
+            Some(Value::String(s)) => params.push(s.clone()),
+            Some(_) => bail!("Value of \"{}\" should be string", key),
+            None => bail!("\"{}\" is missing in config entry: {}", key, entry),
+        }
+    }
+    for (key, value) in members {
+        if matches!(positional, Some(p) if p == key) {
+            continue;
+        }
+        match value {
+            Value::String(s) => params.push(format!("{}={}", key, s)),
+            Value::Number(n) => params.push(format!("{}={}", key, n)),
+            // Flag property without value, such as `server` of chardev.
+            Value::Bool(true) => params.push(key.clone()),
+            Value::Bool(false) => {}
+            _ => bail!("Value of \"{}\" should be string, number or bool", key),
+        }
+    }
+    Ok(params.join(","))
+}
+
+impl VmConfig {
+    /// Add `-config config_file` to `VmConfig`. The options are the same as cmdline,
+    /// and cmdline options given besides it are added afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `config_file` - The path of json config file.
+    pub fn add_config_file(&mut self, config_file: &str) -> Result<()> {
+        let content = std::fs::read_to_string(config_file)
+            .with_context(|| format!("Failed to read config file {}", config_file))?;
+        self.add_config_json(&content)
+            .with_context(|| format!("Failed to parse config file {}", config_file))
+    }
+
+    fn add_config_json(&mut self, content: &str) -> Result<()> {
+        let file: VmConfigFile = serde_json::from_str(content)?;
+
+        if let Some(name) = &file.name {
+            self.add_name(name)?;
+        }
+        if let Some(machine) = &file.machine {
+            self.add_machine(&entry_to_cmdline(machine, None)?)?;
+        }
+        if let Some(accel) = &file.accel {
+            self.add_accel(&entry_to_cmdline(accel, None)?)?;
+        }
+        if let Some(memory) = &file.memory {
+            self.add_memory(&entry_to_cmdline(memory, None)?)?;
+        }
+        if let Some(mem_path) = &file.mem_path {
+            self.add_mem_path(mem_path)?;
+        }
+        if let Some(smp) = &file.smp {
+            self.add_cpu(&entry_to_cmdline(smp, None)?)?;
+        }
+        if let Some(cpu) = &file.cpu {
+            self.add_cpu_feature(&entry_to_cmdline(cpu, None)?)?;
+        }
+        if let Some(kernel) = &file.kernel {
+            self.add_kernel(kernel)?;
+        }
+        if let Some(kernel_cmdline) = &file.kernel_cmdline {
+            self.add_kernel_cmdline(&[kernel_cmdline.clone()]);
+        }
+        if let Some(initrd) = &file.initrd {
+            self.add_initrd(initrd)?;
+        }
+        if let Some(dtb) = &file.dtb {
+            self.add_dtb(dtb)?;
+        }
+        if let Some(serial) = &file.serial {
+            self.add_serial(&entry_to_cmdline(serial, None)?)?;
+        }
+        for object in &file.objects {
+            self.add_object(&entry_to_cmdline(object, Some("type"))?)?;
+        }
+        for numa in &file.numa {
+            self.add_numa(&entry_to_cmdline(numa, Some("type"))?)?;
+        }
+        for drive in &file.drives {
+            self.add_drive(&entry_to_cmdline(drive, None)?)?;
+        }
+        for netdev in &file.netdevs {
+            self.add_netdev(&entry_to_cmdline(netdev, Some("type"))?)?;
+        }
+        for chardev in &file.chardevs {
+            self.add_chardev(&entry_to_cmdline(chardev, Some("backend"))?)?;
+        }
+        for device in &file.devices {
+            self.add_device(&entry_to_cmdline(device, Some("driver"))?)?;
+        }
+        for global in &file.global {
+            self.add_global_config(&entry_to_cmdline(global, None)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ChardevType, MachineType};
+
+    #[test]
+    fn test_entry_to_cmdline() {
+        let entry = serde_json::json!("virtio-blk-pci,id=blk0,drive=rootfs");
+        assert_eq!(
+            entry_to_cmdline(&entry, Some("driver")).unwrap(),
+            "virtio-blk-pci,id=blk0,drive=rootfs"
+        );
+        let entry =
+            serde_json::json!({"id": "chr0", "backend": "socket", "server": true, "nowait": false});
+        assert_eq!(
+            entry_to_cmdline(&entry, Some("backend")).unwrap(),
+            "socket,id=chr0,server"
+        );
+        let entry = serde_json::json!({"cpus": 4, "maxcpus": 8});
+        assert_eq!(entry_to_cmdline(&entry, None).unwrap(), "cpus=4,maxcpus=8");
+        // Positional member is missing.
+        let entry = serde_json::json!({"id": "net0"});
+        assert!(entry_to_cmdline(&entry, Some("type")).is_err());
+        // Nested value is not supported.
+        let entry = serde_json::json!({"id": ["net0"]});
+        assert!(entry_to_cmdline(&entry, None).is_err());
+        assert!(entry_to_cmdline(&serde_json::json!(["net0"]), None).is_err());
+    }
+
+    #[test]
+    fn test_add_config_json() {
+        let content = r#"
+        {
+            "name": "test_vm",
+            "machine": {"type": "none", "dump-guest-core": "off"},
+            "memory": "1G",
+            "smp": {"cpus": 2},
+            "kernel": "/path/to/vmlinux",
+            "kernel-cmdline": "console=ttyS0 root=/dev/vda",
+            "drives": [
+                {"id": "rootfs", "file": "/path/to/rootfs", "readonly": "off"}
+            ],
+            "chardevs": [
+                {"backend": "socket", "id": "chr0", "path": "/path/to/socket", "server": true, "nowait": true}
+            ],
+            "devices": [
+                {"driver": "virtio-blk-device", "id": "blk0", "drive": "rootfs"},
+                "virtio-serial-device,id=serial0"
+            ]
+        }"#;
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_config_json(content).is_ok());
+        assert_eq!(vm_config.guest_name, "test_vm");
+        assert_eq!(vm_config.machine_config.mach_type, MachineType::None);
+        assert_eq!(vm_config.machine_config.mem_config.mem_size, 1 << 30);
+        assert_eq!(vm_config.machine_config.nr_cpus, 2);
+        assert_eq!(
+            vm_config.boot_source.kernel_cmdline.to_string(),
+            "console=ttyS0 root=/dev/vda"
+        );
+        assert!(vm_config.drives.get("rootfs").is_some());
+        assert_eq!(
+            vm_config.chardev.get("chr0").unwrap().backend,
+            ChardevType::Socket {
+                path: "/path/to/socket".to_string(),
+                server: true,
+                nowait: true,
+            }
+        );
+        assert_eq!(vm_config.devices.len(), 2);
+        assert_eq!(vm_config.devices[0].0, "virtio-blk-device");
+
+        // Unknown entry is rejected.
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_config_json(r#"{"memroy": "1G"}"#).is_err());
+        // Invalid option is reported as cmdline.
+        assert!(vm_config
+            .add_config_json(r#"{"smp": {"cpus": 0}}"#)
+            .is_err());
+        assert!(vm_config.add_config_file("/path/not/exist").is_err());
+    }
+}
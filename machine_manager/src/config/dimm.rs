@@ -0,0 +1,124 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use super::error::ConfigError;
+use crate::config::{check_arg_too_long, CmdParser, ConfigCheck, VmConfig, MAX_MEM_SLOTS};
+use crate::qmp::qmp_schema;
+
+/// Config structure for pluggable memory device.
+#[derive(Debug, Clone, Default)]
+pub struct DimmConfig {
+    pub id: String,
+    /// Id of the memory backend object, anonymous memory is used if not set.
+    pub memdev: Option<String>,
+    /// Index of the memory slot, the first free slot is used if not set.
+    pub slot: Option<u8>,
+}
+
+impl ConfigCheck for DimmConfig {
+    fn check(&self) -> Result<()> {
+        check_arg_too_long(&self.id, "id")?;
+        if let Some(memdev) = self.memdev.as_ref() {
+            check_arg_too_long(memdev, "memdev")?;
+        }
+        if let Some(slot) = self.slot {
+            if slot >= MAX_MEM_SLOTS {
+                return Err(anyhow!(ConfigError::IllegalValue(
+                    "slot of pc-dimm".to_string(),
+                    0,
+                    true,
+                    MAX_MEM_SLOTS as u64,
+                    false,
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn parse_dimm(vm_config: &VmConfig, dimm_config: &str) -> Result<DimmConfig> {
+    let mut cmd_parser = CmdParser::new("pc-dimm");
+    cmd_parser.push("").push("id").push("memdev").push("slot");
+    cmd_parser.parse(dimm_config)?;
+
+    let dimm_cfg = DimmConfig {
+        id: cmd_parser.get_value::<String>("id")?.with_context(|| {
+            ConfigError::FieldIsMissing("id".to_string(), "pc-dimm".to_string())
+        })?,
+        memdev: cmd_parser.get_value::<String>("memdev")?,
+        slot: cmd_parser.get_value::<u8>("slot")?,
+    };
+
+    if let Some(memdev) = dimm_cfg.memdev.as_ref() {
+        if vm_config.object.mem_object.get(memdev).is_none() {
+            bail!("Object for memory-backend {} config not found", memdev);
+        }
+    }
+
+    if let Some(slot) = dimm_cfg.slot {
+        if slot >= vm_config.machine_config.mem_config.slots {
+            bail!(
+                "Slot {} of pc-dimm exceeds the memory slots {}",
+                slot,
+                vm_config.machine_config.mem_config.slots
+            );
+        }
+    }
+
+    dimm_cfg.check()?;
+    Ok(dimm_cfg)
+}
+
+impl VmConfig {
+    /// Add 'pc-dimm' device to `VmConfig devices`.
+    pub fn add_dimm_device_config(&mut self, args: &qmp_schema::DeviceAddArgument, slot: u8) {
+        let mut device_info = format!("{},id={},slot={}", args.driver, args.id, slot);
+        if let Some(memdev) = &args.memdev {
+            device_info = format!("{},memdev={}", device_info, memdev);
+        }
+
+        self.devices.push((args.driver.clone(), device_info));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dimm_config_cmdline_parser() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_memory("size=2G,slots=4,maxmem=6G").is_ok());
+        assert!(vm_config
+            .add_object("memory-backend-ram,size=1G,id=mem0")
+            .is_ok());
+
+        let dimm_cfg = parse_dimm(&vm_config, "pc-dimm,id=dimm0,memdev=mem0,slot=1").unwrap();
+        assert_eq!(dimm_cfg.id, "dimm0");
+        assert_eq!(dimm_cfg.memdev, Some("mem0".to_string()));
+        assert_eq!(dimm_cfg.slot, Some(1));
+
+        let dimm_cfg = parse_dimm(&vm_config, "pc-dimm,id=dimm1").unwrap();
+        assert_eq!(dimm_cfg.memdev, None);
+        assert_eq!(dimm_cfg.slot, None);
+
+        // Id is missing.
+        assert!(parse_dimm(&vm_config, "pc-dimm,memdev=mem0").is_err());
+        // Memory backend is not found.
+        assert!(parse_dimm(&vm_config, "pc-dimm,id=dimm2,memdev=mem1").is_err());
+        // Slot is out of range.
+        assert!(parse_dimm(&vm_config, "pc-dimm,id=dimm3,slot=4").is_err());
+    }
+}
@@ -24,7 +24,7 @@ use crate::config::{
     check_arg_too_long, get_chardev_socket_path, memory_unit_conversion, CmdParser, ConfigCheck,
     ExBool, VmConfig, DEFAULT_VIRTQUEUE_SIZE, MAX_PATH_LENGTH, MAX_STRING_LENGTH, MAX_VIRTIO_QUEUE,
 };
-use crate::qmp::qmp_schema;
+use crate::qmp::{fdset_id_from_path, qmp_schema};
 use util::aio::{aio_probe, AioEngine, WriteZeroesState};
 const MAX_SERIAL_NUM: usize = 20;
 const MAX_IOPS: u64 = 1_000_000;
@@ -169,6 +169,10 @@ impl Default for DriveConfig {
 impl DriveConfig {
     /// Check whether the drive file path on the host is valid.
     pub fn check_path(&self) -> Result<()> {
+        // The file descriptor in fd set is checked when it's opened.
+        if fdset_id_from_path(&self.path_on_host).is_some() {
+            return Ok(());
+        }
         let blk = Path::new(&self.path_on_host);
         match metadata(blk) {
             Ok(meta) => {
@@ -644,6 +648,58 @@ impl VmConfig {
         }
     }
 
+    /// Get the information of all drives for `query-block`.
+    pub fn get_block_info(&self) -> Vec<qmp_schema::BlockInfo> {
+        let mut block_info: Vec<qmp_schema::BlockInfo> = self
+            .drives
+            .values()
+            .map(|drive| {
+                // Find the device which the drive is attached to.
+                let drive_arg = format!("drive={}", drive.id);
+                let qdev = self
+                    .devices
+                    .iter()
+                    .find(|dev| dev.1.split(',').any(|arg| arg == drive_arg))
+                    .and_then(|dev| {
+                        dev.1
+                            .split(',')
+                            .find_map(|arg| arg.strip_prefix("id="))
+                            .map(String::from)
+                    });
+                let drv = match drive.format {
+                    DiskFormat::Raw => "raw",
+                    DiskFormat::Qcow2 => "qcow2",
+                };
+                let inserted = if drive.path_on_host.is_empty() {
+                    None
+                } else {
+                    Some(qmp_schema::BlockDeviceInfo {
+                        file: drive.path_on_host.clone(),
+                        node_name: drive.id.clone(),
+                        ro: drive.read_only,
+                        drv: drv.to_string(),
+                        encrypted: false,
+                        cache: qmp_schema::BlockdevCacheInfo {
+                            writeback: true,
+                            direct: drive.direct,
+                            no_flush: false,
+                        },
+                    })
+                };
+
+                qmp_schema::BlockInfo {
+                    device: drive.id.clone(),
+                    qdev,
+                    removable: drive.media == "cdrom",
+                    locked: false,
+                    inserted,
+                }
+            })
+            .collect();
+        block_info.sort_by(|a, b| a.device.cmp(&b.device));
+        block_info
+    }
+
     /// Add new flash device to `VmConfig`.
     fn add_flashdev(&mut self, pflash: PFlashConfig) -> Result<()> {
         if self.pflashs.is_some() {
@@ -877,6 +933,53 @@ mod tests {
         assert!(vm_config.add_drive_with_config(drive_conf).is_err());
     }
 
+    #[test]
+    fn test_get_block_info() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.get_block_info().is_empty());
+
+        let drive_conf = DriveConfig {
+            id: String::from("drive-1"),
+            path_on_host: String::from("/path/to/cdrom"),
+            read_only: true,
+            media: String::from("cdrom"),
+            format: DiskFormat::Qcow2,
+            ..Default::default()
+        };
+        assert!(vm_config.add_drive_with_config(drive_conf).is_ok());
+        let drive_conf = DriveConfig {
+            id: String::from("drive-0"),
+            path_on_host: String::from("/path/to/block"),
+            direct: false,
+            ..Default::default()
+        };
+        assert!(vm_config.add_drive_with_config(drive_conf).is_ok());
+        vm_config.devices.push((
+            String::from("virtio-blk-pci"),
+            String::from("virtio-blk-pci,id=blk-0,drive=drive-0,bus=pcie.0,addr=0x2"),
+        ));
+
+        let block_info = vm_config.get_block_info();
+        assert_eq!(block_info.len(), 2);
+        assert_eq!(block_info[0].device, "drive-0");
+        assert_eq!(block_info[0].qdev, Some(String::from("blk-0")));
+        assert!(!block_info[0].removable);
+        let inserted = block_info[0].inserted.as_ref().unwrap();
+        assert_eq!(inserted.file, "/path/to/block");
+        assert_eq!(inserted.node_name, "drive-0");
+        assert_eq!(inserted.drv, "raw");
+        assert!(!inserted.ro);
+        assert!(!inserted.cache.direct);
+
+        assert_eq!(block_info[1].device, "drive-1");
+        assert_eq!(block_info[1].qdev, None);
+        assert!(block_info[1].removable);
+        let inserted = block_info[1].inserted.as_ref().unwrap();
+        assert_eq!(inserted.drv, "qcow2");
+        assert!(inserted.ro);
+        assert!(inserted.cache.direct);
+    }
+
     #[test]
     fn test_del_drive_by_id() {
         let mut vm_config = VmConfig::default();
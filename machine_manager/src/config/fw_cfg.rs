@@ -0,0 +1,128 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::error::ConfigError;
+use crate::config::{check_arg_too_long, CmdParser, ConfigCheck, VmConfig};
+
+/// Max length of fw_cfg file name, including the terminating NUL.
+const FW_CFG_MAX_FILE_PATH: usize = 56;
+
+/// Config struct for user-defined fw_cfg file entry.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FwCfgConfig {
+    /// Name of fw_cfg file entry.
+    pub name: String,
+    /// Host file whose content is the data of the entry.
+    pub file: Option<String>,
+    /// String which is the data of the entry.
+    pub string: Option<String>,
+}
+
+impl ConfigCheck for FwCfgConfig {
+    fn check(&self) -> Result<()> {
+        if self.name.is_empty() || self.name.len() >= FW_CFG_MAX_FILE_PATH {
+            return Err(anyhow!(ConfigError::StringLengthTooLong(
+                "fw_cfg name".to_string(),
+                FW_CFG_MAX_FILE_PATH - 1
+            )));
+        }
+        if !self.name.starts_with("opt/") {
+            bail!("fw_cfg name {:?} should start with \"opt/\"", self.name);
+        }
+        match (&self.file, &self.string) {
+            (Some(file), None) => {
+                check_arg_too_long(file, "fw_cfg file")?;
+                if !Path::new(file).is_file() {
+                    return Err(anyhow!(ConfigError::FileNotExist(file.to_string())));
+                }
+            }
+            (None, Some(string)) => check_arg_too_long(string, "fw_cfg string")?,
+            _ => bail!("One and only one of \"file\" and \"string\" should be set for fw_cfg"),
+        }
+        Ok(())
+    }
+}
+
+impl VmConfig {
+    /// Add user-defined fw_cfg file entry to `VmConfig`.
+    ///
+    /// # Arguments
+    ///
+    /// * `fw_cfg_config` - The args of fw_cfg entry.
+    pub fn add_fw_cfg(&mut self, fw_cfg_config: &str) -> Result<()> {
+        let mut cmd_parser = CmdParser::new("fw_cfg");
+        cmd_parser.push("name").push("file").push("string");
+        cmd_parser.parse(fw_cfg_config)?;
+
+        let name = cmd_parser.get_value::<String>("name")?.ok_or_else(|| {
+            anyhow!(ConfigError::FieldIsMissing(
+                "name".to_string(),
+                "fw_cfg".to_string()
+            ))
+        })?;
+        let config = FwCfgConfig {
+            name,
+            file: cmd_parser.get_value::<String>("file")?,
+            string: cmd_parser.get_value::<String>("string")?,
+        };
+        config.check()?;
+
+        if self.fw_cfg.iter().any(|entry| entry.name == config.name) {
+            return Err(anyhow!(ConfigError::IdRepeat(
+                "fw_cfg".to_string(),
+                config.name
+            )));
+        }
+        self.fw_cfg.push(config);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_fw_cfg() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config
+            .add_fw_cfg("name=opt/com.example/str,string=hello")
+            .is_ok());
+        assert_eq!(vm_config.fw_cfg[0].string, Some("hello".to_string()));
+        // Repeated name.
+        assert!(vm_config
+            .add_fw_cfg("name=opt/com.example/str,string=world")
+            .is_err());
+        // Name should start with "opt/".
+        assert!(vm_config.add_fw_cfg("name=com.example,string=hi").is_err());
+        // Missing name.
+        assert!(vm_config.add_fw_cfg("string=hi").is_err());
+        // File and string can not be set together.
+        assert!(vm_config
+            .add_fw_cfg("name=opt/com.example/blob,file=/dev/null,string=hi")
+            .is_err());
+        assert!(vm_config.add_fw_cfg("name=opt/com.example/blob").is_err());
+        // File should exist.
+        assert!(vm_config
+            .add_fw_cfg("name=opt/com.example/blob,file=/path/not/exist")
+            .is_err());
+        let long_name = format!("opt/{}", "a".repeat(FW_CFG_MAX_FILE_PATH));
+        assert!(vm_config
+            .add_fw_cfg(&format!("name={},string=hi", long_name))
+            .is_err());
+    }
+}
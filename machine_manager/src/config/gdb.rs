@@ -0,0 +1,69 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::net::Ipv4Addr;
+
+use anyhow::{bail, Result};
+
+use super::VmConfig;
+
+impl VmConfig {
+    /// Add the tcp address of gdbstub, as `tcp:[<ip>]:<port>`. It listens on
+    /// all addresses if ip is omitted.
+    pub fn add_gdb(&mut self, config: &str) -> Result<()> {
+        if cfg!(not(target_arch = "x86_64")) {
+            bail!("gdbstub is only supported on x86_64");
+        }
+
+        let parse_vec: Vec<&str> = config.split(':').collect();
+        if parse_vec.len() != 3 || parse_vec[0] != "tcp" {
+            bail!(
+                "Invalid gdb address {}, only tcp socket is supported",
+                config
+            );
+        }
+        let ip = if parse_vec[1].is_empty() {
+            "0.0.0.0"
+        } else {
+            parse_vec[1]
+        };
+        if ip.parse::<Ipv4Addr>().is_err() {
+            bail!("Invalid ip address {}", ip);
+        }
+        if parse_vec[2].parse::<u16>().is_err() {
+            bail!("Invalid ip port {}", parse_vec[2]);
+        }
+
+        self.gdb = Some(format!("{}:{}", ip, parse_vec[2]));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_add_gdb() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_gdb("tcp::1234").is_ok());
+        assert_eq!(vm_config.gdb.as_deref(), Some("0.0.0.0:1234"));
+        assert!(vm_config.add_gdb("tcp:127.0.0.1:1234").is_ok());
+        assert_eq!(vm_config.gdb.as_deref(), Some("127.0.0.1:1234"));
+
+        assert!(vm_config.add_gdb("tcp:1234").is_err());
+        assert!(vm_config.add_gdb("unix:/tmp/gdb.sock").is_err());
+        assert!(vm_config.add_gdb("tcp:localhost:1234").is_err());
+        assert!(vm_config.add_gdb("tcp::65536").is_err());
+    }
+}
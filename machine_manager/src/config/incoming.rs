@@ -22,6 +22,8 @@ pub enum MigrateMode {
     File,
     Unix,
     Tcp,
+    Fd,
+    Rdma,
     Unknown,
 }
 
@@ -31,6 +33,8 @@ impl From<&str> for MigrateMode {
             "file" | "File" | "FILE" => MigrateMode::File,
             "unix" | "Unix" | "UNIX" => MigrateMode::Unix,
             "tcp" | "Tcp" | "TCP" => MigrateMode::Tcp,
+            "fd" | "Fd" | "FD" => MigrateMode::Fd,
+            "rdma" | "Rdma" | "RDMA" => MigrateMode::Rdma,
             _ => MigrateMode::Unknown,
         }
     }
@@ -43,11 +47,12 @@ pub fn parse_incoming_uri(uri: &str) -> Result<(MigrateMode, String)> {
         match MigrateMode::from(parse_vec[0]) {
             MigrateMode::File => Ok((MigrateMode::File, String::from(parse_vec[1]))),
             MigrateMode::Unix => Ok((MigrateMode::Unix, String::from(parse_vec[1]))),
+            MigrateMode::Fd => Ok((MigrateMode::Fd, String::from(parse_vec[1]))),
             _ => bail!("Invalid incoming uri {}", uri),
         }
     } else if parse_vec.len() == 3 {
         match MigrateMode::from(parse_vec[0]) {
-            MigrateMode::Tcp => {
+            mode @ (MigrateMode::Tcp | MigrateMode::Rdma) => {
                 if parse_vec[1].parse::<Ipv4Addr>().is_err() {
                     bail!("Invalid ip address {}", parse_vec[1]);
                 }
@@ -55,10 +60,7 @@ pub fn parse_incoming_uri(uri: &str) -> Result<(MigrateMode, String)> {
                     bail!("Invalid ip port {}", parse_vec[2]);
                 }
 
-                Ok((
-                    MigrateMode::Tcp,
-                    format!("{}:{}", parse_vec[1], parse_vec[2]),
-                ))
+                Ok((mode, format!("{}:{}", parse_vec[1], parse_vec[2])))
             }
 
             _ => bail!("Invalid incoming uri {}", uri),
@@ -78,6 +80,14 @@ impl VmConfig {
             MigrateMode::File => (MigrateMode::File, uri),
             MigrateMode::Unix => (MigrateMode::Unix, uri),
             MigrateMode::Tcp => (MigrateMode::Tcp, uri),
+            MigrateMode::Rdma => (MigrateMode::Rdma, uri),
+            MigrateMode::Fd => {
+                // The fd of incoming migration is inherited from the parent process.
+                if uri.parse::<i32>().map_or(true, |fd| fd < 0) {
+                    bail!("Invalid fd {} for incoming migration", uri);
+                }
+                (MigrateMode::Fd, uri)
+            }
             MigrateMode::Unknown => {
                 bail!("Unsupported incoming unix path type")
             }
@@ -86,6 +96,18 @@ impl VmConfig {
         self.incoming = Some(incoming);
         Ok(())
     }
+
+    /// Add the address of NBD server, which exports the drives of VM as the
+    /// target of storage migration.
+    pub fn add_nbd_server(&mut self, config: &str) -> Result<()> {
+        let (mode, uri) = parse_incoming_uri(config)?;
+        if mode != MigrateMode::Tcp && mode != MigrateMode::Unix {
+            bail!("Only tcp and unix socket are supported by NBD server");
+        }
+
+        self.nbd_server = Some((mode, uri));
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -97,7 +119,9 @@ mod tests {
         assert_eq!(MigrateMode::from("File"), MigrateMode::File);
         assert_eq!(MigrateMode::from("UNIX"), MigrateMode::Unix);
         assert_eq!(MigrateMode::from("tcp"), MigrateMode::Tcp);
-        assert_eq!(MigrateMode::from("fd"), MigrateMode::Unknown);
+        assert_eq!(MigrateMode::from("fd"), MigrateMode::Fd);
+        assert_eq!(MigrateMode::from("rdma"), MigrateMode::Rdma);
+        assert_eq!(MigrateMode::from("exec"), MigrateMode::Unknown);
     }
 
     #[test]
@@ -127,6 +151,21 @@ mod tests {
         let incoming_case5 = "tcp:192.168.1.2:65568";
         let result_5 = parse_incoming_uri(incoming_case5);
         assert!(result_5.is_err());
+
+        let incoming_case6 = "fd:migrate-fd";
+        let result_6 = parse_incoming_uri(incoming_case6).unwrap();
+        assert_eq!(result_6, (MigrateMode::Fd, "migrate-fd".to_string()));
+
+        let incoming_case7 = "rdma:192.168.1.2:4446";
+        let result_7 = parse_incoming_uri(incoming_case7).unwrap();
+        assert_eq!(
+            result_7,
+            (MigrateMode::Rdma, "192.168.1.2:4446".to_string())
+        );
+
+        let incoming_case8 = "rdma:/tmp/stratovirt.sock";
+        let result_8 = parse_incoming_uri(incoming_case8);
+        assert!(result_8.is_err());
     }
 
     #[test]
@@ -149,5 +188,35 @@ mod tests {
 
         let mut vm_config_case2 = VmConfig::default();
         assert!(vm_config_case2.add_incoming("unkonw:/tmp/").is_err());
+
+        let mut vm_config_case3 = VmConfig::default();
+        assert!(vm_config_case3.add_incoming("fd:10").is_ok());
+        assert_eq!(
+            vm_config_case3.incoming.unwrap(),
+            (MigrateMode::Fd, "10".to_string())
+        );
+        let mut vm_config_case4 = VmConfig::default();
+        assert!(vm_config_case4.add_incoming("fd:migrate-fd").is_err());
+    }
+
+    #[test]
+    fn test_add_nbd_server() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_nbd_server("tcp:192.168.1.2:10809").is_ok());
+        assert_eq!(
+            vm_config.nbd_server.unwrap(),
+            (MigrateMode::Tcp, "192.168.1.2:10809".to_string())
+        );
+
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_nbd_server("unix:/tmp/nbd.sock").is_ok());
+        assert_eq!(
+            vm_config.nbd_server.unwrap(),
+            (MigrateMode::Unix, "/tmp/nbd.sock".to_string())
+        );
+
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_nbd_server("fd:10").is_err());
+        assert!(vm_config.add_nbd_server("file:/tmp/nbd").is_err());
     }
 }
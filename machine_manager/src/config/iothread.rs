@@ -17,11 +17,29 @@ use crate::config::{check_arg_too_long, CmdParser, ConfigCheck, VmConfig};
 use anyhow::{anyhow, Result};
 
 const MAX_IOTHREAD_NUM: usize = 8;
+const DEFAULT_POLL_MAX_NS: u64 = 32768;
 
 /// Config structure for iothread.
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IothreadConfig {
     pub id: String,
+    /// Max time in nanoseconds to poll events before sleeping, 0 disables polling.
+    pub poll_max_ns: u64,
+    /// Factor to grow the polling time by, 0 means the default factor 2.
+    pub poll_grow: u64,
+    /// Factor to shrink the polling time by, 0 means resetting it to 0.
+    pub poll_shrink: u64,
+}
+
+impl Default for IothreadConfig {
+    fn default() -> Self {
+        IothreadConfig {
+            id: String::new(),
+            poll_max_ns: DEFAULT_POLL_MAX_NS,
+            poll_grow: 0,
+            poll_shrink: 0,
+        }
+    }
 }
 
 impl ConfigCheck for IothreadConfig {
@@ -34,13 +52,27 @@ impl VmConfig {
     /// Add new iothread device to `VmConfig`.
     pub fn add_iothread(&mut self, iothread_config: &str) -> Result<()> {
         let mut cmd_parser = CmdParser::new("iothread");
-        cmd_parser.push("").push("id");
+        cmd_parser
+            .push("")
+            .push("id")
+            .push("poll-max-ns")
+            .push("poll-grow")
+            .push("poll-shrink");
         cmd_parser.parse(iothread_config)?;
 
         let mut iothread = IothreadConfig::default();
         if let Some(id) = cmd_parser.get_value::<String>("id")? {
             iothread.id = id;
         }
+        if let Some(poll_max_ns) = cmd_parser.get_value::<u64>("poll-max-ns")? {
+            iothread.poll_max_ns = poll_max_ns;
+        }
+        if let Some(poll_grow) = cmd_parser.get_value::<u64>("poll-grow")? {
+            iothread.poll_grow = poll_grow;
+        }
+        if let Some(poll_shrink) = cmd_parser.get_value::<u64>("poll-shrink")? {
+            iothread.poll_shrink = poll_shrink;
+        }
         iothread.check()?;
 
         if self.iothreads.is_some() {
@@ -105,4 +137,31 @@ mod tests {
         assert!(vm_config.add_object("iothread,id=iothread0").is_ok());
         assert!(vm_config.add_object("iothread,id=iothread0").is_err());
     }
+
+    #[test]
+    fn test_iothread_config_poll_params() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_object("iothread,id=iothread0").is_ok());
+        assert!(vm_config
+            .add_object("iothread,id=iothread1,poll-max-ns=0")
+            .is_ok());
+        assert!(vm_config
+            .add_object("iothread,id=iothread2,poll-max-ns=65536,poll-grow=4,poll-shrink=2")
+            .is_ok());
+        assert!(vm_config
+            .add_object("iothread,id=iothread3,poll-grow=-1")
+            .is_err());
+
+        let iothreads = vm_config.iothreads.unwrap();
+        assert_eq!(iothreads[0].poll_max_ns, 32768);
+        assert_eq!(iothreads[1].poll_max_ns, 0);
+        assert_eq!(
+            (
+                iothreads[2].poll_max_ns,
+                iothreads[2].poll_grow,
+                iothreads[2].poll_shrink
+            ),
+            (65536, 4, 2)
+        );
+    }
 }
@@ -35,6 +35,23 @@ const MAX_MEMSIZE: u64 = 549_755_813_888;
 const MIN_MEMSIZE: u64 = 134_217_728;
 pub const M: u64 = 1024 * 1024;
 pub const G: u64 = 1024 * 1024 * 1024;
+/// Max number of hotpluggable memory slots.
+pub const MAX_MEM_SLOTS: u8 = 32;
+/// Size of hotpluggable memory slot must be aligned to the memory block size of guest.
+const MEM_SLOT_ALIGN: u64 = 128 * M;
+// Max vector length of SVE is 2048 bits, which is 16 quadwords.
+const MAX_SVE_VQ: u32 = 16;
+// Range of the period of cpu cgroup in microseconds, the quota can't be less than the min period.
+const DEFAULT_CPU_PERIOD: u64 = 100_000;
+const MIN_CPU_PERIOD: u64 = 1000;
+const MAX_CPU_PERIOD: u64 = 1_000_000;
+// Range of cpu.shares of cgroup v1.
+const MIN_CPU_SHARES: u64 = 2;
+const MAX_CPU_SHARES: u64 = 262_144;
+/// Default interval in seconds to count guest crashes for crash loop protection.
+const DEFAULT_CRASH_INTERVAL: u64 = 60;
+const MIN_DIRTY_RING_SIZE: u32 = 1024;
+const MAX_DIRTY_RING_SIZE: u32 = 65536;
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MachineType {
@@ -50,6 +67,7 @@ impl FromStr for MachineType {
         match s.to_lowercase().as_str() {
             "none" => Ok(MachineType::None),
             "microvm" => Ok(MachineType::MicroVm),
+            "standard_vm" => Ok(MachineType::StandardVm),
             #[cfg(target_arch = "x86_64")]
             "q35" => Ok(MachineType::StandardVm),
             #[cfg(target_arch = "aarch64")]
@@ -59,6 +77,122 @@ impl FromStr for MachineType {
     }
 }
 
+/// Version of a machine type, e.g. `2.2` in `microvm-2.2`.
+///
+/// A versioned machine type freezes the device defaults and the migration sections
+/// of the StratoVirt release it is named after, so that a VM started on that release
+/// can be migrated to a newer one.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MachineVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl MachineVersion {
+    /// Machine version of StratoVirt 2.2, the first versioned release.
+    pub const V2_2: MachineVersion = MachineVersion { major: 2, minor: 2 };
+    /// Machine version of StratoVirt 2.3.
+    pub const V2_3: MachineVersion = MachineVersion { major: 2, minor: 3 };
+    /// Machine version used by unversioned machine types.
+    pub const LATEST: MachineVersion = MachineVersion::V2_3;
+    /// All supported machine versions, in ascending order.
+    pub const SUPPORTED: [MachineVersion; 2] = [MachineVersion::V2_2, MachineVersion::V2_3];
+
+    /// Get the value of property `prop` of `driver` that this machine version
+    /// freezes, `None` means the property takes the current default.
+    pub fn compat_prop(&self, driver: &str, prop: &str) -> Option<&'static str> {
+        // Compat properties are applied to all machine versions older than the
+        // version which changed the default. If the default changed more than once,
+        // the value before the first change after this version is taken.
+        MACHINE_COMPAT_PROPS
+            .iter()
+            .filter(|p| *self < p.0 && p.1 == driver && p.2 == prop)
+            .map(|p| p.3)
+            .next()
+    }
+
+    /// Check whether the migration section `name` is part of this machine version.
+    pub fn has_section(&self, name: &str) -> bool {
+        !MACHINE_COMPAT_SECTIONS
+            .iter()
+            .any(|s| *self < s.0 && s.1 == name)
+    }
+
+    /// Machine version of a VM config which doesn't record it. It is only used when
+    /// deserializing a VM config sent by StratoVirt 2.2.
+    pub fn legacy() -> Self {
+        MachineVersion::V2_2
+    }
+}
+
+impl Default for MachineVersion {
+    fn default() -> Self {
+        MachineVersion::LATEST
+    }
+}
+
+impl FromStr for MachineVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (major, minor) = s.split_once('.').ok_or(())?;
+        let version = MachineVersion {
+            major: major.parse::<u8>().map_err(|_| ())?,
+            minor: minor.parse::<u8>().map_err(|_| ())?,
+        };
+        if !MachineVersion::SUPPORTED.contains(&version) {
+            return Err(());
+        }
+        Ok(version)
+    }
+}
+
+impl std::fmt::Display for MachineVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Device properties whose default changed, as (version which changed the default,
+/// driver, property, value of older versions). Newer entries must be appended.
+const MACHINE_COMPAT_PROPS: &[(MachineVersion, &str, &str, &str)] =
+    &[(MachineVersion::V2_3, "virtio-net", "status", "off")];
+
+/// Migration sections, as (version which introduced the section, section name).
+/// Older machine versions neither send nor expect these sections.
+const MACHINE_COMPAT_SECTIONS: &[(MachineVersion, &str)] = &[
+    (MachineVersion::V2_3, "BalloonState"),
+    (MachineVersion::V2_3, "VhostUserState"),
+];
+
+/// Split machine type name like `microvm-2.2` into machine type and machine version,
+/// unversioned name means the latest machine version.
+fn parse_machine_type(name: &str) -> Result<(MachineType, MachineVersion)> {
+    if let Ok(mach_type) = MachineType::from_str(name) {
+        return Ok((mach_type, MachineVersion::LATEST));
+    }
+    let (type_name, version) = name
+        .rsplit_once('-')
+        .with_context(|| format!("Unrecognized machine type {}", name))?;
+    let mach_type = MachineType::from_str(type_name)
+        .map_err(|_| anyhow!("Unrecognized machine type {}", name))?;
+    if mach_type == MachineType::None {
+        bail!("Machine type none doesn't support versions");
+    }
+    let version = MachineVersion::from_str(version).map_err(|_| {
+        anyhow!(
+            "Unsupported machine version {}, supported versions: {}",
+            version,
+            MachineVersion::SUPPORTED
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    })?;
+    Ok((mach_type, version))
+}
+
 #[repr(u32)]
 #[derive(PartialEq, Eq)]
 pub enum HostMemPolicy {
@@ -92,6 +226,16 @@ pub struct MemZoneConfig {
     pub share: bool,
     pub prealloc: bool,
     pub memfd: bool,
+    /// Allocate memory from hugetlbfs, only for memory-backend-memfd.
+    pub hugetlb: bool,
+    /// Size of huge page, 0 means the default huge page size of host.
+    pub hugetlb_size: u64,
+    /// Fall back to normal pages if allocating huge pages fails.
+    pub hugetlb_fallback: bool,
+    /// Seal the size of memfd, only for memory-backend-memfd.
+    pub seal: bool,
+    /// Use transparent huge page or not, `None` means the host default.
+    pub thp: Option<bool>,
 }
 
 impl Default for MemZoneConfig {
@@ -106,19 +250,43 @@ impl Default for MemZoneConfig {
             share: false,
             prealloc: false,
             memfd: false,
+            hugetlb: false,
+            hugetlb_size: 0,
+            hugetlb_fallback: false,
+            seal: false,
+            thp: None,
         }
     }
 }
 
 /// Config that contains machine's memory information config.
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MachineMemConfig {
     pub mem_size: u64,
     pub mem_path: Option<String>,
     pub dump_guest_core: bool,
     pub mem_share: bool,
     pub mem_prealloc: bool,
+    /// Lock all guest memory in host RAM.
+    pub mem_lock: bool,
     pub mem_zones: Option<Vec<MemZoneConfig>>,
+    /// Number of hotpluggable memory slots.
+    pub slots: u8,
+    /// Max memory size including hotpluggable memory, 0 means no hotpluggable memory.
+    pub max_size: u64,
+    /// Number of entries of the per-vcpu KVM dirty ring, 0 means the dirty ring is disabled.
+    pub dirty_ring_size: u32,
+}
+
+impl MachineMemConfig {
+    /// Get the size of each hotpluggable memory slot.
+    pub fn slot_size(&self) -> u64 {
+        if self.slots == 0 || self.max_size <= self.mem_size {
+            return 0;
+        }
+        (self.max_size - self.mem_size) / self.slots as u64
+    }
 }
 
 impl Default for MachineMemConfig {
@@ -129,14 +297,32 @@ impl Default for MachineMemConfig {
             dump_guest_core: true,
             mem_share: false,
             mem_prealloc: false,
+            mem_lock: false,
             mem_zones: None,
+            slots: 0,
+            max_size: 0,
+            dirty_ring_size: 0,
         }
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct CpuConfig {
+    /// CPU model, `None` means the host model.
+    pub model: Option<String>,
+    /// CPU feature flags, `(name, true)` for `+name` and `(name, false)` for `-name`.
+    pub flags: Vec<(String, bool)>,
     pub pmu: PmuConfig,
+    /// Enable Scalable Vector Extension.
+    pub sve: bool,
+    /// Max SVE vector length in quadwords, `None` means the max length supported by host.
+    pub sve_max_vq: Option<u32>,
+    /// Enable pointer authentication.
+    pub pauth: bool,
+    /// Enable paravirtual steal time.
+    pub steal_time: bool,
+    /// Guest TSC frequency in kHz, `None` means the frequency of host.
+    pub tsc_khz: Option<u32>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
@@ -153,11 +339,152 @@ pub enum ShutdownAction {
     ShutdownActionPause,
 }
 
+/// Action taken when the watchdog timer expires.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum WatchdogAction {
+    #[default]
+    Reset,
+    Shutdown,
+    Pause,
+    InjectNmi,
+    None,
+}
+
+impl FromStr for WatchdogAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "reset" => Ok(WatchdogAction::Reset),
+            "shutdown" => Ok(WatchdogAction::Shutdown),
+            "pause" => Ok(WatchdogAction::Pause),
+            "inject-nmi" => Ok(WatchdogAction::InjectNmi),
+            "none" => Ok(WatchdogAction::None),
+            _ => Err(()),
+        }
+    }
+}
+
+impl WatchdogAction {
+    /// Name of the action reported in `WATCHDOG` event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WatchdogAction::Reset => "reset",
+            WatchdogAction::Shutdown => "shutdown",
+            WatchdogAction::Pause => "pause",
+            WatchdogAction::InjectNmi => "inject-nmi",
+            WatchdogAction::None => "none",
+        }
+    }
+}
+
+/// Action taken when guest panic is detected.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PanicAction {
+    #[default]
+    Pause,
+    Shutdown,
+    Reset,
+    None,
+}
+
+impl FromStr for PanicAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "pause" => Ok(PanicAction::Pause),
+            "shutdown" => Ok(PanicAction::Shutdown),
+            "reset" => Ok(PanicAction::Reset),
+            "none" => Ok(PanicAction::None),
+            _ => Err(()),
+        }
+    }
+}
+
+impl PanicAction {
+    /// Name of the action reported in `GUEST_PANICKED` event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PanicAction::Pause => "pause",
+            PanicAction::Shutdown => "poweroff",
+            PanicAction::Reset => "reset",
+            PanicAction::None => "run",
+        }
+    }
+}
+
+/// Action taken when guest requests a reboot.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum RebootAction {
+    #[default]
+    Reset,
+    Shutdown,
+}
+
+impl FromStr for RebootAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "reset" => Ok(RebootAction::Reset),
+            "shutdown" => Ok(RebootAction::Shutdown),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Mode of the interrupt controller emulated by KVM.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum KernelIrqchip {
+    /// PIC, IOAPIC and LAPIC are all emulated in KVM.
+    #[default]
+    On,
+    /// Only LAPIC is emulated in KVM, IOAPIC is emulated in userspace.
+    Split,
+}
+
+impl FromStr for KernelIrqchip {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "on" => Ok(KernelIrqchip::On),
+            "split" => Ok(KernelIrqchip::Split),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Scheduling class of vCPU threads.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VcpuSched {
+    /// SCHED_OTHER with the nice value, in [-20, 19].
+    Nice(i32),
+    /// SCHED_FIFO with the real-time priority, in [1, 99].
+    Fifo(i32),
+}
+
+/// Limits of the CPU usage of vCPU threads, which are set by the cpu cgroup of the VM.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CpuQuota {
+    /// Max run time in microseconds of all vCPU threads in each period, `None` means no limit.
+    pub quota: Option<u64>,
+    /// Length of the period in microseconds.
+    pub period: u64,
+    /// Relative weight as `cpu.shares` of cgroup v1, `None` means the default of host.
+    pub shares: Option<u64>,
+}
+
 /// Config struct for machine-config.
 /// Contains some basic Vm config about cpu, memory, name.
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MachineConfig {
     pub mach_type: MachineType,
+    /// Version of machine type, a VM config without it comes from StratoVirt 2.2.
+    #[serde(default = "MachineVersion::legacy")]
+    pub mach_version: MachineVersion,
     pub nr_cpus: u8,
     pub nr_threads: u8,
     pub nr_cores: u8,
@@ -167,8 +494,27 @@ pub struct MachineConfig {
     pub max_cpus: u8,
     pub mem_config: MachineMemConfig,
     pub cpu_config: CpuConfig,
+    /// Host CPU pinned to by each vCPU thread, as `(vcpu index, host cpu index)`.
+    pub cpu_pin: Vec<(u8, usize)>,
+    /// Scheduling class of vCPU threads, `None` means inherited from the main thread.
+    pub cpu_sched: Option<VcpuSched>,
+    /// Limits of the CPU usage of vCPU threads, `None` means vCPU threads are not
+    /// placed into a dedicated cgroup.
+    pub cpu_quota: Option<CpuQuota>,
     pub shutdown_action: ShutdownAction,
+    pub watchdog_action: WatchdogAction,
+    pub panic_action: PanicAction,
+    pub reboot_action: RebootAction,
+    /// Max number of guest crashes within `crash_interval` seconds, the VM is shut down
+    /// if it's exceeded. `0` means crash loop protection is disabled.
+    pub crash_limit: u32,
+    pub crash_interval: u64,
     pub battery: bool,
+    pub kernel_irqchip: KernelIrqchip,
+    /// Max time in nanoseconds to poll before halting a vCPU, `None` means the default of host kvm.
+    pub halt_poll_ns: Option<u32>,
+    /// Guest memory is private to guest and inaccessible to host except the shared part.
+    pub protected: bool,
 }
 
 impl Default for MachineConfig {
@@ -176,6 +522,7 @@ impl Default for MachineConfig {
     fn default() -> Self {
         MachineConfig {
             mach_type: MachineType::MicroVm,
+            mach_version: MachineVersion::default(),
             nr_cpus: DEFAULT_CPUS,
             nr_threads: DEFAULT_THREADS,
             nr_cores: DEFAULT_CORES,
@@ -185,8 +532,19 @@ impl Default for MachineConfig {
             max_cpus: DEFAULT_MAX_CPUS,
             mem_config: MachineMemConfig::default(),
             cpu_config: CpuConfig::default(),
+            cpu_pin: Vec::new(),
+            cpu_sched: None,
+            cpu_quota: None,
             shutdown_action: ShutdownAction::default(),
+            watchdog_action: WatchdogAction::default(),
+            panic_action: PanicAction::default(),
+            reboot_action: RebootAction::default(),
+            crash_limit: 0,
+            crash_interval: DEFAULT_CRASH_INTERVAL,
             battery: false,
+            kernel_irqchip: KernelIrqchip::default(),
+            halt_poll_ns: None,
+            protected: false,
         }
     }
 }
@@ -198,6 +556,45 @@ impl ConfigCheck for MachineConfig {
             &self.mem_config.mem_size);
         }
 
+        for (vcpu, _) in self.cpu_pin.iter() {
+            if *vcpu >= self.max_cpus {
+                bail!(
+                    "vcpu{} to be pinned doesn't exist, max cpus: {}",
+                    vcpu,
+                    self.max_cpus
+                );
+            }
+        }
+
+        let mem_config = &self.mem_config;
+        if mem_config.slots == 0 {
+            if mem_config.max_size != 0 && mem_config.max_size != mem_config.mem_size {
+                bail!("Memory slots must be set when maxmem is larger than memory size");
+            }
+            return Ok(());
+        }
+        if mem_config.slots > MAX_MEM_SLOTS {
+            return Err(anyhow!(ConfigError::IllegalValue(
+                "memory slots".to_string(),
+                0,
+                true,
+                MAX_MEM_SLOTS as u64,
+                true,
+            )));
+        }
+        if mem_config.max_size <= mem_config.mem_size || mem_config.max_size > MAX_MEMSIZE {
+            bail!(
+                "Maxmem must > memory size and <= 512GiB, current maxmem: {:?} bytes",
+                mem_config.max_size
+            );
+        }
+        if mem_config.slot_size() == 0 || mem_config.slot_size() % MEM_SLOT_ALIGN != 0 {
+            bail!(
+                "Size of each memory slot (maxmem - size) / slots must be aligned to 128MiB, current slot size: {:?} bytes",
+                mem_config.slot_size()
+            );
+        }
+
         Ok(())
     }
 }
@@ -216,9 +613,13 @@ impl VmConfig {
             .push("accel")
             .push("usb")
             .push("dump-guest-core")
-            .push("mem-share");
+            .push("mem-share")
+            .push("mem-lock")
+            .push("protected");
         #[cfg(target_arch = "aarch64")]
         cmd_parser.push("gic-version");
+        #[cfg(target_arch = "x86_64")]
+        cmd_parser.push("kernel-irqchip");
         cmd_parser.parse(mach_config)?;
 
         #[cfg(target_arch = "aarch64")]
@@ -239,17 +640,12 @@ impl VmConfig {
                 bail!("Argument \'usb\' should be set to \'off\'");
             }
         }
-        if let Some(mach_type) = cmd_parser
-            .get_value::<MachineType>("")
-            .with_context(|| "Unrecognized machine type")?
-        {
-            self.machine_config.mach_type = mach_type;
-        }
-        if let Some(mach_type) = cmd_parser
-            .get_value::<MachineType>("type")
-            .with_context(|| "Unrecognized machine type")?
-        {
-            self.machine_config.mach_type = mach_type;
+        for key in ["", "type"] {
+            if let Some(name) = cmd_parser.get_value::<String>(key)? {
+                let (mach_type, mach_version) = parse_machine_type(&name)?;
+                self.machine_config.mach_type = mach_type;
+                self.machine_config.mach_version = mach_version;
+            }
         }
         if let Some(dump_guest) = cmd_parser.get_value::<ExBool>("dump-guest-core")? {
             self.machine_config.mem_config.dump_guest_core = dump_guest.into();
@@ -257,6 +653,19 @@ impl VmConfig {
         if let Some(mem_share) = cmd_parser.get_value::<ExBool>("mem-share")? {
             self.machine_config.mem_config.mem_share = mem_share.into();
         }
+        if let Some(mem_lock) = cmd_parser.get_value::<ExBool>("mem-lock")? {
+            self.machine_config.mem_config.mem_lock = mem_lock.into();
+        }
+        if let Some(protected) = cmd_parser.get_value::<ExBool>("protected")? {
+            self.machine_config.protected = protected.into();
+        }
+        #[cfg(target_arch = "x86_64")]
+        if let Some(irqchip) = cmd_parser
+            .get_value::<KernelIrqchip>("kernel-irqchip")
+            .with_context(|| "Invalid kernel-irqchip, only \'on\' and \'split\' are supported")?
+        {
+            self.machine_config.kernel_irqchip = irqchip;
+        }
 
         Ok(())
     }
@@ -264,7 +673,10 @@ impl VmConfig {
     /// Add '-accel' accelerator config to `VmConfig`.
     pub fn add_accel(&mut self, accel_config: &str) -> Result<()> {
         let mut cmd_parser = CmdParser::new("accel");
-        cmd_parser.push("");
+        cmd_parser
+            .push("")
+            .push("dirty-ring-size")
+            .push("halt-poll-ns");
         cmd_parser.parse(accel_config)?;
 
         if let Some(accel) = cmd_parser.get_value::<String>("")? {
@@ -272,6 +684,22 @@ impl VmConfig {
                 bail!("Only \'kvm\' is supported for \'accel\'");
             }
         }
+        if let Some(size) = cmd_parser.get_value::<u32>("dirty-ring-size")? {
+            if size != 0
+                && (!size.is_power_of_two()
+                    || !(MIN_DIRTY_RING_SIZE..=MAX_DIRTY_RING_SIZE).contains(&size))
+            {
+                bail!(
+                    "dirty-ring-size must be 0 or a power of 2 in [{}, {}]",
+                    MIN_DIRTY_RING_SIZE,
+                    MAX_DIRTY_RING_SIZE
+                );
+            }
+            self.machine_config.mem_config.dirty_ring_size = size;
+        }
+        if let Some(ns) = cmd_parser.get_value::<u32>("halt-poll-ns")? {
+            self.machine_config.halt_poll_ns = Some(ns);
+        }
 
         Ok(())
     }
@@ -279,7 +707,11 @@ impl VmConfig {
     /// Add '-m' memory config to `VmConfig`.
     pub fn add_memory(&mut self, mem_config: &str) -> Result<()> {
         let mut cmd_parser = CmdParser::new("m");
-        cmd_parser.push("").push("size");
+        cmd_parser
+            .push("")
+            .push("size")
+            .push("slots")
+            .push("maxmem");
 
         cmd_parser.parse(mem_config)?;
 
@@ -296,6 +728,13 @@ impl VmConfig {
 
         self.machine_config.mem_config.mem_size = mem;
 
+        if let Some(slots) = cmd_parser.get_value::<u8>("slots")? {
+            self.machine_config.mem_config.slots = slots;
+        }
+        if let Some(max_size) = cmd_parser.get_value::<String>("maxmem")? {
+            self.machine_config.mem_config.max_size = memory_unit_conversion(&max_size)?;
+        }
+
         Ok(())
     }
 
@@ -396,10 +835,22 @@ impl VmConfig {
     }
 
     pub fn add_cpu_feature(&mut self, features: &str) -> Result<()> {
+        #[cfg(target_arch = "x86_64")]
+        let features = &self.add_cpu_flags(features)?;
+
         let mut cmd_parser = CmdParser::new("cpu");
         cmd_parser.push("");
         cmd_parser.push("pmu");
+        cmd_parser.push("sve");
+        cmd_parser.push("sve-max-vq");
+        cmd_parser.push("pauth");
+        cmd_parser.push("steal-time");
+        #[cfg(target_arch = "x86_64")]
+        cmd_parser.push("tsc-frequency");
         cmd_parser.parse(features)?;
+        if let Some(model) = cmd_parser.get_value::<String>("")? {
+            self.machine_config.cpu_config.model = Some(model);
+        }
         //Check PMU when actually enabling PMU.
         if let Some(k) = cmd_parser.get_value::<String>("pmu")? {
             self.machine_config.cpu_config.pmu = match k.as_ref() {
@@ -408,9 +859,201 @@ impl VmConfig {
                 _ => bail!("Invalid PMU option,must be one of \'on\" or \"off\"."),
             }
         }
+        if let Some(sve) = cmd_parser.get_value::<ExBool>("sve")? {
+            self.machine_config.cpu_config.sve = sve.into();
+        }
+        if let Some(vq) = cmd_parser.get_value::<u32>("sve-max-vq")? {
+            if vq == 0 || vq > MAX_SVE_VQ {
+                return Err(anyhow!(ConfigError::IllegalValue(
+                    "sve-max-vq".to_string(),
+                    1,
+                    true,
+                    MAX_SVE_VQ as u64,
+                    true
+                )));
+            }
+            self.machine_config.cpu_config.sve_max_vq = Some(vq);
+        }
+        if self.machine_config.cpu_config.sve_max_vq.is_some()
+            && !self.machine_config.cpu_config.sve
+        {
+            bail!("sve-max-vq can only be set when sve is on");
+        }
+        if let Some(pauth) = cmd_parser.get_value::<ExBool>("pauth")? {
+            self.machine_config.cpu_config.pauth = pauth.into();
+        }
+        if let Some(steal_time) = cmd_parser.get_value::<ExBool>("steal-time")? {
+            self.machine_config.cpu_config.steal_time = steal_time.into();
+        }
+        #[cfg(target_arch = "x86_64")]
+        if let Some(freq) = cmd_parser.get_value::<u64>("tsc-frequency")? {
+            let khz = freq / 1000;
+            if khz == 0 || khz > u64::from(u32::MAX) {
+                bail!("Invalid tsc-frequency {}, it's in Hz", freq);
+            }
+            self.machine_config.cpu_config.tsc_khz = Some(khz as u32);
+        }
+        Ok(())
+    }
+
+    /// Add argument `cpu-pin` to `VmConfig`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu_pin` - Host CPU of each vCPU, as `vcpu0=3,vcpu1=5`.
+    pub fn add_cpu_pin(&mut self, cpu_pin: &str) -> Result<()> {
+        for item in cpu_pin.split(',') {
+            let (vcpu, host_cpu) = item
+                .split_once('=')
+                .and_then(|(vcpu, host_cpu)| {
+                    Some((
+                        vcpu.strip_prefix("vcpu")?.parse::<u8>().ok()?,
+                        host_cpu.parse::<usize>().ok()?,
+                    ))
+                })
+                .ok_or_else(|| {
+                    anyhow!(ConfigError::InvalidParam(
+                        item.to_string(),
+                        "cpu-pin".to_string()
+                    ))
+                })?;
+            if self.machine_config.cpu_pin.iter().any(|(v, _)| *v == vcpu) {
+                bail!("vcpu{} is pinned more than once", vcpu);
+            }
+            self.machine_config.cpu_pin.push((vcpu, host_cpu));
+        }
+        Ok(())
+    }
+
+    /// Add argument `cpu-sched` to `VmConfig`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu_sched` - Scheduling class of vCPU threads, as `policy=other,nice=5`
+    ///   or `policy=fifo,priority=10`.
+    pub fn add_cpu_sched(&mut self, cpu_sched: &str) -> Result<()> {
+        let mut cmd_parser = CmdParser::new("cpu-sched");
+        cmd_parser.push("policy").push("nice").push("priority");
+        cmd_parser.parse(cpu_sched)?;
+
+        let policy = cmd_parser
+            .get_value::<String>("policy")?
+            .unwrap_or_else(|| "other".to_string());
+        let nice = cmd_parser.get_value::<i32>("nice")?;
+        let priority = cmd_parser.get_value::<i32>("priority")?;
+        let sched = match policy.as_str() {
+            "other" => {
+                if priority.is_some() {
+                    bail!("priority is only supported by fifo policy of cpu-sched");
+                }
+                let nice = nice.ok_or_else(|| {
+                    anyhow!(ConfigError::FieldIsMissing(
+                        "nice".to_string(),
+                        "cpu-sched".to_string()
+                    ))
+                })?;
+                if !(-20..=19).contains(&nice) {
+                    bail!("nice of cpu-sched must be in [-20, 19]");
+                }
+                VcpuSched::Nice(nice)
+            }
+            "fifo" => {
+                if nice.is_some() {
+                    bail!("nice is only supported by other policy of cpu-sched");
+                }
+                let priority = priority.ok_or_else(|| {
+                    anyhow!(ConfigError::FieldIsMissing(
+                        "priority".to_string(),
+                        "cpu-sched".to_string()
+                    ))
+                })?;
+                if !(1..=99).contains(&priority) {
+                    bail!("priority of cpu-sched must be in [1, 99]");
+                }
+                VcpuSched::Fifo(priority)
+            }
+            _ => {
+                return Err(anyhow!(ConfigError::InvalidParam(
+                    policy,
+                    "cpu-sched".to_string()
+                )))
+            }
+        };
+        self.machine_config.cpu_sched = Some(sched);
+        Ok(())
+    }
+
+    /// Add argument `cpu-quota` to `VmConfig`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu_quota` - Limits of the CPU usage of vCPU threads, as `quota=50000,period=100000`
+    ///   or `shares=512`.
+    pub fn add_cpu_quota(&mut self, cpu_quota: &str) -> Result<()> {
+        let mut cmd_parser = CmdParser::new("cpu-quota");
+        cmd_parser.push("quota").push("period").push("shares");
+        cmd_parser.parse(cpu_quota)?;
+
+        let quota = cmd_parser.get_value::<u64>("quota")?;
+        let period = cmd_parser
+            .get_value::<u64>("period")?
+            .unwrap_or(DEFAULT_CPU_PERIOD);
+        let shares = cmd_parser.get_value::<u64>("shares")?;
+        if quota.is_none() && shares.is_none() {
+            bail!("At least one of quota and shares should be set for cpu-quota");
+        }
+        if !(MIN_CPU_PERIOD..=MAX_CPU_PERIOD).contains(&period) {
+            bail!(
+                "period of cpu-quota must be in [{}, {}]",
+                MIN_CPU_PERIOD,
+                MAX_CPU_PERIOD
+            );
+        }
+        if matches!(quota, Some(q) if q < MIN_CPU_PERIOD) {
+            bail!("quota of cpu-quota must be at least {}", MIN_CPU_PERIOD);
+        }
+        if matches!(shares, Some(s) if !(MIN_CPU_SHARES..=MAX_CPU_SHARES).contains(&s)) {
+            bail!(
+                "shares of cpu-quota must be in [{}, {}]",
+                MIN_CPU_SHARES,
+                MAX_CPU_SHARES
+            );
+        }
+        self.machine_config.cpu_quota = Some(CpuQuota {
+            quota,
+            period,
+            shares,
+        });
         Ok(())
     }
 
+    /// Pick up the `+feature` and `-feature` flags from cpu parameters, and
+    /// return the remaining parameters.
+    #[cfg(target_arch = "x86_64")]
+    fn add_cpu_flags(&mut self, features: &str) -> Result<String> {
+        let mut params = Vec::new();
+        for item in features.split(',') {
+            let (name, enable) = if let Some(name) = item.strip_prefix('+') {
+                (name, true)
+            } else if let Some(name) = item.strip_prefix('-') {
+                (name, false)
+            } else {
+                params.push(item);
+                continue;
+            };
+            if name.is_empty() {
+                return Err(anyhow!(ConfigError::InvalidParam(
+                    item.to_string(),
+                    "cpu".to_string()
+                )));
+            }
+            let flags = &mut self.machine_config.cpu_config.flags;
+            flags.retain(|(flag, _)| flag != name);
+            flags.push((name.to_string(), enable));
+        }
+        Ok(params.join(","))
+    }
+
     pub fn add_mem_path(&mut self, mem_path: &str) -> Result<()> {
         self.machine_config.mem_config.mem_path = Some(mem_path.replace('\"', ""));
         Ok(())
@@ -425,6 +1068,69 @@ impl VmConfig {
         true
     }
 
+    /// Set the action taken when the watchdog timer expires.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - One of reset, shutdown, pause, inject-nmi and none.
+    pub fn add_watchdog_action(&mut self, action: &str) -> Result<()> {
+        self.machine_config.watchdog_action = WatchdogAction::from_str(action).map_err(|_| {
+            anyhow!(ConfigError::InvalidParam(
+                "watchdog-action".to_string(),
+                action.to_string()
+            ))
+        })?;
+        Ok(())
+    }
+
+    /// Set the actions taken on guest events, such as "panic=shutdown", and the
+    /// crash loop protection, such as "crash-limit=3,crash-interval=60".
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - Comma separated list of event=action pairs.
+    pub fn add_action(&mut self, action: &str) -> Result<()> {
+        let mut cmd_parser = CmdParser::new("action");
+        cmd_parser
+            .push("reboot")
+            .push("panic")
+            .push("watchdog")
+            .push("crash-limit")
+            .push("crash-interval");
+        cmd_parser.parse(action)?;
+
+        if let Some(reboot) = cmd_parser.get_value::<String>("reboot")? {
+            self.machine_config.reboot_action = RebootAction::from_str(&reboot).map_err(|_| {
+                anyhow!(ConfigError::InvalidParam(
+                    "reboot".to_string(),
+                    reboot.clone()
+                ))
+            })?;
+        }
+
+        if let Some(panic) = cmd_parser.get_value::<String>("panic")? {
+            self.machine_config.panic_action = PanicAction::from_str(&panic).map_err(|_| {
+                anyhow!(ConfigError::InvalidParam(
+                    "panic".to_string(),
+                    panic.clone()
+                ))
+            })?;
+        }
+        if let Some(watchdog) = cmd_parser.get_value::<String>("watchdog")? {
+            self.add_watchdog_action(&watchdog)?;
+        }
+        if let Some(limit) = cmd_parser.get_value::<u32>("crash-limit")? {
+            self.machine_config.crash_limit = limit;
+        }
+        if let Some(interval) = cmd_parser.get_value::<u64>("crash-interval")? {
+            if interval == 0 {
+                bail!("crash-interval should be greater than 0");
+            }
+            self.machine_config.crash_interval = interval;
+        }
+        Ok(())
+    }
+
     pub fn add_battery(&mut self) -> bool {
         self.machine_config.battery = true;
         true
@@ -523,6 +1229,48 @@ impl VmConfig {
         Ok(true)
     }
 
+    fn get_mem_hugetlb(&self, cmd_parser: &CmdParser) -> Result<bool> {
+        if let Some(hugetlb) = cmd_parser.get_value::<ExBool>("hugetlb")? {
+            return Ok(hugetlb.into());
+        }
+        Ok(false)
+    }
+
+    fn get_mem_hugetlb_size(&self, cmd_parser: &CmdParser) -> Result<u64> {
+        if let Some(size) = cmd_parser.get_value::<String>("hugetlbsize")? {
+            let size = memory_unit_conversion(&size)?;
+            if !size.is_power_of_two() {
+                return Err(anyhow!(ConfigError::InvalidParam(
+                    "hugetlbsize".to_string(),
+                    size.to_string()
+                )));
+            }
+            return Ok(size);
+        }
+        Ok(0)
+    }
+
+    fn get_mem_hugetlb_fallback(&self, cmd_parser: &CmdParser) -> Result<bool> {
+        if let Some(fallback) = cmd_parser.get_value::<ExBool>("hugetlb-fallback")? {
+            return Ok(fallback.into());
+        }
+        Ok(false)
+    }
+
+    fn get_mem_seal(&self, cmd_parser: &CmdParser, mem_type: &str) -> Result<bool> {
+        if let Some(seal) = cmd_parser.get_value::<ExBool>("seal")? {
+            if mem_type.ne("memory-backend-memfd") {
+                bail!("Object type: {} does not support seal", mem_type);
+            }
+            return Ok(seal.into());
+        }
+        Ok(mem_type.eq("memory-backend-memfd"))
+    }
+
+    fn get_mem_thp(&self, cmd_parser: &CmdParser) -> Result<Option<bool>> {
+        Ok(cmd_parser.get_value::<ExBool>("thp")?.map(|thp| thp.into()))
+    }
+
     fn get_mem_prealloc(&self, cmd_parser: &CmdParser) -> Result<bool> {
         if let Some(mem_prealloc) = cmd_parser.get_value::<ExBool>("mem-prealloc")? {
             return Ok(mem_prealloc.into());
@@ -547,7 +1295,12 @@ impl VmConfig {
             .push("share")
             .push("mem-path")
             .push("dump-guest-core")
-            .push("mem-prealloc");
+            .push("mem-prealloc")
+            .push("hugetlb")
+            .push("hugetlbsize")
+            .push("hugetlb-fallback")
+            .push("seal")
+            .push("thp");
         cmd_parser.parse(mem_zone)?;
 
         let zone_config = MemZoneConfig {
@@ -560,8 +1313,41 @@ impl VmConfig {
             mem_path: self.get_mem_path(&cmd_parser)?,
             prealloc: self.get_mem_prealloc(&cmd_parser)?,
             memfd: mem_type.eq("memory-backend-memfd"),
+            hugetlb: self.get_mem_hugetlb(&cmd_parser)?,
+            hugetlb_size: self.get_mem_hugetlb_size(&cmd_parser)?,
+            hugetlb_fallback: self.get_mem_hugetlb_fallback(&cmd_parser)?,
+            seal: self.get_mem_seal(&cmd_parser, &mem_type)?,
+            thp: self.get_mem_thp(&cmd_parser)?,
         };
 
+        if zone_config.hugetlb && !zone_config.memfd {
+            bail!("Object type: {} does not support hugetlb", mem_type);
+        }
+        if zone_config.hugetlb && zone_config.thp.is_some() {
+            bail!("thp can not be used with hugetlb");
+        }
+        if !zone_config.hugetlb && (zone_config.hugetlb_size != 0 || zone_config.hugetlb_fallback) {
+            bail!("hugetlbsize and hugetlb-fallback need hugetlb to be on");
+        }
+        if zone_config.hugetlb_size != 0 && zone_config.size % zone_config.hugetlb_size != 0 {
+            bail!(
+                "Memory size 0x{:x} is not aligned with hugetlbsize 0x{:x}",
+                zone_config.size,
+                zone_config.hugetlb_size
+            );
+        }
+
+        let policy = HostMemPolicy::from(zone_config.policy.clone());
+        match zone_config.host_numa_nodes.as_ref() {
+            None if policy != HostMemPolicy::Default => {
+                bail!("host-nodes is required by policy {}", zone_config.policy);
+            }
+            Some(nodes) if policy == HostMemPolicy::Preferred && nodes.len() != 1 => {
+                bail!("Only one host node is supported by policy preferred");
+            }
+            _ => {}
+        }
+
         if (zone_config.mem_path.is_none() && mem_type.eq("memory-backend-file"))
             || (zone_config.mem_path.is_some() && mem_type.ne("memory-backend-file"))
         {
@@ -576,10 +1362,6 @@ impl VmConfig {
             bail!("Object: {} has been added", zone_config.id);
         }
 
-        if zone_config.host_numa_nodes.is_none() {
-            return Ok(zone_config);
-        }
-
         if self.machine_config.mem_config.mem_zones.is_some() {
             self.machine_config
                 .mem_config
@@ -710,10 +1492,15 @@ mod tests {
             mem_share: false,
             dump_guest_core: false,
             mem_prealloc: false,
+            mem_lock: false,
             mem_zones: None,
+            slots: 0,
+            max_size: 0,
+            dirty_ring_size: 0,
         };
         let mut machine_config = MachineConfig {
             mach_type: MachineType::MicroVm,
+            mach_version: MachineVersion::default(),
             nr_cpus: 1,
             nr_cores: 1,
             nr_threads: 1,
@@ -723,8 +1510,19 @@ mod tests {
             max_cpus: MIN_NR_CPUS as u8,
             mem_config: memory_config,
             cpu_config: CpuConfig::default(),
+            cpu_pin: Vec::new(),
+            cpu_sched: None,
+            cpu_quota: None,
             shutdown_action: ShutdownAction::default(),
+            watchdog_action: WatchdogAction::default(),
+            panic_action: PanicAction::default(),
+            reboot_action: RebootAction::default(),
+            crash_limit: 0,
+            crash_interval: DEFAULT_CRASH_INTERVAL,
             battery: false,
+            kernel_irqchip: KernelIrqchip::default(),
+            halt_poll_ns: None,
+            protected: false,
         };
         assert!(machine_config.check().is_ok());
 
@@ -864,6 +1662,12 @@ mod tests {
         let machine_type = MachineType::from_str(test_string);
         assert!(machine_type.is_err());
 
+        let test_string = "standard_vm";
+        let machine_type = MachineType::from_str(test_string);
+        assert!(machine_type.is_ok());
+        let machine_type = machine_type.unwrap();
+        assert_eq!(machine_type, MachineType::StandardVm);
+
         #[cfg(target_arch = "x86_64")]
         {
             let test_string = "q35";
@@ -925,16 +1729,74 @@ mod tests {
         assert_eq!(mem_size, 8 * 1024 * 1024 * 1024);
     }
 
+    #[test]
+    fn test_add_hotplug_memory() {
+        let mut vm_config = VmConfig::default();
+        let memory_cfg = "size=2G,slots=4,maxmem=6G";
+        assert!(vm_config.add_memory(memory_cfg).is_ok());
+        let mem_config = &vm_config.machine_config.mem_config;
+        assert_eq!(mem_config.slots, 4);
+        assert_eq!(mem_config.max_size, 6 * G);
+        assert_eq!(mem_config.slot_size(), G);
+        assert!(vm_config.machine_config.check().is_ok());
+
+        // Maxmem without slots.
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_memory("size=2G,maxmem=6G").is_ok());
+        assert!(vm_config.machine_config.check().is_err());
+
+        // Maxmem is not larger than memory size.
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_memory("size=2G,slots=4,maxmem=2G").is_ok());
+        assert!(vm_config.machine_config.check().is_err());
+
+        // Slot size is not aligned.
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_memory("size=2G,slots=3,maxmem=3G").is_ok());
+        assert!(vm_config.machine_config.check().is_err());
+
+        // Too many slots.
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_memory("size=2G,slots=33,maxmem=35G").is_ok());
+        assert!(vm_config.machine_config.check().is_err());
+    }
+
+    #[test]
+    fn test_add_accel() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_accel("kvm").is_ok());
+        assert_eq!(vm_config.machine_config.mem_config.dirty_ring_size, 0);
+        assert!(vm_config.add_accel("kvm,dirty-ring-size=4096").is_ok());
+        assert_eq!(vm_config.machine_config.mem_config.dirty_ring_size, 4096);
+        assert!(vm_config.add_accel("kvm,dirty-ring-size=0").is_ok());
+        assert_eq!(vm_config.machine_config.mem_config.dirty_ring_size, 0);
+
+        assert!(vm_config.add_accel("tcg").is_err());
+        assert!(vm_config.add_accel("kvm,dirty-ring-size=4000").is_err());
+        assert!(vm_config.add_accel("kvm,dirty-ring-size=512").is_err());
+        assert!(vm_config.add_accel("kvm,dirty-ring-size=131072").is_err());
+
+        assert_eq!(vm_config.machine_config.halt_poll_ns, None);
+        assert!(vm_config.add_accel("kvm,halt-poll-ns=200000").is_ok());
+        assert_eq!(vm_config.machine_config.halt_poll_ns, Some(200000));
+        assert!(vm_config.add_accel("kvm,halt-poll-ns=0").is_ok());
+        assert_eq!(vm_config.machine_config.halt_poll_ns, Some(0));
+        assert!(vm_config.add_accel("kvm,halt-poll-ns=-1").is_err());
+    }
+
     #[test]
     fn test_add_machine() {
         let mut vm_config = VmConfig::default();
-        let memory_cfg_str = "type=none,dump-guest-core=on,mem-share=on,accel=kvm,usb=off";
+        let memory_cfg_str =
+            "type=none,dump-guest-core=on,mem-share=on,mem-lock=on,protected=on,accel=kvm,usb=off";
         let machine_cfg_ret = vm_config.add_machine(memory_cfg_str);
         assert!(machine_cfg_ret.is_ok());
         let machine_cfg = vm_config.machine_config;
         assert_eq!(machine_cfg.mach_type, MachineType::None);
         assert_eq!(machine_cfg.mem_config.dump_guest_core, true);
         assert_eq!(machine_cfg.mem_config.mem_share, true);
+        assert_eq!(machine_cfg.mem_config.mem_lock, true);
+        assert_eq!(machine_cfg.protected, true);
 
         let mut vm_config = VmConfig::default();
         let memory_cfg_str = "type=none,dump-guest-core=off,mem-share=off,accel=kvm,usb=off";
@@ -944,6 +1806,8 @@ mod tests {
         assert_eq!(machine_cfg.mach_type, MachineType::None);
         assert_eq!(machine_cfg.mem_config.dump_guest_core, false);
         assert_eq!(machine_cfg.mem_config.mem_share, false);
+        assert_eq!(machine_cfg.mem_config.mem_lock, false);
+        assert_eq!(machine_cfg.protected, false);
 
         let mut vm_config = VmConfig::default();
         let memory_cfg_str = "type=none,accel=kvm-tcg";
@@ -982,6 +1846,64 @@ mod tests {
             let machine_cfg_ret = vm_config.add_machine(memory_cfg_str);
             assert!(machine_cfg_ret.is_err());
         }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            let mut vm_config = VmConfig::default();
+            assert!(vm_config.add_machine("type=q35").is_ok());
+            assert_eq!(vm_config.machine_config.kernel_irqchip, KernelIrqchip::On);
+
+            let mut vm_config = VmConfig::default();
+            let memory_cfg_str = "type=q35,kernel-irqchip=split";
+            let machine_cfg_ret = vm_config.add_machine(memory_cfg_str);
+            assert!(machine_cfg_ret.is_ok());
+            assert_eq!(
+                vm_config.machine_config.kernel_irqchip,
+                KernelIrqchip::Split
+            );
+
+            let mut vm_config = VmConfig::default();
+            let memory_cfg_str = "type=q35,kernel-irqchip=off";
+            let machine_cfg_ret = vm_config.add_machine(memory_cfg_str);
+            assert!(machine_cfg_ret.is_err());
+        }
+    }
+
+    #[test]
+    fn test_versioned_machine_type() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_machine("microvm").is_ok());
+        let machine_cfg = &vm_config.machine_config;
+        assert_eq!(machine_cfg.mach_type, MachineType::MicroVm);
+        assert_eq!(machine_cfg.mach_version, MachineVersion::LATEST);
+
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_machine("type=microvm-2.2").is_ok());
+        let machine_cfg = &vm_config.machine_config;
+        assert_eq!(machine_cfg.mach_type, MachineType::MicroVm);
+        assert_eq!(machine_cfg.mach_version, MachineVersion::V2_2);
+
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_machine("standard_vm-2.3").is_ok());
+        let machine_cfg = &vm_config.machine_config;
+        assert_eq!(machine_cfg.mach_type, MachineType::StandardVm);
+        assert_eq!(machine_cfg.mach_version, MachineVersion::V2_3);
+
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_machine("microvm-1.0").is_err());
+        assert!(vm_config.add_machine("microvm-2").is_err());
+        assert!(vm_config.add_machine("none-2.2").is_err());
+        assert!(vm_config.add_machine("machine-2.2").is_err());
+
+        let v2_2 = MachineVersion::V2_2;
+        assert_eq!(v2_2.to_string(), "2.2");
+        assert_eq!(v2_2.compat_prop("virtio-net", "status"), Some("off"));
+        assert_eq!(v2_2.compat_prop("virtio-net", "mq"), None);
+        assert!(!v2_2.has_section("BalloonState"));
+        assert!(v2_2.has_section("CpuState"));
+        let latest = MachineVersion::LATEST;
+        assert_eq!(latest.compat_prop("virtio-net", "status"), None);
+        assert!(latest.has_section("BalloonState"));
     }
 
     #[test]
@@ -1042,6 +1964,110 @@ mod tests {
         assert!(cpu_cfg_ret.is_err());
     }
 
+    #[test]
+    fn test_add_watchdog_action() {
+        let mut vm_config = VmConfig::default();
+        assert_eq!(
+            vm_config.machine_config.watchdog_action,
+            WatchdogAction::Reset
+        );
+        assert!(vm_config.add_watchdog_action("inject-nmi").is_ok());
+        assert_eq!(
+            vm_config.machine_config.watchdog_action,
+            WatchdogAction::InjectNmi
+        );
+        assert!(vm_config.add_watchdog_action("none").is_ok());
+        assert_eq!(
+            vm_config.machine_config.watchdog_action,
+            WatchdogAction::None
+        );
+        assert!(vm_config.add_watchdog_action("poweroff").is_err());
+
+        let action = WatchdogAction::InjectNmi;
+        assert_eq!(WatchdogAction::from_str(action.name()), Ok(action));
+    }
+
+    #[test]
+    fn test_add_action() {
+        let mut vm_config = VmConfig::default();
+        assert_eq!(vm_config.machine_config.panic_action, PanicAction::Pause);
+        assert!(vm_config.add_action("panic=shutdown").is_ok());
+        assert_eq!(vm_config.machine_config.panic_action, PanicAction::Shutdown);
+        assert!(vm_config.add_action("panic=none,watchdog=pause").is_ok());
+        assert_eq!(vm_config.machine_config.panic_action, PanicAction::None);
+        assert_eq!(
+            vm_config.machine_config.watchdog_action,
+            WatchdogAction::Pause
+        );
+        assert!(vm_config.add_action("panic=reboot").is_err());
+
+        assert_eq!(vm_config.machine_config.reboot_action, RebootAction::Reset);
+        assert!(vm_config.add_action("reboot=shutdown,panic=reset").is_ok());
+        assert_eq!(
+            vm_config.machine_config.reboot_action,
+            RebootAction::Shutdown
+        );
+        assert_eq!(vm_config.machine_config.panic_action, PanicAction::Reset);
+        assert!(vm_config.add_action("reboot=pause").is_err());
+
+        assert_eq!(vm_config.machine_config.crash_limit, 0);
+        assert!(vm_config.add_action("crash-limit=3").is_ok());
+        assert_eq!(vm_config.machine_config.crash_limit, 3);
+        assert_eq!(vm_config.machine_config.crash_interval, 60);
+        assert!(vm_config
+            .add_action("crash-limit=5,crash-interval=300")
+            .is_ok());
+        assert_eq!(vm_config.machine_config.crash_limit, 5);
+        assert_eq!(vm_config.machine_config.crash_interval, 300);
+        assert!(vm_config.add_action("crash-interval=0").is_err());
+        assert!(vm_config.add_action("crash-limit=-1").is_err());
+    }
+
+    #[test]
+    fn test_mem_zone_host_policy() {
+        let mut vm_config = VmConfig::default();
+        let zone_config = vm_config
+            .add_mem_zone(
+                "-object memory-backend-ram,size=2G,id=mem0,host-nodes=0,policy=preferred",
+                String::from("memory-backend-ram"),
+            )
+            .unwrap();
+        assert_eq!(zone_config.policy, "preferred");
+        // Memory backend without host-nodes is also a memory zone.
+        vm_config
+            .add_mem_zone(
+                "-object memory-backend-memfd,size=2G,id=mem1",
+                String::from("memory-backend-memfd"),
+            )
+            .unwrap();
+        let zones = vm_config.machine_config.mem_config.mem_zones.unwrap();
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones[1].id, "mem1");
+        assert!(zones[1].host_numa_nodes.is_none());
+
+        let mut vm_config = VmConfig::default();
+        // Policy except default needs host-nodes.
+        assert!(vm_config
+            .add_mem_zone(
+                "-object memory-backend-ram,size=2G,id=mem2,policy=bind",
+                String::from("memory-backend-ram"),
+            )
+            .is_err());
+        // Policy preferred supports only one host node.
+        assert!(vm_config
+            .add_mem_zone(
+                "-object memory-backend-ram,size=2G,id=mem3,host-nodes=0-1,policy=preferred",
+                String::from("memory-backend-ram"),
+            )
+            .is_err());
+        assert!(vm_config
+            .add_mem_zone(
+                "-object memory-backend-ram,size=2G,id=mem4,host-nodes=0-1,policy=interleave",
+                String::from("memory-backend-ram"),
+            )
+            .is_ok());
+    }
+
     #[test]
     fn test_add_mem_zone() {
         let mut vm_config = VmConfig::default();
@@ -1089,6 +2115,78 @@ mod tests {
             )
             .unwrap();
         assert_eq!(zone_config_5.memfd, true);
+        assert_eq!(zone_config_5.hugetlb, false);
+        assert_eq!(zone_config_5.seal, true);
+
+        let zone_config_6 = vm_config
+            .add_mem_zone(
+                "-object memory-backend-memfd,size=2G,id=mem6,hugetlb=on,hugetlbsize=1G,hugetlb-fallback=on",
+                String::from("memory-backend-memfd"),
+            )
+            .unwrap();
+        assert_eq!(zone_config_6.hugetlb, true);
+        assert_eq!(zone_config_6.hugetlb_size, 1024 * 1024 * 1024);
+        assert_eq!(zone_config_6.hugetlb_fallback, true);
+        assert_eq!(zone_config_6.seal, true);
+
+        let zone_config_7 = vm_config
+            .add_mem_zone(
+                "-object memory-backend-memfd,size=2M,id=mem11,seal=off",
+                String::from("memory-backend-memfd"),
+            )
+            .unwrap();
+        assert_eq!(zone_config_7.seal, false);
+        assert_eq!(zone_config_7.thp, None);
+        let zone_config_8 = vm_config
+            .add_mem_zone(
+                "-object memory-backend-ram,size=2M,id=mem13,thp=on",
+                String::from("memory-backend-ram"),
+            )
+            .unwrap();
+        assert_eq!(zone_config_8.thp, Some(true));
+        // Transparent huge page can not be used with hugetlb.
+        assert!(vm_config
+            .add_mem_zone(
+                "-object memory-backend-memfd,size=2M,id=mem14,hugetlb=on,thp=off",
+                String::from("memory-backend-memfd"),
+            )
+            .is_err());
+        // Seal is only supported by memory-backend-memfd.
+        assert!(vm_config
+            .add_mem_zone(
+                "-object memory-backend-ram,size=2M,id=mem12,seal=on",
+                String::from("memory-backend-ram"),
+            )
+            .is_err());
+
+        // Hugetlb is only supported by memory-backend-memfd.
+        assert!(vm_config
+            .add_mem_zone(
+                "-object memory-backend-ram,size=2M,id=mem7,hugetlb=on",
+                String::from("memory-backend-ram"),
+            )
+            .is_err());
+        // Hugetlbsize needs hugetlb to be on.
+        assert!(vm_config
+            .add_mem_zone(
+                "-object memory-backend-memfd,size=2M,id=mem8,hugetlbsize=2M",
+                String::from("memory-backend-memfd"),
+            )
+            .is_err());
+        // Hugetlbsize should be power of 2.
+        assert!(vm_config
+            .add_mem_zone(
+                "-object memory-backend-memfd,size=6M,id=mem9,hugetlb=on,hugetlbsize=3M",
+                String::from("memory-backend-memfd"),
+            )
+            .is_err());
+        // Memory size should be aligned with hugetlbsize.
+        assert!(vm_config
+            .add_mem_zone(
+                "-object memory-backend-memfd,size=3M,id=mem10,hugetlb=on,hugetlbsize=2M",
+                String::from("memory-backend-memfd"),
+            )
+            .is_err());
     }
 
     #[test]
@@ -1103,6 +2201,103 @@ mod tests {
         assert!(policy == HostMemPolicy::NotSupported);
     }
 
+    #[test]
+    fn test_add_cpu_pin() {
+        let mut vm_config = VmConfig::default();
+        vm_config.add_cpu("4").unwrap();
+        vm_config.add_cpu_pin("vcpu0=3,vcpu1=5").unwrap();
+        assert_eq!(vm_config.machine_config.cpu_pin, vec![(0, 3), (1, 5)]);
+        assert!(vm_config.machine_config.check().is_ok());
+
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_cpu_pin("vcpu0=3,vcpu0=5").is_err());
+        assert!(vm_config.add_cpu_pin("cpu0=3").is_err());
+        assert!(vm_config.add_cpu_pin("vcpu1").is_err());
+        assert!(vm_config.add_cpu_pin("vcpu1=-1").is_err());
+        assert!(vm_config.add_cpu_pin("").is_err());
+
+        let mut vm_config = VmConfig::default();
+        vm_config.add_cpu("2").unwrap();
+        vm_config.add_cpu_pin("vcpu2=3").unwrap();
+        assert!(vm_config.machine_config.check().is_err());
+    }
+
+    #[test]
+    fn test_add_cpu_quota() {
+        let mut vm_config = VmConfig::default();
+        vm_config.add_cpu_quota("quota=50000").unwrap();
+        assert_eq!(
+            vm_config.machine_config.cpu_quota,
+            Some(CpuQuota {
+                quota: Some(50000),
+                period: 100000,
+                shares: None
+            })
+        );
+        vm_config
+            .add_cpu_quota("quota=200000,period=50000,shares=512")
+            .unwrap();
+        assert_eq!(
+            vm_config.machine_config.cpu_quota,
+            Some(CpuQuota {
+                quota: Some(200000),
+                period: 50000,
+                shares: Some(512)
+            })
+        );
+        vm_config.add_cpu_quota("shares=2048").unwrap();
+        assert_eq!(
+            vm_config.machine_config.cpu_quota,
+            Some(CpuQuota {
+                quota: None,
+                period: 100000,
+                shares: Some(2048)
+            })
+        );
+
+        assert!(vm_config.add_cpu_quota("period=50000").is_err());
+        assert!(vm_config.add_cpu_quota("quota=500").is_err());
+        assert!(vm_config.add_cpu_quota("quota=50000,period=999").is_err());
+        assert!(vm_config
+            .add_cpu_quota("quota=50000,period=1000001")
+            .is_err());
+        assert!(vm_config.add_cpu_quota("shares=1").is_err());
+        assert!(vm_config.add_cpu_quota("shares=262145").is_err());
+        assert!(vm_config.add_cpu_quota("quota=-1").is_err());
+    }
+
+    #[test]
+    fn test_add_cpu_sched() {
+        let mut vm_config = VmConfig::default();
+        vm_config.add_cpu_sched("nice=-5").unwrap();
+        assert_eq!(
+            vm_config.machine_config.cpu_sched,
+            Some(VcpuSched::Nice(-5))
+        );
+        vm_config.add_cpu_sched("policy=other,nice=10").unwrap();
+        assert_eq!(
+            vm_config.machine_config.cpu_sched,
+            Some(VcpuSched::Nice(10))
+        );
+        vm_config.add_cpu_sched("policy=fifo,priority=20").unwrap();
+        assert_eq!(
+            vm_config.machine_config.cpu_sched,
+            Some(VcpuSched::Fifo(20))
+        );
+
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_cpu_sched("policy=other").is_err());
+        assert!(vm_config.add_cpu_sched("nice=20").is_err());
+        assert!(vm_config.add_cpu_sched("policy=other,priority=10").is_err());
+        assert!(vm_config.add_cpu_sched("policy=fifo").is_err());
+        assert!(vm_config.add_cpu_sched("policy=fifo,priority=0").is_err());
+        assert!(vm_config
+            .add_cpu_sched("policy=fifo,nice=1,priority=10")
+            .is_err());
+        assert!(vm_config.add_cpu_sched("policy=rr,priority=10").is_err());
+        assert!(vm_config.machine_config.cpu_sched.is_none());
+    }
+
     #[cfg(target_arch = "aarch64")]
     #[test]
     fn test_cpu_features() {
@@ -1118,5 +2313,74 @@ mod tests {
         assert!(vm_config.machine_config.cpu_config.pmu == PmuConfig::On);
         vm_config.add_cpu_feature("pmu=on").unwrap();
         assert!(vm_config.machine_config.cpu_config.pmu == PmuConfig::On);
+
+        // Test SVE and pointer authentication flags
+        let mut vm_config = VmConfig::default();
+        vm_config.add_cpu_feature("host").unwrap();
+        assert!(!vm_config.machine_config.cpu_config.sve);
+        assert!(!vm_config.machine_config.cpu_config.pauth);
+        vm_config
+            .add_cpu_feature("host,sve=on,sve-max-vq=4,pauth=on")
+            .unwrap();
+        assert!(vm_config.machine_config.cpu_config.sve);
+        assert_eq!(vm_config.machine_config.cpu_config.sve_max_vq, Some(4));
+        assert!(vm_config.machine_config.cpu_config.pauth);
+
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_cpu_feature("host,sve-max-vq=4").is_err());
+        assert!(vm_config
+            .add_cpu_feature("host,sve=on,sve-max-vq=0")
+            .is_err());
+        assert!(vm_config
+            .add_cpu_feature("host,sve=on,sve-max-vq=17")
+            .is_err());
+        assert!(vm_config.add_cpu_feature("host,pauth=yes").is_err());
+
+        // Test steal time flag
+        let mut vm_config = VmConfig::default();
+        vm_config.add_cpu_feature("host").unwrap();
+        assert!(!vm_config.machine_config.cpu_config.steal_time);
+        vm_config.add_cpu_feature("host,steal-time=on").unwrap();
+        assert!(vm_config.machine_config.cpu_config.steal_time);
+        vm_config.add_cpu_feature("host,steal-time=off").unwrap();
+        assert!(!vm_config.machine_config.cpu_config.steal_time);
+        assert!(vm_config.add_cpu_feature("host,steal-time=1").is_err());
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_cpu_features() {
+        let mut vm_config = VmConfig::default();
+        vm_config.add_cpu_feature("host").unwrap();
+        assert_eq!(
+            vm_config.machine_config.cpu_config.model,
+            Some("host".to_string())
+        );
+        assert!(vm_config.machine_config.cpu_config.flags.is_empty());
+
+        let mut vm_config = VmConfig::default();
+        vm_config
+            .add_cpu_feature("Skylake-Client,+avx2,-rtm,+rtm")
+            .unwrap();
+        let cpu_config = &vm_config.machine_config.cpu_config;
+        assert_eq!(cpu_config.model, Some("Skylake-Client".to_string()));
+        assert_eq!(
+            cpu_config.flags,
+            vec![("avx2".to_string(), true), ("rtm".to_string(), true)]
+        );
+
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_cpu_feature("host,+").is_err());
+        assert!(vm_config.add_cpu_feature("host,avx2").is_err());
+
+        let mut vm_config = VmConfig::default();
+        vm_config
+            .add_cpu_feature("host,+invtsc,tsc-frequency=2400000000")
+            .unwrap();
+        assert_eq!(vm_config.machine_config.cpu_config.tsc_khz, Some(2400000));
+        assert!(vm_config.add_cpu_feature("host,tsc-frequency=999").is_err());
+        assert!(vm_config
+            .add_cpu_feature("host,tsc-frequency=2.4G")
+            .is_err());
     }
 }
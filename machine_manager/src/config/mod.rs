@@ -16,10 +16,13 @@ pub use camera::*;
 pub use chardev::*;
 pub use demo_dev::*;
 pub use devices::*;
+pub use dimm::*;
 pub use display::*;
 pub use drive::*;
 pub use error::ConfigError;
 pub use fs::*;
+pub use fw_cfg::*;
+pub use gdb::*;
 pub use gpu::*;
 pub use incoming::*;
 pub use iothread::*;
@@ -27,26 +30,35 @@ pub use machine_config::*;
 pub use network::*;
 pub use numa::*;
 pub use pci::*;
+pub use pvpanic::*;
+pub use qom::*;
 pub use ramfb::*;
 pub use rng::*;
 pub use sasl_auth::*;
 pub use scsi::*;
+pub use secret::*;
 pub use smbios::*;
 pub use tls_creds::*;
+pub use tpm::*;
 pub use usb::*;
 pub use vfio::*;
 pub use vnc::*;
+pub use watchdog::*;
 
 mod balloon;
 mod boot_source;
 pub mod camera;
 mod chardev;
+mod config_file;
 mod demo_dev;
 mod devices;
+mod dimm;
 pub mod display;
 mod drive;
 pub mod error;
 mod fs;
+mod fw_cfg;
+mod gdb;
 mod gpu;
 mod incoming;
 mod iothread;
@@ -54,22 +66,29 @@ mod machine_config;
 mod network;
 mod numa;
 mod pci;
+mod pvpanic;
+mod qom;
 mod ramfb;
 mod rng;
 mod sasl_auth;
 pub mod scream;
 mod scsi;
+mod secret;
 mod smbios;
 mod tls_creds;
+mod tpm;
 mod usb;
 mod vfio;
 pub mod vnc;
+mod watchdog;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
+use std::os::unix::io::{AsRawFd, FromRawFd};
 use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use anyhow::{anyhow, bail, Context, Result};
 use log::error;
@@ -77,12 +96,16 @@ use log::error;
 use util::device_tree::{self, FdtBuilder};
 use util::{
     file::{get_file_alignment, open_file},
+    logger::{LogConfig, LogFormat, LogRotateTime},
     num_ops::str_to_usize,
+    seccomp::SeccompOpt,
     test_helper::is_test_enabled,
-    trace::enable_trace_events,
+    trace::{enable_trace_events, set_trace_backend, TraceBackend},
     AsAny,
 };
 
+use crate::qmp::{fdset_id_from_path, QmpChannel};
+
 pub const MAX_STRING_LENGTH: usize = 255;
 pub const MAX_PATH_LENGTH: usize = 4096;
 // Maximum length of the socket path is restricted by linux.
@@ -102,10 +125,13 @@ pub struct ObjectConfig {
     pub mem_object: HashMap<String, MemZoneConfig>,
     pub tls_object: HashMap<String, TlsCredObjConfig>,
     pub sasl_object: HashMap<String, SaslAuthObjConfig>,
+    pub secret_object: HashMap<String, SecretObjConfig>,
 }
 
 /// This main config structure for Vm, contains Vm's basic configuration and devices.
+/// Fields missing in the config sent by older StratoVirt take the default value.
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct VmConfig {
     pub guest_name: String,
     pub machine_config: MachineConfig,
@@ -123,11 +149,17 @@ pub struct VmConfig {
     pub global_config: HashMap<String, String>,
     pub numa_nodes: Vec<(String, String)>,
     pub incoming: Option<Incoming>,
+    /// NBD server exporting drives as the target of storage migration.
+    pub nbd_server: Option<Incoming>,
+    /// Tcp address of gdbstub for guest debugging.
+    pub gdb: Option<String>,
     pub vnc: Option<VncConfig>,
     pub display: Option<DisplayConfig>,
     pub camera_backend: HashMap<String, CameraDevConfig>,
     pub windows_emu_pid: Option<String>,
     pub smbios: SmbiosConfig,
+    pub tpmdevs: HashMap<String, TpmDevConfig>,
+    pub fw_cfg: Vec<FwCfgConfig>,
 }
 
 impl VmConfig {
@@ -219,6 +251,9 @@ impl VmConfig {
             "authz-simple" => {
                 self.add_saslauth(object_args)?;
             }
+            "secret" => {
+                self.add_secret(object_args)?;
+            }
             _ => {
                 bail!("Unknow object type: {:?}", &device_type);
             }
@@ -227,6 +262,98 @@ impl VmConfig {
         Ok(())
     }
 
+    /// Add an object created by qmp command `object-add`, whose properties are
+    /// the same as `-object` in cmdline.
+    ///
+    /// # Arguments
+    ///
+    /// * `qom_type` - The type of the object.
+    /// * `id` - The id of the object.
+    /// * `props` - Other properties of the object.
+    pub fn add_object_from_qmp(
+        &mut self,
+        qom_type: &str,
+        id: &str,
+        props: &BTreeMap<String, Value>,
+    ) -> Result<()> {
+        let mut object_args = format!("{},id={}", qom_type, id);
+        for (key, value) in props {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                Value::Bool(value) => if *value { "on" } else { "off" }.to_string(),
+                // Size of memory backend is in bytes in qmp.
+                Value::Number(size) if key == "size" => {
+                    let size = size.as_u64().unwrap_or_default();
+                    if size == 0 || size % M != 0 {
+                        bail!("Size {} of object {} is not aligned with 1M", size, id);
+                    }
+                    format!("{}M", size / M)
+                }
+                Value::Number(value) => value.to_string(),
+                Value::Array(list) if list.iter().all(Value::is_u64) => list
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<String>>()
+                    .join(":"),
+                _ => bail!("Invalid value {} of property {}", value, key),
+            };
+            if value.contains(',') || id.contains(',') {
+                bail!("Invalid value {} of property {}", value, key);
+            }
+            object_args += &format!(",{}={}", key, value);
+        }
+        self.add_object(&object_args)
+    }
+
+    /// Delete an object which is not referenced by any device, and return its type.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the object.
+    pub fn del_object(&mut self, id: &str) -> Result<&'static str> {
+        let refs: Vec<String> = ["iothread", "memdev", "rng"]
+            .iter()
+            .map(|key| format!("{}={}", key, id))
+            .collect();
+        if let Some((driver, _)) = self
+            .devices
+            .iter()
+            .chain(self.numa_nodes.iter())
+            .find(|(_, args)| args.split(',').any(|arg| refs.iter().any(|r| r == arg)))
+        {
+            bail!("Object {} is in use by {}", id, driver);
+        }
+        if let Some(vnc) = self.vnc.as_ref() {
+            if vnc.tls_creds == id || vnc.sasl_authz == id {
+                bail!("Object {} is in use by vnc", id);
+            }
+        }
+
+        if let Some(iothreads) = self.iothreads.as_mut() {
+            if let Some(index) = iothreads.iter().position(|t| t.id == id) {
+                iothreads.remove(index);
+                return Ok("iothread");
+            }
+        }
+        if let Some(zones) = self.machine_config.mem_config.mem_zones.as_mut() {
+            zones.retain(|zone| zone.id != id);
+        }
+        let object = &mut self.object;
+        if object.rng_object.remove(id).is_some() {
+            Ok("rng-random")
+        } else if object.mem_object.remove(id).is_some() {
+            Ok("memory-backend")
+        } else if object.tls_object.remove(id).is_some() {
+            Ok("tls-creds-x509")
+        } else if object.sasl_object.remove(id).is_some() {
+            Ok("authz-simple")
+        } else if object.secret_object.remove(id).is_some() {
+            Ok("secret")
+        } else {
+            bail!("Object {} not found", id)
+        }
+    }
+
     /// Add argument `global` to `VmConfig`.
     ///
     /// # Arguments
@@ -288,7 +415,7 @@ impl VmConfig {
                 ));
             }
         }
-        let file = open_file(path, read_only, direct)?;
+        let file = open_drive_file(path, read_only, direct)?;
         let (req_align, buf_align) = get_file_alignment(&file, direct);
         if req_align == 0 || buf_align == 0 {
             bail!(
@@ -591,14 +718,82 @@ impl From<ExBool> for bool {
 
 pub fn add_trace_events(config: &str) -> Result<()> {
     let mut cmd_parser = CmdParser::new("trace");
-    cmd_parser.push("events");
+    cmd_parser.push("events").push("backend");
     cmd_parser.get_parameters(config)?;
 
-    if let Some(file) = cmd_parser.get_value::<String>("events")? {
+    let backend = cmd_parser.get_value::<String>("backend")?;
+    let events = cmd_parser.get_value::<String>("events")?;
+    if backend.is_none() && events.is_none() {
+        bail!("trace: events file or backend must be set.");
+    }
+
+    if let Some(backend) = backend {
+        set_trace_backend(TraceBackend::from_str(&backend)?)?;
+    }
+    if let Some(file) = events {
         enable_trace_events(&file)?;
-        return Ok(());
     }
-    bail!("trace: events file must be set.");
+    Ok(())
+}
+
+/// Parse the argument `-seccomp-mode`, return the action for the syscalls not allowed.
+pub fn parse_seccomp_mode(mode: &str) -> Result<SeccompOpt> {
+    match mode {
+        "enforce" => Ok(SeccompOpt::Trap),
+        "log" => Ok(SeccompOpt::Log),
+        _ => Err(anyhow!(ConfigError::InvalidParam(
+            mode.to_string(),
+            "seccomp-mode".to_string()
+        ))),
+    }
+}
+
+const MAX_LOG_ROTATE_COUNT: u64 = 1024;
+
+/// Parse the argument `-log`, the id of VM in the returned config is left empty.
+pub fn parse_log_config(config: &str) -> Result<LogConfig> {
+    let mut cmd_parser = CmdParser::new("log");
+    cmd_parser
+        .push("format")
+        .push("rotate-size")
+        .push("rotate-time")
+        .push("rotate-count");
+    cmd_parser.get_parameters(config)?;
+
+    let mut log_config = LogConfig::default();
+    if let Some(format) = cmd_parser.get_value::<String>("format")? {
+        log_config.format = LogFormat::from_str(&format)?;
+    }
+    if let Some(size) = cmd_parser.get_value::<u64>("rotate-size")? {
+        if size == 0 {
+            return Err(anyhow!(ConfigError::IllegalValueUnilateral(
+                "rotate-size".to_string(),
+                true,
+                true,
+                1
+            )));
+        }
+        log_config.rotate_size = size
+            .checked_mul(M)
+            .and_then(|size| usize::try_from(size).ok())
+            .with_context(|| ConfigError::IntegerOverflow("rotate-size".to_string()))?;
+    }
+    if let Some(time) = cmd_parser.get_value::<String>("rotate-time")? {
+        log_config.rotate_time = LogRotateTime::from_str(&time)?;
+    }
+    if let Some(count) = cmd_parser.get_value::<u64>("rotate-count")? {
+        if !(2..=MAX_LOG_ROTATE_COUNT).contains(&count) {
+            return Err(anyhow!(ConfigError::IllegalValue(
+                "rotate-count".to_string(),
+                2,
+                true,
+                MAX_LOG_ROTATE_COUNT,
+                true
+            )));
+        }
+        log_config.rotate_count = count as u32;
+    }
+    Ok(log_config)
 }
 
 /// This struct is a wrapper for `usize`.
@@ -681,6 +876,31 @@ pub fn check_arg_nonexist(arg: Option<String>, name: &str, device: &str) -> Resu
     Ok(())
 }
 
+/// Open the drive file, the path with the format of `/dev/fdset/<fdset-id>` refers
+/// to the file descriptor in fd set added by qmp command `add-fd`.
+fn open_drive_file(path: &str, read_only: bool, direct: bool) -> Result<File> {
+    let fdset_id = match fdset_id_from_path(path) {
+        Some(id) => id,
+        None => return open_file(path, read_only, direct),
+    };
+    let fd = QmpChannel::dup_fdset_fd(fdset_id, read_only)
+        .with_context(|| format!("Failed to open the file for block {}", path))?;
+    // SAFETY: the file descriptor is duplicated from fd set and owned by the file.
+    let file = unsafe { File::from_raw_fd(fd) };
+    if direct {
+        // SAFETY: the file descriptor is valid.
+        let ret = unsafe {
+            let flags = libc::fcntl(file.as_raw_fd(), libc::F_GETFL);
+            libc::fcntl(file.as_raw_fd(), libc::F_SETFL, flags | libc::O_DIRECT)
+        };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to set O_DIRECT for block {}", path));
+        }
+    }
+    Ok(file)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -778,6 +998,38 @@ mod tests {
         assert!(add_trace_events("event=test_trace_events").is_err());
         assert!(add_trace_events("events").is_err());
         assert!(add_trace_events("events=test_trace_events").is_err());
+        assert!(add_trace_events("backend=stderr").is_err());
+        assert!(add_trace_events("backend=log,events=test_trace_events").is_err());
+    }
+
+    #[test]
+    fn test_parse_seccomp_mode() {
+        assert_eq!(parse_seccomp_mode("enforce").unwrap(), SeccompOpt::Trap);
+        assert_eq!(parse_seccomp_mode("log").unwrap(), SeccompOpt::Log);
+        assert!(parse_seccomp_mode("kill").is_err());
+        assert!(parse_seccomp_mode("").is_err());
+    }
+
+    #[test]
+    fn test_parse_log_config() {
+        let log_config = parse_log_config("format=json").unwrap();
+        assert_eq!(log_config.format, LogFormat::Json);
+        assert_eq!(log_config.rotate_time, LogRotateTime::Daily);
+
+        let log_config =
+            parse_log_config("format=text,rotate-size=10,rotate-time=hourly,rotate-count=3")
+                .unwrap();
+        assert_eq!(log_config.format, LogFormat::Text);
+        assert_eq!(log_config.rotate_size, 10 * 1024 * 1024);
+        assert_eq!(log_config.rotate_time, LogRotateTime::Hourly);
+        assert_eq!(log_config.rotate_count, 3);
+
+        assert!(parse_log_config("format=xml").is_err());
+        assert!(parse_log_config("rotate-size=0").is_err());
+        assert!(parse_log_config("rotate-time=weekly").is_err());
+        assert!(parse_log_config("rotate-count=1").is_err());
+        assert!(parse_log_config("rotate-count=1025").is_err());
+        assert!(parse_log_config("level=info").is_err());
     }
 
     #[test]
@@ -828,4 +1080,42 @@ mod tests {
         let res = vm_config.add_global_config("pcie-root-port.fast-unplug=1");
         assert!(res.is_err());
     }
+
+    #[test]
+    fn test_object_add_del() {
+        let mut vm_config = VmConfig::default();
+        let props: BTreeMap<String, Value> =
+            serde_json::from_str(r#"{"size": 1073741824, "share": true}"#).unwrap();
+        assert!(vm_config
+            .add_object_from_qmp("memory-backend-ram", "mem0", &props)
+            .is_ok());
+        let mem_zone = vm_config.object.mem_object.get("mem0").unwrap();
+        assert_eq!(mem_zone.size, 1 << 30);
+        assert!(mem_zone.share);
+
+        // Unaligned size or invalid value.
+        let props: BTreeMap<String, Value> = serde_json::from_str(r#"{"size": 1000}"#).unwrap();
+        assert!(vm_config
+            .add_object_from_qmp("memory-backend-ram", "mem1", &props)
+            .is_err());
+        let props: BTreeMap<String, Value> =
+            serde_json::from_str(r#"{"size": "1G,share=on"}"#).unwrap();
+        assert!(vm_config
+            .add_object_from_qmp("memory-backend-ram", "mem1", &props)
+            .is_err());
+
+        assert!(vm_config
+            .add_object_from_qmp("iothread", "iothread1", &BTreeMap::new())
+            .is_ok());
+        vm_config.devices.push((
+            "virtio-blk-pci".to_string(),
+            "virtio-blk-pci,id=drive0,iothread=iothread1".to_string(),
+        ));
+        assert!(vm_config.del_object("iothread1").is_err());
+        vm_config.devices.clear();
+        assert_eq!(vm_config.del_object("iothread1").unwrap(), "iothread");
+        assert_eq!(vm_config.del_object("mem0").unwrap(), "memory-backend");
+        assert!(vm_config.object.mem_object.get("mem0").is_none());
+        assert!(vm_config.del_object("mem0").is_err());
+    }
 }
@@ -21,7 +21,7 @@ use crate::config::{
     check_arg_too_long, CmdParser, ConfigCheck, ExBool, VmConfig, DEFAULT_VIRTQUEUE_SIZE,
     MAX_PATH_LENGTH, MAX_VIRTIO_QUEUE,
 };
-use crate::qmp::{qmp_schema, QmpChannel};
+use crate::qmp::{fdset_id_from_path, qmp_schema, QmpChannel};
 
 const MAC_ADDRESS_LENGTH: usize = 17;
 
@@ -29,6 +29,8 @@ const MAC_ADDRESS_LENGTH: usize = 17;
 pub const MAX_QUEUE_SIZE_NET: u16 = 4096;
 /// Max num of virtqueues.
 const MAX_QUEUE_PAIRS: usize = MAX_VIRTIO_QUEUE / 2;
+/// Max delay of coalesced notification in microseconds.
+const MAX_COALESCE_USECS: u32 = 100_000;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetDevcfg {
@@ -97,6 +99,16 @@ pub struct NetworkInterfaceConfig {
     pub socket_path: Option<String>,
     /// All queues of a net device have the same queue size now.
     pub queue_size: u16,
+    /// Whether the device works as the standby device of failover.
+    pub failover: bool,
+    /// Max number of completed packets before notifying guest, 0 means no coalescing.
+    pub coalesce_frames: u16,
+    /// Max delay of notifying guest in microseconds, 0 means notify at the end of each batch.
+    pub coalesce_usecs: u32,
+    /// Whether to offer the link status feature to guest.
+    pub status: bool,
+    /// Boot order of the device for network boot.
+    pub boot_index: Option<u8>,
 }
 
 impl Default for NetworkInterfaceConfig {
@@ -113,6 +125,11 @@ impl Default for NetworkInterfaceConfig {
             mq: false,
             socket_path: None,
             queue_size: DEFAULT_VIRTQUEUE_SIZE,
+            failover: false,
+            coalesce_frames: 0,
+            coalesce_usecs: 0,
+            status: true,
+            boot_index: None,
         }
     }
 }
@@ -138,6 +155,21 @@ impl ConfigCheck for NetworkInterfaceConfig {
             )));
         }
 
+        if self.failover {
+            if self.mac.is_none() {
+                return Err(anyhow!(ConfigError::FieldIsMissing(
+                    "mac".to_string(),
+                    "failover net".to_string()
+                )));
+            }
+            if self.vhost_type.is_some() {
+                return Err(anyhow!(ConfigError::InvalidParam(
+                    "failover".to_string(),
+                    "vhost net".to_string()
+                )));
+            }
+        }
+
         if self.queue_size < DEFAULT_VIRTQUEUE_SIZE || self.queue_size > MAX_QUEUE_SIZE_NET {
             return Err(anyhow!(ConfigError::IllegalValue(
                 "queue size of net device".to_string(),
@@ -152,6 +184,26 @@ impl ConfigCheck for NetworkInterfaceConfig {
             bail!("queue size of net device should be power of 2!");
         }
 
+        if self.coalesce_frames > self.queue_size {
+            return Err(anyhow!(ConfigError::IllegalValue(
+                "coalesce-frames of net device".to_string(),
+                0,
+                true,
+                self.queue_size as u64,
+                true
+            )));
+        }
+
+        if self.coalesce_usecs > MAX_COALESCE_USECS {
+            return Err(anyhow!(ConfigError::IllegalValue(
+                "coalesce-usecs of net device".to_string(),
+                0,
+                true,
+                MAX_COALESCE_USECS as u64,
+                true
+            )));
+        }
+
         Ok(())
     }
 }
@@ -265,7 +317,12 @@ pub fn parse_net(vm_config: &mut VmConfig, net_config: &str) -> Result<NetworkIn
         .push("multifunction")
         .push("mac")
         .push("iothread")
-        .push("queue-size");
+        .push("queue-size")
+        .push("failover")
+        .push("coalesce-frames")
+        .push("coalesce-usecs")
+        .push("status")
+        .push("bootindex");
 
     cmd_parser.parse(net_config)?;
     pci_args_check(&cmd_parser)?;
@@ -284,6 +341,25 @@ pub fn parse_net(vm_config: &mut VmConfig, net_config: &str) -> Result<NetworkIn
     if let Some(queue_size) = cmd_parser.get_value::<u16>("queue-size")? {
         netdevinterfacecfg.queue_size = queue_size;
     }
+    if let Some(failover) = cmd_parser.get_value::<ExBool>("failover")? {
+        netdevinterfacecfg.failover = failover.inner;
+    }
+    if let Some(frames) = cmd_parser.get_value::<u16>("coalesce-frames")? {
+        netdevinterfacecfg.coalesce_frames = frames;
+    }
+    if let Some(usecs) = cmd_parser.get_value::<u32>("coalesce-usecs")? {
+        netdevinterfacecfg.coalesce_usecs = usecs;
+    }
+    if let Some(status) = cmd_parser.get_value::<ExBool>("status")? {
+        netdevinterfacecfg.status = status.inner;
+    } else if let Some(status) = vm_config
+        .machine_config
+        .mach_version
+        .compat_prop("virtio-net", "status")
+    {
+        netdevinterfacecfg.status = status == "on";
+    }
+    netdevinterfacecfg.boot_index = cmd_parser.get_value::<u8>("bootindex")?;
 
     if let Some(netcfg) = &vm_config.netdevs.remove(&netdev) {
         netdevinterfacecfg.id = netid;
@@ -306,6 +382,8 @@ pub fn parse_net(vm_config: &mut VmConfig, net_config: &str) -> Result<NetworkIn
 fn get_netdev_fd(fd_name: &str) -> Result<RawFd> {
     if let Some(fd) = QmpChannel::get_fd(fd_name) {
         Ok(fd)
+    } else if let Some(fdset_id) = fdset_id_from_path(fd_name) {
+        QmpChannel::dup_fdset_fd(fdset_id, false)
     } else {
         // try to convert string to RawFd
         let fd_num = fd_name
@@ -493,6 +571,22 @@ impl VmConfig {
             device_info = format!("{},mq={}", device_info, mq);
         }
 
+        if args.failover == Some(true) {
+            device_info = format!("{},failover=on", device_info);
+        }
+
+        if let Some(frames) = args.coalesce_frames {
+            device_info = format!("{},coalesce-frames={}", device_info, frames);
+        }
+
+        if let Some(usecs) = args.coalesce_usecs {
+            device_info = format!("{},coalesce-usecs={}", device_info, usecs);
+        }
+
+        if let Some(boot_index) = args.boot_index {
+            device_info = format!("{},bootindex={}", device_info, boot_index);
+        }
+
         self.devices.push((args.driver.clone(), device_info));
     }
 }
@@ -699,6 +793,89 @@ mod tests {
         assert!(net_cfg_res.is_err());
     }
 
+    #[test]
+    fn test_bootindex_network_config_cmdline_parser() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_netdev("tap,id=eth1,ifname=tap1").is_ok());
+        let net_cfg = "virtio-net-pci,id=net1,netdev=eth1,bus=pcie.0,addr=0x1";
+        let network_configs = parse_net(&mut vm_config, net_cfg).unwrap();
+        assert_eq!(network_configs.boot_index, None);
+
+        assert!(vm_config.add_netdev("tap,id=eth2,ifname=tap2").is_ok());
+        let net_cfg = "virtio-net-pci,id=net2,netdev=eth2,bus=pcie.0,addr=0x2,bootindex=2";
+        let network_configs = parse_net(&mut vm_config, net_cfg).unwrap();
+        assert_eq!(network_configs.boot_index, Some(2));
+
+        assert!(vm_config.add_netdev("tap,id=eth3,ifname=tap3").is_ok());
+        let net_cfg = "virtio-net-pci,id=net3,netdev=eth3,bus=pcie.0,addr=0x3,bootindex=256";
+        assert!(parse_net(&mut vm_config, net_cfg).is_err());
+    }
+
+    #[test]
+    fn test_failover_network_config_cmdline_parser() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_netdev("tap,id=eth1,ifname=tap1").is_ok());
+        let net_cfg = "virtio-net-pci,id=net1,netdev=eth1,bus=pcie.0,addr=0x1,mac=12:34:56:78:9A:BC,failover=on";
+        let network_configs = parse_net(&mut vm_config, net_cfg).unwrap();
+        assert!(network_configs.failover);
+
+        // Failover requires mac address.
+        assert!(vm_config.add_netdev("tap,id=eth2,ifname=tap2").is_ok());
+        let net_cfg = "virtio-net-pci,id=net2,netdev=eth2,bus=pcie.0,addr=0x2,failover=on";
+        assert!(parse_net(&mut vm_config, net_cfg).is_err());
+
+        // Failover is not supported by vhost net.
+        assert!(vm_config
+            .add_netdev("tap,id=eth3,ifname=tap3,vhost=on")
+            .is_ok());
+        let net_cfg = "virtio-net-pci,id=net3,netdev=eth3,bus=pcie.0,addr=0x3,mac=12:34:56:78:9A:BC,failover=on";
+        assert!(parse_net(&mut vm_config, net_cfg).is_err());
+    }
+
+    #[test]
+    fn test_coalesce_network_config_cmdline_parser() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_netdev("tap,id=eth1,ifname=tap1").is_ok());
+        let net_cfg =
+            "virtio-net-pci,id=net1,netdev=eth1,bus=pcie.0,addr=0x1,coalesce-frames=32,coalesce-usecs=50";
+        let network_configs = parse_net(&mut vm_config, net_cfg).unwrap();
+        assert_eq!(network_configs.coalesce_frames, 32);
+        assert_eq!(network_configs.coalesce_usecs, 50);
+
+        // Coalesced frames can't exceed queue size.
+        assert!(vm_config.add_netdev("tap,id=eth2,ifname=tap2").is_ok());
+        let net_cfg = "virtio-net-pci,id=net2,netdev=eth2,bus=pcie.0,addr=0x2,coalesce-frames=257";
+        assert!(parse_net(&mut vm_config, net_cfg).is_err());
+
+        // Coalescing delay is out of range.
+        assert!(vm_config.add_netdev("tap,id=eth3,ifname=tap3").is_ok());
+        let net_cfg =
+            "virtio-net-pci,id=net3,netdev=eth3,bus=pcie.0,addr=0x3,coalesce-usecs=100001";
+        assert!(parse_net(&mut vm_config, net_cfg).is_err());
+    }
+
+    #[test]
+    fn test_status_network_config_cmdline_parser() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_netdev("tap,id=eth1,ifname=tap1").is_ok());
+        let net_cfg = "virtio-net-pci,id=net1,netdev=eth1,bus=pcie.0,addr=0x1";
+        let network_configs = parse_net(&mut vm_config, net_cfg).unwrap();
+        assert!(network_configs.status);
+
+        // Older machine versions don't offer link status by default.
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.add_machine("microvm-2.2").is_ok());
+        assert!(vm_config.add_netdev("tap,id=eth1,ifname=tap1").is_ok());
+        let net_cfg = "virtio-net-pci,id=net1,netdev=eth1,bus=pcie.0,addr=0x1";
+        let network_configs = parse_net(&mut vm_config, net_cfg).unwrap();
+        assert!(!network_configs.status);
+
+        assert!(vm_config.add_netdev("tap,id=eth2,ifname=tap2").is_ok());
+        let net_cfg = "virtio-net-pci,id=net2,netdev=eth2,bus=pcie.0,addr=0x2,status=on";
+        let network_configs = parse_net(&mut vm_config, net_cfg).unwrap();
+        assert!(network_configs.status);
+    }
+
     #[test]
     fn test_netdev_config_check() {
         let mut netdev_conf = NetDevcfg::default();
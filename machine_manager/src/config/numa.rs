@@ -12,11 +12,12 @@
 
 use std::cmp::max;
 use std::collections::{BTreeMap, HashSet};
+use std::str::FromStr;
 
 use anyhow::{anyhow, bail, Context, Result};
 
 use super::error::ConfigError;
-use crate::config::{CmdParser, IntegerList, VmConfig, MAX_NODES};
+use crate::config::{memory_unit_conversion, CmdParser, IntegerList, VmConfig, M, MAX_NODES};
 
 const MIN_NUMA_DISTANCE: u8 = 10;
 
@@ -26,6 +27,52 @@ pub struct NumaDistance {
     pub distance: u8,
 }
 
+/// Data type of the memory latency and bandwidth information reported by ACPI HMAT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HmatDataType {
+    AccessLatency = 0,
+    ReadLatency = 1,
+    WriteLatency = 2,
+    AccessBandwidth = 3,
+    ReadBandwidth = 4,
+    WriteBandwidth = 5,
+}
+
+impl HmatDataType {
+    pub fn is_latency(&self) -> bool {
+        matches!(
+            self,
+            HmatDataType::AccessLatency | HmatDataType::ReadLatency | HmatDataType::WriteLatency
+        )
+    }
+}
+
+impl FromStr for HmatDataType {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "access-latency" => Ok(HmatDataType::AccessLatency),
+            "read-latency" => Ok(HmatDataType::ReadLatency),
+            "write-latency" => Ok(HmatDataType::WriteLatency),
+            "access-bandwidth" => Ok(HmatDataType::AccessBandwidth),
+            "read-bandwidth" => Ok(HmatDataType::ReadBandwidth),
+            "write-bandwidth" => Ok(HmatDataType::WriteBandwidth),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The memory latency or bandwidth from the initiator node to the target node.
+#[derive(Debug)]
+pub struct HmatLbConfig {
+    pub initiator: u32,
+    pub target: u32,
+    pub data_type: HmatDataType,
+    /// Latency in nanoseconds, or bandwidth in MB/s.
+    pub value: u64,
+}
+
 #[derive(Default, Debug)]
 pub struct NumaConfig {
     pub numa_id: u32,
@@ -41,6 +88,9 @@ pub struct NumaNode {
     pub distances: BTreeMap<u32, u8>,
     pub size: u64,
     pub mem_dev: String,
+    /// Memory latency or bandwidth from this node to the target node, indexed
+    /// by data type and target node.
+    pub hmat_lb: BTreeMap<(HmatDataType, u32), u64>,
 }
 
 pub type NumaNodes = BTreeMap<u32, NumaNode>;
@@ -84,6 +134,19 @@ pub fn complete_numa_node(numa_nodes: &mut NumaNodes, nr_cpus: u8, mem_size: u64
         }
     }
 
+    // The distance between two nodes is symmetric if only one direction is specified.
+    let mut reverse_distances = Vec::new();
+    for (src, node) in numa_nodes.iter() {
+        for (dst, distance) in node.distances.iter() {
+            reverse_distances.push((*dst, *src, *distance));
+        }
+    }
+    for (src, dst, distance) in reverse_distances {
+        if let Some(node) = numa_nodes.get_mut(&src) {
+            node.distances.entry(dst).or_insert(distance);
+        }
+    }
+
     if total_ram_size != mem_size {
         bail!(
             "Total memory {} of NUMA nodes is not equals to memory size {}",
@@ -231,6 +294,83 @@ pub fn parse_numa_distance(numa_dist: &str) -> Result<(u32, NumaDistance)> {
     Ok((numa_id, dist))
 }
 
+/// Get the id of NUMA node from parameter `name`.
+fn get_numa_id(cmd_parser: &CmdParser, name: &str) -> Result<u32> {
+    let id = cmd_parser
+        .get_value::<u32>(name)?
+        .with_context(|| ConfigError::FieldIsMissing(name.to_string(), "numa".to_string()))?;
+    if id >= MAX_NODES {
+        return Err(anyhow!(ConfigError::IllegalValue(
+            name.to_string(),
+            0,
+            true,
+            MAX_NODES as u64,
+            false,
+        )));
+    }
+    Ok(id)
+}
+
+/// Parse the NUMA memory latency and bandwidth parameters for HMAT.
+///
+/// # Arguments
+///
+/// * `hmat_lb` - The NUMA memory latency and bandwidth configuration.
+pub fn parse_hmat_lb(hmat_lb: &str) -> Result<HmatLbConfig> {
+    let mut cmd_parser = CmdParser::new("numa");
+    cmd_parser
+        .push("")
+        .push("initiator")
+        .push("target")
+        .push("hierarchy")
+        .push("data-type")
+        .push("latency")
+        .push("bandwidth");
+    cmd_parser.parse(hmat_lb)?;
+
+    let initiator = get_numa_id(&cmd_parser, "initiator")?;
+    let target = get_numa_id(&cmd_parser, "target")?;
+    if let Some(hierarchy) = cmd_parser.get_value::<String>("hierarchy")? {
+        // The memory side caches are not emulated.
+        if hierarchy != "memory" {
+            bail!(
+                "Unsupported hierarchy {} of hmat-lb, only memory is supported",
+                hierarchy
+            );
+        }
+    }
+    let data_type = cmd_parser
+        .get_value::<String>("data-type")?
+        .with_context(|| {
+            ConfigError::FieldIsMissing("data-type".to_string(), "numa".to_string())
+        })?;
+    let data_type = HmatDataType::from_str(&data_type).map_err(|_| {
+        anyhow!(ConfigError::InvalidParam(
+            data_type,
+            "data-type".to_string()
+        ))
+    })?;
+
+    let latency = cmd_parser.get_value::<u64>("latency")?;
+    let bandwidth = cmd_parser.get_value::<String>("bandwidth")?;
+    let value = match (data_type.is_latency(), latency, bandwidth) {
+        (true, Some(latency), None) => latency,
+        (false, None, Some(bandwidth)) => memory_unit_conversion(&bandwidth)? / M,
+        (true, ..) => bail!("Only latency should be set for hmat-lb with latency data-type"),
+        (false, ..) => bail!("Only bandwidth should be set for hmat-lb with bandwidth data-type"),
+    };
+    if value == 0 {
+        bail!("The latency or bandwidth of hmat-lb should be positive");
+    }
+
+    Ok(HmatLbConfig {
+        initiator,
+        target,
+        data_type,
+        value,
+    })
+}
+
 impl VmConfig {
     /// Add the NUMA node config to vm config.
     ///
@@ -313,6 +453,50 @@ mod tests {
         assert!(parse_numa_distance(numa.1.as_str()).is_err());
     }
 
+    #[test]
+    fn test_parse_hmat_lb() {
+        let lb = parse_hmat_lb(
+            "hmat-lb,initiator=0,target=1,hierarchy=memory,data-type=access-latency,latency=20",
+        )
+        .unwrap();
+        assert_eq!(lb.initiator, 0);
+        assert_eq!(lb.target, 1);
+        assert_eq!(lb.data_type, HmatDataType::AccessLatency);
+        assert_eq!(lb.value, 20);
+
+        let lb =
+            parse_hmat_lb("hmat-lb,initiator=1,target=1,data-type=read-bandwidth,bandwidth=2G")
+                .unwrap();
+        assert_eq!(lb.data_type, HmatDataType::ReadBandwidth);
+        assert_eq!(lb.value, 2048);
+
+        // Unsupported hierarchy or data type.
+        assert!(parse_hmat_lb(
+            "hmat-lb,initiator=0,target=1,hierarchy=first-level,data-type=access-latency,latency=5"
+        )
+        .is_err());
+        assert!(parse_hmat_lb("hmat-lb,initiator=0,target=1,data-type=latency,latency=5").is_err());
+        // Value doesn't match the data type.
+        assert!(parse_hmat_lb(
+            "hmat-lb,initiator=0,target=1,data-type=access-latency,bandwidth=1G"
+        )
+        .is_err());
+        assert!(parse_hmat_lb(
+            "hmat-lb,initiator=0,target=1,data-type=write-bandwidth,latency=5,bandwidth=1G"
+        )
+        .is_err());
+        assert!(
+            parse_hmat_lb("hmat-lb,initiator=0,target=1,data-type=access-latency,latency=0")
+                .is_err()
+        );
+        // Missing or invalid node.
+        assert!(parse_hmat_lb("hmat-lb,target=1,data-type=access-latency,latency=5").is_err());
+        assert!(
+            parse_hmat_lb("hmat-lb,initiator=0,target=128,data-type=access-latency,latency=5")
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_check_numa_nodes() {
         let nr_cpus = 4;
@@ -322,12 +506,14 @@ mod tests {
             cpus: vec![0, 1],
             distances: Default::default(),
             size: 1073741824,
+            hmat_lb: Default::default(),
             mem_dev: String::from("numa_node1"),
         };
         let numa_node2 = NumaNode {
             cpus: vec![2, 3],
             distances: Default::default(),
             size: 1073741824,
+            hmat_lb: Default::default(),
             mem_dev: String::from("numa_node2"),
         };
 
@@ -340,6 +526,7 @@ mod tests {
             cpus: vec![2],
             distances: Default::default(),
             size: 1073741824,
+            hmat_lb: Default::default(),
             mem_dev: String::from("numa_node3"),
         };
         numa_nodes.remove(&1);
@@ -350,6 +537,7 @@ mod tests {
             cpus: vec![2, 3, 4],
             distances: Default::default(),
             size: 1073741824,
+            hmat_lb: Default::default(),
             mem_dev: String::from("numa_node4"),
         };
         numa_nodes.remove(&1);
@@ -360,6 +548,7 @@ mod tests {
             cpus: vec![3, 4],
             distances: Default::default(),
             size: 1073741824,
+            hmat_lb: Default::default(),
             mem_dev: String::from("numa_node5"),
         };
         numa_nodes.remove(&1);
@@ -370,6 +559,7 @@ mod tests {
             cpus: vec![0, 1],
             distances: Default::default(),
             size: 1073741824,
+            hmat_lb: Default::default(),
             mem_dev: String::from("numa_node6"),
         };
         numa_nodes.remove(&1);
@@ -380,10 +570,53 @@ mod tests {
             cpus: vec![2, 3],
             distances: Default::default(),
             size: 2147483648,
+            hmat_lb: Default::default(),
             mem_dev: String::from("numa_node7"),
         };
         numa_nodes.remove(&1);
         numa_nodes.insert(1, numa_node7);
         assert!(complete_numa_node(&mut numa_nodes, nr_cpus, mem_size).is_err());
     }
+
+    #[test]
+    fn test_complete_numa_distance() {
+        let mut numa_nodes = BTreeMap::new();
+        numa_nodes.insert(
+            0,
+            NumaNode {
+                cpus: vec![0, 1],
+                distances: BTreeMap::from([(2, 25), (3, 30)]),
+                size: 1073741824,
+                hmat_lb: Default::default(),
+                mem_dev: String::from("mem0"),
+            },
+        );
+        numa_nodes.insert(
+            2,
+            NumaNode {
+                cpus: vec![2],
+                distances: Default::default(),
+                size: 1073741824,
+                hmat_lb: Default::default(),
+                mem_dev: String::from("mem2"),
+            },
+        );
+        numa_nodes.insert(
+            3,
+            NumaNode {
+                cpus: vec![3],
+                distances: BTreeMap::from([(0, 35)]),
+                size: 1073741824,
+                hmat_lb: Default::default(),
+                mem_dev: String::from("mem3"),
+            },
+        );
+        assert!(complete_numa_node(&mut numa_nodes, 4, 3221225472).is_ok());
+
+        // Reverse distance is filled if not specified.
+        assert_eq!(numa_nodes.get(&2).unwrap().distances.get(&0), Some(&25));
+        // Specified distance is kept.
+        assert_eq!(numa_nodes.get(&3).unwrap().distances.get(&0), Some(&35));
+        assert_eq!(numa_nodes.get(&0).unwrap().distances.get(&3), Some(&30));
+    }
 }
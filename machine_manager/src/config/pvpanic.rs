@@ -0,0 +1,54 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use anyhow::Result;
+
+use crate::config::{check_arg_too_long, CmdParser, ConfigCheck};
+
+/// Config struct for pvpanic device.
+#[derive(Debug, Clone, Default)]
+pub struct PvPanicConfig {
+    pub id: String,
+}
+
+impl ConfigCheck for PvPanicConfig {
+    fn check(&self) -> Result<()> {
+        check_arg_too_long(&self.id, "id")
+    }
+}
+
+pub fn parse_pvpanic(cfg_args: &str) -> Result<PvPanicConfig> {
+    let mut cmd_parser = CmdParser::new("pvpanic");
+    cmd_parser.push("").push("id");
+    cmd_parser.parse(cfg_args)?;
+
+    let config = PvPanicConfig {
+        id: cmd_parser.get_value::<String>("id")?.unwrap_or_default(),
+    };
+    config.check()?;
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pvpanic() {
+        let config = parse_pvpanic("pvpanic,id=pvpanic0").unwrap();
+        assert_eq!(config.id, "pvpanic0");
+        let config = parse_pvpanic("pvpanic").unwrap();
+        assert_eq!(config.id, "");
+        assert!(parse_pvpanic("pvpanic,id=pvpanic0,ioport=0x505").is_err());
+    }
+}
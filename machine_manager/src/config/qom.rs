@@ -0,0 +1,298 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::config::{MachineType, VmConfig};
+use crate::qmp::qmp_schema::{json_type_name, Any};
+
+/// The qom path of devices with id.
+const QOM_PERIPHERAL_PATH: &str = "/machine/peripheral";
+
+/// Property of the object in qom tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QomProperty {
+    pub name: String,
+    pub prop_type: String,
+    pub value: Any,
+}
+
+impl QomProperty {
+    fn new(name: &str, value: Any) -> Self {
+        QomProperty {
+            name: name.to_string(),
+            prop_type: json_type_name(&value).to_string(),
+            value,
+        }
+    }
+
+    fn child(name: &str, child_type: &str, parent: &str) -> Self {
+        let path = if parent == "/" {
+            format!("/{}", name)
+        } else {
+            format!("{}/{}", parent, name)
+        };
+        QomProperty {
+            name: name.to_string(),
+            prop_type: format!("child<{}>", child_type),
+            value: Any::String(path),
+        }
+    }
+}
+
+/// Get the id of device from its qom path, such as "/machine/peripheral/<id>".
+pub fn qom_peripheral_id(path: &str) -> Option<&str> {
+    path.trim_end_matches('/')
+        .strip_prefix(QOM_PERIPHERAL_PATH)
+        .and_then(|id| id.strip_prefix('/'))
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+}
+
+/// Get the properties of config object, fields which are not set are skipped.
+fn config_properties<T: Serialize>(obj_type: &str, config: &T) -> Vec<QomProperty> {
+    let mut props = vec![QomProperty::new("type", Any::from(obj_type))];
+    if let Ok(Any::Object(fields)) = serde_json::to_value(config) {
+        for (name, value) in fields {
+            if !value.is_null() {
+                props.push(QomProperty::new(&name, value));
+            }
+        }
+    }
+    props
+}
+
+impl VmConfig {
+    /// Get the properties of the object at qom path. The qom tree is built from the
+    /// configuration of vm, which contains machine, peripheral devices with id, objects
+    /// and backends.
+    pub fn qom_properties(&self, path: &str) -> Result<Vec<QomProperty>> {
+        let path = match path.trim_end_matches('/') {
+            "" => "/",
+            p => p,
+        };
+        let mut props = Vec::new();
+        match path {
+            "/" => {
+                props.push(QomProperty::child("machine", "machine", path));
+                props.push(QomProperty::child("objects", "container", path));
+                props.push(QomProperty::child("backends", "container", path));
+            }
+            "/machine" => {
+                let mach_type = match self.machine_config.mach_type {
+                    MachineType::None => "none",
+                    MachineType::MicroVm => "microvm",
+                    #[cfg(target_arch = "x86_64")]
+                    MachineType::StandardVm => "q35",
+                    #[cfg(target_arch = "aarch64")]
+                    MachineType::StandardVm => "virt",
+                };
+                props.push(QomProperty::new("type", Any::from(mach_type)));
+                props.push(QomProperty::new(
+                    "smp",
+                    Any::from(self.machine_config.nr_cpus),
+                ));
+                props.push(QomProperty::new(
+                    "memory",
+                    Any::from(self.machine_config.mem_config.mem_size),
+                ));
+                props.push(QomProperty::child("peripheral", "container", path));
+            }
+            QOM_PERIPHERAL_PATH => {
+                for (driver, args) in &self.devices {
+                    if let Some(id) = args.split(',').find_map(|arg| arg.strip_prefix("id=")) {
+                        props.push(QomProperty::child(id, driver, path));
+                    }
+                }
+            }
+            "/objects" => {
+                if let Some(iothreads) = &self.iothreads {
+                    for iothread in iothreads {
+                        props.push(QomProperty::child(&iothread.id, "iothread", path));
+                    }
+                }
+                for id in self.object.rng_object.keys() {
+                    props.push(QomProperty::child(id, "rng-random", path));
+                }
+                for id in self.object.mem_object.keys() {
+                    props.push(QomProperty::child(id, "memory-backend", path));
+                }
+                for id in self.object.tls_object.keys() {
+                    props.push(QomProperty::child(id, "tls-creds-x509", path));
+                }
+                for id in self.object.sasl_object.keys() {
+                    props.push(QomProperty::child(id, "authz-simple", path));
+                }
+                for id in self.object.secret_object.keys() {
+                    props.push(QomProperty::child(id, "secret", path));
+                }
+            }
+            "/backends" => {
+                props.push(QomProperty::child("drive", "container", path));
+                props.push(QomProperty::child("netdev", "container", path));
+                props.push(QomProperty::child("chardev", "container", path));
+            }
+            "/backends/drive" => {
+                for id in self.drives.keys() {
+                    props.push(QomProperty::child(id, "drive", path));
+                }
+            }
+            "/backends/netdev" => {
+                for id in self.netdevs.keys() {
+                    props.push(QomProperty::child(id, "netdev", path));
+                }
+            }
+            "/backends/chardev" => {
+                for id in self.chardev.keys() {
+                    props.push(QomProperty::child(id, "chardev", path));
+                }
+            }
+            _ => return self.qom_object_properties(path),
+        }
+        Ok(props)
+    }
+
+    fn qom_object_properties(&self, path: &str) -> Result<Vec<QomProperty>> {
+        if let Some(id) = qom_peripheral_id(path) {
+            let id_arg = format!("id={}", id);
+            if let Some((driver, args)) = self
+                .devices
+                .iter()
+                .find(|dev| dev.1.split(',').any(|arg| arg == id_arg))
+            {
+                let mut props = vec![QomProperty::new("type", Any::from(driver.as_str()))];
+                for arg in args.split(',').skip(1) {
+                    // Flag without value, such as "multifunction", is regarded as "on".
+                    let (name, value) = arg.split_once('=').unwrap_or((arg, "on"));
+                    props.push(QomProperty::new(name, Any::from(value)));
+                }
+                return Ok(props);
+            }
+        } else if let Some((parent, id)) = path.rsplit_once('/') {
+            let props = match parent {
+                "/objects" => {
+                    if let Some(iothread) = self
+                        .iothreads
+                        .as_ref()
+                        .and_then(|iothreads| iothreads.iter().find(|t| t.id == id))
+                    {
+                        Some(config_properties("iothread", iothread))
+                    } else if let Some(rng) = self.object.rng_object.get(id) {
+                        Some(config_properties("rng-random", rng))
+                    } else if let Some(mem) = self.object.mem_object.get(id) {
+                        Some(config_properties("memory-backend", mem))
+                    } else if let Some(tls) = self.object.tls_object.get(id) {
+                        Some(config_properties("tls-creds-x509", tls))
+                    } else if let Some(sasl) = self.object.sasl_object.get(id) {
+                        Some(config_properties("authz-simple", sasl))
+                    } else {
+                        self.object
+                            .secret_object
+                            .get(id)
+                            .map(|secret| config_properties("secret", secret))
+                    }
+                }
+                "/backends/drive" => self
+                    .drives
+                    .get(id)
+                    .map(|drive| config_properties("drive", drive)),
+                "/backends/netdev" => self
+                    .netdevs
+                    .get(id)
+                    .map(|netdev| config_properties("netdev", netdev)),
+                "/backends/chardev" => self
+                    .chardev
+                    .get(id)
+                    .map(|chardev| config_properties("chardev", chardev)),
+                _ => None,
+            };
+            if let Some(props) = props {
+                return Ok(props);
+            }
+        }
+        bail!("Device '{}' not found", path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qom_properties() {
+        let mut vm_config = VmConfig::default();
+        vm_config.add_object("iothread,id=iothread0").unwrap();
+        vm_config
+            .add_netdev("tap,id=netdevid0,ifname=tap0")
+            .unwrap();
+        vm_config.devices.push((
+            "virtio-net-pci".to_string(),
+            "virtio-net-pci,id=net0,netdev=netdevid0,bus=pcie.0,addr=0x2".to_string(),
+        ));
+        vm_config
+            .devices
+            .push(("pcie-root-port".to_string(), "pcie-root-port".to_string()));
+
+        let root = vm_config.qom_properties("/").unwrap();
+        assert_eq!(root.len(), 3);
+        assert_eq!(root[0].prop_type, "child<machine>");
+        assert_eq!(root[0].value, Any::from("/machine"));
+
+        // Only devices with id are listed.
+        let peripheral = vm_config.qom_properties("/machine/peripheral/").unwrap();
+        assert_eq!(
+            peripheral,
+            vec![QomProperty::child(
+                "net0",
+                "virtio-net-pci",
+                "/machine/peripheral"
+            )]
+        );
+
+        let net = vm_config
+            .qom_properties("/machine/peripheral/net0")
+            .unwrap();
+        assert_eq!(
+            net[0],
+            QomProperty::new("type", Any::from("virtio-net-pci"))
+        );
+        assert!(net.contains(&QomProperty::new("netdev", Any::from("netdevid0"))));
+        assert!(net.contains(&QomProperty::new("addr", Any::from("0x2"))));
+
+        let objects = vm_config.qom_properties("/objects").unwrap();
+        assert_eq!(objects[0].prop_type, "child<iothread>");
+        let iothread = vm_config.qom_properties("/objects/iothread0").unwrap();
+        assert!(iothread.contains(&QomProperty::new("id", Any::from("iothread0"))));
+
+        let netdev = vm_config
+            .qom_properties("/backends/netdev/netdevid0")
+            .unwrap();
+        assert_eq!(netdev[0], QomProperty::new("type", Any::from("netdev")));
+        assert!(netdev.iter().any(|prop| prop.name == "ifname"));
+
+        assert!(vm_config
+            .qom_properties("/machine/peripheral/net1")
+            .is_err());
+        assert!(vm_config.qom_properties("/objects/iothread1").is_err());
+        assert!(vm_config.qom_properties("/unknown").is_err());
+    }
+
+    #[test]
+    fn test_qom_peripheral_id() {
+        assert_eq!(qom_peripheral_id("/machine/peripheral/net0"), Some("net0"));
+        assert_eq!(qom_peripheral_id("/machine/peripheral/net0/"), Some("net0"));
+        assert_eq!(qom_peripheral_id("/machine/peripheral"), None);
+        assert_eq!(qom_peripheral_id("/machine/peripheral/"), None);
+        assert_eq!(qom_peripheral_id("/machine/peripheral-anon"), None);
+        assert_eq!(qom_peripheral_id("/machine/peripheral/net0/x"), None);
+    }
+}
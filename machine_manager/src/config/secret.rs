@@ -0,0 +1,109 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::fmt;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{
+    ConfigError, {CmdParser, VmConfig},
+};
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SecretObjConfig {
+    /// Object Id.
+    pub id: String,
+    /// Content of the secret, which is neither printed nor serialized.
+    #[serde(skip)]
+    pub data: String,
+}
+
+impl fmt::Debug for SecretObjConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretObjConfig")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl VmConfig {
+    pub fn add_secret(&mut self, secret_config: &str) -> Result<()> {
+        let mut cmd_parser = CmdParser::new("secret");
+        cmd_parser
+            .push("")
+            .push("id")
+            .push("data")
+            .push("file")
+            .push("format");
+        cmd_parser.parse(secret_config)?;
+
+        let id = cmd_parser
+            .get_value::<String>("id")?
+            .with_context(|| ConfigError::FieldIsMissing("id".to_string(), "secret".to_string()))?;
+        if let Some(format) = cmd_parser.get_value::<String>("format")? {
+            if format != "raw" {
+                bail!("Format {} of secret is not supported", format);
+            }
+        }
+        let data = match (
+            cmd_parser.get_value::<String>("data")?,
+            cmd_parser.get_value::<String>("file")?,
+        ) {
+            (Some(data), None) => data,
+            (None, Some(file)) => std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read secret from file {}", file))?,
+            _ => bail!(
+                "Only one of 'data' and 'file' should be set for secret {}",
+                id
+            ),
+        };
+
+        if self.object.secret_object.get(&id).is_some() {
+            return Err(anyhow!(ConfigError::IdRepeat("secret".to_string(), id)));
+        }
+        self.object
+            .secret_object
+            .insert(id.clone(), SecretObjConfig { id, data });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_secret() {
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config
+            .add_object("secret,id=sec0,data=letmein,format=raw")
+            .is_ok());
+        let secret = vm_config.object.secret_object.get("sec0").unwrap();
+        assert_eq!(secret.data, "letmein");
+        assert!(!format!("{:?}", secret).contains("letmein"));
+        assert!(!serde_json::to_string(secret).unwrap().contains("letmein"));
+
+        // Repeated id.
+        assert!(vm_config.add_object("secret,id=sec0,data=123").is_err());
+        // Unsupported format.
+        assert!(vm_config
+            .add_object("secret,id=sec1,data=123,format=base64")
+            .is_err());
+        // Data and file are both set or neither is set.
+        assert!(vm_config
+            .add_object("secret,id=sec1,data=123,file=/path/to/secret")
+            .is_err());
+        assert!(vm_config.add_object("secret,id=sec1").is_err());
+    }
+}
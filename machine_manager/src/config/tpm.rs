@@ -0,0 +1,141 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::error::ConfigError;
+use crate::config::{
+    check_arg_too_long, get_chardev_socket_path, CmdParser, ConfigCheck, VmConfig,
+};
+
+/// Config struct for TPM backend, only swtpm emulator is supported.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TpmDevConfig {
+    pub id: String,
+    /// Chardev of the swtpm control socket.
+    pub chardev: String,
+}
+
+impl ConfigCheck for TpmDevConfig {
+    fn check(&self) -> Result<()> {
+        check_arg_too_long(&self.id, "tpmdev id")?;
+        check_arg_too_long(&self.chardev, "tpmdev chardev")
+    }
+}
+
+/// Config struct for TPM device.
+#[derive(Debug, Clone, Default)]
+pub struct TpmConfig {
+    pub id: String,
+    /// Path of the swtpm control socket.
+    pub socket_path: String,
+}
+
+impl VmConfig {
+    /// Add TPM backend to `VmConfig`.
+    pub fn add_tpmdev(&mut self, tpmdev_config: &str) -> Result<()> {
+        let mut cmd_parser = CmdParser::new("tpmdev");
+        cmd_parser.push("").push("id").push("chardev");
+        cmd_parser.parse(tpmdev_config)?;
+
+        let backend = cmd_parser.get_value::<String>("")?.unwrap_or_default();
+        if backend != "emulator" {
+            bail!(
+                "Unsupported tpmdev backend {:?}, only emulator is supported",
+                backend
+            );
+        }
+        let id = cmd_parser.get_value::<String>("id")?.ok_or_else(|| {
+            anyhow!(ConfigError::FieldIsMissing(
+                "id".to_string(),
+                "tpmdev".to_string()
+            ))
+        })?;
+        let chardev = cmd_parser.get_value::<String>("chardev")?.ok_or_else(|| {
+            anyhow!(ConfigError::FieldIsMissing(
+                "chardev".to_string(),
+                "tpmdev".to_string()
+            ))
+        })?;
+        let tpmdev = TpmDevConfig { id, chardev };
+        tpmdev.check()?;
+
+        if self.tpmdevs.contains_key(&tpmdev.id) {
+            bail!(ConfigError::IdRepeat("tpmdev".to_string(), tpmdev.id));
+        }
+        self.tpmdevs.insert(tpmdev.id.clone(), tpmdev);
+        Ok(())
+    }
+}
+
+/// Parse the config of TPM device, the TPM backend and its chardev are consumed.
+///
+/// # Arguments
+///
+/// * `vm_config` - VmConfig struct reference.
+/// * `cfg_args` - Device configuration args.
+pub fn parse_tpm(vm_config: &mut VmConfig, cfg_args: &str) -> Result<TpmConfig> {
+    let mut cmd_parser = CmdParser::new("tpm-crb");
+    cmd_parser.push("").push("id").push("tpmdev");
+    cmd_parser.parse(cfg_args)?;
+
+    let id = cmd_parser
+        .get_value::<String>("id")?
+        .unwrap_or_else(|| "tpm0".to_string());
+    check_arg_too_long(&id, "id")?;
+    let tpmdev_id = cmd_parser.get_value::<String>("tpmdev")?.ok_or_else(|| {
+        anyhow!(ConfigError::FieldIsMissing(
+            "tpmdev".to_string(),
+            "tpm-crb".to_string()
+        ))
+    })?;
+    let tpmdev = vm_config
+        .tpmdevs
+        .remove(&tpmdev_id)
+        .with_context(|| format!("tpmdev {:?} not found", tpmdev_id))?;
+    let socket_path = get_chardev_socket_path(&tpmdev.chardev, vm_config)?;
+
+    Ok(TpmConfig { id, socket_path })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tpm() {
+        let mut vm_config = VmConfig::default();
+        vm_config
+            .add_chardev("socket,id=chrtpm,path=/tmp/swtpm-sock")
+            .unwrap();
+        assert!(vm_config
+            .add_tpmdev("passthrough,id=tpm0,chardev=chrtpm")
+            .is_err());
+        assert!(vm_config.add_tpmdev("emulator,chardev=chrtpm").is_err());
+        assert!(vm_config.add_tpmdev("emulator,id=tpm0").is_err());
+        assert!(vm_config
+            .add_tpmdev("emulator,id=tpm0,chardev=chrtpm")
+            .is_ok());
+        assert!(vm_config
+            .add_tpmdev("emulator,id=tpm0,chardev=chrtpm")
+            .is_err());
+
+        assert!(parse_tpm(&mut vm_config, "tpm-crb,id=tpm1").is_err());
+        assert!(parse_tpm(&mut vm_config, "tpm-crb,tpmdev=tpm1").is_err());
+        let config = parse_tpm(&mut vm_config, "tpm-crb,tpmdev=tpm0").unwrap();
+        assert_eq!(config.id, "tpm0");
+        assert_eq!(config.socket_path, "/tmp/swtpm-sock");
+        // Backend can only be used by one device.
+        assert!(parse_tpm(&mut vm_config, "tpm-crb,tpmdev=tpm0").is_err());
+    }
+}
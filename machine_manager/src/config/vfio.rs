@@ -11,19 +11,25 @@
 // See the Mulan PSL v2 for more details.
 
 use super::error::ConfigError;
-use crate::config::{check_arg_too_long, CmdParser, ConfigCheck};
-use anyhow::{anyhow, Result};
+use crate::config::{check_arg_too_long, CmdParser, ConfigCheck, ExBool, VmConfig};
+use crate::qmp::qmp_schema;
+use anyhow::{anyhow, bail, Result};
 #[derive(Default, Debug)]
 pub struct VfioConfig {
     pub sysfsdev: String,
     pub host: String,
     pub id: String,
+    /// Id of the standby virtio-net device, if the vfio device is the primary device of failover.
+    pub failover_pair_id: Option<String>,
 }
 
 impl ConfigCheck for VfioConfig {
     fn check(&self) -> Result<()> {
         check_arg_too_long(&self.host, "host")?;
         check_arg_too_long(&self.id, "id")?;
+        if let Some(pair_id) = &self.failover_pair_id {
+            check_arg_too_long(pair_id, "failover_pair_id")?;
+        }
 
         Ok(())
     }
@@ -38,7 +44,8 @@ pub fn parse_vfio(vfio_config: &str) -> Result<VfioConfig> {
         .push("id")
         .push("bus")
         .push("addr")
-        .push("multifunction");
+        .push("multifunction")
+        .push("failover_pair_id");
     cmd_parser.parse(vfio_config)?;
 
     let mut vfio: VfioConfig = VfioConfig::default();
@@ -67,11 +74,76 @@ pub fn parse_vfio(vfio_config: &str) -> Result<VfioConfig> {
     if let Some(id) = cmd_parser.get_value::<String>("id")? {
         vfio.id = id;
     }
+    vfio.failover_pair_id = cmd_parser.get_value::<String>("failover_pair_id")?;
     vfio.check()?;
 
     Ok(vfio)
 }
 
+impl VmConfig {
+    /// Add hot-plugged vfio device config to `VmConfig`.
+    pub fn add_vfio_device_config(&mut self, args: &qmp_schema::DeviceAddArgument) {
+        let mut device_info = format!("{},id={}", args.driver, args.id);
+
+        if let Some(host) = &args.host {
+            device_info = format!("{},host={}", device_info, host);
+        }
+
+        if let Some(sysfsdev) = &args.sysfsdev {
+            device_info = format!("{},sysfsdev={}", device_info, sysfsdev);
+        }
+
+        if let Some(pair_id) = &args.failover_pair_id {
+            device_info = format!("{},failover_pair_id={}", device_info, pair_id);
+        }
+
+        self.devices.push((args.driver.clone(), device_info));
+    }
+
+    /// Check that the standby device of failover pair exists and has failover enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair_id` - Id of the standby virtio-net device.
+    pub fn check_failover_pair(&self, pair_id: &str) -> Result<()> {
+        for (dev_type, dev_args) in self.devices.iter() {
+            if dev_type != "virtio-net-pci" {
+                continue;
+            }
+            let mut cmd_parser = CmdParser::new("virtio-net");
+            cmd_parser.push("").push("id").push("failover");
+            cmd_parser.get_parameters(dev_args)?;
+            if cmd_parser.get_value::<String>("id")?.as_deref() != Some(pair_id) {
+                continue;
+            }
+            let failover = cmd_parser
+                .get_value::<ExBool>("failover")?
+                .map_or(false, |f| f.inner);
+            if !failover {
+                bail!("Failover is not enabled on net device {}", pair_id);
+            }
+            return Ok(());
+        }
+        bail!("Failover standby net device {} not found", pair_id);
+    }
+
+    /// Get ids of the vfio devices which are the primary devices of failover.
+    pub fn get_failover_primaries(&self) -> Vec<String> {
+        let mut primaries = Vec::new();
+        for (dev_type, dev_args) in self.devices.iter() {
+            if dev_type != "vfio-pci" {
+                continue;
+            }
+            if let Ok(vfio) = parse_vfio(dev_args) {
+                if vfio.failover_pair_id.is_some() {
+                    primaries.push(vfio.id);
+                }
+            }
+        }
+        primaries
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -129,4 +201,34 @@ mod tests {
             "vfio-pci,host=0000:1a:00.3,id=net,bus=pcie.0,addr=0x1.0x2,multifunction=on";
         assert!(parse_vfio(vfio_cfg1).is_ok());
     }
+
+    #[test]
+    fn test_vfio_failover_config() {
+        let vfio_cfg =
+            "vfio-pci,host=0000:1a:00.3,id=hostnet0,bus=pcie.0,addr=0x2,failover_pair_id=net0";
+        let vfio_config = parse_vfio(vfio_cfg).unwrap();
+        assert_eq!(vfio_config.failover_pair_id, Some("net0".to_string()));
+
+        let mut vm_config = VmConfig::default();
+        assert!(vm_config.check_failover_pair("net0").is_err());
+        vm_config
+            .add_device(
+                "virtio-net-pci,id=net0,netdev=tap0,mac=12:34:56:78:9A:BC,bus=pcie.0,addr=0x1",
+            )
+            .unwrap();
+        // Failover is not enabled on net0.
+        assert!(vm_config.check_failover_pair("net0").is_err());
+
+        let mut vm_config = VmConfig::default();
+        vm_config
+            .add_device("virtio-net-pci,id=net0,netdev=tap0,mac=12:34:56:78:9A:BC,bus=pcie.0,addr=0x1,failover=on")
+            .unwrap();
+        assert!(vm_config.check_failover_pair("net0").is_ok());
+        assert!(vm_config.get_failover_primaries().is_empty());
+        vm_config.add_device(vfio_cfg).unwrap();
+        assert_eq!(
+            vm_config.get_failover_primaries(),
+            vec!["hostnet0".to_string()]
+        );
+    }
 }
@@ -0,0 +1,61 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use anyhow::{anyhow, Result};
+
+use super::{error::ConfigError, pci_args_check};
+use crate::config::{check_arg_too_long, CmdParser, ConfigCheck};
+
+/// Config struct for watchdog device.
+#[derive(Debug, Clone, Default)]
+pub struct WatchdogConfig {
+    pub id: String,
+}
+
+impl ConfigCheck for WatchdogConfig {
+    fn check(&self) -> Result<()> {
+        check_arg_too_long(&self.id, "id")
+    }
+}
+
+pub fn parse_watchdog(cfg_args: &str) -> Result<WatchdogConfig> {
+    let mut cmd_parser = CmdParser::new("i6300esb");
+    cmd_parser.push("").push("id").push("bus").push("addr");
+    cmd_parser.parse(cfg_args)?;
+
+    pci_args_check(&cmd_parser)?;
+
+    let config = WatchdogConfig {
+        id: cmd_parser.get_value::<String>("id")?.ok_or_else(|| {
+            anyhow!(ConfigError::FieldIsMissing(
+                "id".to_string(),
+                "i6300esb".to_string()
+            ))
+        })?,
+    };
+    config.check()?;
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_watchdog() {
+        let config = parse_watchdog("i6300esb,id=wdt0,bus=pcie.0,addr=0x5").unwrap();
+        assert_eq!(config.id, "wdt0");
+        assert!(parse_watchdog("i6300esb,bus=pcie.0,addr=0x5").is_err());
+        assert!(parse_watchdog("i6300esb,id=wdt0,bus=pcie.0,addr=0x5,action=reset").is_err());
+    }
+}
@@ -19,11 +19,12 @@ use super::config::IothreadConfig;
 use crate::machine::IOTHREADS;
 use crate::qmp::qmp_schema::IothreadInfo;
 
-use anyhow::bail;
-use log::info;
+use anyhow::{bail, Context};
+use log::{error, info};
 use util::loop_context::{
     gen_delete_notifiers, get_notifiers_fds, EventLoopContext, EventLoopManager, EventNotifier,
 };
+use util::seccomp::{apply_thread_filter, ThreadKind};
 
 /// This struct used to manage all events occur during VM lifetime.
 /// # Notes
@@ -35,7 +36,7 @@ pub struct EventLoop {
     /// Used to handle all events which are not monitored by io-threads
     main_loop: EventLoopContext,
     /// Used to monitor events of specified device.
-    io_threads: HashMap<String, EventLoopContext>,
+    io_threads: HashMap<String, Box<EventLoopContext>>,
 }
 
 static mut GLOBAL_EVENT_LOOP: Option<EventLoop> = None;
@@ -50,7 +51,7 @@ impl EventLoop {
         let mut io_threads = HashMap::new();
         if let Some(thrs) = iothreads {
             for thr in thrs {
-                io_threads.insert(thr.id.clone(), EventLoopContext::new());
+                io_threads.insert(thr.id.clone(), Self::create_iothread_ctx(thr));
             }
         }
 
@@ -65,21 +66,12 @@ impl EventLoop {
 
                 if let Some(event_loop) = GLOBAL_EVENT_LOOP.as_mut() {
                     for (id, ctx) in &mut event_loop.io_threads {
-                        thread::Builder::new().name(id.to_string()).spawn(move || {
-                            let iothread_info = IothreadInfo {
-                                shrink: 0,
-                                pid: process::id(),
-                                grow: 0,
-                                max: 0,
-                                id: id.to_string(),
-                            };
-                            IOTHREADS.lock().unwrap().push(iothread_info);
-                            while let Ok(ret) = ctx.iothread_run() {
-                                if !ret {
-                                    break;
-                                }
-                            }
-                        })?;
+                        let config = iothreads
+                            .iter()
+                            .flatten()
+                            .find(|thr| &thr.id == id)
+                            .with_context(|| format!("Iothread {} is not configured", id))?;
+                        Self::spawn_iothread(config, ctx.as_mut())?;
                     }
                 } else {
                     bail!("Global Event Loop have not been initialized.")
@@ -90,6 +82,77 @@ impl EventLoop {
         Ok(())
     }
 
+    fn create_iothread_ctx(config: &IothreadConfig) -> Box<EventLoopContext> {
+        let mut ctx = Box::new(EventLoopContext::new());
+        ctx.set_poll_params(config.poll_max_ns, config.poll_grow, config.poll_shrink);
+        ctx
+    }
+
+    fn spawn_iothread(
+        config: &IothreadConfig,
+        ctx: &'static mut EventLoopContext,
+    ) -> util::Result<()> {
+        let id = config.id.clone();
+        let iothread_info = IothreadInfo {
+            shrink: config.poll_shrink,
+            pid: process::id(),
+            grow: config.poll_grow,
+            max: config.poll_max_ns,
+            id: id.clone(),
+        };
+        thread::Builder::new().name(id).spawn(move || {
+            IOTHREADS.lock().unwrap().push(iothread_info);
+            loop {
+                if let Err(e) = apply_thread_filter(ThreadKind::Iothread) {
+                    error!("Failed to apply seccomp filter of iothread: {:?}", e);
+                }
+                match ctx.iothread_run() {
+                    Ok(true) => continue,
+                    _ => break,
+                }
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Create a new io-thread loop at runtime, such as by QMP command `object-add`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The config of the io-thread.
+    pub fn add_iothread(config: &IothreadConfig) -> util::Result<()> {
+        let id = &config.id;
+        // SAFETY: The io-thread is only added by the main loop thread. Each io-thread
+        // loop is boxed, so running io-threads are not affected by the insertion.
+        unsafe {
+            if let Some(event_loop) = GLOBAL_EVENT_LOOP.as_mut() {
+                if event_loop.io_threads.contains_key(id) {
+                    bail!("Iothread {} already exists", id);
+                }
+                let mut ctx = Self::create_iothread_ctx(config);
+                let ctx_ptr = ctx.as_mut() as *mut EventLoopContext;
+                Self::spawn_iothread(config, &mut *ctx_ptr)?;
+                event_loop.io_threads.insert(id.to_string(), ctx);
+                info!("Iothread {} is added", id);
+                Ok(())
+            } else {
+                bail!("Global Event Loop have not been initialized.")
+            }
+        }
+    }
+
+    /// Kick all the io-thread loops to re-evaluate their events.
+    pub fn kick_iothreads() {
+        // SAFETY: Kicking the loop only accesses atomic flag and eventfd.
+        unsafe {
+            if let Some(event_loop) = GLOBAL_EVENT_LOOP.as_mut() {
+                for ctx in event_loop.io_threads.values_mut() {
+                    ctx.kick();
+                }
+            }
+        }
+    }
+
     /// Return main loop or io-thread loop specified by input `name`
     ///
     /// # Arguments
@@ -100,7 +163,7 @@ impl EventLoop {
         unsafe {
             if let Some(event_loop) = GLOBAL_EVENT_LOOP.as_mut() {
                 if let Some(name) = name {
-                    return event_loop.io_threads.get_mut(name);
+                    return event_loop.io_threads.get_mut(name).map(|ctx| ctx.as_mut());
                 }
 
                 return Some(&mut event_loop.main_loop);
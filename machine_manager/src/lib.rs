@@ -27,8 +27,10 @@ pub mod error;
 pub mod event_loop;
 pub mod machine;
 pub mod qmp;
+pub mod rest_api;
 pub mod signal_handler;
 pub mod socket;
 pub mod temp_cleaner;
+pub mod tls;
 pub use error::MachineManagerError;
 pub mod test_server;
@@ -10,19 +10,21 @@
 // NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
 // See the Mulan PSL v2 for more details.
 
+use std::collections::VecDeque;
 use std::os::unix::io::RawFd;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use once_cell::sync::Lazy;
-use strum::VariantNames;
 
-use crate::config::ShutdownAction;
+use crate::config::{PanicAction, RebootAction, ShutdownAction};
 use crate::qmp::qmp_schema::{
-    BlockDevAddArgument, BlockdevSnapshotInternalArgument, CameraDevAddArgument,
-    CharDevAddArgument, ChardevInfo, Cmd, CmdLine, CmdParameter, DeviceAddArgument, DeviceProps,
-    Events, GicCap, HumanMonitorCmdArgument, IothreadInfo, KvmInfo, MachineInfo,
-    MigrateCapabilities, NetDevAddArgument, PropList, QmpCommand, QmpErrorClass, QmpEvent, Target,
-    TypeLists, UpdateRegionArgument,
+    migrate_set_capabilities, migrate_set_parameters, object_add, qmp_command_names,
+    qmp_event_names, qmp_schema_info, Any, BlockDevAddArgument, BlockdevSnapshotInternalArgument,
+    CameraDevAddArgument, CharDevAddArgument, ChardevInfo, Cmd, CmdLine, CmdParameter,
+    DeviceAddArgument, DeviceProps, Events, GicCap, GuestAgentCmdArgument, HumanMonitorCmdArgument,
+    IothreadInfo, KvmInfo, MachineInfo, MigrateCapabilities, NetDevAddArgument, PciInfo,
+    QmpErrorClass, RunState, StatusInfo, Target, TypeLists, UpdateRegionArgument,
 };
 use crate::qmp::{Response, Version};
 
@@ -41,6 +43,7 @@ pub enum KvmVmState {
     Migrated = 4,
     Paused = 5,
     Shutdown = 6,
+    Suspended = 7,
 }
 
 /// Event over StratoVirt lifetime.
@@ -51,6 +54,124 @@ pub enum VmEvent {
     ShutdownCauseInternalError,
 }
 
+/// Reason why the VM was paused last time, which refines the run state of a
+/// paused VM reported by `query-status`.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub enum StopReason {
+    /// Paused by the management, such as qmp command `stop`.
+    #[default]
+    User,
+    /// Started with all vcpus frozen and not continued yet.
+    Prelaunch,
+    /// Guest panicked and the panic action is `pause`.
+    GuestPanicked,
+    /// Guest shut down and the shutdown action is `pause`.
+    GuestShutdown,
+    /// Paused because of the I/O error of a block device.
+    IoError,
+    /// Watchdog expired and the watchdog action is `pause`.
+    Watchdog,
+    /// Paused to send the remaining state at the end of live migration.
+    Migrate,
+    /// Stopped by the debugger, such as breakpoint hit.
+    Debug,
+}
+
+impl StopReason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StopReason::User => "user",
+            StopReason::Prelaunch => "prelaunch",
+            StopReason::GuestPanicked => "guest-panicked",
+            StopReason::GuestShutdown => "guest-shutdown",
+            StopReason::IoError => "io-error",
+            StopReason::Watchdog => "watchdog",
+            StopReason::Migrate => "migrate",
+            StopReason::Debug => "debug",
+        }
+    }
+
+    fn run_state(&self) -> RunState {
+        match self {
+            StopReason::User => RunState::paused,
+            StopReason::Prelaunch => RunState::prelaunch,
+            StopReason::GuestPanicked => RunState::guest_panicked,
+            StopReason::GuestShutdown => RunState::shutdown,
+            StopReason::IoError => RunState::io_error,
+            StopReason::Watchdog => RunState::watchdog,
+            StopReason::Migrate => RunState::finish_migrate,
+            StopReason::Debug => RunState::debug,
+        }
+    }
+}
+
+static STOP_REASON: Lazy<Mutex<StopReason>> = Lazy::new(|| Mutex::new(StopReason::User));
+
+/// Record the reason before pausing the VM. It's reset to `User` once the VM
+/// runs again.
+pub fn set_stop_reason(reason: StopReason) {
+    *STOP_REASON.lock().unwrap() = reason;
+}
+
+/// Get the reason why the VM was paused last time.
+pub fn get_stop_reason() -> StopReason {
+    *STOP_REASON.lock().unwrap()
+}
+
+static GUEST_CRASHES: Lazy<Mutex<VecDeque<Instant>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+/// Record a guest crash, and check whether the guest crashed more than `limit`
+/// times within `interval` seconds, which means it's in a crash loop.
+///
+/// # Arguments
+///
+/// * `limit` - Max number of crashes, `0` means the check is disabled.
+/// * `interval` - Interval in seconds to count the crashes.
+pub fn detect_crash_loop(limit: u32, interval: u64) -> bool {
+    if limit == 0 {
+        return false;
+    }
+
+    let now = Instant::now();
+    let mut crashes = GUEST_CRASHES.lock().unwrap();
+    crashes.push_back(now);
+    while let Some(crash) = crashes.front() {
+        if now.duration_since(*crash) <= Duration::from_secs(interval) {
+            break;
+        }
+        crashes.pop_front();
+    }
+    crashes.len() > limit as usize
+}
+
+/// Get the status reported by `query-status` from the state of the VM.
+///
+/// # Arguments
+///
+/// * `state` - The current `KvmVmState` of the VM.
+pub fn vm_status_info(state: KvmVmState) -> StatusInfo {
+    let mut stop_reason = None;
+    let status = match state {
+        KvmVmState::Created => RunState::prelaunch,
+        KvmVmState::Running => RunState::running,
+        KvmVmState::InMigrating => RunState::inmigrate,
+        KvmVmState::Migrated => RunState::postmigrate,
+        KvmVmState::Paused => {
+            let reason = get_stop_reason();
+            stop_reason = Some(reason.name().to_string());
+            reason.run_state()
+        }
+        KvmVmState::Shutdown => RunState::shutdown,
+        KvmVmState::Suspended => RunState::suspended,
+    };
+    StatusInfo {
+        singlestep: false,
+        running: state == KvmVmState::Running,
+        status,
+        stop_reason,
+    }
+}
+
 /// Trait to handle virtual machine lifecycle.
 ///
 /// # Notes
@@ -61,6 +182,8 @@ pub enum VmEvent {
 /// `Created` --`(start)`--> `Running`
 /// `Running` --`(pause)`--> `Paused`
 /// `Paused` --`(resume)`--> `Running`
+/// `Running` --`(suspend)`--> `Suspended`
+/// `Suspended` --`(wakeup)`--> `Running`
 /// `KVM_VMSTATE_*` --`(destroy)`--> `None`
 ///
 /// **Notice**:
@@ -104,6 +227,16 @@ pub trait MachineLifecycle {
         self.notify_lifecycle(KvmVmState::Running, KvmVmState::Shutdown)
     }
 
+    /// Suspend VM to RAM, all vCPUs are stopped until VM is woken up.
+    fn suspend(&self) -> bool {
+        self.notify_lifecycle(KvmVmState::Running, KvmVmState::Suspended)
+    }
+
+    /// Wake up VM from suspended state.
+    fn wakeup(&mut self) -> bool {
+        self.notify_lifecycle(KvmVmState::Suspended, KvmVmState::Running)
+    }
+
     /// When VM or Device life state changed, notify concerned entry.
     ///
     /// # Arguments
@@ -116,6 +249,22 @@ pub trait MachineLifecycle {
     fn get_shutdown_action(&self) -> ShutdownAction {
         ShutdownAction::ShutdownActionPoweroff
     }
+
+    /// Get panic_action to determine the operation when guest panicked.
+    fn get_panic_action(&self) -> PanicAction {
+        PanicAction::default()
+    }
+
+    /// Get reboot_action to determine the operation when guest requests a reboot.
+    fn get_reboot_action(&self) -> RebootAction {
+        RebootAction::default()
+    }
+
+    /// Record a guest crash, return true if the guest is in a crash loop and
+    /// should be shut down.
+    fn in_crash_loop(&self) -> bool {
+        false
+    }
 }
 
 /// `AddressSpace` access interface of `Machine`.
@@ -141,6 +290,12 @@ pub trait MachineAddressInterface {
     fn mmio_read(&self, addr: u64, data: &mut [u8]) -> bool;
 
     fn mmio_write(&self, addr: u64, data: &[u8]) -> bool;
+
+    /// Get the name of the device handling the MMIO or PIO address, which is used
+    /// by the tracing of accesses.
+    fn io_device_name(&self, _addr: u64, _pio: bool) -> Option<String> {
+        None
+    }
 }
 
 /// Device external api
@@ -156,9 +311,25 @@ pub trait DeviceInterface {
     /// Query each cpu's the topology info.
     fn query_cpus(&self) -> Response;
 
+    /// Query each cpu's index, thread id and topology info without interrupting
+    /// the vcpus.
+    fn query_cpus_fast(&self) -> Response;
+
+    /// Query the runtime statistics of each cpu, including exit counts, run time
+    /// and halt time.
+    fn query_vcpu_stats(&self) -> Response;
+
     /// Query each `hotpluggable_cpus`'s topology info and hotplug message.
     fn query_hotpluggable_cpus(&self) -> Response;
 
+    /// Pin the thread of vcpu to the host CPU.
+    fn set_vcpu_affinity(&self, _cpu_index: usize, _host_cpu: usize) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("set-vcpu-affinity is not supported".to_string()),
+            None,
+        )
+    }
+
     /// Add a device with configuration.
     fn device_add(&mut self, args: Box<DeviceAddArgument>) -> Response;
 
@@ -188,6 +359,22 @@ pub trait DeviceInterface {
     /// Delete a camera device.
     fn cameradev_del(&mut self, id: String) -> Response;
 
+    /// Create a backend object at runtime.
+    fn object_add(&mut self, _args: object_add) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("object-add is not supported".to_string()),
+            None,
+        )
+    }
+
+    /// Remove a backend object which is not in use.
+    fn object_del(&mut self, _id: String) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("object-del is not supported".to_string()),
+            None,
+        )
+    }
+
     /// Receive a file descriptor via SCM rights and assign it a name.
     fn getfd(&self, fd_name: String, if_fd: Option<RawFd>) -> Response;
 
@@ -197,12 +384,51 @@ pub trait DeviceInterface {
     /// Query machine mem size.
     fn query_mem(&self) -> Response;
 
+    /// Query the host memory resident in each range of guest RAM.
+    fn query_memory_rss(&self) -> Response;
+
     /// Query the info of vnc server.
     fn query_vnc(&self) -> Response;
 
+    /// List the properties of the object at the qom path.
+    fn qom_list(&mut self, path: String) -> Response;
+
+    /// Get the value of a property of the object at the qom path.
+    fn qom_get(&mut self, path: String, property: String) -> Response;
+
+    /// Set the value of a writable property of the object at the qom path.
+    fn qom_set(&mut self, path: String, property: String, value: Any) -> Response;
+
     /// Set balloon's size.
     fn balloon(&self, size: u64) -> Response;
 
+    /// Set the interval to request guest memory statistics of balloon.
+    fn balloon_set_stats_interval(&self, interval: u64) -> Response;
+
+    /// Set the action taken when the watchdog timer expires.
+    fn watchdog_set_action(&mut self, _action: String) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("watchdog-set-action is not supported".to_string()),
+            None,
+        )
+    }
+
+    /// Query the kernel cmdline used at the next boot.
+    fn query_kernel_cmdline(&self) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("query-kernel-cmdline is not supported".to_string()),
+            None,
+        )
+    }
+
+    /// Amend the kernel cmdline, which takes effect at the next boot.
+    fn set_kernel_cmdline(&mut self, _cmdline: String, _append: Option<bool>) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("set-kernel-cmdline is not supported".to_string()),
+            None,
+        )
+    }
+
     /// Query the version of StratoVirt.
     fn query_version(&self) -> Response {
         let version = Version::new(1, 0, 5);
@@ -212,11 +438,8 @@ pub trait DeviceInterface {
     /// Query all commands of StratoVirt.
     fn query_commands(&self) -> Response {
         let mut vec_cmd = Vec::new();
-        for qmp_cmd in QmpCommand::VARIANTS {
-            let cmd = Cmd {
-                name: String::from(*qmp_cmd),
-            };
-            vec_cmd.push(cmd);
+        for name in qmp_command_names() {
+            vec_cmd.push(Cmd { name });
         }
         Response::create_response(serde_json::to_value(&vec_cmd).unwrap(), None)
     }
@@ -236,13 +459,10 @@ pub trait DeviceInterface {
 
     /// Query all events of StratoVirt.
     fn query_events(&self) -> Response {
-        let mut vec_events = Vec::new();
-        for event in QmpEvent::VARIANTS {
-            let cmd = Events {
-                name: String::from(*event),
-            };
-            vec_events.push(cmd);
-        }
+        let vec_events: Vec<Events> = qmp_event_names()
+            .into_iter()
+            .map(|name| Events { name })
+            .collect();
         Response::create_response(serde_json::to_value(&vec_events).unwrap(), None)
     }
 
@@ -316,6 +536,7 @@ pub trait DeviceInterface {
             #[cfg(target_arch = "aarch64")]
             ("gpex-pcihost", "pcie-host-bridge"),
             ("nec-usb-xhci", "base-xhci"),
+            ("qemu-xhci", "base-xhci"),
             ("usb-tablet", "usb-hid"),
             ("usb-kbd", "usb-hid"),
             ("usb-storage", "usb-storage-dev"),
@@ -389,7 +610,7 @@ pub trait DeviceInterface {
     }
 
     fn query_qmp_schema(&self) -> Response {
-        Response::create_empty_response()
+        Response::create_response(serde_json::to_value(qmp_schema_info()).unwrap(), None)
     }
 
     fn query_sev_capabilities(&self) -> Response {
@@ -412,16 +633,6 @@ pub trait DeviceInterface {
         Response::create_response(serde_json::to_value(&vec_chardev_info).unwrap(), None)
     }
 
-    fn qom_list(&self) -> Response {
-        let vec_cmd: Vec<PropList> = Vec::new();
-        Response::create_response(serde_json::to_value(vec_cmd).unwrap(), None)
-    }
-
-    fn qom_get(&self) -> Response {
-        let vec_cmd: Vec<ChardevInfo> = Vec::new();
-        Response::create_response(serde_json::to_value(vec_cmd).unwrap(), None)
-    }
-
     fn query_block(&self) -> Response {
         let vec_cmd: Vec<ChardevInfo> = Vec::new();
         Response::create_response(serde_json::to_value(vec_cmd).unwrap(), None)
@@ -457,6 +668,12 @@ pub trait DeviceInterface {
         Response::create_response(serde_json::to_value(&vec_iothreads).unwrap(), None)
     }
 
+    /// Query the information of PCI buses and devices, no PCI bus by default.
+    fn query_pci(&mut self) -> Response {
+        let vec_pci: Vec<PciInfo> = Vec::new();
+        Response::create_response(serde_json::to_value(vec_pci).unwrap(), None)
+    }
+
     fn update_region(&mut self, args: UpdateRegionArgument) -> Response;
 
     // Send event to input device for testing only.
@@ -471,6 +688,14 @@ pub trait DeviceInterface {
         )
     }
 
+    /// Pass a command through to guest agent.
+    fn guest_agent_command(&self, _args: GuestAgentCmdArgument) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("guest-agent-command is not supported yet".to_string()),
+            None,
+        )
+    }
+
     fn blockdev_snapshot_internal_sync(&self, _args: BlockdevSnapshotInternalArgument) -> Response {
         Response::create_empty_response()
     }
@@ -494,6 +719,14 @@ pub trait MigrateInterface {
         Response::create_empty_response()
     }
 
+    /// Checks whether the destination VM is able to receive migration.
+    fn migrate_check(&self, _uri: String) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("migrate-check is not supported".to_string()),
+            None,
+        )
+    }
+
     /// Returns information about current migration.
     fn query_migrate(&self) -> Response {
         Response::create_empty_response()
@@ -502,6 +735,47 @@ pub trait MigrateInterface {
     fn cancel_migrate(&self) -> Response {
         Response::create_empty_response()
     }
+
+    /// Saves the memory and device state of VM to the snapshot dir, or to a single
+    /// file if `single_file` is true.
+    fn savevm(&self, _path: String, _single_file: Option<bool>) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("savevm is not supported".to_string()),
+            None,
+        )
+    }
+
+    /// Loads the memory and device state of VM from the snapshot dir and resumes it.
+    fn loadvm(&self, _path: String) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("loadvm is not supported".to_string()),
+            None,
+        )
+    }
+
+    /// Enables or disables migration capabilities.
+    fn migrate_set_capabilities(&self, _args: migrate_set_capabilities) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("migrate-set-capabilities is not supported".to_string()),
+            None,
+        )
+    }
+
+    /// Sets the parameters of migration.
+    fn migrate_set_parameters(&self, _args: migrate_set_parameters) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("migrate-set-parameters is not supported".to_string()),
+            None,
+        )
+    }
+
+    /// Returns the parameters of migration.
+    fn query_migrate_parameters(&self) -> Response {
+        Response::create_error_response(
+            QmpErrorClass::GenericError("query-migrate-parameters is not supported".to_string()),
+            None,
+        )
+    }
 }
 
 /// Machine interface which is exposed to inner hypervisor.
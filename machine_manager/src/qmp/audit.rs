@@ -0,0 +1,171 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+//! Audit log of the commands received by the monitors.
+//!
+//! Each executed command is appended to the audit file as one line of json,
+//! which records the client, the command with its arguments and the result.
+//! Values of the arguments carrying secrets are replaced by `"<redacted>"`.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use log::error;
+use once_cell::sync::OnceCell;
+use serde_json::{json, Value};
+
+use super::{create_timestamp, Response};
+
+static AUDIT_FILE: OnceCell<Mutex<File>> = OnceCell::new();
+
+/// Argument names whose values are never written to the audit file.
+const SECRET_KEYS: [&str; 5] = ["password", "passwd", "passphrase", "secret", "token"];
+const REDACTED: &str = "<redacted>";
+
+/// Open the audit file, the records are appended to it if it already exists.
+///
+/// # Arguments
+///
+/// * `path` - The path of the audit file.
+pub fn init_audit_log(path: &str) -> Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Failed to open qmp audit file {}", path))?;
+    AUDIT_FILE
+        .set(Mutex::new(file))
+        .map_err(|_| anyhow!("Qmp audit log is already initialized"))
+}
+
+/// Whether the audit log is enabled.
+pub fn is_enabled() -> bool {
+    AUDIT_FILE.get().is_some()
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                if SECRET_KEYS.contains(&key.to_lowercase().as_str()) {
+                    *v = Value::String(REDACTED.to_string());
+                } else {
+                    redact(v);
+                }
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+fn audit_record(client: &str, mut command: Value, response: &Response) -> Value {
+    redact(&mut command);
+    // The content of a secret object is carried by the plain `data` property.
+    if command["execute"] == "object-add" && command["arguments"]["qom-type"] == "secret" {
+        if let Some(data) = command["arguments"].get_mut("data") {
+            *data = Value::String(REDACTED.to_string());
+        }
+    }
+    let mut record = json!({
+        "timestamp": create_timestamp(),
+        "client": client,
+        "execute": command["execute"],
+        "arguments": command.get("arguments").cloned().unwrap_or_else(|| json!({})),
+    });
+    if let Some(id) = command.get("id") {
+        record["id"] = id.clone();
+    }
+    match &response.error {
+        Some(err) => {
+            record["result"] = json!("error");
+            record["error"] = json!(err.desc);
+        }
+        None => record["result"] = json!("success"),
+    }
+    record
+}
+
+/// Append the record of an executed command to the audit file.
+///
+/// # Arguments
+///
+/// * `client` - The identity of the client which sends the command.
+/// * `command` - The command serialized before execution.
+/// * `response` - The response of the command.
+pub fn audit_command(client: &str, command: Value, response: &Response) {
+    let file = match AUDIT_FILE.get() {
+        Some(file) => file,
+        None => return,
+    };
+    let record = audit_record(client, command, response);
+    let mut file = file.lock().unwrap();
+    if let Err(e) = writeln!(file, "{}", record) {
+        error!("Failed to write qmp audit log: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qmp::qmp_schema::{self as schema, QmpCommand};
+
+    #[test]
+    fn test_audit_record() {
+        let cmd_str = r#"{"execute":"balloon","arguments":{"value":1073741824},"id":"1"}"#;
+        let cmd: QmpCommand = serde_json::from_str(cmd_str).unwrap();
+        let command = serde_json::to_value(&cmd).unwrap();
+        let response = Response::create_empty_response();
+        let record = audit_record("unix:pid=1,uid=0", command, &response);
+        assert_eq!(record["client"], "unix:pid=1,uid=0");
+        assert_eq!(record["execute"], "balloon");
+        assert_eq!(record["arguments"]["value"], 1073741824_u64);
+        assert_eq!(record["id"], "1");
+        assert_eq!(record["result"], "success");
+
+        let mut value = json!({
+            "id": "drive0",
+            "key": "a",
+            "secret-id": "sec0",
+            "passwd": "123",
+            "opts": [{"Password": "123"}, {"token": 1}],
+        });
+        redact(&mut value);
+        assert_eq!(value["id"], "drive0");
+        assert_eq!(value["key"], "a");
+        assert_eq!(value["secret-id"], "sec0");
+        assert_eq!(value["passwd"], REDACTED);
+        assert_eq!(value["opts"][0]["Password"], REDACTED);
+        assert_eq!(value["opts"][1]["token"], REDACTED);
+
+        let command = json!({"execute": "device_del", "arguments": {"id": "net0"}});
+        let err = schema::QmpErrorClass::GenericError("Failed to find device".to_string());
+        let response = Response::create_error_response(err, None);
+        let record = audit_record("tcp:127.0.0.1:4444", command, &response);
+        assert_eq!(record["execute"], "device_del");
+        assert_eq!(record["arguments"]["id"], "net0");
+        assert!(record.get("id").is_none());
+        assert_eq!(record["result"], "error");
+        assert_eq!(record["error"], "Failed to find device");
+
+        let cmd_str = r#"{"execute":"object-add","arguments":{"qom-type":"secret","id":"sec0","data":"letmein"}}"#;
+        let cmd: QmpCommand = serde_json::from_str(cmd_str).unwrap();
+        let command = serde_json::to_value(&cmd).unwrap();
+        let record = audit_record("unix:pid=1,uid=0", command, &response);
+        assert_eq!(record["arguments"]["id"], "sec0");
+        assert_eq!(record["arguments"]["data"], REDACTED);
+    }
+}
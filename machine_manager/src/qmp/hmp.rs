@@ -0,0 +1,550 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+//! Human monitor (HMP), which is a line based monitor for operators.
+//!
+//! Every command line is translated to a QMP command, which is executed by the
+//! same `DeviceInterface` as QMP, and the response is printed in human readable
+//! format.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::info;
+use serde::de::{self, value::MapDeserializer, IntoDeserializer, Unexpected, Visitor};
+use serde::{forward_to_deserialize_any, Deserialize, Deserializer};
+use serde_json::Value;
+
+use super::qmp_schema::{self as schema, QmpCommand};
+use super::{audit, handle_quit, qmp_command_exec, Response};
+use crate::machine::MachineExternalInterface;
+use crate::socket::SocketHandler;
+
+/// Prompt printed when human monitor is ready for the next command.
+pub const HMP_PROMPT: &str = "(stratovirt) ";
+/// Banner sent to the client when it connects to human monitor.
+pub const HMP_BANNER: &str = "StratoVirt monitor - type 'help' for more information";
+
+/// Commands of human monitor: name, arguments and help message.
+const HMP_COMMANDS: &[(&str, &str, &str)] = &[
+    ("help|?", "", "show the help info"),
+    (
+        "info",
+        "status|version|cpus|block|chardev|iothreads|balloon|migrate",
+        "show various information about the system state",
+    ),
+    ("stop", "", "stop emulation"),
+    ("cont|c", "", "resume emulation"),
+    ("system_reset", "", "reset the system"),
+    ("system_powerdown", "", "send system power down event"),
+    ("system_wakeup", "", "wakeup guest from suspend"),
+    (
+        "device_add",
+        "driver[,prop=value][,...]",
+        "add device, like -device on the command line",
+    ),
+    ("device_del", "device", "remove device"),
+    ("drive_add", "dummy file=...", "add drive backend"),
+    ("drive_del", "device", "remove drive backend"),
+    (
+        "balloon",
+        "target",
+        "request VM to change its memory allocation (in MB)",
+    ),
+    ("quit|q", "", "quit the emulator"),
+];
+
+/// Information items of `info` command.
+const HMP_INFO_ITEMS: &[&str] = &[
+    "status",
+    "version",
+    "cpus",
+    "block",
+    "chardev",
+    "iothreads",
+    "balloon",
+    "migrate",
+];
+
+/// Human monitor command.
+#[derive(Debug)]
+enum HmpCommand {
+    /// Show the help info.
+    Help,
+    /// Show the information item, which is queried by the qmp command.
+    Info(&'static str, QmpCommand),
+    /// Execute the qmp command, and only the error is printed.
+    Execute(QmpCommand),
+}
+
+/// Value of the "key=value" property, which is converted to the type of the field
+/// when it's deserialized.
+struct PropValue(String);
+
+impl PropValue {
+    fn parse_u64(&self) -> std::result::Result<u64, de::value::Error> {
+        let value = match self.0.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => self.0.parse::<u64>(),
+        };
+        value.map_err(|_| de::Error::invalid_value(Unexpected::Str(&self.0), &"an integer"))
+    }
+}
+
+macro_rules! deserialize_unsigned {
+    ($($method:ident => $visit:ident: $ty:ty),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, Self::Error> {
+                let value = <$ty>::try_from(self.parse_u64()?).map_err(|_| {
+                    de::Error::invalid_value(Unexpected::Str(&self.0), &stringify!($ty))
+                })?;
+                visitor.$visit(value)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for PropValue {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        visitor.visit_string(self.0)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        match self.0.as_str() {
+            "on" | "true" => visitor.visit_bool(true),
+            "off" | "false" => visitor.visit_bool(false),
+            _ => Err(de::Error::invalid_value(
+                Unexpected::Str(&self.0),
+                &"on or off",
+            )),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    deserialize_unsigned!(
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64
+    );
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u128 f32 f64 char str string bytes byte_buf unit unit_struct
+        newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, de::value::Error> for PropValue {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Parse the properties such as "driver,key1=value1,key2=value2" to the arguments of
+/// qmp command. The first property without key is regarded as the value of `first_key`.
+fn parse_props<T: for<'de> Deserialize<'de>>(props: &str, first_key: &str) -> Result<T> {
+    let mut pairs = Vec::new();
+    for (i, prop) in props.split(',').enumerate() {
+        let (key, value) = match prop.split_once('=') {
+            Some((key, value)) => (key, value),
+            None if i == 0 => (first_key, prop),
+            // Flag without value, such as "multifunction", is regarded as "on".
+            None => (prop, "on"),
+        };
+        pairs.push((key.to_string(), PropValue(value.to_string())));
+    }
+
+    let deserializer: MapDeserializer<_, de::value::Error> =
+        MapDeserializer::new(pairs.into_iter());
+    T::deserialize(deserializer).map_err(|e| anyhow!("{}", e))
+}
+
+/// Translate the command line of human monitor to the command to execute.
+fn parse_hmp_command(cmd_line: &str) -> Result<HmpCommand> {
+    let (name, args) = match cmd_line.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (cmd_line, ""),
+    };
+    let no_args = |cmd: QmpCommand| -> Result<HmpCommand> {
+        if !args.is_empty() {
+            bail!("Command '{}' takes no arguments", name);
+        }
+        Ok(HmpCommand::Execute(cmd))
+    };
+    let required_arg = || -> Result<&str> {
+        if args.is_empty() {
+            bail!("Command '{}' requires an argument", name);
+        }
+        Ok(args)
+    };
+
+    match name {
+        "help" | "?" => Ok(HmpCommand::Help),
+        "info" => parse_info_command(args),
+        "stop" => no_args(QmpCommand::stop {
+            arguments: Default::default(),
+            id: None,
+        }),
+        "cont" | "c" => no_args(QmpCommand::cont {
+            arguments: Default::default(),
+            id: None,
+        }),
+        "system_reset" => no_args(QmpCommand::system_reset {
+            arguments: Default::default(),
+            id: None,
+        }),
+        "system_powerdown" => no_args(QmpCommand::system_powerdown {
+            arguments: Default::default(),
+            id: None,
+        }),
+        "system_wakeup" => no_args(QmpCommand::system_wakeup {
+            arguments: Default::default(),
+            id: None,
+        }),
+        "quit" | "q" => no_args(QmpCommand::quit {
+            arguments: Default::default(),
+            id: None,
+        }),
+        "device_add" => Ok(HmpCommand::Execute(QmpCommand::device_add {
+            arguments: Box::new(parse_props(required_arg()?, "driver")?),
+            id: None,
+        })),
+        "device_del" => Ok(HmpCommand::Execute(QmpCommand::device_del {
+            arguments: schema::device_del {
+                id: required_arg()?.to_string(),
+            },
+            id: None,
+        })),
+        "balloon" => {
+            let target = required_arg()?
+                .parse::<u64>()
+                .map_err(|_| anyhow!("Invalid balloon target: {}", args))?;
+            Ok(HmpCommand::Execute(QmpCommand::balloon {
+                arguments: schema::balloon {
+                    value: target << 20,
+                },
+                id: None,
+            }))
+        }
+        // Commands which are handled by `human-monitor-command` of qmp.
+        "drive_add" | "drive_del" => Ok(HmpCommand::Execute(QmpCommand::human_monitor_command {
+            arguments: schema::human_monitor_command {
+                command_line: cmd_line.to_string(),
+            },
+            id: None,
+        })),
+        _ => bail!("Unknown command: '{}'", name),
+    }
+}
+
+fn parse_info_command(item: &str) -> Result<HmpCommand> {
+    if item.is_empty() {
+        bail!(
+            "Command 'info' requires an item: {}",
+            HMP_INFO_ITEMS.join("|")
+        );
+    }
+    let item = HMP_INFO_ITEMS
+        .iter()
+        .find(|i| **i == item)
+        .with_context(|| format!("Unknown info item: '{}'", item))?;
+    // Every information item is queried by "query-<item>" of qmp.
+    let cmd = serde_json::from_value(serde_json::json!({ "execute": format!("query-{}", item) }))?;
+    Ok(HmpCommand::Info(item, cmd))
+}
+
+fn help_info() -> String {
+    HMP_COMMANDS
+        .iter()
+        .map(|(name, args, help)| {
+            if args.is_empty() {
+                format!("{} -- {}", name, help)
+            } else {
+                format!("{} {} -- {}", name, args, help)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn value_str<'a>(value: &'a Value, key: &str) -> &'a str {
+    value[key].as_str().unwrap_or_default()
+}
+
+/// Print the result of qmp query command in human readable format.
+fn format_info(item: &str, value: &Value) -> String {
+    let list = value.as_array().cloned().unwrap_or_default();
+    let lines: Vec<String> = match item {
+        "status" => match value["stop-reason"].as_str() {
+            Some(reason) => vec![format!(
+                "VM status: {} (stop reason: {})",
+                value_str(value, "status"),
+                reason
+            )],
+            None => vec![format!("VM status: {}", value_str(value, "status"))],
+        },
+        "version" => {
+            let number = &value["qemu"];
+            vec![format!(
+                "{}.{}.{} ({})",
+                number["major"],
+                number["minor"],
+                number["micro"],
+                value_str(value, "package")
+            )]
+        }
+        "cpus" => list
+            .iter()
+            .map(|cpu| {
+                let current = if cpu["current"].as_bool().unwrap_or(false) {
+                    '*'
+                } else {
+                    ' '
+                };
+                format!(
+                    "{} CPU #{}: thread_id={}",
+                    current, cpu["CPU"], cpu["thread_id"]
+                )
+            })
+            .collect(),
+        "block" => list
+            .iter()
+            .map(|block| {
+                let inserted = &block["inserted"];
+                if inserted.is_object() {
+                    let ro = if inserted["ro"].as_bool().unwrap_or(false) {
+                        ", read-only"
+                    } else {
+                        ""
+                    };
+                    format!(
+                        "{}: {} ({}{})",
+                        value_str(block, "device"),
+                        value_str(inserted, "file"),
+                        value_str(inserted, "drv"),
+                        ro
+                    )
+                } else {
+                    format!("{}: [not inserted]", value_str(block, "device"))
+                }
+            })
+            .collect(),
+        "chardev" => list
+            .iter()
+            .map(|chardev| {
+                format!(
+                    "{}: filename={}",
+                    value_str(chardev, "label"),
+                    value_str(chardev, "filename")
+                )
+            })
+            .collect(),
+        "iothreads" => list
+            .iter()
+            .map(|iothread| {
+                format!(
+                    "{}: thread_id={}",
+                    value_str(iothread, "id"),
+                    iothread["thread-id"]
+                )
+            })
+            .collect(),
+        "balloon" => vec![format!(
+            "balloon: actual={}",
+            value["actual"].as_u64().unwrap_or_default() >> 20
+        )],
+        _ => vec![format!(
+            "Migration status: {}",
+            value["status"].as_str().unwrap_or("none")
+        )],
+    };
+    lines.join("\n")
+}
+
+/// Execute the command line of human monitor, return the output and whether the
+/// monitor requests to quit.
+fn hmp_command_exec(
+    cmd_line: &str,
+    client: &str,
+    controller: &Arc<Mutex<dyn MachineExternalInterface>>,
+) -> (String, bool) {
+    let cmd = match parse_hmp_command(cmd_line) {
+        Ok(cmd) => cmd,
+        Err(e) => return (format!("Error: {}", e), false),
+    };
+    let (item, qmp_command) = match cmd {
+        HmpCommand::Help => return (help_info(), false),
+        HmpCommand::Info(item, qmp_command) => (Some(item), qmp_command),
+        HmpCommand::Execute(qmp_command) => (None, qmp_command),
+    };
+
+    let audit_value =
+        audit::is_enabled().then(|| serde_json::to_value(&qmp_command).unwrap_or_default());
+    let (response, shutdown_flag) = qmp_command_exec(qmp_command, controller, None);
+    if let Some(value) = audit_value {
+        audit::audit_command(client, value, &response);
+    }
+    let output = format_response(item, &response);
+    (output, shutdown_flag)
+}
+
+fn format_response(item: Option<&str>, response: &Response) -> String {
+    if let Some(error) = &response.error {
+        return format!("Error: {}", error.desc);
+    }
+    match (item, &response.return_) {
+        (Some(item), Some(value)) => format_info(item, value),
+        _ => String::new(),
+    }
+}
+
+/// Accept human monitor command line, execute it and print the result.
+///
+/// # Arguments
+///
+/// * `hmp_service` - The handler of the input stream.
+/// * `controller` - The controller which execute actual command.
+///
+/// # Errors
+///
+/// This function will fail when socket file description broke.
+pub fn handle_hmp(
+    hmp_service: &mut SocketHandler,
+    controller: &Arc<Mutex<dyn MachineExternalInterface>>,
+) -> Result<()> {
+    let buffer = match hmp_service.get_line()? {
+        Some(buffer) => buffer,
+        None => return Ok(()),
+    };
+
+    for cmd_line in buffer.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        info!("HMP: <-- {:?}", cmd_line);
+        let (output, shutdown_flag) = hmp_command_exec(cmd_line, hmp_service.peer(), controller);
+        if shutdown_flag {
+            handle_quit("host-hmp-quit");
+        }
+        if !output.is_empty() {
+            hmp_service.send_str(&output)?;
+        }
+    }
+    hmp_service.send_raw_str(HMP_PROMPT)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmp_parse_command() {
+        assert!(matches!(
+            parse_hmp_command("help").unwrap(),
+            HmpCommand::Help
+        ));
+        assert!(matches!(
+            parse_hmp_command("info status").unwrap(),
+            HmpCommand::Info("status", QmpCommand::query_status { .. })
+        ));
+        assert!(matches!(
+            parse_hmp_command("c").unwrap(),
+            HmpCommand::Execute(QmpCommand::cont { .. })
+        ));
+        match parse_hmp_command("balloon 1024").unwrap() {
+            HmpCommand::Execute(QmpCommand::balloon { arguments, .. }) => {
+                assert_eq!(arguments.value, 1 << 30)
+            }
+            _ => panic!("Invalid balloon command"),
+        }
+        assert!(parse_hmp_command("info").is_err());
+        assert!(parse_hmp_command("info unknown").is_err());
+        assert!(parse_hmp_command("stop now").is_err());
+        assert!(parse_hmp_command("device_del").is_err());
+        assert!(parse_hmp_command("unknown").is_err());
+    }
+
+    #[test]
+    fn test_hmp_device_add() {
+        let cmd = parse_hmp_command(
+            "device_add virtio-blk-pci,id=blk1,drive=drive1,bus=pcie.0,addr=0x2,num-queues=4,\
+             multifunction,boot_index=1",
+        )
+        .unwrap();
+        match cmd {
+            HmpCommand::Execute(QmpCommand::device_add { arguments, .. }) => {
+                assert_eq!(arguments.driver, "virtio-blk-pci");
+                assert_eq!(arguments.id, "blk1");
+                assert_eq!(arguments.drive, Some("drive1".to_string()));
+                assert_eq!(arguments.addr, Some("0x2".to_string()));
+                assert_eq!(arguments.queues, Some(4));
+                assert_eq!(arguments.multifunction, Some(true));
+                assert_eq!(arguments.boot_index, Some(1));
+            }
+            _ => panic!("Invalid device_add command"),
+        }
+
+        assert!(parse_hmp_command("device_add virtio-blk-pci,id=blk1,unknown=1").is_err());
+        assert!(parse_hmp_command("device_add virtio-blk-pci,id=blk1,num-queues=a").is_err());
+        assert!(parse_hmp_command("device_add virtio-blk-pci,id=blk1,boot_index=256").is_err());
+    }
+
+    #[test]
+    fn test_hmp_format_info() {
+        let status = serde_json::json!({"singlestep": false, "running": true, "status": "running"});
+        assert_eq!(format_info("status", &status), "VM status: running");
+        let status = serde_json::json!({"singlestep": false, "running": false,
+            "status": "guest-panicked", "stop-reason": "guest-panicked"});
+        assert_eq!(
+            format_info("status", &status),
+            "VM status: guest-panicked (stop reason: guest-panicked)"
+        );
+
+        let cpus = serde_json::json!([
+            {"arch": "x86", "current": true, "qom_path": "/machine/unattached/device[0]",
+             "halted": false, "CPU": 0, "thread_id": 1234},
+            {"arch": "x86", "current": false, "qom_path": "/machine/unattached/device[1]",
+             "halted": false, "CPU": 1, "thread_id": 1235}
+        ]);
+        assert_eq!(
+            format_info("cpus", &cpus),
+            "* CPU #0: thread_id=1234\n  CPU #1: thread_id=1235"
+        );
+
+        let block = serde_json::json!([
+            {"device": "drive0", "removable": false, "locked": false,
+             "inserted": {"file": "/path/to/img", "drv": "raw", "ro": true}},
+            {"device": "drive1", "removable": false, "locked": false}
+        ]);
+        assert_eq!(
+            format_info("block", &block),
+            "drive0: /path/to/img (raw, read-only)\ndrive1: [not inserted]"
+        );
+
+        let balloon = serde_json::json!({"actual": 2_u64 << 30});
+        assert_eq!(format_info("balloon", &balloon), "balloon: actual=2048");
+    }
+}
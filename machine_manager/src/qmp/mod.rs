@@ -19,13 +19,16 @@
 //! It has three feature:
 //! 1. Qmp server is no-async service as well as Qemu's.
 //! Command + events can replace asynchronous command.
-//! 2. Qmp server can only be connected a client at one time.
-//! It's no situation where be communicated with many clients.
-//! When it must use, can use other communication way not QMP.
+//! 2. Each Qmp socket can only be connected a client at one time.
+//! Several Qmp sockets can be configured for many clients, each client
+//! negotiates capabilities independently and events are sent to all of them,
+//! unless the client only subscribes to some classes of events.
 //! 3. Qmp's message structure base is transformed by scripts from Qemu's
 //! `qmp-schema.json`. It's can be compatible by Qemu's zoology. Those
 //! transformed structures can be found in `machine_manager/src/qmp/qmp_schema.rs`
 
+pub mod audit;
+pub mod hmp;
 #[allow(non_upper_case_globals)]
 #[allow(non_camel_case_types)]
 #[allow(non_snake_case)]
@@ -33,7 +36,7 @@ pub mod qmp_schema;
 
 use std::collections::BTreeMap;
 use std::io::Write;
-use std::os::unix::io::RawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -44,15 +47,18 @@ use serde_json::Value;
 use util::leak_bucket::LeakBucket;
 use util::set_termi_canon_mode;
 use util::time::NANOSECONDS_PER_SECOND;
+use util::trace;
 
 use self::qmp_schema::{self as schema, QmpCommand};
 use crate::event_loop::EventLoop;
 use crate::machine::MachineExternalInterface;
-use crate::socket::SocketRWHandler;
+use crate::socket::{SocketHandler, SocketRWHandler};
 use crate::temp_cleaner::TempCleaner;
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
 static mut QMP_CHANNEL: Option<Arc<QmpChannel>> = None;
+/// Path prefix to refer to the fd set added by `add-fd`.
+pub const FDSET_PATH_PREFIX: &str = "/dev/fdset/";
 
 /// Macro `event!`: send event to qmp-client.
 ///
@@ -338,7 +344,7 @@ pub fn create_timestamp() -> TimeStamp {
 ///
 /// # Arguments
 ///
-/// * `stream_fd` - The input stream file description.
+/// * `qmp_service` - The handler of the input stream.
 /// * `controller` - The controller which execute actual qmp command.
 /// * `leak_bucket` - The LeakBucket flow controller for qmp command.
 ///
@@ -346,11 +352,11 @@ pub fn create_timestamp() -> TimeStamp {
 ///
 /// This function will fail when json parser failed or socket file description broke.
 pub fn handle_qmp(
-    stream_fd: RawFd,
+    qmp_service: &mut SocketHandler,
     controller: &Arc<Mutex<dyn MachineExternalInterface>>,
     leak_bucket: &mut LeakBucket,
 ) -> Result<()> {
-    let mut qmp_service = crate::socket::SocketHandler::new(stream_fd);
+    let stream_fd = qmp_service.as_raw_fd();
 
     // If flow over `LEAK_BUCKET_LIMIT` per seconds, discard the request and return
     // a `OperationThrottled` error.
@@ -370,21 +376,27 @@ pub fn handle_qmp(
         (Ok(buffer), if_fd) => {
             info!("QMP: <-- {:?}", buffer);
             let qmp_command: schema::QmpCommand = buffer.unwrap();
-            let (return_msg, shutdown_flag) = qmp_command_exec(qmp_command, controller, if_fd);
+            let audit_value =
+                audit::is_enabled().then(|| serde_json::to_value(&qmp_command).unwrap_or_default());
+            let (qmp_response, shutdown_flag) = match qmp_command {
+                QmpCommand::qmp_capabilities { arguments, id } => {
+                    (qmp_capabilities_exec(stream_fd, arguments, id), false)
+                }
+                QmpCommand::event_subscribe { arguments, id } => {
+                    (event_subscribe_exec(stream_fd, arguments, id), false)
+                }
+                _ => qmp_command_exec(qmp_command, controller, if_fd),
+            };
+            if let Some(value) = audit_value {
+                audit::audit_command(qmp_service.peer(), value, &qmp_response);
+            }
+            let return_msg = serde_json::to_string(&qmp_response).unwrap();
             info!("QMP: --> {:?}", return_msg);
             qmp_service.send_str(&return_msg)?;
 
             // handle shutdown command
             if shutdown_flag {
-                let shutdown_msg = schema::Shutdown {
-                    guest: false,
-                    reason: "host-qmp-quit".to_string(),
-                };
-                event!(Shutdown; shutdown_msg);
-                TempCleaner::clean();
-                set_termi_canon_mode().expect("Failed to set terminal to canonical mode.");
-
-                std::process::exit(0);
+                handle_quit("host-qmp-quit");
             }
 
             Ok(())
@@ -400,13 +412,153 @@ pub fn handle_qmp(
     }
 }
 
+/// Negotiate the capabilities of the client connected with `stream_fd`.
+fn qmp_capabilities_exec(
+    stream_fd: RawFd,
+    arguments: schema::qmp_capabilities,
+    id: Option<String>,
+) -> Response {
+    // No capability is supported in greeting message.
+    if let Some(cap) = arguments.enable.as_ref().and_then(|caps| caps.first()) {
+        let err =
+            schema::QmpErrorClass::GenericError(format!("Capability '{}' not available", cap));
+        return Response::create_error_response(err, id);
+    }
+    if !QmpChannel::negotiate(stream_fd) {
+        let err = schema::QmpErrorClass::CommandNotFound(
+            "Capabilities negotiation is already complete, command ignored".to_string(),
+        );
+        return Response::create_error_response(err, id);
+    }
+    let mut response = Response::create_empty_response();
+    response.change_id(id);
+    response
+}
+
+/// Select the event classes sent to the client connected with `stream_fd`.
+fn event_subscribe_exec(
+    stream_fd: RawFd,
+    arguments: schema::event_subscribe,
+    id: Option<String>,
+) -> Response {
+    if let Some(class) = arguments
+        .classes
+        .iter()
+        .flatten()
+        .find(|class| !schema::EVENT_CLASSES.contains(&class.as_str()))
+    {
+        let err = schema::QmpErrorClass::GenericError(format!("Invalid event class '{}'", class));
+        return Response::create_error_response(err, id);
+    }
+    QmpChannel::subscribe(stream_fd, arguments.classes);
+    let mut response = Response::create_empty_response();
+    response.change_id(id);
+    response
+}
+
+/// Close the file descriptor received by `getfd`.
+fn closefd_exec(arguments: schema::closefd) -> Response {
+    match QmpChannel::remove_fd(&arguments.fd_name) {
+        Some(fd) => {
+            // SAFETY: the file descriptor is removed from `QMP_CHANNEL` and owned here.
+            unsafe { libc::close(fd) };
+            Response::create_empty_response()
+        }
+        None => {
+            let err = schema::QmpErrorClass::GenericError(format!(
+                "File descriptor named '{}' not found",
+                arguments.fd_name
+            ));
+            Response::create_error_response(err, None)
+        }
+    }
+}
+
+/// Add the file descriptor received via SCM rights to fd set.
+fn add_fd_exec(arguments: schema::add_fd, if_fd: Option<RawFd>) -> Response {
+    let fd = match if_fd {
+        Some(fd) => fd,
+        None => {
+            let err = schema::QmpErrorClass::GenericError(
+                "No file descriptor supplied via SCM_RIGHTS".to_string(),
+            );
+            return Response::create_error_response(err, None);
+        }
+    };
+    let fdset_id = QmpChannel::add_fdset_fd(arguments.fdset_id, fd, arguments.opaque);
+    let info = schema::AddfdInfo { fdset_id, fd };
+    Response::create_response(serde_json::to_value(info).unwrap(), None)
+}
+
+/// Remove the file descriptor from fd set.
+fn remove_fd_exec(arguments: schema::remove_fd) -> Response {
+    match QmpChannel::remove_fdset_fd(arguments.fdset_id, arguments.fd) {
+        Ok(()) => Response::create_empty_response(),
+        Err(e) => {
+            let err = schema::QmpErrorClass::GenericError(e.to_string());
+            Response::create_error_response(err, None)
+        }
+    }
+}
+
+/// Query the state of trace events.
+fn trace_event_get_state_exec(arguments: schema::trace_event_get_state) -> Response {
+    let events: Vec<schema::TraceEventInfo> = trace::get_trace_events_state(&arguments.name)
+        .into_iter()
+        .map(|(name, enabled)| schema::TraceEventInfo {
+            name,
+            state: if enabled {
+                schema::TraceEventState::enabled
+            } else {
+                schema::TraceEventState::disabled
+            },
+        })
+        .collect();
+    Response::create_response(serde_json::to_value(events).unwrap(), None)
+}
+
+/// Enable or disable trace events.
+fn trace_event_set_state_exec(arguments: schema::trace_event_set_state) -> Response {
+    match trace::set_trace_event_state(&arguments.name, arguments.enable) {
+        Ok(()) => Response::create_empty_response(),
+        Err(e) => {
+            let err = schema::QmpErrorClass::GenericError(e.to_string());
+            Response::create_error_response(err, None)
+        }
+    }
+}
+
+/// Get the fdset id from path with the format of `/dev/fdset/<fdset-id>`.
+///
+/// # Arguments
+///
+/// * `path` - The path of drive or netdev.
+pub fn fdset_id_from_path(path: &str) -> Option<u64> {
+    path.strip_prefix(FDSET_PATH_PREFIX)
+        .and_then(|id| id.parse::<u64>().ok())
+}
+
+/// Report the shutdown event, clean the temporary files and exit the process,
+/// which is the last step of `quit` command.
+fn handle_quit(reason: &str) -> ! {
+    let shutdown_msg = schema::Shutdown {
+        guest: false,
+        reason: reason.to_string(),
+    };
+    event!(Shutdown; shutdown_msg);
+    TempCleaner::clean();
+    set_termi_canon_mode().expect("Failed to set terminal to canonical mode.");
+
+    std::process::exit(0);
+}
+
 /// Create a match , where `qmp_command` and its arguments matching by handle
 /// function, and exec this qmp command.
 fn qmp_command_exec(
     qmp_command: QmpCommand,
     controller: &Arc<Mutex<dyn MachineExternalInterface>>,
     if_fd: Option<RawFd>,
-) -> (String, bool) {
+) -> (Response, bool) {
     let mut qmp_response = Response::create_empty_response();
     let mut shutdown_flag = false;
 
@@ -417,6 +569,7 @@ fn qmp_command_exec(
         (cont, resume),
         (system_powerdown, powerdown),
         (system_reset, reset),
+        (system_wakeup, wakeup),
         (query_status, query_status),
         (query_version, query_version),
         (query_commands, query_commands),
@@ -431,20 +584,24 @@ fn qmp_command_exec(
         (query_qmp_schema, query_qmp_schema),
         (query_sev_capabilities, query_sev_capabilities),
         (query_chardev, query_chardev),
-        (qom_list, qom_list),
-        (qom_get, qom_get),
         (query_block, query_block),
         (query_named_block_nodes, query_named_block_nodes),
         (query_blockstats, query_blockstats),
         (query_block_jobs, query_block_jobs),
         (query_gic_capabilities, query_gic_capabilities),
         (query_iothreads, query_iothreads),
+        (query_pci, query_pci),
         (query_migrate, query_migrate),
+        (query_migrate_parameters, query_migrate_parameters),
         (cancel_migrate, cancel_migrate),
         (query_cpus, query_cpus),
+        (query_cpus_fast, query_cpus_fast),
+        (query_vcpu_stats, query_vcpu_stats),
         (query_balloon, query_balloon),
         (query_mem, query_mem),
+        (query_memory_rss, query_memory_rss),
         (query_vnc, query_vnc),
+        (query_kernel_cmdline, query_kernel_cmdline),
         (list_type, list_type),
         (query_hotpluggable_cpus, query_hotpluggable_cpus);
         (input_event, input_event, key, value),
@@ -452,17 +609,32 @@ fn qmp_command_exec(
         (device_del, device_del, id),
         (blockdev_del, blockdev_del, node_name),
         (netdev_del, netdev_del, id),
+        (object_del, object_del, id),
         (chardev_remove, chardev_remove, id),
         (cameradev_del, cameradev_del,id),
         (balloon, balloon, value),
-        (migrate, migrate, uri);
+        (balloon_set_stats_interval, balloon_set_stats_interval, interval),
+        (watchdog_set_action, watchdog_set_action, action),
+        (set_kernel_cmdline, set_kernel_cmdline, cmdline, append),
+        (set_vcpu_affinity, set_vcpu_affinity, cpu_index, host_cpu),
+        (migrate, migrate, uri),
+        (migrate_check, migrate_check, uri),
+        (savevm, savevm, path, single_file),
+        (loadvm, loadvm, path),
+        (qom_list, qom_list, path),
+        (qom_get, qom_get, path, property),
+        (qom_set, qom_set, path, property, value);
         (device_add, device_add),
         (blockdev_add, blockdev_add),
         (netdev_add, netdev_add),
+        (object_add, object_add),
         (chardev_add, chardev_add),
         (cameradev_add, cameradev_add),
+        (migrate_set_capabilities, migrate_set_capabilities),
+        (migrate_set_parameters, migrate_set_parameters),
         (update_region, update_region),
         (human_monitor_command, human_monitor_command),
+        (guest_agent_command, guest_agent_command),
         (blockdev_snapshot_internal_sync, blockdev_snapshot_internal_sync),
         (blockdev_snapshot_delete_internal_sync, blockdev_snapshot_delete_internal_sync)
     );
@@ -479,24 +651,62 @@ fn qmp_command_exec(
                 qmp_response = controller.lock().unwrap().getfd(arguments.fd_name, if_fd);
                 id
             }
+            QmpCommand::closefd { arguments, id } => {
+                qmp_response = closefd_exec(arguments);
+                id
+            }
+            QmpCommand::add_fd { arguments, id } => {
+                qmp_response = add_fd_exec(arguments, if_fd);
+                id
+            }
+            QmpCommand::remove_fd { arguments, id } => {
+                qmp_response = remove_fd_exec(arguments);
+                id
+            }
+            QmpCommand::query_fdsets { id, .. } => {
+                let fdsets = QmpChannel::query_fdsets();
+                qmp_response =
+                    Response::create_response(serde_json::to_value(fdsets).unwrap(), None);
+                id
+            }
+            QmpCommand::trace_event_get_state { arguments, id } => {
+                qmp_response = trace_event_get_state_exec(arguments);
+                id
+            }
+            QmpCommand::trace_event_set_state { arguments, id } => {
+                qmp_response = trace_event_set_state_exec(arguments);
+                id
+            }
             _ => None,
         }
     }
 
     // Change response id with input qmp message
     qmp_response.change_id(id);
-    (serde_json::to_string(&qmp_response).unwrap(), shutdown_flag)
+    (qmp_response, shutdown_flag)
+}
+
+/// Client connected to qmp socket.
+struct QmpClient {
+    /// The `writer` to send `QmpEvent`.
+    writer: SocketRWHandler,
+    /// Whether the client has negotiated capabilities.
+    negotiated: bool,
+    /// The event classes subscribed by the client, all events are sent if it's `None`.
+    event_classes: Option<Vec<String>>,
 }
 
 /// The struct `QmpChannel` is the only struct can handle Global variable
 /// `QMP_CHANNEL`.
-/// It is used to send event to qmp client and restore some file descriptor
+/// It is used to send event to qmp clients and restore some file descriptor
 /// which was sended by client.
 pub struct QmpChannel {
-    /// The `writer` to send `QmpEvent`.
-    event_writer: RwLock<Option<SocketRWHandler>>,
+    /// The clients to send `QmpEvent`, indexed by the stream fd of client.
+    clients: RwLock<BTreeMap<RawFd, QmpClient>>,
     /// Restore file descriptor received from client.
     fds: Arc<RwLock<BTreeMap<String, RawFd>>>,
+    /// Fd sets added by `add-fd`, indexed by fdset id.
+    fdsets: RwLock<BTreeMap<u64, Vec<schema::FdsetFdInfo>>>,
 }
 
 impl QmpChannel {
@@ -505,30 +715,72 @@ impl QmpChannel {
         unsafe {
             if QMP_CHANNEL.is_none() {
                 QMP_CHANNEL = Some(Arc::new(QmpChannel {
-                    event_writer: RwLock::new(None),
+                    clients: RwLock::new(BTreeMap::new()),
                     fds: Arc::new(RwLock::new(BTreeMap::new())),
+                    fdsets: RwLock::new(BTreeMap::new()),
                 }));
             }
         }
     }
 
-    /// Bind a `SocketRWHandler` to `QMP_CHANNEL`.
+    /// Bind a `SocketRWHandler` of new client to `QMP_CHANNEL`.
     ///
     /// # Arguments
     ///
     /// * `writer` - The `SocketRWHandler` used to communicate with client.
     pub fn bind_writer(writer: SocketRWHandler) {
-        *Self::inner().event_writer.write().unwrap() = Some(writer);
+        let client = QmpClient {
+            writer,
+            negotiated: false,
+            event_classes: None,
+        };
+        Self::inner()
+            .clients
+            .write()
+            .unwrap()
+            .insert(client.writer.as_raw_fd(), client);
     }
 
-    /// Unbind `SocketRWHandler` from `QMP_CHANNEL`.
-    pub fn unbind() {
-        *Self::inner().event_writer.write().unwrap() = None;
+    /// Unbind the `SocketRWHandler` of client from `QMP_CHANNEL`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_fd` - The stream fd of client.
+    pub fn unbind(stream_fd: RawFd) {
+        Self::inner().clients.write().unwrap().remove(&stream_fd);
     }
 
-    /// Check whether a `SocketRWHandler` bind with `QMP_CHANNEL` or not.
+    /// Check whether any `SocketRWHandler` bind with `QMP_CHANNEL` or not.
     pub fn is_connected() -> bool {
-        Self::inner().event_writer.read().unwrap().is_some()
+        !Self::inner().clients.read().unwrap().is_empty()
+    }
+
+    /// Mark the capabilities of client negotiated, return false if they have been
+    /// negotiated before.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_fd` - The stream fd of client.
+    pub fn negotiate(stream_fd: RawFd) -> bool {
+        match Self::inner().clients.write().unwrap().get_mut(&stream_fd) {
+            Some(client) if !client.negotiated => {
+                client.negotiated = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Set the event classes sent to the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_fd` - The stream fd of client.
+    /// * `classes` - The event classes to send, `None` means all events.
+    pub fn subscribe(stream_fd: RawFd, classes: Option<Vec<String>>) {
+        if let Some(client) = Self::inner().clients.write().unwrap().get_mut(&stream_fd) {
+            client.event_classes = classes;
+        }
     }
 
     /// Restore extern file descriptor in `QMP_CHANNEL`.
@@ -550,26 +802,146 @@ impl QmpChannel {
         Self::inner().fds.read().unwrap().get(name).copied()
     }
 
-    /// Send a `QmpEvent` to client.
+    /// Remove extern file descriptor restored in `QMP_CHANNEL`, the caller
+    /// takes the ownership of the returned file descriptor.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of file descriptor.
+    pub fn remove_fd(name: &str) -> Option<RawFd> {
+        Self::inner().fds.write().unwrap().remove(name)
+    }
+
+    /// Add file descriptor to the fd set, return the id of fd set. A new fd set
+    /// with the lowest unused id is created if `fdset_id` is not set.
+    ///
+    /// # Arguments
+    ///
+    /// * `fdset_id` - Id of fd set.
+    /// * `fd` - File descriptor sent by client.
+    /// * `opaque` - Description of the file descriptor.
+    pub fn add_fdset_fd(fdset_id: Option<u64>, fd: RawFd, opaque: Option<String>) -> u64 {
+        let mut fdsets = Self::inner().fdsets.write().unwrap();
+        let id = fdset_id.unwrap_or_else(|| {
+            (0..)
+                .find(|id| !fdsets.contains_key(id))
+                .unwrap_or_default()
+        });
+        fdsets
+            .entry(id)
+            .or_default()
+            .push(schema::FdsetFdInfo { fd, opaque });
+        id
+    }
+
+    /// Remove and close file descriptor in fd set. All file descriptors are
+    /// removed if `fd` is not set, and the fd set is freed when it's empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `fdset_id` - Id of fd set.
+    /// * `fd` - File descriptor to be removed.
+    pub fn remove_fdset_fd(fdset_id: u64, fd: Option<RawFd>) -> Result<()> {
+        let mut fdsets = Self::inner().fdsets.write().unwrap();
+        let fds = fdsets
+            .get_mut(&fdset_id)
+            .with_context(|| format!("Fd set {} not found", fdset_id))?;
+        let removed: Vec<schema::FdsetFdInfo> = match fd {
+            Some(fd) => {
+                let pos = fds
+                    .iter()
+                    .position(|info| info.fd == fd)
+                    .with_context(|| format!("Fd {} not found in fd set {}", fd, fdset_id))?;
+                vec![fds.remove(pos)]
+            }
+            None => fds.drain(..).collect(),
+        };
+        if fds.is_empty() {
+            fdsets.remove(&fdset_id);
+        }
+        for info in removed {
+            // SAFETY: the file descriptor is owned by fd set.
+            unsafe { libc::close(info.fd) };
+        }
+        Ok(())
+    }
+
+    /// Get the information of all fd sets.
+    pub fn query_fdsets() -> Vec<schema::FdsetInfo> {
+        Self::inner()
+            .fdsets
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(id, fds)| schema::FdsetInfo {
+                fdset_id: *id,
+                fds: fds.clone(),
+            })
+            .collect()
+    }
+
+    /// Duplicate a file descriptor in fd set whose access mode matches, the
+    /// caller owns the returned file descriptor. The file descriptor in fd set
+    /// is kept, so that it can be used again, e.g. for reopening the drive.
+    ///
+    /// # Arguments
+    ///
+    /// * `fdset_id` - Id of fd set.
+    /// * `read_only` - Whether the file descriptor is used read-only.
+    pub fn dup_fdset_fd(fdset_id: u64, read_only: bool) -> Result<RawFd> {
+        let fdsets = Self::inner().fdsets.read().unwrap();
+        let fds = fdsets
+            .get(&fdset_id)
+            .with_context(|| format!("Fd set {} not found", fdset_id))?;
+        for info in fds {
+            // SAFETY: the file descriptor is owned by fd set and valid.
+            let flags = unsafe { libc::fcntl(info.fd, libc::F_GETFL) };
+            if flags < 0 {
+                continue;
+            }
+            let acc_mode = flags & libc::O_ACCMODE;
+            if acc_mode == libc::O_RDWR || (read_only && acc_mode == libc::O_RDONLY) {
+                // SAFETY: the file descriptor is valid.
+                let fd = unsafe { libc::fcntl(info.fd, libc::F_DUPFD_CLOEXEC, 0) };
+                if fd < 0 {
+                    return Err(std::io::Error::last_os_error())
+                        .with_context(|| format!("Failed to dup file descriptor {}", info.fd));
+                }
+                return Ok(fd);
+            }
+        }
+        bail!(
+            "No {} file descriptor found in fd set {}",
+            if read_only { "readable" } else { "read-write" },
+            fdset_id
+        );
+    }
+
+    /// Send a `QmpEvent` to all clients.
     ///
     /// # Arguments
     ///
-    /// * `event` - The `QmpEvent` sent to client.
+    /// * `event` - The `QmpEvent` sent to clients.
     #[allow(clippy::unused_io_amount)]
     pub fn send_event(event: &schema::QmpEvent) {
         if Self::is_connected() {
             let mut event_str = serde_json::to_string(&event).unwrap();
-            let mut writer_unlocked = Self::inner().event_writer.write().unwrap();
-            let writer = writer_unlocked.as_mut().unwrap();
-
-            if let Err(e) = writer.flush() {
-                error!("flush err, {:?}", e);
-                return;
-            }
             event_str.push_str("\r\n");
-            if let Err(e) = writer.write(event_str.as_bytes()) {
-                error!("write err, {:?}", e);
-                return;
+            let class = event.class();
+            let mut clients = Self::inner().clients.write().unwrap();
+            for (fd, client) in clients.iter_mut() {
+                if let Some(classes) = client.event_classes.as_ref() {
+                    if !classes.iter().any(|c| c == class) {
+                        continue;
+                    }
+                }
+                if let Err(e) = client.writer.flush() {
+                    error!("flush err for client {}, {:?}", fd, e);
+                    continue;
+                }
+                if let Err(e) = client.writer.write(event_str.as_bytes()) {
+                    error!("write err for client {}, {:?}", fd, e);
+                }
             }
             info!("EVENT: --> {:?}", event);
         }
@@ -648,6 +1020,7 @@ mod tests {
             singlestep: false,
             running: true,
             status: schema::RunState::running,
+            stop_reason: None,
         };
         let resp = Response::create_response(serde_json::to_value(&resp_value).unwrap(), None);
 
@@ -711,22 +1084,34 @@ mod tests {
         let socket = Socket::from_unix_listener(listener, None);
         socket.bind_unix_stream(server);
         QmpChannel::bind_writer(SocketRWHandler::new(socket.get_stream_fd()));
+        let (listener_2, mut client_2, server_2) = prepare_unix_socket_environment("08");
+        let socket_2 = Socket::from_unix_listener(listener_2, None);
+        socket_2.bind_unix_stream(server_2);
+        QmpChannel::bind_writer(SocketRWHandler::new(socket_2.get_stream_fd()));
 
-        // 1.send no-content event
+        // 1.send no-content event, which is broadcast to all clients
         event!(Stop);
-        let length = client.read(&mut buffer).unwrap();
-        let qmp_event: schema::QmpEvent =
-            serde_json::from_str(&(String::from_utf8_lossy(&buffer[..length]))).unwrap();
-        match qmp_event {
-            schema::QmpEvent::Stop {
-                data: _,
-                timestamp: _,
-            } => {
-                assert!(true);
+        for client in [&mut client, &mut client_2] {
+            let length = client.read(&mut buffer).unwrap();
+            let qmp_event: schema::QmpEvent =
+                serde_json::from_str(&(String::from_utf8_lossy(&buffer[..length]))).unwrap();
+            match qmp_event {
+                schema::QmpEvent::Stop {
+                    data: _,
+                    timestamp: _,
+                } => {
+                    assert!(true);
+                }
+                _ => assert!(false),
             }
-            _ => assert!(false),
         }
 
+        // Capabilities are negotiated independently, and only once for each client.
+        assert!(QmpChannel::negotiate(socket.get_stream_fd()));
+        assert!(!QmpChannel::negotiate(socket.get_stream_fd()));
+        assert!(QmpChannel::negotiate(socket_2.get_stream_fd()));
+        QmpChannel::unbind(socket_2.get_stream_fd());
+
         // 2.send with-content event
         let shutdown_event = schema::Shutdown {
             guest: true,
@@ -744,8 +1129,28 @@ mod tests {
             _ => assert!(false),
         }
 
+        // 3.events out of the subscribed classes are not sent
+        QmpChannel::subscribe(socket.get_stream_fd(), Some(vec!["device".to_string()]));
+        event!(Stop);
+        let deleted_event = schema::DeviceDeleted {
+            device: Some("net0".to_string()),
+            path: "/path".to_string(),
+        };
+        event!(DeviceDeleted; deleted_event);
+        let length = client.read(&mut buffer).unwrap();
+        let qmp_event: schema::QmpEvent =
+            serde_json::from_str(&(String::from_utf8_lossy(&buffer[..length]))).unwrap();
+        match qmp_event {
+            schema::QmpEvent::DeviceDeleted { data, timestamp: _ } => {
+                assert_eq!(data.device, Some("net0".to_string()));
+            }
+            _ => assert!(false),
+        }
+        QmpChannel::subscribe(socket.get_stream_fd(), None);
+
         // After test. Environment Recover
         recover_unix_socket_environment("06");
+        recover_unix_socket_environment("08");
     }
 
     #[test]
@@ -820,4 +1225,43 @@ mod tests {
         let resp = Response::create_error_response(qmp_err, None);
         assert_eq!(resp.error, Some(msg));
     }
+
+    #[test]
+    fn test_qmp_fdset() {
+        QmpChannel::object_init();
+        let (fd0, fd1) = create_pipe();
+
+        // Read end of pipe is read-only, write end is write-only.
+        let fdset_id = QmpChannel::add_fdset_fd(Some(100), fd0, Some("rdonly".to_string()));
+        assert_eq!(fdset_id, 100);
+        assert_eq!(QmpChannel::add_fdset_fd(Some(100), fd1, None), fdset_id);
+        let fdsets = QmpChannel::query_fdsets();
+        let fdset = fdsets.iter().find(|set| set.fdset_id == fdset_id).unwrap();
+        assert_eq!(fdset.fds.len(), 2);
+        assert_eq!(fdset.fds[0].opaque, Some("rdonly".to_string()));
+
+        let fd = QmpChannel::dup_fdset_fd(fdset_id, true).unwrap();
+        assert_ne!(fd, fd0);
+        unsafe { libc::close(fd) };
+        assert!(QmpChannel::dup_fdset_fd(fdset_id, false).is_err());
+        assert!(QmpChannel::dup_fdset_fd(101, true).is_err());
+
+        assert_eq!(fdset_id_from_path("/dev/fdset/100"), Some(100));
+        assert_eq!(fdset_id_from_path("/dev/fdset/a"), None);
+        assert_eq!(fdset_id_from_path("/tmp/fdset/100"), None);
+
+        assert!(QmpChannel::remove_fdset_fd(fdset_id, Some(-1)).is_err());
+        assert!(QmpChannel::remove_fdset_fd(fdset_id, Some(fd0)).is_ok());
+        assert!(QmpChannel::remove_fdset_fd(fdset_id, None).is_ok());
+        assert!(QmpChannel::query_fdsets()
+            .iter()
+            .all(|set| set.fdset_id != fdset_id));
+        assert!(QmpChannel::remove_fdset_fd(fdset_id, None).is_err());
+    }
+
+    fn create_pipe() -> (RawFd, RawFd) {
+        let mut fds = [0 as RawFd; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        (fds[0], fds[1])
+    }
 }
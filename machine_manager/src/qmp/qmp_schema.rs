@@ -10,8 +10,11 @@
 // NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
 // See the Mulan PSL v2 for more details.
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 pub use serde_json::Value as Any;
+use strum::IntoEnumIterator;
 use strum_macros::{EnumIter, EnumString, EnumVariantNames};
 
 use super::Version;
@@ -62,6 +65,14 @@ pub enum QmpCommand {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    #[serde(rename = "event-subscribe")]
+    #[strum(serialize = "event-subscribe")]
+    event_subscribe {
+        #[serde(default)]
+        arguments: event_subscribe,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
     quit {
         #[serde(default)]
         arguments: quit,
@@ -92,6 +103,12 @@ pub enum QmpCommand {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    system_wakeup {
+        #[serde(default)]
+        arguments: system_wakeup,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
     device_add {
         arguments: Box<device_add>,
         #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -114,6 +131,20 @@ pub enum QmpCommand {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    #[serde(rename = "object-add")]
+    #[strum(serialize = "object-add")]
+    object_add {
+        arguments: object_add,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "object-del")]
+    #[strum(serialize = "object-del")]
+    object_del {
+        arguments: object_del,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
     netdev_add {
         arguments: Box<netdev_add>,
         #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -134,6 +165,13 @@ pub enum QmpCommand {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    #[serde(rename = "set-vcpu-affinity")]
+    #[strum(serialize = "set-vcpu-affinity")]
+    set_vcpu_affinity {
+        arguments: set_vcpu_affinity,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
     #[serde(rename = "query-hotpluggable-cpus")]
     #[strum(serialize = "query-hotpluggable-cpus")]
     query_hotpluggable_cpus {
@@ -150,6 +188,22 @@ pub enum QmpCommand {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    #[serde(rename = "query-cpus-fast")]
+    #[strum(serialize = "query-cpus-fast")]
+    query_cpus_fast {
+        #[serde(default)]
+        arguments: query_cpus_fast,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "query-vcpu-stats")]
+    #[strum(serialize = "query-vcpu-stats")]
+    query_vcpu_stats {
+        #[serde(default)]
+        arguments: query_vcpu_stats,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
     #[serde(rename = "query-status")]
     query_status {
         #[serde(default)]
@@ -162,6 +216,45 @@ pub enum QmpCommand {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    closefd {
+        arguments: closefd,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "add-fd")]
+    add_fd {
+        #[serde(default)]
+        arguments: add_fd,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "remove-fd")]
+    remove_fd {
+        arguments: remove_fd,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "query-fdsets")]
+    query_fdsets {
+        #[serde(default)]
+        arguments: query_fdsets,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "trace-event-get-state")]
+    #[strum(serialize = "trace-event-get-state")]
+    trace_event_get_state {
+        arguments: trace_event_get_state,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "trace-event-set-state")]
+    #[strum(serialize = "trace-event-set-state")]
+    trace_event_set_state {
+        arguments: trace_event_set_state,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
     #[serde(rename = "blockdev-add")]
     blockdev_add {
         arguments: Box<blockdev_add>,
@@ -188,6 +281,43 @@ pub enum QmpCommand {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    #[serde(rename = "query-memory-rss")]
+    #[strum(serialize = "query-memory-rss")]
+    query_memory_rss {
+        #[serde(default)]
+        arguments: query_memory_rss,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "watchdog-set-action")]
+    #[strum(serialize = "watchdog-set-action")]
+    watchdog_set_action {
+        arguments: watchdog_set_action,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "query-kernel-cmdline")]
+    #[strum(serialize = "query-kernel-cmdline")]
+    query_kernel_cmdline {
+        #[serde(default)]
+        arguments: query_kernel_cmdline,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "set-kernel-cmdline")]
+    #[strum(serialize = "set-kernel-cmdline")]
+    set_kernel_cmdline {
+        arguments: set_kernel_cmdline,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "balloon-set-stats-interval")]
+    #[strum(serialize = "balloon-set-stats-interval")]
+    balloon_set_stats_interval {
+        arguments: balloon_set_stats_interval,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
     #[serde(rename = "query-balloon")]
     query_balloon {
         #[serde(default)]
@@ -209,6 +339,44 @@ pub enum QmpCommand {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    #[serde(rename = "migrate-check")]
+    #[strum(serialize = "migrate-check")]
+    migrate_check {
+        arguments: migrate_check,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "savevm")]
+    savevm {
+        arguments: savevm,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "loadvm")]
+    loadvm {
+        arguments: loadvm,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "migrate-set-capabilities")]
+    migrate_set_capabilities {
+        arguments: migrate_set_capabilities,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "migrate-set-parameters")]
+    migrate_set_parameters {
+        arguments: migrate_set_parameters,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename = "query-migrate-parameters")]
+    query_migrate_parameters {
+        #[serde(default)]
+        arguments: query_migrate_parameters,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
     #[serde(rename = "query-migrate")]
     query_migrate {
         #[serde(default)]
@@ -346,14 +514,20 @@ pub enum QmpCommand {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
-    #[serde(rename = "qom_get")]
-    #[strum(serialize = "qom_get")]
+    #[serde(rename = "qom-get", alias = "qom_get")]
+    #[strum(serialize = "qom-get")]
     qom_get {
-        #[serde(default)]
         arguments: qom_get,
         #[serde(default, skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    #[serde(rename = "qom-set")]
+    #[strum(serialize = "qom-set")]
+    qom_set {
+        arguments: qom_set,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
     #[serde(rename = "query-block")]
     #[strum(serialize = "query-block")]
     query_block {
@@ -402,6 +576,14 @@ pub enum QmpCommand {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    #[serde(rename = "query-pci")]
+    #[strum(serialize = "query-pci")]
+    query_pci {
+        #[serde(default)]
+        arguments: query_pci,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
     #[serde(rename = "update_region")]
     #[strum(serialize = "update_region")]
     update_region {
@@ -434,11 +616,27 @@ pub enum QmpCommand {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    #[serde(rename = "guest-agent-command")]
+    #[strum(serialize = "guest-agent-command")]
+    guest_agent_command {
+        arguments: guest_agent_command,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
 }
 
 /// qmp_capabilities
 ///
-/// Enable QMP capabilities.
+/// Enable QMP capabilities. Each client negotiates its capabilities independently,
+/// and it can be executed only once by a client.
+///
+/// # Arguments
+///
+/// * `enable` - The capabilities to enable, which are listed in greeting message.
+///
+/// # Errors
+///
+/// If the capabilities have been negotiated by the client, CommandNotFound.
 ///
 /// # Examples
 ///
@@ -448,7 +646,10 @@ pub enum QmpCommand {
 /// ```
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
-pub struct qmp_capabilities {}
+pub struct qmp_capabilities {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enable: Option<Vec<String>>,
+}
 
 impl Command for qmp_capabilities {
     type Res = Empty;
@@ -458,6 +659,39 @@ impl Command for qmp_capabilities {
     }
 }
 
+/// event-subscribe
+///
+/// Select the classes of events sent to the client, which only affects the
+/// client executing the command.
+///
+/// # Arguments
+///
+/// * `classes` - The event classes to receive, one of "lifecycle", "device",
+///   "balloon", "guest-agent", "block" and "migration". All events are sent
+///   if it is not set.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "event-subscribe",
+///      "arguments": { "classes": [ "lifecycle", "migration" ] } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct event_subscribe {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub classes: Option<Vec<String>>,
+}
+
+impl Command for event_subscribe {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
 /// quit
 ///
 /// This command will cause the StratoVirt process to exit gracefully. While every
@@ -571,6 +805,28 @@ impl Command for system_reset {
     }
 }
 
+/// system_wakeup
+///
+/// Wake up guest from suspend. If the guest is not suspended, an error is returned.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "system_wakeup" }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct system_wakeup {}
+
+impl Command for system_wakeup {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
 /// device_add
 ///
 /// # Arguments
@@ -641,6 +897,14 @@ pub struct device_add {
     pub productid: Option<String>,
     pub isobufs: Option<String>,
     pub isobsize: Option<String>,
+    pub failover: Option<bool>,
+    pub failover_pair_id: Option<String>,
+    #[serde(rename = "coalesce-frames")]
+    pub coalesce_frames: Option<u16>,
+    #[serde(rename = "coalesce-usecs")]
+    pub coalesce_usecs: Option<u32>,
+    pub memdev: Option<String>,
+    pub slot: Option<u8>,
 }
 
 pub type DeviceAddArgument = device_add;
@@ -1016,6 +1280,69 @@ impl Command for blockdev_del {
     }
 }
 
+/// object-add
+///
+/// Create a backend object at runtime, such as iothread, memory backend, tls
+/// credentials and secret.
+///
+/// # Arguments
+///
+/// * `qom-type` - The type of the object.
+/// * `id` - The name of the new object.
+/// * Other properties are the same as those of `-object` in cmdline.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "object-add",
+///      "arguments": { "qom-type": "iothread", "id": "iothread1" } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct object_add {
+    #[serde(rename = "qom-type")]
+    pub qom_type: String,
+    pub id: String,
+    #[serde(flatten)]
+    pub props: BTreeMap<String, Any>,
+}
+
+impl Command for object_add {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
+/// object-del
+///
+/// Remove a backend object which is not used by any device.
+///
+/// # Arguments
+///
+/// * `id` - The name of the object to remove.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "object-del", "arguments": { "id": "mem1" } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct object_del {
+    pub id: String,
+}
+
+impl Command for object_del {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
 /// netdev_del
 ///
 /// Remove a network backend.
@@ -1115,6 +1442,109 @@ impl Command for query_hotpluggable_cpus {
     }
 }
 
+/// set-vcpu-affinity
+///
+/// Pin the thread of vcpu to a host CPU.
+///
+/// # Arguments
+///
+/// * `cpu-index` - the index of vcpu.
+/// * `host-cpu` - the index of host CPU.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "set-vcpu-affinity", "arguments": { "cpu-index": 1, "host-cpu": 5 } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct set_vcpu_affinity {
+    #[serde(rename = "cpu-index")]
+    pub cpu_index: usize,
+    #[serde(rename = "host-cpu")]
+    pub host_cpu: usize,
+}
+
+impl Command for set_vcpu_affinity {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
+/// query-vcpu-stats
+///
+/// Returns the runtime statistics of each virtual CPU, the exits are the ones
+/// handled in userspace.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "query-vcpu-stats" }
+/// <- { "return": [
+///          {
+///             "cpu-index": 0,
+///             "thread-id": 25627,
+///             "run-time-ns": 5293018372,
+///             "halt-time-ns": 4960193844,
+///             "kvm-exits": 103982,
+///             "exits": { "io": 8801, "mmio": 1240, "hlt": 0, "shutdown": 0,
+///                        "system-event": 0, "debug": 0, "intr": 12, "other": 0 }
+///          }
+///       ]
+///    }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct query_vcpu_stats {}
+
+impl Command for query_vcpu_stats {
+    type Res = Vec<VcpuStats>;
+
+    fn back(self) -> Vec<VcpuStats> {
+        Default::default()
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct VcpuStats {
+    #[serde(rename = "cpu-index")]
+    pub cpu_index: isize,
+    #[serde(rename = "thread-id")]
+    pub thread_id: isize,
+    /// Time in nanoseconds spent in KVM_RUN, including the halt time.
+    #[serde(rename = "run-time-ns")]
+    pub run_time_ns: u64,
+    /// Time in nanoseconds the vcpu is halted in kvm, `None` if it's not provided by kvm.
+    #[serde(
+        rename = "halt-time-ns",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub halt_time_ns: Option<u64>,
+    /// Number of all the exits including the ones handled in kvm, `None` if it's not
+    /// provided by kvm.
+    #[serde(rename = "kvm-exits", default, skip_serializing_if = "Option::is_none")]
+    pub kvm_exits: Option<u64>,
+    pub exits: VcpuExitStats,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct VcpuExitStats {
+    pub io: u64,
+    pub mmio: u64,
+    pub hlt: u64,
+    pub shutdown: u64,
+    #[serde(rename = "system-event")]
+    pub system_event: u64,
+    pub debug: u64,
+    /// Exits caused by signals, e.g. to pause the vcpu.
+    pub intr: u64,
+    pub other: u64,
+}
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct HotpluggableCPU {
@@ -1241,42 +1671,102 @@ pub struct CpuInfoX86 {}
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct CpuInfoArm {}
 
-/// query-status
+/// query-cpus-fast
 ///
-/// Query the run status of all VCPUs.
+/// Returns information about all virtual CPUs without interrupting the vCPU
+/// threads.
 ///
 /// # Returns
 ///
-/// `StatusInfo` reflecting all VCPUs.
+/// A list of `CpuInfoFast` for each virtual CPU.
 ///
 /// # Examples
 ///
 /// ```text
-/// -> { "execute": "query-status" }
-/// <- { "return": { "running": true,
-///                  "singlestep": false,
-///                  "status": "running" } }
+/// -> { "execute": "query-cpus-fast" }
+/// <- { "return": [
+///          {
+///             "cpu-index": 0,
+///             "qom-path": "/machine/unattached/device[0]",
+///             "thread-id": 25627,
+///             "props": {
+///                "core-id": 0,
+///                "socket-id": 0,
+///                "thread-id": 0
+///             },
+///             "target": "x86_64"
+///          }
+///       ]
+///    }
 /// ```
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
-pub struct query_status {}
+pub struct query_cpus_fast {}
 
-impl Command for query_status {
-    type Res = StatusInfo;
+impl Command for query_cpus_fast {
+    type Res = Vec<CpuInfoFast>;
 
-    fn back(self) -> StatusInfo {
+    fn back(self) -> Vec<CpuInfoFast> {
         Default::default()
     }
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
-pub struct StatusInfo {
-    #[serde(rename = "singlestep")]
-    pub singlestep: bool,
-    #[serde(rename = "running")]
-    pub running: bool,
-    #[serde(rename = "status")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpuInfoFast {
+    #[serde(rename = "cpu-index")]
+    pub cpu_index: isize,
+    #[serde(rename = "qom-path")]
+    pub qom_path: String,
+    #[serde(rename = "thread-id")]
+    pub thread_id: isize,
+    #[serde(rename = "props", default, skip_serializing_if = "Option::is_none")]
+    pub props: Option<CpuInstanceProperties>,
+    #[serde(rename = "target")]
+    pub target: String,
+}
+
+/// query-status
+///
+/// Query the run status of all VCPUs.
+///
+/// # Returns
+///
+/// `StatusInfo` reflecting all VCPUs.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "query-status" }
+/// <- { "return": { "running": true,
+///                  "singlestep": false,
+///                  "status": "running" } }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct query_status {}
+
+impl Command for query_status {
+    type Res = StatusInfo;
+
+    fn back(self) -> StatusInfo {
+        Default::default()
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct StatusInfo {
+    #[serde(rename = "singlestep")]
+    pub singlestep: bool,
+    #[serde(rename = "running")]
+    pub running: bool,
+    #[serde(rename = "status")]
     pub status: RunState,
+    #[serde(
+        rename = "stop-reason",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub stop_reason: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -1339,9 +1829,240 @@ impl Command for migrate {
     }
 }
 
+/// migrate-check
+///
+/// Check whether the destination VM is able to receive migration of the current
+/// VM before migration, by comparing machine type and version, vCPUs, memory,
+/// devices, device state versions and CPU features. The destination VM must be
+/// started with `-incoming`, and it keeps waiting for migration after the check.
+///
+/// # Arguments
+///
+/// * `uri` - the Uniform Resource Identifier of the destination VM, tcp, unix and rdma are supported.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "migrate-check", "arguments": { "uri": "tcp:192.168.1.2:4446" } }
+/// <- { "return": { "compatible": false,
+///      "errors": ["Migration config vCPU number mismatch: source 4, destination 2."] } }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct migrate_check {
+    pub uri: String,
+}
+
+impl Command for migrate_check {
+    type Res = MigrateCheckInfo;
+
+    fn back(self) -> MigrateCheckInfo {
+        Default::default()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigrateCheckInfo {
+    /// Whether the destination VM is compatible.
+    pub compatible: bool,
+    /// Incompatibilities found by the destination VM.
+    pub errors: Vec<String>,
+}
+
+/// savevm
+///
+/// Save the memory and device state of VM to the snapshot dir. The running VM is
+/// paused during saving and resumed after that.
+///
+/// # Arguments
+///
+/// * `path` - the snapshot dir, it is created if not exists.
+/// * `single-file` - save the snapshot to the single file `path` instead of dir.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "savevm", "arguments": { "path": "/path/to/snapshot" } }
+/// <- { "return": {} }
+/// -> { "execute": "savevm",
+///      "arguments": { "path": "/path/to/snapshot.img", "single-file": true } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct savevm {
+    pub path: String,
+    #[serde(rename = "single-file", default)]
+    pub single_file: Option<bool>,
+}
+
+impl Command for savevm {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
+/// loadvm
+///
+/// Load the memory and device state of VM from the snapshot dir or file saved by
+/// `savevm`, and resume the VM. It can only be executed before the VM starts running,
+/// that is, the VM is launched with `-S`.
+///
+/// # Arguments
+///
+/// * `path` - the snapshot dir or file.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "loadvm", "arguments": { "path": "/path/to/snapshot" } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct loadvm {
+    pub path: String,
+}
+
+impl Command for loadvm {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
+/// migrate-set-capabilities
+///
+/// Enable or disable migration capabilities. Supported capabilities are:
+/// `compress`, which compresses memory pages with zstd before sending them, and
+/// `block`, which mirrors writable drives to the NBD server at `block-uri`.
+///
+/// # Arguments
+///
+/// * `capabilities` - the list of capabilities and their new state.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "migrate-set-capabilities",
+///      "arguments": { "capabilities": [ { "capability": "compress", "state": true } ] } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct migrate_set_capabilities {
+    pub capabilities: Vec<MigrateCapabilities>,
+}
+
+impl Command for migrate_set_capabilities {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
+/// migrate-set-parameters
+///
+/// Set the parameters of migration. Parameters which are not given are left
+/// unchanged.
+///
+/// # Arguments
+///
+/// * `compress-level` - zstd compression level of memory pages, from 1 to 22.
+/// * `compress-threads` - number of threads compressing memory pages, from 1 to 255.
+/// * `max-bandwidth` - max bandwidth of sending memory in bytes per second, 0 means unlimited.
+/// * `downtime-limit` - max downtime of VM in milliseconds, from 0 to 2000000.
+/// * `block-uri` - address of NBD server of destination for storage migration,
+///                 `tcp:<ip>:<port>` or `unix:<path>`.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "migrate-set-parameters",
+///      "arguments": { "max-bandwidth": 134217728, "downtime-limit": 300 } }
+/// <- { "return": {} }
+/// -> { "execute": "migrate-set-parameters",
+///      "arguments": { "block-uri": "tcp:192.168.1.2:10809" } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct migrate_set_parameters {
+    #[serde(rename = "compress-level")]
+    pub compress_level: Option<i32>,
+    #[serde(rename = "compress-threads")]
+    pub compress_threads: Option<u8>,
+    #[serde(rename = "max-bandwidth")]
+    pub max_bandwidth: Option<u64>,
+    #[serde(rename = "downtime-limit")]
+    pub downtime_limit: Option<u64>,
+    #[serde(rename = "block-uri")]
+    pub block_uri: Option<String>,
+}
+
+impl Command for migrate_set_parameters {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
+/// query-migrate-parameters
+///
+/// Query the current parameters of migration.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "query-migrate-parameters" }
+/// <- { "return": { "compress-level": 1, "compress-threads": 8, "max-bandwidth": 0,
+///      "downtime-limit": 50 } }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct query_migrate_parameters {}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationParameters {
+    #[serde(rename = "compress-level")]
+    pub compress_level: i32,
+    #[serde(rename = "compress-threads")]
+    pub compress_threads: u8,
+    #[serde(rename = "max-bandwidth")]
+    pub max_bandwidth: u64,
+    #[serde(rename = "downtime-limit")]
+    pub downtime_limit: u64,
+    #[serde(rename = "block-uri", skip_serializing_if = "Option::is_none")]
+    pub block_uri: Option<String>,
+}
+
+impl Command for query_migrate_parameters {
+    type Res = MigrationParameters;
+
+    fn back(self) -> MigrationParameters {
+        Default::default()
+    }
+}
+
 /// query-migrate:
 ///
 /// Returns information about current migration.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "query-migrate" }
+/// <- { "return": { "status": "active", "total-time": 1532, "expected-downtime": 21,
+///                  "throttle-percentage": 0,
+///                  "ram": { "transferred": 1073741824, "remaining": 10485760,
+///                           "total": 2147483648, "dirtied": 41943040,
+///                           "dirty-pages-rate": 2560, "dirty-sync-count": 3,
+///                           "page-size": 4096 } } }
+/// ```
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct query_migrate {}
 
@@ -1371,6 +2092,48 @@ impl Command for cancel_migrate {
 pub struct MigrationInfo {
     #[serde(rename = "status", default, skip_serializing_if = "Option::is_none")]
     pub status: Option<String>,
+    #[serde(
+        rename = "total-time",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub total_time: Option<u64>,
+    #[serde(
+        rename = "expected-downtime",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expected_downtime: Option<u64>,
+    #[serde(
+        rename = "throttle-percentage",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub throttle_percentage: Option<u64>,
+    #[serde(rename = "ram", default, skip_serializing_if = "Option::is_none")]
+    pub ram: Option<MigrationStats>,
+}
+
+/// Memory statistics of migration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigrationStats {
+    /// Bytes of memory data transferred to the destination VM.
+    pub transferred: u64,
+    /// Bytes of memory remaining to be sent in the current pass.
+    pub remaining: u64,
+    /// Total size of guest memory in bytes.
+    pub total: u64,
+    /// Bytes of memory dirtied since migration starts.
+    pub dirtied: u64,
+    /// Pages dirtied per second in the last iteration.
+    #[serde(rename = "dirty-pages-rate")]
+    pub dirty_pages_rate: u64,
+    /// Number of times the dirty log is synchronized.
+    #[serde(rename = "dirty-sync-count")]
+    pub dirty_sync_count: u64,
+    /// Size of the page counted in `dirty-pages-rate`.
+    #[serde(rename = "page-size")]
+    pub page_size: u64,
 }
 
 /// getfd
@@ -1402,6 +2165,233 @@ impl Command for getfd {
     }
 }
 
+/// closefd
+///
+/// Close a file descriptor previously passed via SCM rights by `getfd`.
+///
+/// # Arguments
+///
+/// * `fdname` - File descriptor name.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "closefd", "arguments": { "fdname": "fd1" } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct closefd {
+    #[serde(rename = "fdname")]
+    pub fd_name: String,
+}
+
+impl Command for closefd {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
+/// add-fd
+///
+/// Add a file descriptor, that was passed via SCM rights, to an fd set. The fd set
+/// can be used by the path "/dev/fdset/<fdset-id>" of drive and netdev.
+///
+/// # Arguments
+///
+/// * `fdset-id` - The ID of the fd set to add the file descriptor to, a new fd set
+///   is created if it's not set.
+/// * `opaque` - A free-form string that can be used to describe the fd.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "add-fd", "arguments": { "fdset-id": 1 } }
+/// <- { "return": { "fdset-id": 1, "fd": 3 } }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct add_fd {
+    #[serde(rename = "fdset-id", default, skip_serializing_if = "Option::is_none")]
+    pub fdset_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opaque: Option<String>,
+}
+
+impl Command for add_fd {
+    type Res = AddfdInfo;
+
+    fn back(self) -> AddfdInfo {
+        Default::default()
+    }
+}
+
+/// Information about a file descriptor that was added to an fd set.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddfdInfo {
+    #[serde(rename = "fdset-id")]
+    pub fdset_id: u64,
+    pub fd: i32,
+}
+
+/// remove-fd
+///
+/// Remove a file descriptor from an fd set.
+///
+/// # Arguments
+///
+/// * `fdset-id` - The ID of the fd set that the file descriptor belongs to.
+/// * `fd` - The file descriptor to remove, all the file descriptors in the fd set
+///   are removed if it's not set.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "remove-fd", "arguments": { "fdset-id": 1, "fd": 3 } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct remove_fd {
+    #[serde(rename = "fdset-id")]
+    pub fdset_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fd: Option<i32>,
+}
+
+impl Command for remove_fd {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
+/// query-fdsets
+///
+/// Return information describing all fd sets.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "query-fdsets" }
+/// <- { "return": [ { "fdset-id": 1, "fds": [ { "fd": 3, "opaque": "rdonly:/path/to/img" } ] } ] }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct query_fdsets {}
+
+impl Command for query_fdsets {
+    type Res = Vec<FdsetInfo>;
+
+    fn back(self) -> Vec<FdsetInfo> {
+        Default::default()
+    }
+}
+
+/// Information about an fd set.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FdsetInfo {
+    #[serde(rename = "fdset-id")]
+    pub fdset_id: u64,
+    pub fds: Vec<FdsetFdInfo>,
+}
+
+/// Information about a file descriptor in an fd set.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FdsetFdInfo {
+    pub fd: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opaque: Option<String>,
+}
+
+/// trace-event-get-state
+///
+/// Query the state of trace events.
+///
+/// # Arguments
+///
+/// * `name` - Event name pattern, `*` matches any characters. Only the enabled
+///   events are listed if the pattern contains `*`.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "trace-event-get-state", "arguments": { "name": "trace_request" } }
+/// <- { "return": [ { "name": "trace_request", "state": "enabled" } ] }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct trace_event_get_state {
+    pub name: String,
+}
+
+impl Command for trace_event_get_state {
+    type Res = Vec<TraceEventInfo>;
+
+    fn back(self) -> Vec<TraceEventInfo> {
+        Default::default()
+    }
+}
+
+/// trace-event-set-state
+///
+/// Enable or disable trace events at runtime.
+///
+/// # Arguments
+///
+/// * `name` - Event name pattern, `*` matches any characters. A pattern with `*`
+///   applies to the enabled events, so it can only be used to disable events.
+/// * `enable` - Whether to enable the events.
+/// * `ignore-unavailable` - Accepted for compatibility, all events are available.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "trace-event-set-state",
+///      "arguments": { "name": "trace_request", "enable": true } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct trace_event_set_state {
+    pub name: String,
+    pub enable: bool,
+    #[serde(
+        rename = "ignore-unavailable",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ignore_unavailable: Option<bool>,
+}
+
+impl Command for trace_event_set_state {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
+/// State of a trace event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TraceEventState {
+    #[serde(rename = "disabled")]
+    #[default]
+    disabled,
+    #[serde(rename = "enabled")]
+    enabled,
+}
+
+/// Information of a trace event.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceEventInfo {
+    pub name: String,
+    pub state: TraceEventState,
+}
+
 /// Shutdown
 ///
 /// Emitted when the virtual machine has shut down, indicating that StratoVirt is
@@ -1456,6 +2446,115 @@ pub struct Resume {}
 #[serde(deny_unknown_fields)]
 pub struct Powerdown {}
 
+/// Suspend
+///
+/// Emitted when guest enters a hardware suspension state, for example, S3 state.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Suspend {}
+
+/// Wakeup
+///
+/// Emitted when the guest has woken up from suspend state and is running.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Wakeup {}
+
+/// Watchdog
+///
+/// Emitted when the watchdog device's timer is expired.
+///
+/// # Examples
+///
+/// ```text
+/// <- { "event": "WATCHDOG",
+///      "data": { "action": "reset" },
+///      "timestamp": { "seconds": 1265044230, "microseconds": 450486 } }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Watchdog {
+    /// Action that has been taken, one of "reset", "shutdown", "pause",
+    /// "inject-nmi" and "none".
+    pub action: String,
+}
+
+/// GuestPanicked
+///
+/// Emitted when guest OS panic is detected.
+///
+/// # Examples
+///
+/// ```text
+/// <- { "event": "GUEST_PANICKED",
+///      "data": { "action": "pause",
+///                "info": { "type": "kvm-crash", "cpu": 0, "pc": 18446744071579263954 } },
+///      "timestamp": { "seconds": 1265044230, "microseconds": 450486 } }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct GuestPanicked {
+    /// Action that has been taken, one of "pause", "poweroff" and "run".
+    #[serde(rename = "action")]
+    pub action: String,
+    /// Information about the panic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info: Option<GuestPanicInformation>,
+}
+
+/// Information about the guest panic.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct GuestPanicInformation {
+    /// Source of the panic, "pvpanic" or "kvm-crash".
+    #[serde(rename = "type")]
+    pub panic_type: String,
+    /// Index of the vCPU which reported the crash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<u8>,
+    /// Program counter of the crashed vCPU.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pc: Option<u64>,
+}
+
+/// VcpuExitFailure
+///
+/// Emitted when a vCPU fails to run with `KVM_EXIT_INTERNAL_ERROR` or
+/// `KVM_EXIT_FAIL_ENTRY`, and the vCPU stops.
+///
+/// # Examples
+///
+/// ```text
+/// <- { "event": "VCPU_EXIT_FAILURE",
+///      "data": { "cpu": 0, "reason": "internal-error", "suberror": 1, "data": [],
+///                "pc": 18446744071579263954, "last-access": "mmio-write 0xfee00300 len 4" },
+///      "timestamp": { "seconds": 1265044230, "microseconds": 450486 } }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct VcpuExitFailure {
+    /// Index of the failed vCPU.
+    pub cpu: u8,
+    /// "internal-error" or "fail-entry".
+    pub reason: String,
+    /// Suberror of "internal-error".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suberror: Option<u32>,
+    /// Data of "internal-error" from kvm, or hardware entry failure reason and
+    /// host CPU of "fail-entry".
+    pub data: Vec<u64>,
+    /// Program counter of the failed vCPU.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pc: Option<u64>,
+    /// The last MMIO/PIO access of the vCPU.
+    #[serde(
+        rename = "last-access",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_access: Option<String>,
+}
+
 /// DeviceDeleted
 ///
 /// Emitted whenever the device removal completion is acknowledged by the guest.
@@ -1470,55 +2569,229 @@ pub struct Powerdown {}
 ///                "path": "/machine/peripheral/virtio-net-mmio-0" },
 ///      "timestamp": { "seconds": 1265044230, "microseconds": 450486 } }
 /// ```
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceDeleted {
+    /// Device name.
+    #[serde(rename = "device", default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// Device path.
+    #[serde(rename = "path")]
+    pub path: String,
+}
+
+/// GuestAgentResponse
+///
+/// Emitted when qemu-guest-agent replies the command passed through by
+/// `guest-agent-command`.
+///
+/// # Examples
+///
+/// ```text
+/// <- { "event": "GUEST_AGENT_RESPONSE",
+///      "data": { "response": "{\"return\": {}}" },
+///      "timestamp": { "seconds": 1265044230, "microseconds": 450486 } }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct GuestAgentResponse {
+    /// Reply of guest agent.
+    pub response: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, EnumIter, EnumVariantNames, EnumString)]
+#[serde(tag = "event")]
+pub enum QmpEvent {
+    #[serde(rename = "SHUTDOWN")]
+    Shutdown {
+        data: Shutdown,
+        timestamp: TimeStamp,
+    },
+    #[serde(rename = "RESET")]
+    Reset { data: Reset, timestamp: TimeStamp },
+    #[serde(rename = "STOP")]
+    Stop {
+        #[serde(default)]
+        data: Stop,
+        timestamp: TimeStamp,
+    },
+    #[serde(rename = "RESUME")]
+    Resume {
+        #[serde(default)]
+        data: Resume,
+        timestamp: TimeStamp,
+    },
+    #[serde(rename = "POWERDOWN")]
+    Powerdown {
+        #[serde(default)]
+        data: Powerdown,
+        timestamp: TimeStamp,
+    },
+    #[serde(rename = "SUSPEND")]
+    Suspend {
+        #[serde(default)]
+        data: Suspend,
+        timestamp: TimeStamp,
+    },
+    #[serde(rename = "WAKEUP")]
+    Wakeup {
+        #[serde(default)]
+        data: Wakeup,
+        timestamp: TimeStamp,
+    },
+    #[serde(rename = "GUEST_PANICKED")]
+    GuestPanicked {
+        data: GuestPanicked,
+        timestamp: TimeStamp,
+    },
+    #[serde(rename = "VCPU_EXIT_FAILURE")]
+    VcpuExitFailure {
+        data: VcpuExitFailure,
+        timestamp: TimeStamp,
+    },
+    #[serde(rename = "WATCHDOG")]
+    Watchdog {
+        data: Watchdog,
+        timestamp: TimeStamp,
+    },
+    #[serde(rename = "DEVICE_DELETED")]
+    DeviceDeleted {
+        data: DeviceDeleted,
+        timestamp: TimeStamp,
+    },
+    #[serde(rename = "BALLOON_CHANGED")]
+    BalloonChanged {
+        data: BalloonInfo,
+        timestamp: TimeStamp,
+    },
+    #[serde(rename = "BALLOON_CHANGE")]
+    BalloonChange {
+        data: BalloonInfo,
+        timestamp: TimeStamp,
+    },
+    #[serde(rename = "GUEST_AGENT_RESPONSE")]
+    GuestAgentResponse {
+        data: GuestAgentResponse,
+        timestamp: TimeStamp,
+    },
+}
+
+/// Classes of events which a client can subscribe to.
+pub const EVENT_CLASSES: [&str; 6] = [
+    "lifecycle",
+    "device",
+    "balloon",
+    "guest-agent",
+    "block",
+    "migration",
+];
+
+impl QmpEvent {
+    /// Get the class of the event, which is one of `EVENT_CLASSES`.
+    pub fn class(&self) -> &'static str {
+        match self {
+            QmpEvent::Shutdown { .. }
+            | QmpEvent::Reset { .. }
+            | QmpEvent::Stop { .. }
+            | QmpEvent::Resume { .. }
+            | QmpEvent::Powerdown { .. }
+            | QmpEvent::Suspend { .. }
+            | QmpEvent::Wakeup { .. }
+            | QmpEvent::GuestPanicked { .. }
+            | QmpEvent::VcpuExitFailure { .. }
+            | QmpEvent::Watchdog { .. } => "lifecycle",
+            QmpEvent::DeviceDeleted { .. } => "device",
+            QmpEvent::BalloonChanged { .. } | QmpEvent::BalloonChange { .. } => "balloon",
+            QmpEvent::GuestAgentResponse { .. } => "guest-agent",
+        }
+    }
+}
+
+/// watchdog-set-action
+///
+/// Set the action taken when the watchdog timer expires.
+///
+/// # Arguments
+///
+/// * `action` - One of "reset", "shutdown", "pause", "inject-nmi" and "none".
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "watchdog-set-action", "arguments": { "action": "pause" } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct watchdog_set_action {
+    pub action: String,
+}
+
+impl Command for watchdog_set_action {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
+
+/// query-kernel-cmdline
+///
+/// Query the kernel cmdline used at the next boot of VM.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "query-kernel-cmdline" }
+/// <- { "return": { "cmdline": "console=ttyS0 reboot=k panic=1" } }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct query_kernel_cmdline {}
+
+impl Command for query_kernel_cmdline {
+    type Res = KernelCmdlineInfo;
+
+    fn back(self) -> KernelCmdlineInfo {
+        Default::default()
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct KernelCmdlineInfo {
+    pub cmdline: String,
+}
+
+/// set-kernel-cmdline
+///
+/// Amend the kernel cmdline, which takes effect at the next boot of VM.
+///
+/// # Arguments
+///
+/// * `cmdline` - Kernel cmdline parameters.
+/// * `append` - Append to the current kernel cmdline instead of replacing it, default false.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "set-kernel-cmdline",
+///      "arguments": { "cmdline": "quiet", "append": true } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
-pub struct DeviceDeleted {
-    /// Device name.
-    #[serde(rename = "device", default, skip_serializing_if = "Option::is_none")]
-    pub device: Option<String>,
-    /// Device path.
-    #[serde(rename = "path")]
-    pub path: String,
+pub struct set_kernel_cmdline {
+    pub cmdline: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub append: Option<bool>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, EnumIter, EnumVariantNames, EnumString)]
-#[serde(tag = "event")]
-pub enum QmpEvent {
-    #[serde(rename = "SHUTDOWN")]
-    Shutdown {
-        data: Shutdown,
-        timestamp: TimeStamp,
-    },
-    #[serde(rename = "RESET")]
-    Reset { data: Reset, timestamp: TimeStamp },
-    #[serde(rename = "STOP")]
-    Stop {
-        #[serde(default)]
-        data: Stop,
-        timestamp: TimeStamp,
-    },
-    #[serde(rename = "RESUME")]
-    Resume {
-        #[serde(default)]
-        data: Resume,
-        timestamp: TimeStamp,
-    },
-    #[serde(rename = "POWERDOWN")]
-    Powerdown {
-        #[serde(default)]
-        data: Powerdown,
-        timestamp: TimeStamp,
-    },
-    #[serde(rename = "DEVICE_DELETED")]
-    DeviceDeleted {
-        data: DeviceDeleted,
-        timestamp: TimeStamp,
-    },
-    #[serde(rename = "BALLOON_CHANGED")]
-    BalloonChanged {
-        data: BalloonInfo,
-        timestamp: TimeStamp,
-    },
+impl Command for set_kernel_cmdline {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
 }
 
 /// query-balloon:
@@ -1547,6 +2820,116 @@ impl Command for query_balloon {
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct BalloonInfo {
     pub actual: u64,
+    /// Guest memory statistics, only reported if the stats virtqueue is negotiated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<BalloonStats>,
+}
+
+/// Guest memory statistics reported by the stats virtqueue of balloon device.
+/// The statistics which guest doesn't report are omitted. Sizes are in bytes.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalloonStats {
+    /// Amount of memory swapped in.
+    #[serde(
+        rename = "stat-swap-in",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub swap_in: Option<u64>,
+    /// Amount of memory swapped out.
+    #[serde(
+        rename = "stat-swap-out",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub swap_out: Option<u64>,
+    /// Number of major page faults.
+    #[serde(
+        rename = "stat-major-faults",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub major_faults: Option<u64>,
+    /// Number of minor page faults.
+    #[serde(
+        rename = "stat-minor-faults",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub minor_faults: Option<u64>,
+    /// Amount of memory not used for any purpose.
+    #[serde(
+        rename = "stat-free-memory",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub free_memory: Option<u64>,
+    /// Total amount of memory available to guest.
+    #[serde(
+        rename = "stat-total-memory",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub total_memory: Option<u64>,
+    /// Estimation of memory available for starting new applications.
+    #[serde(
+        rename = "stat-available-memory",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub available_memory: Option<u64>,
+    /// Amount of memory used for disk caches which can be reclaimed quickly.
+    #[serde(
+        rename = "stat-disk-caches",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub disk_caches: Option<u64>,
+    /// Number of successful hugetlb page allocations.
+    #[serde(
+        rename = "stat-htlb-pgalloc",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub htlb_pgalloc: Option<u64>,
+    /// Number of failed hugetlb page allocations.
+    #[serde(
+        rename = "stat-htlb-pgfail",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub htlb_pgfail: Option<u64>,
+    /// Time of the last update, in seconds since the Unix epoch.
+    #[serde(rename = "last-update")]
+    pub last_update: u64,
+}
+
+/// balloon-set-stats-interval
+///
+/// Set the interval to request guest memory statistics by the stats virtqueue.
+///
+/// # Arguments
+///
+/// * `interval` - Polling interval in seconds, 0 stops polling.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "balloon-set-stats-interval", "arguments": { "interval": 5 } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct balloon_set_stats_interval {
+    pub interval: u64,
+}
+
+impl Command for balloon_set_stats_interval {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
 }
 
 /// query-vnc:
@@ -1646,7 +3029,7 @@ impl Command for balloon {
 ///
 /// ```text
 /// -> { "execute": "query-version" }
-/// <- {"return":{"version":{"qemu":{"minor":1,"micro":0,"major":5},"package":"StratoVirt-2.2.0"},"capabilities":[]}}
+/// <- {"return":{"qemu":{"micro":1,"minor":0,"major":5},"package":"StratoVirt-2.2.0"}}
 /// ```
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct query_version {}
@@ -1668,19 +3051,9 @@ impl Command for query_version {
 /// ```text
 /// -> { "execute": "query-commands" }
 /// <- {"return":[{"name":"qmp_capabilities"},{"name":"quit"},{"name":"stop"},{"name":"cont"},
-/// {"name":"system_powerdown"},{"name":"system_reset"},{"name":"device_add"},{"name":"device_del"},
-/// {"name":"chardev_add"},{"name":"chardev_remove"},{"name":"netdev_add"},{"name":"netdev_del"},
-/// {"name":"cameradev_add"},{"name":"cameradev_del"},{"name":"query-hotpluggable-cpus"},
-/// {"name":"query-cpus"},{"name":"query_status"},{"name":"getfd"},{"name":"blockdev_add"},
-/// {"name":"blockdev_del"},{"name":"balloon"},{"name":"query_balloon"},{"name":"query-vnc"},
-/// {"name":"migrate"},{"name":"query_migrate"},{"name":"cancel_migrate"},{"name":"query_version"},
-/// {"name":"query_commands"},{"name":"query_target"},{"name":"query_kvm"},{"name":"query_machines"},
-/// {"name":"query-events"},{"name":"list_type"},{"name":"device_list_properties"},{"name":"block-commit"},
-/// {"name":"query_tpm_models"},{"name":"query_tpm_types"},{"name":"query_command_line_options"},
-/// {"name":"query_migrate_capabilities"},{"name":"query_qmp_schema"},{"name":"query_sev_capabilities"},
-/// {"name":"query-chardev"},{"name":"qom-list"},{"name":"qom_get"},{"name":"query-block"},{"name":"query-named-block-nodes"},
-/// {"name":"query-blockstats"},{"name":"query-block-jobs"},{"name":"query-gic-capabilities"},{"name":"query-iothreads"},
-/// {"name":"update_region"},{"name":"input_event"},{"name":"human_monitor_command"}]}
+/// {"name":"system_powerdown"},{"name":"system_reset"},{"name":"system_wakeup"},{"name":"device_add"},
+/// {"name":"device_del"},{"name":"chardev-add"},{"name":"chardev-remove"},{"name":"netdev_add"},
+/// {"name":"netdev_del"},{"name":"cameradev_add"},{"name":"cameradev_del"},...]}
 /// ```
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct query_commands {}
@@ -1772,9 +3145,9 @@ impl Command for query_machines {
 ///
 /// ```text
 /// -> { "execute": "query-events" }
-/// <- {"return":[{"name":"Shutdown"},{"name":"Reset"},
-/// {"name":"Stop"},{"name":"Resume"},{"name":"DeviceDeleted"},
-/// {"name":"BalloonChanged"}]}
+/// <- {"return":[{"name":"SHUTDOWN"},{"name":"RESET"},
+/// {"name":"STOP"},{"name":"RESUME"},{"name":"DEVICE_DELETED"},
+/// {"name":"BALLOON_CHANGED"}]}
 /// ```
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Events {
@@ -1988,25 +3361,150 @@ impl Command for query_migrate_capabilities {
     }
 }
 
-/// Query target of StratoVirt.
+/// Query the schema of all qmp commands and events of StratoVirt.
+///
+/// # Notes
+///
+/// The arguments of a command or the data of an event is described by an object
+/// named `<name>-arg`. The member which can be omitted is marked as `optional`.
 ///
 /// # Example
 ///
 /// ```text
 /// -> { "execute": "query-qmp-schema" }
-/// <- {"return":{}}
+/// <- {"return":[{"name":"device_del","meta-type":"command","arg-type":"device_del-arg"},
+///     {"name":"device_del-arg","meta-type":"object","members":[{"name":"id","type":"str"}]},...]}
 /// ```
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct query_qmp_schema {}
 
 impl Command for query_qmp_schema {
-    type Res = Empty;
+    type Res = Vec<SchemaInfo>;
 
-    fn back(self) -> Empty {
+    fn back(self) -> Vec<SchemaInfo> {
         Default::default()
     }
 }
 
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SchemaInfo {
+    pub name: String,
+    /// One of `command`, `event` and `object`.
+    #[serde(rename = "meta-type")]
+    pub meta_type: String,
+    #[serde(rename = "arg-type", skip_serializing_if = "Option::is_none")]
+    pub arg_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub members: Option<Vec<SchemaMember>>,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SchemaMember {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub member_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optional: Option<bool>,
+}
+
+impl SchemaInfo {
+    /// Create the schema of a command or an event, followed by the schema of its arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the command or event.
+    /// * `meta_type` - `command` or `event`.
+    /// * `args` - The serialized default arguments.
+    fn from_args(name: &str, meta_type: &str, args: Option<&Any>) -> Vec<SchemaInfo> {
+        let arg_type = format!("{}-arg", name);
+        let mut members = Vec::new();
+        if let Some(Any::Object(fields)) = args {
+            for (field, value) in fields {
+                members.push(SchemaMember {
+                    name: field.clone(),
+                    member_type: json_type_name(value).to_string(),
+                    // Only the optional member is serialized as null by default.
+                    optional: value.is_null().then_some(true),
+                });
+            }
+        }
+
+        vec![
+            SchemaInfo {
+                name: name.to_string(),
+                meta_type: meta_type.to_string(),
+                arg_type: Some(arg_type.clone()),
+                members: None,
+            },
+            SchemaInfo {
+                name: arg_type,
+                meta_type: "object".to_string(),
+                arg_type: None,
+                members: Some(members),
+            },
+        ]
+    }
+}
+
+/// Get the type name of json value, which is used in qmp introspection.
+pub fn json_type_name(value: &Any) -> &'static str {
+    match value {
+        Any::Null => "any",
+        Any::Bool(_) => "bool",
+        Any::Number(n) if n.is_f64() => "number",
+        Any::Number(_) => "int",
+        Any::String(_) => "str",
+        Any::Array(_) => "array",
+        Any::Object(_) => "object",
+    }
+}
+
+/// Get the names of all qmp commands, which are the same as the `execute` field in request.
+pub fn qmp_command_names() -> Vec<String> {
+    QmpCommand::iter()
+        .filter_map(|cmd| {
+            serde_json::to_value(cmd)
+                .ok()
+                .and_then(|value| value["execute"].as_str().map(String::from))
+        })
+        .collect()
+}
+
+/// Get the names of all qmp events, which are the same as the `event` field in notification.
+pub fn qmp_event_names() -> Vec<String> {
+    QmpEvent::iter()
+        .filter_map(|event| {
+            serde_json::to_value(event)
+                .ok()
+                .and_then(|value| value["event"].as_str().map(String::from))
+        })
+        .collect()
+}
+
+/// Get the schema of all qmp commands and events.
+pub fn qmp_schema_info() -> Vec<SchemaInfo> {
+    let mut schema = Vec::new();
+    for cmd in QmpCommand::iter() {
+        if let Ok(value) = serde_json::to_value(cmd) {
+            if let Some(name) = value["execute"].as_str() {
+                schema.append(&mut SchemaInfo::from_args(
+                    name,
+                    "command",
+                    value.get("arguments"),
+                ));
+            }
+        }
+    }
+    for event in QmpEvent::iter() {
+        if let Ok(value) = serde_json::to_value(event) {
+            if let Some(name) = value["event"].as_str() {
+                schema.append(&mut SchemaInfo::from_args(name, "event", value.get("data")));
+            }
+        }
+    }
+    schema
+}
+
 /// Query capabilities of sev.
 ///
 /// # Example
@@ -2026,16 +3524,25 @@ impl Command for query_sev_capabilities {
     }
 }
 
-/// List all Qom.
+/// List the properties of the object at the qom path.
+///
+/// # Arguments
+///
+/// * `path` - The path of object, "/" is used if not set.
 ///
 /// # Example
 ///
 /// ```text
-/// -> { "execute": "qom-list" }
-/// <- {"return":[]}
+/// -> { "execute": "qom-list", "arguments": { "path": "/machine" } }
+/// <- { "return": [ { "name": "type", "type": "str" },
+///                  { "name": "peripheral", "type": "child<container>" } ] }
 /// ```
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-pub struct qom_list {}
+#[serde(deny_unknown_fields)]
+pub struct qom_list {
+    #[serde(default)]
+    pub path: String,
+}
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct PropList {
@@ -2079,21 +3586,62 @@ impl Command for query_chardev {
     }
 }
 
-/// Get qom properties.
+/// Get the value of a property of the object at the qom path.
+///
+/// # Arguments
+///
+/// * `path` - The path of object.
+/// * `property` - The name of property.
 ///
 /// # Example
 ///
 /// ```text
-/// -> { "execute": "qom_get" }
-/// <- {"return":[]}
+/// -> { "execute": "qom-get",
+///      "arguments": { "path": "/machine/peripheral/net-0", "property": "link" } }
+/// <- { "return": true }
 /// ```
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-pub struct qom_get {}
+#[serde(deny_unknown_fields)]
+pub struct qom_get {
+    pub path: String,
+    pub property: String,
+}
 
 impl Command for qom_get {
-    type Res = bool;
+    type Res = Any;
+
+    fn back(self) -> Any {
+        Default::default()
+    }
+}
+
+/// Set the value of a writable property of the object at the qom path.
+///
+/// # Arguments
+///
+/// * `path` - The path of object.
+/// * `property` - The name of property.
+/// * `value` - The new value of property.
+///
+/// # Example
+///
+/// ```text
+/// -> { "execute": "qom-set",
+///      "arguments": { "path": "/machine/peripheral/net-0", "property": "link", "value": false } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct qom_set {
+    pub path: String,
+    pub property: String,
+    pub value: Any,
+}
+
+impl Command for qom_set {
+    type Res = Empty;
 
-    fn back(self) -> bool {
+    fn back(self) -> Empty {
         Default::default()
     }
 }
@@ -2104,19 +3652,54 @@ impl Command for qom_get {
 ///
 /// ```text
 /// -> { "execute": "query-block" }
-/// <- {"return":[]}
+/// <- {"return":[{"device":"drive-0","qdev":"blk-0","removable":false,"locked":false,
+///     "inserted":{"file":"/path/to/block","node-name":"drive-0","ro":false,"drv":"raw",
+///     "encrypted":false,"cache":{"writeback":true,"direct":true,"no-flush":false}}}]}
 /// ```
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct query_block {}
 
 impl Command for query_block {
-    type Res = Vec<Cmd>;
+    type Res = Vec<BlockInfo>;
 
-    fn back(self) -> Vec<Cmd> {
+    fn back(self) -> Vec<BlockInfo> {
         Default::default()
     }
 }
 
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// The id of the drive.
+    pub device: String,
+    /// The id of the device which the drive is attached to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qdev: Option<String>,
+    pub removable: bool,
+    pub locked: bool,
+    /// The medium of the drive, it is none if the medium is ejected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inserted: Option<BlockDeviceInfo>,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BlockDeviceInfo {
+    pub file: String,
+    #[serde(rename = "node-name")]
+    pub node_name: String,
+    pub ro: bool,
+    pub drv: String,
+    pub encrypted: bool,
+    pub cache: BlockdevCacheInfo,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BlockdevCacheInfo {
+    pub writeback: bool,
+    pub direct: bool,
+    #[serde(rename = "no-flush")]
+    pub no_flush: bool,
+}
+
 /// Query named block node.
 ///
 /// # Example
@@ -2214,13 +3797,13 @@ pub struct query_iothreads {}
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct IothreadInfo {
     #[serde(rename = "poll-shrink")]
-    pub shrink: u32,
+    pub shrink: u64,
     #[serde(rename = "thread-id")]
     pub pid: u32,
     #[serde(rename = "poll-grow")]
-    pub grow: u32,
+    pub grow: u64,
     #[serde(rename = "poll-max-ns")]
-    pub max: u32,
+    pub max: u64,
     pub id: String,
 }
 
@@ -2231,6 +3814,118 @@ impl Command for query_iothreads {
         Default::default()
     }
 }
+
+/// Query information of PCI buses and devices.
+///
+/// # Example
+///
+/// ```text
+/// -> { "execute": "query-pci" }
+/// <- { "return": [ { "bus": 0, "devices": [ { "bus": 0, "slot": 0, "function": 0,
+///      "class_info": { "desc": "Host bridge", "class": 1536 },
+///      "id": { "device": 11, "vendor": 6966, "subsystem": 0, "subsystem-vendor": 0 },
+///      "irq_pin": 0, "qdev_id": "", "regions": [] } ] } ] }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct query_pci {}
+
+impl Command for query_pci {
+    type Res = Vec<PciInfo>;
+
+    fn back(self) -> Vec<PciInfo> {
+        Default::default()
+    }
+}
+
+/// Information of a PCI root bus.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PciInfo {
+    pub bus: u8,
+    pub devices: Vec<PciDeviceInfo>,
+}
+
+/// Information of a PCI device.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PciDeviceInfo {
+    pub bus: u8,
+    pub slot: u8,
+    pub function: u8,
+    pub class_info: PciDeviceClass,
+    pub id: PciDeviceId,
+    /// The interrupt line routed to, it's not set if the device doesn't use INTx.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub irq: Option<u8>,
+    /// The INTx pin, 1 for INTA# and 0 for no INTx.
+    pub irq_pin: u8,
+    pub qdev_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pci_bridge: Option<PciBridgeInfo>,
+    pub regions: Vec<PciMemoryRegion>,
+}
+
+/// Class code of a PCI device, `class` is base class << 8 | sub class.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PciDeviceClass {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub desc: Option<String>,
+    pub class: u16,
+}
+
+/// Vendor and device IDs of a PCI device.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PciDeviceId {
+    pub device: u16,
+    pub vendor: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subsystem: Option<u16>,
+    #[serde(
+        rename = "subsystem-vendor",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub subsystem_vendor: Option<u16>,
+}
+
+/// Information of a PCI bridge and devices behind it.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PciBridgeInfo {
+    pub bus: PciBusInfo,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub devices: Option<Vec<PciDeviceInfo>>,
+}
+
+/// Bus numbers and address windows of a PCI bridge.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PciBusInfo {
+    pub number: u8,
+    pub secondary: u8,
+    pub subordinate: u8,
+    pub io_range: PciMemoryRange,
+    pub memory_range: PciMemoryRange,
+    pub prefetchable_range: PciMemoryRange,
+}
+
+/// Address window of a PCI bridge.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PciMemoryRange {
+    pub base: u64,
+    pub limit: u64,
+}
+
+/// BAR of a PCI device. `address` is all ones if the BAR is not mapped.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PciMemoryRegion {
+    pub bar: u8,
+    #[serde(rename = "type")]
+    pub region_type: String,
+    pub address: u64,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefetch: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mem_type_64: Option<bool>,
+}
 /// input_event
 ///
 /// # Arguments
@@ -2279,7 +3974,39 @@ pub struct human_monitor_command {
     #[serde(rename = "command-line")]
     pub command_line: String,
 }
-pub type HumanMonitorCmdArgument = human_monitor_command;
+pub type HumanMonitorCmdArgument = human_monitor_command;
+
+/// guest-agent-command
+///
+/// Pass a command through to qemu-guest-agent in guest. The command is written to
+/// the virtio serial port named "org.qemu.guest_agent.0", and the reply of guest
+/// agent is reported by `GUEST_AGENT_RESPONSE` event.
+///
+/// # Arguments
+///
+/// * `command` - the guest agent command in json format.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "guest-agent-command",
+///      "arguments": { "command": "{\"execute\": \"guest-ping\"}" } }
+/// <- { "return": {} }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct guest_agent_command {
+    pub command: String,
+}
+pub type GuestAgentCmdArgument = guest_agent_command;
+
+impl Command for guest_agent_command {
+    type Res = Empty;
+
+    fn back(self) -> Empty {
+        Default::default()
+    }
+}
 
 /// blockdev-snapshot-internal-sync
 ///
@@ -2372,6 +4099,48 @@ impl Command for query_mem {
     }
 }
 
+/// query-memory-rss
+///
+/// Returns the host memory actually consumed by each range of guest RAM, which
+/// is the size of pages resident in host.
+///
+/// # Examples
+///
+/// ```text
+/// -> { "execute": "query-memory-rss" }
+/// <- { "return": [
+///          {
+///             "name": "MachineRam",
+///             "guest-addr": 0,
+///             "size": 3221225472,
+///             "resident": 419430400
+///          }
+///       ]
+///    }
+/// ```
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct query_memory_rss {}
+
+impl Command for query_memory_rss {
+    type Res = Vec<MemoryRss>;
+
+    fn back(self) -> Vec<MemoryRss> {
+        Default::default()
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryRss {
+    /// Name of the memory region.
+    pub name: String,
+    #[serde(rename = "guest-addr")]
+    pub guest_addr: u64,
+    pub size: u64,
+    /// Size in bytes of the pages resident in host.
+    pub resident: u64,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2486,6 +4255,19 @@ mod tests {
         let ret_msg = r#"invalid type: string "isdf", expected struct system_reset"#;
         assert!(err_msg == ret_msg);
 
+        // qmp: system_wakeup.
+        let json_msg = r#"
+        {
+            "execute": "system_wakeup"
+        }
+        "#;
+        let err_msg = match serde_json::from_str::<QmpCommand>(json_msg) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        };
+        let ret_msg = r#"ok"#;
+        assert!(err_msg == ret_msg);
+
         // qmp: query-hotpluggable-cpus.
         let json_msg = r#"
         {
@@ -2540,6 +4322,20 @@ mod tests {
         let ret_msg = r#"invalid type: string "isdf", expected struct query_cpus"#;
         assert!(err_msg == ret_msg);
 
+        // unexpected arguments for query-cpus-fast.
+        let json_msg = r#"
+        {
+            "execute": "query-cpus-fast" ,
+            "arguments": "isdf"
+        }
+        "#;
+        let err_msg = match serde_json::from_str::<QmpCommand>(json_msg) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        };
+        let ret_msg = r#"invalid type: string "isdf", expected struct query_cpus_fast"#;
+        assert!(err_msg == ret_msg);
+
         // qmp: query-ststus.
         let json_msg = r#"
         {
@@ -2702,6 +4498,51 @@ mod tests {
         let ret_msg = r#"ok"#;
         assert!(err_msg == ret_msg);
 
+        // add-fd without fdset-id.
+        let json_msg = r#"
+        {
+            "execute": "add-fd"
+        }
+        "#;
+        let err_msg = match serde_json::from_str::<QmpCommand>(json_msg) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        };
+        let ret_msg = r#"ok"#;
+        assert!(err_msg == ret_msg);
+
+        // missing fdset-id for remove-fd.
+        let json_msg = r#"
+        {
+            "execute": "remove-fd",
+            "arguments": {
+                "fd": 3
+            }
+        }
+        "#;
+        let err_msg = match serde_json::from_str::<QmpCommand>(json_msg) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        };
+        let ret_msg = r#"missing field `fdset-id`"#;
+        assert!(err_msg.contains(ret_msg));
+
+        // missing enable for trace-event-set-state.
+        let json_msg = r#"
+        {
+            "execute": "trace-event-set-state",
+            "arguments": {
+                "name": "trace_request"
+            }
+        }
+        "#;
+        let err_msg = match serde_json::from_str::<QmpCommand>(json_msg) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        };
+        let ret_msg = r#"missing field `enable`"#;
+        assert!(err_msg.contains(ret_msg));
+
         // right arguments for blockdev-add.
         let json_msg = r#"
         {
@@ -2819,6 +4660,67 @@ mod tests {
         assert!(err_msg.contains(part_msg));
     }
 
+    #[test]
+    fn test_qmp_schema_info() {
+        let names = qmp_command_names();
+        assert!(names.contains(&"query-version".to_string()));
+        assert!(names.contains(&"chardev-add".to_string()));
+        assert!(names.contains(&"migrate_cancel".to_string()));
+        // Every command name can be used to execute the command.
+        for name in names.iter() {
+            let json_msg = format!(r#"{{"execute": "{}"}}"#, name);
+            let err_msg = match serde_json::from_str::<QmpCommand>(&json_msg) {
+                Ok(_) => "ok".to_string(),
+                Err(e) => e.to_string(),
+            };
+            assert!(!err_msg.contains("unknown variant"));
+        }
+
+        let schema = qmp_schema_info();
+        let cmd = schema.iter().find(|s| s.name == "device_del").unwrap();
+        assert_eq!(cmd.meta_type, "command");
+        assert_eq!(cmd.arg_type, Some("device_del-arg".to_string()));
+        let args = schema.iter().find(|s| s.name == "device_del-arg").unwrap();
+        assert_eq!(args.meta_type, "object");
+        assert_eq!(
+            args.members,
+            Some(vec![SchemaMember {
+                name: "id".to_string(),
+                member_type: "str".to_string(),
+                optional: None,
+            }])
+        );
+        let events = qmp_event_names();
+        assert!(events.contains(&"SHUTDOWN".to_string()));
+        assert!(events.contains(&"BALLOON_CHANGE".to_string()));
+
+        let event = schema.iter().find(|s| s.name == "SHUTDOWN").unwrap();
+        assert_eq!(event.meta_type, "event");
+    }
+
+    #[test]
+    fn test_qmp_event_class() {
+        for event in QmpEvent::iter() {
+            assert!(EVENT_CLASSES.contains(&event.class()));
+        }
+        let event: QmpEvent = serde_json::from_str(
+            r#"{"event":"DEVICE_DELETED","data":{"path":"/path"},"timestamp":{"seconds":0,"microseconds":0}}"#,
+        )
+        .unwrap();
+        assert_eq!(event.class(), "device");
+
+        let cmd: QmpCommand = serde_json::from_str(
+            r#"{"execute":"event-subscribe","arguments":{"classes":["lifecycle"]}}"#,
+        )
+        .unwrap();
+        match cmd {
+            QmpCommand::event_subscribe { arguments, .. } => {
+                assert_eq!(arguments.classes, Some(vec!["lifecycle".to_string()]));
+            }
+            _ => panic!("Unexpected command"),
+        }
+    }
+
     #[test]
     fn test_qmp_netdev_add() {
         // Normal netdev_add test.
@@ -2882,6 +4784,227 @@ mod tests {
         assert!(err_msg.contains(part_msg));
     }
 
+    #[test]
+    fn test_qmp_object_add() {
+        let json_msg = r#"
+        {
+            "execute": "object-add",
+            "arguments": {
+                "qom-type": "memory-backend-ram",
+                "id": "mem1",
+                "size": 1073741824,
+                "share": true
+            }
+        }
+        "#;
+        match serde_json::from_str::<QmpCommand>(json_msg).unwrap() {
+            QmpCommand::object_add { arguments, .. } => {
+                assert_eq!(arguments.qom_type, "memory-backend-ram");
+                assert_eq!(arguments.id, "mem1");
+                assert_eq!(arguments.props.len(), 2);
+                assert_eq!(arguments.props["size"], 1073741824_u64);
+                assert_eq!(arguments.props["share"], true);
+            }
+            _ => panic!("Unexpected command"),
+        }
+
+        // Abnormal object-add test without id.
+        let json_msg = r#"
+        {
+            "execute": "object-add",
+            "arguments": {
+                "qom-type": "iothread"
+            }
+        }
+        "#;
+        let err_msg = match serde_json::from_str::<QmpCommand>(json_msg) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        };
+        let part_msg = r#"missing field `id`"#;
+        assert!(err_msg.contains(part_msg));
+    }
+
+    #[test]
+    fn test_qmp_migrate_set_parameters() {
+        let json_msg = r#"
+        {
+            "execute": "migrate-set-parameters",
+            "arguments": {
+                "compress-level": 3,
+                "downtime-limit": 300
+            }
+        }
+        "#;
+        match serde_json::from_str::<QmpCommand>(json_msg).unwrap() {
+            QmpCommand::migrate_set_parameters { arguments, .. } => {
+                assert_eq!(arguments.compress_level, Some(3));
+                assert_eq!(arguments.compress_threads, None);
+                assert_eq!(arguments.max_bandwidth, None);
+                assert_eq!(arguments.downtime_limit, Some(300));
+                assert_eq!(arguments.block_uri, None);
+            }
+            _ => panic!("Unexpected command"),
+        }
+
+        let json_msg = r#"
+        {
+            "execute": "migrate-set-parameters",
+            "arguments": {
+                "block-uri": "tcp:192.168.1.2:10809"
+            }
+        }
+        "#;
+        match serde_json::from_str::<QmpCommand>(json_msg).unwrap() {
+            QmpCommand::migrate_set_parameters { arguments, .. } => {
+                assert_eq!(
+                    arguments.block_uri,
+                    Some("tcp:192.168.1.2:10809".to_string())
+                );
+            }
+            _ => panic!("Unexpected command"),
+        }
+
+        // Abnormal migrate-set-parameters test with unknown parameter.
+        let json_msg = r#"
+        {
+            "execute": "migrate-set-parameters",
+            "arguments": {
+                "compress-wait-thread": true
+            }
+        }
+        "#;
+        let err_msg = match serde_json::from_str::<QmpCommand>(json_msg) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        };
+        let part_msg = r#"unknown field `compress-wait-thread`"#;
+        assert!(err_msg.contains(part_msg));
+    }
+
+    #[test]
+    fn test_qmp_set_vcpu_affinity() {
+        let json_msg = r#"
+        {
+            "execute": "set-vcpu-affinity",
+            "arguments": {
+                "cpu-index": 1,
+                "host-cpu": 5
+            }
+        }
+        "#;
+        match serde_json::from_str::<QmpCommand>(json_msg).unwrap() {
+            QmpCommand::set_vcpu_affinity { arguments, .. } => {
+                assert_eq!(arguments.cpu_index, 1);
+                assert_eq!(arguments.host_cpu, 5);
+            }
+            _ => panic!("Unexpected command"),
+        }
+
+        let json_msg = r#"
+        {
+            "execute": "set-vcpu-affinity",
+            "arguments": {
+                "cpu-index": 1
+            }
+        }
+        "#;
+        let err_msg = match serde_json::from_str::<QmpCommand>(json_msg) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        };
+        assert!(err_msg.contains("missing field `host-cpu`"));
+    }
+
+    #[test]
+    fn test_qmp_savevm() {
+        let json_msg = r#"
+        {
+            "execute": "savevm",
+            "arguments": {
+                "path": "/path/to/snapshot"
+            }
+        }
+        "#;
+        match serde_json::from_str::<QmpCommand>(json_msg).unwrap() {
+            QmpCommand::savevm { arguments, .. } => {
+                assert_eq!(arguments.path, "/path/to/snapshot");
+                assert_eq!(arguments.single_file, None);
+            }
+            _ => panic!("Unexpected command"),
+        }
+
+        let json_msg = r#"
+        {
+            "execute": "savevm",
+            "arguments": {
+                "path": "/path/to/snapshot.img",
+                "single-file": true
+            }
+        }
+        "#;
+        match serde_json::from_str::<QmpCommand>(json_msg).unwrap() {
+            QmpCommand::savevm { arguments, .. } => {
+                assert_eq!(arguments.single_file, Some(true));
+            }
+            _ => panic!("Unexpected command"),
+        }
+    }
+
+    #[test]
+    fn test_qmp_migrate_check() {
+        let json_msg = r#"
+        {
+            "execute": "migrate-check",
+            "arguments": {
+                "uri": "tcp:192.168.1.2:4446"
+            }
+        }
+        "#;
+        match serde_json::from_str::<QmpCommand>(json_msg).unwrap() {
+            QmpCommand::migrate_check { arguments, .. } => {
+                assert_eq!(arguments.uri, "tcp:192.168.1.2:4446");
+            }
+            _ => panic!("Unexpected command"),
+        }
+
+        let info = MigrateCheckInfo {
+            compatible: false,
+            errors: vec!["CPU features missing on destination: avx512f".to_string()],
+        };
+        let json_msg =
+            r#"{"compatible":false,"errors":["CPU features missing on destination: avx512f"]}"#;
+        assert_eq!(serde_json::to_string(&info).unwrap(), json_msg);
+    }
+
+    #[test]
+    fn test_qmp_query_migrate() {
+        let info = MigrationInfo {
+            status: Some("completed".to_string()),
+            ..Default::default()
+        };
+        let json_msg = r#"{"status":"completed"}"#;
+        assert_eq!(serde_json::to_string(&info).unwrap(), json_msg);
+
+        let info = MigrationInfo {
+            status: Some("active".to_string()),
+            total_time: Some(1532),
+            expected_downtime: Some(21),
+            throttle_percentage: Some(0),
+            ram: Some(MigrationStats {
+                transferred: 1024,
+                remaining: 512,
+                total: 4096,
+                dirtied: 2048,
+                dirty_pages_rate: 10,
+                dirty_sync_count: 3,
+                page_size: 4096,
+            }),
+        };
+        let json_msg = r#"{"status":"active","total-time":1532,"expected-downtime":21,"throttle-percentage":0,"ram":{"transferred":1024,"remaining":512,"total":4096,"dirtied":2048,"dirty-pages-rate":10,"dirty-sync-count":3,"page-size":4096}}"#;
+        assert_eq!(serde_json::to_string(&info).unwrap(), json_msg);
+    }
+
     #[test]
     fn test_qmp_input_event() {
         // key event
@@ -2952,4 +5075,93 @@ mod tests {
         let part_msg = r#"unknown field `invalid_key`, expected `command-line`"#;
         assert!(err_msg.contains(part_msg));
     }
+
+    #[test]
+    fn test_qmp_guest_agent_command() {
+        // Normal test.
+        let json_msg = r#"
+        {
+            "execute": "guest-agent-command" ,
+            "arguments": {
+                "command": "{\"execute\": \"guest-network-get-interfaces\"}"
+            }
+        }
+        "#;
+        let err_msg = match serde_json::from_str::<QmpCommand>(json_msg) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        };
+        let part_msg = r#"ok"#;
+        assert!(err_msg.contains(part_msg));
+
+        // Abnormal test with invalid arguments.
+        let json_msg = r#"
+        {
+            "execute": "guest-agent-command" ,
+            "arguments": {
+                "cmd": "guest-ping"
+            }
+        }
+        "#;
+        let err_msg = match serde_json::from_str::<QmpCommand>(json_msg) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        };
+        let part_msg = r#"unknown field `cmd`, expected `command`"#;
+        assert!(err_msg.contains(part_msg));
+    }
+
+    #[test]
+    fn test_qmp_guest_panicked_event() {
+        let panicked = GuestPanicked {
+            action: "pause".to_string(),
+            info: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&panicked).unwrap(),
+            r#"{"action":"pause"}"#
+        );
+
+        let panicked = GuestPanicked {
+            action: "poweroff".to_string(),
+            info: Some(GuestPanicInformation {
+                panic_type: "kvm-crash".to_string(),
+                cpu: Some(1),
+                pc: Some(4096),
+            }),
+        };
+        assert_eq!(
+            serde_json::to_string(&panicked).unwrap(),
+            r#"{"action":"poweroff","info":{"type":"kvm-crash","cpu":1,"pc":4096}}"#
+        );
+    }
+
+    #[test]
+    fn test_qmp_vcpu_exit_failure_event() {
+        let failure = VcpuExitFailure {
+            cpu: 1,
+            reason: "fail-entry".to_string(),
+            suberror: None,
+            data: vec![0x80000021, 3],
+            pc: None,
+            last_access: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&failure).unwrap(),
+            r#"{"cpu":1,"reason":"fail-entry","data":[2147483681,3]}"#
+        );
+
+        let failure = VcpuExitFailure {
+            cpu: 0,
+            reason: "internal-error".to_string(),
+            suberror: Some(1),
+            data: Vec::new(),
+            pc: Some(4096),
+            last_access: Some("pio-out 0x3f8 len 1".to_string()),
+        };
+        assert_eq!(
+            serde_json::to_string(&failure).unwrap(),
+            r#"{"cpu":0,"reason":"internal-error","suberror":1,"data":[],"pc":4096,"last-access":"pio-out 0x3f8 len 1"}"#
+        );
+    }
 }
@@ -0,0 +1,602 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+//! This module implements a REST API compatible with a subset of Firecracker's API.
+//!
+//! The API is served over HTTP/1.1 on a unix socket. Each connection carries one
+//! request, which is translated to the same `MachineExternalInterface` calls as QMP.
+//! Supported requests:
+//! 1. `GET /` and `GET /machine-config` to query the VM.
+//! 2. `PUT /machine-config` to check the configuration of the created VM.
+//! 3. `PUT /drives/{drive_id}` and `PUT /network-interfaces/{iface_id}` to attach
+//!    devices to micro VM.
+//! 4. `PUT /actions` with `InstanceStart` and `SendCtrlAltDel`.
+//! 5. `PATCH /vm` to pause and resume the VM.
+
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use log::{error, info};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use util::loop_context::{EventNotifier, EventNotifierHelper, NotifierCallback, NotifierOperation};
+use vmm_sys_util::epoll::EventSet;
+
+use crate::machine::MachineExternalInterface;
+use crate::qmp::{qmp_schema as schema, Response};
+
+/// Max length of the http request, including header and body.
+const REST_API_MAX_REQUEST_LEN: usize = 64 * 1024;
+/// Timeout to read the request, the client which doesn't send the whole request
+/// in time is dropped.
+const REST_API_READ_TIMEOUT: Duration = Duration::from_secs(1);
+const REST_API_APP_NAME: &str = "StratoVirt";
+
+/// The configuration of VM reported by REST API.
+#[derive(Debug, Clone, Default)]
+pub struct RestApiVmInfo {
+    /// Id of the VM.
+    pub id: String,
+    /// Number of vCPUs.
+    pub vcpu_count: u8,
+    /// Memory size in MiB.
+    pub mem_size_mib: u64,
+    /// Whether simultaneous multithreading is enabled.
+    pub smt: bool,
+    /// Whether the VM is micro VM, devices can only be attached to micro VM.
+    pub micro_vm: bool,
+    /// Whether vCPUs are frozen at startup, which is started by `InstanceStart`.
+    pub freeze_cpu: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MachineConfig {
+    vcpu_count: u8,
+    mem_size_mib: u64,
+    #[serde(default)]
+    smt: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Drive {
+    drive_id: String,
+    path_on_host: String,
+    /// The root device is selected by the kernel command line.
+    #[allow(dead_code)]
+    is_root_device: bool,
+    #[serde(default)]
+    is_read_only: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NetworkInterface {
+    iface_id: String,
+    host_dev_name: String,
+    guest_mac: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InstanceActionInfo {
+    action_type: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Vm {
+    state: String,
+}
+
+/// Http request received from client.
+#[derive(Debug, PartialEq, Eq)]
+struct HttpRequest {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+/// Http response sent to client.
+#[derive(Debug, PartialEq, Eq)]
+struct HttpResponse {
+    status: u16,
+    body: Option<String>,
+}
+
+impl HttpResponse {
+    fn no_content() -> Self {
+        HttpResponse {
+            status: 204,
+            body: None,
+        }
+    }
+
+    fn ok(body: Value) -> Self {
+        HttpResponse {
+            status: 200,
+            body: Some(body.to_string()),
+        }
+    }
+
+    fn fault(msg: &str) -> Self {
+        HttpResponse {
+            status: 400,
+            body: Some(serde_json::json!({ "fault_message": msg }).to_string()),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let reason = match self.status {
+            200 => "OK",
+            204 => "No Content",
+            _ => "Bad Request",
+        };
+        let mut resp = format!(
+            "HTTP/1.1 {} {}\r\nServer: {}\r\nConnection: close\r\n",
+            self.status, reason, REST_API_APP_NAME
+        );
+        if let Some(body) = &self.body {
+            resp.push_str(&format!(
+                "Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            ));
+        } else {
+            resp.push_str("\r\n");
+        }
+        resp.into_bytes()
+    }
+}
+
+/// Parse the http request in `buf`, return `None` if the request is incomplete.
+fn parse_request(buf: &[u8]) -> Result<Option<HttpRequest>> {
+    let header_end = match buf.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => pos,
+        None => return Ok(None),
+    };
+    let header = std::str::from_utf8(&buf[..header_end])
+        .with_context(|| "Invalid http header, it's not utf8")?;
+    let mut lines = header.split("\r\n");
+    let request_line: Vec<&str> = lines.next().unwrap_or_default().split(' ').collect();
+    if request_line.len() != 3 || !request_line[2].starts_with("HTTP/1.") {
+        bail!("Invalid http request line: {:?}", request_line.join(" "));
+    }
+
+    let mut content_len = 0;
+    for line in lines {
+        let (name, value) = line
+            .split_once(':')
+            .with_context(|| format!("Invalid http header line: {:?}", line))?;
+        if name.trim().eq_ignore_ascii_case("Content-Length") {
+            content_len = value
+                .trim()
+                .parse::<usize>()
+                .with_context(|| format!("Invalid Content-Length: {:?}", value))?;
+        }
+    }
+    let body_start = header_end + 4;
+    if body_start + content_len > REST_API_MAX_REQUEST_LEN {
+        bail!("Http request is too long");
+    }
+    if buf.len() < body_start + content_len {
+        return Ok(None);
+    }
+
+    // Query string is not used by any request.
+    let path = request_line[1].split('?').next().unwrap_or_default();
+    Ok(Some(HttpRequest {
+        method: request_line[0].to_string(),
+        path: path.to_string(),
+        body: buf[body_start..body_start + content_len].to_vec(),
+    }))
+}
+
+fn read_request(stream: &mut UnixStream) -> Result<HttpRequest> {
+    let mut buf = Vec::new();
+    let mut read_buf = [0_u8; 4096];
+    loop {
+        let len = stream
+            .read(&mut read_buf)
+            .with_context(|| "Failed to read http request")?;
+        if len == 0 {
+            bail!("Connection is closed before the whole http request is received");
+        }
+        buf.extend(&read_buf[..len]);
+        if let Some(request) = parse_request(&buf)? {
+            return Ok(request);
+        }
+        if buf.len() > REST_API_MAX_REQUEST_LEN {
+            bail!("Http request is too long");
+        }
+    }
+}
+
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> std::result::Result<T, HttpResponse> {
+    serde_json::from_slice(body)
+        .map_err(|e| HttpResponse::fault(&format!("Invalid request body: {}", e)))
+}
+
+/// Convert the qmp response to http response. The return value of qmp command
+/// is dropped if `with_body` is false.
+fn qmp_to_http(resp: Response, with_body: bool) -> HttpResponse {
+    let value = serde_json::to_value(resp).unwrap_or_default();
+    if let Some(desc) = value["error"]["desc"].as_str() {
+        return HttpResponse::fault(desc);
+    }
+    if with_body {
+        HttpResponse::ok(value["return"].clone())
+    } else {
+        HttpResponse::no_content()
+    }
+}
+
+/// Server of REST API, which listens on the unix socket.
+pub struct RestApiServer {
+    /// Listener of the unix socket.
+    listener: UnixListener,
+    /// Configuration of the VM.
+    vm_info: RestApiVmInfo,
+    /// Whether the VM is started by `InstanceStart` or at startup.
+    started: bool,
+    /// Controller to operate the VM.
+    controller: Option<Arc<Mutex<dyn MachineExternalInterface>>>,
+}
+
+impl RestApiServer {
+    /// Create REST API server.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - Bound unix listener.
+    /// * `vm_info` - Configuration of the VM.
+    pub fn new(listener: UnixListener, vm_info: RestApiVmInfo) -> Self {
+        RestApiServer {
+            listener,
+            started: !vm_info.freeze_cpu,
+            vm_info,
+            controller: None,
+        }
+    }
+
+    /// Set the controller to operate the VM.
+    pub fn set_controller(&mut self, controller: Arc<Mutex<dyn MachineExternalInterface>>) {
+        self.controller = Some(controller);
+    }
+
+    fn get_listener_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+
+    fn handle_connection(&mut self) -> Result<()> {
+        let (mut stream, _) = self
+            .listener
+            .accept()
+            .with_context(|| "Failed to accept rest api connection")?;
+        stream.set_read_timeout(Some(REST_API_READ_TIMEOUT))?;
+        let response = match read_request(&mut stream) {
+            Ok(request) => {
+                info!("REST API: <-- {} {}", request.method, request.path);
+                self.handle_request(&request)
+            }
+            Err(e) => HttpResponse::fault(&format!("{:?}", e)),
+        };
+        info!("REST API: --> {}", response.status);
+        stream
+            .write_all(&response.to_bytes())
+            .with_context(|| "Failed to send rest api response")
+    }
+
+    fn handle_request(&mut self, request: &HttpRequest) -> HttpResponse {
+        let controller = match &self.controller {
+            Some(controller) => controller.clone(),
+            None => return HttpResponse::fault("The VM is not created"),
+        };
+        let mut path = request.path.trim_start_matches('/').splitn(2, '/');
+        let resource = path.next().unwrap_or_default();
+        let id = path.next().filter(|id| !id.is_empty());
+        let result = match (request.method.as_str(), resource, id) {
+            ("GET", "", None) => Ok(self.instance_info(&controller)),
+            ("GET", "machine-config", None) => Ok(HttpResponse::ok(
+                serde_json::to_value(self.machine_config()).unwrap(),
+            )),
+            ("PUT", "machine-config", None) => parse_body(&request.body)
+                .map(|config: MachineConfig| self.put_machine_config(&config)),
+            ("PUT", "drives", Some(id)) => {
+                parse_body(&request.body).map(|drive: Drive| self.put_drive(&controller, id, drive))
+            }
+            ("PUT", "network-interfaces", Some(id)) => parse_body(&request.body)
+                .map(|iface: NetworkInterface| self.put_network_interface(&controller, id, iface)),
+            ("PUT", "actions", None) => parse_body(&request.body)
+                .map(|action: InstanceActionInfo| self.put_action(&controller, &action)),
+            ("PATCH", "vm", None) => {
+                parse_body(&request.body).map(|vm: Vm| Self::patch_vm(&controller, &vm))
+            }
+            _ => Ok(HttpResponse::fault(&format!(
+                "Invalid request method and/or path: {} {}",
+                request.method, request.path
+            ))),
+        };
+        result.unwrap_or_else(|fault| fault)
+    }
+
+    fn instance_info(&self, controller: &Arc<Mutex<dyn MachineExternalInterface>>) -> HttpResponse {
+        let status =
+            serde_json::to_value(controller.lock().unwrap().query_status()).unwrap_or_default();
+        let state = if !self.started {
+            "Not started"
+        } else if status["return"]["running"].as_bool().unwrap_or(false) {
+            "Running"
+        } else {
+            "Paused"
+        };
+        HttpResponse::ok(serde_json::json!({
+            "id": self.vm_info.id,
+            "state": state,
+            "vmm_version": env!("CARGO_PKG_VERSION"),
+            "app_name": REST_API_APP_NAME,
+        }))
+    }
+
+    fn machine_config(&self) -> MachineConfig {
+        MachineConfig {
+            vcpu_count: self.vm_info.vcpu_count,
+            mem_size_mib: self.vm_info.mem_size_mib,
+            smt: self.vm_info.smt,
+        }
+    }
+
+    /// The machine is configured by command line, so only the same configuration
+    /// is accepted.
+    fn put_machine_config(&self, config: &MachineConfig) -> HttpResponse {
+        if *config != self.machine_config() {
+            return HttpResponse::fault(
+                "The machine configuration can't be changed after the VM is created, \
+                please set it by command line",
+            );
+        }
+        HttpResponse::no_content()
+    }
+
+    fn put_drive(
+        &self,
+        controller: &Arc<Mutex<dyn MachineExternalInterface>>,
+        id: &str,
+        drive: Drive,
+    ) -> HttpResponse {
+        if drive.drive_id != id {
+            return HttpResponse::fault("The id from the path does not match the id from the body");
+        }
+        if !self.vm_info.micro_vm {
+            return HttpResponse::fault("Drives can only be attached to micro VM");
+        }
+        let blockdev = schema::BlockDevAddArgument {
+            node_name: drive.drive_id.clone(),
+            file: schema::FileOptions {
+                driver: "file".to_string(),
+                filename: drive.path_on_host,
+            },
+            read_only: Some(drive.is_read_only),
+            ..Default::default()
+        };
+        let resp = qmp_to_http(
+            controller.lock().unwrap().blockdev_add(Box::new(blockdev)),
+            false,
+        );
+        if resp.status != 204 {
+            return resp;
+        }
+        let device = schema::DeviceAddArgument {
+            id: drive.drive_id.clone(),
+            driver: "virtio-blk-mmio".to_string(),
+            drive: Some(drive.drive_id.clone()),
+            ..Default::default()
+        };
+        let resp = qmp_to_http(
+            controller.lock().unwrap().device_add(Box::new(device)),
+            false,
+        );
+        if resp.status != 204 {
+            controller.lock().unwrap().blockdev_del(drive.drive_id);
+        }
+        resp
+    }
+
+    fn put_network_interface(
+        &self,
+        controller: &Arc<Mutex<dyn MachineExternalInterface>>,
+        id: &str,
+        iface: NetworkInterface,
+    ) -> HttpResponse {
+        if iface.iface_id != id {
+            return HttpResponse::fault("The id from the path does not match the id from the body");
+        }
+        if !self.vm_info.micro_vm {
+            return HttpResponse::fault("Network interfaces can only be attached to micro VM");
+        }
+        if iface.guest_mac.is_some() {
+            return HttpResponse::fault("Setting guest_mac is not supported");
+        }
+        let netdev = schema::NetDevAddArgument {
+            id: iface.iface_id.clone(),
+            if_name: Some(iface.host_dev_name),
+            ..Default::default()
+        };
+        let resp = qmp_to_http(
+            controller.lock().unwrap().netdev_add(Box::new(netdev)),
+            false,
+        );
+        if resp.status != 204 {
+            return resp;
+        }
+        let device = schema::DeviceAddArgument {
+            id: iface.iface_id.clone(),
+            driver: "virtio-net-mmio".to_string(),
+            netdev: Some(iface.iface_id.clone()),
+            ..Default::default()
+        };
+        let resp = qmp_to_http(
+            controller.lock().unwrap().device_add(Box::new(device)),
+            false,
+        );
+        if resp.status != 204 {
+            controller.lock().unwrap().netdev_del(iface.iface_id);
+        }
+        resp
+    }
+
+    fn put_action(
+        &mut self,
+        controller: &Arc<Mutex<dyn MachineExternalInterface>>,
+        action: &InstanceActionInfo,
+    ) -> HttpResponse {
+        match action.action_type.as_str() {
+            "InstanceStart" => {
+                if self.started {
+                    return HttpResponse::fault("The VM is already started");
+                }
+                if !controller.lock().unwrap().resume() {
+                    return HttpResponse::fault("Failed to start the VM");
+                }
+                self.started = true;
+            }
+            "SendCtrlAltDel" => {
+                if !controller.lock().unwrap().powerdown() {
+                    return HttpResponse::fault("Failed to send powerdown request to the VM");
+                }
+            }
+            action_type => {
+                return HttpResponse::fault(&format!("Unsupported action type: {}", action_type))
+            }
+        }
+        HttpResponse::no_content()
+    }
+
+    fn patch_vm(controller: &Arc<Mutex<dyn MachineExternalInterface>>, vm: &Vm) -> HttpResponse {
+        let ret = match vm.state.as_str() {
+            "Paused" => controller.lock().unwrap().pause(),
+            "Resumed" => controller.lock().unwrap().resume(),
+            state => return HttpResponse::fault(&format!("Invalid VM state: {}", state)),
+        };
+        if !ret {
+            return HttpResponse::fault(&format!("Failed to change VM state to {}", vm.state));
+        }
+        HttpResponse::no_content()
+    }
+}
+
+impl EventNotifierHelper for RestApiServer {
+    fn internal_notifiers(server: Arc<Mutex<Self>>) -> Vec<EventNotifier> {
+        let server_clone = server.clone();
+        let handler: Rc<NotifierCallback> = Rc::new(move |_, _| {
+            if let Err(e) = server_clone.lock().unwrap().handle_connection() {
+                error!("{:?}", e);
+            }
+            None
+        });
+        let notifier = EventNotifier::new(
+            NotifierOperation::AddShared,
+            server.lock().unwrap().get_listener_fd(),
+            None,
+            EventSet::IN,
+            vec![handler],
+        );
+        vec![notifier]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        // Incomplete header.
+        let req = b"GET / HTTP/1.1\r\nHost: localhost\r\n";
+        assert_eq!(parse_request(req).unwrap(), None);
+
+        let req = b"GET /machine-config?x=1 HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let request = parse_request(req).unwrap().unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/machine-config");
+        assert!(request.body.is_empty());
+
+        // Incomplete body.
+        let req = b"PATCH /vm HTTP/1.1\r\ncontent-length: 20\r\n\r\n{\"state\":\"Paused\"";
+        assert_eq!(parse_request(req).unwrap(), None);
+        let req = b"PATCH /vm HTTP/1.1\r\ncontent-length: 18\r\n\r\n{\"state\":\"Paused\"}";
+        let request = parse_request(req).unwrap().unwrap();
+        assert_eq!(request.method, "PATCH");
+        let vm: Vm = parse_body(&request.body).unwrap();
+        assert_eq!(vm.state, "Paused");
+
+        // Invalid request.
+        assert!(parse_request(b"GET /\r\n\r\n").is_err());
+        assert!(parse_request(b"GET / HTTP/1.1\r\nContent-Length: x\r\n\r\n").is_err());
+        let req = format!(
+            "PUT /drives/rootfs HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            REST_API_MAX_REQUEST_LEN
+        );
+        assert!(parse_request(req.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_http_response() {
+        let resp = HttpResponse::no_content();
+        assert_eq!(
+            String::from_utf8(resp.to_bytes()).unwrap(),
+            "HTTP/1.1 204 No Content\r\nServer: StratoVirt\r\nConnection: close\r\n\r\n"
+        );
+
+        let resp = HttpResponse::fault("error");
+        let body = r#"{"fault_message":"error"}"#;
+        assert_eq!(
+            String::from_utf8(resp.to_bytes()).unwrap(),
+            format!(
+                "HTTP/1.1 400 Bad Request\r\nServer: StratoVirt\r\nConnection: close\r\n\
+                Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        );
+
+        let qmp_resp = Response::create_empty_response();
+        assert_eq!(qmp_to_http(qmp_resp, false), HttpResponse::no_content());
+        let err = schema::QmpErrorClass::GenericError("failed".to_string());
+        let qmp_resp = Response::create_error_response(err, None);
+        assert_eq!(qmp_to_http(qmp_resp, true), HttpResponse::fault("failed"));
+    }
+
+    #[test]
+    fn test_rest_api_body() {
+        let drive =
+            r#"{"drive_id":"rootfs","path_on_host":"/path/to/rootfs","is_root_device":true}"#;
+        let drive: Drive = parse_body(drive.as_bytes()).unwrap();
+        assert_eq!(drive.drive_id, "rootfs");
+        assert!(!drive.is_read_only);
+
+        let drive = r#"{"drive_id":"rootfs","path_on_host":"/path","is_root_device":true,"rate_limiter":{}}"#;
+        let resp = parse_body::<Drive>(drive.as_bytes()).unwrap_err();
+        assert_eq!(resp.status, 400);
+
+        let config = r#"{"vcpu_count":2,"mem_size_mib":1024}"#;
+        let config: MachineConfig = parse_body(config.as_bytes()).unwrap();
+        assert!(!config.smt);
+    }
+}
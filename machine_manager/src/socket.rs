@@ -12,22 +12,27 @@
 
 use std::io::{Error, ErrorKind, Read, Write};
 use std::mem::size_of;
+use std::net::{TcpListener, TcpStream};
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex, RwLock};
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use libc::{
-    c_void, iovec, msghdr, recvmsg, sendmsg, CMSG_DATA, CMSG_FIRSTHDR, CMSG_LEN, CMSG_NXTHDR,
-    MSG_DONTWAIT, MSG_NOSIGNAL, SCM_RIGHTS, SOL_SOCKET,
+    c_void, getsockopt, iovec, msghdr, recvmsg, sendmsg, socklen_t, ucred, CMSG_DATA,
+    CMSG_FIRSTHDR, CMSG_LEN, CMSG_NXTHDR, MSG_DONTWAIT, MSG_NOSIGNAL, SCM_RIGHTS, SOL_SOCKET,
+    SO_PEERCRED,
 };
 use log::{error, info};
+use rustls::ServerConfig;
 use serde::Deserialize;
 use vmm_sys_util::epoll::EventSet;
 
 use crate::machine::MachineExternalInterface;
+use crate::qmp::hmp::{HMP_BANNER, HMP_PROMPT};
 use crate::qmp::{QmpChannel, QmpGreeting, Response};
+use crate::tls::TlsSession;
 use util::leak_bucket::LeakBucket;
 use util::loop_context::{
     gen_delete_notifiers, read_fd, EventNotifier, EventNotifierHelper, NotifierCallback,
@@ -41,7 +46,7 @@ const MAX_RECV_BUF_LEN: usize = 4096;
 const MAX_RECV_FDS_LEN: usize = MAX_RECV_BUF_LEN;
 pub(crate) const LEAK_BUCKET_LIMIT: u64 = 100;
 
-/// The wrapper over Unix socket and socket handler.
+/// The wrapper over Unix or TCP socket and socket handler.
 ///
 /// # Example
 ///
@@ -68,11 +73,27 @@ pub struct Socket {
     /// Type for Socket
     sock_type: SocketType,
     /// Socket listener tuple
-    listener: UnixListener,
+    listener: SocketListener,
     /// Socket stream with RwLock
     stream: RwLock<Option<SocketStream>>,
     /// Perform socket command
     performer: Option<Arc<Mutex<dyn MachineExternalInterface>>>,
+    /// Mode of the monitor on socket
+    mode: MonitorMode,
+    /// Tls config for TCP socket, the client must do tls handshake if it's set
+    tls_config: Option<Arc<ServerConfig>>,
+    /// Identity of the connected client, recorded in the qmp audit log
+    peer: RwLock<String>,
+}
+
+/// Mode of the monitor on socket.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MonitorMode {
+    /// Machine protocol in json format, which is QMP.
+    #[default]
+    Control,
+    /// Human monitor with line based commands, which is HMP.
+    Readline,
 }
 
 impl Socket {
@@ -88,31 +109,97 @@ impl Socket {
     ) -> Self {
         Socket {
             sock_type: SocketType::Unix,
-            listener,
+            listener: SocketListener::Unix(listener),
             stream: RwLock::new(None),
             performer,
+            mode: MonitorMode::Control,
+            tls_config: None,
+            peer: RwLock::new(String::new()),
         }
     }
 
+    /// Allocates a new `Socket` with `TcpListener`.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - The `TcpListener` bind to `Socket`.
+    /// * `tls_config` - The tls config if the stream is encrypted by tls.
+    /// * `performer` - The `VM` to perform socket command.
+    pub fn from_tcp_listener(
+        listener: TcpListener,
+        tls_config: Option<Arc<ServerConfig>>,
+        performer: Option<Arc<Mutex<dyn MachineExternalInterface>>>,
+    ) -> Self {
+        Socket {
+            sock_type: SocketType::Tcp,
+            listener: SocketListener::Tcp(listener),
+            stream: RwLock::new(None),
+            performer,
+            mode: MonitorMode::Control,
+            tls_config,
+            peer: RwLock::new(String::new()),
+        }
+    }
+
+    /// Set the `VM` to perform socket command.
+    ///
+    /// # Arguments
+    ///
+    /// * `performer` - The `VM` to perform socket command.
+    pub fn set_performer(&mut self, performer: Arc<Mutex<dyn MachineExternalInterface>>) {
+        self.performer = Some(performer);
+    }
+
+    /// Set the mode of monitor on `Socket`, the default mode is `Control`.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The monitor mode of `Socket`.
+    pub fn set_monitor_mode(&mut self, mode: MonitorMode) {
+        self.mode = mode;
+    }
+
     /// Get listener's fd from `Socket`.
     pub fn get_listener_fd(&self) -> RawFd {
         self.listener.as_raw_fd()
     }
 
     /// Accept stream and bind to Socket.
-    pub fn accept(&self) {
-        match self.sock_type {
-            SocketType::Unix => {
+    ///
+    /// # Errors
+    ///
+    /// The tls handshake with TCP client failed.
+    pub fn accept(&self) -> Result<()> {
+        match &self.listener {
+            SocketListener::Unix(_) => {
                 let stream = self.accept_unix_stream();
+                *self.peer.write().unwrap() = unix_peer_identity(&stream);
                 self.bind_unix_stream(stream);
             }
+            SocketListener::Tcp(listener) => {
+                let (stream, addr) = listener.accept()?;
+                let tls = match &self.tls_config {
+                    Some(config) => {
+                        let session = TlsSession::handshake(stream.try_clone()?, config.clone())
+                            .with_context(|| format!("Tls client {} is rejected", addr))?;
+                        Some(Arc::new(Mutex::new(session)))
+                    }
+                    None => None,
+                };
+                info!("Api channel accepts TCP client {}", addr);
+                *self.peer.write().unwrap() = format!("tcp:{}", addr);
+                *self.stream.write().unwrap() = Some(SocketStream::Tcp { stream, tls });
+            }
         }
+        Ok(())
     }
 
     /// Accept a new incoming connection unix stream from unix listener.
     pub fn accept_unix_stream(&self) -> UnixStream {
-        let (stream, _) = self.listener.accept().unwrap();
-        stream
+        match &self.listener {
+            SocketListener::Unix(listener) => listener.accept().unwrap().0,
+            SocketListener::Tcp(_) => panic!("Failed to accept unix stream from TCP socket!"),
+        }
     }
 
     /// Get socket type from `Socket`.
@@ -149,9 +236,26 @@ impl Socket {
         }
     }
 
+    /// Get a `SocketRWHandler` from `Socket`.
+    pub fn get_rw_handler(&self) -> SocketRWHandler {
+        let stream = self.stream.read().unwrap();
+        match stream.as_ref() {
+            Some(SocketStream::Tcp {
+                stream,
+                tls: Some(session),
+            }) => SocketRWHandler::with_tls(stream.as_raw_fd(), session.clone()),
+            Some(stream) => SocketRWHandler::new(stream.as_raw_fd()),
+            None => panic!("Failed to get socket fd!"),
+        }
+    }
+
     /// Get a `SocketHandler` from `Socket`.
     pub fn get_socket_handler(&self) -> SocketHandler {
-        SocketHandler::new(self.get_stream_fd())
+        SocketHandler {
+            stream: self.get_rw_handler(),
+            buffer: String::new(),
+            peer: self.peer.read().unwrap().clone(),
+        }
     }
 
     /// In qmp feature, send empty or greeting response to client.
@@ -173,6 +277,13 @@ impl Socket {
         Ok(())
     }
 
+    /// In human monitor, send banner and prompt to client.
+    fn send_hmp_banner(&self) -> std::io::Result<()> {
+        let mut handler = self.get_socket_handler();
+        handler.send_str(HMP_BANNER)?;
+        handler.send_raw_str(HMP_PROMPT)
+    }
+
     /// Create socket's accepted stream to `event_notifier`.
     fn create_event_notifier(&mut self, shared_socket: Arc<Mutex<Self>>) -> Vec<EventNotifier> {
         let mut notifiers = Vec::new();
@@ -186,32 +297,51 @@ impl Socket {
         let shared_leak_bucket = leak_bucket.clone();
         let leak_bucket_fd = leak_bucket.lock().unwrap().as_raw_fd();
 
-        self.accept();
-        QmpChannel::bind_writer(SocketRWHandler::new(self.get_stream_fd()));
-        if let Err(e) = self.send_response(true) {
+        if let Err(e) = self.accept() {
+            error!("{:?}", e);
+            return notifiers;
+        }
+        let mode = self.mode;
+        if mode == MonitorMode::Control {
+            QmpChannel::bind_writer(self.get_rw_handler());
+            if let Err(e) = self.send_response(true) {
+                error!("{:?}", e);
+                QmpChannel::unbind(self.get_stream_fd());
+                return notifiers;
+            }
+        } else if let Err(e) = self.send_hmp_banner() {
             error!("{:?}", e);
-            QmpChannel::unbind();
             return notifiers;
         }
         let handler: Rc<NotifierCallback> = Rc::new(move |event, _| {
             if event == EventSet::IN {
                 let socket_mutexed = shared_socket.lock().unwrap();
-                let stream_fd = socket_mutexed.get_stream_fd();
+                let mut sock_handler = socket_mutexed.get_socket_handler();
 
                 let performer = &socket_mutexed.performer.as_ref().unwrap();
-                if let Err(e) = crate::qmp::handle_qmp(
-                    stream_fd,
-                    performer,
-                    &mut shared_leak_bucket.lock().unwrap(),
-                ) {
+                let ret = match mode {
+                    MonitorMode::Control => crate::qmp::handle_qmp(
+                        &mut sock_handler,
+                        performer,
+                        &mut shared_leak_bucket.lock().unwrap(),
+                    ),
+                    MonitorMode::Readline => {
+                        crate::qmp::hmp::handle_hmp(&mut sock_handler, performer)
+                    }
+                };
+                if let Err(e) = ret {
                     error!("{:?}", e);
                 }
             }
-            if event & EventSet::HANG_UP == EventSet::HANG_UP {
+            // The TCP client which closes the connection only reports READ_HANG_UP.
+            if event.intersects(EventSet::HANG_UP | EventSet::READ_HANG_UP) {
                 let socket_mutexed = shared_socket.lock().unwrap();
                 let stream_fd = socket_mutexed.get_stream_fd();
 
-                QmpChannel::unbind();
+                // Events are only reported to the monitor in control mode.
+                if mode == MonitorMode::Control {
+                    QmpChannel::unbind(stream_fd);
+                }
                 Some(gen_delete_notifiers(&[stream_fd, leak_bucket_fd]))
             } else {
                 None
@@ -221,7 +351,7 @@ impl Socket {
             NotifierOperation::AddShared,
             self.get_stream_fd(),
             Some(self.get_listener_fd()),
-            EventSet::IN | EventSet::HANG_UP,
+            EventSet::IN | EventSet::HANG_UP | EventSet::READ_HANG_UP,
             vec![handler],
         );
         notifiers.push(qmp_notifier);
@@ -267,21 +397,75 @@ impl EventNotifierHelper for Socket {
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum SocketType {
     Unix = 1,
+    Tcp = 2,
+}
+
+/// Wrapper over UnixListener and TcpListener.
+enum SocketListener {
+    Unix(UnixListener),
+    Tcp(TcpListener),
+}
+
+impl AsRawFd for SocketListener {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            SocketListener::Unix(listener) => listener.as_raw_fd(),
+            SocketListener::Tcp(listener) => listener.as_raw_fd(),
+        }
+    }
 }
 
-/// Wrapper over UnixSteam.
-#[derive(Debug)]
-struct SocketStream(UnixStream);
+/// Get the identity of the process connected with the unix stream from its
+/// credentials.
+fn unix_peer_identity(stream: &UnixStream) -> String {
+    let mut cred = ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = size_of::<ucred>() as socklen_t;
+    // SAFETY: cred and len are valid and big enough to hold the credentials.
+    let ret = unsafe {
+        getsockopt(
+            stream.as_raw_fd(),
+            SOL_SOCKET,
+            SO_PEERCRED,
+            &mut cred as *mut ucred as *mut c_void,
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        error!(
+            "Failed to get credentials of unix client: {:?}",
+            Error::last_os_error()
+        );
+        return "unix:unknown".to_string();
+    }
+    format!("unix:pid={},uid={}", cred.pid, cred.uid)
+}
+
+/// Wrapper over UnixSteam and TcpStream.
+enum SocketStream {
+    Unix(UnixStream),
+    Tcp {
+        stream: TcpStream,
+        /// Tls session shared by the handlers of the stream.
+        tls: Option<Arc<Mutex<TlsSession>>>,
+    },
+}
 
 impl SocketStream {
     fn from_unix_stream(stream: UnixStream) -> Self {
-        SocketStream(stream)
+        SocketStream::Unix(stream)
     }
 }
 
 impl AsRawFd for SocketStream {
     fn as_raw_fd(&self) -> RawFd {
-        self.0.as_raw_fd()
+        match self {
+            SocketStream::Unix(stream) => stream.as_raw_fd(),
+            SocketStream::Tcp { stream, .. } => stream.as_raw_fd(),
+        }
     }
 }
 
@@ -316,6 +500,8 @@ pub struct SocketRWHandler {
     pos: usize,
     /// Fds when read from fd's scm right
     scm_fd: Vec<RawFd>,
+    /// Tls session to encrypt the message, if the socket is TCP with tls
+    tls: Option<Arc<Mutex<TlsSession>>>,
 }
 
 impl SocketRWHandler {
@@ -330,6 +516,20 @@ impl SocketRWHandler {
             buf: Vec::new(),
             pos: 0,
             scm_fd: Vec::new(),
+            tls: None,
+        }
+    }
+
+    /// Allocates a new `SocketRWHandler` with a socket fd and its tls session.
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - The file descriptor for socket.
+    /// * `tls` - The tls session over the socket.
+    pub fn with_tls(r: RawFd, tls: Arc<Mutex<TlsSession>>) -> Self {
+        SocketRWHandler {
+            tls: Some(tls),
+            ..Self::new(r)
         }
     }
 
@@ -391,6 +591,15 @@ impl SocketRWHandler {
     /// # Errors
     /// The socket file descriptor is broken.
     fn read_fd(&mut self) -> std::io::Result<()> {
+        if let Some(tls) = &self.tls {
+            let len = tls.lock().unwrap().read_plaintext(&mut self.buf)?;
+            self.pos = self
+                .pos
+                .checked_add(len)
+                .ok_or_else(|| Error::from(ErrorKind::InvalidInput))?;
+            return Ok(());
+        }
+
         let recv_buf = [0_u8; MAX_RECV_BUF_LEN];
         let mut iov = iovec {
             iov_base: recv_buf.as_ptr() as *mut c_void,
@@ -447,6 +656,14 @@ impl SocketRWHandler {
     /// # Errors
     /// The socket file descriptor is broken.
     fn write_fd(&mut self, length: usize) -> std::io::Result<()> {
+        if let Some(tls) = &self.tls {
+            return tls
+                .lock()
+                .unwrap()
+                .write_plaintext(&self.buf[(self.pos - length)..self.pos])
+                .map_err(|_| Error::new(ErrorKind::BrokenPipe, "The socket pipe is broken!"));
+        }
+
         let mut iov = iovec {
             iov_base: self.buf.as_slice()[(self.pos - length)..(self.pos - 1)].as_ptr()
                 as *mut c_void,
@@ -482,6 +699,12 @@ impl SocketRWHandler {
     }
 }
 
+impl AsRawFd for SocketRWHandler {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket_fd
+    }
+}
+
 impl Read for SocketRWHandler {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         let start = self.pos;
@@ -538,6 +761,8 @@ pub struct SocketHandler {
     stream: SocketRWHandler,
     /// Buffer to leave with read result
     buffer: String,
+    /// Identity of the client on the other end of the stream
+    peer: String,
 }
 
 impl SocketHandler {
@@ -550,9 +775,16 @@ impl SocketHandler {
         SocketHandler {
             stream: SocketRWHandler::new(r),
             buffer: String::new(),
+            peer: String::new(),
         }
     }
 
+    /// Get the identity of the client, such as `unix:pid=1234,uid=0` or
+    /// `tcp:127.0.0.1:4444`.
+    pub fn peer(&self) -> &str {
+        &self.peer
+    }
+
     pub fn get_line(&mut self) -> Result<Option<String>> {
         self.buffer.clear();
         self.stream.clear();
@@ -626,6 +858,25 @@ impl SocketHandler {
             )),
         }
     }
+
+    /// Send String to `SocketHandler` without line ending, such as the prompt of
+    /// human monitor.
+    pub fn send_raw_str(&mut self, s: &str) -> std::io::Result<()> {
+        self.stream.flush().unwrap();
+        match self.stream.write(s.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::new(
+                ErrorKind::BrokenPipe,
+                "The socket pipe is broken!",
+            )),
+        }
+    }
+}
+
+impl AsRawFd for SocketHandler {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,195 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+//! TLS session of the api channel over TCP.
+
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig, ServerConnection};
+
+use crate::config::TlsCredObjConfig;
+
+const TLS_CREDS_SERVER_CACERT: &str = "cacert.pem";
+const TLS_CREDS_SERVERCERT: &str = "servercert.pem";
+const TLS_CREDS_SERVERKEY: &str = "serverkey.pem";
+/// Timeout of tls handshake, the client which doesn't finish handshake in time is dropped.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Buffer size to read plaintext from tls session.
+const TLS_READ_BUF_LEN: usize = 4096;
+
+/// Create the tls server config from `tls-creds-x509` object, the certificate and
+/// private key of server are loaded from the directory of credentials. If `verify-peer`
+/// is set, the client must present a certificate signed by `cacert.pem`.
+///
+/// # Arguments
+///
+/// * `creds` - Configuration of `tls-creds-x509` object.
+pub fn make_server_config(creds: &TlsCredObjConfig) -> Result<Arc<ServerConfig>> {
+    if creds.endpoint.as_deref() == Some("client") {
+        bail!("Tls credentials '{}' is not for server endpoint", creds.id);
+    }
+    let certs = load_certs(&format!("{}/{}", creds.dir, TLS_CREDS_SERVERCERT))?;
+    let key = load_private_key(&format!("{}/{}", creds.dir, TLS_CREDS_SERVERKEY))?;
+
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let config = if creds.verifypeer {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&format!("{}/{}", creds.dir, TLS_CREDS_SERVER_CACERT))? {
+            roots.add(&cert)?;
+        }
+        builder
+            .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            .with_single_cert(certs, key)?
+    } else {
+        builder.with_no_client_auth().with_single_cert(certs, key)?
+    };
+    Ok(Arc::new(config))
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let certs: Vec<Certificate> = rustls_pemfile::certs(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse certificates in {}", path))?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    if certs.is_empty() {
+        bail!("No certificate found in {}", path);
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &str) -> Result<PrivateKey> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let mut reader = BufReader::new(file);
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)
+        .with_context(|| format!("Failed to parse private key in {}", path))?
+    {
+        match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => {}
+        }
+    }
+    bail!("No private key found in {}", path);
+}
+
+/// Tls session over the accepted TCP stream, which is shared by the command handler
+/// and the event writer of the client.
+pub struct TlsSession {
+    /// Stream connected with client.
+    stream: TcpStream,
+    /// Tls server connection.
+    conn: ServerConnection,
+}
+
+impl TlsSession {
+    /// Do tls handshake with client. After handshake, the stream is set to non-blocking
+    /// mode, which is the same as `MSG_DONTWAIT` for unix socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The accepted TCP stream.
+    /// * `config` - The tls server config.
+    pub fn handshake(mut stream: TcpStream, config: Arc<ServerConfig>) -> Result<Self> {
+        let mut conn = ServerConnection::new(config)?;
+        stream.set_read_timeout(Some(TLS_HANDSHAKE_TIMEOUT))?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)
+                .with_context(|| "Failed to do tls handshake")?;
+        }
+        stream.set_read_timeout(None)?;
+        stream.set_nonblocking(true)?;
+        Ok(TlsSession { stream, conn })
+    }
+
+    /// Read all the plaintext received from client.
+    pub fn read_plaintext(&mut self, buf: &mut Vec<u8>) -> std::io::Result<usize> {
+        loop {
+            match self.conn.read_tls(&mut self.stream) {
+                Ok(0) => break,
+                Ok(_) => {
+                    self.conn
+                        .process_new_packets()
+                        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        // Alerts or session tickets may be sent after processing packets.
+        self.flush_tls()?;
+
+        let mut len = 0;
+        let mut read_buf = [0_u8; TLS_READ_BUF_LEN];
+        loop {
+            match self.conn.reader().read(&mut read_buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    buf.extend(&read_buf[..n]);
+                    len += n;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(len)
+    }
+
+    /// Encrypt the plaintext and send it to client.
+    pub fn write_plaintext(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.conn.writer().write_all(buf)?;
+        self.flush_tls()
+    }
+
+    fn flush_tls(&mut self) -> std::io::Result<()> {
+        while self.conn.wants_write() {
+            match self.conn.write_tls(&mut self.stream) {
+                Ok(_) => {}
+                Err(e)
+                    if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_make_server_config() {
+        let mut creds = TlsCredObjConfig {
+            id: "tls0".to_string(),
+            dir: "/path/not/exist".to_string(),
+            cred_type: "x509".to_string(),
+            endpoint: Some("client".to_string()),
+            verifypeer: false,
+        };
+        assert!(make_server_config(&creds).is_err());
+
+        // Certificates are missing in the directory.
+        creds.endpoint = Some("server".to_string());
+        let err = make_server_config(&creds).unwrap_err();
+        assert!(format!("{:?}", err).contains(TLS_CREDS_SERVERCERT));
+    }
+}
@@ -42,6 +42,8 @@ pub enum MigrationError {
     },
     #[error("Migration compat_version {0} higher than current version {1}")]
     VersionNotFit(u32, u32),
+    #[error("Migration state version {0} newer than current version {1}")]
+    VersionTooNew(u32, u32),
     #[error("{0} for snapshot file / migration stream is not fit")]
     HeaderItemNotFit(String),
     #[error("Failed to transfer migration status from {0} to {1}.")]
@@ -62,6 +64,8 @@ pub enum MigrationError {
     RecvVmMemoryErr(String),
     #[error("Response error")]
     ResponseErr,
+    #[error("Migration is canceled")]
+    Canceled,
     #[error("Migration status mismatch: source {0}, destination {1}.")]
     MigrationStatusErr(String, String),
     #[error("Migration config {0} mismatch: source {1}, destination {2}.")]
@@ -14,6 +14,7 @@ use std::collections::{hash_map::DefaultHasher, HashMap};
 use std::hash::{Hash, Hasher};
 use std::io::{Read, Write};
 use std::mem::size_of;
+use std::sync::atomic::Ordering;
 
 use crate::manager::{Instance, MIGRATION_MANAGER};
 use crate::protocol::{
@@ -21,6 +22,7 @@ use crate::protocol::{
 };
 use crate::{MigrationError, MigrationManager};
 use anyhow::{anyhow, Context, Result};
+use machine_manager::machine::{set_stop_reason, StopReason};
 use util::unix::host_page_size;
 
 impl MigrationManager {
@@ -185,11 +187,19 @@ impl MigrationManager {
                     .add_padding(snap_desc, &mut state_data)
                     .with_context(|| "Failed to transform snapshot data version")?;
             }
+            VersionCheck::Mismatch if snap_desc.current_version > current_desc.current_version => {
+                return Err(anyhow!(MigrationError::VersionTooNew(
+                    snap_desc.current_version,
+                    current_desc.current_version,
+                )))
+                .with_context(|| format!("Failed to restore state of {}", snap_desc.name));
+            }
             VersionCheck::Mismatch => {
                 return Err(anyhow!(MigrationError::VersionNotFit(
                     current_desc.compat_version,
                     snap_desc.current_version,
                 )))
+                .with_context(|| format!("Failed to restore state of {}", snap_desc.name));
             }
         }
 
@@ -231,6 +241,17 @@ impl MigrationManager {
         let mut status = MIGRATION_MANAGER.status.write().unwrap();
         *status = status.transfer(new_status)?;
 
+        // Record the total time when the outgoing migration finishes.
+        if matches!(
+            *status,
+            MigrationStatus::Completed | MigrationStatus::Failed | MigrationStatus::Canceled
+        ) {
+            let mut progress = MIGRATION_MANAGER.progress.write().unwrap();
+            if let (Some(start_time), None) = (progress.start_time, progress.total_time) {
+                progress.total_time = Some(start_time.elapsed().as_millis() as u64);
+            }
+        }
+
         Ok(())
     }
 
@@ -249,7 +270,9 @@ pub trait Lifecycle {
     /// Pause VM during migration.
     fn pause() -> Result<()> {
         if let Some(locked_vm) = &MIGRATION_MANAGER.vmm.read().unwrap().vm {
-            locked_vm.lock().unwrap().pause();
+            set_stop_reason(StopReason::Migrate);
+            let paused = locked_vm.lock().unwrap().pause();
+            MIGRATION_MANAGER.vm_paused.store(paused, Ordering::SeqCst);
         }
 
         Ok(())
@@ -269,6 +292,26 @@ pub trait Lifecycle {
 
         Ok(())
     }
+
+    /// Stop the devices processing outside of vmm after VM is paused.
+    fn suspend_backends() -> Result<()> {
+        let locked_devices = &MIGRATION_MANAGER.vmm.read().unwrap().devices;
+        for (_, device) in locked_devices.iter() {
+            device.lock().unwrap().suspend_backend()?;
+        }
+
+        Ok(())
+    }
+
+    /// Restart the devices processing stopped by `suspend_backends`.
+    fn resume_backends() -> Result<()> {
+        let locked_devices = &MIGRATION_MANAGER.vmm.read().unwrap().devices;
+        for (_, device) in locked_devices.iter() {
+            device.lock().unwrap().resume_backend()?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Lifecycle for MigrationManager {}
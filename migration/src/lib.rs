@@ -19,18 +19,25 @@ pub mod general;
 pub mod manager;
 pub mod migration;
 pub mod protocol;
+pub mod rdma;
 pub mod snapshot;
 
+use std::io::{Read, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
 use std::time::Duration;
 use std::{net::TcpStream, os::unix::net::UnixStream, thread};
 
 pub use anyhow::Result;
-use log::error;
+use log::{error, info};
 
 pub use error::MigrationError;
-use machine_manager::qmp::{qmp_schema, Response};
+use machine_manager::config::{parse_incoming_uri, MigrateMode};
+use machine_manager::qmp::{fdset_id_from_path, qmp_schema, QmpChannel, Response};
 pub use manager::{MigrationHook, MigrationManager};
+use manager::{MAX_COMPRESS_LEVEL, MAX_DOWNTIME_LIMIT, MIGRATION_MANAGER};
 pub use protocol::{DeviceStateDesc, FieldDesc, MemBlock, MigrationStatus, StateTransfer};
+use rdma::RdmaStream;
+use util::unix::host_page_size;
 
 /// Start to snapshot VM.
 ///
@@ -50,6 +57,30 @@ pub fn snapshot(path: String) -> Response {
     Response::create_empty_response()
 }
 
+/// Send migration to destination VM, and recover the source VM if migration
+/// fails or is canceled.
+///
+/// # Arguments
+///
+/// * `socket` - The connected stream to destination VM.
+fn send_migration<T: Read + Write>(socket: &mut T) {
+    if let Err(e) = MigrationManager::send_migration(socket) {
+        let canceled = MigrationManager::is_canceled();
+        if canceled {
+            info!("Migration is canceled: {:?}", e);
+        } else {
+            error!("Failed to send migration: {:?}", e);
+        }
+        if let Err(e) = MigrationManager::recover_from_migration() {
+            error!("Failed to recover from migration: {:?}", e);
+        }
+        if !canceled {
+            let _ = MigrationManager::set_status(MigrationStatus::Failed)
+                .map_err(|e| error!("{:?}", e));
+        }
+    }
+}
+
 /// Start to migrate VM with unix mode.
 ///
 /// # Arguments
@@ -79,12 +110,7 @@ pub fn migration_unix_mode(path: String) -> Response {
     if let Err(e) = thread::Builder::new()
         .name("unix_migrate".to_string())
         .spawn(move || {
-            if let Err(e) = MigrationManager::send_migration(&mut socket) {
-                error!("Failed to send migration: {:?}", e);
-                let _ = MigrationManager::recover_from_migration();
-                let _ = MigrationManager::set_status(MigrationStatus::Failed)
-                    .map_err(|e| error!("{:?}", e));
-            }
+            send_migration(&mut socket);
         })
     {
         return Response::create_error_response(
@@ -125,12 +151,7 @@ pub fn migration_tcp_mode(path: String) -> Response {
     if let Err(e) = thread::Builder::new()
         .name("tcp_migrate".to_string())
         .spawn(move || {
-            if let Err(e) = MigrationManager::send_migration(&mut socket) {
-                error!("Failed to send migration: {:?}", e);
-                let _ = MigrationManager::recover_from_migration();
-                let _ = MigrationManager::set_status(MigrationStatus::Failed)
-                    .map_err(|e| error!("{:?}", e));
-            }
+            send_migration(&mut socket);
         })
     {
         return Response::create_error_response(
@@ -142,16 +163,181 @@ pub fn migration_tcp_mode(path: String) -> Response {
     Response::create_empty_response()
 }
 
+/// Start to migrate VM with rdma mode.
+///
+/// # Arguments
+///
+/// * `path` - Ip and port of RDMA device, as 192.168.1.1:4446.
+pub fn migration_rdma_mode(path: String) -> Response {
+    let mut socket = match RdmaStream::connect(&path) {
+        Ok(sock) => sock,
+        Err(e) => {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(format!("{:?}", e)),
+                None,
+            )
+        }
+    };
+
+    if let Err(e) = thread::Builder::new()
+        .name("rdma_migrate".to_string())
+        .spawn(move || {
+            send_migration(&mut socket);
+        })
+    {
+        return Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+            None,
+        );
+    }
+
+    Response::create_empty_response()
+}
+
+/// Get the file descriptor used by migration, which is received by `getfd` or
+/// added to fd set by `add-fd`. The caller owns the returned file descriptor.
+fn get_migration_fd(name: &str) -> Result<RawFd> {
+    if let Some(fd) = QmpChannel::remove_fd(name) {
+        Ok(fd)
+    } else if let Some(fdset_id) = fdset_id_from_path(name) {
+        QmpChannel::dup_fdset_fd(fdset_id, false)
+    } else {
+        anyhow::bail!("File descriptor named {} not found", name)
+    }
+}
+
+/// Start to migrate VM with fd mode.
+///
+/// # Arguments
+///
+/// * `name` - Name of the connected socket fd, or path of fd set as /dev/fdset/1.
+pub fn migration_fd_mode(name: String) -> Response {
+    let mut socket = match get_migration_fd(&name) {
+        Ok(fd) => {
+            // SAFETY: the file descriptor is valid and owned by migration now.
+            let _sock = unsafe { UnixStream::from_raw_fd(fd) };
+            // Specify the receiving or send timeout.
+            let time_out = Some(Duration::from_secs(30));
+            _sock
+                .set_read_timeout(time_out)
+                .unwrap_or_else(|e| error!("{:?}", e));
+            _sock
+                .set_write_timeout(time_out)
+                .unwrap_or_else(|e| error!("{:?}", e));
+            _sock
+        }
+        Err(e) => {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+                None,
+            )
+        }
+    };
+
+    if let Err(e) = thread::Builder::new()
+        .name("fd_migrate".to_string())
+        .spawn(move || {
+            send_migration(&mut socket);
+        })
+    {
+        return Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(e.to_string()),
+            None,
+        );
+    }
+
+    Response::create_empty_response()
+}
+
 /// Query the current migration status.
 pub fn query_migrate() -> Response {
-    let status_str = MigrationManager::status().to_string();
-    let migration_info = qmp_schema::MigrationInfo {
-        status: Some(status_str),
+    let status = MigrationManager::status();
+    let mut migration_info = qmp_schema::MigrationInfo {
+        status: Some(status.to_string()),
+        ..Default::default()
     };
 
+    // Only the source VM of migration reports the progress statistics.
+    let progress = MIGRATION_MANAGER.progress.read().unwrap();
+    if let Some(start_time) = progress.start_time {
+        let total_time = progress
+            .total_time
+            .unwrap_or_else(|| start_time.elapsed().as_millis() as u64);
+        migration_info.total_time = Some(total_time);
+        if status == MigrationStatus::Active {
+            let limit_downtime = MIGRATION_MANAGER.limit.read().unwrap().limit_downtime;
+            migration_info.expected_downtime = Some(progress.expected_downtime(limit_downtime));
+            migration_info.throttle_percentage = Some(progress.throttle_percentage);
+        }
+        migration_info.ram = Some(qmp_schema::MigrationStats {
+            transferred: progress.transferred,
+            remaining: progress.remaining,
+            total: progress.total,
+            dirtied: progress.dirtied,
+            dirty_pages_rate: progress.dirty_pages_rate,
+            dirty_sync_count: progress.iterations,
+            page_size: host_page_size(),
+        });
+    }
+
     Response::create_response(serde_json::to_value(migration_info).unwrap(), None)
 }
 
+/// Connect to the destination VM and check whether it is able to receive
+/// migration of this VM, nothing of either VM is changed.
+///
+/// # Arguments
+///
+/// * `uri` - Address of the destination VM, tcp, unix and rdma are supported.
+pub fn check_migration(uri: String) -> Response {
+    if MigrationManager::is_active() {
+        return Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError("Migration is in progress".to_string()),
+            None,
+        );
+    }
+
+    let time_out = Some(Duration::from_secs(30));
+    let result = match parse_incoming_uri(&uri) {
+        Ok((MigrateMode::Unix, path)) => UnixStream::connect(path)
+            .and_then(|sock| {
+                sock.set_read_timeout(time_out)?;
+                sock.set_write_timeout(time_out)?;
+                Ok(sock)
+            })
+            .map_err(anyhow::Error::from)
+            .and_then(|mut sock| MigrationManager::check_migration(&mut sock)),
+        Ok((MigrateMode::Tcp, path)) => TcpStream::connect(path)
+            .and_then(|sock| {
+                sock.set_read_timeout(time_out)?;
+                sock.set_write_timeout(time_out)?;
+                Ok(sock)
+            })
+            .map_err(anyhow::Error::from)
+            .and_then(|mut sock| MigrationManager::check_migration(&mut sock)),
+        Ok((MigrateMode::Rdma, path)) => RdmaStream::connect(&path)
+            .and_then(|mut sock| MigrationManager::check_migration(&mut sock)),
+        _ => Err(anyhow::anyhow!(
+            "Invalid uri {}, only tcp, unix and rdma are supported",
+            uri
+        )),
+    };
+
+    match result {
+        Ok(errors) => {
+            let check_info = qmp_schema::MigrateCheckInfo {
+                compatible: errors.is_empty(),
+                errors,
+            };
+            Response::create_response(serde_json::to_value(check_info).unwrap(), None)
+        }
+        Err(e) => Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(format!("{:?}", e)),
+            None,
+        ),
+    }
+}
+
 /// Cancel the current migration.
 pub fn cancel_migrate() -> Response {
     if let Err(e) = MigrationManager::set_status(MigrationStatus::Canceled) {
@@ -163,3 +349,143 @@ pub fn cancel_migrate() -> Response {
 
     Response::create_empty_response()
 }
+
+/// Query the capabilities of migration.
+pub fn query_migrate_capabilities() -> Response {
+    let params = MIGRATION_MANAGER.params.read().unwrap();
+    let caps = vec![
+        qmp_schema::MigrateCapabilities {
+            state: params.compress,
+            capability: "compress".to_string(),
+        },
+        qmp_schema::MigrateCapabilities {
+            state: params.block,
+            capability: "block".to_string(),
+        },
+    ];
+
+    Response::create_response(serde_json::to_value(caps).unwrap(), None)
+}
+
+/// Enable or disable the capabilities of migration.
+///
+/// # Arguments
+///
+/// * `caps` - The capabilities and their new state.
+pub fn set_migrate_capabilities(caps: Vec<qmp_schema::MigrateCapabilities>) -> Response {
+    if MigrationManager::is_active() {
+        return Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(
+                "Can't set capabilities during migration".to_string(),
+            ),
+            None,
+        );
+    }
+    if let Some(cap) = caps
+        .iter()
+        .find(|cap| cap.capability != "compress" && cap.capability != "block")
+    {
+        return Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(format!(
+                "Unsupported migration capability: {}",
+                cap.capability
+            )),
+            None,
+        );
+    }
+
+    let mut params = MIGRATION_MANAGER.params.write().unwrap();
+    for cap in caps.iter() {
+        match cap.capability.as_str() {
+            "compress" => params.compress = cap.state,
+            _ => params.block = cap.state,
+        }
+    }
+
+    Response::create_empty_response()
+}
+
+/// Set the parameters of migration.
+///
+/// # Arguments
+///
+/// * `args` - The parameters to be set, absent ones are left unchanged.
+pub fn set_migrate_parameters(args: qmp_schema::migrate_set_parameters) -> Response {
+    if let Some(level) = args.compress_level {
+        if !(1..=MAX_COMPRESS_LEVEL).contains(&level) {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(format!(
+                    "Invalid compress-level {}, it should be in range 1 to {}",
+                    level, MAX_COMPRESS_LEVEL
+                )),
+                None,
+            );
+        }
+    }
+    if args.compress_threads == Some(0) {
+        return Response::create_error_response(
+            qmp_schema::QmpErrorClass::GenericError(
+                "Invalid compress-threads 0, it should be in range 1 to 255".to_string(),
+            ),
+            None,
+        );
+    }
+    if let Some(downtime) = args.downtime_limit {
+        if downtime > MAX_DOWNTIME_LIMIT {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(format!(
+                    "Invalid downtime-limit {}, it should be in range 0 to {}",
+                    downtime, MAX_DOWNTIME_LIMIT
+                )),
+                None,
+            );
+        }
+    }
+    if let Some(uri) = args.block_uri.as_ref() {
+        if !matches!(
+            parse_incoming_uri(uri),
+            Ok((MigrateMode::Tcp, _)) | Ok((MigrateMode::Unix, _))
+        ) {
+            return Response::create_error_response(
+                qmp_schema::QmpErrorClass::GenericError(format!(
+                    "Invalid block-uri {}, it should be tcp:<ip>:<port> or unix:<path>",
+                    uri
+                )),
+                None,
+            );
+        }
+    }
+
+    let mut params = MIGRATION_MANAGER.params.write().unwrap();
+    if let Some(level) = args.compress_level {
+        params.compress_level = level;
+    }
+    if let Some(threads) = args.compress_threads {
+        params.compress_threads = threads;
+    }
+    if let Some(bandwidth) = args.max_bandwidth {
+        params.max_bandwidth = bandwidth;
+    }
+    if let Some(downtime) = args.downtime_limit {
+        MIGRATION_MANAGER.limit.write().unwrap().limit_downtime = downtime;
+    }
+    if let Some(uri) = args.block_uri {
+        params.block_uri = Some(uri);
+    }
+
+    Response::create_empty_response()
+}
+
+/// Query the parameters of migration.
+pub fn query_migrate_parameters() -> Response {
+    let params = MIGRATION_MANAGER.params.read().unwrap();
+    let migration_params = qmp_schema::MigrationParameters {
+        compress_level: params.compress_level,
+        compress_threads: params.compress_threads,
+        max_bandwidth: params.max_bandwidth,
+        downtime_limit: MIGRATION_MANAGER.limit.read().unwrap().limit_downtime,
+        block_uri: params.block_uri.clone(),
+    };
+
+    Response::create_response(serde_json::to_value(migration_params).unwrap(), None)
+}
@@ -14,6 +14,7 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::hash::Hash;
 use std::io::{Read, Write};
+use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::Instant;
 
@@ -24,7 +25,8 @@ use crate::general::translate_id;
 use crate::migration::DirtyBitmap;
 use crate::protocol::{DeviceStateDesc, MemBlock, MigrationStatus, StateTransfer};
 use anyhow::{Context, Result};
-use machine_manager::config::VmConfig;
+use block_backend::mirror::BlockMirror;
+use machine_manager::config::{MachineVersion, VmConfig};
 use machine_manager::machine::MachineLifecycle;
 use util::byte_code::ByteCode;
 
@@ -35,6 +37,12 @@ pub(crate) static MIGRATION_MANAGER: Lazy<MigrationManager> = Lazy::new(|| Migra
     status: Arc::new(RwLock::new(MigrationStatus::None)),
     vmm_bitmaps: Arc::new(RwLock::new(HashMap::new())),
     limit: Arc::new(RwLock::new(MigrationLimit::default())),
+    params: Arc::new(RwLock::new(MigrationParams::default())),
+    progress: Arc::new(RwLock::new(MigrationProgress::default())),
+    vm_paused: Arc::new(AtomicBool::new(false)),
+    mach_version: Arc::new(RwLock::new(MachineVersion::default())),
+    block_mirrors: Arc::new(Mutex::new(Vec::new())),
+    cpu_features: Arc::new(RwLock::new(Vec::new())),
 });
 
 /// A hook for `Device` to save device state to `Write` object and load device
@@ -109,6 +117,16 @@ pub trait MigrationHook: StateTransfer {
         Ok(())
     }
 
+    /// Load memory data from snapshot memory file into the existing memory.
+    ///
+    /// # Arguments
+    ///
+    /// * _memory - The file of memory data.
+    /// * _state - device state from memory.
+    fn load_memory(&self, _memory: &mut File, _state: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
     /// Send memory data to `Write` trait.
     ///
     /// # Arguments
@@ -138,6 +156,35 @@ pub trait MigrationHook: StateTransfer {
     fn resume(&mut self) -> Result<()> {
         Ok(())
     }
+
+    /// Start logging the guest memory written by the device outside of vmm,
+    /// such as the vhost-user backend.
+    fn start_dirty_log(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Stop logging the guest memory written by the device outside of vmm.
+    fn stop_dirty_log(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Get and clear the guest memory written by the device outside of vmm.
+    ///
+    /// # Arguments
+    ///
+    /// * _mark - Callback with guest physical address and length of dirty memory.
+    fn sync_dirty_log(&mut self, _mark: &mut dyn FnMut(u64, u64)) {}
+
+    /// Stop the device processing outside of vmm after VM is paused, so that the
+    /// device state and guest memory don't change any more.
+    fn suspend_backend(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Restart the device processing stopped by `suspend_backend`.
+    fn resume_backend(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// The instance represents a single object in VM.
@@ -199,6 +246,82 @@ impl Default for MigrationLimit {
     }
 }
 
+/// Default zstd compression level of memory pages.
+pub const DEFAULT_COMPRESS_LEVEL: i32 = 1;
+/// Max zstd compression level of memory pages.
+pub const MAX_COMPRESS_LEVEL: i32 = 22;
+/// Max downtime limit of VM in milliseconds.
+pub const MAX_DOWNTIME_LIMIT: u64 = 2_000_000;
+/// Default number of threads compressing memory pages.
+pub const DEFAULT_COMPRESS_THREADS: u8 = 8;
+
+/// Tunable parameters of migration.
+pub struct MigrationParams {
+    /// Compress memory pages with zstd before sending them.
+    pub compress: bool,
+    /// Compression level of zstd.
+    pub compress_level: i32,
+    /// Number of threads compressing memory pages in parallel.
+    pub compress_threads: u8,
+    /// Max bandwidth of sending memory in bytes per second, 0 means unlimited.
+    pub max_bandwidth: u64,
+    /// Mirror writable drives to the NBD server of destination.
+    pub block: bool,
+    /// Address of the NBD server of destination.
+    pub block_uri: Option<String>,
+}
+
+impl Default for MigrationParams {
+    fn default() -> Self {
+        Self {
+            compress: false,
+            compress_level: DEFAULT_COMPRESS_LEVEL,
+            compress_threads: DEFAULT_COMPRESS_THREADS,
+            max_bandwidth: 0,
+            block: false,
+            block_uri: None,
+        }
+    }
+}
+
+/// Progress statistics of the ongoing or last outgoing migration.
+#[derive(Default)]
+pub struct MigrationProgress {
+    /// Start time of migration, `None` if no outgoing migration is started.
+    pub start_time: Option<Instant>,
+    /// Total time of migration in milliseconds, set when migration finishes.
+    pub total_time: Option<u64>,
+    /// Total size of guest memory in bytes.
+    pub total: u64,
+    /// Bytes of memory data written to the destination VM.
+    pub transferred: u64,
+    /// Bytes of memory which is not sent in the current pass.
+    pub remaining: u64,
+    /// Bytes of memory dirtied by guest and devices since migration starts.
+    pub dirtied: u64,
+    /// Pages dirtied per second in the last iteration.
+    pub dirty_pages_rate: u64,
+    /// Number of times the dirty log is synchronized.
+    pub iterations: u64,
+    /// Bandwidth of sending memory in the last pass, in bytes per second.
+    pub bandwidth: u64,
+    /// Percentage of time waiting for `max-bandwidth` in the last pass.
+    pub throttle_percentage: u64,
+    /// Time when the dirty log is synchronized last time.
+    pub last_sync: Option<Instant>,
+}
+
+impl MigrationProgress {
+    /// Expected downtime in milliseconds if the VM is paused now and the remaining
+    /// dirty memory is sent with the measured bandwidth.
+    pub fn expected_downtime(&self, limit_downtime: u64) -> u64 {
+        if self.bandwidth == 0 {
+            return limit_downtime;
+        }
+        self.remaining * 1000 / self.bandwidth
+    }
+}
+
 /// This structure is to manage all resource during migration.
 /// It is also the only way to call on `MIGRATION_MANAGER`.
 pub struct MigrationManager {
@@ -212,6 +335,19 @@ pub struct MigrationManager {
     pub vmm_bitmaps: Arc<RwLock<HashMap<u32, DirtyBitmap>>>,
     /// Limiting elements of migration.
     pub limit: Arc<RwLock<MigrationLimit>>,
+    /// Tunable parameters of migration.
+    pub params: Arc<RwLock<MigrationParams>>,
+    /// Progress statistics of migration.
+    pub progress: Arc<RwLock<MigrationProgress>>,
+    /// Whether the VM is paused by migration, only such VM is resumed when
+    /// migration fails or is canceled.
+    pub vm_paused: Arc<AtomicBool>,
+    /// Machine version of the VM, which decides the migration sections.
+    pub mach_version: Arc<RwLock<MachineVersion>>,
+    /// Mirrors of drives during storage migration.
+    pub block_mirrors: Arc<Mutex<Vec<BlockMirror>>>,
+    /// Names of CPU features exposed to guest, which are checked before migration.
+    pub cpu_features: Arc<RwLock<Vec<String>>>,
 }
 
 impl MigrationManager {
@@ -228,6 +364,25 @@ impl MigrationManager {
         }
     }
 
+    /// Register machine version of the VM, it must be called before any device
+    /// is registered.
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The machine version from machine config.
+    pub fn register_machine_version(version: MachineVersion) {
+        *MIGRATION_MANAGER.mach_version.write().unwrap() = version;
+    }
+
+    /// Register the CPU features exposed to guest.
+    ///
+    /// # Arguments
+    ///
+    /// * `features` - Names of the CPU features.
+    pub fn register_cpu_features(features: Vec<String>) {
+        *MIGRATION_MANAGER.cpu_features.write().unwrap() = features;
+    }
+
     /// Register vm config to vmm.
     ///
     /// # Arguments
@@ -318,6 +473,17 @@ impl MigrationManager {
     ) where
         T: MigrationHook + Sync + Send + 'static,
     {
+        // Sections introduced after the machine version are neither sent nor
+        // expected, to keep the same layout as the older StratoVirt.
+        let mach_version = *MIGRATION_MANAGER.mach_version.read().unwrap();
+        if !mach_version.has_section(&device_desc.name) {
+            info!(
+                "Skip migration section {} for machine version {}",
+                device_desc.name, mach_version
+            );
+            return;
+        }
+
         let name = device_desc.name.clone() + "/" + id;
         Self::register_device_desc(device_desc);
 
@@ -423,4 +589,15 @@ mod tests {
             translate_id("DeviceV2State")
         );
     }
+
+    #[test]
+    fn test_expected_downtime() {
+        let mut progress = MigrationProgress::default();
+        // No bandwidth is measured yet, the downtime limit is expected.
+        assert_eq!(progress.expected_downtime(50), 50);
+
+        progress.bandwidth = 100 * 1024 * 1024;
+        progress.remaining = 10 * 1024 * 1024;
+        assert_eq!(progress.expected_downtime(50), 100);
+    }
 }
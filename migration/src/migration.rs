@@ -20,14 +20,57 @@ use kvm_bindings::kvm_userspace_memory_region as MemorySlot;
 use log::{info, warn};
 
 use crate::general::Lifecycle;
-use crate::manager::MIGRATION_MANAGER;
-use crate::protocol::{MemBlock, MigrationStatus, Request, Response, TransStatus};
+use crate::manager::{MigrationProgress, MIGRATION_MANAGER};
+use crate::protocol::{
+    CompatInfo, MemBlock, MigrationStatus, Request, Response, TransStatus, VersionCheck,
+};
 use crate::{MigrationError, MigrationManager};
 use anyhow::{anyhow, bail, Context, Result};
+use block_backend::mirror::BlockMirror;
 use hypervisor::kvm::KVM_FDS;
 use machine_manager::config::{get_pci_bdf, PciBdf, VmConfig};
 use util::unix::host_page_size;
 
+/// Max length of memory chunk which is compressed or throttled as a whole.
+const MEMORY_CHUNK_SIZE: u64 = 1 << 20;
+
+/// Throttle of sending memory which keeps the bandwidth under limit.
+struct Throttle {
+    /// Max bandwidth in bytes per second, 0 means unlimited.
+    max_bandwidth: u64,
+    /// Start time of sending.
+    start_time: Instant,
+    /// Bytes sent since `start_time`.
+    sent: u64,
+    /// Time waiting for the bandwidth limit since `start_time`.
+    waited: Duration,
+}
+
+impl Throttle {
+    fn new(max_bandwidth: u64) -> Self {
+        Throttle {
+            max_bandwidth,
+            start_time: Instant::now(),
+            sent: 0,
+            waited: Duration::ZERO,
+        }
+    }
+
+    /// Account the sent bytes, and sleep if the bandwidth exceeds the limit.
+    fn account(&mut self, len: u64) {
+        self.sent += len;
+        if self.max_bandwidth == 0 {
+            return;
+        }
+        let expected = Duration::from_secs_f64(self.sent as f64 / self.max_bandwidth as f64);
+        let elapsed = self.start_time.elapsed();
+        if expected > elapsed {
+            self.waited += expected - elapsed;
+            std::thread::sleep(expected - elapsed);
+        }
+    }
+}
+
 impl MigrationManager {
     /// Start VM live migration at source VM.
     ///
@@ -46,6 +89,9 @@ impl MigrationManager {
         // Send source virtual machine configuration.
         Self::send_vm_config(fd).with_context(|| "Failed to send vm config")?;
 
+        // Copy drives to destination if storage migration is enabled.
+        Self::start_block_mirror().with_context(|| "Failed to start mirroring drives")?;
+
         // Start logging dirty pages.
         Self::start_dirty_log().with_context(|| "Failed to start logging dirty page")?;
 
@@ -69,14 +115,27 @@ impl MigrationManager {
         if Self::is_canceled() {
             // Cancel the migration of source and destination.
             Self::cancel_migration(fd).with_context(|| "Failed to cancel migration")?;
-            return Ok(());
+            bail!(MigrationError::Canceled);
         }
 
         // Pause virtual machine.
         Self::pause()?;
+        // Stop the devices processing outside of vmm, such as vhost-user backends.
+        Self::suspend_backends().with_context(|| "Failed to suspend device backends")?;
 
         // Send remaining virtual machine dirty memory.
-        Self::send_dirty_memory(fd).with_context(|| "Failed to send dirty memory")?;
+        // The VM is paused, send the remaining dirty memory without bandwidth limit to
+        // shorten the downtime.
+        Self::send_dirty_memory(fd, false).with_context(|| "Failed to send dirty memory")?;
+        // Copy the remaining data of drives, which don't change any more.
+        Self::complete_block_mirror().with_context(|| "Failed to complete mirroring drives")?;
+
+        // The migration can still be canceled before the state of VM is sent,
+        // then the source VM is resumed by `recover_from_migration`.
+        if Self::is_canceled() {
+            Self::cancel_migration(fd).with_context(|| "Failed to cancel migration")?;
+            bail!(MigrationError::Canceled);
+        }
 
         // Stop logging dirty pages.
         Self::stop_dirty_log().with_context(|| "Failed to stop logging dirty page")?;
@@ -100,13 +159,21 @@ impl MigrationManager {
     /// * `fd` - The fd implements `Read` and `Write` trait object. it
     /// will receive source VM memory data and devices state. And,
     /// it will send confirmation to source VM.
-    pub fn recv_migration<T>(fd: &mut T) -> Result<()>
+    ///
+    /// Return false if the source only checks the compatibility of destination,
+    /// then no migration is received.
+    pub fn recv_migration<T>(fd: &mut T) -> Result<bool>
     where
         T: Read + Write,
     {
         // Activate the migration status.
         let request = Request::recv_msg(fd)?;
-        if request.status == TransStatus::Active {
+        if request.status == TransStatus::Check {
+            info!("Receive Check status");
+            Self::reply_compat_check(fd, request.length)
+                .with_context(|| "Failed to check compatibility")?;
+            return Ok(false);
+        } else if request.status == TransStatus::Active {
             info!("Active the migration");
             Self::set_status(MigrationStatus::Active)?;
             Response::send_msg(fd, TransStatus::Ok)?;
@@ -139,6 +206,10 @@ impl MigrationManager {
                     info!("Receive Memory status");
                     Self::recv_vm_memory(fd, request.length)?;
                 }
+                TransStatus::CompressedMemory => {
+                    info!("Receive CompressedMemory status");
+                    Self::recv_compressed_memory(fd, request.length)?;
+                }
                 TransStatus::State => {
                     info!("Receive State status");
                     Self::recv_vmstate(fd)?;
@@ -157,9 +228,143 @@ impl MigrationManager {
             }
         }
 
+        Ok(true)
+    }
+
+    /// Check whether the destination VM is able to receive migration of the
+    /// source VM, without changing the state of either side. Return all the
+    /// incompatibilities found by destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `fd` - The fd implements `Read` and `Write` trait object.
+    pub fn check_migration<T>(fd: &mut T) -> Result<Vec<String>>
+    where
+        T: Read + Write,
+    {
+        let info = CompatInfo {
+            config: MIGRATION_MANAGER
+                .vmm
+                .read()
+                .unwrap()
+                .config
+                .lock()
+                .unwrap()
+                .clone(),
+            devices: MIGRATION_MANAGER
+                .desc_db
+                .read()
+                .unwrap()
+                .values()
+                .cloned()
+                .collect(),
+            cpu_features: MIGRATION_MANAGER.cpu_features.read().unwrap().clone(),
+        };
+        let data = serde_json::to_vec(&info)?;
+        Request::send_msg(fd, TransStatus::Check, data.len() as u64)?;
+        fd.write_all(&data)?;
+
+        let reply = Request::recv_msg(fd)?;
+        if reply.status != TransStatus::Check {
+            return Err(anyhow!(MigrationError::MigrationStatusErr(
+                TransStatus::Check.to_string(),
+                (reply.status as u16).to_string(),
+            )));
+        }
+        let mut data: Vec<u8> = Vec::new();
+        data.resize_with(reply.length as usize, Default::default);
+        fd.read_exact(&mut data)?;
+
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Check the compatibility information from source, and reply with all the
+    /// incompatibilities.
+    fn reply_compat_check<T>(fd: &mut T, len: u64) -> Result<()>
+    where
+        T: Write + Read,
+    {
+        let mut data: Vec<u8> = Vec::new();
+        data.resize_with(len as usize, Default::default);
+        fd.read_exact(&mut data)?;
+
+        let errors = Self::check_compat(&serde_json::from_slice(&data)?);
+        if errors.is_empty() {
+            info!("Destination is compatible with source");
+        } else {
+            warn!("Destination is incompatible with source: {:?}", errors);
+        }
+        let data = serde_json::to_vec(&errors)?;
+        Request::send_msg(fd, TransStatus::Check, data.len() as u64)?;
+        fd.write_all(&data)?;
+
         Ok(())
     }
 
+    /// Check the compatibility information from source against destination.
+    fn check_compat(src: &CompatInfo) -> Vec<String> {
+        let dest_config = &MIGRATION_MANAGER
+            .vmm
+            .read()
+            .unwrap()
+            .config
+            .lock()
+            .unwrap()
+            .clone();
+        let mut errors = Vec::new();
+        for check in [
+            Self::check_machine,
+            Self::check_vcpu,
+            Self::check_memory,
+            Self::check_devices,
+            Self::check_cpu_config,
+        ] {
+            if let Err(e) = check(&src.config, dest_config) {
+                errors.push(format!("{:#}", e));
+            }
+        }
+
+        let desc_db = MIGRATION_MANAGER.desc_db.read().unwrap();
+        for src_desc in src.devices.iter() {
+            let desc = match desc_db.get(&src_desc.name) {
+                Some(desc) => desc,
+                None => {
+                    errors.push(format!(
+                        "Device state {} is not supported by destination",
+                        src_desc.name
+                    ));
+                    continue;
+                }
+            };
+            let err = match desc.check_version(src_desc) {
+                VersionCheck::Mismatch if src_desc.current_version > desc.current_version => {
+                    MigrationError::VersionTooNew(src_desc.current_version, desc.current_version)
+                }
+                VersionCheck::Mismatch => {
+                    MigrationError::VersionNotFit(desc.compat_version, src_desc.current_version)
+                }
+                _ => continue,
+            };
+            errors.push(format!("Device state {}: {}", src_desc.name, err));
+        }
+
+        let dest_features = MIGRATION_MANAGER.cpu_features.read().unwrap();
+        let missing: Vec<&str> = src
+            .cpu_features
+            .iter()
+            .filter(|f| !dest_features.contains(f))
+            .map(|f| f.as_str())
+            .collect();
+        if !missing.is_empty() {
+            errors.push(format!(
+                "CPU features missing on destination: {}",
+                missing.join(", ")
+            ));
+        }
+
+        errors
+    }
+
     /// Send Vm configuration from source virtual machine.
     fn send_vm_config<T>(fd: &mut T) -> Result<()>
     where
@@ -203,6 +408,7 @@ impl MigrationManager {
             .lock()
             .unwrap()
             .clone();
+        Self::check_machine(src_config, dest_config)?;
         // Check vCPU number.
         Self::check_vcpu(src_config, dest_config)?;
         Self::check_memory(src_config, dest_config)?;
@@ -213,6 +419,33 @@ impl MigrationManager {
         Ok(())
     }
 
+    /// Check machine type and machine version config.
+    fn check_machine(src_config: &VmConfig, dest_config: &VmConfig) -> Result<()> {
+        let src_type = src_config.machine_config.mach_type;
+        let dest_type = dest_config.machine_config.mach_type;
+        if src_type != dest_type {
+            return Err(anyhow!(MigrationError::MigrationConfigErr(
+                "machine type".to_string(),
+                format!("{:?}", src_type),
+                format!("{:?}", dest_type),
+            )));
+        }
+
+        // The destination must be started with the machine version of the source,
+        // which freezes device defaults and migration sections.
+        let src_version = src_config.machine_config.mach_version;
+        let dest_version = dest_config.machine_config.mach_version;
+        if src_version != dest_version {
+            return Err(anyhow!(MigrationError::MigrationConfigErr(
+                "machine version".to_string(),
+                src_version.to_string(),
+                dest_version.to_string(),
+            )));
+        }
+
+        Ok(())
+    }
+
     /// Check vcpu number config.
     fn check_vcpu(src_config: &VmConfig, dest_config: &VmConfig) -> Result<()> {
         let src_cpu = src_config.machine_config.nr_cpus;
@@ -275,6 +508,45 @@ impl MigrationManager {
         Ok(())
     }
 
+    /// Check the CPU features which are configured explicitly.
+    fn check_cpu_config(src_config: &VmConfig, dest_config: &VmConfig) -> Result<()> {
+        let src_cpu = &src_config.machine_config.cpu_config;
+        let dest_cpu = &dest_config.machine_config.cpu_config;
+        for (item, src, dest) in [
+            (
+                "PMU",
+                format!("{:?}", src_cpu.pmu),
+                format!("{:?}", dest_cpu.pmu),
+            ),
+            ("SVE", src_cpu.sve.to_string(), dest_cpu.sve.to_string()),
+            (
+                "SVE max vector length",
+                format!("{:?}", src_cpu.sve_max_vq),
+                format!("{:?}", dest_cpu.sve_max_vq),
+            ),
+            (
+                "pointer authentication",
+                src_cpu.pauth.to_string(),
+                dest_cpu.pauth.to_string(),
+            ),
+            (
+                "TSC frequency",
+                format!("{:?}", src_cpu.tsc_khz),
+                format!("{:?}", dest_cpu.tsc_khz),
+            ),
+        ] {
+            if src != dest {
+                return Err(anyhow!(MigrationError::MigrationConfigErr(
+                    item.to_string(),
+                    src,
+                    dest
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Start to send dirty memory page iteratively. Return true if it should
     /// continue to the next iteration. Otherwise, return false.
     ///
@@ -285,8 +557,9 @@ impl MigrationManager {
     where
         T: Write + Read,
     {
+        Self::sync_block_mirror().with_context(|| "Failed to sync mirror of drives")?;
         let mut state =
-            Self::send_dirty_memory(fd).with_context(|| "Failed to send dirty memory")?;
+            Self::send_dirty_memory(fd, true).with_context(|| "Failed to send dirty memory")?;
 
         // Check the virtual machine downtime.
         if MIGRATION_MANAGER
@@ -319,14 +592,7 @@ impl MigrationManager {
     where
         T: Write + Read,
     {
-        let mut blocks = Vec::<MemBlock>::new();
-        blocks.resize_with(len as usize / (size_of::<MemBlock>()), Default::default);
-        fd.read_exact(unsafe {
-            std::slice::from_raw_parts_mut(
-                blocks.as_ptr() as *mut MemBlock as *mut u8,
-                len as usize,
-            )
-        })?;
+        let blocks = Self::recv_mem_blocks(fd, len)?;
 
         if let Some(locked_memory) = &MIGRATION_MANAGER.vmm.read().unwrap().memory {
             for block in blocks.iter() {
@@ -345,16 +611,112 @@ impl MigrationManager {
         Ok(())
     }
 
+    /// Receive zstd compressed memory data from source VM.
+    ///
+    /// # Arguments
+    ///
+    /// * `fd` - The fd implements `Read` and `Write` trait object.
+    /// * `len` - The length of Block data.
+    fn recv_compressed_memory<T>(fd: &mut T, len: u64) -> Result<()>
+    where
+        T: Write + Read,
+    {
+        let blocks = Self::recv_mem_blocks(fd, len)?;
+
+        if let Some(locked_memory) = &MIGRATION_MANAGER.vmm.read().unwrap().memory {
+            for block in blocks.iter() {
+                let mut size = [0_u8; size_of::<u64>()];
+                fd.read_exact(&mut size)?;
+                let size = u64::from_le_bytes(size) as usize;
+                if block.len > MEMORY_CHUNK_SIZE
+                    || size > zstd::zstd_safe::compress_bound(block.len as usize)
+                {
+                    bail!(
+                        "Invalid compressed memory chunk at 0x{:x}, length {}, compressed {}",
+                        block.gpa,
+                        block.len,
+                        size
+                    );
+                }
+                let mut data = vec![0_u8; size];
+                fd.read_exact(&mut data)?;
+
+                let raw = zstd::bulk::decompress(&data, block.len as usize)
+                    .with_context(|| "Failed to decompress memory data")?;
+                if raw.len() as u64 != block.len {
+                    bail!(
+                        "Decompressed memory length {} mismatch with block length {}",
+                        raw.len(),
+                        block.len
+                    );
+                }
+                locked_memory.recv_memory(&mut raw.as_slice(), block.clone())?;
+            }
+        }
+
+        Response::send_msg(fd, TransStatus::Ok)?;
+
+        Ok(())
+    }
+
+    /// Receive the array of memory blocks from source VM.
+    ///
+    /// # Arguments
+    ///
+    /// * `fd` - The fd implements `Read` trait object.
+    /// * `len` - The length of Block data.
+    fn recv_mem_blocks<T>(fd: &mut T, len: u64) -> Result<Vec<MemBlock>>
+    where
+        T: Read,
+    {
+        let mut blocks = Vec::<MemBlock>::new();
+        blocks.resize_with(len as usize / (size_of::<MemBlock>()), Default::default);
+        fd.read_exact(unsafe {
+            std::slice::from_raw_parts_mut(
+                blocks.as_ptr() as *mut MemBlock as *mut u8,
+                len as usize,
+            )
+        })?;
+
+        Ok(blocks)
+    }
+
     /// Send memory data to destination VM.
     ///
     /// # Arguments
     ///
     /// * `fd` - The fd implements `Read` and `Write` trait object.
     /// * `blocks` - The memory blocks need to be sent.
-    fn send_memory<T>(fd: &mut T, blocks: Vec<MemBlock>) -> Result<()>
+    /// * `throttled` - Whether the bandwidth of sending is limited by `max-bandwidth`.
+    fn send_memory<T>(fd: &mut T, blocks: Vec<MemBlock>, throttled: bool) -> Result<()>
     where
         T: Read + Write,
     {
+        let params = MIGRATION_MANAGER.params.read().unwrap();
+        let mut throttle = Throttle::new(if throttled { params.max_bandwidth } else { 0 });
+        let dirty_ring = KVM_FDS.load().dirty_ring_enabled();
+        if params.compress {
+            let level = params.compress_level;
+            let threads = params.compress_threads;
+            drop(params);
+            return Self::send_compressed_memory(
+                fd,
+                blocks,
+                level,
+                threads,
+                &mut throttle,
+                dirty_ring,
+            );
+        }
+        drop(params);
+
+        // Split blocks into chunks, so that the bandwidth can be checked, the dirty
+        // rings can be harvested and the progress can be updated between chunks.
+        let blocks: Vec<MemBlock> = blocks
+            .iter()
+            .flat_map(|block| block.split(MEMORY_CHUNK_SIZE))
+            .collect();
+        Self::start_progress_pass(&blocks);
         let len = size_of::<MemBlock>() * blocks.len();
         Request::send_msg(fd, TransStatus::Memory, len as u64)?;
         fd.write_all(unsafe {
@@ -370,8 +732,103 @@ impl MigrationManager {
                         len: block.len,
                     },
                 )?;
+                throttle.account(block.len);
+                Self::account_progress(block.len, block.len);
+                // Abort the pass, the destination fails for the incomplete data.
+                if Self::is_canceled() {
+                    bail!(MigrationError::Canceled);
+                }
+                if dirty_ring {
+                    Self::harvest_dirty_rings()?;
+                }
+            }
+        }
+        Self::finish_progress_pass(&throttle);
+
+        let result = Response::recv_msg(fd)?;
+        if result.is_err() {
+            return Err(anyhow!(MigrationError::ResponseErr));
+        }
+
+        Ok(())
+    }
+
+    /// Send zstd compressed memory data to destination VM.
+    ///
+    /// Memory blocks are split into chunks, which are compressed by
+    /// `threads` threads in parallel and sent in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `fd` - The fd implements `Read` and `Write` trait object.
+    /// * `blocks` - The memory blocks need to be sent.
+    /// * `level` - The compression level of zstd.
+    /// * `threads` - The number of compression threads.
+    /// * `throttle` - The throttle limiting the bandwidth of sending.
+    /// * `dirty_ring` - Whether to harvest the dirty rings between chunks.
+    fn send_compressed_memory<T>(
+        fd: &mut T,
+        blocks: Vec<MemBlock>,
+        level: i32,
+        threads: u8,
+        throttle: &mut Throttle,
+        dirty_ring: bool,
+    ) -> Result<()>
+    where
+        T: Read + Write,
+    {
+        let chunks: Vec<MemBlock> = blocks
+            .iter()
+            .flat_map(|block| block.split(MEMORY_CHUNK_SIZE))
+            .collect();
+        Self::start_progress_pass(&chunks);
+        let len = size_of::<MemBlock>() * chunks.len();
+        Request::send_msg(fd, TransStatus::CompressedMemory, len as u64)?;
+        fd.write_all(unsafe {
+            std::slice::from_raw_parts(chunks.as_ptr() as *const MemBlock as *const u8, len)
+        })?;
+
+        if let Some(locked_memory) = &MIGRATION_MANAGER.vmm.read().unwrap().memory {
+            for batch in chunks.chunks(std::cmp::max(threads, 1) as usize) {
+                let compressed = std::thread::scope(|s| {
+                    let handles: Vec<_> = batch
+                        .iter()
+                        .map(|chunk| {
+                            s.spawn(move || -> Result<Vec<u8>> {
+                                let mut raw = Vec::with_capacity(chunk.len as usize);
+                                locked_memory.send_memory(&mut raw, chunk.clone())?;
+                                zstd::bulk::compress(&raw, level)
+                                    .with_context(|| "Failed to compress memory data")
+                            })
+                        })
+                        .collect();
+                    handles
+                        .into_iter()
+                        .map(|handle| {
+                            handle
+                                .join()
+                                .map_err(|_| anyhow!("Memory compression thread panicked"))?
+                        })
+                        .collect::<Result<Vec<Vec<u8>>>>()
+                })?;
+
+                for (chunk, data) in batch.iter().zip(compressed.iter()) {
+                    fd.write_all(&(data.len() as u64).to_le_bytes())?;
+                    fd.write_all(data)?;
+                    let sent = (size_of::<u64>() + data.len()) as u64;
+                    throttle.account(sent);
+                    Self::account_progress(chunk.len, sent);
+                }
+                // Abort the pass, the destination fails for the incomplete data.
+                if Self::is_canceled() {
+                    bail!(MigrationError::Canceled);
+                }
+                if dirty_ring {
+                    Self::harvest_dirty_rings()?;
+                }
             }
         }
+        Self::finish_progress_pass(throttle);
 
         let result = Response::recv_msg(fd)?;
         if result.is_err() {
@@ -399,7 +856,7 @@ impl MigrationManager {
             });
         }
 
-        Self::send_memory(fd, blocks)?;
+        Self::send_memory(fd, blocks, true)?;
 
         Ok(())
     }
@@ -409,26 +866,183 @@ impl MigrationManager {
     /// # Arguments
     ///
     /// * `fd` - The fd implements `Read` and `Write` trait object.
-    fn send_dirty_memory<T>(fd: &mut T) -> Result<bool>
+    /// * `throttled` - Whether the bandwidth of sending is limited by `max-bandwidth`, and
+    ///   clearing the dirty log in kvm is paced, as vcpus are running.
+    fn send_dirty_memory<T>(fd: &mut T, throttled: bool) -> Result<bool>
     where
         T: Read + Write,
     {
+        // Harvest the dirty rings of all vcpus once for all slots, instead of
+        // syncing the whole dirty bitmap of each slot.
+        let dirty_ring = KVM_FDS.load().dirty_ring_enabled();
+        if dirty_ring {
+            Self::harvest_dirty_rings()?;
+        }
+
+        Self::sync_devices_dirty_log();
+
         let mut blocks: Vec<MemBlock> = Vec::new();
         let mem_slots = KVM_FDS.load().get_mem_slots();
         for (_, slot) in mem_slots.lock().unwrap().iter() {
-            let sub_blocks: Vec<MemBlock> = Self::get_dirty_log(slot)?;
+            let sub_blocks: Vec<MemBlock> = Self::get_dirty_log(slot, dirty_ring, throttled)?;
             blocks.extend(sub_blocks);
         }
+        Self::account_dirty_memory(&blocks);
 
         if blocks.is_empty() {
             return Ok(false);
         }
 
-        Self::send_memory(fd, blocks)?;
+        Self::send_memory(fd, blocks, throttled)?;
 
         Ok(true)
     }
 
+    /// Start mirroring writable drives to the NBD server of destination and copy
+    /// the whole drives, if the `block` capability is enabled.
+    fn start_block_mirror() -> Result<()> {
+        let params = MIGRATION_MANAGER.params.read().unwrap();
+        if !params.block {
+            return Ok(());
+        }
+        let uri = params
+            .block_uri
+            .clone()
+            .with_context(|| "block-uri is not set for storage migration")?;
+        let max_bandwidth = params.max_bandwidth;
+        drop(params);
+
+        let drives: Vec<(String, String)> = MIGRATION_MANAGER
+            .vmm
+            .read()
+            .unwrap()
+            .config
+            .lock()
+            .unwrap()
+            .drives
+            .values()
+            .filter(|drive| !drive.read_only && !drive.path_on_host.is_empty())
+            .map(|drive| (drive.id.clone(), drive.path_on_host.clone()))
+            .collect();
+        let mut mirrors = Vec::new();
+        for (id, path) in drives.iter() {
+            mirrors.push(BlockMirror::new(id, path, &uri)?);
+        }
+        // Mirrors are dropped by `recover_from_migration` if migration fails.
+        *MIGRATION_MANAGER.block_mirrors.lock().unwrap() = mirrors;
+
+        let mut throttle = Throttle::new(max_bandwidth);
+        for mirror in MIGRATION_MANAGER.block_mirrors.lock().unwrap().iter_mut() {
+            mirror.copy_all(&mut |len| Self::account_block_copy(&mut throttle, len))?;
+        }
+
+        Ok(())
+    }
+
+    /// Copy the data of drives written by guest since last copying.
+    fn sync_block_mirror() -> Result<()> {
+        let max_bandwidth = MIGRATION_MANAGER.params.read().unwrap().max_bandwidth;
+        let mut throttle = Throttle::new(max_bandwidth);
+        for mirror in MIGRATION_MANAGER.block_mirrors.lock().unwrap().iter_mut() {
+            mirror.sync(&mut |len| Self::account_block_copy(&mut throttle, len))?;
+        }
+
+        Ok(())
+    }
+
+    /// Copy the remaining data of drives after VM is paused, and finish mirroring.
+    fn complete_block_mirror() -> Result<()> {
+        let mut mirrors = MIGRATION_MANAGER.block_mirrors.lock().unwrap();
+        for mirror in mirrors.iter_mut() {
+            mirror.complete()?;
+        }
+        mirrors.clear();
+
+        Ok(())
+    }
+
+    /// Throttle copying drives, and abort it if migration is canceled.
+    fn account_block_copy(throttle: &mut Throttle, len: u64) -> Result<()> {
+        throttle.account(len);
+        if Self::is_canceled() {
+            bail!(MigrationError::Canceled);
+        }
+        Ok(())
+    }
+
+    /// Reset the progress statistics when a new outgoing migration starts.
+    fn reset_progress() {
+        let total = KVM_FDS
+            .load()
+            .get_mem_slots()
+            .lock()
+            .unwrap()
+            .values()
+            .map(|slot| slot.memory_size)
+            .sum();
+        let now = Instant::now();
+        *MIGRATION_MANAGER.progress.write().unwrap() = MigrationProgress {
+            start_time: Some(now),
+            total,
+            last_sync: Some(now),
+            ..Default::default()
+        };
+    }
+
+    /// Start a pass of sending memory in progress statistics.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The memory blocks to be sent in this pass.
+    fn start_progress_pass(blocks: &[MemBlock]) {
+        MIGRATION_MANAGER.progress.write().unwrap().remaining =
+            blocks.iter().map(|block| block.len).sum();
+    }
+
+    /// Account the memory sent to destination VM in progress statistics.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - Length of the guest memory sent.
+    /// * `sent` - Bytes written to the destination VM for the memory.
+    fn account_progress(len: u64, sent: u64) {
+        let mut progress = MIGRATION_MANAGER.progress.write().unwrap();
+        progress.transferred += sent;
+        progress.remaining = progress.remaining.saturating_sub(len);
+    }
+
+    /// Update the bandwidth and throttle statistics when a pass of sending
+    /// memory finishes.
+    fn finish_progress_pass(throttle: &Throttle) {
+        let elapsed = throttle.start_time.elapsed().as_secs_f64();
+        if elapsed == 0.0 {
+            return;
+        }
+        let mut progress = MIGRATION_MANAGER.progress.write().unwrap();
+        progress.bandwidth = (throttle.sent as f64 / elapsed) as u64;
+        progress.throttle_percentage = std::cmp::min(
+            (throttle.waited.as_secs_f64() * 100.0 / elapsed) as u64,
+            100,
+        );
+    }
+
+    /// Account the dirty memory collected from the dirty log in progress statistics.
+    fn account_dirty_memory(blocks: &[MemBlock]) {
+        let dirtied: u64 = blocks.iter().map(|block| block.len).sum();
+        let now = Instant::now();
+        let mut progress = MIGRATION_MANAGER.progress.write().unwrap();
+        progress.dirtied += dirtied;
+        progress.iterations += 1;
+        if let Some(last_sync) = progress.last_sync {
+            let elapsed = now.duration_since(last_sync).as_secs_f64();
+            if elapsed > 0.0 {
+                progress.dirty_pages_rate =
+                    (dirtied as f64 / host_page_size() as f64 / elapsed) as u64;
+            }
+        }
+        progress.last_sync = Some(now);
+    }
+
     /// Send VM state data to destination VM.
     ///
     /// # Arguments
@@ -479,6 +1093,8 @@ impl MigrationManager {
     where
         T: Read + Write,
     {
+        Self::reset_progress();
+        MIGRATION_MANAGER.vm_paused.store(false, Ordering::SeqCst);
         Self::set_status(MigrationStatus::Active)?;
         Request::send_msg(fd, TransStatus::Active, 0)?;
         let result = Response::recv_msg(fd)?;
@@ -563,10 +1179,25 @@ impl MigrationManager {
         Ok(())
     }
 
-    /// Recover the virtual machine if migration is failed.
+    /// Recover the virtual machine if migration is failed or canceled.
     pub fn recover_from_migration() -> Result<()> {
-        if let Some(locked_vm) = &MIGRATION_MANAGER.vmm.read().unwrap().vm {
-            locked_vm.lock().unwrap().resume();
+        // Tear down the dirty log which may be left by the failed migration.
+        if let Err(e) = Self::stop_dirty_log() {
+            warn!(
+                "Failed to stop dirty log when recovering from migration: {:?}",
+                e
+            );
+        }
+        // Stop logging the drives which are not mirrored completely.
+        MIGRATION_MANAGER.block_mirrors.lock().unwrap().clear();
+        Self::resume_backends()?;
+
+        // Only resume the VM paused by migration, and the drive files are
+        // activated again by resuming VM.
+        if MIGRATION_MANAGER.vm_paused.swap(false, Ordering::SeqCst) {
+            if let Some(locked_vm) = &MIGRATION_MANAGER.vmm.read().unwrap().vm {
+                locked_vm.lock().unwrap().resume();
+            }
         }
 
         Ok(())
@@ -665,6 +1296,23 @@ pub trait Migratable {
         // Start logging dirty memory in kvm.
         KVM_FDS.load().start_dirty_log()?;
 
+        // Start logging dirty memory written by devices outside of vmm.
+        let locked_devices = &MIGRATION_MANAGER.vmm.read().unwrap().devices;
+        for (_, device) in locked_devices.iter() {
+            device.lock().unwrap().start_dirty_log()?;
+        }
+
+        Ok(())
+    }
+
+    /// Harvest the dirty rings while sending memory, so that vcpus don't exit
+    /// for full dirty ring before the next iteration.
+    fn harvest_dirty_rings() -> Result<()> {
+        KVM_FDS
+            .load()
+            .harvest_dirty_rings()
+            .with_context(|| "Failed to harvest dirty rings")?;
+
         Ok(())
     }
 
@@ -677,15 +1325,44 @@ pub trait Migratable {
         // Stop logging dirty memory in kvm.
         KVM_FDS.load().stop_dirty_log()?;
 
+        Self::stop_devices_dirty_log()
+    }
+
+    /// Stop the dirty log of devices outside of vmm.
+    fn stop_devices_dirty_log() -> Result<()> {
+        let locked_devices = &MIGRATION_MANAGER.vmm.read().unwrap().devices;
+        for (_, device) in locked_devices.iter() {
+            device.lock().unwrap().stop_dirty_log()?;
+        }
+
         Ok(())
     }
 
+    /// Collect the dirty log of devices outside of vmm into vmm bitmaps.
+    fn sync_devices_dirty_log() {
+        let bitmaps = MIGRATION_MANAGER.vmm_bitmaps.read().unwrap();
+        let mut mark = |gpa: u64, len: u64| {
+            for (_, map) in bitmaps.iter() {
+                if (gpa >= map.gpa) && ((gpa + len) <= (map.gpa + map.len)) {
+                    map.mark_bitmap(gpa, len);
+                }
+            }
+        };
+
+        let locked_devices = &MIGRATION_MANAGER.vmm.read().unwrap().devices;
+        for (_, device) in locked_devices.iter() {
+            device.lock().unwrap().sync_dirty_log(&mut mark);
+        }
+    }
+
     /// Collect the dirty log from kvm and vmm.
     ///
     /// # Arguments
     ///
     /// * `slot` - The memory slot.
-    fn get_dirty_log(slot: &MemorySlot) -> Result<Vec<MemBlock>> {
+    /// * `dirty_ring` - Whether the dirty pages have been harvested from dirty rings.
+    /// * `paced` - Whether to pace clearing the dirty log in kvm, as vcpus are running.
+    fn get_dirty_log(slot: &MemorySlot, dirty_ring: bool, paced: bool) -> Result<Vec<MemBlock>> {
         // Get dirty memory from vmm.
         let mut vmm_dirty_bitmap = Vec::new();
         let bitmaps = MIGRATION_MANAGER.vmm_bitmaps.write().unwrap();
@@ -696,10 +1373,14 @@ pub trait Migratable {
         }
 
         // Get dirty memory from kvm.
-        let vm_dirty_bitmap = KVM_FDS
-            .load()
-            .get_dirty_log(slot.slot, slot.memory_size)
-            .unwrap();
+        let vm_dirty_bitmap = if dirty_ring {
+            KVM_FDS.load().take_dirty_pages(slot.slot, slot.memory_size)
+        } else {
+            KVM_FDS
+                .load()
+                .get_dirty_log(slot.slot, slot.memory_size, paced)
+                .unwrap()
+        };
 
         // Merge dirty bitmap.
         let dirty_bitmap: Vec<u64> = vm_dirty_bitmap
@@ -16,6 +16,7 @@ use std::mem::size_of;
 use std::slice::{from_raw_parts, from_raw_parts_mut};
 
 use kvm_ioctls::Kvm;
+use machine_manager::config::VmConfig;
 use serde::{Deserialize, Serialize};
 
 use crate::MigrationError;
@@ -129,6 +130,10 @@ pub enum TransStatus {
     Ok,
     /// Something error in migration .
     Error,
+    /// Processing zstd compressed memory data stage in migration.
+    CompressedMemory,
+    /// Check compatibility of destination before migration.
+    Check,
     /// Unknown status in migration .
     Unknown,
 }
@@ -153,6 +158,8 @@ impl std::fmt::Display for TransStatus {
                 TransStatus::Cancel => "Cancel",
                 TransStatus::Ok => "Ok",
                 TransStatus::Error => "Error",
+                TransStatus::CompressedMemory => "CompressedMemory",
+                TransStatus::Check => "Check",
                 TransStatus::Unknown => "Unknown",
             }
         )
@@ -271,6 +278,18 @@ impl Response {
     }
 }
 
+/// Information of source VM, which is sent to destination to check the
+/// compatibility before migration.
+#[derive(Serialize, Deserialize)]
+pub struct CompatInfo {
+    /// Configuration of source VM.
+    pub config: VmConfig,
+    /// Descriptors of the device states to be migrated.
+    pub devices: Vec<DeviceStateDesc>,
+    /// Names of CPU features exposed to guest.
+    pub cpu_features: Vec<String>,
+}
+
 /// Structure is used to save guest physical address and length of
 /// memory block that needs to send.
 #[repr(C)]
@@ -282,6 +301,28 @@ pub struct MemBlock {
     pub len: u64,
 }
 
+impl MemBlock {
+    /// Split memory block into blocks no larger than `size`.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The max length of split memory block, must not be zero.
+    pub fn split(&self, size: u64) -> Vec<MemBlock> {
+        let mut blocks = Vec::new();
+        let mut offset = 0;
+        while offset < self.len {
+            let len = std::cmp::min(size, self.len - offset);
+            blocks.push(MemBlock {
+                gpa: self.gpa + offset,
+                len,
+            });
+            offset += len;
+        }
+
+        blocks
+    }
+}
+
 /// Magic number for migration header. Those bytes represent "STRATOVIRT".
 const MAGIC_NUMBER: [u8; 16] = [
     0x53, 0x54, 0x52, 0x41, 0x54, 0x4f, 0x56, 0x49, 0x52, 0x54, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
@@ -584,8 +625,8 @@ impl DeviceStateDesc {
 
     /// Check device state version descriptor version message.
     /// If version is same, return enum `Same`.
-    /// If version is not same but fit, return enum `Compat`.
-    /// if version is not fit, return enum `Mismatch`.
+    /// If version is older but not lower than `compat_version`, return enum `Compat`.
+    /// If version is lower than `compat_version` or newer, return enum `Mismatch`.
     ///
     /// # Arguments
     ///
@@ -593,8 +634,10 @@ impl DeviceStateDesc {
     pub fn check_version(&self, desc: &DeviceStateDesc) -> VersionCheck {
         match self.current_version.cmp(&desc.current_version) {
             Ordering::Equal => VersionCheck::Same,
-            Ordering::Greater => VersionCheck::Compat,
-            Ordering::Less => VersionCheck::Mismatch,
+            Ordering::Greater if desc.current_version >= self.compat_version => {
+                VersionCheck::Compat
+            }
+            _ => VersionCheck::Mismatch,
         }
     }
 }
@@ -1106,6 +1149,28 @@ pub mod tests {
         assert_eq!(device_v5.state.rii, device_v2.state.iir as u64);
     }
 
+    #[test]
+    fn test_desc_version_mismatch() {
+        let state_1_desc = DeviceV1State::descriptor();
+        let state_2_desc = DeviceV2State::descriptor();
+        let state_3_desc = DeviceV3State::descriptor();
+
+        // State older than compat version of current device can't be restored.
+        assert_eq!(
+            state_3_desc.check_version(&state_1_desc),
+            VersionCheck::Mismatch
+        );
+        // State newer than current device can't be restored.
+        assert_eq!(
+            state_2_desc.check_version(&state_3_desc),
+            VersionCheck::Mismatch
+        );
+        assert_eq!(
+            state_2_desc.check_version(&state_2_desc),
+            VersionCheck::Same
+        );
+    }
+
     #[test]
     fn test_check_header() {
         if !Kvm::new().is_ok() {
@@ -1115,4 +1180,27 @@ pub mod tests {
         let header = MigrationHeader::default();
         assert_eq!(header.check_header().is_ok(), true);
     }
+
+    #[test]
+    fn test_mem_block_split() {
+        let block = MemBlock {
+            gpa: 0x1000,
+            len: 0x2800,
+        };
+        let blocks = block.split(0x1000);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!((blocks[0].gpa, blocks[0].len), (0x1000, 0x1000));
+        assert_eq!((blocks[1].gpa, blocks[1].len), (0x2000, 0x1000));
+        assert_eq!((blocks[2].gpa, blocks[2].len), (0x3000, 0x800));
+
+        let blocks = block.split(0x4000);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!((blocks[0].gpa, blocks[0].len), (0x1000, 0x2800));
+
+        let empty = MemBlock {
+            gpa: 0x1000,
+            len: 0,
+        };
+        assert!(empty.split(0x1000).is_empty());
+    }
 }
@@ -0,0 +1,272 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+//! RDMA transport of migration stream, which is based on rsocket of librdmacm.
+//!
+//! The rsocket API is the same as BSD socket, and the data is transferred by
+//! RDMA over RoCE or InfiniBand. librdmacm is loaded when RDMA migration is
+//! used, so StratoVirt doesn't depend on it otherwise.
+
+use std::ffi::CStr;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::mem::size_of;
+use std::net::SocketAddrV4;
+use std::os::raw::{c_char, c_int, c_void};
+
+use anyhow::{anyhow, Context, Result};
+use libc::{sockaddr, sockaddr_in, socklen_t};
+use once_cell::sync::Lazy;
+
+/// Name of the shared library offering rsocket.
+const RDMACM_LIB: &[u8] = b"librdmacm.so.1\0";
+/// Max length of the queue of pending connections.
+const RDMA_LISTEN_BACKLOG: c_int = 1;
+
+type RsocketFn = unsafe extern "C" fn(c_int, c_int, c_int) -> c_int;
+type RbindFn = unsafe extern "C" fn(c_int, *const sockaddr, socklen_t) -> c_int;
+type RlistenFn = unsafe extern "C" fn(c_int, c_int) -> c_int;
+type RacceptFn = unsafe extern "C" fn(c_int, *mut sockaddr, *mut socklen_t) -> c_int;
+type RconnectFn = unsafe extern "C" fn(c_int, *const sockaddr, socklen_t) -> c_int;
+type RrecvFn = unsafe extern "C" fn(c_int, *mut c_void, usize, c_int) -> isize;
+type RsendFn = unsafe extern "C" fn(c_int, *const c_void, usize, c_int) -> isize;
+type RcloseFn = unsafe extern "C" fn(c_int) -> c_int;
+
+/// Functions of rsocket loaded from librdmacm.
+struct Rsocket {
+    socket: RsocketFn,
+    bind: RbindFn,
+    listen: RlistenFn,
+    accept: RacceptFn,
+    connect: RconnectFn,
+    recv: RrecvFn,
+    send: RsendFn,
+    close: RcloseFn,
+}
+
+static RSOCKET: Lazy<std::result::Result<Rsocket, String>> = Lazy::new(Rsocket::load);
+
+fn dl_error() -> String {
+    // SAFETY: dlerror returns null or a valid C string.
+    let err = unsafe { libc::dlerror() };
+    if err.is_null() {
+        return "unknown error".to_string();
+    }
+    // SAFETY: err is not null and points to a C string.
+    unsafe { CStr::from_ptr(err) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+macro_rules! load_symbol {
+    ($handle: expr, $name: expr, $ty: ty) => {{
+        // SAFETY: handle is valid and the symbol name ends with nul.
+        let sym = unsafe { libc::dlsym($handle, concat!($name, "\0").as_ptr() as *const c_char) };
+        if sym.is_null() {
+            return Err(format!("Failed to load {}: {}", $name, dl_error()));
+        }
+        // SAFETY: the symbol is the function with the same signature in librdmacm.
+        unsafe { std::mem::transmute::<*mut c_void, $ty>(sym) }
+    }};
+}
+
+impl Rsocket {
+    fn load() -> std::result::Result<Self, String> {
+        // SAFETY: the library name ends with nul, and the library is never unloaded.
+        let handle = unsafe { libc::dlopen(RDMACM_LIB.as_ptr() as *const c_char, libc::RTLD_NOW) };
+        if handle.is_null() {
+            return Err(format!("Failed to load librdmacm: {}", dl_error()));
+        }
+
+        Ok(Rsocket {
+            socket: load_symbol!(handle, "rsocket", RsocketFn),
+            bind: load_symbol!(handle, "rbind", RbindFn),
+            listen: load_symbol!(handle, "rlisten", RlistenFn),
+            accept: load_symbol!(handle, "raccept", RacceptFn),
+            connect: load_symbol!(handle, "rconnect", RconnectFn),
+            recv: load_symbol!(handle, "rrecv", RrecvFn),
+            send: load_symbol!(handle, "rsend", RsendFn),
+            close: load_symbol!(handle, "rclose", RcloseFn),
+        })
+    }
+}
+
+fn rsocket() -> Result<&'static Rsocket> {
+    RSOCKET.as_ref().map_err(|e| anyhow!(e.clone()))
+}
+
+/// Convert address as `192.168.0.1:4446` to `sockaddr_in`.
+fn to_sockaddr(addr: &str) -> Result<sockaddr_in> {
+    let addr = addr
+        .parse::<SocketAddrV4>()
+        .with_context(|| format!("Invalid rdma address {}", addr))?;
+    Ok(sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: addr.port().to_be(),
+        sin_addr: libc::in_addr {
+            s_addr: u32::from(*addr.ip()).to_be(),
+        },
+        sin_zero: [0; 8],
+    })
+}
+
+/// Create rsocket, and call `op` on it with the address.
+fn rsocket_with_addr(
+    addr: &str,
+    op: impl FnOnce(&Rsocket, c_int, *const sockaddr, socklen_t) -> c_int,
+) -> Result<c_int> {
+    let rs = rsocket()?;
+    let sockaddr = to_sockaddr(addr)?;
+    // SAFETY: the arguments are valid.
+    let fd = unsafe { (rs.socket)(libc::AF_INET, libc::SOCK_STREAM, 0) };
+    if fd < 0 {
+        return Err(Error::last_os_error()).with_context(|| "Failed to create rsocket");
+    }
+    let ret = op(
+        rs,
+        fd,
+        &sockaddr as *const sockaddr_in as *const sockaddr,
+        size_of::<sockaddr_in>() as socklen_t,
+    );
+    if ret < 0 {
+        let err = Error::last_os_error();
+        // SAFETY: fd is the rsocket created above.
+        unsafe { (rs.close)(fd) };
+        return Err(err).with_context(|| format!("Failed to set up rsocket with {}", addr));
+    }
+    Ok(fd)
+}
+
+/// Connected RDMA stream.
+pub struct RdmaStream {
+    rs: &'static Rsocket,
+    fd: c_int,
+}
+
+impl RdmaStream {
+    /// Connect to the RDMA listener.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Ip and port of the listener, as `192.168.0.1:4446`.
+    pub fn connect(addr: &str) -> Result<Self> {
+        let fd = rsocket_with_addr(addr, |rs, fd, sockaddr, len| {
+            // SAFETY: sockaddr is valid with the length.
+            unsafe { (rs.connect)(fd, sockaddr, len) }
+        })?;
+        Ok(RdmaStream { rs: rsocket()?, fd })
+    }
+}
+
+impl Read for RdmaStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            // SAFETY: buf is valid with the length.
+            let ret =
+                unsafe { (self.rs.recv)(self.fd, buf.as_mut_ptr() as *mut c_void, buf.len(), 0) };
+            if ret >= 0 {
+                return Ok(ret as usize);
+            }
+            let err = Error::last_os_error();
+            if err.kind() != ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+impl Write for RdmaStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        loop {
+            // SAFETY: buf is valid with the length.
+            let ret =
+                unsafe { (self.rs.send)(self.fd, buf.as_ptr() as *const c_void, buf.len(), 0) };
+            if ret >= 0 {
+                return Ok(ret as usize);
+            }
+            let err = Error::last_os_error();
+            if err.kind() != ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for RdmaStream {
+    fn drop(&mut self) {
+        // SAFETY: fd is the rsocket owned by the stream.
+        unsafe { (self.rs.close)(self.fd) };
+    }
+}
+
+/// RDMA listener waiting for the connection of source VM.
+pub struct RdmaListener {
+    rs: &'static Rsocket,
+    fd: c_int,
+}
+
+impl RdmaListener {
+    /// Listen on the address.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Ip and port to listen on, as `192.168.0.1:4446`.
+    pub fn bind(addr: &str) -> Result<Self> {
+        let fd = rsocket_with_addr(addr, |rs, fd, sockaddr, len| {
+            // SAFETY: sockaddr is valid with the length.
+            let ret = unsafe { (rs.bind)(fd, sockaddr, len) };
+            if ret < 0 {
+                return ret;
+            }
+            // SAFETY: fd is bound above.
+            unsafe { (rs.listen)(fd, RDMA_LISTEN_BACKLOG) }
+        })?;
+        Ok(RdmaListener { rs: rsocket()?, fd })
+    }
+
+    /// Wait for a connection.
+    pub fn accept(&self) -> Result<RdmaStream> {
+        // SAFETY: the peer address is not needed.
+        let fd = unsafe { (self.rs.accept)(self.fd, std::ptr::null_mut(), std::ptr::null_mut()) };
+        if fd < 0 {
+            return Err(Error::last_os_error()).with_context(|| "Failed to accept rsocket");
+        }
+        Ok(RdmaStream { rs: self.rs, fd })
+    }
+}
+
+impl Drop for RdmaListener {
+    fn drop(&mut self) {
+        // SAFETY: fd is the rsocket owned by the listener.
+        unsafe { (self.rs.close)(self.fd) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rdma_sockaddr() {
+        let addr = to_sockaddr("192.168.0.1:4446").unwrap();
+        assert_eq!(addr.sin_family, libc::AF_INET as libc::sa_family_t);
+        assert_eq!(u16::from_be(addr.sin_port), 4446);
+        assert_eq!(u32::from_be(addr.sin_addr.s_addr), 0xc0a8_0001);
+
+        assert!(to_sockaddr("192.168.0.1").is_err());
+        assert!(to_sockaddr("192.168.0.300:4446").is_err());
+        assert!(to_sockaddr("/tmp/stratovirt.sock").is_err());
+    }
+}
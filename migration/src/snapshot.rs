@@ -17,7 +17,7 @@ use crate::MigrationError;
 use anyhow::{anyhow, bail, Context, Result};
 use std::collections::HashMap;
 use std::fs::{create_dir, File};
-use std::io::{Read, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
 use util::unix::host_page_size;
 
@@ -32,6 +32,8 @@ pub const PL031_SNAPSHOT_ID: &str = "pl031";
 const MEMORY_PATH_SUFFIX: &str = "memory";
 /// The suffix used for snapshot device state storage.
 const DEVICE_PATH_SUFFIX: &str = "state";
+/// Length of the trailer of single file snapshot, which holds the offset of device state.
+const SNAPSHOT_TRAILER_LENGTH: u64 = 8;
 
 impl MigrationManager {
     /// Save snapshot for `VM`.
@@ -86,6 +88,37 @@ impl MigrationManager {
         Ok(())
     }
 
+    /// Save snapshot for `VM` to a single file.
+    ///
+    /// # Notes
+    ///
+    /// The file holds the same memory data and device state as the snapshot dir. The
+    /// memory data comes first so that it's page aligned in file, then the device state
+    /// follows, and the file ends with the offset of device state. The disks are not
+    /// saved, so the snapshot must be restored with the same disks.
+    ///
+    /// # Argument
+    ///
+    /// * `path` - snapshot file path. If file exists, it will be overwritten.
+    pub fn save_snapshot_file(path: &str) -> Result<()> {
+        // Set status to `Active`
+        MigrationManager::set_status(MigrationStatus::Active)?;
+
+        let mut file = File::create(path)
+            .with_context(|| format!("Failed to create snapshot file {}", path))?;
+        Self::save_memory(Some(FileFormat::MemoryFull), &mut file)?;
+        let state_offset = file.stream_position()?;
+        Self::save_vmstate(Some(FileFormat::Device), &mut file)?;
+        file.write_all(&state_offset.to_le_bytes())?;
+        file.sync_all()
+            .with_context(|| format!("Failed to sync snapshot file {}", path))?;
+
+        // Set status to `Completed`
+        MigrationManager::set_status(MigrationStatus::Completed)?;
+
+        Ok(())
+    }
+
     /// Restore snapshot for `VM`.
     ///
     /// # Notes
@@ -100,41 +133,115 @@ impl MigrationManager {
         // Set status to `Active`
         MigrationManager::set_status(MigrationStatus::Active)?;
 
+        let (mut memory_file, mut device_state_file, desc_len) = Self::open_snapshot(path)?;
+        Self::restore_memory(&mut memory_file).with_context(|| "Failed to load snapshot memory")?;
+        let snapshot_desc_db = Self::restore_desc_db(&mut device_state_file, desc_len)
+            .with_context(|| "Failed to load device descriptor db")?;
+        Self::restore_vmstate(snapshot_desc_db, &mut device_state_file)
+            .with_context(|| "Failed to load snapshot device state")?;
+        Self::resume()?;
+
+        // Set status to `Completed`
+        MigrationManager::set_status(MigrationStatus::Completed)?;
+
+        Ok(())
+    }
+
+    /// Load snapshot into the created `VM`.
+    ///
+    /// # Notes
+    ///
+    /// Different from `restore_snapshot`, the memory of VM has been allocated, so the
+    /// memory data in snapshot file is copied into it. The VM must not have run yet.
+    ///
+    /// # Argument
+    ///
+    /// * `path` - snapshot dir path.
+    pub fn load_snapshot(path: &str) -> Result<()> {
+        // Set status to `Active`
+        MigrationManager::set_status(MigrationStatus::Active)?;
+
+        let (mut memory_file, mut device_state_file, desc_len) = Self::open_snapshot(path)?;
+        Self::load_memory(&mut memory_file).with_context(|| "Failed to load snapshot memory")?;
+        let snapshot_desc_db = Self::restore_desc_db(&mut device_state_file, desc_len)
+            .with_context(|| "Failed to load device descriptor db")?;
+        Self::restore_vmstate(snapshot_desc_db, &mut device_state_file)
+            .with_context(|| "Failed to load snapshot device state")?;
+        Self::resume()?;
+
+        // Set status to `Completed`
+        MigrationManager::set_status(MigrationStatus::Completed)?;
+
+        Ok(())
+    }
+
+    /// Open and check the memory file and device state file in snapshot dir, or the
+    /// memory data and device state in single snapshot file, return them with the
+    /// length of device descriptor db.
+    ///
+    /// # Argument
+    ///
+    /// * `path` - snapshot dir or file path.
+    fn open_snapshot(path: &str) -> Result<(File, File, usize)> {
         let mut snapshot_path = PathBuf::from(path);
-        if !snapshot_path.is_dir() {
+        let (mut memory_file, mut device_state_file) = if snapshot_path.is_file() {
+            Self::open_snapshot_file(path)?
+        } else if snapshot_path.is_dir() {
+            snapshot_path.push(MEMORY_PATH_SUFFIX);
+            let memory_file = File::open(&snapshot_path)
+                .with_context(|| "Failed to open memory snapshot file")?;
+            snapshot_path.pop();
+            snapshot_path.push(DEVICE_PATH_SUFFIX);
+            let device_state_file = File::open(&snapshot_path)
+                .with_context(|| "Failed to open device state snapshot file")?;
+            (memory_file, device_state_file)
+        } else {
             return Err(anyhow!(MigrationError::InvalidSnapshotPath));
-        }
+        };
 
-        snapshot_path.push(MEMORY_PATH_SUFFIX);
-        let mut memory_file =
-            File::open(&snapshot_path).with_context(|| "Failed to open memory snapshot file")?;
         let memory_header = Self::restore_header(&mut memory_file)?;
         memory_header.check_header()?;
         if memory_header.format != FileFormat::MemoryFull {
             bail!("Invalid memory snapshot file");
         }
-        snapshot_path.pop();
-        snapshot_path.push(DEVICE_PATH_SUFFIX);
-        let mut device_state_file = File::open(&snapshot_path)
-            .with_context(|| "Failed to open device state snapshot file")?;
         let device_state_header = Self::restore_header(&mut device_state_file)?;
         device_state_header.check_header()?;
         if device_state_header.format != FileFormat::Device {
             bail!("Invalid device state snapshot file");
         }
 
-        Self::restore_memory(&mut memory_file).with_context(|| "Failed to load snapshot memory")?;
-        let snapshot_desc_db =
-            Self::restore_desc_db(&mut device_state_file, device_state_header.desc_len)
-                .with_context(|| "Failed to load device descriptor db")?;
-        Self::restore_vmstate(snapshot_desc_db, &mut device_state_file)
-            .with_context(|| "Failed to load snapshot device state")?;
-        Self::resume()?;
+        Ok((memory_file, device_state_file, device_state_header.desc_len))
+    }
 
-        // Set status to `Completed`
-        MigrationManager::set_status(MigrationStatus::Completed)?;
+    /// Open single snapshot file twice, return them positioned at the memory data
+    /// and the device state respectively.
+    ///
+    /// # Argument
+    ///
+    /// * `path` - snapshot file path.
+    fn open_snapshot_file(path: &str) -> Result<(File, File)> {
+        let memory_file =
+            File::open(path).with_context(|| format!("Failed to open snapshot file {}", path))?;
+        let mut device_state_file =
+            File::open(path).with_context(|| format!("Failed to open snapshot file {}", path))?;
+
+        let len = device_state_file.seek(SeekFrom::End(0))?;
+        if len < SNAPSHOT_TRAILER_LENGTH {
+            return Err(anyhow!(MigrationError::InvalidSnapshotPath));
+        }
+        let mut trailer = [0_u8; SNAPSHOT_TRAILER_LENGTH as usize];
+        device_state_file.seek(SeekFrom::Start(len - SNAPSHOT_TRAILER_LENGTH))?;
+        device_state_file.read_exact(&mut trailer)?;
+        let state_offset = u64::from_le_bytes(trailer);
+        if state_offset >= len - SNAPSHOT_TRAILER_LENGTH {
+            bail!(
+                "Invalid device state offset {} in snapshot file",
+                state_offset
+            );
+        }
+        device_state_file.seek(SeekFrom::Start(state_offset))?;
 
-        Ok(())
+        Ok((memory_file, device_state_file))
     }
 
     /// Save memory state and data to `Write` trait object.
@@ -169,6 +276,24 @@ impl MigrationManager {
         Ok(())
     }
 
+    /// Load memory data from snapshot memory file into the existing memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - snapshot memory file.
+    fn load_memory(file: &mut File) -> Result<()> {
+        let mut state_bytes = [0_u8].repeat((host_page_size() as usize) * 2 - HEADER_LENGTH);
+        file.read_exact(&mut state_bytes)?;
+        let locked_vmm = MIGRATION_MANAGER.vmm.read().unwrap();
+        locked_vmm
+            .memory
+            .as_ref()
+            .unwrap()
+            .load_memory(file, &state_bytes)?;
+
+        Ok(())
+    }
+
     /// Save vm state to `Write` trait object as bytes..
     ///
     /// # Arguments
@@ -15,9 +15,18 @@ use std::sync::{Arc, Mutex, Weak};
 
 use address_space::Region;
 use log::debug;
+use machine_manager::qmp::qmp_schema::{
+    PciBridgeInfo, PciBusInfo, PciDeviceClass, PciDeviceId, PciDeviceInfo, PciMemoryRange,
+};
 
 use super::{
-    config::{BRIDGE_CONTROL, BRIDGE_CTL_SEC_BUS_RESET, SECONDARY_BUS_NUM, SUBORDINATE_BUS_NUM},
+    config::{
+        BRIDGE_CONTROL, BRIDGE_CTL_SEC_BUS_RESET, DEVICE_ID, HEADER_TYPE, HEADER_TYPE_BRIDGE,
+        HEADER_TYPE_MULTIFUNC, INTERRUPT_LINE, INTERRUPT_PIN, IO_BASE, IO_LIMIT, MEMORY_BASE,
+        MEMORY_LIMIT, PREF_MEMORY_BASE, PREF_MEMORY_LIMIT, PREF_MEM_BASE_UPPER,
+        PREF_MEM_LIMIT_UPPER, PREF_MEM_RANGE_64BIT, PRIMARY_BUS_NUM, SECONDARY_BUS_NUM,
+        SUBORDINATE_BUS_NUM, SUBSYSTEM_ID, SUBSYSTEM_VENDOR_ID, SUB_CLASS_CODE, VENDOR_ID,
+    },
     hotplug::HotplugOps,
     PciDevOps, PciIntxState,
 };
@@ -202,6 +211,35 @@ impl PciBus {
         Ok(())
     }
 
+    /// Query the information of devices attached to the bus and buses behind bridges.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - Bus to query.
+    /// * `bus_num` - The bus number.
+    pub fn query_devices(bus: &Arc<Mutex<Self>>, bus_num: u8) -> Vec<PciDeviceInfo> {
+        let locked_bus = bus.lock().unwrap();
+        let mut devfns: Vec<u8> = locked_bus.devices.keys().copied().collect();
+        devfns.sort_unstable();
+
+        let mut vec_info = Vec::new();
+        for devfn in devfns {
+            let dev = locked_bus.devices.get(&devfn).unwrap();
+            let mut info = query_device_info(dev, bus_num, devfn);
+            if let Some(bridge) = info.pci_bridge.as_mut() {
+                let child_bus = locked_bus
+                    .child_buses
+                    .iter()
+                    .find(|b| b.lock().unwrap().name == info.qdev_id);
+                if let Some(child_bus) = child_bus {
+                    bridge.devices = Some(PciBus::query_devices(child_bus, bridge.bus.secondary));
+                }
+            }
+            vec_info.push(info);
+        }
+        vec_info
+    }
+
     pub fn reset(&mut self) -> Result<()> {
         for (_id, pci_dev) in self.devices.iter() {
             pci_dev
@@ -247,6 +285,128 @@ impl PciBus {
     }
 }
 
+fn read_config_u8(dev: &mut dyn PciDevOps, offset: u8) -> u8 {
+    let mut data = [0_u8; 1];
+    dev.read_config(offset as usize, &mut data);
+    data[0]
+}
+
+fn read_config_u16(dev: &mut dyn PciDevOps, offset: u8) -> u16 {
+    let mut data = [0_u8; 2];
+    dev.read_config(offset as usize, &mut data);
+    u16::from_le_bytes(data)
+}
+
+fn read_config_u32(dev: &mut dyn PciDevOps, offset: u8) -> u32 {
+    let mut data = [0_u8; 4];
+    dev.read_config(offset as usize, &mut data);
+    u32::from_le_bytes(data)
+}
+
+/// Get the description of the class code, `None` if it's unknown.
+fn pci_class_desc(class: u16) -> Option<&'static str> {
+    let desc = match class {
+        0x0100 => "SCSI controller",
+        0x0106 => "SATA controller",
+        0x0180 => "Storage controller",
+        0x0200 => "Ethernet controller",
+        0x0300 => "VGA controller",
+        0x0500 => "RAM controller",
+        0x0600 => "Host bridge",
+        0x0601 => "ISA bridge",
+        0x0604 => "PCI bridge",
+        0x0780 => "Communication controller",
+        0x0880 => "System peripheral",
+        0x0c03 => "USB controller",
+        0x00ff => "Unclassified device",
+        _ => return None,
+    };
+    Some(desc)
+}
+
+/// Decode the address windows of a bridge from the type 1 configuration header.
+fn query_bridge_info(dev: &mut dyn PciDevOps) -> PciBusInfo {
+    let io_base = u64::from(read_config_u8(dev, IO_BASE) & 0xf0) << 8;
+    let io_limit = u64::from(read_config_u8(dev, IO_LIMIT) & 0xf0) << 8 | 0xfff;
+    let mem_base = u64::from(read_config_u16(dev, MEMORY_BASE) & 0xfff0) << 16;
+    let mem_limit = u64::from(read_config_u16(dev, MEMORY_LIMIT) & 0xfff0) << 16 | 0xf_ffff;
+
+    let pref_base_reg = read_config_u16(dev, PREF_MEMORY_BASE);
+    let pref_limit_reg = read_config_u16(dev, PREF_MEMORY_LIMIT);
+    let mut pref_base = u64::from(pref_base_reg & 0xfff0) << 16;
+    let mut pref_limit = u64::from(pref_limit_reg & 0xfff0) << 16 | 0xf_ffff;
+    if pref_base_reg & u16::from(PREF_MEM_RANGE_64BIT) != 0 {
+        pref_base |= u64::from(read_config_u32(dev, PREF_MEM_BASE_UPPER)) << 32;
+        pref_limit |= u64::from(read_config_u32(dev, PREF_MEM_LIMIT_UPPER)) << 32;
+    }
+
+    PciBusInfo {
+        number: read_config_u8(dev, PRIMARY_BUS_NUM),
+        secondary: read_config_u8(dev, SECONDARY_BUS_NUM),
+        subordinate: read_config_u8(dev, SUBORDINATE_BUS_NUM),
+        io_range: PciMemoryRange {
+            base: io_base,
+            limit: io_limit,
+        },
+        memory_range: PciMemoryRange {
+            base: mem_base,
+            limit: mem_limit,
+        },
+        prefetchable_range: PciMemoryRange {
+            base: pref_base,
+            limit: pref_limit,
+        },
+    }
+}
+
+/// Collect the information of a device from its configuration space, devices behind
+/// the bridge are not filled in here.
+fn query_device_info(dev: &Arc<Mutex<dyn PciDevOps>>, bus_num: u8, devfn: u8) -> PciDeviceInfo {
+    let mut locked_dev = dev.lock().unwrap();
+    let class = read_config_u16(&mut *locked_dev, SUB_CLASS_CODE);
+    let header_type = read_config_u8(&mut *locked_dev, HEADER_TYPE) & !HEADER_TYPE_MULTIFUNC;
+    let irq_pin = read_config_u8(&mut *locked_dev, INTERRUPT_PIN);
+
+    let mut id = PciDeviceId {
+        vendor: read_config_u16(&mut *locked_dev, VENDOR_ID),
+        device: read_config_u16(&mut *locked_dev, DEVICE_ID),
+        ..Default::default()
+    };
+    let mut pci_bridge = None;
+    if header_type == HEADER_TYPE_BRIDGE {
+        pci_bridge = Some(PciBridgeInfo {
+            bus: query_bridge_info(&mut *locked_dev),
+            devices: None,
+        });
+    } else {
+        id.subsystem = Some(read_config_u16(&mut *locked_dev, SUBSYSTEM_ID as u8));
+        id.subsystem_vendor = Some(read_config_u16(&mut *locked_dev, SUBSYSTEM_VENDOR_ID as u8));
+    }
+
+    PciDeviceInfo {
+        bus: bus_num,
+        slot: devfn >> 3,
+        function: devfn & 0x07,
+        class_info: PciDeviceClass {
+            desc: pci_class_desc(class).map(String::from),
+            class,
+        },
+        id,
+        irq: if irq_pin != 0 {
+            Some(read_config_u8(&mut *locked_dev, INTERRUPT_LINE))
+        } else {
+            None
+        },
+        irq_pin,
+        qdev_id: locked_dev.name(),
+        pci_bridge,
+        regions: locked_dev
+            .get_pci_config()
+            .map(|config| config.get_bar_regions())
+            .unwrap_or_default(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use address_space::{AddressSpace, Region};
@@ -418,4 +578,51 @@ mod tests {
         let info = PciBus::find_attached_bus(&locked_pci_host.root_bus, "test1");
         assert!(info.is_none());
     }
+
+    #[test]
+    fn test_query_devices() {
+        let pci_host = create_pci_host();
+        let locked_pci_host = pci_host.lock().unwrap();
+        let root_bus = Arc::downgrade(&locked_pci_host.root_bus);
+
+        let root_port = RootPort::new("pcie.1".to_string(), 8, 0, root_bus.clone(), false);
+        root_port.realize().unwrap();
+
+        let pci_dev = PciDevice {
+            name: String::from("test1"),
+            devfn: 10,
+            config: PciConfig::new(PCI_CONFIG_SPACE_SIZE, 0),
+            parent_bus: root_bus,
+        };
+        pci_dev.realize().unwrap();
+
+        let bus = PciBus::find_bus_by_name(&locked_pci_host.root_bus, "pcie.1").unwrap();
+        let pci_dev = PciDevice {
+            name: String::from("test2"),
+            devfn: 0,
+            config: PciConfig::new(PCI_CONFIG_SPACE_SIZE, 0),
+            parent_bus: Arc::downgrade(&bus),
+        };
+        pci_dev.realize().unwrap();
+
+        let devices = PciBus::query_devices(&locked_pci_host.root_bus, 0);
+        assert_eq!(devices.len(), 2);
+
+        // Devices are sorted by devfn, the root port is the first one.
+        assert_eq!(devices[0].qdev_id, "pcie.1");
+        assert_eq!((devices[0].slot, devices[0].function), (1, 0));
+        assert_eq!(devices[0].class_info.class, 0x0604);
+        assert_eq!(devices[0].class_info.desc.as_deref(), Some("PCI bridge"));
+        assert!(devices[0].id.subsystem.is_none());
+        let bridge = devices[0].pci_bridge.as_ref().unwrap();
+        let behind = bridge.devices.as_ref().unwrap();
+        assert_eq!(behind.len(), 1);
+        assert_eq!(behind[0].qdev_id, "test2");
+
+        assert_eq!(devices[1].qdev_id, "test1");
+        assert_eq!((devices[1].slot, devices[1].function), (1, 2));
+        assert!(devices[1].pci_bridge.is_none());
+        assert!(devices[1].irq.is_none());
+        assert_eq!(devices[1].id.subsystem, Some(0));
+    }
 }
@@ -15,6 +15,7 @@ use std::sync::{Arc, Mutex};
 
 use address_space::Region;
 use log::{error, warn};
+use machine_manager::qmp::qmp_schema::PciMemoryRegion;
 
 use crate::intx::Intx;
 use crate::msix::{is_msix_enabled, Msix};
@@ -59,6 +60,8 @@ pub const SUBORDINATE_BUS_NUM: u8 = 0x1a;
 pub const IO_BASE: u8 = 0x1c;
 /// Memory base register.
 pub const MEMORY_BASE: u8 = 0x20;
+/// Memory limit register.
+pub const MEMORY_LIMIT: u8 = 0x22;
 /// Prefetchable memory base register.
 pub const PREF_MEMORY_BASE: u8 = 0x24;
 /// Prefetchable memory limit register.
@@ -105,8 +108,9 @@ const CACHE_LINE_SIZE: u8 = 0x0c;
 pub const PRIMARY_BUS_NUM: u8 = 0x18;
 pub const IO_LIMIT: u8 = 0x1d;
 pub const PREF_MEM_BASE_UPPER: u8 = 0x28;
+pub const PREF_MEM_LIMIT_UPPER: u8 = 0x2c;
 const CAP_LIST: u8 = 0x34;
-const INTERRUPT_LINE: u8 = 0x3c;
+pub const INTERRUPT_LINE: u8 = 0x3c;
 pub const INTERRUPT_PIN: u8 = 0x3d;
 pub const BRIDGE_CONTROL: u8 = 0x3e;
 
@@ -741,6 +745,32 @@ impl PciConfig {
         }
     }
 
+    /// Get the information of registered BARs, which is reported by `query-pci`.
+    pub fn get_bar_regions(&self) -> Vec<PciMemoryRegion> {
+        let mut regions = Vec::new();
+        for (id, bar) in self.bars.iter().enumerate() {
+            if bar.size == 0 {
+                continue;
+            }
+            let offset: usize = BAR_0 as usize + id * REG_SIZE;
+            let mut region = PciMemoryRegion {
+                bar: id as u8,
+                region_type: "io".to_string(),
+                address: self.get_bar_address(id),
+                size: bar.size,
+                prefetch: None,
+                mem_type_64: None,
+            };
+            if bar.region_type != RegionType::Io {
+                region.region_type = "memory".to_string();
+                region.prefetch = Some(self.config[offset] & BAR_PREFETCH != 0);
+                region.mem_type_64 = Some(bar.region_type == RegionType::Mem64Bit);
+            }
+            regions.push(region);
+        }
+        regions
+    }
+
     /// Register a bar in PciConfig::bars.
     ///
     /// # Arguments
@@ -1281,6 +1311,23 @@ mod tests {
             (MEM_BASE_ADDR_MASK as u32) as u64
         );
         assert_eq!(pci_config.get_bar_address(2), MEM_BASE_ADDR_MASK);
+
+        let regions = pci_config.get_bar_regions();
+        let region = regions.iter().find(|r| r.bar == 2).unwrap();
+        assert_eq!(region.region_type, "memory");
+        assert_eq!(region.address, MEM_BASE_ADDR_MASK);
+        assert_eq!(region.size, 8192);
+        assert_eq!(region.prefetch, Some(true));
+        assert_eq!(region.mem_type_64, Some(true));
+        let region = regions.iter().find(|r| r.bar == 1).unwrap();
+        assert_eq!(region.prefetch, Some(false));
+        assert_eq!(region.mem_type_64, Some(false));
+        #[cfg(target_arch = "x86_64")]
+        {
+            let region = regions.iter().find(|r| r.bar == 0).unwrap();
+            assert_eq!(region.region_type, "io");
+            assert_eq!(region.prefetch, None);
+        }
     }
 
     #[test]
@@ -230,6 +230,10 @@ impl PciDevOps for DemoDev {
         self.name.clone()
     }
 
+    fn get_pci_config(&self) -> Option<&PciConfig> {
+        Some(&self.config)
+    }
+
     /// Reset device
     fn reset(&mut self, _reset_child_device: bool) -> Result<()> {
         self.config.reset_common_regs()
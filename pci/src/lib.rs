@@ -235,6 +235,11 @@ pub trait PciDevOps: Send + AsAny {
     fn get_intx_state(&self) -> Option<Arc<Mutex<PciIntxState>>> {
         None
     }
+
+    /// Get the configuration space of device, which is used to report the BARs.
+    fn get_pci_config(&self) -> Option<&PciConfig> {
+        None
+    }
 }
 
 /// Init multifunction for pci devices.
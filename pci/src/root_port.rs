@@ -480,6 +480,10 @@ impl PciDevOps for RootPort {
         self.name.clone()
     }
 
+    fn get_pci_config(&self) -> Option<&PciConfig> {
+        Some(&self.config)
+    }
+
     fn devfn(&self) -> Option<u8> {
         Some(self.devfn)
     }
@@ -10,14 +10,28 @@
 // NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
 // See the Mulan PSL v2 for more details.
 
-use machine_manager::config::{SmbiosConfig, SmbiosType0Config, SmbiosType1Config};
+use machine_manager::config::{
+    MachineConfig, SmbiosConfig, SmbiosType0Config, SmbiosType17Config, SmbiosType1Config,
+    SmbiosType2Config, SmbiosType3Config, SmbiosType4Config,
+};
 use std::mem::size_of;
 use util::byte_code::ByteCode;
 
 const TYPE0_HANDLE: u16 = 0x0;
 const TYPE1_HANDLE: u16 = 0x100;
+const TYPE2_HANDLE: u16 = 0x200;
+const TYPE3_HANDLE: u16 = 0x300;
+const TYPE4_HANDLE: u16 = 0x400;
+const TYPE16_HANDLE: u16 = 0x1000;
+const TYPE17_HANDLE: u16 = 0x1100;
 const TYPE127_HANDLE: u16 = 0x7F00;
 
+const DEFAULT_MANUFACTURER: &str = "Stratovirt";
+/// Max size of memory described by one type17 memory device.
+const MAX_DIMM_SIZE: u64 = 16 * 1024 * 1024 * 1024;
+const KB: u64 = 1024;
+const MB: u64 = 1024 * 1024;
+
 #[repr(C, packed)]
 #[derive(Default, Copy, Clone)]
 struct SmbiosHeader {
@@ -159,6 +173,375 @@ impl SmbiosType1Table {
     }
 }
 
+/// Type2: Baseboard information
+#[repr(C, packed)]
+#[derive(Default, Copy, Clone)]
+struct SmbiosType2 {
+    header: SmbiosHeader,
+    manufacturer: u8,
+    product_name: u8,
+    version: u8,
+    serial_num: u8,
+    asset_tag_num: u8,
+    feature_flags: u8,
+    location: u8,
+    chassis_handle: [u8; 2],
+    board_type: u8,
+    contained_element_count: u8,
+}
+
+impl ByteCode for SmbiosType2 {}
+
+impl SmbiosType2 {
+    pub fn new() -> SmbiosType2 {
+        SmbiosType2 {
+            header: SmbiosHeader::new(2_u8, size_of::<SmbiosType2>() as u8, TYPE2_HANDLE),
+            // Board is a hosting board.
+            feature_flags: 0x1_u8,
+            chassis_handle: TYPE3_HANDLE.to_le_bytes(),
+            // Motherboard.
+            board_type: 0x0A_u8,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+struct SmbiosType2Table {
+    header: SmbiosType2,
+    body: Vec<u8>,
+    str_index: u8,
+}
+
+impl SmbiosType2Table {
+    pub fn new() -> SmbiosType2Table {
+        SmbiosType2Table {
+            header: SmbiosType2::new(),
+            body: Vec::new(),
+            str_index: 0_u8,
+        }
+    }
+
+    pub fn set_str(&mut self, str: String) {
+        self.str_index += 1;
+        self.body.append(&mut str.as_bytes().to_vec());
+        self.body.append(&mut vec![0]);
+    }
+
+    pub fn finish(&mut self) {
+        if self.str_index == 0 {
+            self.body.append(&mut vec![0; 2]);
+        } else {
+            self.body.append(&mut vec![0]);
+        }
+    }
+}
+
+/// Type3: System enclosure or chassis
+#[repr(C, packed)]
+#[derive(Default, Copy, Clone)]
+struct SmbiosType3 {
+    header: SmbiosHeader,
+    manufacturer: u8,
+    type_id: u8,
+    version: u8,
+    serial_num: u8,
+    asset_tag_num: u8,
+    boot_up_state: u8,
+    power_supply_state: u8,
+    thermal_state: u8,
+    security_status: u8,
+    oem_defined: [u8; 4],
+    height: u8,
+    number_of_power_cords: u8,
+    contained_element_count: u8,
+    contained_element_record_length: u8,
+    sku_num: u8,
+}
+
+impl ByteCode for SmbiosType3 {}
+
+impl SmbiosType3 {
+    pub fn new() -> SmbiosType3 {
+        SmbiosType3 {
+            header: SmbiosHeader::new(3_u8, size_of::<SmbiosType3>() as u8, TYPE3_HANDLE),
+            // Other.
+            type_id: 0x1_u8,
+            // Safe.
+            boot_up_state: 0x3_u8,
+            power_supply_state: 0x3_u8,
+            thermal_state: 0x3_u8,
+            // Unknown.
+            security_status: 0x2_u8,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+struct SmbiosType3Table {
+    header: SmbiosType3,
+    body: Vec<u8>,
+    str_index: u8,
+}
+
+impl SmbiosType3Table {
+    pub fn new() -> SmbiosType3Table {
+        SmbiosType3Table {
+            header: SmbiosType3::new(),
+            body: Vec::new(),
+            str_index: 0_u8,
+        }
+    }
+
+    pub fn set_str(&mut self, str: String) {
+        self.str_index += 1;
+        self.body.append(&mut str.as_bytes().to_vec());
+        self.body.append(&mut vec![0]);
+    }
+
+    pub fn finish(&mut self) {
+        if self.str_index == 0 {
+            self.body.append(&mut vec![0; 2]);
+        } else {
+            self.body.append(&mut vec![0]);
+        }
+    }
+}
+
+/// Type4: Processor information
+#[repr(C, packed)]
+#[derive(Default, Copy, Clone)]
+struct SmbiosType4 {
+    header: SmbiosHeader,
+    socket_design: u8,
+    processor_type: u8,
+    processor_family: u8,
+    processor_manufacturer: u8,
+    processor_id: [u8; 8],
+    processor_version: u8,
+    voltage: u8,
+    external_clock: [u8; 2],
+    max_speed: [u8; 2],
+    current_speed: [u8; 2],
+    status: u8,
+    processor_upgrade: u8,
+    l1_cache_handle: [u8; 2],
+    l2_cache_handle: [u8; 2],
+    l3_cache_handle: [u8; 2],
+    serial_num: u8,
+    asset_tag_num: u8,
+    part_num: u8,
+    core_count: u8,
+    core_enabled: u8,
+    thread_count: u8,
+    processor_characteristics: [u8; 2],
+    processor_family2: [u8; 2],
+    core_count2: [u8; 2],
+    core_enabled2: [u8; 2],
+    thread_count2: [u8; 2],
+}
+
+impl ByteCode for SmbiosType4 {}
+
+impl SmbiosType4 {
+    pub fn new(instance: u16) -> SmbiosType4 {
+        SmbiosType4 {
+            header: SmbiosHeader::new(
+                4_u8,
+                size_of::<SmbiosType4>() as u8,
+                TYPE4_HANDLE + instance,
+            ),
+            // Central processor.
+            processor_type: 0x3_u8,
+            // Other.
+            processor_family: 0x1_u8,
+            // Socket populated, CPU enabled.
+            status: 0x41_u8,
+            processor_upgrade: 0x1_u8,
+            // No cache information is provided.
+            l1_cache_handle: 0xFFFF_u16.to_le_bytes(),
+            l2_cache_handle: 0xFFFF_u16.to_le_bytes(),
+            l3_cache_handle: 0xFFFF_u16.to_le_bytes(),
+            // Unknown.
+            processor_characteristics: 0x2_u16.to_le_bytes(),
+            processor_family2: 0x1_u16.to_le_bytes(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+struct SmbiosType4Table {
+    header: SmbiosType4,
+    body: Vec<u8>,
+    str_index: u8,
+}
+
+impl SmbiosType4Table {
+    pub fn new(instance: u16) -> SmbiosType4Table {
+        SmbiosType4Table {
+            header: SmbiosType4::new(instance),
+            body: Vec::new(),
+            str_index: 0_u8,
+        }
+    }
+
+    pub fn set_str(&mut self, str: String) {
+        self.str_index += 1;
+        self.body.append(&mut str.as_bytes().to_vec());
+        self.body.append(&mut vec![0]);
+    }
+
+    pub fn finish(&mut self) {
+        if self.str_index == 0 {
+            self.body.append(&mut vec![0; 2]);
+        } else {
+            self.body.append(&mut vec![0]);
+        }
+    }
+}
+
+/// Type16: Physical memory array
+#[repr(C, packed)]
+#[derive(Default, Copy, Clone)]
+struct SmbiosType16 {
+    header: SmbiosHeader,
+    location: u8,
+    use_info: u8,
+    error_correction: u8,
+    maximum_capacity: [u8; 4],
+    memory_error_info_handle: [u8; 2],
+    number_of_memory_devices: [u8; 2],
+    extended_maximum_capacity: [u8; 8],
+}
+
+impl ByteCode for SmbiosType16 {}
+
+impl SmbiosType16 {
+    pub fn new(cnt: u16) -> SmbiosType16 {
+        SmbiosType16 {
+            header: SmbiosHeader::new(16_u8, size_of::<SmbiosType16>() as u8, TYPE16_HANDLE),
+            // System board or motherboard.
+            location: 0x3_u8,
+            // System memory.
+            use_info: 0x3_u8,
+            // Multi-bit ECC.
+            error_correction: 0x6_u8,
+            // Not provided.
+            memory_error_info_handle: 0xFFFE_u16.to_le_bytes(),
+            number_of_memory_devices: cnt.to_le_bytes(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+struct SmbiosType16Table {
+    header: SmbiosType16,
+    body: Vec<u8>,
+}
+
+impl SmbiosType16Table {
+    pub fn new(cnt: u16) -> SmbiosType16Table {
+        SmbiosType16Table {
+            header: SmbiosType16::new(cnt),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.body.append(&mut vec![0; 2]);
+    }
+}
+
+/// Type17: Memory device
+#[repr(C, packed)]
+#[derive(Default, Copy, Clone)]
+struct SmbiosType17 {
+    header: SmbiosHeader,
+    physical_memory_array_handle: [u8; 2],
+    memory_error_info_handle: [u8; 2],
+    total_width: [u8; 2],
+    data_width: [u8; 2],
+    size: [u8; 2],
+    form_factor: u8,
+    device_set: u8,
+    device_locator_str: u8,
+    bank_locator_str: u8,
+    memory_type: u8,
+    type_detail: [u8; 2],
+    speed: [u8; 2],
+    manufacturer_str: u8,
+    serial_number_str: u8,
+    asset_tag_number_str: u8,
+    part_number_str: u8,
+    attributes: u8,
+    extended_size: [u8; 4],
+    configured_clock_speed: [u8; 2],
+    minimum_voltage: [u8; 2],
+    maximum_voltage: [u8; 2],
+    configured_voltage: [u8; 2],
+}
+
+impl ByteCode for SmbiosType17 {}
+
+impl SmbiosType17 {
+    pub fn new(instance: u16) -> SmbiosType17 {
+        SmbiosType17 {
+            header: SmbiosHeader::new(
+                17_u8,
+                size_of::<SmbiosType17>() as u8,
+                TYPE17_HANDLE + instance,
+            ),
+            physical_memory_array_handle: TYPE16_HANDLE.to_le_bytes(),
+            // Not provided.
+            memory_error_info_handle: 0xFFFE_u16.to_le_bytes(),
+            // Unknown.
+            total_width: 0xFFFF_u16.to_le_bytes(),
+            data_width: 0xFFFF_u16.to_le_bytes(),
+            // DIMM.
+            form_factor: 0x9_u8,
+            // RAM.
+            memory_type: 0x7_u8,
+            // Other.
+            type_detail: 0x2_u16.to_le_bytes(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+struct SmbiosType17Table {
+    header: SmbiosType17,
+    body: Vec<u8>,
+    str_index: u8,
+}
+
+impl SmbiosType17Table {
+    pub fn new(instance: u16) -> SmbiosType17Table {
+        SmbiosType17Table {
+            header: SmbiosType17::new(instance),
+            body: Vec::new(),
+            str_index: 0_u8,
+        }
+    }
+
+    pub fn set_str(&mut self, str: String) {
+        self.str_index += 1;
+        self.body.append(&mut str.as_bytes().to_vec());
+        self.body.append(&mut vec![0]);
+    }
+
+    pub fn finish(&mut self) {
+        if self.str_index == 0 {
+            self.body.append(&mut vec![0; 2]);
+        } else {
+            self.body.append(&mut vec![0]);
+        }
+    }
+}
+
 /// Type127: End of table
 #[repr(C, packed)]
 #[derive(Default, Copy, Clone)]
@@ -278,6 +661,204 @@ impl SmbiosTable {
         self.entries.append(&mut table1.body);
     }
 
+    fn build_type2(&mut self, type2: SmbiosType2Config) {
+        let mut table2 = SmbiosType2Table::new();
+
+        table2.header.manufacturer = table2.str_index + 1;
+        table2.set_str(
+            type2
+                .manufacturer
+                .unwrap_or_else(|| String::from(DEFAULT_MANUFACTURER)),
+        );
+
+        table2.header.product_name = table2.str_index + 1;
+        table2.set_str(
+            type2
+                .product
+                .unwrap_or_else(|| String::from("Virtual Machine")),
+        );
+
+        if let Some(version) = type2.version {
+            table2.header.version = table2.str_index + 1;
+            table2.set_str(version);
+        }
+
+        if let Some(serial) = type2.serial {
+            table2.header.serial_num = table2.str_index + 1;
+            table2.set_str(serial);
+        }
+
+        if let Some(asset) = type2.asset {
+            table2.header.asset_tag_num = table2.str_index + 1;
+            table2.set_str(asset);
+        }
+
+        if let Some(location) = type2.location {
+            table2.header.location = table2.str_index + 1;
+            table2.set_str(location);
+        }
+        table2.finish();
+
+        self.entries.append(&mut table2.header.as_bytes().to_vec());
+        self.entries.append(&mut table2.body);
+    }
+
+    fn build_type3(&mut self, type3: SmbiosType3Config) {
+        let mut table3 = SmbiosType3Table::new();
+
+        table3.header.manufacturer = table3.str_index + 1;
+        table3.set_str(
+            type3
+                .manufacturer
+                .unwrap_or_else(|| String::from(DEFAULT_MANUFACTURER)),
+        );
+
+        if let Some(version) = type3.version {
+            table3.header.version = table3.str_index + 1;
+            table3.set_str(version);
+        }
+
+        if let Some(serial) = type3.serial {
+            table3.header.serial_num = table3.str_index + 1;
+            table3.set_str(serial);
+        }
+
+        if let Some(asset) = type3.asset {
+            table3.header.asset_tag_num = table3.str_index + 1;
+            table3.set_str(asset);
+        }
+
+        if let Some(sku) = type3.sku {
+            table3.header.sku_num = table3.str_index + 1;
+            table3.set_str(sku);
+        }
+        table3.finish();
+
+        self.entries.append(&mut table3.header.as_bytes().to_vec());
+        self.entries.append(&mut table3.body);
+    }
+
+    fn build_type4(&mut self, type4: SmbiosType4Config, instance: u16, mach_cfg: &MachineConfig) {
+        let mut table4 = SmbiosType4Table::new(instance);
+
+        table4.header.socket_design = table4.str_index + 1;
+        let sock_pfx = type4.sock_pfx.unwrap_or_else(|| String::from("CPU"));
+        table4.set_str(format!("{} {}", sock_pfx, instance));
+
+        table4.header.processor_manufacturer = table4.str_index + 1;
+        table4.set_str(
+            type4
+                .manufacturer
+                .unwrap_or_else(|| String::from(DEFAULT_MANUFACTURER)),
+        );
+
+        if let Some(version) = type4.version {
+            table4.header.processor_version = table4.str_index + 1;
+            table4.set_str(version);
+        }
+
+        if let Some(serial) = type4.serial {
+            table4.header.serial_num = table4.str_index + 1;
+            table4.set_str(serial);
+        }
+
+        if let Some(asset) = type4.asset {
+            table4.header.asset_tag_num = table4.str_index + 1;
+            table4.set_str(asset);
+        }
+
+        if let Some(part) = type4.part {
+            table4.header.part_num = table4.str_index + 1;
+            table4.set_str(part);
+        }
+
+        // Speed has been checked not to exceed u16::MAX in config parsing.
+        let max_speed = type4.max_speed.unwrap_or(2000) as u16;
+        let current_speed = type4.current_speed.unwrap_or(2000) as u16;
+        table4.header.max_speed = max_speed.to_le_bytes();
+        table4.header.current_speed = current_speed.to_le_bytes();
+
+        let sockets = std::cmp::max(mach_cfg.nr_sockets, 1);
+        let threads_per_socket = u16::from(mach_cfg.max_cpus) / u16::from(sockets);
+        let cores_per_socket =
+            threads_per_socket / u16::from(std::cmp::max(mach_cfg.nr_threads, 1));
+        table4.header.core_count = std::cmp::min(cores_per_socket, 0xFF) as u8;
+        table4.header.core_enabled = table4.header.core_count;
+        table4.header.thread_count = std::cmp::min(threads_per_socket, 0xFF) as u8;
+        table4.header.core_count2 = cores_per_socket.to_le_bytes();
+        table4.header.core_enabled2 = cores_per_socket.to_le_bytes();
+        table4.header.thread_count2 = threads_per_socket.to_le_bytes();
+        table4.finish();
+
+        self.entries.append(&mut table4.header.as_bytes().to_vec());
+        self.entries.append(&mut table4.body);
+    }
+
+    fn build_type16(&mut self, mem_size: u64, cnt: u16) {
+        let mut table16 = SmbiosType16Table::new(cnt);
+
+        let size_kb = mem_size / KB;
+        if size_kb < 0x8000_0000 {
+            table16.header.maximum_capacity = (size_kb as u32).to_le_bytes();
+        } else {
+            table16.header.maximum_capacity = 0x8000_0000_u32.to_le_bytes();
+            table16.header.extended_maximum_capacity = mem_size.to_le_bytes();
+        }
+        table16.finish();
+
+        self.entries.append(&mut table16.header.as_bytes().to_vec());
+        self.entries.append(&mut table16.body);
+    }
+
+    fn build_type17(&mut self, type17: SmbiosType17Config, instance: u16, mem_size: u64) {
+        let mut table17 = SmbiosType17Table::new(instance);
+
+        let size_mb = mem_size / MB;
+        if size_mb < 0x7FFF {
+            table17.header.size = (size_mb as u16).to_le_bytes();
+        } else {
+            table17.header.size = 0x7FFF_u16.to_le_bytes();
+            table17.header.extended_size = (size_mb as u32).to_le_bytes();
+        }
+        table17.header.speed = type17.speed.to_le_bytes();
+        table17.header.configured_clock_speed = type17.speed.to_le_bytes();
+
+        table17.header.device_locator_str = table17.str_index + 1;
+        let loc_pfx = type17.loc_pfx.unwrap_or_else(|| String::from("DIMM"));
+        table17.set_str(format!("{} {}", loc_pfx, instance));
+
+        if let Some(bank) = type17.bank {
+            table17.header.bank_locator_str = table17.str_index + 1;
+            table17.set_str(bank);
+        }
+
+        table17.header.manufacturer_str = table17.str_index + 1;
+        table17.set_str(
+            type17
+                .manufacturer
+                .unwrap_or_else(|| String::from(DEFAULT_MANUFACTURER)),
+        );
+
+        if let Some(serial) = type17.serial {
+            table17.header.serial_number_str = table17.str_index + 1;
+            table17.set_str(serial);
+        }
+
+        if let Some(asset) = type17.asset {
+            table17.header.asset_tag_number_str = table17.str_index + 1;
+            table17.set_str(asset);
+        }
+
+        if let Some(part) = type17.part {
+            table17.header.part_number_str = table17.str_index + 1;
+            table17.set_str(part);
+        }
+        table17.finish();
+
+        self.entries.append(&mut table17.header.as_bytes().to_vec());
+        self.entries.append(&mut table17.body);
+    }
+
     fn build_type127(&mut self) {
         let mut table127 = SmbiosType127Table::new();
 
@@ -288,9 +869,32 @@ impl SmbiosTable {
         self.entries.append(&mut table127.body);
     }
 
-    pub fn build_smbios_tables(&mut self, smbios: SmbiosConfig) -> Vec<u8> {
+    pub fn build_smbios_tables(
+        &mut self,
+        smbios: SmbiosConfig,
+        mach_cfg: &MachineConfig,
+    ) -> Vec<u8> {
         self.build_type0(smbios.type0);
         self.build_type1(smbios.type1);
+        self.build_type2(smbios.type2);
+        self.build_type3(smbios.type3);
+
+        let sockets = std::cmp::max(mach_cfg.nr_sockets, 1);
+        for i in 0..u16::from(sockets) {
+            self.build_type4(smbios.type4.clone(), i, mach_cfg);
+        }
+
+        let mem_size = mach_cfg.mem_config.mem_size;
+        let mem_num = ((mem_size + MAX_DIMM_SIZE - 1) / MAX_DIMM_SIZE) as u16;
+        self.build_type16(mem_size, mem_num);
+        for i in 0..mem_num {
+            let dimm_size = if i == mem_num - 1 {
+                mem_size - MAX_DIMM_SIZE * u64::from(i)
+            } else {
+                MAX_DIMM_SIZE
+            };
+            self.build_type17(smbios.type17.clone(), i, dimm_size);
+        }
         self.build_type127();
 
         self.entries.clone()
@@ -333,3 +937,50 @@ pub fn build_smbios_ep30(table_len: u32) -> Vec<u8> {
 
     ep.as_bytes().to_vec()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_smbios_table_size() {
+        assert_eq!(size_of::<SmbiosType2>(), 0x0F);
+        assert_eq!(size_of::<SmbiosType3>(), 0x16);
+        assert_eq!(size_of::<SmbiosType4>(), 0x30);
+        assert_eq!(size_of::<SmbiosType16>(), 0x17);
+        assert_eq!(size_of::<SmbiosType17>(), 0x28);
+    }
+
+    #[test]
+    fn test_build_smbios_tables() {
+        let mut mach_cfg = MachineConfig::default();
+        mach_cfg.nr_sockets = 2;
+        mach_cfg.max_cpus = 8;
+        mach_cfg.nr_threads = 2;
+        mach_cfg.mem_config.mem_size = 20 * 1024 * 1024 * 1024;
+
+        let mut smbios = SmbiosTable::new();
+        let table = smbios.build_smbios_tables(SmbiosConfig::default(), &mach_cfg);
+
+        // Walk through all the structures and collect their types.
+        let mut types = Vec::new();
+        let mut offset = 0;
+        while offset < table.len() {
+            let type_num = table[offset];
+            let len = table[offset + 1] as usize;
+            types.push(type_num);
+            if type_num == 4 {
+                // Core count and thread count of each socket.
+                assert_eq!(table[offset + 35], 2);
+                assert_eq!(table[offset + 37], 4);
+            }
+            offset += len;
+            // Skip the string-set which is terminated with two zero bytes.
+            while table[offset] != 0 || table[offset + 1] != 0 {
+                offset += 1;
+            }
+            offset += 2;
+        }
+        assert_eq!(types, vec![0, 1, 2, 3, 4, 4, 16, 17, 17, 127]);
+    }
+}
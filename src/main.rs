@@ -14,20 +14,20 @@ use std::io::Write;
 use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, Context, Result};
-use log::{error, info};
+use log::{error, info, warn};
 use machine::{LightMachine, MachineOps, StdMachine};
 use machine_manager::{
-    cmdline::{check_api_channel, create_args_parser, create_vmconfig},
+    cmdline::{check_api_channel, check_rest_api, create_args_parser, create_vmconfig},
     config::MachineType,
-    config::VmConfig,
+    config::{parse_log_config, parse_seccomp_mode, VmConfig},
     event_loop::EventLoop,
     qmp::QmpChannel,
     signal_handler::{exit_with_code, register_kill_signal, VM_EXIT_GENE_ERR},
-    socket::Socket,
     temp_cleaner::TempCleaner,
     test_server::TestSock,
 };
 use util::loop_context::EventNotifierHelper;
+use util::seccomp::SeccompOpt;
 use util::test_helper::{is_test_enabled, set_test_enabled};
 use util::{arg_parser, daemonize::daemonize, logger, set_termi_canon_mode};
 
@@ -94,7 +94,12 @@ fn run() -> Result<()> {
     }
 
     let logfile_path = cmd_args.value_of("display log").unwrap_or_default();
-    logger::init_log(logfile_path)?;
+    let mut log_config = match cmd_args.value_of("log") {
+        Some(config) => parse_log_config(&config)?,
+        None => logger::LogConfig::default(),
+    };
+    log_config.vm_id = cmd_args.value_of("name").unwrap_or_default();
+    logger::init_log(logfile_path, log_config)?;
 
     std::panic::set_hook(Box::new(|panic_msg| {
         set_termi_canon_mode().expect("Failed to set terminal to canonical mode.");
@@ -159,7 +164,8 @@ fn real_main(cmd_args: &arg_parser::ArgMatches, vm_config: &mut VmConfig) -> Res
     EventLoop::object_init(&vm_config.iothreads)?;
     register_kill_signal();
 
-    let listeners = check_api_channel(cmd_args, vm_config)?;
+    let api_sockets = check_api_channel(cmd_args, vm_config)?;
+    let mut rest_api = check_rest_api(cmd_args, vm_config)?;
     let mut sockets = Vec::new();
     let vm: Arc<Mutex<dyn MachineOps + Send + Sync>> = match vm_config.machine_config.mach_type {
         MachineType::MicroVm => {
@@ -172,8 +178,12 @@ fn real_main(cmd_args: &arg_parser::ArgMatches, vm_config: &mut VmConfig) -> Res
             MachineOps::realize(&vm, vm_config).with_context(|| "Failed to realize micro VM.")?;
             EventLoop::set_manager(vm.clone(), None);
 
-            for listener in listeners {
-                sockets.push(Socket::from_unix_listener(listener, Some(vm.clone())));
+            for mut socket in api_sockets {
+                socket.set_performer(vm.clone());
+                sockets.push(socket);
+            }
+            if let Some(server) = rest_api.as_mut() {
+                server.set_controller(vm.clone());
             }
             vm
         }
@@ -197,8 +207,12 @@ fn real_main(cmd_args: &arg_parser::ArgMatches, vm_config: &mut VmConfig) -> Res
                 .with_context(|| "Failed to add test socket to MainLoop")?;
             }
 
-            for listener in listeners {
-                sockets.push(Socket::from_unix_listener(listener, Some(vm.clone())));
+            for mut socket in api_sockets {
+                socket.set_performer(vm.clone());
+                sockets.push(socket);
+            }
+            if let Some(server) = rest_api.as_mut() {
+                server.set_controller(vm.clone());
             }
             vm
         }
@@ -211,8 +225,12 @@ fn real_main(cmd_args: &arg_parser::ArgMatches, vm_config: &mut VmConfig) -> Res
             ));
             EventLoop::set_manager(vm.clone(), None);
 
-            for listener in listeners {
-                sockets.push(Socket::from_unix_listener(listener, Some(vm.clone())));
+            for mut socket in api_sockets {
+                socket.set_performer(vm.clone());
+                sockets.push(socket);
+            }
+            if let Some(server) = rest_api.as_mut() {
+                server.set_controller(vm.clone());
             }
             vm
         }
@@ -225,14 +243,28 @@ fn real_main(cmd_args: &arg_parser::ArgMatches, vm_config: &mut VmConfig) -> Res
         )
         .with_context(|| "Failed to add api event to MainLoop")?;
     }
+    if let Some(server) = rest_api {
+        EventLoop::update_event(
+            EventNotifierHelper::internal_notifiers(Arc::new(Mutex::new(server))),
+            None,
+        )
+        .with_context(|| "Failed to add rest api event to MainLoop")?;
+    }
 
     machine::vm_run(&vm, cmd_args).with_context(|| "Failed to start VM.")?;
 
     let balloon_switch_on = vm_config.dev_name.get("balloon").is_some();
     if !cmd_args.is_present("disable-seccomp") {
+        let seccomp_opt = match cmd_args.value_of("seccomp-mode") {
+            Some(mode) => parse_seccomp_mode(&mode)?,
+            None => SeccompOpt::Trap,
+        };
+        if seccomp_opt == SeccompOpt::Log {
+            warn!("Seccomp is in log mode, the syscalls not allowed are only logged by kernel.");
+        }
         vm.lock()
             .unwrap()
-            .register_seccomp(balloon_switch_on)
+            .register_seccomp(balloon_switch_on, seccomp_opt)
             .with_context(|| "Failed to register seccomp rules.")?;
     }
 
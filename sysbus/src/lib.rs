@@ -146,6 +146,18 @@ impl SysBus {
                         )
                     })?;
             }
+            SysBusDevType::PvPanic if cfg!(target_arch = "x86_64") => {
+                #[cfg(target_arch = "x86_64")]
+                self.sys_io
+                    .root()
+                    .add_subregion(region, region_base)
+                    .with_context(|| {
+                        format!(
+                            "Failed to register region in I/O space: offset 0x{:x}, size {}",
+                            region_base, region_size
+                        )
+                    })?;
+            }
             SysBusDevType::Rtc if cfg!(target_arch = "x86_64") => {
                 #[cfg(target_arch = "x86_64")]
                 self.sys_io
@@ -211,6 +223,8 @@ pub enum SysBusDevType {
     FwCfg,
     Flash,
     Ramfb,
+    PvPanic,
+    Tpm,
     Others,
 }
 
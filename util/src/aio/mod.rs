@@ -183,6 +183,8 @@ impl<T: Clone> AioCb<T> {
 }
 
 pub type AioCompleteFunc<T> = fn(&AioCb<T>, i64) -> Result<()>;
+/// Notifier with offset and length of the data written by completed request.
+pub type AioWriteNotifier = dyn Fn(u64, u64) + Send + Sync;
 
 pub struct Aio<T: Clone + 'static> {
     ctx: Option<Box<dyn AioContext<T>>>,
@@ -194,6 +196,7 @@ pub struct Aio<T: Clone + 'static> {
     pub incomplete_cnt: Arc<AtomicU64>,
     max_events: usize,
     pub complete_func: Arc<AioCompleteFunc<T>>,
+    write_notifier: Option<Arc<AioWriteNotifier>>,
 }
 
 pub fn aio_probe(engine: AioEngine) -> Result<()> {
@@ -230,14 +233,51 @@ impl<T: Clone + 'static> Aio<T> {
             incomplete_cnt: Arc::new(AtomicU64::new(0)),
             max_events,
             complete_func: func,
+            write_notifier: None,
         })
     }
 
+    /// Set the notifier which is called when a request changing data of file completes,
+    /// it's used to track the data written, such as by block mirror.
+    pub fn set_write_notifier(&mut self, notifier: Arc<AioWriteNotifier>) {
+        self.write_notifier = Some(notifier);
+    }
+
+    fn complete(&self, cb: &AioCb<T>, res: i64) -> Result<()> {
+        crate::trace_event!(
+            aio_complete,
+            "fd {} opcode {:?} offset {} nbytes {} res {}",
+            cb.file_fd,
+            cb.opcode,
+            cb.offset,
+            cb.nbytes,
+            res
+        );
+        if let Some(notifier) = self.write_notifier.as_ref() {
+            if matches!(
+                cb.opcode,
+                OpCode::Pwritev | OpCode::Discard | OpCode::WriteZeroes | OpCode::WriteZeroesUnmap
+            ) {
+                notifier(cb.offset as u64, cb.nbytes);
+            }
+        }
+        (self.complete_func)(cb, res)
+    }
+
     pub fn get_engine(&self) -> AioEngine {
         self.engine
     }
 
     pub fn submit_request(&mut self, mut cb: AioCb<T>) -> Result<()> {
+        crate::trace_event!(
+            aio_submit,
+            "fd {} opcode {:?} offset {} nbytes {} engine {:?}",
+            cb.file_fd,
+            cb.opcode,
+            cb.offset,
+            cb.nbytes,
+            self.engine
+        );
         if self.request_misaligned(&cb) {
             let max_len = round_down(cb.nbytes + cb.req_align as u64 * 2, cb.req_align as u64)
                 .with_context(|| "Failed to round down request length.")?;
@@ -249,7 +289,7 @@ impl<T: Clone + 'static> Aio<T> {
                 unsafe { libc::memalign(host_page_size() as usize, buff_len as usize) };
             if bounce_buffer.is_null() {
                 error!("Failed to alloc memory for misaligned read/write.");
-                return (self.complete_func)(&cb, -1);
+                return self.complete(&cb, -1);
             }
 
             let res = match self.handle_misaligned_rw(&mut cb, bounce_buffer, buff_len) {
@@ -262,7 +302,7 @@ impl<T: Clone + 'static> Aio<T> {
 
             // SAFETY: the memory is allocated by us and will not be used anymore.
             unsafe { libc::free(bounce_buffer) };
-            return (self.complete_func)(&cb, res);
+            return self.complete(&cb, res);
         }
 
         if cb.opcode == OpCode::Pwritev
@@ -325,7 +365,7 @@ impl<T: Clone + 'static> Aio<T> {
                     -1
                 };
 
-                let res = (self.complete_func)(&(*node).value, res);
+                let res = self.complete(&(*node).value, res);
                 self.aio_in_flight.unlink(&(*node));
                 self.incomplete_cnt.fetch_sub(1, Ordering::SeqCst);
                 // Construct Box to free mem automatically.
@@ -378,7 +418,7 @@ impl<T: Clone + 'static> Aio<T> {
                 // Fail one request, retry the rest.
                 if let Some(node) = self.aio_in_queue.pop_tail() {
                     self.incomplete_cnt.fetch_sub(1, Ordering::SeqCst);
-                    (self.complete_func)(&(node).value, -1)?;
+                    self.complete(&(node).value, -1)?;
                 }
             } else if nr == 0 {
                 // If can't submit any request, break the loop
@@ -414,7 +454,7 @@ impl<T: Clone + 'static> Aio<T> {
             error!("Incomplete sync read/write.");
             ret = -1;
         }
-        (self.complete_func)(&cb, ret)
+        self.complete(&cb, ret)
     }
 
     fn request_misaligned(&self, cb: &AioCb<T>) -> bool {
@@ -590,7 +630,7 @@ impl<T: Clone + 'static> Aio<T> {
         if ret < 0 {
             error!("Failed to do sync flush.");
         }
-        (self.complete_func)(&cb, ret)
+        self.complete(&cb, ret)
     }
 
     fn discard_sync(&mut self, cb: AioCb<T>) -> Result<()> {
@@ -598,7 +638,7 @@ impl<T: Clone + 'static> Aio<T> {
         if ret < 0 {
             error!("Failed to do sync discard.");
         }
-        (self.complete_func)(&cb, ret)
+        self.complete(&cb, ret)
     }
 
     fn write_zeroes_sync(&mut self, cb: AioCb<T>) -> Result<()> {
@@ -606,14 +646,14 @@ impl<T: Clone + 'static> Aio<T> {
         if cb.opcode == OpCode::WriteZeroesUnmap {
             ret = raw_discard(cb.file_fd, cb.offset, cb.nbytes);
             if ret == 0 {
-                return (self.complete_func)(&cb, ret);
+                return self.complete(&cb, ret);
             }
         }
         ret = raw_write_zeroes(cb.file_fd, cb.offset, cb.nbytes);
         if ret < 0 {
             error!("Failed to do sync write zeroes.");
         }
-        (self.complete_func)(&cb, ret)
+        self.complete(&cb, ret)
     }
 }
 
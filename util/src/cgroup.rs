@@ -0,0 +1,213 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+const MOUNTS_FILE: &str = "/proc/self/mounts";
+/// Directory under the cpu controller which holds the cgroups of all VMs.
+const CGROUP_DIR: &str = "stratovirt";
+/// Name of the threaded cgroup of vCPU threads in cgroup v2.
+const VCPU_CGROUP: &str = "vcpus";
+/// Range of `cpu.shares` in cgroup v1.
+const MIN_CPU_SHARES: u64 = 2;
+const MAX_CPU_SHARES: u64 = 262144;
+/// Range of `cpu.weight` in cgroup v2.
+const MIN_CPU_WEIGHT: u64 = 1;
+const MAX_CPU_WEIGHT: u64 = 10000;
+
+/// The cpu cgroup dedicated to the vCPU threads of a VM, which is created
+/// under the mount point of cpu controller as `stratovirt/<vm name>`.
+///
+/// In cgroup v1, vCPU threads are moved into it by `tasks`. In cgroup v2, the
+/// process is moved into it and vCPU threads are moved into its threaded child
+/// cgroup `vcpus`, so that the limits only apply to vCPU threads.
+pub struct CpuCgroup {
+    path: PathBuf,
+    /// File to move threads into the cgroup, which is opened in advance so that
+    /// vCPU threads can join the cgroup when seccomp is enabled.
+    threads: File,
+}
+
+impl CpuCgroup {
+    /// Create the cpu cgroup of the VM and set the limits.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the cgroup, which is the VM name.
+    /// * `quota` - Max run time in microseconds in each period, `None` means no limit.
+    /// * `period` - Length of the period in microseconds.
+    /// * `shares` - Relative weight as `cpu.shares` of cgroup v1, `None` means the default.
+    pub fn new(name: &str, quota: Option<u64>, period: u64, shares: Option<u64>) -> Result<Self> {
+        if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+            bail!("Invalid cgroup name {:?}", name);
+        }
+        let mounts = fs::read_to_string(MOUNTS_FILE)
+            .with_context(|| format!("Failed to read {}", MOUNTS_FILE))?;
+        let (mount, v2) =
+            find_cpu_mount(&mounts).with_context(|| "Failed to find the cpu cgroup controller")?;
+
+        let cgroup = if v2 {
+            Self::create_v2(&mount, name, quota, period, shares)?
+        } else {
+            Self::create_v1(&mount, name, quota, period, shares)?
+        };
+        Ok(cgroup)
+    }
+
+    fn create_v1(
+        mount: &Path,
+        name: &str,
+        quota: Option<u64>,
+        period: u64,
+        shares: Option<u64>,
+    ) -> Result<Self> {
+        let path = mount.join(CGROUP_DIR).join(name);
+        fs::create_dir_all(&path).with_context(|| format!("Failed to create cgroup {:?}", path))?;
+
+        write_cgroup_file(&path, "cpu.cfs_period_us", &period.to_string())?;
+        let cfs_quota = quota.map_or_else(|| "-1".to_string(), |q| q.to_string());
+        write_cgroup_file(&path, "cpu.cfs_quota_us", &cfs_quota)?;
+        if let Some(shares) = shares {
+            write_cgroup_file(&path, "cpu.shares", &shares.to_string())?;
+        }
+
+        let threads = open_cgroup_file(&path, "tasks")?;
+        Ok(CpuCgroup { path, threads })
+    }
+
+    fn create_v2(
+        mount: &Path,
+        name: &str,
+        quota: Option<u64>,
+        period: u64,
+        shares: Option<u64>,
+    ) -> Result<Self> {
+        let parent = mount.join(CGROUP_DIR);
+        let path = parent.join(name);
+        let vcpu_path = path.join(VCPU_CGROUP);
+        fs::create_dir_all(&vcpu_path)
+            .with_context(|| format!("Failed to create cgroup {:?}", vcpu_path))?;
+        write_cgroup_file(mount, "cgroup.subtree_control", "+cpu")?;
+        write_cgroup_file(&parent, "cgroup.subtree_control", "+cpu")?;
+
+        // Turn the VM cgroup into a threaded root, which is able to hold the
+        // process and distribute cpu controller to the vCPU cgroup.
+        let cgroup_type = fs::read_to_string(vcpu_path.join("cgroup.type")).unwrap_or_default();
+        if cgroup_type.trim() != "threaded" {
+            write_cgroup_file(&vcpu_path, "cgroup.type", "threaded")?;
+        }
+        write_cgroup_file(&path, "cgroup.procs", &std::process::id().to_string())?;
+        write_cgroup_file(&path, "cgroup.subtree_control", "+cpu")?;
+
+        let max = quota.map_or_else(|| "max".to_string(), |q| q.to_string());
+        write_cgroup_file(&vcpu_path, "cpu.max", &format!("{} {}", max, period))?;
+        if let Some(shares) = shares {
+            let weight = shares_to_weight(shares);
+            write_cgroup_file(&vcpu_path, "cpu.weight", &weight.to_string())?;
+        }
+
+        let threads = open_cgroup_file(&vcpu_path, "cgroup.threads")?;
+        Ok(CpuCgroup {
+            path: vcpu_path,
+            threads,
+        })
+    }
+
+    /// Move the calling thread into the cgroup.
+    pub fn add_current_thread(&self) -> Result<()> {
+        // Writing 0 moves the thread which writes it.
+        (&self.threads)
+            .write_all(b"0")
+            .with_context(|| format!("Failed to move thread into cgroup {:?}", self.path))
+    }
+}
+
+/// Find the mount point of cpu controller from the content of `/proc/self/mounts`,
+/// and return it with whether it's cgroup v2. The cpu controller of cgroup v1 is
+/// preferred, as cgroup v2 may be mounted without controllers in hybrid mode.
+fn find_cpu_mount(mounts: &str) -> Option<(PathBuf, bool)> {
+    let mut v2_mount = None;
+    for line in mounts.lines() {
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields.len() < 4 {
+            continue;
+        }
+        match fields[2] {
+            "cgroup" if fields[3].split(',').any(|opt| opt == "cpu") => {
+                return Some((PathBuf::from(fields[1]), false));
+            }
+            "cgroup2" if v2_mount.is_none() => v2_mount = Some(PathBuf::from(fields[1])),
+            _ => {}
+        }
+    }
+    v2_mount.map(|mount| (mount, true))
+}
+
+/// Convert `cpu.shares` of cgroup v1 to `cpu.weight` of cgroup v2, in the same way as systemd.
+fn shares_to_weight(shares: u64) -> u64 {
+    let shares = shares.clamp(MIN_CPU_SHARES, MAX_CPU_SHARES);
+    MIN_CPU_WEIGHT
+        + (shares - MIN_CPU_SHARES) * (MAX_CPU_WEIGHT - MIN_CPU_WEIGHT)
+            / (MAX_CPU_SHARES - MIN_CPU_SHARES)
+}
+
+fn open_cgroup_file(path: &Path, file: &str) -> Result<File> {
+    let file_path = path.join(file);
+    OpenOptions::new()
+        .write(true)
+        .open(&file_path)
+        .with_context(|| format!("Failed to open {:?}", file_path))
+}
+
+fn write_cgroup_file(path: &Path, file: &str, value: &str) -> Result<()> {
+    open_cgroup_file(path, file)?
+        .write_all(value.as_bytes())
+        .with_context(|| format!("Failed to write {} to {:?}", value, path.join(file)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_cpu_mount() {
+        let v1 = "sysfs /sys sysfs rw,nosuid 0 0\n\
+                  cgroup2 /sys/fs/cgroup/unified cgroup2 rw,nosuid 0 0\n\
+                  cgroup /sys/fs/cgroup/cpuset cgroup rw,nosuid,cpuset 0 0\n\
+                  cgroup /sys/fs/cgroup/cpu,cpuacct cgroup rw,nosuid,cpu,cpuacct 0 0\n";
+        assert_eq!(
+            find_cpu_mount(v1),
+            Some((PathBuf::from("/sys/fs/cgroup/cpu,cpuacct"), false))
+        );
+
+        let v2 = "proc /proc proc rw 0 0\n\
+                  cgroup2 /sys/fs/cgroup cgroup2 rw,nosuid,nsdelegate 0 0\n";
+        assert_eq!(
+            find_cpu_mount(v2),
+            Some((PathBuf::from("/sys/fs/cgroup"), true))
+        );
+
+        assert_eq!(find_cpu_mount("proc /proc proc rw 0 0\n"), None);
+    }
+
+    #[test]
+    fn test_shares_to_weight() {
+        assert_eq!(shares_to_weight(2), 1);
+        assert_eq!(shares_to_weight(1024), 39);
+        assert_eq!(shares_to_weight(262144), 10000);
+        assert_eq!(shares_to_weight(0), 1);
+    }
+}
@@ -40,6 +40,7 @@ const FDT_LAST_COMP_VERSION: u32 = 16;
 const FDT_BEGIN_NODE: u32 = 0x00000001;
 const FDT_END_NODE: u32 = 0x00000002;
 const FDT_PROP: u32 = 0x00000003;
+const FDT_NOP: u32 = 0x00000004;
 const FDT_END: u32 = 0x00000009;
 // Memory reservation block alignment.
 const MEM_RESERVE_ALIGNMENT: usize = 8;
@@ -290,6 +291,219 @@ impl FdtBuilder {
     }
 }
 
+/// Node of device tree, which is parsed from device tree blob.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FdtNode {
+    /// Node name, which is empty for root node.
+    pub name: String,
+    /// Properties in order, (name, value).
+    pub properties: Vec<(String, Vec<u8>)>,
+    /// Sub nodes in order.
+    pub children: Vec<FdtNode>,
+}
+
+impl FdtNode {
+    pub fn new(name: &str) -> Self {
+        FdtNode {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Get value of the property.
+    pub fn property(&self, name: &str) -> Option<&[u8]> {
+        self.properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Set value of the property, which is added if not exists.
+    pub fn set_property(&mut self, name: &str, val: &[u8]) {
+        match self.properties.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = val.to_vec(),
+            None => self.properties.push((name.to_string(), val.to_vec())),
+        }
+    }
+
+    pub fn set_property_string(&mut self, name: &str, val: &str) {
+        let mut val_array = val.as_bytes().to_vec();
+        val_array.push(0x0_u8);
+        self.set_property(name, &val_array);
+    }
+
+    pub fn set_property_u64(&mut self, name: &str, val: u64) {
+        self.set_property(name, &val.to_be_bytes());
+    }
+
+    pub fn remove_property(&mut self, name: &str) {
+        self.properties.retain(|(n, _)| n != name);
+    }
+
+    /// Get the sub node, which is added if not exists.
+    pub fn child_mut(&mut self, name: &str) -> &mut FdtNode {
+        let index = match self.children.iter().position(|c| c.name == name) {
+            Some(index) => index,
+            None => {
+                self.children.push(FdtNode::new(name));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index]
+    }
+
+    fn compile(&self, fdt: &mut FdtBuilder) -> Result<()> {
+        let node_dep = fdt.begin_node(&self.name)?;
+        for (name, val) in self.properties.iter() {
+            fdt.set_property(name, val)?;
+        }
+        for child in self.children.iter() {
+            child.compile(fdt)?;
+        }
+        fdt.end_node(node_dep)
+    }
+}
+
+/// Device tree parsed from device tree blob, which can be modified and compiled again.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceTree {
+    mem_reserve: Vec<FdtReserveEntry>,
+    boot_cpuid_phys: u32,
+    /// The root node.
+    pub root: FdtNode,
+}
+
+fn fdt_u32(dtb: &[u8], offset: usize) -> Result<u32> {
+    dtb.get(offset..offset + 4)
+        .map(BigEndian::read_u32)
+        .with_context(|| UtilError::InvalidFdt(format!("offset 0x{:x} overflows", offset)))
+}
+
+fn fdt_string(dtb: &[u8], offset: usize) -> Result<String> {
+    let bytes = dtb
+        .get(offset..)
+        .and_then(|b| b.iter().position(|&c| c == 0).map(|len| &b[..len]))
+        .with_context(|| UtilError::InvalidFdt(format!("no string at 0x{:x}", offset)))?;
+    String::from_utf8(bytes.to_vec())
+        .with_context(|| UtilError::InvalidFdt(format!("invalid string at 0x{:x}", offset)))
+}
+
+fn fdt_align(offset: usize) -> usize {
+    (offset + STRUCTURE_BLOCK_ALIGNMENT - 1) & !(STRUCTURE_BLOCK_ALIGNMENT - 1)
+}
+
+impl DeviceTree {
+    /// Parse device tree blob.
+    pub fn from_dtb(dtb: &[u8]) -> Result<Self> {
+        if fdt_u32(dtb, 0)? != FDT_MAGIC {
+            return Err(anyhow!(UtilError::InvalidFdt("bad magic".to_string())));
+        }
+        let total_size = fdt_u32(dtb, 4)? as usize;
+        if total_size > dtb.len() || total_size < FDT_HEADER_SIZE {
+            return Err(anyhow!(UtilError::InvalidFdt(format!(
+                "bad total size 0x{:x}",
+                total_size
+            ))));
+        }
+        let dtb = &dtb[..total_size];
+        if fdt_u32(dtb, 24)? > FDT_VERSION || fdt_u32(dtb, 20)? < FDT_LAST_COMP_VERSION {
+            return Err(anyhow!(UtilError::InvalidFdt(
+                "unsupported version".to_string()
+            )));
+        }
+        let off_dt_struct = fdt_u32(dtb, 8)? as usize;
+        let off_dt_strings = fdt_u32(dtb, 12)? as usize;
+        let strings = dtb
+            .get(off_dt_strings..)
+            .with_context(|| UtilError::InvalidFdt("bad strings block".to_string()))?;
+
+        let mut mem_reserve = Vec::new();
+        let mut offset = fdt_u32(dtb, 16)? as usize;
+        loop {
+            let address = (fdt_u32(dtb, offset)? as u64) << 32 | fdt_u32(dtb, offset + 4)? as u64;
+            let size = (fdt_u32(dtb, offset + 8)? as u64) << 32 | fdt_u32(dtb, offset + 12)? as u64;
+            if address == 0 && size == 0 {
+                break;
+            }
+            mem_reserve.push(FdtReserveEntry { address, size });
+            offset += 16;
+        }
+
+        let mut nodes: Vec<FdtNode> = Vec::new();
+        let mut root = None;
+        let mut offset = off_dt_struct;
+        loop {
+            let token = fdt_u32(dtb, offset)?;
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    if root.is_some() {
+                        return Err(anyhow!(UtilError::InvalidFdt(
+                            "multiple root nodes".to_string()
+                        )));
+                    }
+                    let name = fdt_string(dtb, offset)?;
+                    offset = fdt_align(offset + name.len() + 1);
+                    nodes.push(FdtNode::new(&name));
+                }
+                FDT_PROP => {
+                    let len = fdt_u32(dtb, offset)? as usize;
+                    let name = fdt_string(strings, fdt_u32(dtb, offset + 4)? as usize)?;
+                    offset += 8;
+                    let val = dtb.get(offset..offset + len).with_context(|| {
+                        UtilError::InvalidFdt(format!("property {} overflows", name))
+                    })?;
+                    offset = fdt_align(offset + len);
+                    nodes
+                        .last_mut()
+                        .with_context(|| {
+                            UtilError::InvalidFdt(format!("orphan property {}", name))
+                        })?
+                        .properties
+                        .push((name, val.to_vec()));
+                }
+                FDT_END_NODE => {
+                    let node = nodes
+                        .pop()
+                        .with_context(|| UtilError::InvalidFdt("unmatched node end".to_string()))?;
+                    match nodes.last_mut() {
+                        Some(parent) => parent.children.push(node),
+                        None => root = Some(node),
+                    }
+                }
+                FDT_NOP => {}
+                FDT_END => break,
+                _ => {
+                    return Err(anyhow!(UtilError::InvalidFdt(format!(
+                        "bad token 0x{:x}",
+                        token
+                    ))))
+                }
+            }
+        }
+        if !nodes.is_empty() {
+            return Err(anyhow!(UtilError::NodeUnclosed(nodes.len() as u32)));
+        }
+
+        Ok(DeviceTree {
+            mem_reserve,
+            boot_cpuid_phys: fdt_u32(dtb, 28)?,
+            root: root.with_context(|| UtilError::InvalidFdt("no root node".to_string()))?,
+        })
+    }
+
+    /// Compile to device tree blob.
+    pub fn to_dtb(&self) -> Result<Vec<u8>> {
+        let mut fdt = FdtBuilder::new();
+        if !self.mem_reserve.is_empty() {
+            fdt.add_mem_reserve(&self.mem_reserve)?;
+        }
+        fdt.set_boot_cpuid_phys(self.boot_cpuid_phys);
+        self.root.compile(&mut fdt)?;
+        fdt.finish()
+    }
+}
+
 /// Trait for devices to be added to the Flattened Device Tree.
 #[allow(clippy::upper_case_acronyms)]
 pub trait CompileFDT {
@@ -494,4 +708,65 @@ mod tests {
         ];
         assert!(fdt_builder.add_mem_reserve(&mem_reservations).is_err());
     }
+
+    #[test]
+    fn test_parse_device_tree() {
+        let mut fdt_builder = FdtBuilder::new();
+        fdt_builder
+            .add_mem_reserve(&[FdtReserveEntry {
+                address: 0x1000,
+                size: 0x100,
+            }])
+            .unwrap();
+        let root_node = fdt_builder.begin_node("").unwrap();
+        fdt_builder.set_property_u32("#address-cells", 2).unwrap();
+        let chosen_node = fdt_builder.begin_node("chosen").unwrap();
+        fdt_builder
+            .set_property_string("bootargs", "console=ttyAMA0")
+            .unwrap();
+        fdt_builder.end_node(chosen_node).unwrap();
+        let memory_node = fdt_builder.begin_node("memory").unwrap();
+        fdt_builder
+            .set_property_array_u64("reg", &[0x4000_0000, 0x1000_0000])
+            .unwrap();
+        fdt_builder.end_node(memory_node).unwrap();
+        fdt_builder.end_node(root_node).unwrap();
+        fdt_builder.set_boot_cpuid_phys(1);
+        let dtb = fdt_builder.finish().unwrap();
+
+        // Compiling the parsed device tree gets the same blob.
+        let mut device_tree = DeviceTree::from_dtb(&dtb).unwrap();
+        assert_eq!(device_tree.root.children.len(), 2);
+        assert_eq!(
+            device_tree.root.property("#address-cells"),
+            Some(&[0, 0, 0, 2][..])
+        );
+        assert_eq!(device_tree.to_dtb().unwrap(), dtb);
+
+        let chosen = device_tree.root.child_mut("chosen");
+        chosen.set_property_string("bootargs", "console=ttyS0");
+        chosen.set_property_u64("linux,initrd-start", 0x5000_0000);
+        chosen.remove_property("linux,initrd-start");
+        device_tree.root.child_mut("aliases");
+        let device_tree = DeviceTree::from_dtb(&device_tree.to_dtb().unwrap()).unwrap();
+        assert_eq!(device_tree.root.children.len(), 3);
+        assert_eq!(device_tree.root.children[0].properties.len(), 1);
+        assert_eq!(
+            device_tree.root.children[0].property("bootargs"),
+            Some(&b"console=ttyS0\0"[..])
+        );
+
+        // Invalid blobs.
+        assert!(DeviceTree::from_dtb(&dtb[..0x20]).is_err());
+        let mut bad_dtb = dtb.clone();
+        bad_dtb[0] = 0;
+        assert!(DeviceTree::from_dtb(&bad_dtb).is_err());
+        // Replace FDT_END with a bad token.
+        let mut bad_dtb = dtb;
+        let end = (BigEndian::read_u32(&bad_dtb[8..12]) + BigEndian::read_u32(&bad_dtb[36..40]))
+            as usize
+            - 4;
+        bad_dtb[end..end + 4].copy_from_slice(&0x10_u32.to_be_bytes());
+        assert!(DeviceTree::from_dtb(&bad_dtb).is_err());
+    }
 }
@@ -86,4 +86,6 @@ pub enum UtilError {
     MemReserveOverlap,
     #[error("Failed to set {0} property")]
     SetPropertyErr(String),
+    #[error("Invalid device tree blob: {0}")]
+    InvalidFdt(String),
 }
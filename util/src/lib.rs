@@ -14,6 +14,7 @@ pub mod aio;
 pub mod arg_parser;
 pub mod bitmap;
 pub mod byte_code;
+pub mod cgroup;
 pub mod checksum;
 pub mod daemonize;
 #[cfg(target_arch = "aarch64")]
@@ -15,19 +15,107 @@ use std::io::Write;
 use std::num::Wrapping;
 use std::os::unix::fs::OpenOptionsExt;
 use std::path::Path;
+use std::str::FromStr;
 use std::sync::Mutex;
 use std::time::UNIX_EPOCH;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde_json::json;
 
 use crate::time::{get_format_time, gettime};
 use crate::unix::gettid;
 
-// Max size of the log file is 100MB.
-const LOG_ROTATE_SIZE_MAX: usize = 100 * 1024 * 1024;
-// Logs are retained for seven days.
-const LOG_ROTATE_COUNT_MAX: u32 = 7;
+// Max size of the log file is 100MB by default.
+const LOG_ROTATE_SIZE_DEFAULT: usize = 100 * 1024 * 1024;
+// Logs are retained for seven days by default.
+const LOG_ROTATE_COUNT_DEFAULT: u32 = 7;
+
+/// Format of the log records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Plain text, one record per line.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow!("Unknown log format {}", s)),
+        }
+    }
+}
+
+/// Time-based rotation policy of the log file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogRotateTime {
+    /// Only rotate the log file by size.
+    Never,
+    /// Rotate the log file when the day changes.
+    #[default]
+    Daily,
+    /// Rotate the log file when the hour changes.
+    Hourly,
+}
+
+impl FromStr for LogRotateTime {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "never" => Ok(LogRotateTime::Never),
+            "daily" => Ok(LogRotateTime::Daily),
+            "hourly" => Ok(LogRotateTime::Hourly),
+            _ => Err(anyhow!("Unknown log rotate time {}", s)),
+        }
+    }
+}
+
+impl LogRotateTime {
+    /// Period of the wall time, the log file is rotated when it changes.
+    fn period(&self, sec: i64) -> i32 {
+        let format_time = get_format_time(sec);
+        match self {
+            LogRotateTime::Never => 0,
+            LogRotateTime::Daily => format_time[2],
+            LogRotateTime::Hourly => format_time[2] * 24 + format_time[3],
+        }
+    }
+}
+
+/// Config of the logger.
+#[derive(Clone, Debug)]
+pub struct LogConfig {
+    /// Format of the log records.
+    pub format: LogFormat,
+    /// Id of the VM, which is recorded in the JSON records.
+    pub vm_id: String,
+    /// The log file is rotated when its size exceeds it, in bytes.
+    pub rotate_size: usize,
+    /// Time-based rotation policy of the log file.
+    pub rotate_time: LogRotateTime,
+    /// Number of the log files retained, including the current one.
+    pub rotate_count: u32,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            format: LogFormat::Text,
+            vm_id: String::new(),
+            rotate_size: LOG_ROTATE_SIZE_DEFAULT,
+            rotate_time: LogRotateTime::Daily,
+            rotate_count: LOG_ROTATE_COUNT_DEFAULT,
+        }
+    }
+}
 
 fn format_now() -> String {
     let (sec, nsec) = gettime();
@@ -49,7 +137,10 @@ struct FileRotate {
     handler: Box<dyn Write + Send>,
     path: String,
     current_size: Wrapping<usize>,
-    create_day: i32,
+    create_period: i32,
+    size_max: usize,
+    rotate_time: LogRotateTime,
+    count_max: u32,
 }
 
 impl FileRotate {
@@ -60,13 +151,13 @@ impl FileRotate {
 
         self.current_size += Wrapping(size_inc);
         let sec = gettime().0;
-        let today = get_format_time(sec as i64)[2];
-        if self.current_size < Wrapping(LOG_ROTATE_SIZE_MAX) && self.create_day == today {
+        let period = self.rotate_time.period(sec as i64);
+        if self.current_size < Wrapping(self.size_max) && self.create_period == period {
             return Ok(());
         }
 
         // Remove the oldest log file.
-        let mut rotate_count = LOG_ROTATE_COUNT_MAX - 1;
+        let mut rotate_count = self.count_max - 1;
         let old_name = format!("{}{}", self.path, rotate_count);
         if Path::new(&old_name).exists() {
             std::fs::remove_file(&old_name)
@@ -93,7 +184,7 @@ impl FileRotate {
         // Update log file.
         self.handler = Box::new(open_log_file(&self.path)?);
         self.current_size = Wrapping(0);
-        self.create_day = today;
+        self.create_period = period;
         Ok(())
     }
 }
@@ -102,6 +193,42 @@ impl FileRotate {
 struct VmLogger {
     rotate: Mutex<FileRotate>,
     level: Level,
+    format: LogFormat,
+    vm_id: String,
+}
+
+impl VmLogger {
+    fn format_text(&self, record: &Record, pid: i32, tid: u64) -> String {
+        format_args!(
+            "{:<5}: [{}][{}][{}: {}]:{}: {}\n",
+            format_now(),
+            pid,
+            tid,
+            record.file().unwrap_or(""),
+            record.line().unwrap_or(0),
+            record.level(),
+            record.args()
+        )
+        .to_string()
+    }
+
+    fn format_json(&self, record: &Record, pid: i32, tid: u64) -> String {
+        // Subsystem is the crate which the record comes from, e.g. `virtio`.
+        let subsystem = record.target().split("::").next().unwrap_or("");
+        let value = json!({
+            "timestamp": format_now(),
+            "level": record.level().as_str(),
+            "vm_id": self.vm_id,
+            "subsystem": subsystem,
+            "pid": pid,
+            "tid": tid,
+            "thread": std::thread::current().name().unwrap_or(""),
+            "file": record.file().unwrap_or(""),
+            "line": record.line().unwrap_or(0),
+            "message": record.args().to_string(),
+        });
+        format!("{}\n", value)
+    }
 }
 
 impl Log for VmLogger {
@@ -116,17 +243,10 @@ impl Log for VmLogger {
 
         let pid = unsafe { libc::getpid() };
         let tid = gettid();
-        let formatmsg = format_args!(
-            "{:<5}: [{}][{}][{}: {}]:{}: {}\n",
-            format_now(),
-            pid,
-            tid,
-            record.file().unwrap_or(""),
-            record.line().unwrap_or(0),
-            record.level(),
-            record.args()
-        )
-        .to_string();
+        let formatmsg = match self.format {
+            LogFormat::Text => self.format_text(record, pid, tid),
+            LogFormat::Json => self.format_json(record, pid, tid),
+        };
 
         let mut rotate = self.rotate.lock().unwrap();
         if let Err(e) = rotate.handler.write_all(formatmsg.as_bytes()) {
@@ -145,32 +265,45 @@ fn init_vm_logger(
     level: Level,
     logfile: Box<dyn Write + Send>,
     logfile_path: String,
+    config: LogConfig,
 ) -> Result<()> {
     let current_size;
-    let create_day;
+    let create_period;
     if logfile_path.is_empty() {
         current_size = Wrapping(0);
-        create_day = 0;
+        create_period = 0;
     } else {
         let metadata = File::open(&logfile_path)?.metadata()?;
         current_size = Wrapping(metadata.len() as usize);
         let mod_time = metadata.modified()?;
         let sec = mod_time.duration_since(UNIX_EPOCH)?.as_secs();
-        create_day = get_format_time(sec as i64)[2];
+        create_period = config.rotate_time.period(sec as i64);
     };
     let rotate = Mutex::new(FileRotate {
         handler: logfile,
         path: logfile_path,
         current_size,
-        create_day,
+        create_period,
+        size_max: config.rotate_size,
+        rotate_time: config.rotate_time,
+        count_max: config.rotate_count,
     });
 
-    let logger = VmLogger { rotate, level };
+    let logger = VmLogger {
+        rotate,
+        level,
+        format: config.format,
+        vm_id: config.vm_id,
+    };
     log::set_boxed_logger(Box::new(logger)).map(|()| log::set_max_level(LevelFilter::Trace))?;
     Ok(())
 }
 
-fn init_logger_with_env(logfile: Box<dyn Write + Send>, logfile_path: String) -> Result<()> {
+fn init_logger_with_env(
+    logfile: Box<dyn Write + Send>,
+    logfile_path: String,
+    config: LogConfig,
+) -> Result<()> {
     let level = match std::env::var("STRATOVIRT_LOG_LEVEL") {
         Ok(l) => match l.to_lowercase().as_str() {
             "error" => Level::Error,
@@ -183,7 +316,7 @@ fn init_logger_with_env(logfile: Box<dyn Write + Send>, logfile_path: String) ->
         _ => Level::Info,
     };
 
-    init_vm_logger(level, logfile, logfile_path)?;
+    init_vm_logger(level, logfile, logfile_path, config)?;
     Ok(())
 }
 
@@ -198,12 +331,103 @@ fn open_log_file(path: &str) -> Result<File> {
         .with_context(|| format!("Failed to open log file {}", path))
 }
 
-pub fn init_log(path: String) -> Result<()> {
+pub fn init_log(path: String, config: LogConfig) -> Result<()> {
     let logfile: Box<dyn Write + Send> = if path.is_empty() {
         Box::new(std::io::stderr())
     } else {
         Box::new(open_log_file(&path)?)
     };
-    init_logger_with_env(logfile, path.clone())
+    init_logger_with_env(logfile, path.clone(), config)
         .with_context(|| format!("Failed to init logger: {}", path))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_rotate_by_size() {
+        let path = "/tmp/stratovirt_test_log_rotate".to_string();
+        let old_logs = |count: u32| {
+            (1..count)
+                .map(|i| format!("{}{}", path, i))
+                .filter(|p| Path::new(p).exists())
+                .count()
+        };
+        let mut rotate = FileRotate {
+            handler: Box::new(open_log_file(&path).unwrap()),
+            path: path.clone(),
+            current_size: Wrapping(0),
+            create_period: 0,
+            size_max: 16,
+            rotate_time: LogRotateTime::Never,
+            count_max: 3,
+        };
+
+        rotate.rotate_file(8).unwrap();
+        assert_eq!(old_logs(4), 0);
+        rotate.rotate_file(8).unwrap();
+        assert_eq!(old_logs(4), 1);
+        rotate.rotate_file(16).unwrap();
+        assert_eq!(old_logs(4), 2);
+        // The oldest log file is removed.
+        rotate.rotate_file(16).unwrap();
+        assert_eq!(old_logs(4), 2);
+        assert!(!Path::new(&format!("{}3", path)).exists());
+
+        for i in 0..3 {
+            let name = if i == 0 {
+                path.clone()
+            } else {
+                format!("{}{}", path, i)
+            };
+            std::fs::remove_file(name).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_log_format_json() {
+        let logger = VmLogger {
+            rotate: Mutex::new(FileRotate {
+                handler: Box::new(std::io::stderr()),
+                path: String::new(),
+                current_size: Wrapping(0),
+                create_period: 0,
+                size_max: LOG_ROTATE_SIZE_DEFAULT,
+                rotate_time: LogRotateTime::Daily,
+                count_max: LOG_ROTATE_COUNT_DEFAULT,
+            }),
+            level: Level::Info,
+            format: LogFormat::Json,
+            vm_id: "vm0".to_string(),
+        };
+        let msg = logger.format_json(
+            &Record::builder()
+                .args(format_args!("queue \"{}\" is full", 1))
+                .level(Level::Warn)
+                .target("virtio::device::block")
+                .file(Some("block.rs"))
+                .line(Some(10))
+                .build(),
+            1,
+            2,
+        );
+
+        assert!(msg.ends_with('\n'));
+        let value: serde_json::Value = serde_json::from_str(&msg).unwrap();
+        assert_eq!(value["level"], "WARN");
+        assert_eq!(value["vm_id"], "vm0");
+        assert_eq!(value["subsystem"], "virtio");
+        assert_eq!(value["tid"], 2);
+        assert_eq!(value["line"], 10);
+        assert_eq!(value["message"], "queue \"1\" is full");
+
+        assert_eq!(LogFormat::from_str("json").unwrap(), LogFormat::Json);
+        assert!(LogFormat::from_str("xml").is_err());
+        assert_eq!(
+            LogRotateTime::from_str("hourly").unwrap(),
+            LogRotateTime::Hourly
+        );
+        assert!(LogRotateTime::from_str("weekly").is_err());
+    }
+}
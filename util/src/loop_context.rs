@@ -34,7 +34,10 @@ use std::fmt;
 use std::fmt::Debug;
 
 const READY_EVENT_MAX: usize = 256;
-const AIO_PRFETCH_CYCLE_TIME: usize = 100;
+/// Polling time to start with when it grows from zero.
+const POLL_NS_INITIAL: u64 = 4000;
+/// Polling time is doubled when it grows by default.
+const POLL_GROW_DEFAULT: u64 = 2;
 
 #[derive(Debug)]
 pub enum NotifierOperation {
@@ -210,6 +213,14 @@ pub struct EventLoopContext {
     ready_events: Vec<EpollEvent>,
     /// Timer list
     timers: Arc<Mutex<Vec<Box<Timer>>>>,
+    /// Max time in nanoseconds to poll events before sleeping in epoll, 0 disables polling.
+    poll_max_ns: u64,
+    /// Factor to grow the polling time by, 0 means the default factor.
+    poll_grow: u64,
+    /// Factor to shrink the polling time by, 0 means resetting it to 0.
+    poll_shrink: u64,
+    /// Current polling time in nanoseconds, adjusted adaptively in `[0, poll_max_ns]`.
+    poll_ns: u64,
 }
 
 // SAFETY: The closure in EventNotifier and Timer doesn't impl Send, they're
@@ -229,6 +240,10 @@ impl EventLoopContext {
             gc: Arc::new(RwLock::new(Vec::new())),
             ready_events: vec![EpollEvent::default(); READY_EVENT_MAX],
             timers: Arc::new(Mutex::new(Vec::new())),
+            poll_max_ns: 0,
+            poll_grow: 0,
+            poll_shrink: 0,
+            poll_ns: 0,
         };
         ctx.init_kick();
         ctx
@@ -484,22 +499,83 @@ impl EventLoopContext {
             }
         }
 
-        let min_timeout_ns = self.timers_min_duration();
-        if min_timeout_ns.is_none() {
-            for _i in 0..AIO_PRFETCH_CYCLE_TIME {
-                for notifier in self.events.read().unwrap().values() {
-                    let status_locked = notifier.status.lock().unwrap();
-                    if *status_locked != EventStatus::Alive || notifier.handler_poll.is_none() {
-                        continue;
-                    }
-                    let handler_poll = notifier.handler_poll.as_ref().unwrap();
-                    if handler_poll(EventSet::empty(), notifier.raw_fd).is_some() {
-                        break;
-                    }
+        let mut min_timeout_ns = self.timers_min_duration();
+        let start = Instant::now();
+        if self.poll_events(min_timeout_ns) {
+            // Progress is made, handle the pending events without sleeping.
+            min_timeout_ns = Some(Duration::ZERO);
+        }
+        let ret = self.epoll_wait_manager(min_timeout_ns);
+        self.adjust_poll_ns(start.elapsed().as_nanos() as u64);
+        ret
+    }
+
+    /// Set parameters of adaptive polling for the event loop.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_ns` - Max time in nanoseconds to poll events, 0 disables polling.
+    /// * `grow` - Factor to grow the polling time by, 0 means doubling it.
+    /// * `shrink` - Factor to shrink the polling time by, 0 means resetting it to 0.
+    pub fn set_poll_params(&mut self, max_ns: u64, grow: u64, shrink: u64) {
+        self.poll_max_ns = max_ns;
+        self.poll_grow = grow;
+        self.poll_shrink = shrink;
+        self.poll_ns = std::cmp::min(self.poll_ns, max_ns);
+    }
+
+    /// Spin on the polling handlers of events for at most `poll_ns`, and no longer than
+    /// `time_out`. Return whether any handler made progress.
+    fn poll_events(&self, time_out: Option<Duration>) -> bool {
+        let mut poll_time = Duration::from_nanos(self.poll_ns);
+        if let Some(time_out) = time_out {
+            poll_time = std::cmp::min(poll_time, time_out);
+        }
+        if poll_time.is_zero() {
+            return false;
+        }
+
+        let start = Instant::now();
+        loop {
+            for notifier in self.events.read().unwrap().values() {
+                let status_locked = notifier.status.lock().unwrap();
+                if *status_locked != EventStatus::Alive || notifier.handler_poll.is_none() {
+                    continue;
                 }
+                let handler_poll = notifier.handler_poll.as_ref().unwrap();
+                if handler_poll(EventSet::empty(), notifier.raw_fd).is_some() {
+                    return true;
+                }
+            }
+            if start.elapsed() >= poll_time {
+                return false;
             }
         }
-        self.epoll_wait_manager(min_timeout_ns)
+    }
+
+    /// Adjust the polling time according to how long the loop blocked for events.
+    ///
+    /// If events arrive within the polling time, it's just fine. If the loop blocks
+    /// longer than `poll_max_ns`, polling is wasted so shrink it. Otherwise grow it,
+    /// so that polling is likely to catch the next event without sleeping.
+    fn adjust_poll_ns(&mut self, block_ns: u64) {
+        if block_ns <= self.poll_ns {
+            return;
+        }
+
+        if block_ns > self.poll_max_ns {
+            self.poll_ns = match self.poll_shrink {
+                0 => 0,
+                shrink => self.poll_ns / shrink,
+            };
+        } else if self.poll_ns < self.poll_max_ns {
+            let poll_ns = match (self.poll_ns, self.poll_grow) {
+                (0, _) => POLL_NS_INITIAL,
+                (poll_ns, 0) => poll_ns.saturating_mul(POLL_GROW_DEFAULT),
+                (poll_ns, grow) => poll_ns.saturating_mul(grow),
+            };
+            self.poll_ns = std::cmp::min(poll_ns, self.poll_max_ns);
+        }
     }
 
     /// Call the function given by `func` after `delay` time.
@@ -914,4 +990,42 @@ mod test {
 
         assert!(mainloop.update_events(vec![event]).is_ok());
     }
+
+    #[test]
+    fn adaptive_poll_test() {
+        let mut ctx = EventLoopContext::new();
+        ctx.set_poll_params(32768, 0, 0);
+        assert_eq!(ctx.poll_ns, 0);
+
+        // Grow from the initial polling time, and double it by default.
+        ctx.adjust_poll_ns(10000);
+        assert_eq!(ctx.poll_ns, 4000);
+        ctx.adjust_poll_ns(10000);
+        assert_eq!(ctx.poll_ns, 8000);
+        // Events arrive within the polling time.
+        ctx.adjust_poll_ns(5000);
+        assert_eq!(ctx.poll_ns, 8000);
+        // Never exceed the max polling time.
+        ctx.adjust_poll_ns(30000);
+        ctx.adjust_poll_ns(30000);
+        ctx.adjust_poll_ns(30000);
+        assert_eq!(ctx.poll_ns, 32768);
+        // Reset it when the loop blocks longer than the max polling time.
+        ctx.adjust_poll_ns(50000);
+        assert_eq!(ctx.poll_ns, 0);
+
+        ctx.set_poll_params(32768, 4, 2);
+        ctx.adjust_poll_ns(10000);
+        ctx.adjust_poll_ns(10000);
+        assert_eq!(ctx.poll_ns, 16000);
+        ctx.adjust_poll_ns(50000);
+        assert_eq!(ctx.poll_ns, 8000);
+
+        // Polling is disabled.
+        ctx.set_poll_params(0, 0, 0);
+        assert_eq!(ctx.poll_ns, 0);
+        ctx.adjust_poll_ns(10000);
+        assert_eq!(ctx.poll_ns, 0);
+        assert!(!ctx.poll_events(None));
+    }
 }
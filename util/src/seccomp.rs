@@ -76,8 +76,20 @@
 //! println!("{}", String::from_utf8_lossy(&buffer));
 //! ```
 //! This programe will be trapped.
+//!
+//! ## Thread filters
+//!
+//! Besides the process-wide filter, some kinds of threads (see `ThreadKind`) can have
+//! a narrower filter stacked on it. As a filter can only be installed by the thread
+//! itself, the filter is registered by `SyscallFilter::register_thread_filter`, and
+//! installed when the thread calls `apply_thread_filter` in its loop.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 use anyhow::bail;
+use once_cell::sync::Lazy;
 
 use crate::offset_of;
 use anyhow::Result;
@@ -119,6 +131,8 @@ const SECCOMP_RET_LOG: u32 = 0x7ffc_0000;
 const SECCOMP_RET_MASK: u32 = 0x0000_ffff;
 /// See: https://elixir.bootlin.com/linux/v4.19.123/source/include/uapi/linux/seccomp.h#L16
 const SECCOMP_MODE_FILTER: u32 = 1;
+/// See: https://elixir.bootlin.com/linux/v4.19.123/source/include/uapi/linux/seccomp.h#L17
+const SECCOMP_GET_ACTION_AVAIL: u32 = 2;
 /// See: https://elixir.bootlin.com/linux/v4.19.123/source/include/uapi/linux/seccomp.h#L21
 const SECCOMP_FILETER_FLAG_TSYNC: u32 = 1;
 
@@ -188,6 +202,25 @@ impl From<SeccompOpt> for u32 {
     }
 }
 
+impl SeccompOpt {
+    /// Check whether the action is supported by the kernel, e.g. `Log` is supported
+    /// since Linux 4.14.
+    pub fn is_available(self) -> bool {
+        let action: u32 = self.into();
+        // Only the action is checked, without the data in the lower 16 bits.
+        let action = action & !SECCOMP_RET_MASK;
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                SECCOMP_GET_ACTION_AVAIL,
+                0,
+                &action as *const u32,
+            )
+        };
+        ret == 0
+    }
+}
+
 /// The format of BPF programe executes over.
 ///
 /// See: https://elixir.bootlin.com/linux/v4.19.123/source/include/uapi/linux/seccomp.h#L56
@@ -285,7 +318,7 @@ fn handle_process(opt: SeccompOpt) -> Vec<SockFilter> {
 }
 
 /// A wrapper structure of a list of bpf_filters for a syscall's rule.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct BpfRule {
     /// The first bpf_filter to compare syscall number.
     header_rule: SockFilter,
@@ -308,6 +341,11 @@ impl BpfRule {
         }
     }
 
+    /// Get the number of system call the rule is for.
+    pub fn syscall_num(&self) -> i64 {
+        i64::from(self.header_rule.k)
+    }
+
     /// Allow a syscall with arguments limitation in bpf-filter.
     ///
     /// # Arguments
@@ -399,38 +437,84 @@ impl SyscallFilter {
     /// After use this function, all rules in seccomp will take effect whatever
     /// this structure dropped or not. You can only use this function once in
     /// a thread. Otherwise you will get an error.
-    pub fn realize(mut self) -> Result<()> {
+    pub fn realize(self) -> Result<()> {
+        install_filter(&self.finish(), SECCOMP_FILETER_FLAG_TSYNC)
+    }
+
+    /// Register the filter for a kind of threads, which is stacked on the process-wide
+    /// filter. It takes effect after the thread calls `apply_thread_filter`.
+    ///
+    /// # Arguments
+    /// * `kind` - The kind of threads the filter is for.
+    pub fn register_thread_filter(self, kind: ThreadKind) {
+        THREAD_FILTERS.lock().unwrap()[kind as usize] = Some(Arc::new(self.finish()));
+        THREAD_FILTERS_READY.store(true, Ordering::SeqCst);
+    }
+
+    fn finish(mut self) -> Vec<SockFilter> {
         //Add opt as a bpf_filter to sock_filters
         self.sock_filters.append(&mut handle_process(self.opt));
+        self.sock_filters
+    }
+}
 
-        let sock_bpf_vec = self.sock_filters;
+fn install_filter(sock_bpf_vec: &[SockFilter], flags: u32) -> Result<()> {
+    // This operation can guarantee seccomp make use for all users and subprocess.
+    let ret = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+    if ret != 0 {
+        bail!("Seccomp: prctl(2) set no new privs failed.");
+    }
 
-        // This operation can guarantee seccomp make use for all users and subprocess.
-        let ret = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
-        if ret != 0 {
-            bail!("Seccomp: prctl(2) set no new privs failed.");
-        }
+    let prog = SockFProg {
+        len: sock_bpf_vec.len() as u16,
+        sock_filter: sock_bpf_vec.as_ptr(),
+    };
+    let bpf_prog_ptr = &prog as *const SockFProg;
 
-        let prog = SockFProg {
-            len: sock_bpf_vec.len() as u16,
-            sock_filter: sock_bpf_vec.as_ptr(),
-        };
-        let bpf_prog_ptr = &prog as *const SockFProg;
+    // Use seccomp(2) to make bpf rules take effect.
+    let ret = unsafe { libc::syscall(libc::SYS_seccomp, SECCOMP_MODE_FILTER, flags, bpf_prog_ptr) };
+    if ret != 0 {
+        bail!("Seccomp: seccomp(2) set seccomp filter mode failed.");
+    }
 
-        // Use seccomp(2) to make bpf rules take effect.
-        let ret = unsafe {
-            libc::syscall(
-                libc::SYS_seccomp,
-                SECCOMP_MODE_FILTER,
-                SECCOMP_FILETER_FLAG_TSYNC,
-                bpf_prog_ptr,
-            )
-        };
-        if ret != 0 {
-            bail!("Seccomp: seccomp(2) set seccomp filter mode failed.");
-        }
+    Ok(())
+}
+
+/// Kinds of threads which have their own filter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ThreadKind {
+    /// vCPU threads.
+    Vcpu = 0,
+    /// Io-threads.
+    Iothread = 1,
+}
+
+const THREAD_KIND_NUM: usize = ThreadKind::Iothread as usize + 1;
+
+static THREAD_FILTERS: Lazy<Mutex<[Option<Arc<Vec<SockFilter>>>; THREAD_KIND_NUM]>> =
+    Lazy::new(|| Mutex::new(Default::default()));
+static THREAD_FILTERS_READY: AtomicBool = AtomicBool::new(false);
 
-        Ok(())
+thread_local! {
+    static THREAD_FILTER_APPLIED: Cell<bool> = Cell::new(false);
+}
+
+/// Install the filter registered for the kind of threads to the current thread, if it
+/// isn't installed yet. It's cheap when there is nothing to do, so it can be called in
+/// each round of the thread loop.
+///
+/// # Arguments
+/// * `kind` - The kind of the current thread.
+pub fn apply_thread_filter(kind: ThreadKind) -> Result<()> {
+    if !THREAD_FILTERS_READY.load(Ordering::Relaxed) || THREAD_FILTER_APPLIED.with(|a| a.get()) {
+        return Ok(());
+    }
+
+    let filter = THREAD_FILTERS.lock().unwrap()[kind as usize].clone();
+    THREAD_FILTER_APPLIED.with(|a| a.set(true));
+    match filter {
+        Some(filter) => install_filter(&filter, 0),
+        None => Ok(()),
     }
 }
 
@@ -589,4 +673,21 @@ mod tests {
 
         assert_eq!(seccomp_filter.sock_filters, bpf_vec);
     }
+
+    #[test]
+    fn test_thread_filter() {
+        let rule = BpfRule::new(libc::SYS_read).add_constraint(SeccompCmpOpt::Eq, 2, 1024);
+        assert_eq!(rule.clone().syscall_num(), libc::SYS_read);
+
+        // The filter allowing all syscalls is installed only once in the thread.
+        SyscallFilter::new(SeccompOpt::Allow).register_thread_filter(ThreadKind::Iothread);
+        std::thread::spawn(|| {
+            assert!(!THREAD_FILTER_APPLIED.with(|a| a.get()));
+            apply_thread_filter(ThreadKind::Iothread).unwrap();
+            assert!(THREAD_FILTER_APPLIED.with(|a| a.get()));
+            apply_thread_filter(ThreadKind::Iothread).unwrap();
+        })
+        .join()
+        .unwrap();
+    }
 }
@@ -0,0 +1,71 @@
+// Copyright (c) 2023 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// StratoVirt is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+//! LTTng backend, which writes trace records by `tracef` of LTTng-UST. `liblttng-ust`
+//! is loaded at runtime, so StratoVirt doesn't depend on it unless the backend is used.
+
+use std::ffi::CString;
+
+use libc::{c_char, c_void, dlopen, dlsym, RTLD_GLOBAL, RTLD_NOW};
+use log::error;
+use once_cell::sync::Lazy;
+
+/// Soname of LTTng-UST 2.13+ and the older ones.
+const LTTNG_UST_LIBS: [&str; 2] = ["liblttng-ust.so.1", "liblttng-ust.so.0"];
+/// Symbol of `tracef` in LTTng-UST 2.13+ and the older ones.
+const LTTNG_UST_TRACEF_SYMS: [&str; 2] = ["lttng_ust__tracef", "_lttng_ust_tracef"];
+
+type TracefFn = unsafe extern "C" fn(fmt: *const c_char, ...);
+
+static LTTNG_UST_TRACEF: Lazy<Option<TracefFn>> = Lazy::new(load_tracef);
+
+fn load_tracef() -> Option<TracefFn> {
+    for lib in LTTNG_UST_LIBS {
+        let lib_name = CString::new(lib).unwrap();
+        // SAFETY: lib_name is a valid C string. The library is never closed.
+        let handle = unsafe { dlopen(lib_name.as_ptr(), RTLD_NOW | RTLD_GLOBAL) };
+        if handle.is_null() {
+            continue;
+        }
+        for sym in LTTNG_UST_TRACEF_SYMS {
+            let sym_name = CString::new(sym).unwrap();
+            // SAFETY: handle is valid and sym_name is a valid C string.
+            let func = unsafe { dlsym(handle, sym_name.as_ptr()) };
+            if !func.is_null() {
+                // SAFETY: the symbol is the variadic `tracef` function of LTTng-UST.
+                return Some(unsafe { std::mem::transmute::<*mut c_void, TracefFn>(func) });
+            }
+        }
+    }
+    error!("Failed to load tracef from liblttng-ust.");
+    None
+}
+
+pub(super) fn is_available() -> bool {
+    LTTNG_UST_TRACEF.is_some()
+}
+
+pub(super) fn write(event: &str, msg: &str) {
+    let tracef = match LTTNG_UST_TRACEF.as_ref() {
+        Some(tracef) => tracef,
+        None => return,
+    };
+    let record = match CString::new(format!("[{}] {}", event, msg)) {
+        Ok(record) => record,
+        Err(e) => {
+            error!("Invalid trace record of {}: {:?}", event, e);
+            return;
+        }
+    };
+    // SAFETY: both the format and the argument are valid C strings.
+    unsafe { tracef(b"%s\0".as_ptr() as *const c_char, record.as_ptr()) };
+}
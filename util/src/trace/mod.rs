@@ -0,0 +1,286 @@
+//! Trace events are put in StratoVirt by the macro `trace_event!`, which are disabled
+//! by default. The enabled events are written to the selected backend, which is one
+//! of log, ftrace and LTTng.
+
+mod ftrace;
+mod lttng;
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use log::info;
+use once_cell::sync::Lazy;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+static TRACE_EVENTS: Lazy<ArcSwap<HashSet<String>>> =
+    Lazy::new(|| ArcSwap::new(Arc::new(HashSet::new())));
+static TRACE_BACKEND: AtomicU8 = AtomicU8::new(TraceBackend::Ftrace as u8);
+
+/// Backends which the trace records are written to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TraceBackend {
+    /// Log of StratoVirt, with the target `trace`.
+    Log = 0,
+    /// `trace_marker` of kernel ftrace.
+    #[default]
+    Ftrace = 1,
+    /// LTTng userspace tracer, by the `tracef` provider of `liblttng-ust`.
+    Lttng = 2,
+}
+
+impl FromStr for TraceBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "log" => Ok(TraceBackend::Log),
+            "ftrace" => Ok(TraceBackend::Ftrace),
+            "lttng" => Ok(TraceBackend::Lttng),
+            _ => Err(anyhow!("Unknown trace backend {}", s)),
+        }
+    }
+}
+
+impl TraceBackend {
+    fn is_available(&self) -> bool {
+        match self {
+            TraceBackend::Log => true,
+            TraceBackend::Ftrace => ftrace::is_available(),
+            TraceBackend::Lttng => lttng::is_available(),
+        }
+    }
+}
+
+/// Select the backend which the trace records are written to.
+pub fn set_trace_backend(backend: TraceBackend) -> Result<()> {
+    if !backend.is_available() {
+        bail!("Trace backend {:?} is unavailable.", backend);
+    }
+    TRACE_BACKEND.store(backend as u8, Ordering::SeqCst);
+    Ok(())
+}
+
+pub fn get_trace_backend() -> TraceBackend {
+    match TRACE_BACKEND.load(Ordering::Relaxed) {
+        0 => TraceBackend::Log,
+        2 => TraceBackend::Lttng,
+        _ => TraceBackend::Ftrace,
+    }
+}
+
+/// Write the trace record of the event to the selected backend, if the event is enabled.
+pub fn write_trace_event(event: &str, msg: &str) {
+    if !is_trace_event_enabled(event) {
+        return;
+    }
+
+    match get_trace_backend() {
+        TraceBackend::Log => info!(target: "trace", "[{}] {}", event, msg),
+        TraceBackend::Ftrace => ftrace::write(event, msg),
+        TraceBackend::Lttng => lttng::write(event, msg),
+    }
+}
+
+/// Trace point of the event, the remaining arguments are the same as `format!`.
+/// The record is only formatted when the event is enabled.
+#[macro_export]
+macro_rules! trace_event {
+    ($event: ident) => {
+        $crate::trace_event!($event, "")
+    };
+    ($event: ident, $($arg: tt)*) => {
+        if $crate::trace::is_trace_event_enabled(stringify!($event)) {
+            $crate::trace::write_trace_event(
+                stringify!($event),
+                &format!("{}", format_args!($($arg)*)),
+            );
+        }
+    };
+}
+
+pub fn enable_trace_events(file: &str) -> Result<()> {
+    let fd = File::open(file).with_context(|| format!("Failed to open {}.", file))?;
+    let mut reader = BufReader::new(fd);
+
+    loop {
+        let mut buf = String::new();
+        let size = reader
+            .read_line(&mut buf)
+            .with_context(|| format!("Read {} error.", file))?;
+
+        if size == 0 {
+            return Ok(());
+        }
+
+        let mut trace_events = TRACE_EVENTS.load().deref().deref().clone();
+        trace_events.insert(buf.trim().to_string());
+        TRACE_EVENTS.store(Arc::new(trace_events));
+    }
+}
+
+/// Trace events of the MMIO and PIO accesses handled by vcpus.
+pub const IO_ACCESS_EVENTS: [&str; 4] = ["mmio_read", "mmio_write", "pio_read", "pio_write"];
+
+/// Enable the trace events of all the MMIO and PIO accesses, which is set by `-trace-mmio`.
+pub fn enable_io_access_trace() -> Result<()> {
+    IO_ACCESS_EVENTS
+        .iter()
+        .try_for_each(|event| set_trace_event_state(event, true))
+}
+
+pub fn is_trace_event_enabled(event: &str) -> bool {
+    if TRACE_EVENTS.load().is_empty() {
+        return false;
+    }
+
+    TRACE_EVENTS.load().contains(event)
+}
+
+/// Enable or disable the trace events at runtime.
+///
+/// # Arguments
+///
+/// * `pattern` - Event name, `*` matches any characters. The pattern with `*` only
+///   applies to the enabled events, so it can't be used to enable events.
+/// * `enable` - Whether to enable the events.
+pub fn set_trace_event_state(pattern: &str, enable: bool) -> Result<()> {
+    if enable {
+        if pattern.contains('*') {
+            bail!("Wildcard is not supported to enable trace events.");
+        }
+        let backend = get_trace_backend();
+        if !backend.is_available() {
+            bail!(
+                "Failed to enable trace event {}: trace backend {:?} is unavailable.",
+                pattern,
+                backend
+            );
+        }
+    }
+
+    TRACE_EVENTS.rcu(|events| {
+        let mut trace_events = events.deref().clone();
+        if enable {
+            trace_events.insert(pattern.to_string());
+        } else {
+            trace_events.retain(|event| !pattern_match(pattern, event));
+        }
+        trace_events
+    });
+    Ok(())
+}
+
+/// Get the state of trace events. If `pattern` contains `*`, the enabled events
+/// matching the pattern are returned in order.
+pub fn get_trace_events_state(pattern: &str) -> Vec<(String, bool)> {
+    if !pattern.contains('*') {
+        return vec![(pattern.to_string(), is_trace_event_enabled(pattern))];
+    }
+
+    let mut events: Vec<(String, bool)> = TRACE_EVENTS
+        .load()
+        .iter()
+        .filter(|event| pattern_match(pattern, event))
+        .map(|event| (event.clone(), true))
+        .collect();
+    events.sort();
+    events
+}
+
+/// Check whether the event name matches the pattern, `*` in pattern matches any characters.
+fn pattern_match(pattern: &str, name: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+
+    let first = parts[0];
+    let last = parts[parts.len() - 1];
+    if name.len() < first.len() + last.len() || !name.starts_with(first) || !name.ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_match() {
+        assert!(pattern_match("trace_request", "trace_request"));
+        assert!(!pattern_match("trace_request", "trace_requests"));
+        assert!(pattern_match("*", "trace_request"));
+        assert!(pattern_match("trace_*", "trace_request"));
+        assert!(pattern_match("*_request", "trace_request"));
+        assert!(pattern_match("trace*req*", "trace_request"));
+        assert!(!pattern_match("trace*quest*req*", "trace_request"));
+        assert!(!pattern_match("trace_*_request", "trace_request"));
+    }
+
+    #[test]
+    fn test_trace_events_state() {
+        let events: HashSet<String> = ["trace_request", "trace_send_interrupt", "trace_sysbus"]
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        TRACE_EVENTS.store(Arc::new(events));
+
+        assert_eq!(
+            get_trace_events_state("trace_sysbus"),
+            vec![("trace_sysbus".to_string(), true)]
+        );
+        assert_eq!(
+            get_trace_events_state("trace_vm_state"),
+            vec![("trace_vm_state".to_string(), false)]
+        );
+        assert_eq!(
+            get_trace_events_state("trace_*_*"),
+            vec![("trace_send_interrupt".to_string(), true)]
+        );
+
+        assert!(set_trace_event_state("trace_*", true).is_err());
+        set_trace_event_state("trace_s*", false).unwrap();
+        assert_eq!(
+            get_trace_events_state("*"),
+            vec![("trace_request".to_string(), true)]
+        );
+        set_trace_event_state("trace_request", false).unwrap();
+        assert!(get_trace_events_state("*").is_empty());
+    }
+
+    #[test]
+    fn test_trace_backend() {
+        assert_eq!(TraceBackend::from_str("log").unwrap(), TraceBackend::Log);
+        assert_eq!(
+            TraceBackend::from_str("ftrace").unwrap(),
+            TraceBackend::Ftrace
+        );
+        assert_eq!(
+            TraceBackend::from_str("lttng").unwrap(),
+            TraceBackend::Lttng
+        );
+        assert!(TraceBackend::from_str("stderr").is_err());
+
+        // Log backend is always available.
+        set_trace_backend(TraceBackend::Log).unwrap();
+        assert_eq!(get_trace_backend(), TraceBackend::Log);
+        TRACE_BACKEND.store(TraceBackend::Ftrace as u8, Ordering::SeqCst);
+        assert_eq!(get_trace_backend(), TraceBackend::Ftrace);
+    }
+}
@@ -50,6 +50,127 @@ pub fn host_page_size() -> u64 {
     unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 }
 }
 
+/// Set the host CPUs which the thread is allowed to run on.
+///
+/// # Arguments
+///
+/// * `tid` - Thread id, `0` means the calling thread.
+/// * `cpus` - Index of the host CPUs.
+pub fn set_thread_affinity(tid: u64, cpus: &[usize]) -> Result<()> {
+    // SAFETY: cpu_set_t is a plain bitmap.
+    let mut cpu_set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for cpu in cpus {
+        if *cpu >= libc::CPU_SETSIZE as usize {
+            bail!("Host CPU {} is out of range", cpu);
+        }
+        // SAFETY: the cpu index is checked above.
+        unsafe { libc::CPU_SET(*cpu, &mut cpu_set) };
+    }
+    // SAFETY: cpu_set is valid with the size.
+    let ret = unsafe {
+        libc::sched_setaffinity(tid as libc::pid_t, size_of::<libc::cpu_set_t>(), &cpu_set)
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| {
+            format!(
+                "Failed to set affinity of thread {} to host CPUs {:?}",
+                tid, cpus
+            )
+        });
+    }
+    Ok(())
+}
+
+/// Set the nice value of the thread, with the normal scheduling policy.
+///
+/// # Arguments
+///
+/// * `tid` - Thread id, `0` means the calling thread.
+/// * `nice` - Nice value in [-20, 19].
+pub fn set_thread_nice(tid: u64, nice: i32) -> Result<()> {
+    // SAFETY: sched_param is a plain struct.
+    let param: libc::sched_param = unsafe { std::mem::zeroed() };
+    // SAFETY: param is valid.
+    let ret = unsafe { libc::sched_setscheduler(tid as libc::pid_t, libc::SCHED_OTHER, &param) };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to set normal policy of thread {}", tid));
+    }
+    // SAFETY: setpriority has no pointer argument.
+    let ret = unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice) };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to set nice value of thread {} to {}", tid, nice));
+    }
+    Ok(())
+}
+
+/// Set the thread to the real-time FIFO scheduling policy.
+///
+/// # Arguments
+///
+/// * `tid` - Thread id, `0` means the calling thread.
+/// * `priority` - Real-time priority in [1, 99].
+pub fn set_thread_fifo(tid: u64, priority: i32) -> Result<()> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    // SAFETY: param is valid.
+    let ret = unsafe { libc::sched_setscheduler(tid as libc::pid_t, libc::SCHED_FIFO, &param) };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| {
+            format!(
+                "Failed to set fifo policy of thread {} with priority {}",
+                tid, priority
+            )
+        });
+    }
+    Ok(())
+}
+
+/// Raise the soft limit of locked memory `RLIMIT_MEMLOCK` to hold `size` bytes, which
+/// is capped by the hard limit. Return the soft limit after raising, `None` means unlimited.
+///
+/// # Arguments
+///
+/// * `size` - Size of memory to be locked in bytes.
+pub fn raise_memlock_limit(size: u64) -> Result<Option<u64>> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: limit is valid.
+    let ret = unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| "Failed to get the limit of locked memory");
+    }
+    if limit.rlim_cur == libc::RLIM_INFINITY || limit.rlim_cur >= size {
+        return Ok(memlock_limit(limit.rlim_cur));
+    }
+
+    limit.rlim_cur = if limit.rlim_max == libc::RLIM_INFINITY {
+        size
+    } else {
+        std::cmp::min(size, limit.rlim_max)
+    };
+    // SAFETY: limit is valid.
+    let ret = unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit) };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| "Failed to set the limit of locked memory");
+    }
+    Ok(memlock_limit(limit.rlim_cur))
+}
+
+fn memlock_limit(limit: libc::rlim_t) -> Option<u64> {
+    if limit == libc::RLIM_INFINITY {
+        None
+    } else {
+        Some(limit)
+    }
+}
+
 /// Parse unix uri to unix path.
 ///
 /// # Notions
@@ -436,7 +557,10 @@ mod tests {
 
     use libc::{c_void, iovec};
 
-    use super::{parse_unix_uri, UnixSock};
+    use super::{
+        parse_unix_uri, raise_memlock_limit, set_thread_affinity, set_thread_fifo, set_thread_nice,
+        UnixSock,
+    };
 
     #[test]
     fn test_parse_uri() {
@@ -454,6 +578,42 @@ mod tests {
         assert!(parse_unix_uri(test_uri_03).is_err());
     }
 
+    #[test]
+    fn test_set_thread_affinity() {
+        std::thread::spawn(|| {
+            // SAFETY: sched_getcpu has no argument.
+            let cpu = unsafe { libc::sched_getcpu() };
+            assert!(set_thread_affinity(0, &[cpu as usize]).is_ok());
+            // SAFETY: sched_getcpu has no argument.
+            assert_eq!(unsafe { libc::sched_getcpu() }, cpu);
+            assert!(set_thread_affinity(0, &[libc::CPU_SETSIZE as usize]).is_err());
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_set_thread_nice() {
+        std::thread::spawn(|| {
+            // Lowering the priority is always permitted.
+            assert!(set_thread_nice(0, 19).is_ok());
+            // SAFETY: getpriority has no pointer argument.
+            assert_eq!(unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) }, 19);
+            assert!(set_thread_fifo(0, 0).is_err());
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_raise_memlock_limit() {
+        // The limit is not changed if it's large enough.
+        let limit = raise_memlock_limit(0).unwrap();
+        if let Some(limit) = limit {
+            assert_eq!(raise_memlock_limit(limit).unwrap(), Some(limit));
+        }
+    }
+
     #[test]
     fn test_create_unix_socket() {
         let path_name = String::from("test_socket1.sock");
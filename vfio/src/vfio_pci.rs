@@ -1001,6 +1001,10 @@ impl PciDevOps for VfioPciDevice {
         self.name.clone()
     }
 
+    fn get_pci_config(&self) -> Option<&PciConfig> {
+        Some(&self.pci_config)
+    }
+
     fn reset(&mut self, _reset_child_device: bool) -> pci::Result<()> {
         pci::Result::with_context(self.vfio_device.lock().unwrap().reset(), || {
             "Fail to reset vfio dev"
@@ -132,7 +132,7 @@ fn run() -> Result<()> {
     let cmd_args = create_args_parser().get_matches()?;
 
     let logfile_path = cmd_args.value_of("display log").unwrap_or_default();
-    logger::init_log(logfile_path)?;
+    logger::init_log(logfile_path, logger::LogConfig::default())?;
 
     signal_handler::register_kill_signal();
     set_panic_hook();
@@ -9,6 +9,7 @@
 // KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
 // NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
 // See the Mulan PSL v2 for more details.
+use std::collections::HashMap;
 use std::io::Write;
 use std::mem::size_of;
 use std::os::unix::io::{AsRawFd, RawFd};
@@ -17,21 +18,23 @@ use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 use std::{
     cmp::{self, Reverse},
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use address_space::{
     AddressSpace, FlatRange, GuestAddress, Listener, ListenerReqType, RegionIoEventFd, RegionType,
 };
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use log::{error, warn};
 use machine_manager::{
     config::{BalloonConfig, DEFAULT_VIRTQUEUE_SIZE},
     event,
     event_loop::{register_event_helper, unregister_event_helper},
-    qmp::qmp_schema::BalloonInfo,
+    qmp::qmp_schema::{BalloonInfo, BalloonStats},
     qmp::QmpChannel,
 };
+use migration::{DeviceStateDesc, FieldDesc, MigrationHook, MigrationManager, StateTransfer};
+use migration_derive::{ByteCode, Desc};
 use util::{
     bitmap::Bitmap,
     byte_code::ByteCode,
@@ -50,6 +53,7 @@ use crate::{
     VirtioInterrupt, VirtioInterruptType, VirtioTrace, VIRTIO_F_VERSION_1, VIRTIO_TYPE_BALLOON,
 };
 
+const VIRTIO_BALLOON_F_STATS_VQ: u32 = 1;
 const VIRTIO_BALLOON_F_DEFLATE_ON_OOM: u32 = 2;
 const VIRTIO_BALLOON_F_REPORTING: u32 = 5;
 /// The feature for Auto-balloon
@@ -62,6 +66,21 @@ const BALLOON_DEFLATE_EVENT: bool = false;
 const IN_IOVEC: bool = true;
 const OUT_IOVEC: bool = false;
 const BITS_OF_TYPE_U64: u64 = 64;
+/// Size of transparent huge page if it can't be read from host.
+const DEFAULT_THP_SIZE: u64 = 2 * 1024 * 1024;
+const THP_SIZE_FILE: &str = "/sys/kernel/mm/transparent_hugepage/hpage_pmd_size";
+
+/// Tags of guest memory statistics reported by the stats queue.
+const VIRTIO_BALLOON_S_SWAP_IN: u16 = 0;
+const VIRTIO_BALLOON_S_SWAP_OUT: u16 = 1;
+const VIRTIO_BALLOON_S_MAJFLT: u16 = 2;
+const VIRTIO_BALLOON_S_MINFLT: u16 = 3;
+const VIRTIO_BALLOON_S_MEMFREE: u16 = 4;
+const VIRTIO_BALLOON_S_MEMTOT: u16 = 5;
+const VIRTIO_BALLOON_S_AVAIL: u16 = 6;
+const VIRTIO_BALLOON_S_CACHES: u16 = 7;
+const VIRTIO_BALLOON_S_HTLB_PGALLOC: u16 = 8;
+const VIRTIO_BALLOON_S_HTLB_PGFAIL: u16 = 9;
 
 static mut BALLOON_DEV: Option<Arc<Mutex<Balloon>>> = None;
 
@@ -75,10 +94,9 @@ struct GuestIovec {
 }
 
 #[derive(Clone, Copy, Default)]
-#[allow(dead_code)]
 #[repr(packed(1))]
 struct BalloonStat {
-    _tag: u16,
+    tag: u16,
     val: u64,
 }
 
@@ -180,6 +198,45 @@ fn memory_advise(addr: *mut libc::c_void, len: libc::size_t, advice: libc::c_int
         );
     }
 }
+/// Get the size of transparent huge page of host.
+fn thp_size() -> u64 {
+    std::fs::read_to_string(THP_SIZE_FILE)
+        .ok()
+        .and_then(|size| size.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_THP_SIZE)
+}
+
+/// Drop the pages in memory with transparent huge page advised, whose huge page is not
+/// ballooned entirely, so that discarding them doesn't split the huge page.
+///
+/// # Arguments
+///
+/// * `hvaset` - Host addresses of ballooned pages sorted in descending order.
+/// * `thp_ranges` - Host address ranges of memory with transparent huge page advised.
+/// * `thp_size` - Size of transparent huge page.
+fn filter_thp_pages(hvaset: &mut Vec<(u64, bool)>, thp_ranges: &[(u64, u64)], thp_size: u64) {
+    if thp_ranges.is_empty() {
+        return;
+    }
+    let in_thp = |hva: u64| {
+        thp_ranges
+            .iter()
+            .any(|(start, size)| hva >= *start && hva < *start + *size)
+    };
+    hvaset.dedup_by_key(|page| page.0);
+
+    let mut pages_in_thp: HashMap<u64, u64> = HashMap::new();
+    for (hva, _) in hvaset.iter() {
+        if in_thp(*hva) {
+            *pages_in_thp.entry(*hva / thp_size).or_insert(0) += 1;
+        }
+    }
+    let pages_per_thp = thp_size / BALLOON_PAGE_SIZE;
+    hvaset.retain(|(hva, _)| {
+        !in_thp(*hva) || pages_in_thp.get(&(*hva / thp_size)) == Some(&pages_per_thp)
+    });
+}
+
 struct Request {
     /// The index of descriptor for the request.
     desc_index: u16,
@@ -293,6 +350,10 @@ impl Request {
             self.balloon_deflate_page(&mut hvaset);
             return;
         }
+        let thp_ranges = mem.lock().unwrap().get_thp_ranges();
+        if !thp_ranges.is_empty() {
+            filter_thp_pages(&mut hvaset, &thp_ranges, thp_size());
+        }
 
         let host_page_size = host_page_size();
         let mut advice = 0;
@@ -419,6 +480,8 @@ struct BlnMemoryRegion {
     reg_page_size: Option<u64>,
     /// Region shared or not
     mem_share: bool,
+    /// Transparent huge page is advised for region or not
+    thp: bool,
 }
 
 struct BlnMemInfo {
@@ -450,6 +513,17 @@ impl BlnMemInfo {
         None
     }
 
+    /// Get the host address ranges of regions with transparent huge page advised.
+    fn get_thp_ranges(&self) -> Vec<(u64, u64)> {
+        self.regions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|reg| reg.thp)
+            .map(|reg| (reg.userspace_addr, reg.memory_size))
+            .collect()
+    }
+
     fn has_huge_page(&self) -> bool {
         let all_regions = self.regions.lock().unwrap();
         for reg in all_regions.iter() {
@@ -475,6 +549,7 @@ impl BlnMemInfo {
                 flags_padding: 0_u64,
                 reg_page_size,
                 mem_share: fr.owner.get_host_share().unwrap_or(false),
+                thp: fr.owner.get_host_thp().unwrap_or(false),
             });
         } else {
             error!("Failed to get host address!");
@@ -492,6 +567,7 @@ impl BlnMemInfo {
                 flags_padding: 0_u64,
                 reg_page_size,
                 mem_share: false,
+                thp: false,
             };
             for (index, mr) in mem_regions.iter().enumerate() {
                 if mr.guest_phys_addr == target.guest_phys_addr
@@ -584,6 +660,17 @@ struct BalloonIoHandler {
     msg_queue: Option<Arc<Mutex<Queue>>>,
     /// Auto balloon msg EventFd.
     msg_evt: Option<Arc<EventFd>>,
+    /// Stats queue.
+    stats_queue: Option<Arc<Mutex<Queue>>>,
+    /// Stats EventFd.
+    stats_evt: Option<Arc<EventFd>>,
+    /// Descriptor index of the stats buffer hold by device, which is returned to
+    /// guest to request new statistics.
+    stats_desc_index: Option<u16>,
+    /// Timer to request guest memory statistics periodically.
+    stats_timer: Arc<Mutex<TimerFd>>,
+    /// Guest memory statistics.
+    stats: Arc<Mutex<BalloonStats>>,
     /// Device is broken or not.
     device_broken: Arc<AtomicBool>,
     /// The interrupt call back function.
@@ -720,12 +807,79 @@ impl BalloonIoHandler {
         Ok(())
     }
 
+    fn stats_evt_handler(&mut self) -> Result<()> {
+        let queue = self
+            .stats_queue
+            .as_ref()
+            .with_context(|| VirtioError::VirtQueueIsNone)?;
+        let mut locked_queue = queue.lock().unwrap();
+
+        loop {
+            let elem = locked_queue
+                .vring
+                .pop_avail(&self.mem_space, self.driver_features)
+                .with_context(|| "Failed to pop avail ring for guest memory statistics")?;
+
+            if elem.desc_num == 0 {
+                break;
+            }
+            let req = Request::parse(&elem, OUT_IOVEC)
+                .with_context(|| "Fail to parse available descriptor chain")?;
+            let mut locked_stats = self.stats.lock().unwrap();
+            for iov in req.iovec.iter() {
+                let mut offset = 0;
+                while let Some(stat) = iov_to_buf::<BalloonStat>(&self.mem_space, iov, offset) {
+                    update_guest_stats(&mut locked_stats, stat.tag, stat.val);
+                    offset += size_of::<BalloonStat>() as u64;
+                }
+            }
+            locked_stats.last_update = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |t| t.as_secs());
+            drop(locked_stats);
+
+            // Guest only uses one buffer for statistics, return the old one if any.
+            if let Some(desc_index) = self.stats_desc_index.replace(req.desc_index) {
+                locked_queue
+                    .vring
+                    .add_used(&self.mem_space, desc_index, 0)
+                    .with_context(|| "Failed to add balloon response into used queue")?;
+                (self.interrupt_cb)(&VirtioInterruptType::Vring, Some(&locked_queue), false)
+                    .with_context(|| {
+                        VirtioError::InterruptTrigger("balloon", VirtioInterruptType::Vring)
+                    })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the stats buffer to guest to request new guest memory statistics.
+    fn request_guest_stats(&mut self) -> Result<()> {
+        let desc_index = match self.stats_desc_index.take() {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let queue = self
+            .stats_queue
+            .as_ref()
+            .with_context(|| VirtioError::VirtQueueIsNone)?;
+        let mut locked_queue = queue.lock().unwrap();
+        locked_queue
+            .vring
+            .add_used(&self.mem_space, desc_index, 0)
+            .with_context(|| "Failed to add balloon response into used queue")?;
+        (self.interrupt_cb)(&VirtioInterruptType::Vring, Some(&locked_queue), false)
+            .with_context(|| VirtioError::InterruptTrigger("balloon", VirtioInterruptType::Vring))
+    }
+
     /// Send balloon changed event.
     fn send_balloon_changed_event(&self) {
         let ram_size = self.mem_info.lock().unwrap().get_ram_size();
         let balloon_size = self.get_balloon_memory_size();
         let msg = BalloonInfo {
             actual: ram_size - balloon_size,
+            stats: None,
         };
         event!(BalloonChanged; msg);
     }
@@ -736,6 +890,24 @@ impl BalloonIoHandler {
     }
 }
 
+/// Update guest memory statistics by the stat reported from guest, the memory
+/// sizes are reported in bytes by guest.
+fn update_guest_stats(stats: &mut BalloonStats, tag: u16, val: u64) {
+    match tag {
+        VIRTIO_BALLOON_S_SWAP_IN => stats.swap_in = Some(val),
+        VIRTIO_BALLOON_S_SWAP_OUT => stats.swap_out = Some(val),
+        VIRTIO_BALLOON_S_MAJFLT => stats.major_faults = Some(val),
+        VIRTIO_BALLOON_S_MINFLT => stats.minor_faults = Some(val),
+        VIRTIO_BALLOON_S_MEMFREE => stats.free_memory = Some(val),
+        VIRTIO_BALLOON_S_MEMTOT => stats.total_memory = Some(val),
+        VIRTIO_BALLOON_S_AVAIL => stats.available_memory = Some(val),
+        VIRTIO_BALLOON_S_CACHES => stats.disk_caches = Some(val),
+        VIRTIO_BALLOON_S_HTLB_PGALLOC => stats.htlb_pgalloc = Some(val),
+        VIRTIO_BALLOON_S_HTLB_PGFAIL => stats.htlb_pgfail = Some(val),
+        _ => warn!("Unknown balloon stats tag {}", tag),
+    }
+}
+
 /// Create a new EventNotifier.
 ///
 /// # Arguments
@@ -847,6 +1019,50 @@ impl EventNotifierHelper for BalloonIoHandler {
             notifiers.push(build_event_notifier(msg_evt.as_raw_fd(), handler));
         }
 
+        if let Some(stats_evt) = locked_balloon_io.stats_evt.as_ref() {
+            let cloned_balloon_io = balloon_io.clone();
+            let handler: Rc<NotifierCallback> = Rc::new(move |_, fd: RawFd| {
+                read_fd(fd);
+                let mut locked_balloon_io = cloned_balloon_io.lock().unwrap();
+                if locked_balloon_io.device_broken.load(Ordering::SeqCst) {
+                    return None;
+                }
+                if let Err(e) = locked_balloon_io.stats_evt_handler() {
+                    error!("Failed to get guest memory statistics: {:?}", e);
+                    report_virtio_error(
+                        locked_balloon_io.interrupt_cb.clone(),
+                        locked_balloon_io.driver_features,
+                        &locked_balloon_io.device_broken,
+                    );
+                }
+                None
+            });
+            notifiers.push(build_event_notifier(stats_evt.as_raw_fd(), handler));
+
+            // register event notifier for stats polling timer event.
+            let cloned_balloon_io = balloon_io.clone();
+            let handler: Rc<NotifierCallback> = Rc::new(move |_, fd: RawFd| {
+                read_fd(fd);
+                let mut locked_balloon_io = cloned_balloon_io.lock().unwrap();
+                if locked_balloon_io.device_broken.load(Ordering::SeqCst) {
+                    return None;
+                }
+                if let Err(e) = locked_balloon_io.request_guest_stats() {
+                    error!("Failed to request guest memory statistics: {:?}", e);
+                    report_virtio_error(
+                        locked_balloon_io.interrupt_cb.clone(),
+                        locked_balloon_io.driver_features,
+                        &locked_balloon_io.device_broken,
+                    );
+                }
+                None
+            });
+            notifiers.push(build_event_notifier(
+                locked_balloon_io.stats_timer.lock().unwrap().as_raw_fd(),
+                handler,
+            ));
+        }
+
         // register event notifier for timer event.
         let cloned_balloon_io = balloon_io.clone();
         let handler: Rc<NotifierCallback> = Rc::new(move |_, fd: RawFd| {
@@ -873,6 +1089,21 @@ impl EventNotifierHelper for BalloonIoHandler {
 }
 
 /// A balloon device with some necessary information.
+/// State of balloon device.
+#[repr(C)]
+#[derive(Clone, Copy, Desc, ByteCode)]
+#[desc_version(compat_version = "0.1.0")]
+pub struct BalloonState {
+    /// Bitmask of features supported by the backend.
+    device_features: u64,
+    /// Bitmask of features negotiated by the backend and the frontend.
+    driver_features: u64,
+    /// Target memory pages of balloon device.
+    num_pages: u32,
+    /// Actual memory pages of balloon device.
+    actual: u32,
+}
+
 pub struct Balloon {
     /// Balloon device features.
     device_features: u64,
@@ -897,6 +1128,12 @@ pub struct Balloon {
     /// For auto balloon
     membuf_percent: u32,
     monitor_interval: u32,
+    /// Interval(second) to request guest memory statistics, 0 means no polling.
+    stats_interval: u64,
+    /// Timer to request guest memory statistics periodically.
+    stats_timer: Arc<Mutex<TimerFd>>,
+    /// Guest memory statistics.
+    stats: Arc<Mutex<BalloonStats>>,
 }
 
 impl Balloon {
@@ -916,6 +1153,9 @@ impl Balloon {
         if bln_cfg.auto_balloon {
             device_features |= 1u64 << VIRTIO_BALLOON_F_MESSAGE_VQ;
         }
+        if bln_cfg.guest_stats {
+            device_features |= 1u64 << VIRTIO_BALLOON_F_STATS_VQ;
+        }
 
         Balloon {
             device_features,
@@ -930,6 +1170,9 @@ impl Balloon {
             broken: Arc::new(AtomicBool::new(false)),
             membuf_percent: bln_cfg.membuf_percent,
             monitor_interval: bln_cfg.monitor_interval,
+            stats_interval: bln_cfg.stats_polling_interval as u64,
+            stats_timer: Arc::new(Mutex::new(TimerFd::new().unwrap())),
+            stats: Arc::new(Mutex::new(BalloonStats::default())),
         }
     }
 
@@ -978,6 +1221,7 @@ impl Balloon {
         })?;
         let msg = BalloonInfo {
             actual: self.get_guest_memory_size(),
+            stats: None,
         };
         event!(BalloonChanged; msg);
         Ok(())
@@ -995,6 +1239,48 @@ impl Balloon {
     pub fn set_num_pages(&mut self, target: u32) {
         self.num_pages = target;
     }
+
+    /// Set the interval to request guest memory statistics.
+    ///
+    /// # Argument
+    ///
+    /// * `interval` - Polling interval in seconds, 0 stops polling.
+    pub fn set_stats_interval(&mut self, interval: u64) -> Result<()> {
+        if !virtio_has_feature(self.device_features, VIRTIO_BALLOON_F_STATS_VQ) {
+            bail!("Guest memory statistics is not enabled for balloon device");
+        }
+        self.stats_interval = interval;
+        if self.interrupt_cb.is_some() {
+            self.arm_stats_timer()?;
+        }
+        Ok(())
+    }
+
+    fn arm_stats_timer(&self) -> Result<()> {
+        let mut timer = self.stats_timer.lock().unwrap();
+        if self.stats_interval == 0 {
+            timer
+                .clear()
+                .with_context(|| "Failed to stop timer for guest memory statistics")
+        } else {
+            let interval = Duration::from_secs(self.stats_interval);
+            timer
+                .reset(interval, Some(interval))
+                .with_context(|| "Failed to start timer for guest memory statistics")
+        }
+    }
+
+    /// Get the guest memory statistics, `None` if guest doesn't report statistics.
+    fn get_guest_stats(&self) -> Option<BalloonStats> {
+        if !virtio_has_feature(self.driver_features, VIRTIO_BALLOON_F_STATS_VQ) {
+            return None;
+        }
+        let stats = self.stats.lock().unwrap();
+        if stats.last_update == 0 {
+            return None;
+        }
+        Some(stats.clone())
+    }
 }
 
 impl VirtioDevice for Balloon {
@@ -1014,6 +1300,9 @@ impl VirtioDevice for Balloon {
     /// Get the number of balloon-device queues.
     fn queue_num(&self) -> usize {
         let mut queue_num = QUEUE_NUM_BALLOON;
+        if virtio_has_feature(self.device_features, VIRTIO_BALLOON_F_STATS_VQ) {
+            queue_num += 1;
+        }
         if virtio_has_feature(self.device_features, VIRTIO_BALLOON_F_REPORTING) {
             queue_num += 1;
         }
@@ -1111,6 +1400,14 @@ impl VirtioDevice for Balloon {
         }
         self.actual.store(new_actual, Ordering::Release);
 
+        // Notify the balloon target is reached.
+        if old_actual != new_actual && new_actual == self.num_pages {
+            let msg = BalloonInfo {
+                actual: self.get_guest_memory_size(),
+            };
+            event!(BalloonChange; msg);
+        }
+
         Ok(())
     }
 
@@ -1142,8 +1439,17 @@ impl VirtioDevice for Balloon {
         let def_queue = queues[1].clone();
         let def_evt = queue_evts[1].clone();
 
-        // Get report queue and eventfd.
+        // Get stats queue and eventfd.
         let mut queue_index = 2;
+        let mut stats_queue = None;
+        let mut stats_evt = None;
+        if virtio_has_feature(self.device_features, VIRTIO_BALLOON_F_STATS_VQ) {
+            stats_queue = Some(queues[queue_index].clone());
+            stats_evt = Some(queue_evts[queue_index].clone());
+            queue_index += 1;
+        }
+
+        // Get report queue and eventfd.
         let mut report_queue = None;
         let mut report_evt = None;
         if virtio_has_feature(self.device_features, VIRTIO_BALLOON_F_REPORTING) {
@@ -1172,6 +1478,11 @@ impl VirtioDevice for Balloon {
             report_evt,
             msg_queue,
             msg_evt,
+            stats_queue,
+            stats_evt,
+            stats_desc_index: None,
+            stats_timer: self.stats_timer.clone(),
+            stats: self.stats.clone(),
             device_broken: self.broken.clone(),
             interrupt_cb,
             mem_info: self.mem_info.clone(),
@@ -1182,6 +1493,9 @@ impl VirtioDevice for Balloon {
         let notifiers = EventNotifierHelper::internal_notifiers(Arc::new(Mutex::new(handler)));
         register_event_helper(notifiers, None, &mut self.deactivate_evts)
             .with_context(|| "Failed to register balloon event notifier to MainLoop")?;
+        if virtio_has_feature(self.device_features, VIRTIO_BALLOON_F_STATS_VQ) {
+            self.arm_stats_timer()?;
+        }
         self.broken.store(false, Ordering::SeqCst);
 
         Ok(())
@@ -1203,6 +1517,35 @@ impl VirtioDevice for Balloon {
     }
 }
 
+impl StateTransfer for Balloon {
+    fn get_state_vec(&self) -> migration::Result<Vec<u8>> {
+        let state = BalloonState {
+            device_features: self.device_features,
+            driver_features: self.driver_features,
+            num_pages: self.num_pages,
+            actual: self.actual.load(Ordering::Acquire),
+        };
+        Ok(state.as_bytes().to_vec())
+    }
+
+    fn set_state_mut(&mut self, state: &[u8]) -> migration::Result<()> {
+        let state = BalloonState::from_bytes(state)
+            .with_context(|| migration::error::MigrationError::FromBytesError("BALLOON"))?;
+        self.device_features = state.device_features;
+        self.driver_features = state.driver_features;
+        self.num_pages = state.num_pages;
+        self.actual.store(state.actual, Ordering::Release);
+
+        Ok(())
+    }
+
+    fn get_device_alias(&self) -> u64 {
+        MigrationManager::get_desc_alias(&BalloonState::descriptor().name).unwrap_or(!0)
+    }
+}
+
+impl MigrationHook for Balloon {}
+
 pub fn qmp_balloon(target: u64) -> bool {
     // Safe, because there is no confliction when writing global variable BALLOON_DEV, in other words,
     // this function will not be called simultaneously.
@@ -1221,16 +1564,28 @@ pub fn qmp_balloon(target: u64) -> bool {
     false
 }
 
-pub fn qmp_query_balloon() -> Option<u64> {
+pub fn qmp_query_balloon() -> Option<BalloonInfo> {
     // Safe, because there is no confliction when writing global variable BALLOON_DEV, in other words,
     // this function will not be called simultaneously.
     if let Some(dev) = unsafe { &BALLOON_DEV } {
         let unlocked_dev = dev.lock().unwrap();
-        return Some(unlocked_dev.get_guest_memory_size());
+        return Some(BalloonInfo {
+            actual: unlocked_dev.get_guest_memory_size(),
+            stats: unlocked_dev.get_guest_stats(),
+        });
     }
     None
 }
 
+pub fn qmp_balloon_set_stats_interval(interval: u64) -> Result<()> {
+    // Safe, because there is no confliction when writing global variable BALLOON_DEV, in other words,
+    // this function will not be called simultaneously.
+    if let Some(dev) = unsafe { &BALLOON_DEV } {
+        return dev.lock().unwrap().set_stats_interval(interval);
+    }
+    bail!("Balloon device not configured");
+}
+
 /// Create a syscall bpf rule for device `Balloon`.
 pub fn balloon_allow_list(syscall_allow_list: &mut Vec<BpfRule>) {
     syscall_allow_list.extend(vec![
@@ -1301,6 +1656,8 @@ mod tests {
             auto_balloon: false,
             membuf_percent: 0,
             monitor_interval: 0,
+            guest_stats: false,
+            stats_polling_interval: 0,
         };
 
         let mem_space = address_space_init();
@@ -1350,6 +1707,8 @@ mod tests {
             auto_balloon: false,
             membuf_percent: 0,
             monitor_interval: 0,
+            guest_stats: false,
+            stats_polling_interval: 0,
         };
 
         let mem_space = address_space_init();
@@ -1372,6 +1731,8 @@ mod tests {
             auto_balloon: false,
             membuf_percent: 0,
             monitor_interval: 0,
+            guest_stats: false,
+            stats_polling_interval: 0,
         };
 
         let mem_space = address_space_init();
@@ -1394,6 +1755,8 @@ mod tests {
             auto_balloon: false,
             membuf_percent: 0,
             monitor_interval: 0,
+            guest_stats: false,
+            stats_polling_interval: 0,
         };
 
         let mem_space = address_space_init();
@@ -1415,6 +1778,8 @@ mod tests {
             auto_balloon: false,
             membuf_percent: 0,
             monitor_interval: 0,
+            guest_stats: false,
+            stats_polling_interval: 0,
         };
 
         let mem_space = address_space_init();
@@ -1436,6 +1801,8 @@ mod tests {
             auto_balloon: false,
             membuf_percent: 0,
             monitor_interval: 0,
+            guest_stats: false,
+            stats_polling_interval: 0,
         };
         let mut bln = Balloon::new(&bln_cfg, mem_space.clone());
         bln.realize().unwrap();
@@ -1513,6 +1880,11 @@ mod tests {
             report_evt: None,
             msg_queue: None,
             msg_evt: None,
+            stats_queue: None,
+            stats_evt: None,
+            stats_desc_index: None,
+            stats_timer: bln.stats_timer.clone(),
+            stats: bln.stats.clone(),
             device_broken: bln.broken.clone(),
             interrupt_cb: cb.clone(),
             mem_info: bln.mem_info.clone(),
@@ -1524,7 +1896,10 @@ mod tests {
         Balloon::object_init(balloon);
 
         // Query balloon.
-        assert_eq!(qmp_query_balloon(), Some(MEMORY_SIZE));
+        assert_eq!(
+            qmp_query_balloon().map(|info| info.actual),
+            Some(MEMORY_SIZE)
+        );
 
         // Create SplitVringDesc and set addr to be 0x2000.
         let desc = SplitVringDesc {
@@ -1555,7 +1930,10 @@ mod tests {
 
         assert!(handler.process_balloon_queue(BALLOON_INFLATE_EVENT).is_ok());
         assert_eq!(handler.get_balloon_memory_size(), 0);
-        assert_eq!(qmp_query_balloon(), Some(MEMORY_SIZE));
+        assert_eq!(
+            qmp_query_balloon().map(|info| info.actual),
+            Some(MEMORY_SIZE)
+        );
 
         // SplitVringDesc for deflate.
         let desc = SplitVringDesc {
@@ -1620,6 +1998,8 @@ mod tests {
             auto_balloon: false,
             membuf_percent: 0,
             monitor_interval: 0,
+            guest_stats: false,
+            stats_polling_interval: 0,
         };
         let mut bln = Balloon::new(&bln_cfg, mem_space.clone());
         assert!(bln
@@ -1681,6 +2061,35 @@ mod tests {
         assert!(!btp.is_full(65));
     }
 
+    #[test]
+    fn test_filter_thp_pages() {
+        let thp_size = 4 * BALLOON_PAGE_SIZE;
+        // The 1st huge page is ballooned entirely, the 2nd one is ballooned partially.
+        let mut hvaset: Vec<(u64, bool)> = (0..6).map(|i| (i * BALLOON_PAGE_SIZE, false)).collect();
+        // Pages out of memory with transparent huge page advised.
+        hvaset.push((thp_size * 4, false));
+        hvaset.push((thp_size * 4 + BALLOON_PAGE_SIZE, false));
+        hvaset.sort_by_key(|&b| Reverse(b.0));
+
+        let mut pages = hvaset.clone();
+        filter_thp_pages(&mut pages, &[], thp_size);
+        assert_eq!(pages, hvaset);
+
+        filter_thp_pages(&mut hvaset, &[(0, thp_size * 2)], thp_size);
+        let hvas: Vec<u64> = hvaset.iter().map(|page| page.0).collect();
+        assert_eq!(
+            hvas,
+            vec![
+                thp_size * 4 + BALLOON_PAGE_SIZE,
+                thp_size * 4,
+                BALLOON_PAGE_SIZE * 3,
+                BALLOON_PAGE_SIZE * 2,
+                BALLOON_PAGE_SIZE,
+                0
+            ]
+        );
+    }
+
     #[test]
     fn test_balloon_init_free_page_reporting() {
         let bln_cfg = BalloonConfig {
@@ -1690,6 +2099,8 @@ mod tests {
             auto_balloon: false,
             membuf_percent: 0,
             monitor_interval: 0,
+            guest_stats: false,
+            stats_polling_interval: 0,
         };
         let mem_space = address_space_init();
         let mut bln = Balloon::new(&bln_cfg, mem_space);
@@ -1717,4 +2128,41 @@ mod tests {
 
         assert!(bln.update_config(None).is_err());
     }
+
+    #[test]
+    fn test_balloon_guest_stats() {
+        let mut bln_cfg = BalloonConfig {
+            id: "bln".to_string(),
+            deflate_on_oom: false,
+            free_page_reporting: false,
+            auto_balloon: false,
+            membuf_percent: 0,
+            monitor_interval: 0,
+            guest_stats: false,
+            stats_polling_interval: 0,
+        };
+        let mem_space = address_space_init();
+        let mut bln = Balloon::new(&bln_cfg, mem_space.clone());
+        assert!(bln.set_stats_interval(5).is_err());
+
+        bln_cfg.guest_stats = true;
+        let mut bln = Balloon::new(&bln_cfg, mem_space);
+        assert_eq!(bln.queue_num(), 3);
+        // The timer is armed after the device is activated.
+        assert!(bln.set_stats_interval(5).is_ok());
+        assert!(!bln.stats_timer.lock().unwrap().is_armed().unwrap());
+        assert!(bln.get_guest_stats().is_none());
+
+        let mut stats = bln.stats.lock().unwrap();
+        update_guest_stats(&mut stats, VIRTIO_BALLOON_S_MEMFREE, 1 << 20);
+        update_guest_stats(&mut stats, VIRTIO_BALLOON_S_MAJFLT, 10);
+        update_guest_stats(&mut stats, 100, 1);
+        stats.last_update = 1;
+        drop(stats);
+        bln.driver_features = 1u64 << VIRTIO_BALLOON_F_STATS_VQ;
+        let stats = bln.get_guest_stats().unwrap();
+        assert_eq!(stats.free_memory, Some(1 << 20));
+        assert_eq!(stats.major_faults, Some(10));
+        assert!(stats.swap_in.is_none());
+    }
 }
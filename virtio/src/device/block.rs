@@ -1564,6 +1564,7 @@ mod tests {
         // spawn io thread
         let io_conf = IothreadConfig {
             id: thread_name.clone(),
+            ..Default::default()
         };
         EventLoop::object_init(&Some(vec![io_conf])).unwrap();
 
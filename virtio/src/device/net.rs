@@ -19,14 +19,16 @@ use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::{cmp, fs, mem};
 
 use crate::{
     iov_discard_front, iov_to_buf, mem_to_buf, report_virtio_error, virtio_has_feature, ElemIovec,
-    Element, Queue, VirtioDevice, VirtioError, VirtioInterrupt, VirtioInterruptType, VirtioNetHdr,
-    VirtioTrace, VIRTIO_F_RING_EVENT_IDX, VIRTIO_F_RING_INDIRECT_DESC, VIRTIO_F_VERSION_1,
-    VIRTIO_NET_CTRL_MAC, VIRTIO_NET_CTRL_MAC_ADDR_SET, VIRTIO_NET_CTRL_MAC_TABLE_SET,
-    VIRTIO_NET_CTRL_MQ, VIRTIO_NET_CTRL_MQ_VQ_PAIRS_MAX, VIRTIO_NET_CTRL_MQ_VQ_PAIRS_MIN,
+    Element, NotifyCoalescing, Queue, VirtioDevice, VirtioError, VirtioInterrupt,
+    VirtioInterruptType, VirtioNetHdr, VirtioTrace, VIRTIO_F_RING_EVENT_IDX,
+    VIRTIO_F_RING_INDIRECT_DESC, VIRTIO_F_VERSION_1, VIRTIO_NET_CTRL_MAC,
+    VIRTIO_NET_CTRL_MAC_ADDR_SET, VIRTIO_NET_CTRL_MAC_TABLE_SET, VIRTIO_NET_CTRL_MQ,
+    VIRTIO_NET_CTRL_MQ_VQ_PAIRS_MAX, VIRTIO_NET_CTRL_MQ_VQ_PAIRS_MIN,
     VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET, VIRTIO_NET_CTRL_RX, VIRTIO_NET_CTRL_RX_ALLMULTI,
     VIRTIO_NET_CTRL_RX_ALLUNI, VIRTIO_NET_CTRL_RX_NOBCAST, VIRTIO_NET_CTRL_RX_NOMULTI,
     VIRTIO_NET_CTRL_RX_NOUNI, VIRTIO_NET_CTRL_RX_PROMISC, VIRTIO_NET_CTRL_VLAN,
@@ -35,7 +37,8 @@ use crate::{
     VIRTIO_NET_F_CTRL_VLAN, VIRTIO_NET_F_CTRL_VQ, VIRTIO_NET_F_GUEST_CSUM, VIRTIO_NET_F_GUEST_ECN,
     VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_TSO6, VIRTIO_NET_F_GUEST_UFO,
     VIRTIO_NET_F_HOST_TSO4, VIRTIO_NET_F_HOST_TSO6, VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC,
-    VIRTIO_NET_F_MQ, VIRTIO_NET_OK, VIRTIO_TYPE_NET,
+    VIRTIO_NET_F_MQ, VIRTIO_NET_F_STANDBY, VIRTIO_NET_F_STATUS, VIRTIO_NET_OK,
+    VIRTIO_NET_S_LINK_UP, VIRTIO_TYPE_NET,
 };
 use address_space::{AddressSpace, RegionCache};
 use anyhow::{anyhow, bail, Context, Result};
@@ -699,6 +702,8 @@ struct NetIoHandler {
     is_listening: bool,
     ctrl_info: Arc<Mutex<CtrlInfo>>,
     queue_size: u16,
+    iothread: Option<String>,
+    link_up: Arc<AtomicBool>,
 }
 
 impl NetIoHandler {
@@ -763,8 +768,20 @@ impl NetIoHandler {
         iovecs
     }
 
+    /// Drop all the packets received from tap, which is used when the link is down.
+    fn drop_rx_packets(&mut self) {
+        if let Some(tap) = self.tap.as_mut() {
+            let mut buf = vec![0_u8; u16::MAX as usize];
+            while tap.read(&mut buf).is_ok() {}
+        }
+    }
+
     fn handle_rx(&mut self) -> Result<()> {
         self.trace_request("Net".to_string(), "to rx".to_string());
+        if !self.link_up.load(Ordering::SeqCst) {
+            self.drop_rx_packets();
+            return Ok(());
+        }
         let mut queue = self.rx.queue.lock().unwrap();
 
         let mut rx_packets = 0;
@@ -831,10 +848,7 @@ impl NetIoHandler {
                     )
                 })?;
 
-            if queue
-                .vring
-                .should_notify(&self.mem_space, self.driver_features)
-            {
+            if queue.should_notify_coalesced(&self.mem_space, self.driver_features) {
                 (self.interrupt_cb)(&VirtioInterruptType::Vring, Some(&queue), false)
                     .with_context(|| {
                         VirtioError::InterruptTrigger("net", VirtioInterruptType::Vring)
@@ -852,7 +866,7 @@ impl NetIoHandler {
             }
         }
 
-        Ok(())
+        self.flush_notify(&self.rx.queue, &mut queue)
     }
 
     fn send_packets(&self, tap_fd: libc::c_int, iovecs: &[libc::iovec]) -> i8 {
@@ -900,17 +914,17 @@ impl NetIoHandler {
                 queue.vring.get_cache(),
                 &elem.out_iovec,
             );
-            let tap_fd = if let Some(tap) = self.tap.as_mut() {
-                tap.as_raw_fd() as libc::c_int
-            } else {
-                -1_i32
+            // The packets are dropped if the link is down.
+            let tap_fd = match self.tap.as_mut() {
+                Some(tap) if self.link_up.load(Ordering::SeqCst) => tap.as_raw_fd() as libc::c_int,
+                _ => -1_i32,
             };
             if tap_fd != -1 && self.send_packets(tap_fd, &iovecs) == -1 {
                 queue.vring.push_back();
                 self.tx.queue_evt.write(1).with_context(|| {
                     "Failed to trigger tx queue event when writev blocked".to_string()
                 })?;
-                return Ok(());
+                return self.flush_notify(&self.tx.queue, &mut queue);
             }
 
             queue
@@ -918,10 +932,7 @@ impl NetIoHandler {
                 .add_used(&self.mem_space, elem.index, 0)
                 .with_context(|| format!("Net tx: Failed to add used ring {}", elem.index))?;
 
-            if queue
-                .vring
-                .should_notify(&self.mem_space, self.driver_features)
-            {
+            if queue.should_notify_coalesced(&self.mem_space, self.driver_features) {
                 (self.interrupt_cb)(&VirtioInterruptType::Vring, Some(&queue), false)
                     .with_context(|| {
                         VirtioError::InterruptTrigger("net", VirtioInterruptType::Vring)
@@ -938,6 +949,46 @@ impl NetIoHandler {
             }
         }
 
+        self.flush_notify(&self.tx.queue, &mut queue)
+    }
+
+    /// Send the notification deferred by coalescing at the end of a batch. If the
+    /// coalescing delay is set, it is sent by a timer instead, so that the notifications
+    /// of the following batches within the delay are merged.
+    fn flush_notify(&self, queue_lock: &Arc<Mutex<Queue>>, queue: &mut Queue) -> Result<()> {
+        let usecs = queue.coalescing().usecs;
+        if usecs != 0 {
+            if queue.timer_armed || !queue.has_pending_notify() {
+                return Ok(());
+            }
+            if let Some(ctx) = EventLoop::get_ctx(self.iothread.as_ref()) {
+                let cloned_queue = queue_lock.clone();
+                let interrupt_cb = self.interrupt_cb.clone();
+                let device_broken = self.device_broken.clone();
+                let func = Box::new(move || {
+                    let mut locked_queue = cloned_queue.lock().unwrap();
+                    locked_queue.timer_armed = false;
+                    if device_broken.load(Ordering::SeqCst) || !locked_queue.take_pending_notify() {
+                        return;
+                    }
+                    if let Err(e) =
+                        interrupt_cb(&VirtioInterruptType::Vring, Some(&locked_queue), false)
+                    {
+                        error!("Failed to send coalesced interrupt for net: {:?}", e);
+                    }
+                });
+                ctx.timer_add(func, Duration::from_micros(usecs as u64));
+                queue.timer_armed = true;
+                return Ok(());
+            }
+        }
+
+        if queue.take_pending_notify() {
+            (self.interrupt_cb)(&VirtioInterruptType::Vring, Some(queue), false).with_context(
+                || VirtioError::InterruptTrigger("net", VirtioInterruptType::Vring),
+            )?;
+            self.trace_send_interrupt("Net".to_string());
+        }
         Ok(())
     }
 
@@ -1165,6 +1216,10 @@ pub struct Net {
     broken: Arc<AtomicBool>,
     /// The information about control command.
     ctrl_info: Option<Arc<Mutex<CtrlInfo>>>,
+    /// The link status of net device.
+    link_up: Arc<AtomicBool>,
+    /// The interrupt callback to notify the guest of link status change.
+    interrupt_cb: Option<Arc<VirtioInterrupt>>,
 }
 
 impl Default for Net {
@@ -1178,6 +1233,8 @@ impl Default for Net {
             deactivate_evts: Vec::new(),
             broken: Arc::new(AtomicBool::new(false)),
             ctrl_info: None,
+            link_up: Arc::new(AtomicBool::new(true)),
+            interrupt_cb: None,
         }
     }
 }
@@ -1193,8 +1250,44 @@ impl Net {
             deactivate_evts: Vec::new(),
             broken: Arc::new(AtomicBool::new(false)),
             ctrl_info: None,
+            link_up: Arc::new(AtomicBool::new(true)),
+            interrupt_cb: None,
         }
     }
+
+    /// Get the link status of net device.
+    pub fn link_up(&self) -> bool {
+        self.link_up.load(Ordering::SeqCst)
+    }
+
+    /// Set the link status of net device, and notify the guest if the status is changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `link_up` - The link is up or down.
+    pub fn set_link_up(&mut self, link_up: bool) -> Result<()> {
+        if self.link_up.swap(link_up, Ordering::SeqCst) == link_up {
+            return Ok(());
+        }
+
+        let mut locked_state = self.state.lock().unwrap();
+        if link_up {
+            locked_state.config_space.status |= VIRTIO_NET_S_LINK_UP;
+        } else {
+            locked_state.config_space.status &= !VIRTIO_NET_S_LINK_UP;
+        }
+        let driver_features = locked_state.driver_features;
+        drop(locked_state);
+
+        if let Some(interrupt_cb) = &self.interrupt_cb {
+            if virtio_has_feature(driver_features, VIRTIO_NET_F_STATUS) {
+                interrupt_cb(&VirtioInterruptType::Config, None, false).with_context(|| {
+                    VirtioError::InterruptTrigger("net", VirtioInterruptType::Config)
+                })?;
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Set Mac address configured into the virtio configuration, and return features mask with
@@ -1385,6 +1478,15 @@ impl VirtioDevice for Net {
             | 1 << VIRTIO_NET_F_CTRL_VQ
             | 1 << VIRTIO_F_RING_INDIRECT_DESC
             | 1 << VIRTIO_F_RING_EVENT_IDX;
+        if self.net_cfg.status {
+            locked_state.device_features |= 1 << VIRTIO_NET_F_STATUS;
+        }
+
+        if self.link_up.load(Ordering::SeqCst) {
+            locked_state.config_space.status |= VIRTIO_NET_S_LINK_UP;
+        } else {
+            locked_state.config_space.status &= !VIRTIO_NET_S_LINK_UP;
+        }
 
         let queue_pairs = self.net_cfg.queues / 2;
         if self.net_cfg.mq
@@ -1395,6 +1497,11 @@ impl VirtioDevice for Net {
             locked_state.config_space.max_virtqueue_pairs = queue_pairs;
         }
 
+        // The primary passthrough device is paired with this device by the same MAC address in guest.
+        if self.net_cfg.failover {
+            locked_state.device_features |= 1 << VIRTIO_NET_F_STANDBY;
+        }
+
         if !self.net_cfg.host_dev_name.is_empty() {
             self.taps = None;
             self.taps = create_tap(None, Some(&self.net_cfg.host_dev_name), queue_pairs)
@@ -1584,6 +1691,13 @@ impl VirtioDevice for Net {
                     .with_context(|| "Failed to set tap offload")?;
             }
 
+            let coalescing = NotifyCoalescing {
+                max_frames: self.net_cfg.coalesce_frames,
+                usecs: self.net_cfg.coalesce_usecs,
+            };
+            rx_queue.lock().unwrap().set_coalescing(coalescing);
+            tx_queue.lock().unwrap().set_coalescing(coalescing);
+
             let update_evt = Arc::new(EventFd::new(libc::EFD_NONBLOCK)?);
             let mut handler = NetIoHandler {
                 rx: RxVirtio::new(rx_queue, rx_queue_evt),
@@ -1599,6 +1713,8 @@ impl VirtioDevice for Net {
                 is_listening: true,
                 ctrl_info: ctrl_info.clone(),
                 queue_size: self.queue_size(),
+                iothread: self.net_cfg.iothread.clone(),
+                link_up: self.link_up.clone(),
             };
             if let Some(tap) = &handler.tap {
                 handler.tap_fd = tap.as_raw_fd();
@@ -1613,6 +1729,7 @@ impl VirtioDevice for Net {
             self.update_evts.push(update_evt);
         }
         self.senders = Some(senders);
+        self.interrupt_cb = Some(interrupt_cb);
         self.broken.store(false, Ordering::SeqCst);
 
         Ok(())
@@ -1674,6 +1791,7 @@ impl VirtioDevice for Net {
         unregister_event_helper(self.net_cfg.iothread.as_ref(), &mut self.deactivate_evts)?;
         self.update_evts.clear();
         self.ctrl_info = None;
+        self.interrupt_cb = None;
         Ok(())
     }
 
@@ -1701,6 +1819,13 @@ impl StateTransfer for Net {
         let mut locked_state = self.state.lock().unwrap();
         locked_state.as_mut_bytes().copy_from_slice(state);
         self.broken.store(locked_state.broken, Ordering::SeqCst);
+        // The link status is valid only if the source device supports it.
+        if virtio_has_feature(locked_state.device_features, VIRTIO_NET_F_STATUS) {
+            self.link_up.store(
+                locked_state.config_space.status & VIRTIO_NET_S_LINK_UP != 0,
+                Ordering::SeqCst,
+            );
+        }
 
         Ok(())
     }
@@ -1895,4 +2020,61 @@ mod tests {
             assert!(false);
         }
     }
+
+    #[test]
+    fn test_net_failover_standby() {
+        let mut net = Net::default();
+        net.net_cfg.mac = Some("1A:2B:3C:4D:5E:6F".to_string());
+        net.realize().unwrap();
+        assert_eq!(
+            net.state.lock().unwrap().device_features & (1 << VIRTIO_NET_F_STANDBY),
+            0
+        );
+
+        net.net_cfg.failover = true;
+        net.realize().unwrap();
+        assert_ne!(
+            net.state.lock().unwrap().device_features & (1 << VIRTIO_NET_F_STANDBY),
+            0
+        );
+    }
+
+    #[test]
+    fn test_net_link_status() {
+        let mut net = Net::default();
+        net.net_cfg.mac = Some("1A:2B:3C:4D:5E:6E".to_string());
+        net.realize().unwrap();
+        assert!(net.link_up());
+        assert_ne!(
+            net.state.lock().unwrap().device_features & (1 << VIRTIO_NET_F_STATUS),
+            0
+        );
+        assert_eq!(
+            net.state.lock().unwrap().config_space.status,
+            VIRTIO_NET_S_LINK_UP
+        );
+
+        net.set_link_up(false).unwrap();
+        assert!(!net.link_up());
+        assert_eq!(net.state.lock().unwrap().config_space.status, 0);
+
+        // The link status is kept after the device is realized again.
+        net.realize().unwrap();
+        assert_eq!(net.state.lock().unwrap().config_space.status, 0);
+
+        net.set_link_up(true).unwrap();
+        assert!(net.link_up());
+        assert_eq!(
+            net.state.lock().unwrap().config_space.status,
+            VIRTIO_NET_S_LINK_UP
+        );
+
+        // Link status isn't offered if it's turned off, e.g. by older machine versions.
+        net.net_cfg.status = false;
+        net.realize().unwrap();
+        assert_eq!(
+            net.state.lock().unwrap().device_features & (1 << VIRTIO_NET_F_STATUS),
+            0
+        );
+    }
 }
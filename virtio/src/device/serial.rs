@@ -33,8 +33,11 @@ use address_space::AddressSpace;
 use devices::legacy::{Chardev, ChardevNotifyDevice, ChardevStatus, InputReceiver};
 use machine_manager::{
     config::{ChardevType, VirtioSerialInfo, VirtioSerialPort, DEFAULT_VIRTQUEUE_SIZE},
+    event,
     event_loop::EventLoop,
     event_loop::{register_event_helper, unregister_event_helper},
+    qmp::qmp_schema::GuestAgentResponse,
+    qmp::QmpChannel,
 };
 use migration::{DeviceStateDesc, FieldDesc, MigrationHook, MigrationManager, StateTransfer};
 use migration_derive::{ByteCode, Desc};
@@ -48,6 +51,9 @@ use util::num_ops::read_u32;
 // Buffer size for chardev backend.
 const BUF_SIZE: usize = 4096;
 
+/// The virtio serial port used as guest agent channel.
+static mut GUEST_AGENT_PORT: Option<Arc<Mutex<SerialPort>>> = None;
+
 // The values for event.
 // Sent by the driver at initialization to indicate that it is ready to receive control message.
 const VIRTIO_CONSOLE_DEVICE_READY: u16 = 0;
@@ -371,6 +377,10 @@ pub struct SerialPort {
     host_connected: bool,
     /// The handler used to send control event to guest.
     ctrl_handler: Option<Weak<Mutex<SerialControlHandler>>>,
+    /// Whether a command passed through by qmp is waiting for the reply of guest agent.
+    agent_reply_pending: bool,
+    /// Received part of the guest agent reply.
+    agent_reply: Vec<u8>,
 }
 
 impl SerialPort {
@@ -379,13 +389,15 @@ impl SerialPort {
         let host_connected = port_cfg.is_console || port_cfg.chardev.backend == ChardevType::Pty;
 
         SerialPort {
-            name: Some(port_cfg.id),
+            name: Some(port_cfg.name.unwrap_or(port_cfg.id)),
             chardev: Arc::new(Mutex::new(Chardev::new(port_cfg.chardev))),
             nr: port_cfg.nr,
             is_console: port_cfg.is_console,
             guest_connected: false,
             host_connected,
             ctrl_handler: None,
+            agent_reply_pending: false,
+            agent_reply: Vec::new(),
         }
     }
 
@@ -411,7 +423,70 @@ impl SerialPort {
     fn deactivate(&mut self) {
         self.chardev.lock().unwrap().deactivated = true;
         self.guest_connected = false;
+        self.agent_reply_pending = false;
+        self.agent_reply.clear();
+    }
+
+    /// Collect the output of guest agent, and report every complete reply by qmp event.
+    fn receive_agent_reply(&mut self, buffer: &[u8]) {
+        self.agent_reply.extend_from_slice(buffer);
+        while let Some(pos) = self.agent_reply.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.agent_reply.drain(..=pos).collect();
+            let response = String::from_utf8_lossy(&line).trim().to_string();
+            if response.is_empty() {
+                continue;
+            }
+            self.agent_reply_pending = false;
+            self.agent_reply.clear();
+            let msg = GuestAgentResponse { response };
+            event!(GuestAgentResponse; msg);
+            break;
+        }
+    }
+}
+
+/// Register the virtio serial port used as guest agent channel.
+pub fn set_guest_agent_port(port: Arc<Mutex<SerialPort>>) -> Result<()> {
+    // Safe, because there is no confliction when writing global variable GUEST_AGENT_PORT, in other
+    // words, this function will not be called simultaneously.
+    unsafe {
+        if GUEST_AGENT_PORT.is_some() {
+            bail!("Only one guest agent channel is supported");
+        }
+        GUEST_AGENT_PORT = Some(port);
     }
+    Ok(())
+}
+
+/// Pass the command through to guest agent. The reply is reported by `GUEST_AGENT_RESPONSE` event.
+pub fn qmp_guest_agent_command(command: &str) -> Result<()> {
+    // Safe, because there is no confliction when writing global variable GUEST_AGENT_PORT, in other
+    // words, this function will not be called simultaneously.
+    let port = unsafe { GUEST_AGENT_PORT.as_ref() }
+        .with_context(|| "Guest agent channel not configured")?
+        .clone();
+
+    let chardev = {
+        let mut locked_port = port.lock().unwrap();
+        if !locked_port.guest_connected {
+            bail!("Guest agent is not connected");
+        }
+        // The reply of guest agent will be discarded if the host side is closed.
+        if !locked_port.host_connected {
+            locked_port.chardev_notify(ChardevStatus::Open);
+        }
+        locked_port.agent_reply_pending = true;
+        locked_port.agent_reply.clear();
+        locked_port.chardev.clone()
+    };
+
+    let mut msg = command.trim_end().as_bytes().to_vec();
+    msg.push(b'\n');
+    let ret = chardev.lock().unwrap().inject_input(&msg);
+    if ret.is_err() {
+        port.lock().unwrap().agent_reply_pending = false;
+    }
+    ret
 }
 
 /// Handler for queues which are used for port.
@@ -466,16 +541,32 @@ impl SerialPortHandler {
             }
             debug!("elem desc_unm: {}", elem.desc_num);
 
+            let (host_connected, agent_reply_pending) = match self.port.as_ref() {
+                Some(port) => {
+                    let locked_port = port.lock().unwrap();
+                    (locked_port.host_connected, locked_port.agent_reply_pending)
+                }
+                None => (false, false),
+            };
             // Discard requests when there is no port using this queue or this port's socket is not connected.
             // Popping elements without processing means discarding the request.
-            if self.port.is_some() && self.port.as_ref().unwrap().lock().unwrap().host_connected {
+            if host_connected || agent_reply_pending {
                 let mut iovec = elem.out_iovec;
                 let mut iovec_size = Element::iovec_size(&iovec);
                 while iovec_size > 0 {
                     let mut buffer = [0_u8; BUF_SIZE];
                     let size = iov_to_buf(&self.mem_space, &iovec, &mut buffer)?;
 
-                    self.write_chardev_msg(&buffer, size);
+                    if agent_reply_pending {
+                        self.port
+                            .as_ref()
+                            .unwrap()
+                            .lock()
+                            .unwrap()
+                            .receive_agent_reply(&buffer[..size]);
+                    } else {
+                        self.write_chardev_msg(&buffer, size);
+                    }
 
                     iovec = iov_discard_front(&mut iovec, size as u64)
                         .unwrap_or_default()
@@ -906,6 +997,7 @@ impl ChardevNotifyDevice for SerialPort {
             (ChardevStatus::Open, true) => return,
         }
 
+        // Port is not activated yet, host connection state is reported when port is ready.
         if let Some(handler) = &self.ctrl_handler {
             let handler = handler.upgrade().unwrap();
             handler.lock().unwrap().send_control_event(
@@ -913,8 +1005,6 @@ impl ChardevNotifyDevice for SerialPort {
                 VIRTIO_CONSOLE_PORT_OPEN,
                 status as u16,
             );
-        } else {
-            error!("Control handler for port {} is None", self.nr);
         }
     }
 }
@@ -52,7 +52,10 @@ pub use device::gpu::*;
 pub use device::net::*;
 pub use device::rng::{Rng, RngState};
 pub use device::scsi_cntlr as ScsiCntlr;
-pub use device::serial::{find_port_by_nr, Serial, SerialPort, VirtioSerialState};
+pub use device::serial::{
+    find_port_by_nr, qmp_guest_agent_command, set_guest_agent_port, Serial, SerialPort,
+    VirtioSerialState,
+};
 pub use error::VirtioError;
 pub use error::*;
 pub use queue::*;
@@ -66,6 +69,17 @@ pub fn virtio_has_feature(feature: u64, fbit: u32) -> bool {
     feature & (1 << fbit) != 0
 }
 
+/// Features offered by the transport for all devices at the page `features_select`.
+/// Devices of protected VM can only access the memory shared by guest, which guest
+/// driver is told by `VIRTIO_F_ACCESS_PLATFORM`.
+pub fn transport_features(mem_space: &AddressSpace, features_select: u32) -> u32 {
+    if features_select == 1 && mem_space.protected_memory().is_some() {
+        1 << (VIRTIO_F_ACCESS_PLATFORM - 32)
+    } else {
+        0
+    }
+}
+
 /// Identifier of different virtio device, refer to Virtio Spec.
 pub const VIRTIO_TYPE_NET: u32 = 1;
 pub const VIRTIO_TYPE_BLOCK: u32 = 2;
@@ -121,6 +135,8 @@ pub const VIRTIO_NET_F_HOST_TSO6: u32 = 12;
 pub const VIRTIO_NET_F_HOST_UFO: u32 = 14;
 /// Device can merge receive buffers.
 pub const VIRTIO_NET_F_MRG_RXBUF: u32 = 15;
+/// Configuration status field is available.
+pub const VIRTIO_NET_F_STATUS: u32 = 16;
 /// Control channel is available.
 pub const VIRTIO_NET_F_CTRL_VQ: u32 = 17;
 /// Control channel RX mode support.
@@ -133,6 +149,10 @@ pub const VIRTIO_NET_F_CTRL_RX_EXTRA: u32 = 20;
 pub const VIRTIO_NET_F_MQ: u32 = 22;
 /// Set Mac Address through control channel.
 pub const VIRTIO_NET_F_CTRL_MAC_ADDR: u32 = 23;
+/// Device may act as a standby for a primary device with the same MAC address.
+pub const VIRTIO_NET_F_STANDBY: u32 = 62;
+/// The link of net device is up.
+pub const VIRTIO_NET_S_LINK_UP: u16 = 1;
 /// Configuration cols and rows are valid.
 pub const VIRTIO_CONSOLE_F_SIZE: u64 = 0;
 /// Device has support for multiple ports.
@@ -432,7 +452,7 @@ pub trait VirtioDevice: Send + AsAny {
 /// on the front and back ends.
 pub trait VirtioTrace {
     fn trace_request(&self, device: String, behaviour: String) {
-        util::ftrace!(
+        util::trace_event!(
             trace_request,
             "{} : Request received from Guest {}, ready to start processing.",
             device,
@@ -440,7 +460,7 @@ pub trait VirtioTrace {
         );
     }
     fn trace_send_interrupt(&self, device: String) {
-        util::ftrace!(
+        util::trace_event!(
             trace_send_interrupt,
             "{} : stratovirt processing complete, ready to send interrupt to guest.",
             device
@@ -163,10 +163,27 @@ pub trait VringOps {
     fn get_cache(&self) -> &Option<RegionCache>;
 }
 
+/// Notification coalescing parameters of a virtqueue.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct NotifyCoalescing {
+    /// Max number of used elements before notifying the guest, 0 or 1 means no frame coalescing.
+    pub max_frames: u16,
+    /// Max delay of a pending notification in microseconds, 0 means no delay timer.
+    pub usecs: u32,
+}
+
 /// Virtio queue.
 pub struct Queue {
     /// Vring structure.
     pub vring: Box<dyn VringOps + Send>,
+    /// Notification coalescing parameters.
+    coalescing: NotifyCoalescing,
+    /// Number of used elements added since the last notification.
+    pending_used: u16,
+    /// Whether the guest asked to be notified since the last notification.
+    notify_pending: bool,
+    /// Whether a timer is armed to flush the pending notification.
+    pub timer_armed: bool,
 }
 
 impl Queue {
@@ -184,7 +201,60 @@ impl Queue {
             }
         };
 
-        Ok(Queue { vring })
+        Ok(Queue {
+            vring,
+            coalescing: NotifyCoalescing::default(),
+            pending_used: 0,
+            notify_pending: false,
+            timer_armed: false,
+        })
+    }
+
+    /// Set the notification coalescing parameters of the virtqueue.
+    pub fn set_coalescing(&mut self, coalescing: NotifyCoalescing) {
+        self.coalescing = coalescing;
+    }
+
+    /// Get the notification coalescing parameters of the virtqueue.
+    pub fn coalescing(&self) -> NotifyCoalescing {
+        self.coalescing
+    }
+
+    /// Return true if the guest should be notified right now. It should be called
+    /// after each used element is added. The notification requested by the guest is
+    /// deferred until `max_frames` used elements are accumulated, the deferred one
+    /// can be fetched by `take_pending_notify`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sys_mem` - Address space to which the vring belongs.
+    /// * `features` - Features negotiated by the driver.
+    pub fn should_notify_coalesced(&mut self, sys_mem: &Arc<AddressSpace>, features: u64) -> bool {
+        if self.vring.should_notify(sys_mem, features) {
+            self.notify_pending = true;
+        }
+        self.pending_used = self.pending_used.saturating_add(1);
+
+        if !self.notify_pending
+            || (self.coalescing.max_frames > 1 && self.pending_used < self.coalescing.max_frames)
+        {
+            return false;
+        }
+
+        self.pending_used = 0;
+        self.notify_pending = false;
+        true
+    }
+
+    /// Return true if there is a deferred notification.
+    pub fn has_pending_notify(&self) -> bool {
+        self.notify_pending
+    }
+
+    /// Return true if there is a deferred notification, and clear it.
+    pub fn take_pending_notify(&mut self) -> bool {
+        self.pending_used = 0;
+        std::mem::take(&mut self.notify_pending)
     }
 
     /// Return true if the virtqueue is enabled by driver.
@@ -818,6 +818,14 @@ impl VringOps for SplitVring {
 
         self.get_vring_element(sys_mem, features, &mut element)
             .with_context(|| "Failed to get vring element")?;
+        util::trace_event!(
+            virtqueue_pop_avail,
+            "vq 0x{:x} elem index {} out_num {} in_num {}",
+            self.desc_table.raw_value(),
+            element.index,
+            element.out_iovec.len(),
+            element.in_iovec.len()
+        );
 
         Ok(element)
     }
@@ -831,6 +839,13 @@ impl VringOps for SplitVring {
             return Err(anyhow!(VirtioError::QueueIndex(index, self.size)));
         }
 
+        util::trace_event!(
+            virtqueue_add_used,
+            "vq 0x{:x} elem index {} len {}",
+            self.desc_table.raw_value(),
+            index,
+            len
+        );
         let next_used = u64::from(self.next_used.0 % self.actual_size());
         let used_elem_addr =
             self.addr_cache.used_ring_host + VRING_FLAGS_AND_IDX_LEN + next_used * USEDELEM_LEN;
@@ -912,7 +927,7 @@ impl VringOps for SplitVring {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Queue, QUEUE_TYPE_PACKED_VRING, QUEUE_TYPE_SPLIT_VRING};
+    use crate::{NotifyCoalescing, Queue, QUEUE_TYPE_PACKED_VRING, QUEUE_TYPE_SPLIT_VRING};
     use address_space::{AddressSpace, GuestAddress, HostMemMapping, Region};
 
     fn address_space_init() -> Arc<AddressSpace> {
@@ -1979,4 +1994,59 @@ mod tests {
         assert!(vring.set_used_event_idx(&sys_space, 4).is_ok()); //event_idx
         assert_eq!(vring.should_notify(&sys_space, features), false);
     }
+
+    #[test]
+    fn test_should_notify_coalesced() {
+        let sys_space = address_space_init();
+
+        let mut queue_config = QueueConfig::new(QUEUE_SIZE);
+        queue_config.desc_table = GuestAddress(0);
+        queue_config.addr_cache.desc_table_host =
+            sys_space.get_host_address(queue_config.desc_table).unwrap();
+        queue_config.avail_ring = GuestAddress((QUEUE_SIZE as u64) * DESCRIPTOR_LEN);
+        queue_config.addr_cache.avail_ring_host =
+            sys_space.get_host_address(queue_config.avail_ring).unwrap();
+        queue_config.used_ring = GuestAddress(align(
+            (QUEUE_SIZE as u64) * DESCRIPTOR_LEN
+                + VRING_AVAIL_LEN_EXCEPT_AVAILELEM
+                + AVAILELEM_LEN * (QUEUE_SIZE as u64),
+            4096,
+        ));
+        queue_config.addr_cache.used_ring_host =
+            sys_space.get_host_address(queue_config.used_ring).unwrap();
+        queue_config.ready = true;
+        queue_config.size = QUEUE_SIZE;
+        let vring = SplitVring::new(queue_config);
+        let mut queue = Queue::new(queue_config, QUEUE_TYPE_SPLIT_VRING).unwrap();
+        let features = 0 as u64;
+        assert!(vring.set_avail_ring_flags(&sys_space, 0).is_ok());
+
+        // Notify every time without coalescing.
+        assert_eq!(queue.should_notify_coalesced(&sys_space, features), true);
+        assert_eq!(queue.should_notify_coalesced(&sys_space, features), true);
+        assert_eq!(queue.take_pending_notify(), false);
+
+        // Notify once every 3 used elements.
+        queue.set_coalescing(NotifyCoalescing {
+            max_frames: 3,
+            usecs: 0,
+        });
+        assert_eq!(queue.should_notify_coalesced(&sys_space, features), false);
+        assert_eq!(queue.should_notify_coalesced(&sys_space, features), false);
+        assert_eq!(queue.should_notify_coalesced(&sys_space, features), true);
+
+        // The deferred notification can be taken only once.
+        assert_eq!(queue.should_notify_coalesced(&sys_space, features), false);
+        assert_eq!(queue.take_pending_notify(), true);
+        assert_eq!(queue.take_pending_notify(), false);
+
+        // Nothing is pending if the guest suppresses the notification.
+        assert!(vring
+            .set_avail_ring_flags(&sys_space, VRING_AVAIL_F_NO_INTERRUPT)
+            .is_ok());
+        for _ in 0..4 {
+            assert_eq!(queue.should_notify_coalesced(&sys_space, features), false);
+        }
+        assert_eq!(queue.take_pending_notify(), false);
+    }
 }
@@ -26,9 +26,9 @@ use util::byte_code::ByteCode;
 use vmm_sys_util::eventfd::EventFd;
 
 use crate::{
-    virtio_has_feature, Queue, QueueConfig, VirtioDevice, VirtioInterrupt, VirtioInterruptType,
-    CONFIG_STATUS_ACKNOWLEDGE, CONFIG_STATUS_DRIVER, CONFIG_STATUS_DRIVER_OK, CONFIG_STATUS_FAILED,
-    CONFIG_STATUS_FEATURES_OK, CONFIG_STATUS_NEEDS_RESET, NOTIFY_REG_OFFSET,
+    transport_features, virtio_has_feature, Queue, QueueConfig, VirtioDevice, VirtioInterrupt,
+    VirtioInterruptType, CONFIG_STATUS_ACKNOWLEDGE, CONFIG_STATUS_DRIVER, CONFIG_STATUS_DRIVER_OK,
+    CONFIG_STATUS_FAILED, CONFIG_STATUS_FEATURES_OK, CONFIG_STATUS_NEEDS_RESET, NOTIFY_REG_OFFSET,
     QUEUE_TYPE_PACKED_VRING, QUEUE_TYPE_SPLIT_VRING, VIRTIO_F_RING_PACKED, VIRTIO_MMIO_INT_CONFIG,
     VIRTIO_MMIO_INT_VRING,
 };
@@ -496,7 +496,8 @@ impl SysBusDevOps for VirtioMmioDevice {
     fn read(&mut self, data: &mut [u8], _base: GuestAddress, offset: u64) -> bool {
         match offset {
             0x00..=0xff if data.len() == 4 => {
-                let value = match self.state.lock().unwrap().config_space.read_common_config(
+                let mut locked_state = self.state.lock().unwrap();
+                let mut value = match locked_state.config_space.read_common_config(
                     &self.device,
                     &self.interrupt_status,
                     offset,
@@ -512,6 +513,12 @@ impl SysBusDevOps for VirtioMmioDevice {
                         return false;
                     }
                 };
+                if offset == DEVICE_FEATURES_REG {
+                    value |= transport_features(
+                        &self.mem_space,
+                        locked_state.config_space.features_select,
+                    );
+                }
                 LittleEndian::write_u32(data, value);
             }
             0x100..=0xfff => {
@@ -546,7 +553,14 @@ impl SysBusDevOps for VirtioMmioDevice {
         let mut locked_state = self.state.lock().unwrap();
         match offset {
             0x00..=0xff if data.len() == 4 => {
-                let value = LittleEndian::read_u32(data);
+                let mut value = LittleEndian::read_u32(data);
+                if offset == DRIVER_FEATURES_REG {
+                    // Features offered by transport are not known by the device.
+                    value &= !transport_features(
+                        &self.mem_space,
+                        locked_state.config_space.acked_features_select,
+                    );
+                }
                 if let Err(ref e) = locked_state.config_space.write_common_config(
                     &self.device,
                     &self.interrupt_status,
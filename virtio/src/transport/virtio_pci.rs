@@ -39,8 +39,8 @@ use util::offset_of;
 use vmm_sys_util::eventfd::EventFd;
 
 use crate::{
-    virtio_has_feature, NotifyEventFds, Queue, QueueConfig, VirtioDevice, VirtioInterrupt,
-    VirtioInterruptType,
+    transport_features, virtio_has_feature, NotifyEventFds, Queue, QueueConfig, VirtioDevice,
+    VirtioInterrupt, VirtioInterruptType,
 };
 
 use crate::{
@@ -756,27 +756,8 @@ impl VirtioPciDevice {
         drop(locked_queues);
 
         update_dev_id(&self.parent_bus, self.devfn, &self.dev_id);
-        if self.need_irqfd {
-            let mut queue_num = self.device.lock().unwrap().queue_num();
-            // No need to create call event for control queue.
-            // It will be polled in StratoVirt when activating the device.
-            if self.device.lock().unwrap().has_control_queue() && queue_num % 2 != 0 {
-                queue_num -= 1;
-            }
-            let call_evts = NotifyEventFds::new(queue_num);
-            if let Err(e) = self
-                .device
-                .lock()
-                .unwrap()
-                .set_guest_notifiers(&call_evts.events)
-            {
-                error!("Failed to set guest notifiers, error is {:?}", e);
-                return false;
-            }
-            if !self.queues_register_irqfd(&call_evts.events) {
-                error!("Failed to register queues irqfd.");
-                return false;
-            }
+        if self.need_irqfd && !self.register_guest_notifiers() {
+            return false;
         }
 
         let queue_evts = (*self.notify_eventfds).clone().events;
@@ -794,6 +775,32 @@ impl VirtioPciDevice {
         true
     }
 
+    /// Create the call events which are used by the device outside of StratoVirt
+    /// to notify the guest, and register them as irqfd.
+    fn register_guest_notifiers(&self) -> bool {
+        let mut queue_num = self.device.lock().unwrap().queue_num();
+        // No need to create call event for control queue.
+        // It will be polled in StratoVirt when activating the device.
+        if self.device.lock().unwrap().has_control_queue() && queue_num % 2 != 0 {
+            queue_num -= 1;
+        }
+        let call_evts = NotifyEventFds::new(queue_num);
+        if let Err(e) = self
+            .device
+            .lock()
+            .unwrap()
+            .set_guest_notifiers(&call_evts.events)
+        {
+            error!("Failed to set guest notifiers, error is {:?}", e);
+            return false;
+        }
+        if !self.queues_register_irqfd(&call_evts.events) {
+            error!("Failed to register queues irqfd.");
+            return false;
+        }
+        true
+    }
+
     fn deactivate_device(&self) -> bool {
         if self.need_irqfd && self.config.msix.is_some() {
             let msix = self.config.msix.as_ref().unwrap();
@@ -816,12 +823,10 @@ impl VirtioPciDevice {
     fn build_common_cfg_ops(&mut self) -> RegionOps {
         let cloned_virtio_dev = self.device.clone();
         let cloned_common_cfg = self.common_config.clone();
+        let cloned_sys_mem = self.sys_mem.clone();
         let common_read = move |data: &mut [u8], _addr: GuestAddress, offset: u64| -> bool {
-            let value = match cloned_common_cfg
-                .lock()
-                .unwrap()
-                .read_common_config(&cloned_virtio_dev, offset)
-            {
+            let locked_common_cfg = cloned_common_cfg.lock().unwrap();
+            let mut value = match locked_common_cfg.read_common_config(&cloned_virtio_dev, offset) {
                 Ok(v) => v,
                 Err(e) => {
                     error!(
@@ -831,6 +836,9 @@ impl VirtioPciDevice {
                     return false;
                 }
             };
+            if offset == COMMON_DF_REG {
+                value |= transport_features(&cloned_sys_mem, locked_common_cfg.features_select);
+            }
 
             write_data_u32(data, value)
         };
@@ -842,11 +850,16 @@ impl VirtioPciDevice {
                 return false;
             }
 
-            if let Err(e) = cloned_pci_device
-                .common_config
-                .lock()
-                .unwrap()
-                .write_common_config(&cloned_pci_device, offset, value)
+            let mut locked_common_cfg = cloned_pci_device.common_config.lock().unwrap();
+            if offset == COMMON_GF_REG {
+                // Features offered by transport are not known by the device.
+                value &= !transport_features(
+                    &cloned_pci_device.sys_mem,
+                    locked_common_cfg.acked_features_select,
+                );
+            }
+
+            if let Err(e) = locked_common_cfg.write_common_config(&cloned_pci_device, offset, value)
             {
                 error!(
                     "Failed to write common config of virtio-pci device, error is {:?}",
@@ -1268,6 +1281,10 @@ impl PciDevOps for VirtioPciDevice {
         self.name.clone()
     }
 
+    fn get_pci_config(&self) -> Option<&PciConfig> {
+        Some(&self.config)
+    }
+
     fn devfn(&self) -> Option<u8> {
         Some(self.devfn)
     }
@@ -1306,6 +1323,15 @@ impl PciDevOps for VirtioPciDevice {
                 let dev_path = self.populate_dev_path(parent_dev_path, self.devfn, "/scsi@");
                 Some(dev_path)
             }
+            VIRTIO_TYPE_NET => {
+                // The virtio net device is identified as an ethernet controller with a
+                // single phy, which is the device path firmware uses for network boot.
+                let parent_dev_path = self.get_parent_dev_path(parent_bus);
+                let mut dev_path =
+                    self.populate_dev_path(parent_dev_path, self.devfn, "/ethernet@");
+                dev_path.push_str("/ethernet-phy@0");
+                Some(dev_path)
+            }
             _ => None,
         }
     }
@@ -1435,6 +1461,10 @@ impl MigrationHook for VirtioPciDevice {
                 bail!("Failed to update bar, error is {:?}", e);
             }
 
+            if self.need_irqfd && !self.register_guest_notifiers() {
+                bail!("Failed to register guest notifiers when resuming device");
+            }
+
             let queue_evts = (*self.notify_eventfds).clone().events;
             if let Some(cb) = self.interrupt_cb.clone() {
                 if let Err(e) = self.device.lock().unwrap().activate(
@@ -21,7 +21,7 @@ use std::os::unix::fs::OpenOptionsExt;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 
 use address_space::{
     AddressSpace, FlatRange, GuestAddress, Listener, ListenerReqType, RegionIoEventFd, RegionType,
@@ -128,10 +128,15 @@ struct VhostMemory {
 
 impl ByteCode for VhostMemory {}
 
-#[derive(Clone)]
 struct VhostMemInfo {
     regions: Arc<Mutex<Vec<VhostMemoryRegion>>>,
     enabled: bool,
+    /// The vhost fd which the mem table has been set to. As vhost kernel has no
+    /// ioctl to add or remove a single region, the whole table is set to it again
+    /// when the regions are changed.
+    backend: Option<Weak<File>>,
+    /// The regions are changed since the last commit.
+    changed: AtomicBool,
 }
 
 impl VhostMemInfo {
@@ -139,6 +144,8 @@ impl VhostMemInfo {
         VhostMemInfo {
             regions: Arc::new(Mutex::new(Vec::new())),
             enabled: false,
+            backend: None,
+            changed: AtomicBool::new(false),
         }
     }
 
@@ -220,22 +227,62 @@ impl Listener for VhostMemInfo {
             ListenerReqType::AddRegion => {
                 if Self::check_vhost_mem_range(range.unwrap()) {
                     self.add_mem_range(range.unwrap());
+                    self.changed.store(true, Ordering::SeqCst);
                 }
             }
             ListenerReqType::DeleteRegion => {
                 let fr = range.unwrap();
                 if fr.owner.region_type() == RegionType::Ram {
                     self.delete_mem_range(fr);
+                    self.changed.store(true, Ordering::SeqCst);
                 }
             }
             _ => {}
         }
         Ok(())
     }
+
+    fn commit(&self) -> Result<()> {
+        if !self.changed.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        if let Some(fd) = self.backend.as_ref().and_then(|backend| backend.upgrade()) {
+            vhost_set_mem_table(fd.as_ref(), &self.regions.lock().unwrap())
+                .with_context(|| "Failed to update mem table for vhost")?;
+        }
+        Ok(())
+    }
+}
+
+fn vhost_set_mem_table(fd: &File, regions: &[VhostMemoryRegion]) -> Result<()> {
+    let vm_size = std::mem::size_of::<VhostMemory>();
+    let vmr_size = std::mem::size_of::<VhostMemoryRegion>();
+    let mut bytes: Vec<u8> = vec![0; vm_size + regions.len() * vmr_size];
+
+    bytes[0..vm_size].copy_from_slice(
+        VhostMemory {
+            nregions: regions.len() as u32,
+            padding: 0,
+        }
+        .as_bytes(),
+    );
+    for (index, region) in regions.iter().enumerate() {
+        bytes[(vm_size + index * vmr_size)..(vm_size + (index + 1) * vmr_size)]
+            .copy_from_slice(region.as_bytes());
+    }
+
+    // SAFETY: The fd is a vhost fd and the bytes hold the header and all the regions.
+    let ret = unsafe { ioctl_with_ptr(fd, VHOST_SET_MEM_TABLE(), bytes.as_ptr()) };
+    if ret < 0 {
+        return Err(anyhow!(VirtioError::VhostIoctl(
+            "VHOST_SET_MEM_TABLE".to_string()
+        )));
+    }
+    Ok(())
 }
 
 pub struct VhostBackend {
-    fd: File,
+    fd: Arc<File>,
     mem_info: Arc<Mutex<VhostMemInfo>>,
 }
 
@@ -257,7 +304,10 @@ impl VhostBackend {
         let mem_info = Arc::new(Mutex::new(VhostMemInfo::new()));
         mem_space.register_listener(mem_info.clone())?;
 
-        Ok(VhostBackend { fd, mem_info })
+        Ok(VhostBackend {
+            fd: Arc::new(fd),
+            mem_info,
+        })
     }
 }
 
@@ -285,6 +335,8 @@ impl VhostOps for VhostBackend {
                 "VHOST_RESET_OWNER".to_string()
             )));
         }
+        // The mem table is dropped by vhost with the owner.
+        self.mem_info.lock().unwrap().backend = None;
         Ok(())
     }
 
@@ -310,32 +362,10 @@ impl VhostOps for VhostBackend {
     }
 
     fn set_mem_table(&self) -> Result<()> {
-        let regions = self.mem_info.lock().unwrap().regions.lock().unwrap().len();
-        let vm_size = std::mem::size_of::<VhostMemory>();
-        let vmr_size = std::mem::size_of::<VhostMemoryRegion>();
-        let mut bytes: Vec<u8> = vec![0; vm_size + regions * vmr_size];
-
-        bytes[0..vm_size].copy_from_slice(
-            VhostMemory {
-                nregions: regions as u32,
-                padding: 0,
-            }
-            .as_bytes(),
-        );
-
-        let locked_mem_info = self.mem_info.lock().unwrap();
-        let locked_regions = locked_mem_info.regions.lock().unwrap();
-        for (index, region) in locked_regions.iter().enumerate() {
-            bytes[(vm_size + index * vmr_size)..(vm_size + (index + 1) * vmr_size)]
-                .copy_from_slice(region.as_bytes());
-        }
-
-        let ret = unsafe { ioctl_with_ptr(self, VHOST_SET_MEM_TABLE(), bytes.as_ptr()) };
-        if ret < 0 {
-            return Err(anyhow!(VirtioError::VhostIoctl(
-                "VHOST_SET_MEM_TABLE".to_string()
-            )));
-        }
+        let mut locked_mem_info = self.mem_info.lock().unwrap();
+        vhost_set_mem_table(&self.fd, &locked_mem_info.regions.lock().unwrap())?;
+        // Later changes of the regions are set to vhost when they are committed.
+        locked_mem_info.backend = Some(Arc::downgrade(&self.fd));
         Ok(())
     }
 
@@ -399,6 +429,7 @@ impl VhostOps for VhostBackend {
     }
 
     fn set_vring_base(&self, queue_idx: usize, num: u16) -> Result<()> {
+        util::trace_event!(vhost_set_vring_base, "queue {} num {}", queue_idx, num);
         let vring_state = VhostVringState {
             index: queue_idx as u32,
             num: u32::from(num),
@@ -428,6 +459,12 @@ impl VhostOps for VhostBackend {
     }
 
     fn set_vring_call(&self, queue_idx: usize, fd: Arc<EventFd>) -> Result<()> {
+        util::trace_event!(
+            vhost_set_vring_call,
+            "queue {} fd {}",
+            queue_idx,
+            fd.as_raw_fd()
+        );
         let vring_file = VhostVringFile {
             index: queue_idx as u32,
             fd: fd.as_raw_fd(),
@@ -442,6 +479,12 @@ impl VhostOps for VhostBackend {
     }
 
     fn set_vring_kick(&self, queue_idx: usize, fd: Arc<EventFd>) -> Result<()> {
+        util::trace_event!(
+            vhost_set_vring_kick,
+            "queue {} fd {}",
+            queue_idx,
+            fd.as_raw_fd()
+        );
         let vring_file = VhostVringFile {
             index: queue_idx as u32,
             fd: fd.as_raw_fd(),
@@ -489,6 +489,11 @@ mod tests {
             mq: false,
             socket_path: None,
             queue_size: DEFAULT_VIRTQUEUE_SIZE,
+            failover: false,
+            coalesce_frames: 0,
+            coalesce_usecs: 0,
+            status: true,
+            boot_index: None,
         };
         let conf = vec![net1];
         let confs = Some(conf);
@@ -511,6 +516,11 @@ mod tests {
             mq: false,
             socket_path: None,
             queue_size: DEFAULT_VIRTQUEUE_SIZE,
+            failover: false,
+            coalesce_frames: 0,
+            coalesce_usecs: 0,
+            status: true,
+            boot_index: None,
         };
         let conf = vec![net1];
         let confs = Some(conf);
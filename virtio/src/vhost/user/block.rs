@@ -19,6 +19,7 @@ use std::sync::{Arc, Mutex};
 
 use address_space::AddressSpace;
 use machine_manager::config::BlkDevConfig;
+use migration::{DeviceStateDesc, MigrationHook, MigrationManager, StateTransfer};
 use util::byte_code::ByteCode;
 use util::num_ops::read_u32;
 use vmm_sys_util::eventfd::EventFd;
@@ -26,7 +27,9 @@ use vmm_sys_util::eventfd::EventFd;
 use super::client::VhostUserClient;
 use crate::vhost::VhostOps;
 use crate::VhostUser::client::{
-    VhostBackendType, VHOST_USER_PROTOCOL_F_CONFIG, VHOST_USER_PROTOCOL_F_MQ,
+    VhostBackendType, VhostUserState, VHOST_USER_PROTOCOL_F_CONFIG,
+    VHOST_USER_PROTOCOL_F_CONFIGURE_MEM_SLOTS, VHOST_USER_PROTOCOL_F_LOG_SHMFD,
+    VHOST_USER_PROTOCOL_F_MQ,
 };
 use crate::VhostUser::message::VHOST_USER_F_PROTOCOL_FEATURES;
 use crate::{
@@ -105,8 +108,10 @@ impl Block {
             let protocol_features = locked_client
                 .get_protocol_features()
                 .with_context(|| "Failed to get protocol features for vhost-user blk")?;
-            let supported_protocol_features =
-                1 << VHOST_USER_PROTOCOL_F_MQ | 1 << VHOST_USER_PROTOCOL_F_CONFIG;
+            let supported_protocol_features = 1 << VHOST_USER_PROTOCOL_F_MQ
+                | 1 << VHOST_USER_PROTOCOL_F_CONFIG
+                | 1 << VHOST_USER_PROTOCOL_F_LOG_SHMFD
+                | 1 << VHOST_USER_PROTOCOL_F_CONFIGURE_MEM_SLOTS;
             locked_client
                 .set_protocol_features(supported_protocol_features & protocol_features)
                 .with_context(|| "Failed to set protocol features for vhost-user blk")?;
@@ -306,3 +311,89 @@ impl VirtioDevice for Block {
         &self.broken
     }
 }
+
+impl StateTransfer for Block {
+    fn get_state_vec(&self) -> migration::Result<Vec<u8>> {
+        let bases = match &self.client {
+            Some(client) => client.lock().unwrap().get_vring_bases()?,
+            None => Vec::new(),
+        };
+        let state = VhostUserState::new(
+            self.state.device_features,
+            self.state.driver_features,
+            &bases,
+        )?;
+
+        Ok(state.as_bytes().to_vec())
+    }
+
+    fn set_state_mut(&mut self, state: &[u8]) -> migration::Result<()> {
+        let state = VhostUserState::from_bytes(state)
+            .with_context(|| migration::error::MigrationError::FromBytesError("VHOST_USER_BLK"))?;
+        self.state.driver_features = state.driver_features;
+        if let Some(client) = &self.client {
+            client
+                .lock()
+                .unwrap()
+                .set_vring_bases(state.get_vring_bases());
+        }
+
+        Ok(())
+    }
+
+    fn get_device_alias(&self) -> u64 {
+        MigrationManager::get_desc_alias(&VhostUserState::descriptor().name).unwrap_or(!0)
+    }
+}
+
+impl MigrationHook for Block {
+    fn start_dirty_log(&mut self) -> migration::Result<()> {
+        if let Some(client) = &self.client {
+            client
+                .lock()
+                .unwrap()
+                .start_dirty_log()
+                .with_context(|| "Failed to start dirty log for vhost-user blk")?;
+        }
+        Ok(())
+    }
+
+    fn stop_dirty_log(&mut self) -> migration::Result<()> {
+        if let Some(client) = &self.client {
+            client
+                .lock()
+                .unwrap()
+                .stop_dirty_log()
+                .with_context(|| "Failed to stop dirty log for vhost-user blk")?;
+        }
+        Ok(())
+    }
+
+    fn sync_dirty_log(&mut self, mark: &mut dyn FnMut(u64, u64)) {
+        if let Some(client) = &self.client {
+            client.lock().unwrap().sync_dirty_log(mark);
+        }
+    }
+
+    fn suspend_backend(&mut self) -> migration::Result<()> {
+        if let Some(client) = &self.client {
+            client
+                .lock()
+                .unwrap()
+                .suspend_vrings()
+                .with_context(|| "Failed to suspend vrings for vhost-user blk")?;
+        }
+        Ok(())
+    }
+
+    fn resume_backend(&mut self) -> migration::Result<()> {
+        if let Some(client) = &self.client {
+            client
+                .lock()
+                .unwrap()
+                .resume_vrings()
+                .with_context(|| "Failed to resume vrings for vhost-user blk")?;
+        }
+        Ok(())
+    }
+}
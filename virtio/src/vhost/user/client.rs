@@ -15,6 +15,7 @@ use std::mem::size_of;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::rc::Rc;
 use std::slice::from_raw_parts;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -25,7 +26,11 @@ use address_space::{
     AddressSpace, FileBackend, FlatRange, GuestAddress, Listener, ListenerReqType, RegionIoEventFd,
 };
 use log::{error, info, warn};
+use machine_manager::config::MAX_VIRTIO_QUEUE;
 use machine_manager::event_loop::{register_event_helper, unregister_event_helper, EventLoop};
+use migration::{DeviceStateDesc, FieldDesc};
+use migration_derive::{ByteCode, Desc};
+use util::byte_code::ByteCode;
 use util::loop_context::{
     gen_delete_notifiers, EventNotifier, EventNotifierHelper, NotifierCallback, NotifierOperation,
 };
@@ -34,25 +39,41 @@ use util::unix::do_mmap;
 use super::super::VhostOps;
 use super::message::{
     RegionMemInfo, VhostUserHdrFlag, VhostUserMemContext, VhostUserMemHdr, VhostUserMsgHdr,
-    VhostUserMsgReq, VhostUserVringAddr, VhostUserVringState,
+    VhostUserMsgReq, VhostUserSingleMemReg, VhostUserVringAddr, VhostUserVringState,
 };
 use super::sock::VhostUserSock;
 use crate::device::block::VirtioBlkConfig;
-use crate::VhostUser::message::VhostUserConfig;
+use crate::VhostUser::message::{VhostUserConfig, VHOST_USER_F_PROTOCOL_FEATURES};
 use crate::{virtio_has_feature, Queue, QueueConfig};
 
 /// Vhost supports multiple queue
 pub const VHOST_USER_PROTOCOL_F_MQ: u8 = 0;
+/// Vhost supports `VHOST_USER_SET_LOG_BASE` with shared memory fd.
+pub const VHOST_USER_PROTOCOL_F_LOG_SHMFD: u8 = 1;
 /// Vhost supports `VHOST_USER_SET_CONFIG` and `VHOST_USER_GET_CONFIG` msg.
 pub const VHOST_USER_PROTOCOL_F_CONFIG: u8 = 9;
 /// Vhost supports `VHOST_USER_SET_INFLIGHT_FD` and `VHOST_USER_GET_INFLIGHT_FD` msg.
 pub const VHOST_USER_PROTOCOL_F_INFLIGHT_SHMFD: u8 = 12;
+/// Vhost supports `VHOST_USER_ADD_MEM_REG` and `VHOST_USER_REM_MEM_REG` msg.
+pub const VHOST_USER_PROTOCOL_F_CONFIGURE_MEM_SLOTS: u8 = 15;
+/// Vhost logs all the guest memory it writes to the dirty log.
+pub const VHOST_F_LOG_ALL: u32 = 26;
+/// The writes to the used ring of vring are logged.
+const VHOST_VRING_F_LOG: u32 = 0;
+/// Each bit of the dirty log stands for a page of this size.
+const VHOST_LOG_PAGE: u64 = 0x1000;
 
 struct ClientInternal {
     // Used to send requests to the vhost user backend in userspace.
     sock: VhostUserSock,
     // Maximum number of queues which is supported.
     max_queue_num: u64,
+    // Maximum number of memory slots if `VHOST_USER_PROTOCOL_F_CONFIGURE_MEM_SLOTS`
+    // is negotiated, in which case regions are added and removed one by one.
+    max_mem_slots: Option<u64>,
+    // Whether the mem table has been sent, after which the changes of memory
+    // regions are sent to the backend.
+    mem_table_sent: bool,
 }
 
 impl ClientInternal {
@@ -60,9 +81,56 @@ impl ClientInternal {
         ClientInternal {
             sock,
             max_queue_num,
+            max_mem_slots: None,
+            mem_table_sent: false,
         }
     }
 
+    fn send_mem_table(&mut self, regions: &[RegionInfo]) -> Result<()> {
+        let num_region = regions.len();
+        let mut fds = Vec::with_capacity(num_region);
+        let mut memcontext = VhostUserMemContext::default();
+        for region_info in regions.iter() {
+            memcontext.region_add(region_info.region);
+            fds.push(region_info.file_back.file.as_raw_fd());
+        }
+
+        let len = size_of::<VhostUserMemHdr>() + num_region * size_of::<RegionMemInfo>();
+        let hdr = VhostUserMsgHdr::new(VhostUserMsgReq::SetMemTable as u32, 0, len as u32);
+        let memhdr = VhostUserMemHdr::new(num_region as u32, 0);
+        self.sock
+            .send_msg(
+                Some(&hdr),
+                Some(&memhdr),
+                Some(memcontext.regions.as_slice()),
+                &fds,
+            )
+            .with_context(|| "Failed to send msg for setting mem table")?;
+        self.mem_table_sent = true;
+
+        Ok(())
+    }
+
+    /// Send `VHOST_USER_ADD_MEM_REG` or `VHOST_USER_REM_MEM_REG` for a single region.
+    fn send_mem_reg(&self, request: VhostUserMsgReq, region_info: &RegionInfo) -> Result<()> {
+        let len = size_of::<VhostUserSingleMemReg>();
+        let hdr = VhostUserMsgHdr::new(request as u32, 0, len as u32);
+        let mem_reg = VhostUserSingleMemReg::new(region_info.region);
+        let payload_opt: Option<&[u8]> = None;
+        // The region to remove is identified by its addresses, no fd is needed.
+        let file_fd = region_info.file_back.file.as_raw_fd();
+        let fds: &[RawFd] = if request == VhostUserMsgReq::AddMemReg {
+            &[file_fd]
+        } else {
+            &[]
+        };
+        self.sock
+            .send_msg(Some(&hdr), Some(&mem_reg), payload_opt, fds)
+            .with_context(|| format!("Failed to send msg {:?} for mem region", request))?;
+
+        Ok(())
+    }
+
     fn wait_ack_msg<T: Sized + Default>(&self, request: u32) -> Result<T> {
         self.wait_ack_msg_and_data::<T>(request, None, &mut [])
     }
@@ -180,13 +248,19 @@ struct RegionInfo {
 struct VhostUserMemInfo {
     regions: Arc<Mutex<Vec<RegionInfo>>>,
     enabled: bool,
+    client: Arc<Mutex<ClientInternal>>,
+    /// The regions are changed since the last commit, and the backend needs
+    /// the whole mem table again.
+    changed: Arc<AtomicBool>,
 }
 
 impl VhostUserMemInfo {
-    fn new() -> Self {
+    fn new(client: Arc<Mutex<ClientInternal>>) -> Self {
         VhostUserMemInfo {
             regions: Arc::new(Mutex::new(Vec::new())),
             enabled: false,
+            client,
+            changed: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -212,9 +286,9 @@ impl VhostUserMemInfo {
         None
     }
 
-    fn add_mem_range(&self, fr: &FlatRange) -> address_space::Result<()> {
+    fn add_mem_range(&self, fr: &FlatRange) -> address_space::Result<Option<RegionInfo>> {
         if fr.owner.region_type() != address_space::RegionType::Ram {
-            return Ok(());
+            return Ok(None);
         }
 
         let guest_phys_addr = fr.addr_range.base.raw_value();
@@ -224,10 +298,10 @@ impl VhostUserMemInfo {
             None => bail!("Failed to get host address to add mem range for vhost user device"),
         };
         let file_back = match fr.owner.get_file_backend() {
-            Some(file_back_) => file_back_,
+            Some(file_back_) if fr.owner.get_host_share() == Some(true) => file_back_,
             _ => {
                 info!("It is not share memory for vhost user device");
-                return Ok(());
+                return Ok(None);
             }
         };
 
@@ -238,14 +312,14 @@ impl VhostUserMemInfo {
             mmap_offset: file_back.offset + fr.offset_in_region,
         };
         let region_info = RegionInfo { region, file_back };
-        self.regions.lock().unwrap().push(region_info);
+        self.regions.lock().unwrap().push(region_info.clone());
 
-        Ok(())
+        Ok(Some(region_info))
     }
 
-    fn delete_mem_range(&self, fr: &FlatRange) -> address_space::Result<()> {
+    fn delete_mem_range(&self, fr: &FlatRange) -> address_space::Result<Option<RegionInfo>> {
         if fr.owner.region_type() != address_space::RegionType::Ram {
-            return Ok(());
+            return Ok(None);
         }
 
         let file_back = fr
@@ -268,8 +342,7 @@ impl VhostUserMemInfo {
             let mr = &region_info.region;
             if *mr == target && region_info.file_back.file.as_raw_fd() == file_back.file.as_raw_fd()
             {
-                mem_regions.remove(index);
-                return Ok(());
+                return Ok(Some(mem_regions.remove(index)));
             }
         }
         warn!(
@@ -277,6 +350,36 @@ impl VhostUserMemInfo {
             target
         );
 
+        Ok(None)
+    }
+
+    /// Tell the backend which has got the mem table about the added or removed region.
+    fn update_mem_region(&self, request: VhostUserMsgReq, region_info: &RegionInfo) -> Result<()> {
+        // Regions are locked before the client, the same as setting mem table.
+        let nregions = self.regions.lock().unwrap().len() as u64;
+        let client = self.client.lock().unwrap();
+        if !client.mem_table_sent {
+            return Ok(());
+        }
+        let max_mem_slots = match client.max_mem_slots {
+            Some(slots) => slots,
+            None => {
+                // The whole mem table is sent again when the changes are committed.
+                self.changed.store(true, Ordering::SeqCst);
+                return Ok(());
+            }
+        };
+        if request == VhostUserMsgReq::AddMemReg && nregions > max_mem_slots {
+            bail!(
+                "Vhost user backend supports at most {} memory slots",
+                max_mem_slots
+            );
+        }
+
+        // The backend gets the whole mem table again when it's reconnected.
+        if let Err(e) = client.send_mem_reg(request, region_info) {
+            error!("Failed to update mem region for vhost user, {:?}", e);
+        }
         Ok(())
     }
 }
@@ -306,19 +409,34 @@ impl Listener for VhostUserMemInfo {
     ) -> std::result::Result<(), anyhow::Error> {
         match req_type {
             ListenerReqType::AddRegion => {
-                self.add_mem_range(
+                if let Some(region_info) = self.add_mem_range(
                     range.with_context(|| "Flat range is None when adding region")?,
-                )?;
+                )? {
+                    self.update_mem_region(VhostUserMsgReq::AddMemReg, &region_info)?;
+                }
             }
             ListenerReqType::DeleteRegion => {
-                self.delete_mem_range(
+                if let Some(region_info) = self.delete_mem_range(
                     range.with_context(|| "Flat range is None when deleting region")?,
-                )?;
+                )? {
+                    self.update_mem_region(VhostUserMsgReq::RemMemReg, &region_info)?;
+                }
             }
             _ => {}
         }
         Ok(())
     }
+
+    fn commit(&self) -> Result<()> {
+        if !self.changed.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let regions = self.regions.lock().unwrap();
+        if let Err(e) = self.client.lock().unwrap().send_mem_table(&regions) {
+            error!("Failed to update mem table for vhost user, {:?}", e);
+        }
+        Ok(())
+    }
 }
 
 /// Struct for set and get inflight fd request, field is defined by dpdk.
@@ -346,6 +464,120 @@ pub struct VhostInflight {
     pub inner: VhostUserInflight,
 }
 
+/// Struct for set log base request, field is defined by vhost-user protocol.
+#[repr(C)]
+#[derive(Debug, Default, Clone)]
+pub struct VhostUserLog {
+    // The size of the dirty log area.
+    pub mmap_size: u64,
+    // The offset from the start of the supplied file descriptor.
+    pub mmap_offset: u64,
+}
+
+/// Shared memory area where the backend logs the guest memory it writes, one bit
+/// for each `VHOST_LOG_PAGE` of guest physical memory.
+struct VhostLogArea {
+    // The memfd backing the log area.
+    file: File,
+    // Host address of the log area.
+    addr: u64,
+    // Size of the log area in bytes.
+    size: u64,
+}
+
+impl VhostLogArea {
+    /// Create a log area covering guest physical memory below `max_gpa`.
+    fn new(max_gpa: u64) -> Result<Self> {
+        let pages = (max_gpa + VHOST_LOG_PAGE - 1) / VHOST_LOG_PAGE;
+        let size = (pages + 63) / 64 * size_of::<u64>() as u64;
+
+        let name = b"stratovirt_vhost_log\0";
+        // SAFETY: The name is a valid C string, and the return value is checked.
+        let fd = unsafe { libc::syscall(libc::SYS_memfd_create, name.as_ptr(), 0) } as RawFd;
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| "Failed to create memfd for vhost dirty log");
+        }
+        // SAFETY: The fd is just created and owned by the file.
+        let file = unsafe { File::from_raw_fd(fd) };
+        file.set_len(size)
+            .with_context(|| "Failed to set the length of vhost dirty log")?;
+        let addr = do_mmap(&Some(&file), size, 0, false, true, false)
+            .with_context(|| "Failed to map vhost dirty log")?;
+
+        Ok(VhostLogArea { file, addr, size })
+    }
+
+    /// Get and clear the dirty log.
+    ///
+    /// # Arguments
+    ///
+    /// * `mark` - Callback with guest physical address and length of dirty memory.
+    fn sync(&self, mark: &mut dyn FnMut(u64, u64)) {
+        let words = self.size as usize / size_of::<u64>();
+        // SAFETY: The area is mapped with `size` bytes in `new`, and is only
+        // accessed atomically as it's shared with the backend.
+        let log = unsafe { std::slice::from_raw_parts(self.addr as *const AtomicU64, words) };
+        for (idx, word) in log.iter().enumerate() {
+            if word.load(Ordering::Relaxed) == 0 {
+                continue;
+            }
+            let dirty = word.swap(0, Ordering::SeqCst);
+            for bit in 0..64 {
+                if (dirty >> bit) & 1 != 0 {
+                    mark((idx as u64 * 64 + bit) * VHOST_LOG_PAGE, VHOST_LOG_PAGE);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for VhostLogArea {
+    fn drop(&mut self) {
+        // SAFETY: The area is mapped in `new` with the same size.
+        unsafe {
+            libc::munmap(self.addr as *mut libc::c_void, self.size as libc::size_t);
+        }
+    }
+}
+
+/// State of vhost user device which is saved for migration.
+#[repr(C)]
+#[derive(Clone, Copy, Desc, ByteCode)]
+#[desc_version(compat_version = "0.1.0")]
+pub struct VhostUserState {
+    /// Bit mask of features supported by the backend.
+    pub device_features: u64,
+    /// Bit mask of features negotiated by the backend and the frontend.
+    pub driver_features: u64,
+    /// Number of vrings handled by the backend.
+    pub vring_num: u32,
+    /// Last avail idx of vrings processed by the backend.
+    pub vring_bases: [u16; MAX_VIRTIO_QUEUE],
+}
+
+impl VhostUserState {
+    pub fn new(device_features: u64, driver_features: u64, bases: &[u16]) -> Result<Self> {
+        if bases.len() > MAX_VIRTIO_QUEUE {
+            bail!("Too many vrings {} for vhost-user migration", bases.len());
+        }
+
+        let mut vring_bases = [0_u16; MAX_VIRTIO_QUEUE];
+        vring_bases[..bases.len()].copy_from_slice(bases);
+        Ok(VhostUserState {
+            device_features,
+            driver_features,
+            vring_num: bases.len() as u32,
+            vring_bases,
+        })
+    }
+
+    pub fn get_vring_bases(&self) -> Vec<u16> {
+        let num = std::cmp::min(self.vring_num as usize, MAX_VIRTIO_QUEUE);
+        self.vring_bases[..num].to_vec()
+    }
+}
+
 #[derive(PartialEq, Eq)]
 pub enum VhostBackendType {
     TypeNet,
@@ -366,6 +598,11 @@ pub struct VhostUserClient {
     reconnecting: bool,
     inflight: Option<VhostInflight>,
     backend_type: VhostBackendType,
+    /// Dirty log area shared with the backend during migration.
+    log: Option<VhostLogArea>,
+    /// Vring bases saved when vrings are suspended, or restored from migration.
+    /// They are used instead of the avail idx of guest when vrings start.
+    vring_bases: Option<Vec<u16>>,
 }
 
 impl VhostUserClient {
@@ -383,12 +620,11 @@ impl VhostUserClient {
             )
         })?;
 
-        let mem_info = VhostUserMemInfo::new();
+        let client = Arc::new(Mutex::new(ClientInternal::new(sock, max_queue_num)));
+        let mem_info = VhostUserMemInfo::new(client.clone());
         mem_space
             .register_listener(Arc::new(Mutex::new(mem_info.clone())))
             .with_context(|| "Failed to register memory for vhost user client")?;
-
-        let client = Arc::new(Mutex::new(ClientInternal::new(sock, max_queue_num)));
         Ok(VhostUserClient {
             client,
             mem_info,
@@ -401,6 +637,8 @@ impl VhostUserClient {
             reconnecting: false,
             inflight: None,
             backend_type,
+            log: None,
+            vring_bases: None,
         })
     }
 
@@ -474,12 +712,17 @@ impl VhostUserClient {
         self.set_owner()
             .with_context(|| "Failed to set owner for vhost-user")?;
 
-        self.set_features(self.features)
+        self.set_features(self.logged_features())
             .with_context(|| "Failed to set features for vhost-user")?;
 
         self.set_mem_table()
             .with_context(|| "Failed to set mem table for vhost-user")?;
 
+        if let Some(log) = &self.log {
+            self.set_log_base(log)
+                .with_context(|| "Failed to set log base for vhost-user")?;
+        }
+
         let queue_size = self
             .queues
             .first()
@@ -489,6 +732,15 @@ impl VhostUserClient {
             .vring
             .actual_size();
         self.set_inflight(self.queues.len() as u16, queue_size)?;
+
+        // The vring bases restored from migration take precedence over the avail idx of guest.
+        let vring_bases = self.get_vring_bases()?;
+        self.vring_bases = None;
+        self.start_vrings(&vring_bases)
+    }
+
+    /// Set up all vrings and start them from `vring_bases`.
+    fn start_vrings(&self, vring_bases: &[u16]) -> Result<()> {
         // Set all vring num to notify ovs/dpdk how many queues it needs to poll
         // before setting vring info.
         for (queue_index, queue_mutex) in self.queues.iter().enumerate() {
@@ -503,17 +755,16 @@ impl VhostUserClient {
         }
 
         for (queue_index, queue_mutex) in self.queues.iter().enumerate() {
-            let queue = queue_mutex.lock().unwrap();
-            let queue_config = queue.vring.get_queue_config();
+            let queue_config = queue_mutex.lock().unwrap().vring.get_queue_config();
 
-            self.set_vring_addr(&queue_config, queue_index, 0)
+            self.set_vring_addr(&queue_config, queue_index, self.vring_flags())
                 .with_context(|| {
                     format!(
                         "Failed to set vring addr for vhost-user, index: {}",
                         queue_index,
                     )
                 })?;
-            let last_avail_idx = queue.vring.get_avail_idx(&self.mem_space)?;
+            let last_avail_idx = vring_bases.get(queue_index).copied().unwrap_or_default();
             self.set_vring_base(queue_index, last_avail_idx)
                 .with_context(|| {
                     format!(
@@ -562,10 +813,171 @@ impl VhostUserClient {
         self.queue_evts.clear();
         self.call_events.clear();
         self.queues.clear();
+        self.vring_bases = None;
 
         Ok(())
     }
 
+    /// Features sent to the backend, with `VHOST_F_LOG_ALL` if dirty log is started.
+    fn logged_features(&self) -> u64 {
+        if self.log.is_some() {
+            self.features | 1 << VHOST_F_LOG_ALL
+        } else {
+            self.features
+        }
+    }
+
+    /// Flags of vring address, with `VHOST_VRING_F_LOG` if dirty log is started.
+    fn vring_flags(&self) -> u32 {
+        if self.log.is_some() {
+            1 << VHOST_VRING_F_LOG
+        } else {
+            0
+        }
+    }
+
+    /// Start logging the guest memory written by the backend. The backend must
+    /// support `VHOST_F_LOG_ALL` and `VHOST_USER_PROTOCOL_F_LOG_SHMFD`.
+    pub fn start_dirty_log(&mut self) -> Result<()> {
+        if self.log.is_some() {
+            return Ok(());
+        }
+
+        let features = self
+            .get_features()
+            .with_context(|| "Failed to get features for vhost-user")?;
+        if !virtio_has_feature(features, VHOST_F_LOG_ALL)
+            || !virtio_has_feature(features, VHOST_USER_F_PROTOCOL_FEATURES)
+        {
+            bail!(
+                "Vhost-user backend doesn't support dirty log, features: {:#b}",
+                features
+            );
+        }
+        let protocol_features = self
+            .get_protocol_features()
+            .with_context(|| "Failed to get protocol features for vhost-user")?;
+        if !virtio_has_feature(protocol_features, VHOST_USER_PROTOCOL_F_LOG_SHMFD as u32) {
+            bail!(
+                "Vhost-user backend doesn't support shared memory dirty log, protocol features: {:#b}",
+                protocol_features
+            );
+        }
+
+        let max_gpa = self
+            .mem_info
+            .regions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|info| info.region.guest_phys_addr + info.region.memory_size)
+            .max()
+            .unwrap_or_default();
+        self.log = Some(VhostLogArea::new(max_gpa)?);
+
+        // The log is sent when the device is activated if it isn't activated yet.
+        if !self.queues.is_empty() {
+            self.set_log_base(self.log.as_ref().unwrap())
+                .with_context(|| "Failed to set log base for vhost-user")?;
+            self.update_log_all()?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop logging the guest memory written by the backend.
+    pub fn stop_dirty_log(&mut self) -> Result<()> {
+        if self.log.is_none() {
+            return Ok(());
+        }
+
+        let log = self.log.take();
+        if !self.queues.is_empty() {
+            self.update_log_all()?;
+        }
+        drop(log);
+
+        Ok(())
+    }
+
+    /// Get and clear the guest memory written by the backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `mark` - Callback with guest physical address and length of dirty memory.
+    pub fn sync_dirty_log(&self, mark: &mut dyn FnMut(u64, u64)) {
+        if let Some(log) = &self.log {
+            log.sync(mark);
+        }
+    }
+
+    /// Tell the backend whether to log the guest memory writes, according to
+    /// whether the dirty log is started.
+    fn update_log_all(&self) -> Result<()> {
+        self.set_features(self.logged_features())
+            .with_context(|| "Failed to set features for vhost-user")?;
+        for (queue_index, queue_mutex) in self.queues.iter().enumerate() {
+            let queue_config = queue_mutex.lock().unwrap().vring.get_queue_config();
+            self.set_vring_addr(&queue_config, queue_index, self.vring_flags())
+                .with_context(|| {
+                    format!(
+                        "Failed to set vring addr for vhost-user, index: {}",
+                        queue_index,
+                    )
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop all vrings in the backend and save their bases, so that the backend
+    /// writes no more guest memory.
+    pub fn suspend_vrings(&mut self) -> Result<()> {
+        if self.queues.is_empty() || self.vring_bases.is_some() {
+            return Ok(());
+        }
+
+        let mut bases = Vec::with_capacity(self.queues.len());
+        for queue_index in 0..self.queues.len() {
+            let base = self
+                .get_vring_base(queue_index)
+                .with_context(|| format!("Failed to get vring base, index: {}", queue_index))?;
+            bases.push(base);
+        }
+        self.vring_bases = Some(bases);
+
+        Ok(())
+    }
+
+    /// Restart the vrings stopped by `suspend_vrings`.
+    pub fn resume_vrings(&mut self) -> Result<()> {
+        if let Some(bases) = self.vring_bases.take() {
+            self.start_vrings(&bases)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the bases of vrings. They are the saved ones if vrings are suspended,
+    /// otherwise the avail idx of guest.
+    pub fn get_vring_bases(&self) -> Result<Vec<u16>> {
+        if let Some(bases) = &self.vring_bases {
+            return Ok(bases.clone());
+        }
+
+        let mut bases = Vec::with_capacity(self.queues.len());
+        for queue in self.queues.iter() {
+            bases.push(queue.lock().unwrap().vring.get_avail_idx(&self.mem_space)?);
+        }
+        Ok(bases)
+    }
+
+    /// Set the bases of vrings restored from migration, which are used when the
+    /// device is activated.
+    pub fn set_vring_bases(&mut self, bases: Vec<u16>) {
+        self.vring_bases = Some(bases);
+    }
+
     pub fn add_event(client: &Arc<Mutex<Self>>) -> Result<()> {
         let notifiers = EventNotifierHelper::internal_notifiers(client.clone());
         register_event_helper(notifiers, None, &mut client.lock().unwrap().delete_evts)
@@ -611,7 +1023,36 @@ impl VhostUserClient {
 
     /// Set protocol features to vhost.
     pub fn set_protocol_features(&self, features: u64) -> Result<()> {
-        self.set_value(VhostUserMsgReq::SetProtocolFeatures, features)
+        self.set_value(VhostUserMsgReq::SetProtocolFeatures, features)?;
+
+        let max_mem_slots =
+            if virtio_has_feature(features, VHOST_USER_PROTOCOL_F_CONFIGURE_MEM_SLOTS as u32) {
+                Some(
+                    self.get_max_mem_slots()
+                        .with_context(|| "Failed to get max mem slots for vhost-user")?,
+                )
+            } else {
+                None
+            };
+        self.client.lock().unwrap().max_mem_slots = max_mem_slots;
+        Ok(())
+    }
+
+    /// Get max number of memory slots that vhost supports.
+    fn get_max_mem_slots(&self) -> Result<u64> {
+        let request = VhostUserMsgReq::GetMaxMemSlots as u32;
+        let hdr = VhostUserMsgHdr::new(request, VhostUserHdrFlag::NeedReply as u32, 0);
+        let body_opt: Option<&u32> = None;
+        let payload_opt: Option<&[u8]> = None;
+        let client = self.client.lock().unwrap();
+        client
+            .sock
+            .send_msg(Some(&hdr), body_opt, payload_opt, &[])
+            .with_context(|| "Failed to send msg for getting max mem slots")?;
+        let slots = client
+            .wait_ack_msg::<u64>(request)
+            .with_context(|| "Failed to wait ack msg for getting max mem slots")?;
+        Ok(slots)
     }
 
     /// Get virtio blk config from vhost.
@@ -710,6 +1151,57 @@ impl VhostUserClient {
         Ok(res)
     }
 
+    /// Send the dirty log area to vhost.
+    fn set_log_base(&self, log: &VhostLogArea) -> Result<()> {
+        let request = VhostUserMsgReq::SetLogBase as u32;
+        let len = size_of::<VhostUserLog>();
+        let hdr = VhostUserMsgHdr::new(request, 0, len as u32);
+        let log_msg = VhostUserLog {
+            mmap_size: log.size,
+            mmap_offset: 0,
+        };
+        let payload_opt: Option<&[u8]> = None;
+        let client = self.client.lock().unwrap();
+        client
+            .sock
+            .send_msg(
+                Some(&hdr),
+                Some(&log_msg),
+                payload_opt,
+                &[log.file.as_raw_fd()],
+            )
+            .with_context(|| "Failed to send msg for setting log base")?;
+
+        // The backend replies after it maps the log area, the reply carries no
+        // payload or a zero u64 depending on the backend.
+        let mut reply_hdr = VhostUserMsgHdr::default();
+        let mut reply_body = 0_u64;
+        let payload_opt: Option<&mut [u8]> = None;
+        let (recv_len, _) = client
+            .sock
+            .recv_msg(
+                Some(&mut reply_hdr),
+                Some(&mut reply_body),
+                payload_opt,
+                &mut [],
+            )
+            .with_context(|| "Failed to recv ack msg for setting log base")?;
+        if reply_hdr.request != request
+            || !reply_hdr.is_reply()
+            || recv_len != size_of::<VhostUserMsgHdr>() + reply_hdr.size as usize
+        {
+            bail!(
+                "The ack msg is invalid, request: {}, header request: {}, reply type: {}, recv len: {}",
+                request,
+                reply_hdr.request,
+                reply_hdr.is_reply(),
+                recv_len
+            );
+        }
+
+        Ok(())
+    }
+
     /// Set inflight file info and send inflight fd to vhost.
     pub fn set_inflight_fd(&self, inflight: VhostUserInflight, fd: RawFd) -> Result<()> {
         let request = VhostUserMsgReq::SetInflightFd as u32;
@@ -765,34 +1257,12 @@ impl VhostOps for VhostUserClient {
     fn set_mem_table(&self) -> Result<()> {
         let mem_regions = self.mem_info.regions.lock().unwrap();
         if mem_regions.is_empty() {
-            bail!("Failed to initial vhost user memory map, consider using command mem-share=on");
-        }
-
-        let num_region = mem_regions.len();
-        let mut fds = Vec::with_capacity(num_region);
-        let mut memcontext = VhostUserMemContext::default();
-        for region_info in mem_regions.iter() {
-            memcontext.region_add(region_info.region);
-            fds.push(region_info.file_back.file.as_raw_fd());
+            bail!(
+                "Failed to initial vhost user memory map, consider using command mem-share=on or share=on of memory backend"
+            );
         }
-        drop(mem_regions);
 
-        let len = size_of::<VhostUserMemHdr>() + num_region * size_of::<RegionMemInfo>();
-        let hdr = VhostUserMsgHdr::new(VhostUserMsgReq::SetMemTable as u32, 0, len as u32);
-        let memhdr = VhostUserMemHdr::new(num_region as u32, 0);
-        self.client
-            .lock()
-            .unwrap()
-            .sock
-            .send_msg(
-                Some(&hdr),
-                Some(&memhdr),
-                Some(memcontext.regions.as_slice()),
-                &fds,
-            )
-            .with_context(|| "Failed to send msg for setting mem table")?;
-
-        Ok(())
+        self.client.lock().unwrap().send_mem_table(&mem_regions)
     }
 
     fn set_vring_num(&self, queue_idx: usize, num: u16) -> Result<()> {
@@ -860,7 +1330,7 @@ impl VhostOps for VhostUserClient {
             desc_user_addr,
             used_user_addr,
             avail_user_addr,
-            log_guest_addr: 0_u64,
+            log_guest_addr: queue.used_ring.0,
         };
         self.client
             .lock()
@@ -873,6 +1343,12 @@ impl VhostOps for VhostUserClient {
     }
 
     fn set_vring_base(&self, queue_idx: usize, last_avail_idx: u16) -> Result<()> {
+        util::trace_event!(
+            vhost_user_set_vring_base,
+            "queue {} last_avail_idx {}",
+            queue_idx,
+            last_avail_idx
+        );
         let client = self.client.lock().unwrap();
         if queue_idx as u64 > client.max_queue_num {
             bail!(
@@ -898,6 +1374,12 @@ impl VhostOps for VhostUserClient {
     }
 
     fn set_vring_call(&self, queue_idx: usize, fd: Arc<EventFd>) -> Result<()> {
+        util::trace_event!(
+            vhost_user_set_vring_call,
+            "queue {} fd {}",
+            queue_idx,
+            fd.as_raw_fd()
+        );
         let client = self.client.lock().unwrap();
         if queue_idx as u64 > client.max_queue_num {
             bail!(
@@ -922,6 +1404,12 @@ impl VhostOps for VhostUserClient {
     }
 
     fn set_vring_kick(&self, queue_idx: usize, fd: Arc<EventFd>) -> Result<()> {
+        util::trace_event!(
+            vhost_user_set_vring_kick,
+            "queue {} fd {}",
+            queue_idx,
+            fd.as_raw_fd()
+        );
         let client = self.client.lock().unwrap();
         if queue_idx as u64 > client.max_queue_num {
             bail!(
@@ -946,6 +1434,12 @@ impl VhostOps for VhostUserClient {
     }
 
     fn set_vring_enable(&self, queue_idx: usize, status: bool) -> Result<()> {
+        util::trace_event!(
+            vhost_user_set_vring_enable,
+            "queue {} status {}",
+            queue_idx,
+            status
+        );
         let client = self.client.lock().unwrap();
         if queue_idx as u64 > client.max_queue_num {
             bail!(
@@ -57,7 +57,10 @@ pub enum VhostUserMsgReq {
     PostcopyEnd = 30,
     GetInflightFd = 31,
     SetInflightFd = 32,
-    MaxCmd = 33,
+    GetMaxMemSlots = 36,
+    AddMemReg = 37,
+    RemMemReg = 38,
+    MaxCmd = 39,
 }
 
 impl From<u32> for VhostUserMsgReq {
@@ -96,6 +99,9 @@ impl From<u32> for VhostUserMsgReq {
             30 => VhostUserMsgReq::PostcopyEnd,
             31 => VhostUserMsgReq::GetInflightFd,
             32 => VhostUserMsgReq::SetInflightFd,
+            36 => VhostUserMsgReq::GetMaxMemSlots,
+            37 => VhostUserMsgReq::AddMemReg,
+            38 => VhostUserMsgReq::RemMemReg,
             _ => VhostUserMsgReq::MaxCmd,
         }
     }
@@ -212,6 +218,21 @@ pub struct RegionMemInfo {
     pub mmap_offset: u64,
 }
 
+/// The message of a single memory region to be added or removed.
+#[repr(C)]
+pub struct VhostUserSingleMemReg {
+    /// Padding for alignment.
+    pub padding: u64,
+    /// The memory region.
+    pub region: RegionMemInfo,
+}
+
+impl VhostUserSingleMemReg {
+    pub fn new(region: RegionMemInfo) -> Self {
+        VhostUserSingleMemReg { padding: 0, region }
+    }
+}
+
 /// The header for the message of memory table.
 #[repr(C)]
 pub struct VhostUserMemHdr {
@@ -19,13 +19,17 @@ use std::sync::{Arc, Mutex};
 use address_space::AddressSpace;
 use machine_manager::config::NetworkInterfaceConfig;
 use machine_manager::event_loop::{register_event_helper, unregister_event_helper};
+use migration::{DeviceStateDesc, MigrationHook, MigrationManager, StateTransfer};
 use util::byte_code::ByteCode;
 use util::loop_context::EventNotifierHelper;
 use util::num_ops::read_u32;
 use vmm_sys_util::eventfd::EventFd;
 
 use super::super::VhostOps;
-use super::{VhostBackendType, VhostUserClient};
+use super::{
+    VhostBackendType, VhostUserClient, VhostUserState, VHOST_USER_F_PROTOCOL_FEATURES,
+    VHOST_USER_PROTOCOL_F_CONFIGURE_MEM_SLOTS, VHOST_USER_PROTOCOL_F_LOG_SHMFD,
+};
 use crate::error::VirtioError;
 use crate::{
     device::net::{build_device_config_space, CtrlInfo, VirtioNetState, MAC_ADDR_LEN},
@@ -123,11 +127,23 @@ impl VirtioDevice for Net {
         VhostUserClient::add_event(&client)?;
 
         let mut locked_state = self.state.lock().unwrap();
-        locked_state.device_features = client
-            .lock()
-            .unwrap()
+        let mut locked_client = client.lock().unwrap();
+        locked_state.device_features = locked_client
             .get_features()
             .with_context(|| "Failed to get features for vhost-user net")?;
+        if virtio_has_feature(locked_state.device_features, VHOST_USER_F_PROTOCOL_FEATURES) {
+            // The dirty log is used by migration, and the memory slots are used
+            // to update memory regions one by one.
+            let protocol_features = locked_client
+                .get_protocol_features()
+                .with_context(|| "Failed to get protocol features for vhost-user net")?;
+            let supported_protocol_features = 1 << VHOST_USER_PROTOCOL_F_LOG_SHMFD
+                | 1 << VHOST_USER_PROTOCOL_F_CONFIGURE_MEM_SLOTS;
+            locked_client
+                .set_protocol_features(protocol_features & supported_protocol_features)
+                .with_context(|| "Failed to set protocol features for vhost-user net")?;
+        }
+        drop(locked_client);
 
         let features = 1 << VIRTIO_F_VERSION_1
             | 1 << VIRTIO_NET_F_GUEST_CSUM
@@ -321,3 +337,90 @@ impl VirtioDevice for Net {
         &self.broken
     }
 }
+
+impl StateTransfer for Net {
+    fn get_state_vec(&self) -> migration::Result<Vec<u8>> {
+        let locked_state = self.state.lock().unwrap();
+        let bases = match &self.client {
+            Some(client) => client.lock().unwrap().get_vring_bases()?,
+            None => Vec::new(),
+        };
+        let state = VhostUserState::new(
+            locked_state.device_features,
+            locked_state.driver_features,
+            &bases,
+        )?;
+
+        Ok(state.as_bytes().to_vec())
+    }
+
+    fn set_state_mut(&mut self, state: &[u8]) -> migration::Result<()> {
+        let state = VhostUserState::from_bytes(state)
+            .with_context(|| migration::error::MigrationError::FromBytesError("VHOST_USER_NET"))?;
+        self.state.lock().unwrap().driver_features = state.driver_features;
+        if let Some(client) = &self.client {
+            client
+                .lock()
+                .unwrap()
+                .set_vring_bases(state.get_vring_bases());
+        }
+
+        Ok(())
+    }
+
+    fn get_device_alias(&self) -> u64 {
+        MigrationManager::get_desc_alias(&VhostUserState::descriptor().name).unwrap_or(!0)
+    }
+}
+
+impl MigrationHook for Net {
+    fn start_dirty_log(&mut self) -> migration::Result<()> {
+        if let Some(client) = &self.client {
+            client
+                .lock()
+                .unwrap()
+                .start_dirty_log()
+                .with_context(|| "Failed to start dirty log for vhost-user net")?;
+        }
+        Ok(())
+    }
+
+    fn stop_dirty_log(&mut self) -> migration::Result<()> {
+        if let Some(client) = &self.client {
+            client
+                .lock()
+                .unwrap()
+                .stop_dirty_log()
+                .with_context(|| "Failed to stop dirty log for vhost-user net")?;
+        }
+        Ok(())
+    }
+
+    fn sync_dirty_log(&mut self, mark: &mut dyn FnMut(u64, u64)) {
+        if let Some(client) = &self.client {
+            client.lock().unwrap().sync_dirty_log(mark);
+        }
+    }
+
+    fn suspend_backend(&mut self) -> migration::Result<()> {
+        if let Some(client) = &self.client {
+            client
+                .lock()
+                .unwrap()
+                .suspend_vrings()
+                .with_context(|| "Failed to suspend vrings for vhost-user net")?;
+        }
+        Ok(())
+    }
+
+    fn resume_backend(&mut self) -> migration::Result<()> {
+        if let Some(client) = &self.client {
+            client
+                .lock()
+                .unwrap()
+                .resume_vrings()
+                .with_context(|| "Failed to resume vrings for vhost-user net")?;
+        }
+        Ok(())
+    }
+}